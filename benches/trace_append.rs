@@ -0,0 +1,58 @@
+//! Append and read cost of the rotating trace over a long run.
+//!
+//! Appends a batch of eight device records at 100 Hz for 30 simulated
+//! minutes, reading the trace once a simulated second as snapshots and
+//! flushes do, and prints the mean time per tick of each 3-minute window,
+//! reads included. The times should stay flat as the trace grows.
+//!
+//! Run with `cargo bench --bench trace_append`.
+use emt::utils::trace_rotation::RotatingTrace;
+use polars::df;
+use polars::prelude::DataFrame;
+use std::hint::black_box;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const RATE_HZ: usize = 100;
+const DEVICES: usize = 8;
+const MINUTES: usize = 30;
+const WINDOW_MINUTES: usize = 3;
+
+fn batch(timestamp_ms: i64, tick: usize) -> DataFrame {
+    df![
+        "pid" => vec![1u32; DEVICES],
+        "timestamp" => vec![timestamp_ms; DEVICES],
+        "device" => (0..DEVICES).map(|device| format!("rapl:socket:{device}:package")).collect::<Vec<_>>(),
+        "energy" => vec![tick as f64 * 1e-3; DEVICES],
+    ]
+    .unwrap()
+}
+
+fn main() {
+    let mut trace = RotatingTrace::new(i64::MAX / 1000);
+    let start_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as i64;
+    let window = WINDOW_MINUTES * 60 * RATE_HZ;
+
+    println!("{:>8} {:>10} {:>14}", "minutes", "rows", "mean tick");
+    let mut elapsed = Duration::ZERO;
+    for tick in 0..MINUTES * 60 * RATE_HZ {
+        let data = batch(start_ms + (tick * 1000 / RATE_HZ) as i64, tick);
+        let started = Instant::now();
+        trace.append(&data).unwrap();
+        if tick % RATE_HZ == 0 {
+            black_box(trace.data().height());
+        }
+        elapsed += started.elapsed();
+        if (tick + 1) % window == 0 {
+            println!(
+                "{:>8} {:>10} {:>12.2?}",
+                (tick + 1) / (60 * RATE_HZ),
+                trace.row_count(),
+                elapsed / window as u32
+            );
+            elapsed = Duration::ZERO;
+        }
+    }
+}
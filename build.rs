@@ -0,0 +1,14 @@
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    #[cfg(feature = "grpc")]
+    {
+        // SAFETY: the build script is single-threaded.
+        unsafe {
+            std::env::set_var(
+                "PROTOC",
+                protoc_bin_vendored::protoc_bin_path().expect("vendored protoc"),
+            );
+        }
+        tonic_prost_build::compile_protos("proto/emt.proto").expect("compile proto/emt.proto");
+    }
+}
@@ -0,0 +1,213 @@
+/// Arrow Stream Module
+///
+/// Hands a trace to another Arrow implementation without copying it. A
+/// frame is exported through the Arrow C stream interface as one struct
+/// array per chunk, whose buffers stay owned by the stream until the
+/// consumer releases it. The Python extension wraps the stream in an
+/// `arrow_array_stream` capsule, which `polars.DataFrame` and
+/// `pyarrow.table` read in place.
+///
+/// Consumers in another process or on another machine get energy records
+/// as an Arrow IPC stream instead, written batch by batch with
+/// [`RecordStreamWriter`], which `pyarrow.ipc.open_stream`,
+/// `polars.read_ipc_stream` and DuckDB's `arrow` extension read without
+/// conversion.
+use crate::energy_group::EnergyRecord;
+use polars::prelude::{
+    ArrowDataType, ArrowField, Column, CompatLevel, DataFrame, PlSmallStr, PolarsResult, SchemaExt,
+};
+use polars_arrow::array::{Array, StructArray};
+use polars_arrow::ffi::{ArrowArrayStream, export_iterator};
+use polars_arrow::io::ipc::write::{StreamWriter, WriteOptions};
+use std::io::Write;
+
+/// Media type of an Arrow IPC stream.
+pub const ARROW_STREAM_MEDIA_TYPE: &str = "application/vnd.apache.arrow.stream";
+
+/// Export `df` as an Arrow C stream of struct arrays, one per chunk.
+pub fn export_frame(mut df: DataFrame) -> ArrowArrayStream {
+    let fields: Vec<ArrowField> = df
+        .get_columns()
+        .iter()
+        .map(|column| column.field().to_arrow(CompatLevel::newest()))
+        .collect();
+    let dtype = ArrowDataType::Struct(fields);
+    let batches: Vec<Box<dyn Array>> = if df.width() == 0 {
+        Vec::new()
+    } else {
+        df.align_chunks();
+        df.iter_chunks(CompatLevel::newest(), false)
+            .map(|batch| {
+                let length = batch.height();
+                Box::new(StructArray::new(
+                    dtype.clone(),
+                    length,
+                    batch.into_arrays(),
+                    None,
+                )) as Box<dyn Array>
+            })
+            .collect()
+    };
+    export_iterator(
+        Box::new(batches.into_iter().map(Ok)),
+        ArrowField::new(PlSmallStr::EMPTY, dtype, false),
+    )
+}
+
+/// `records` as a frame with the fields of [`EnergyRecord`] in its order.
+pub fn records_frame(records: &[EnergyRecord]) -> PolarsResult<DataFrame> {
+    DataFrame::new(vec![
+        Column::new(
+            "pid".into(),
+            records.iter().map(|r| r.pid).collect::<Vec<_>>(),
+        ),
+        Column::new(
+            "timestamp_us".into(),
+            records.iter().map(|r| r.timestamp_us).collect::<Vec<_>>(),
+        ),
+        Column::new(
+            "monotonic_ns".into(),
+            records.iter().map(|r| r.monotonic_ns).collect::<Vec<_>>(),
+        ),
+        Column::new(
+            "device".into(),
+            records
+                .iter()
+                .map(|r| r.device.as_str())
+                .collect::<Vec<_>>(),
+        ),
+        Column::new(
+            "energy".into(),
+            records.iter().map(|r| r.energy).collect::<Vec<_>>(),
+        ),
+        Column::new(
+            "raw_counter".into(),
+            records.iter().map(|r| r.raw_counter).collect::<Vec<_>>(),
+        ),
+    ])
+}
+
+/// Writes energy records as an Arrow IPC stream, one record batch per
+/// call to [`write`](Self::write).
+///
+/// Strings are written as `large_utf8` rather than views, which older
+/// Arrow readers do not understand.
+pub struct RecordStreamWriter<W: Write> {
+    writer: StreamWriter<W>,
+}
+
+impl<W: Write> RecordStreamWriter<W> {
+    /// Start a stream on `writer` with the schema of the records.
+    pub fn new(writer: W) -> PolarsResult<Self> {
+        let mut writer = StreamWriter::new(writer, WriteOptions { compression: None });
+        let schema = records_frame(&[])?.schema().to_arrow(CompatLevel::oldest());
+        writer.start(&schema, None)?;
+        Ok(Self { writer })
+    }
+
+    pub fn write(&mut self, records: &[EnergyRecord]) -> PolarsResult<()> {
+        if records.is_empty() {
+            return Ok(());
+        }
+        let mut frame = records_frame(records)?;
+        frame.align_chunks();
+        for batch in frame.iter_chunks(CompatLevel::oldest(), false) {
+            self.writer.write(&batch, None)?;
+        }
+        Ok(())
+    }
+
+    /// End the stream and return its writer.
+    pub fn finish(mut self) -> PolarsResult<W> {
+        self.writer.finish()?;
+        Ok(self.writer.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use polars::df;
+    use polars::prelude::{IpcStreamReader, SerReader};
+    use polars_arrow::ffi::ArrowArrayStreamReader;
+    use std::io::Cursor;
+
+    #[test]
+    fn exports_every_chunk_of_the_frame() {
+        let mut trace = df![
+            "pid" => [7u32, 8],
+            "device" => ["cpu", "gpu"],
+            "energy" => [1.5, 2.0],
+        ]
+        .unwrap();
+        trace
+            .vstack_mut(&df!["pid" => [9u32], "device" => ["cpu"], "energy" => [0.5]].unwrap())
+            .unwrap();
+
+        let mut stream = Box::new(export_frame(trace));
+        let mut reader = unsafe { ArrowArrayStreamReader::try_new(&mut *stream).unwrap() };
+
+        let ArrowDataType::Struct(fields) = reader.field().dtype() else {
+            panic!("expected a struct stream");
+        };
+        let names: Vec<&str> = fields.iter().map(|field| field.name.as_str()).collect();
+        assert_eq!(names, ["pid", "device", "energy"]);
+        let mut lengths = Vec::new();
+        while let Some(batch) = unsafe { reader.next() } {
+            lengths.push(batch.unwrap().len());
+        }
+        assert_eq!(lengths, [2, 1]);
+    }
+
+    #[test]
+    fn exports_a_frame_without_columns_as_an_empty_stream() {
+        let mut stream = Box::new(export_frame(DataFrame::empty()));
+        let mut reader = unsafe { ArrowArrayStreamReader::try_new(&mut *stream).unwrap() };
+
+        assert!(unsafe { reader.next() }.is_none());
+    }
+
+    #[test]
+    fn writes_records_as_an_ipc_stream_of_batches() {
+        let record = |pid, energy, raw_counter| EnergyRecord {
+            pid,
+            timestamp_us: 1_000,
+            monotonic_ns: 2_000,
+            device: "rapl:socket:0:package".to_string(),
+            energy,
+            raw_counter,
+        };
+        let mut writer = RecordStreamWriter::new(Vec::new()).unwrap();
+        writer
+            .write(&[record(7, 0.5, Some(12.5)), record(8, 1.0, None)])
+            .unwrap();
+        writer.write(&[]).unwrap();
+        writer.write(&[record(7, 0.25, None)]).unwrap();
+        let bytes = writer.finish().unwrap();
+
+        let trace = IpcStreamReader::new(Cursor::new(bytes)).finish().unwrap();
+        assert_eq!(
+            trace.get_column_names_str(),
+            [
+                "pid",
+                "timestamp_us",
+                "monotonic_ns",
+                "device",
+                "energy",
+                "raw_counter"
+            ]
+        );
+        assert_eq!(trace.height(), 3);
+        assert_eq!(
+            trace
+                .column("energy")
+                .unwrap()
+                .f64()
+                .unwrap()
+                .into_no_null_iter()
+                .sum::<f64>(),
+            1.75
+        );
+        assert_eq!(trace.column("raw_counter").unwrap().null_count(), 2);
+    }
+}
@@ -0,0 +1,121 @@
+/// Baseline Module
+///
+/// Idle power subtraction. [`EnergyGroup::calibrate_baseline`] measures each
+/// device's power while the system idles, before tracking begins; from then
+/// on every read of a device is reduced by the idle energy of its interval,
+/// and the records of the read are scaled down by the same factor into
+/// [`MARGINAL_ENERGY_COLUMN`]. The `energy` column keeps the energy as read,
+/// so the trace carries both.
+///
+/// [`EnergyGroup::calibrate_baseline`]: crate::energy_group::EnergyGroup::calibrate_baseline
+/// [`MARGINAL_ENERGY_COLUMN`]: crate::energy_group::MARGINAL_ENERGY_COLUMN
+use crate::energy_group::EnergyRecord;
+use std::collections::{BTreeMap, HashMap};
+
+/// Idle power of each device and when it was last read.
+#[derive(Debug, Clone)]
+pub(crate) struct IdleBaseline {
+    watts: BTreeMap<String, f64>,
+    /// Monotonic time of each device's latest read
+    last_read_ns: HashMap<String, i64>,
+    /// Interval assumed for a device's first read, in seconds
+    first_interval_seconds: f64,
+}
+
+impl IdleBaseline {
+    /// Baseline from the energy each device reported over `seconds` of
+    /// idling; `rate` is the collection rate, in Hz.
+    pub(crate) fn from_energy(energy: BTreeMap<String, f64>, seconds: f64, rate: f64) -> Self {
+        let watts = energy
+            .into_iter()
+            .map(|(device, joules)| (device, (joules / seconds).max(0.0)))
+            .collect();
+        Self {
+            watts,
+            last_read_ns: HashMap::new(),
+            first_interval_seconds: 1.0 / rate,
+        }
+    }
+
+    pub(crate) fn watts(&self) -> &BTreeMap<String, f64> {
+        &self.watts
+    }
+
+    /// Energy of each of `records` above the idle power. The records of one
+    /// read of a device, with the same device and monotonic time, share its
+    /// idle energy in proportion to their energy. Devices without a
+    /// baseline keep their energy.
+    pub(crate) fn marginal(&mut self, records: &[EnergyRecord]) -> Vec<f64> {
+        let mut reads: BTreeMap<(i64, &str), f64> = BTreeMap::new();
+        for record in records {
+            *reads
+                .entry((record.monotonic_ns, record.device.as_str()))
+                .or_default() += record.energy;
+        }
+        let mut factors = HashMap::with_capacity(reads.len());
+        // In time order, so each read is measured from the one before it
+        for (&(monotonic_ns, device), &total) in &reads {
+            let Some(&watts) = self.watts.get(device) else {
+                factors.insert((monotonic_ns, device), 1.0);
+                continue;
+            };
+            let seconds = self
+                .last_read_ns
+                .insert(device.to_string(), monotonic_ns)
+                .filter(|&previous| previous < monotonic_ns)
+                .map_or(self.first_interval_seconds, |previous| {
+                    (monotonic_ns - previous) as f64 / 1e9
+                });
+            let factor = if total > 0.0 {
+                ((total - watts * seconds) / total).max(0.0)
+            } else {
+                0.0
+            };
+            factors.insert((monotonic_ns, device), factor);
+        }
+        records
+            .iter()
+            .map(|record| record.energy * factors[&(record.monotonic_ns, record.device.as_str())])
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(pid: u32, device: &str, energy: f64, monotonic_ns: i64) -> EnergyRecord {
+        EnergyRecord {
+            pid,
+            timestamp_us: monotonic_ns / 1_000,
+            monotonic_ns,
+            device: device.to_string(),
+            energy,
+            raw_counter: None,
+        }
+    }
+
+    #[test]
+    fn subtracts_idle_energy_of_each_interval_in_proportion() {
+        let idle = BTreeMap::from([("rapl:socket:0:package".to_string(), 20.0)]);
+        // 20 W over 2 s of calibration
+        let mut baseline = IdleBaseline::from_energy(
+            idle.into_iter().map(|(d, w)| (d, w * 2.0)).collect(),
+            2.0,
+            10.0,
+        );
+        assert_eq!(baseline.watts()["rapl:socket:0:package"], 20.0);
+
+        // A first read is taken as one collection interval: 2 J idle of 6 J.
+        let first = baseline.marginal(&[
+            record(1, "rapl:socket:0:package", 3.0, 1_000_000_000),
+            record(0, "rapl:socket:0:package", 3.0, 1_000_000_000),
+            record(1, "nvidia:gpu:0", 5.0, 1_000_000_000),
+        ]);
+        assert_eq!(first, [2.0, 2.0, 5.0]);
+
+        // Half a second later 10 J idle of 8 J leaves nothing.
+        let second = baseline.marginal(&[record(1, "rapl:socket:0:package", 8.0, 1_500_000_000)]);
+        assert_eq!(second, [0.0]);
+    }
+}
@@ -0,0 +1,1358 @@
+/// Budgets Module
+///
+/// Per-process and per-task energy budgets with enforcement. A budget targets
+/// either one pid (its workload, or the single process when the pid is not a
+/// workload root) or every workload whose task name matches a glob. A target
+/// is over budget while its cumulative energy exceeds `joules` or its power
+/// exceeds `watts`, and the configured action runs:
+///
+/// - `log`: warn once;
+/// - `duty_cycle`: alternate SIGSTOP/SIGCONT so the target only runs for
+///   `duty_cycle` of every `period_secs`;
+/// - `throttle`: write `cpu_max` (and `cpu_weight`, if set) to the target's
+///   cgroup v2 directory;
+/// - `deprioritize`: raise the target's nice value to `nice` and, if
+///   `ionice` is set, lower its I/O scheduling class, as soft enforcement on
+///   interactive machines;
+/// - `cap_gpu_power`: lower the NVML power limit of each GPU in `gpus` to
+///   `gpu_power_limit_watts` (see [`crate::power_limits`]);
+/// - `terminate`: send SIGTERM once `grace_secs` have passed.
+///
+/// Throttling uses the target's own cgroup when it holds only the target's
+/// processes. Otherwise, and for processes in the root cgroup, the processes
+/// are moved into a scoped `emt-budget-*` cgroup first so that unrelated
+/// processes are not limited. Processes that join the target while it is
+/// throttled are moved in on the next snapshot.
+///
+/// When a power budget is met again, every action is undone: stopped
+/// processes are resumed, the previous cgroup limits, scheduling priorities
+/// and GPU power limits are written back and scoped cgroups are dissolved.
+/// A duty cycle is only lifted once a full period has passed without the
+/// budget being exceeded, since a stopped target draws almost no power.
+/// Budgets are evaluated on every monitor snapshot, so enforcement is only
+/// as fine-grained as the collection rate.
+/// All actions are undone when the enforcer is released.
+///
+/// [`RunLimits`] are checked once, when a batch run ends, and produce a
+/// [`RunVerdict`] that automation can act on.
+use crate::metrics_sink::MetricsSink;
+use crate::monitor::{MetricsSnapshot, WorkloadSnapshot};
+use crate::power_limits::{GpuPowerCaps, GpuPowerControl, GpuTarget, NvmlPowerControl};
+use crate::schema::SCHEMA_VERSION;
+use crate::utils::pattern::glob_match;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub use crate::utils::cgroup::CGROUP_ROOT;
+
+/// What happens when a budget is exceeded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BudgetAction {
+    #[default]
+    Log,
+    DutyCycle,
+    Throttle,
+    Deprioritize,
+    CapGpuPower,
+    Terminate,
+}
+
+/// I/O scheduling class applied under `deprioritize`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IoClass {
+    /// Lowest best-effort priority level.
+    BestEffort,
+    /// Only served when no other process needs the disk.
+    Idle,
+}
+
+impl IoClass {
+    /// Raw `ioprio` value for `ioprio_set(2)`.
+    pub fn ioprio(self) -> u16 {
+        const CLASS_SHIFT: u16 = 13;
+        match self {
+            Self::BestEffort => (2 << CLASS_SHIFT) | 7,
+            Self::Idle => 3 << CLASS_SHIFT,
+        }
+    }
+}
+
+/// Energy budget for one pid or for tasks matching a name pattern.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EnergyBudget {
+    /// Target pid. Exactly one of `pid` and `task` must be set.
+    pub pid: Option<u32>,
+    /// Task name pattern; `*` and `?` are wildcards.
+    pub task: Option<String>,
+    /// Cumulative energy allowance in joules.
+    pub joules: Option<f64>,
+    /// Power allowance in watts. Unlike `joules`, a target can get back
+    /// within it, which undoes the action.
+    pub watts: Option<f64>,
+    pub action: BudgetAction,
+    /// Fraction of each period the target may run under `duty_cycle`.
+    pub duty_cycle: f64,
+    /// Length of one duty cycle, in seconds.
+    pub period_secs: f64,
+    /// Value written to `cpu.max` under `throttle`.
+    pub cpu_max: String,
+    /// Value written to `cpu.weight` under `throttle`, if set.
+    pub cpu_weight: Option<u32>,
+    /// Nice value set under `deprioritize`; never lowers an existing one.
+    pub nice: i32,
+    /// I/O class set under `deprioritize`; `None` leaves I/O priority alone.
+    pub ionice: Option<IoClass>,
+    /// GPUs capped under `cap_gpu_power`: indices, UUIDs or
+    /// `<gpu>:<MIG instance>`.
+    pub gpus: Vec<String>,
+    /// Power limit set under `cap_gpu_power`, clamped to what each GPU
+    /// accepts.
+    pub gpu_power_limit_watts: Option<f64>,
+    /// Delay between exceeding the budget and SIGTERM under `terminate`.
+    pub grace_secs: f64,
+}
+
+impl Default for EnergyBudget {
+    fn default() -> Self {
+        Self {
+            pid: None,
+            task: None,
+            joules: None,
+            watts: None,
+            action: BudgetAction::Log,
+            duty_cycle: 0.5,
+            period_secs: 1.0,
+            cpu_max: "10000 100000".to_string(),
+            cpu_weight: None,
+            nice: 10,
+            ionice: Some(IoClass::BestEffort),
+            gpus: Vec::new(),
+            gpu_power_limit_watts: None,
+            grace_secs: 30.0,
+        }
+    }
+}
+
+impl EnergyBudget {
+    /// Human-readable target, for logs and config errors.
+    pub fn target(&self) -> String {
+        match (&self.pid, &self.task) {
+            (Some(pid), _) => format!("pid {pid}"),
+            (None, Some(task)) => format!("task '{task}'"),
+            (None, None) => "nothing".to_string(),
+        }
+    }
+
+    /// Check that the budget is well-formed.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.pid.is_some() == self.task.is_some() {
+            return Err("set exactly one of pid and task".to_string());
+        }
+        if self.joules.is_none() && self.watts.is_none() {
+            return Err("set joules, watts or both".to_string());
+        }
+        if [self.joules, self.watts]
+            .into_iter()
+            .flatten()
+            .any(|limit| !(limit.is_finite() && limit > 0.0))
+        {
+            return Err("joules and watts must be positive numbers".to_string());
+        }
+        if !(self.duty_cycle > 0.0 && self.duty_cycle < 1.0) {
+            return Err("duty_cycle must be between 0 and 1".to_string());
+        }
+        if !(self.period_secs.is_finite() && self.period_secs > 0.0) {
+            return Err("period_secs must be a positive number".to_string());
+        }
+        if self
+            .cpu_weight
+            .is_some_and(|weight| !(1..=10_000).contains(&weight))
+        {
+            return Err("cpu_weight must be between 1 and 10000".to_string());
+        }
+        if !(-20..=19).contains(&self.nice) {
+            return Err("nice must be between -20 and 19".to_string());
+        }
+        if !(self.grace_secs.is_finite() && self.grace_secs >= 0.0) {
+            return Err("grace_secs must be zero or more".to_string());
+        }
+        if self.action == BudgetAction::CapGpuPower {
+            if self.gpus.is_empty() {
+                return Err("cap_gpu_power needs gpus".to_string());
+            }
+            if let Some(error) = self
+                .gpus
+                .iter()
+                .find_map(|gpu| gpu.parse::<GpuTarget>().err())
+            {
+                return Err(error.to_string());
+            }
+            if !self
+                .gpu_power_limit_watts
+                .is_some_and(|watts| watts.is_finite() && watts > 0.0)
+            {
+                return Err("cap_gpu_power needs a positive gpu_power_limit_watts".to_string());
+            }
+        }
+        Ok(())
+    }
+
+    fn is_exceeded(&self, usage: &Usage) -> bool {
+        self.joules.is_some_and(|limit| usage.joules > limit)
+            || self.watts.is_some_and(|limit| usage.watts > limit)
+    }
+
+    /// Targets matched in `snapshot`, keyed by workload group or pid.
+    fn targets(&self, snapshot: &MetricsSnapshot) -> Vec<(String, Usage)> {
+        let workload_target = |workload: &WorkloadSnapshot| {
+            (
+                workload.group_id.clone(),
+                Usage {
+                    joules: workload.energy.total(),
+                    watts: workload.power_watts,
+                    pids: workload.processes.iter().map(|p| p.pid).collect(),
+                },
+            )
+        };
+        match (&self.pid, &self.task) {
+            (Some(pid), _) => {
+                if let Some(workload) = snapshot.workloads.iter().find(|w| w.root_pid == *pid) {
+                    return vec![workload_target(workload)];
+                }
+                snapshot
+                    .workloads
+                    .iter()
+                    .flat_map(|w| &w.processes)
+                    .find(|process| process.pid == *pid)
+                    .map(|process| {
+                        let usage = Usage {
+                            joules: process.energy.total(),
+                            watts: process.power_watts,
+                            pids: vec![*pid],
+                        };
+                        vec![(format!("pid:{pid}"), usage)]
+                    })
+                    .unwrap_or_default()
+            }
+            (None, Some(task)) => snapshot
+                .workloads
+                .iter()
+                .filter(|workload| workload.is_live && glob_match(task, &workload.name))
+                .map(workload_target)
+                .collect(),
+            (None, None) => Vec::new(),
+        }
+    }
+}
+
+/// Consumption of one budget target in a snapshot.
+struct Usage {
+    joules: f64,
+    watts: f64,
+    pids: Vec<u32>,
+}
+
+/// Signals sent by the enforcer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProcessSignal {
+    Stop,
+    Continue,
+    Terminate,
+}
+
+/// Operating-system side of enforcement, replaceable in tests. Cgroup paths
+/// are relative to the cgroup v2 root; the empty path is the root itself.
+pub trait ProcessControl: Send {
+    fn signal(&self, pid: u32, signal: ProcessSignal) -> io::Result<()>;
+    /// Cgroup containing `pid`.
+    fn cgroup_of(&self, pid: u32) -> io::Result<PathBuf>;
+    fn read_cgroup_file(&self, cgroup: &Path, file: &str) -> io::Result<String>;
+    fn write_cgroup_file(&self, cgroup: &Path, file: &str, value: &str) -> io::Result<()>;
+    fn create_cgroup(&self, cgroup: &Path) -> io::Result<()>;
+    fn remove_cgroup(&self, cgroup: &Path) -> io::Result<()>;
+    /// Nice value of `pid`.
+    fn nice(&self, pid: u32) -> io::Result<i32>;
+    /// Set the nice value of every thread of `pid`.
+    fn set_nice(&self, pid: u32, nice: i32) -> io::Result<()>;
+    /// Raw `ioprio` value of `pid`.
+    fn io_priority(&self, pid: u32) -> io::Result<u16>;
+    /// Set the `ioprio` value of every thread of `pid`.
+    fn set_io_priority(&self, pid: u32, ioprio: u16) -> io::Result<()>;
+}
+
+/// Signals via `kill(2)` and cgroups under [`CGROUP_ROOT`].
+#[derive(Debug, Default)]
+pub struct SystemProcessControl;
+
+impl SystemProcessControl {
+    fn path(cgroup: &Path) -> PathBuf {
+        Path::new(CGROUP_ROOT).join(cgroup)
+    }
+
+    /// Thread ids of `pid`; scheduling priorities are per thread.
+    fn threads(pid: u32) -> io::Result<Vec<u32>> {
+        let mut threads: Vec<u32> = fs::read_dir(format!("/proc/{pid}/task"))?
+            .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse().ok())
+            .collect();
+        threads.sort_unstable();
+        Ok(threads)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn check(result: libc::c_long) -> io::Result<()> {
+        if result == -1 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl ProcessControl for SystemProcessControl {
+    #[cfg(target_os = "linux")]
+    fn signal(&self, pid: u32, signal: ProcessSignal) -> io::Result<()> {
+        let signal = match signal {
+            ProcessSignal::Stop => libc::SIGSTOP,
+            ProcessSignal::Continue => libc::SIGCONT,
+            ProcessSignal::Terminate => libc::SIGTERM,
+        };
+        // SAFETY: kill(2) has no memory-safety preconditions.
+        if unsafe { libc::kill(pid as libc::pid_t, signal) } == 0 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn signal(&self, _pid: u32, _signal: ProcessSignal) -> io::Result<()> {
+        Err(io::Error::from(io::ErrorKind::Unsupported))
+    }
+
+    fn cgroup_of(&self, pid: u32) -> io::Result<PathBuf> {
+        let contents = fs::read_to_string(format!("/proc/{pid}/cgroup"))?;
+        unified_cgroup_path(&contents)
+            .map(|path| PathBuf::from(path.trim_start_matches('/')))
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "process is not in a cgroup v2"))
+    }
+
+    fn read_cgroup_file(&self, cgroup: &Path, file: &str) -> io::Result<String> {
+        fs::read_to_string(Self::path(cgroup).join(file))
+    }
+
+    fn write_cgroup_file(&self, cgroup: &Path, file: &str, value: &str) -> io::Result<()> {
+        fs::write(Self::path(cgroup).join(file), value)
+    }
+
+    fn create_cgroup(&self, cgroup: &Path) -> io::Result<()> {
+        fs::create_dir(Self::path(cgroup))
+    }
+
+    fn remove_cgroup(&self, cgroup: &Path) -> io::Result<()> {
+        fs::remove_dir(Self::path(cgroup))
+    }
+
+    fn nice(&self, pid: u32) -> io::Result<i32> {
+        let stat = fs::read_to_string(format!("/proc/{pid}/stat"))?;
+        parse_stat_nice(&stat)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "malformed stat file"))
+    }
+
+    #[cfg(target_os = "linux")]
+    fn set_nice(&self, pid: u32, nice: i32) -> io::Result<()> {
+        for tid in Self::threads(pid)? {
+            // SAFETY: setpriority(2) has no memory-safety preconditions.
+            let result = unsafe { libc::setpriority(libc::PRIO_PROCESS, tid, nice) };
+            Self::check(result.into())?;
+        }
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    fn io_priority(&self, pid: u32) -> io::Result<u16> {
+        // SAFETY: ioprio_get(2) has no memory-safety preconditions.
+        let result = unsafe { libc::syscall(libc::SYS_ioprio_get, IOPRIO_WHO_PROCESS, pid) };
+        Self::check(result)?;
+        Ok(result as u16)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn set_io_priority(&self, pid: u32, ioprio: u16) -> io::Result<()> {
+        for tid in Self::threads(pid)? {
+            // SAFETY: ioprio_set(2) has no memory-safety preconditions.
+            let result = unsafe {
+                libc::syscall(
+                    libc::SYS_ioprio_set,
+                    IOPRIO_WHO_PROCESS,
+                    tid,
+                    libc::c_int::from(ioprio),
+                )
+            };
+            Self::check(result)?;
+        }
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn set_nice(&self, _pid: u32, _nice: i32) -> io::Result<()> {
+        Err(io::Error::from(io::ErrorKind::Unsupported))
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn io_priority(&self, _pid: u32) -> io::Result<u16> {
+        Err(io::Error::from(io::ErrorKind::Unsupported))
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn set_io_priority(&self, _pid: u32, _ioprio: u16) -> io::Result<()> {
+        Err(io::Error::from(io::ErrorKind::Unsupported))
+    }
+}
+
+#[cfg(target_os = "linux")]
+const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+
+/// Nice value (field 19) of `/proc/<pid>/stat` contents. Fields are counted
+/// after the parenthesised command name, which may contain spaces.
+fn parse_stat_nice(stat: &str) -> Option<i32> {
+    let (_, fields) = stat.rsplit_once(')')?;
+    fields.split_whitespace().nth(16)?.parse().ok()
+}
+
+/// Path of the cgroup v2 (`0::`) entry in `/proc/<pid>/cgroup` contents.
+fn unified_cgroup_path(contents: &str) -> Option<&str> {
+    contents.lines().find_map(|line| line.strip_prefix("0::"))
+}
+
+/// Limits applied to a cgroup, with what is needed to undo them.
+#[derive(Debug, Default)]
+struct Throttle {
+    cgroup: PathBuf,
+    /// Previous values of the files written, in write order.
+    previous: Vec<(&'static str, String)>,
+    /// Original cgroups of processes moved into a scoped cgroup; empty when
+    /// the target's own cgroup was throttled.
+    moved: Vec<(u32, PathBuf)>,
+    scoped: bool,
+    /// Whether the last attempt failed, so a persistent failure is only
+    /// warned about once.
+    failing: bool,
+}
+
+#[derive(Debug, Default)]
+struct TargetState {
+    exceeded_at: Option<i64>,
+    /// Timestamp of the last snapshot in which the budget was exceeded.
+    last_exceeded: i64,
+    stopped: HashSet<u32>,
+    throttle: Option<Throttle>,
+    /// Original `(nice, ioprio)` of deprioritized processes.
+    priorities: HashMap<u32, (i32, Option<u16>)>,
+    gpus_capped: bool,
+    terminated: bool,
+}
+
+/// Evaluates budgets against monitor snapshots and applies their actions.
+pub struct BudgetEnforcer {
+    budgets: Vec<EnergyBudget>,
+    control: Box<dyn ProcessControl>,
+    /// `None` when no budget caps GPUs or NVML is unavailable.
+    gpu_caps: Option<GpuPowerCaps>,
+    /// State per (budget index, target key).
+    states: HashMap<(usize, String), TargetState>,
+}
+
+impl BudgetEnforcer {
+    pub fn new(budgets: Vec<EnergyBudget>) -> Self {
+        let caps_gpus = budgets
+            .iter()
+            .any(|budget| budget.action == BudgetAction::CapGpuPower);
+        let enforcer = Self::with_control(budgets, Box::new(SystemProcessControl));
+        if !caps_gpus {
+            return enforcer;
+        }
+        match NvmlPowerControl::new() {
+            Ok(control) => enforcer.with_gpu_control(Box::new(control)),
+            Err(e) => {
+                log::warn!("GPU power capping is unavailable: {e}");
+                enforcer
+            }
+        }
+    }
+
+    pub fn with_control(budgets: Vec<EnergyBudget>, control: Box<dyn ProcessControl>) -> Self {
+        Self {
+            budgets,
+            control,
+            gpu_caps: None,
+            states: HashMap::new(),
+        }
+    }
+
+    /// Cap GPUs through `control` under `cap_gpu_power`.
+    pub fn with_gpu_control(mut self, control: Box<dyn GpuPowerControl>) -> Self {
+        self.gpu_caps = Some(GpuPowerCaps::new(control));
+        self
+    }
+
+    /// Keys of targets that are over budget, per budget index.
+    pub fn exceeded(&self) -> Vec<(usize, String)> {
+        let mut exceeded: Vec<_> = self
+            .states
+            .iter()
+            .filter(|(_, state)| state.exceeded_at.is_some())
+            .map(|(key, _)| key.clone())
+            .collect();
+        exceeded.sort();
+        exceeded
+    }
+
+    /// Resume stopped processes and undo cgroup throttling and GPU power
+    /// caps for every target.
+    pub fn release(&mut self) {
+        for state in self.states.values_mut() {
+            restore(self.control.as_ref(), std::mem::take(state));
+        }
+        if let Some(gpu_caps) = &mut self.gpu_caps {
+            gpu_caps.release();
+        }
+    }
+
+    fn enforce(&mut self, index: usize, key: String, usage: Usage, now: i64) {
+        let budget = &self.budgets[index];
+        let control = self.control.as_ref();
+        let holder = format!("{index}:{key}");
+        let state = self.states.entry((index, key)).or_default();
+        let period_ms = (budget.period_secs * 1000.0).max(1.0);
+        let exceeded = budget.is_exceeded(&usage);
+        let cycling = budget.action == BudgetAction::DutyCycle
+            && state.exceeded_at.is_some()
+            && ((now - state.last_exceeded) as f64) < period_ms;
+        if !exceeded && !cycling {
+            if state.exceeded_at.is_some() {
+                log::info!("Back within energy budget for {}", budget.target());
+                if state.gpus_capped
+                    && let Some(gpu_caps) = &mut self.gpu_caps
+                {
+                    gpu_caps.uncap(&holder);
+                }
+                restore(control, std::mem::take(state));
+            }
+            return;
+        }
+        let exceeded_at = *state.exceeded_at.get_or_insert_with(|| {
+            log::warn!(
+                "Energy budget exceeded for {}: {:.1} J, {:.1} W, action {:?}",
+                budget.target(),
+                usage.joules,
+                usage.watts,
+                budget.action
+            );
+            now
+        });
+        if exceeded {
+            state.last_exceeded = now;
+        }
+
+        match budget.action {
+            BudgetAction::Log => {}
+            BudgetAction::DutyCycle => {
+                let phase = ((now - exceeded_at) as f64 % period_ms) / period_ms;
+                let run = phase < budget.duty_cycle;
+                for &pid in &usage.pids {
+                    let signal = match (run, state.stopped.contains(&pid)) {
+                        (true, true) => ProcessSignal::Continue,
+                        (false, false) => ProcessSignal::Stop,
+                        _ => continue,
+                    };
+                    match control.signal(pid, signal) {
+                        Ok(()) if signal == ProcessSignal::Stop => {
+                            state.stopped.insert(pid);
+                        }
+                        Ok(()) => {
+                            state.stopped.remove(&pid);
+                        }
+                        Err(e) => log::warn!("Failed to duty-cycle pid {pid}: {e}"),
+                    }
+                }
+            }
+            BudgetAction::Throttle => {
+                let throttle = state.throttle.get_or_insert_with(Throttle::default);
+                match apply_throttle(control, throttle, index, budget, &usage.pids) {
+                    Ok(()) => throttle.failing = false,
+                    Err(e) if throttle.failing => {
+                        log::debug!("Still failing to throttle {}: {e}", budget.target());
+                    }
+                    Err(e) => {
+                        log::warn!("Failed to throttle {}: {e}", budget.target());
+                        throttle.failing = true;
+                    }
+                }
+            }
+            BudgetAction::Deprioritize => {
+                for &pid in &usage.pids {
+                    if state.priorities.contains_key(&pid) {
+                        continue;
+                    }
+                    match deprioritize(control, budget, pid) {
+                        Ok(original) => {
+                            state.priorities.insert(pid, original);
+                        }
+                        Err(e) => log::warn!("Failed to deprioritize pid {pid}: {e}"),
+                    }
+                }
+            }
+            BudgetAction::CapGpuPower if !state.gpus_capped => {
+                state.gpus_capped = true;
+                let Some(gpu_caps) = &mut self.gpu_caps else {
+                    return;
+                };
+                let watts = budget.gpu_power_limit_watts.unwrap_or_default();
+                for gpu in &budget.gpus {
+                    match gpu
+                        .parse()
+                        .and_then(|target| gpu_caps.cap(&holder, &target, watts))
+                    {
+                        Ok(applied) => log::info!("Capped GPU {gpu} at {applied:.0} W"),
+                        Err(e) => log::warn!("Failed to cap GPU {gpu}: {e}"),
+                    }
+                }
+            }
+            BudgetAction::Terminate
+                if !state.terminated
+                    && (now - exceeded_at) as f64 >= budget.grace_secs * 1000.0 =>
+            {
+                state.terminated = true;
+                for &pid in &usage.pids {
+                    if let Err(e) = control.signal(pid, ProcessSignal::Terminate) {
+                        log::warn!("Failed to terminate pid {pid}: {e}");
+                    }
+                }
+            }
+            BudgetAction::CapGpuPower | BudgetAction::Terminate => {}
+        }
+    }
+}
+
+/// Write the budget's limits to the cgroup of `pids`, moving them into a
+/// scoped cgroup when their own is the root or is shared with other
+/// processes. Progress is recorded in `throttle`, and calling this again
+/// only does what is still missing: processes that joined the target since
+/// are moved and a partial failure is retried, while everything done so far
+/// can still be undone.
+fn apply_throttle(
+    control: &dyn ProcessControl,
+    throttle: &mut Throttle,
+    index: usize,
+    budget: &EnergyBudget,
+    pids: &[u32],
+) -> io::Result<()> {
+    let Some(&first) = pids.first() else {
+        return Ok(());
+    };
+    if throttle.cgroup.as_os_str().is_empty() {
+        let cgroup = control.cgroup_of(first)?;
+        let shared = cgroup.as_os_str().is_empty()
+            || control
+                .read_cgroup_file(&cgroup, "cgroup.procs")?
+                .lines()
+                .filter_map(|line| line.trim().parse::<u32>().ok())
+                .any(|member| !pids.contains(&member));
+        if shared {
+            let scoped = PathBuf::from(format!("emt-budget-{index}-{first}"));
+            if let Err(e) =
+                control.write_cgroup_file(Path::new(""), "cgroup.subtree_control", "+cpu")
+            {
+                log::debug!("Could not enable the cpu controller: {e}");
+            }
+            control.create_cgroup(&scoped)?;
+            throttle.cgroup = scoped;
+            throttle.scoped = true;
+        } else {
+            throttle.cgroup = cgroup;
+        }
+    }
+
+    if throttle.scoped {
+        for &pid in pids {
+            if throttle.moved.iter().any(|(moved, _)| *moved == pid) {
+                continue;
+            }
+            let current = control.cgroup_of(pid)?;
+            if current == throttle.cgroup {
+                // Forked inside the scoped cgroup; it is moved back along
+                // with the processes that were moved in.
+                if let Some((_, origin)) = throttle.moved.first() {
+                    let origin = origin.clone();
+                    throttle.moved.push((pid, origin));
+                }
+                continue;
+            }
+            control.write_cgroup_file(&throttle.cgroup, "cgroup.procs", &pid.to_string())?;
+            throttle.moved.push((pid, current));
+        }
+    }
+
+    let mut limits = vec![("cpu.max", budget.cpu_max.clone())];
+    if let Some(weight) = budget.cpu_weight {
+        limits.push(("cpu.weight", weight.to_string()));
+    }
+    for (file, value) in limits {
+        if throttle
+            .previous
+            .iter()
+            .any(|(written, _)| *written == file)
+        {
+            continue;
+        }
+        let previous = control.read_cgroup_file(&throttle.cgroup, file)?;
+        control.write_cgroup_file(&throttle.cgroup, file, &value)?;
+        throttle.previous.push((file, previous.trim().to_string()));
+    }
+    Ok(())
+}
+
+/// Lower the scheduling priority of `pid`, returning the original
+/// `(nice, ioprio)` to restore later.
+fn deprioritize(
+    control: &dyn ProcessControl,
+    budget: &EnergyBudget,
+    pid: u32,
+) -> io::Result<(i32, Option<u16>)> {
+    let nice = control.nice(pid)?;
+    let ioprio = match budget.ionice {
+        Some(_) => Some(control.io_priority(pid)?),
+        None => None,
+    };
+    if budget.nice > nice {
+        control.set_nice(pid, budget.nice)?;
+    }
+    if let Some(class) = budget.ionice {
+        control.set_io_priority(pid, class.ioprio())?;
+    }
+    Ok((nice, ioprio))
+}
+
+/// Undo every action recorded in `state`.
+fn restore(control: &dyn ProcessControl, state: TargetState) {
+    for pid in state.stopped {
+        if let Err(e) = control.signal(pid, ProcessSignal::Continue) {
+            log::warn!("Failed to resume pid {pid}: {e}");
+        }
+    }
+    for (pid, (nice, ioprio)) in state.priorities {
+        // Processes may have exited since they were deprioritized.
+        if let Err(e) = control.set_nice(pid, nice) {
+            log::debug!("Could not restore the nice value of pid {pid}: {e}");
+        }
+        if let Some(ioprio) = ioprio
+            && let Err(e) = control.set_io_priority(pid, ioprio)
+        {
+            log::debug!("Could not restore the I/O priority of pid {pid}: {e}");
+        }
+    }
+    let Some(throttle) = state.throttle else {
+        return;
+    };
+    for (file, value) in throttle.previous.iter().rev() {
+        if let Err(e) = control.write_cgroup_file(&throttle.cgroup, file, value) {
+            log::warn!(
+                "Failed to restore {file} of cgroup {}: {e}",
+                throttle.cgroup.display()
+            );
+        }
+    }
+    if throttle.scoped {
+        for (pid, origin) in &throttle.moved {
+            // Exited processes cannot be moved back; that is expected.
+            if let Err(e) = control.write_cgroup_file(origin, "cgroup.procs", &pid.to_string()) {
+                log::debug!("Could not move pid {pid} back to its cgroup: {e}");
+            }
+        }
+        if let Err(e) = control.remove_cgroup(&throttle.cgroup) {
+            log::warn!("Failed to remove cgroup {}: {e}", throttle.cgroup.display());
+        }
+    }
+}
+
+/// Limits on the system energy of a whole run.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RunLimits {
+    pub max_energy_joules: Option<f64>,
+    pub max_average_watts: Option<f64>,
+}
+
+/// Whether a run stayed within its [`RunLimits`].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RunVerdict {
+    pub schema_version: u32,
+    pub passed: bool,
+    /// Names of the limits exceeded, such as `max_energy_joules`
+    pub exceeded: Vec<&'static str>,
+    pub energy_joules: f64,
+    pub average_watts: f64,
+    pub duration_seconds: f64,
+    pub max_energy_joules: Option<f64>,
+    pub max_average_watts: Option<f64>,
+}
+
+impl RunLimits {
+    pub fn is_empty(&self) -> bool {
+        self.max_energy_joules.is_none() && self.max_average_watts.is_none()
+    }
+
+    /// Check a run that used `energy_joules` over `duration_seconds`.
+    pub fn check(&self, energy_joules: f64, duration_seconds: f64) -> RunVerdict {
+        let average_watts = if duration_seconds > 0.0 {
+            energy_joules / duration_seconds
+        } else {
+            0.0
+        };
+        let exceeded: Vec<_> = [
+            ("max_energy_joules", self.max_energy_joules, energy_joules),
+            ("max_average_watts", self.max_average_watts, average_watts),
+        ]
+        .into_iter()
+        .filter(|(_, limit, used)| limit.is_some_and(|limit| *used > limit))
+        .map(|(name, _, _)| name)
+        .collect();
+        RunVerdict {
+            schema_version: SCHEMA_VERSION,
+            passed: exceeded.is_empty(),
+            exceeded,
+            energy_joules,
+            average_watts,
+            duration_seconds,
+            max_energy_joules: self.max_energy_joules,
+            max_average_watts: self.max_average_watts,
+        }
+    }
+}
+
+impl MetricsSink for BudgetEnforcer {
+    fn update(&mut self, snapshot: &MetricsSnapshot) {
+        for index in 0..self.budgets.len() {
+            for (key, usage) in self.budgets[index].targets(snapshot) {
+                self.enforce(index, key, usage, snapshot.timestamp);
+            }
+        }
+    }
+}
+
+impl Drop for BudgetEnforcer {
+    fn drop(&mut self) {
+        self.release();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::monitor::{DeviceEnergy, ProcessEnergySnapshot};
+    use crate::power_limits::{GpuPowerLimit, PowerLimitError};
+    use std::sync::{Arc, Mutex};
+
+    /// In-memory processes and cgroups; every call that changes them is
+    /// logged.
+    #[derive(Default)]
+    struct FakeSystem {
+        calls: Vec<String>,
+        members: HashMap<u32, PathBuf>,
+        files: HashMap<(PathBuf, String), String>,
+    }
+
+    #[derive(Default, Clone)]
+    struct RecordingControl {
+        system: Arc<Mutex<FakeSystem>>,
+    }
+
+    impl ProcessControl for RecordingControl {
+        fn signal(&self, pid: u32, signal: ProcessSignal) -> io::Result<()> {
+            let mut system = self.system.lock().unwrap();
+            system.calls.push(format!("{signal:?} {pid}"));
+            Ok(())
+        }
+
+        fn cgroup_of(&self, pid: u32) -> io::Result<PathBuf> {
+            let system = self.system.lock().unwrap();
+            Ok(system.members.get(&pid).cloned().unwrap_or_default())
+        }
+
+        fn read_cgroup_file(&self, cgroup: &Path, file: &str) -> io::Result<String> {
+            let system = self.system.lock().unwrap();
+            if file == "cgroup.procs" {
+                let mut pids: Vec<_> = system
+                    .members
+                    .iter()
+                    .filter(|(_, member_of)| member_of.as_path() == cgroup)
+                    .map(|(pid, _)| pid.to_string())
+                    .collect();
+                pids.sort();
+                return Ok(pids.join("\n"));
+            }
+            let default = if file == "cpu.max" {
+                "max 100000\n"
+            } else {
+                "100\n"
+            };
+            Ok(system
+                .files
+                .get(&(cgroup.to_path_buf(), file.to_string()))
+                .map_or(default.to_string(), Clone::clone))
+        }
+
+        fn write_cgroup_file(&self, cgroup: &Path, file: &str, value: &str) -> io::Result<()> {
+            let mut system = self.system.lock().unwrap();
+            system
+                .calls
+                .push(format!("write {}/{file} {value}", cgroup.display()));
+            if file == "cgroup.procs" {
+                let pid = value.parse().unwrap();
+                system.members.insert(pid, cgroup.to_path_buf());
+            } else {
+                system
+                    .files
+                    .insert((cgroup.to_path_buf(), file.to_string()), value.to_string());
+            }
+            Ok(())
+        }
+
+        fn create_cgroup(&self, cgroup: &Path) -> io::Result<()> {
+            let mut system = self.system.lock().unwrap();
+            system.calls.push(format!("mkdir {}", cgroup.display()));
+            Ok(())
+        }
+
+        fn remove_cgroup(&self, cgroup: &Path) -> io::Result<()> {
+            let mut system = self.system.lock().unwrap();
+            system.calls.push(format!("rmdir {}", cgroup.display()));
+            Ok(())
+        }
+
+        fn nice(&self, _pid: u32) -> io::Result<i32> {
+            Ok(0)
+        }
+
+        fn set_nice(&self, pid: u32, nice: i32) -> io::Result<()> {
+            let mut system = self.system.lock().unwrap();
+            system.calls.push(format!("nice {pid} {nice}"));
+            Ok(())
+        }
+
+        fn io_priority(&self, _pid: u32) -> io::Result<u16> {
+            Ok(0)
+        }
+
+        fn set_io_priority(&self, pid: u32, ioprio: u16) -> io::Result<()> {
+            let mut system = self.system.lock().unwrap();
+            system.calls.push(format!("ioprio {pid} {ioprio:#x}"));
+            Ok(())
+        }
+    }
+
+    /// GPUs by index, logging limits set on the shared fake system.
+    impl GpuPowerControl for RecordingControl {
+        fn uuid(&self, gpu: &str) -> Result<String, PowerLimitError> {
+            Ok(format!("GPU-{gpu}"))
+        }
+
+        fn power_limit(&self, _uuid: &str) -> Result<GpuPowerLimit, PowerLimitError> {
+            Ok(GpuPowerLimit {
+                limit_watts: 300.0,
+                default_watts: 300.0,
+                min_watts: 100.0,
+                max_watts: 300.0,
+                enforced_watts: 300.0,
+                persistence_mode: true,
+            })
+        }
+
+        fn set_power_limit(&self, uuid: &str, watts: f64) -> Result<(), PowerLimitError> {
+            let mut system = self.system.lock().unwrap();
+            system.calls.push(format!("limit {uuid} {watts}"));
+            Ok(())
+        }
+
+        fn set_persistence_mode(&self, _uuid: &str, _enabled: bool) -> Result<(), PowerLimitError> {
+            Ok(())
+        }
+    }
+
+    fn snapshot(timestamp: i64, joules: f64) -> MetricsSnapshot {
+        let energy = DeviceEnergy {
+            cpu_joules: joules,
+            ..DeviceEnergy::default()
+        };
+        MetricsSnapshot {
+            timestamp,
+            workloads: vec![WorkloadSnapshot {
+                root_pid: 10,
+                group_id: "pid:10".to_string(),
+                name: "trainer".to_string(),
+                user: "alice".to_string(),
+                processes: vec![
+                    ProcessEnergySnapshot {
+                        pid: 10,
+                        name: "trainer".to_string(),
+                        energy: energy.clone(),
+                        power_watts: 0.0,
+                    },
+                    ProcessEnergySnapshot {
+                        pid: 11,
+                        name: "worker".to_string(),
+                        energy: DeviceEnergy::default(),
+                        power_watts: 0.0,
+                    },
+                ],
+                is_live: true,
+                energy,
+                power_watts: 0.0,
+                percentage_of_system: 0.0,
+                container: None,
+            }],
+            ..MetricsSnapshot::default()
+        }
+    }
+
+    fn enforcer(budget: EnergyBudget) -> (BudgetEnforcer, Arc<Mutex<FakeSystem>>) {
+        let control = RecordingControl::default();
+        let system = Arc::clone(&control.system);
+        (
+            BudgetEnforcer::with_control(vec![budget], Box::new(control)),
+            system,
+        )
+    }
+
+    fn calls(system: &Arc<Mutex<FakeSystem>>) -> Vec<String> {
+        system.lock().unwrap().calls.clone()
+    }
+
+    #[test]
+    fn duty_cycle_stops_and_resumes_workload_and_releases_on_drop() {
+        let (mut enforcer, system) = enforcer(EnergyBudget {
+            task: Some("train*".to_string()),
+            joules: Some(100.0),
+            action: BudgetAction::DutyCycle,
+            ..EnergyBudget::default()
+        });
+
+        enforcer.update(&snapshot(0, 50.0));
+        assert!(calls(&system).is_empty());
+        enforcer.update(&snapshot(1_000, 150.0));
+        enforcer.update(&snapshot(1_600, 160.0));
+        assert_eq!(enforcer.exceeded(), vec![(0, "pid:10".to_string())]);
+        enforcer.update(&snapshot(2_100, 170.0));
+        assert_eq!(
+            calls(&system),
+            vec!["Stop 10", "Stop 11", "Continue 10", "Continue 11"]
+        );
+
+        enforcer.update(&snapshot(2_600, 180.0));
+        drop(enforcer);
+        assert_eq!(calls(&system).len(), 8);
+    }
+
+    #[test]
+    fn duty_cycle_on_power_budget_holds_until_a_full_period_is_met() {
+        let (mut enforcer, system) = enforcer(EnergyBudget {
+            task: Some("trainer".to_string()),
+            watts: Some(50.0),
+            action: BudgetAction::DutyCycle,
+            period_secs: 2.0,
+            ..EnergyBudget::default()
+        });
+        let mut busy = snapshot(0, 1.0);
+        busy.workloads[0].power_watts = 80.0;
+
+        enforcer.update(&busy);
+        enforcer.update(&snapshot(1_200, 2.0));
+        assert_eq!(enforcer.exceeded(), vec![(0, "pid:10".to_string())]);
+        assert_eq!(calls(&system), vec!["Stop 10", "Stop 11"]);
+
+        enforcer.update(&snapshot(2_000, 2.0));
+        assert!(enforcer.exceeded().is_empty());
+        let mut resumed = calls(&system).split_off(2);
+        resumed.sort();
+        assert_eq!(resumed, vec!["Continue 10", "Continue 11"]);
+    }
+
+    #[test]
+    fn throttle_writes_own_cgroup_and_restores_below_power_budget() {
+        let (mut enforcer, system) = enforcer(EnergyBudget {
+            task: Some("trainer".to_string()),
+            watts: Some(50.0),
+            action: BudgetAction::Throttle,
+            cpu_weight: Some(20),
+            ..EnergyBudget::default()
+        });
+        for pid in [10, 11] {
+            let scope = PathBuf::from("app.slice/trainer.scope");
+            system.lock().unwrap().members.insert(pid, scope);
+        }
+        let mut busy = snapshot(0, 1.0);
+        busy.workloads[0].power_watts = 80.0;
+
+        enforcer.update(&busy);
+        enforcer.update(&busy);
+        enforcer.update(&snapshot(1_000, 2.0));
+
+        assert_eq!(
+            calls(&system),
+            vec![
+                "write app.slice/trainer.scope/cpu.max 10000 100000",
+                "write app.slice/trainer.scope/cpu.weight 20",
+                "write app.slice/trainer.scope/cpu.weight 100",
+                "write app.slice/trainer.scope/cpu.max max 100000",
+            ]
+        );
+        assert!(enforcer.exceeded().is_empty());
+    }
+
+    #[test]
+    fn throttle_moves_processes_out_of_shared_cgroup_until_released() {
+        let (mut enforcer, system) = enforcer(EnergyBudget {
+            pid: Some(11),
+            joules: Some(0.5),
+            action: BudgetAction::Throttle,
+            ..EnergyBudget::default()
+        });
+        system.lock().unwrap().members.insert(12, PathBuf::new());
+        let mut exceeded = snapshot(0, 10.0);
+        exceeded.workloads[0].processes[1].energy.gpu_joules = 1.0;
+
+        enforcer.update(&exceeded);
+        assert_eq!(
+            system.lock().unwrap().members.get(&11),
+            Some(&PathBuf::from("emt-budget-0-11"))
+        );
+        drop(enforcer);
+
+        assert_eq!(
+            calls(&system),
+            vec![
+                "write /cgroup.subtree_control +cpu",
+                "mkdir emt-budget-0-11",
+                "write emt-budget-0-11/cgroup.procs 11",
+                "write emt-budget-0-11/cpu.max 10000 100000",
+                "write emt-budget-0-11/cpu.max max 100000",
+                "write /cgroup.procs 11",
+                "rmdir emt-budget-0-11",
+            ]
+        );
+    }
+
+    #[test]
+    fn throttle_moves_processes_that_join_the_target_later() {
+        let (mut enforcer, system) = enforcer(EnergyBudget {
+            task: Some("trainer".to_string()),
+            watts: Some(50.0),
+            action: BudgetAction::Throttle,
+            ..EnergyBudget::default()
+        });
+        system.lock().unwrap().members.insert(10, PathBuf::new());
+        let mut busy = snapshot(0, 1.0);
+        busy.workloads[0].power_watts = 80.0;
+        busy.workloads[0].processes.truncate(1);
+        enforcer.update(&busy);
+
+        let mut busy_with_child = snapshot(500, 1.0);
+        busy_with_child.workloads[0].power_watts = 80.0;
+        enforcer.update(&busy_with_child);
+        assert_eq!(
+            system.lock().unwrap().members.get(&11),
+            Some(&PathBuf::from("emt-budget-0-10"))
+        );
+        enforcer.update(&snapshot(1_000, 2.0));
+
+        assert_eq!(
+            calls(&system),
+            vec![
+                "write /cgroup.subtree_control +cpu",
+                "mkdir emt-budget-0-10",
+                "write emt-budget-0-10/cgroup.procs 10",
+                "write emt-budget-0-10/cpu.max 10000 100000",
+                "write emt-budget-0-10/cgroup.procs 11",
+                "write emt-budget-0-10/cpu.max max 100000",
+                "write /cgroup.procs 10",
+                "write /cgroup.procs 11",
+                "rmdir emt-budget-0-10",
+            ]
+        );
+    }
+
+    #[test]
+    fn deprioritize_new_processes_and_restore_below_power_budget() {
+        let (mut enforcer, system) = enforcer(EnergyBudget {
+            pid: Some(10),
+            watts: Some(50.0),
+            action: BudgetAction::Deprioritize,
+            ionice: Some(IoClass::Idle),
+            ..EnergyBudget::default()
+        });
+        let mut busy = snapshot(0, 1.0);
+        busy.workloads[0].power_watts = 80.0;
+        busy.workloads[0].processes.truncate(1);
+
+        enforcer.update(&busy);
+        let mut busy_with_child = snapshot(500, 1.0);
+        busy_with_child.workloads[0].power_watts = 80.0;
+        enforcer.update(&busy_with_child);
+        enforcer.update(&snapshot(1_000, 2.0));
+
+        let mut calls = calls(&system);
+        calls[4..].sort();
+        assert_eq!(
+            calls,
+            vec![
+                "nice 10 10",
+                "ioprio 10 0x6000",
+                "nice 11 10",
+                "ioprio 11 0x6000",
+                "ioprio 10 0x0",
+                "ioprio 11 0x0",
+                "nice 10 0",
+                "nice 11 0",
+            ]
+        );
+    }
+
+    #[test]
+    fn cap_gpu_power_lowers_gpu_limits_until_back_within_budget() {
+        let control = RecordingControl::default();
+        let system = Arc::clone(&control.system);
+        let budget = EnergyBudget {
+            task: Some("trainer".to_string()),
+            watts: Some(50.0),
+            action: BudgetAction::CapGpuPower,
+            gpus: vec!["0".to_string(), "1:0".to_string(), "1:1".to_string()],
+            gpu_power_limit_watts: Some(150.0),
+            ..EnergyBudget::default()
+        };
+        assert!(budget.validate().is_ok());
+        let mut enforcer = BudgetEnforcer::with_control(vec![budget], Box::new(control.clone()))
+            .with_gpu_control(Box::new(control));
+        let mut busy = snapshot(0, 1.0);
+        busy.workloads[0].power_watts = 80.0;
+
+        enforcer.update(&busy);
+        enforcer.update(&busy);
+        enforcer.update(&snapshot(1_000, 2.0));
+
+        assert_eq!(
+            calls(&system),
+            vec![
+                "limit GPU-0 150",
+                "limit GPU-1 150",
+                "limit GPU-1 150",
+                "limit GPU-0 300",
+                "limit GPU-1 300",
+            ]
+        );
+    }
+
+    #[test]
+    fn terminate_after_grace_period() {
+        let (mut enforcer, system) = enforcer(EnergyBudget {
+            pid: Some(10),
+            joules: Some(1.0),
+            action: BudgetAction::Terminate,
+            grace_secs: 5.0,
+            ..EnergyBudget::default()
+        });
+        enforcer.update(&snapshot(0, 2.0));
+        enforcer.update(&snapshot(4_000, 3.0));
+        assert!(calls(&system).is_empty());
+        enforcer.update(&snapshot(5_000, 4.0));
+        enforcer.update(&snapshot(6_000, 5.0));
+        assert_eq!(calls(&system), vec!["Terminate 10", "Terminate 11"]);
+    }
+
+    #[test]
+    fn validate_requires_one_target_and_positive_limits() {
+        let budget = EnergyBudget {
+            pid: Some(1),
+            joules: Some(10.0),
+            ..EnergyBudget::default()
+        };
+        assert!(budget.validate().is_ok());
+        assert!(
+            EnergyBudget {
+                task: Some("x".to_string()),
+                ..budget.clone()
+            }
+            .validate()
+            .is_err()
+        );
+        assert!(
+            EnergyBudget {
+                joules: Some(0.0),
+                ..budget.clone()
+            }
+            .validate()
+            .is_err()
+        );
+        assert!(
+            EnergyBudget {
+                joules: None,
+                ..budget.clone()
+            }
+            .validate()
+            .is_err()
+        );
+        assert!(
+            EnergyBudget {
+                cpu_weight: Some(0),
+                ..budget.clone()
+            }
+            .validate()
+            .is_err()
+        );
+        assert!(
+            EnergyBudget {
+                action: BudgetAction::CapGpuPower,
+                gpus: vec!["MIG-5f3e".to_string()],
+                gpu_power_limit_watts: Some(200.0),
+                ..budget
+            }
+            .validate()
+            .is_err()
+        );
+        assert_eq!(
+            parse_stat_nice("42 (a) b) S 1 42 42 0 -1 0 0 0 0 0 0 0 0 0 20 5 1 0"),
+            Some(5)
+        );
+        assert_eq!(
+            unified_cgroup_path("1:name=systemd:/x\n0::/user.slice/a.scope\n"),
+            Some("/user.slice/a.scope")
+        );
+    }
+
+    #[test]
+    fn run_limits_name_every_exceeded_limit() {
+        let limits = RunLimits {
+            max_energy_joules: Some(100.0),
+            max_average_watts: Some(20.0),
+        };
+
+        let within = limits.check(90.0, 10.0);
+        assert!(within.passed);
+        assert!(within.exceeded.is_empty());
+
+        let over_power = limits.check(90.0, 4.0);
+        assert!(!over_power.passed);
+        assert_eq!(over_power.exceeded, ["max_average_watts"]);
+        assert_eq!(over_power.average_watts, 22.5);
+
+        assert_eq!(
+            limits.check(150.0, 5.0).exceeded,
+            ["max_energy_joules", "max_average_watts"]
+        );
+        assert!(RunLimits::default().check(1e9, 0.0).passed);
+    }
+}
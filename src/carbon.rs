@@ -0,0 +1,256 @@
+/// Carbon Module
+///
+/// Converts energy to emissions with the carbon intensity of the grid, in
+/// grams CO2e per kWh. The intensity is static, from
+/// `footprint.carbon_intensity_g_per_kwh`, or fetched periodically from a
+/// [`CarbonIntensityProvider`]. Builds with the `carbon-api` feature
+/// include [`ElectricityMaps`].
+///
+/// Every batch is converted at the intensity current when it arrives, so a
+/// footprint follows the grid over a long run. Energy collected while no
+/// intensity is known is not counted.
+use crate::config::EnergyDomainRule;
+use crate::energy_totals::EnergyTotals;
+use async_trait::async_trait;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Duration;
+use thiserror::Error;
+
+#[cfg(feature = "carbon-api")]
+use crate::config::ElectricityMapsConfig;
+
+pub const JOULES_PER_KWH: f64 = 3_600_000.0;
+
+#[derive(Debug, Error)]
+pub enum CarbonError {
+    #[cfg(feature = "carbon-api")]
+    #[error("HTTP error: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("invalid carbon intensity response: {0}")]
+    Response(String),
+    #[error("environment variable {0} is not set")]
+    MissingToken(String),
+}
+
+/// Source of the current grid carbon intensity.
+#[async_trait]
+pub trait CarbonIntensityProvider: Send + Sync {
+    /// Current intensity in grams CO2e per kWh
+    async fn intensity(&self) -> Result<f64, CarbonError>;
+}
+
+/// The same intensity at all times.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StaticIntensity(pub f64);
+
+#[async_trait]
+impl CarbonIntensityProvider for StaticIntensity {
+    async fn intensity(&self) -> Result<f64, CarbonError> {
+        Ok(self.0)
+    }
+}
+
+/// Emissions of the energy collected so far.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CarbonFootprint {
+    /// Emissions of the platform, each energy domain counted once
+    pub grams_co2e: f64,
+    /// Emissions per device
+    pub devices: BTreeMap<String, f64>,
+    /// Intensity of the latest conversion, in grams CO2e per kWh
+    pub intensity_g_per_kwh: f64,
+}
+
+/// Emissions of `joules` at `intensity` grams CO2e per kWh.
+pub fn grams_co2e(joules: f64, intensity: f64) -> f64 {
+    joules / JOULES_PER_KWH * intensity
+}
+
+/// Latest intensity, shared between the refresh task and the group.
+#[derive(Debug, Clone)]
+pub(crate) struct GridIntensity(Arc<AtomicU64>);
+
+impl Default for GridIntensity {
+    fn default() -> Self {
+        Self(Arc::new(AtomicU64::new(f64::NAN.to_bits())))
+    }
+}
+
+impl GridIntensity {
+    pub(crate) fn get(&self) -> Option<f64> {
+        let intensity = f64::from_bits(self.0.load(Ordering::Relaxed));
+        (!intensity.is_nan()).then_some(intensity)
+    }
+
+    pub(crate) fn set(&self, intensity: f64) {
+        self.0.store(intensity.to_bits(), Ordering::Relaxed);
+    }
+
+    /// Ask `provider` for the current intensity, keeping the last one when
+    /// it fails.
+    pub(crate) async fn refresh(&self, provider: &dyn CarbonIntensityProvider) {
+        match provider.intensity().await {
+            Ok(intensity) if intensity.is_finite() && intensity >= 0.0 => self.set(intensity),
+            Ok(intensity) => log::warn!("Ignoring carbon intensity of {intensity} g/kWh"),
+            Err(e) => log::warn!("Failed to update carbon intensity: {}", e),
+        }
+    }
+}
+
+/// Refresh `intensity` from `provider` every `interval` while `running`.
+pub(crate) async fn run_refresh_loop(
+    provider: Arc<dyn CarbonIntensityProvider>,
+    intensity: GridIntensity,
+    interval: Duration,
+    running: Arc<AtomicBool>,
+) {
+    loop {
+        tokio::time::sleep(interval).await;
+        if !running.load(Ordering::SeqCst) {
+            return;
+        }
+        intensity.refresh(provider.as_ref()).await;
+    }
+}
+
+/// Emissions per device, converted batch by batch.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct CarbonAccount {
+    pub(crate) intensity: GridIntensity,
+    device_grams: BTreeMap<String, f64>,
+}
+
+impl CarbonAccount {
+    /// Emissions of `joules` at the current intensity, if known.
+    pub(crate) fn grams(&self, joules: f64) -> Option<f64> {
+        self.intensity
+            .get()
+            .map(|intensity| grams_co2e(joules, intensity))
+    }
+
+    pub(crate) fn add(&mut self, device: &str, joules: f64) {
+        if let Some(grams) = self.grams(joules) {
+            *self.device_grams.entry(device.to_string()).or_default() += grams;
+        }
+    }
+
+    /// The footprint so far, counting each of `domains` once; `None` while
+    /// no intensity is known.
+    pub(crate) fn footprint(&self, domains: &[EnergyDomainRule]) -> Option<CarbonFootprint> {
+        let intensity_g_per_kwh = self.intensity.get()?;
+        // Emissions are proportional to energy, so domains combine alike.
+        let totals = EnergyTotals::from_devices(domains, &self.device_grams);
+        Some(CarbonFootprint {
+            grams_co2e: totals.total_joules,
+            devices: self.device_grams.clone(),
+            intensity_g_per_kwh,
+        })
+    }
+}
+
+/// Latest carbon intensity of a grid zone from the Electricity Maps API.
+#[cfg(feature = "carbon-api")]
+pub struct ElectricityMaps {
+    client: reqwest::Client,
+    url: String,
+    zone: String,
+    token: String,
+}
+
+#[cfg(feature = "carbon-api")]
+impl ElectricityMaps {
+    pub fn new(zone: impl Into<String>, token: impl Into<String>) -> Self {
+        let defaults = ElectricityMapsConfig::default();
+        Self {
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_secs(10))
+                .build()
+                .unwrap_or_default(),
+            url: defaults.url,
+            zone: zone.into(),
+            token: token.into(),
+        }
+    }
+
+    /// Provider for `config`, reading the token from its environment
+    /// variable.
+    pub fn from_config(config: &ElectricityMapsConfig) -> Result<Self, CarbonError> {
+        let token = std::env::var(&config.token_env)
+            .map_err(|_| CarbonError::MissingToken(config.token_env.clone()))?;
+        Ok(Self::new(&config.zone, token).with_url(&config.url))
+    }
+
+    /// Query `url` instead of the public API.
+    pub fn with_url(mut self, url: impl Into<String>) -> Self {
+        self.url = url.into();
+        self
+    }
+}
+
+#[cfg(feature = "carbon-api")]
+#[async_trait]
+impl CarbonIntensityProvider for ElectricityMaps {
+    async fn intensity(&self) -> Result<f64, CarbonError> {
+        let body = self
+            .client
+            .get(&self.url)
+            .query(&[("zone", &self.zone)])
+            .header("auth-token", &self.token)
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+        parse_electricity_maps(&body)
+    }
+}
+
+/// `carbonIntensity` of an Electricity Maps latest intensity response.
+#[cfg(feature = "carbon-api")]
+fn parse_electricity_maps(body: &str) -> Result<f64, CarbonError> {
+    #[derive(serde::Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Latest {
+        carbon_intensity: f64,
+    }
+    serde_json::from_str::<Latest>(body)
+        .map(|latest| latest.carbon_intensity)
+        .map_err(|e| CarbonError::Response(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::default_energy_domains;
+
+    #[tokio::test]
+    async fn converts_each_batch_at_the_intensity_current_then() {
+        let mut account = CarbonAccount::default();
+        account.add("rapl:socket:0:package", 3_600_000.0);
+        assert_eq!(account.footprint(&default_energy_domains()), None);
+
+        account.intensity.refresh(&StaticIntensity(400.0)).await;
+        account.add("rapl:socket:0:package", 3_600_000.0);
+        account.intensity.refresh(&StaticIntensity(100.0)).await;
+        account.add("rapl:socket:0:package", 1_800_000.0);
+        account.add("rapl:socket:0:dram", 1_800_000.0);
+        // A negative intensity is ignored.
+        account.intensity.refresh(&StaticIntensity(-1.0)).await;
+
+        let footprint = account.footprint(&default_energy_domains()).unwrap();
+        assert_eq!(footprint.grams_co2e, 500.0);
+        assert_eq!(footprint.devices["rapl:socket:0:package"], 450.0);
+        assert_eq!(footprint.intensity_g_per_kwh, 100.0);
+    }
+
+    #[cfg(feature = "carbon-api")]
+    #[test]
+    fn parses_the_latest_electricity_maps_intensity() {
+        let body = r#"{"zone":"DE","carbonIntensity":302,"datetime":"2026-10-16T10:00:00.000Z"}"#;
+        assert_eq!(parse_electricity_maps(body).unwrap(), 302.0);
+        assert!(parse_electricity_maps("{}").is_err());
+    }
+}
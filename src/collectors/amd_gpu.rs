@@ -0,0 +1,526 @@
+/// AMD GPU Collector
+///
+/// Reads AMD GPUs through the amdgpu driver's hwmon interface under
+/// `/sys/class/drm/card*/device/hwmon`, without the ROCm SMI library. The
+/// cumulative `energy1_input` counter is differenced where the driver
+/// exposes it; otherwise the average power is integrated over the time
+/// since the previous read.
+///
+/// Energy is attributed like RAPL attributes CPU energy: each tracked
+/// process gets the share of the interval its engines were busy on the GPU,
+/// from the `drm-engine-*` times in its DRM fdinfo, and the rest is recorded
+/// as unattributed.
+use crate::collectors::drm_fdinfo::{attribute_busy_share, usage_by_slot};
+use crate::device_registry::DeviceInfo;
+use crate::energy_group::{EnergyCollector, EnergyRecord};
+use crate::read_schedule::ReadSchedule;
+use crate::utils::errors::CollectorError;
+use crate::utils::time::RecordTime;
+use async_trait::async_trait;
+use log::{debug, warn};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, RwLock};
+use std::time::Instant;
+
+const DEFAULT_DRM_PATH: &str = "/sys/class/drm";
+const DEFAULT_PROC_PATH: &str = "/proc";
+/// PCI vendor id of AMD in `device/vendor`.
+const AMD_PCI_VENDOR: &str = "0x1002";
+/// `drm-driver` of amdgpu clients in fdinfo.
+const AMD_DRM_DRIVER: &str = "amdgpu";
+
+/// An AMD GPU found under the DRM class directory.
+#[derive(Debug, Clone, PartialEq)]
+struct AmdCard {
+    /// Number of the `card<N>` entry
+    index: u32,
+    /// PCI address, matched against `drm-pdev` in fdinfo
+    pci_slot: Option<String>,
+    /// The card's `device` directory
+    device_dir: PathBuf,
+    /// The card's hwmon directory
+    hwmon_dir: PathBuf,
+}
+
+impl AmdCard {
+    fn device_name(&self) -> String {
+        format!("amd:gpu:{}", self.index)
+    }
+
+    /// Read the card's energy: the cumulative counter in microjoules when
+    /// the driver has one, otherwise the power in microwatts.
+    fn read(&self) -> Result<HwmonReading, String> {
+        let energy_path = self.hwmon_dir.join("energy1_input");
+        if energy_path.exists() {
+            return read_u64(&energy_path).map(HwmonReading::EnergyMicrojoules);
+        }
+        ["power1_average", "power1_input"]
+            .iter()
+            .map(|file| self.hwmon_dir.join(file))
+            .find(|path| path.exists())
+            .ok_or_else(|| format!("no energy or power file in {}", self.hwmon_dir.display()))
+            .and_then(|path| read_u64(&path))
+            .map(HwmonReading::PowerMicrowatts)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum HwmonReading {
+    EnergyMicrojoules(u64),
+    PowerMicrowatts(u64),
+}
+
+/// Previous read of a card.
+#[derive(Debug, Default)]
+struct CardState {
+    reading: Option<HwmonReading>,
+    monotonic_ns: i64,
+    /// Cumulative engine busy time per tracked pid, in nanoseconds
+    busy_ns: HashMap<u32, u64>,
+}
+
+/// AMD GPU energy collector using sysfs hwmon and DRM fdinfo.
+pub struct AmdGpu {
+    drm_dir: PathBuf,
+    proc_dir: PathBuf,
+    /// GPUs as of the last discovery
+    cards: RwLock<Vec<AmdCard>>,
+    /// PIDs to attribute energy to
+    tracked_pids: Mutex<Vec<u32>>,
+    /// Previous read per card index
+    state: Mutex<HashMap<u32, CardState>>,
+    /// Offsets of the GPU reads within each tick
+    read_schedule: Mutex<ReadSchedule>,
+}
+
+impl AmdGpu {
+    /// Construct a collector for the AMD GPUs under `drm_path`, by default
+    /// `/sys/class/drm`.
+    pub fn new(drm_path: Option<String>) -> Self {
+        let drm_dir = PathBuf::from(drm_path.unwrap_or_else(|| DEFAULT_DRM_PATH.to_string()));
+        let cards = discover_cards(&drm_dir);
+        Self {
+            drm_dir,
+            proc_dir: PathBuf::from(DEFAULT_PROC_PATH),
+            cards: RwLock::new(cards),
+            tracked_pids: Mutex::new(Vec::new()),
+            state: Mutex::new(HashMap::new()),
+            read_schedule: Mutex::new(ReadSchedule::default()),
+        }
+    }
+
+    /// Engine busy time of each tracked process per GPU PCI address, in
+    /// nanoseconds, from DRM fdinfo.
+    fn busy_ns_by_slot(&self, pids: &[u32]) -> HashMap<String, HashMap<u32, u64>> {
+        usage_by_slot(&self.proc_dir, pids, &[AMD_DRM_DRIVER])
+            .into_iter()
+            .map(|(slot, usage)| {
+                let busy = usage
+                    .into_iter()
+                    .map(|(pid, usage)| (pid, usage.engine_ns))
+                    .collect();
+                (slot, busy)
+            })
+            .collect()
+    }
+}
+
+impl Default for AmdGpu {
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+
+fn read_u64(path: &Path) -> Result<u64, String> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+    contents
+        .trim()
+        .parse()
+        .map_err(|e| format!("invalid value in {}: {}", path.display(), e))
+}
+
+fn read_trimmed(path: &Path) -> Option<String> {
+    fs::read_to_string(path)
+        .ok()
+        .map(|contents| contents.trim().to_string())
+        .filter(|contents| !contents.is_empty())
+}
+
+/// The AMD GPUs under `drm_dir` that have a hwmon directory, by card number.
+fn discover_cards(drm_dir: &Path) -> Vec<AmdCard> {
+    let Ok(entries) = fs::read_dir(drm_dir) else {
+        return Vec::new();
+    };
+    let mut cards: Vec<AmdCard> = entries
+        .flatten()
+        .filter_map(|entry| {
+            // Connectors such as card0-DP-1 are skipped by the parse.
+            let index = entry
+                .file_name()
+                .to_str()?
+                .strip_prefix("card")?
+                .parse()
+                .ok()?;
+            let device_dir = entry.path().join("device");
+            if read_trimmed(&device_dir.join("vendor")).as_deref() != Some(AMD_PCI_VENDOR) {
+                return None;
+            }
+            let hwmon_dir = fs::read_dir(device_dir.join("hwmon"))
+                .ok()?
+                .flatten()
+                .map(|hwmon| hwmon.path())
+                .filter(|path| {
+                    path.file_name()
+                        .and_then(|name| name.to_str())
+                        .is_some_and(|name| name.starts_with("hwmon"))
+                })
+                .min()?;
+            let pci_slot = read_trimmed(&device_dir.join("uevent")).and_then(|uevent| {
+                uevent
+                    .lines()
+                    .find_map(|line| line.strip_prefix("PCI_SLOT_NAME="))
+                    .map(str::to_string)
+            });
+            Some(AmdCard {
+                index,
+                pci_slot,
+                device_dir,
+                hwmon_dir,
+            })
+        })
+        .collect();
+    cards.sort_by_key(|card| card.index);
+    cards
+}
+
+#[async_trait]
+impl EnergyCollector for AmdGpu {
+    fn set_tracked_pids(&self, pids: Vec<u32>) {
+        *self.tracked_pids.lock().unwrap() = pids;
+    }
+
+    fn set_read_schedule(&self, schedule: ReadSchedule) {
+        *self.read_schedule.lock().unwrap() = schedule;
+    }
+
+    async fn get_energy_trace(&self) -> Result<Vec<EnergyRecord>, CollectorError> {
+        let tick_start = Instant::now();
+        let schedule = self.read_schedule.lock().unwrap().clone();
+        let cards = self.cards.read().unwrap().clone();
+        let pids = self.tracked_pids.lock().unwrap().clone();
+        let mut busy_by_slot = self.busy_ns_by_slot(&pids);
+
+        let mut records = Vec::new();
+        for card in cards {
+            let device = card.device_name();
+            schedule.wait(tick_start, &device).await;
+            let reading = match card.read() {
+                Ok(reading) => reading,
+                Err(e) => {
+                    warn!("Failed to read AMD GPU {}: {}", card.index, e);
+                    continue;
+                }
+            };
+            let time = RecordTime::now();
+            let busy_ns = card
+                .pci_slot
+                .as_ref()
+                .and_then(|slot| busy_by_slot.remove(slot))
+                .unwrap_or_default();
+
+            let mut state = self.state.lock().unwrap();
+            let state = state.entry(card.index).or_default();
+            let elapsed_ns = (time.monotonic_ns - state.monotonic_ns).max(0) as u64;
+            let (delta_joules, raw_counter) = match (state.reading, reading) {
+                (
+                    Some(HwmonReading::EnergyMicrojoules(previous)),
+                    HwmonReading::EnergyMicrojoules(current),
+                ) => (
+                    current.saturating_sub(previous) as f64 / 1e6,
+                    Some(current as f64 / 1e6),
+                ),
+                (
+                    Some(HwmonReading::PowerMicrowatts(_)),
+                    HwmonReading::PowerMicrowatts(current),
+                ) => (current as f64 / 1e6 * elapsed_ns as f64 / 1e9, None),
+                // First read, or the driver switched files: a new baseline.
+                _ => (0.0, None),
+            };
+            let busy_deltas: Vec<(u32, u64)> = busy_ns
+                .iter()
+                .map(|(&pid, &busy)| {
+                    let previous = state.busy_ns.get(&pid).copied().unwrap_or(busy);
+                    (pid, busy.saturating_sub(previous))
+                })
+                .collect();
+            state.reading = Some(reading);
+            state.monotonic_ns = time.monotonic_ns;
+            state.busy_ns = busy_ns;
+
+            records.extend(attribute_busy_share(
+                &device,
+                delta_joules,
+                elapsed_ns,
+                &busy_deltas,
+                time,
+                raw_counter,
+            ));
+        }
+
+        debug!("AMD GPU energy trace collected: {} records", records.len());
+        Ok(records)
+    }
+
+    fn devices(&self) -> Vec<DeviceInfo> {
+        self.cards
+            .read()
+            .unwrap()
+            .iter()
+            .map(|card| {
+                let max_power_watts = read_u64(&card.hwmon_dir.join("power1_cap_max"))
+                    .ok()
+                    .map(|microwatts| microwatts as f64 / 1e6);
+                DeviceInfo::new(card.device_name())
+                    .with_vendor(Some("AMD".to_string()))
+                    .with_model(read_trimmed(&card.device_dir.join("product_name")))
+                    .with_max_power_watts(max_power_watts)
+                    .with_source(Some("hwmon".to_string()))
+            })
+            .collect()
+    }
+
+    fn rediscover(&self) -> bool {
+        let cards = discover_cards(&self.drm_dir);
+        let mut current = self.cards.write().unwrap();
+        if *current == cards {
+            return false;
+        }
+        log::info!(
+            "AMD GPUs changed: {} -> {} devices",
+            current.len(),
+            cards.len()
+        );
+        // A card number may now belong to another GPU, so only cards found
+        // unchanged keep their baselines.
+        self.state.lock().unwrap().retain(|index, _| {
+            current
+                .iter()
+                .any(|card| card.index == *index && cards.contains(card))
+        });
+        *current = cards;
+        true
+    }
+
+    fn is_available() -> bool {
+        discover_cards(Path::new(DEFAULT_DRM_PATH))
+            .iter()
+            .any(|card| card.read().is_ok())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collectors::drm_fdinfo::{
+        DrmClient, EngineUsage, UNATTRIBUTED_PID, parse_drm_fdinfo,
+    };
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    struct TempTestDir {
+        path: PathBuf,
+    }
+
+    impl TempTestDir {
+        fn new(name: &str) -> Self {
+            let unique = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_nanos();
+            let path = std::env::temp_dir().join(format!(
+                "emt-amd-{}-{}-{}",
+                name,
+                std::process::id(),
+                unique
+            ));
+            fs::create_dir_all(&path).unwrap();
+            Self { path }
+        }
+    }
+
+    impl Drop for TempTestDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.path);
+        }
+    }
+
+    /// A card with a hwmon directory holding `files`.
+    fn write_card(root: &Path, entry: &str, vendor: &str, slot: &str, files: &[(&str, &str)]) {
+        let device_dir = root.join(entry).join("device");
+        let hwmon_dir = device_dir.join("hwmon").join("hwmon3");
+        fs::create_dir_all(&hwmon_dir).unwrap();
+        fs::write(device_dir.join("vendor"), format!("{vendor}\n")).unwrap();
+        fs::write(
+            device_dir.join("uevent"),
+            format!("DRIVER=amdgpu\nPCI_SLOT_NAME={slot}\n"),
+        )
+        .unwrap();
+        for (file, contents) in files {
+            fs::write(hwmon_dir.join(file), contents).unwrap();
+        }
+    }
+
+    fn fdinfo(slot: &str, client_id: u64, gfx_ns: u64) -> String {
+        format!(
+            "pos:\t0\nflags:\t02100002\ndrm-driver:\tamdgpu\ndrm-client-id:\t{client_id}\n\
+             drm-pdev:\t{slot}\ndrm-memory-vram:\t4460 KiB\ndrm-engine-gfx:\t{gfx_ns} ns\n\
+             drm-engine-compute:\t0 ns\n"
+        )
+    }
+
+    #[test]
+    fn discovers_amd_cards_with_hwmon() {
+        let dir = TempTestDir::new("discover");
+        write_card(
+            &dir.path,
+            "card1",
+            AMD_PCI_VENDOR,
+            "0000:03:00.0",
+            &[("energy1_input", "5")],
+        );
+        write_card(
+            &dir.path,
+            "card0",
+            "0x8086",
+            "0000:00:02.0",
+            &[("power1_average", "5")],
+        );
+        fs::create_dir_all(dir.path.join("card1-DP-1")).unwrap();
+        fs::write(dir.path.join("version"), "drm 1.1.0").unwrap();
+
+        let cards = discover_cards(&dir.path);
+
+        assert_eq!(cards.len(), 1);
+        assert_eq!(cards[0].index, 1);
+        assert_eq!(cards[0].pci_slot.as_deref(), Some("0000:03:00.0"));
+        assert_eq!(cards[0].read(), Ok(HwmonReading::EnergyMicrojoules(5)));
+    }
+
+    #[test]
+    fn parses_amdgpu_fdinfo_and_ignores_other_files() {
+        let mut contents = fdinfo("0000:03:00.0", 14, 1_000);
+        contents.push_str("drm-engine-dma:\t500 ns\ndrm-engine-capacity-gfx:\t2\n");
+        assert_eq!(
+            parse_drm_fdinfo(&contents, &[AMD_DRM_DRIVER]),
+            Some(DrmClient {
+                pdev: "0000:03:00.0".to_string(),
+                client_id: 14,
+                usage: EngineUsage {
+                    engine_ns: 1_500,
+                    ..EngineUsage::default()
+                },
+            })
+        );
+        assert_eq!(
+            parse_drm_fdinfo("pos:\t0\nflags:\t02\nmnt_id:\t24\n", &[AMD_DRM_DRIVER]),
+            None
+        );
+        assert_eq!(
+            parse_drm_fdinfo(
+                &fdinfo("0000:03:00.0", 1, 1).replace("amdgpu", "i915"),
+                &[AMD_DRM_DRIVER]
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn attributes_busy_share_and_records_the_rest_as_unattributed() {
+        let time = RecordTime {
+            timestamp_us: 1_000,
+            monotonic_ns: 2_000,
+        };
+        let records =
+            attribute_busy_share("amd:gpu:0", 10.0, 1_000, &[(7, 250), (8, 0)], time, None);
+        let energies: Vec<(u32, f64)> = records.iter().map(|r| (r.pid, r.energy)).collect();
+        assert_eq!(energies, [(7, 2.5), (UNATTRIBUTED_PID, 7.5)]);
+
+        // Engines busier than the interval in total share all of it.
+        let records = attribute_busy_share(
+            "amd:gpu:0",
+            9.0,
+            1_000,
+            &[(7, 1_000), (8, 2_000)],
+            time,
+            None,
+        );
+        let energies: Vec<(u32, f64)> = records.iter().map(|r| (r.pid, r.energy)).collect();
+        assert_eq!(energies, [(7, 3.0), (8, 6.0)]);
+
+        assert!(attribute_busy_share("amd:gpu:0", 0.0, 1_000, &[(7, 1)], time, None).is_empty());
+    }
+
+    #[tokio::test]
+    async fn differences_energy_and_attributes_it_to_tracked_clients() {
+        let drm = TempTestDir::new("drm");
+        let proc = TempTestDir::new("proc");
+        write_card(
+            &drm.path,
+            "card0",
+            AMD_PCI_VENDOR,
+            "0000:03:00.0",
+            &[("energy1_input", "1000000")],
+        );
+        let fdinfo_dir = proc.path.join("42").join("fdinfo");
+        fs::create_dir_all(&fdinfo_dir).unwrap();
+        fs::write(fdinfo_dir.join("5"), fdinfo("0000:03:00.0", 3, 0)).unwrap();
+
+        let mut gpu = AmdGpu::new(Some(drm.path.to_string_lossy().into_owned()));
+        gpu.proc_dir = proc.path.clone();
+        gpu.set_tracked_pids(vec![42]);
+        assert!(gpu.get_energy_trace().await.unwrap().is_empty());
+
+        let energy_path = drm.path.join("card0/device/hwmon/hwmon3/energy1_input");
+        fs::write(&energy_path, "3000000").unwrap();
+        // Busy for longer than the test ran, so the process gets it all;
+        // the same client through a second descriptor counts once.
+        let busy_ns = 1_000_000_000_000;
+        fs::write(fdinfo_dir.join("5"), fdinfo("0000:03:00.0", 3, busy_ns)).unwrap();
+        fs::write(fdinfo_dir.join("6"), fdinfo("0000:03:00.0", 3, busy_ns)).unwrap();
+        assert_eq!(
+            gpu.busy_ns_by_slot(&[42]),
+            HashMap::from([("0000:03:00.0".to_string(), HashMap::from([(42, busy_ns)]))])
+        );
+        let records = gpu.get_energy_trace().await.unwrap();
+
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].pid, 42);
+        assert_eq!(records[0].device, "amd:gpu:0");
+        assert!((records[0].energy - 2.0).abs() < 1e-9);
+        assert_eq!(records[0].raw_counter, Some(3.0));
+
+        let devices = gpu.devices();
+        assert_eq!(devices[0].vendor.as_deref(), Some("AMD"));
+        assert_eq!(devices[0].source.as_deref(), Some("hwmon"));
+    }
+
+    #[test]
+    fn rediscovery_reports_added_cards() {
+        let drm = TempTestDir::new("rediscover");
+        let gpu = AmdGpu::new(Some(drm.path.to_string_lossy().into_owned()));
+        assert!(gpu.devices().is_empty());
+        assert!(!gpu.rediscover());
+
+        write_card(
+            &drm.path,
+            "card0",
+            AMD_PCI_VENDOR,
+            "0000:03:00.0",
+            &[("power1_average", "150000000")],
+        );
+        assert!(gpu.rediscover());
+        assert_eq!(gpu.devices()[0].name, "amd:gpu:0");
+    }
+}
@@ -0,0 +1,401 @@
+/// Apple Silicon Collector
+///
+/// Reads the CPU, GPU and Neural Engine (ANE) power of Apple Silicon Macs
+/// from `powermetrics`, which samples the SMC and IOReport energy channels.
+/// One `powermetrics --samplers cpu_power,gpu_power` process runs for the
+/// life of the collector; the power of every sample is integrated over the
+/// sample's elapsed time into a cumulative energy counter per domain, which
+/// is differenced between reads like a RAPL counter. `powermetrics` needs
+/// root.
+///
+/// CPU energy is attributed by each tracked process's CPU time, as a share
+/// of the time of all logical CPUs over the interval. The rest of it, and
+/// all GPU and ANE energy, is recorded as unattributed.
+use crate::collectors::drm_fdinfo::attribute_busy_share;
+use crate::device_registry::DeviceInfo;
+use crate::energy_group::{EnergyCollector, EnergyRecord};
+use crate::utils::errors::CollectorError;
+use crate::utils::time::RecordTime;
+use async_trait::async_trait;
+use std::collections::{BTreeMap, HashMap};
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use sysinfo::{Pid, ProcessRefreshKind, ProcessesToUpdate, System};
+
+const DEFAULT_POWERMETRICS_PATH: &str = "/usr/bin/powermetrics";
+const DEFAULT_SAMPLE_INTERVAL: Duration = Duration::from_millis(250);
+/// Power line prefix in `powermetrics` output and device name of each
+/// domain.
+const DOMAINS: [(&str, &str); 3] = [
+    ("CPU", "apple:cpu"),
+    ("GPU", "apple:gpu"),
+    ("ANE", "apple:ane"),
+];
+const CPU_DEVICE: &str = "apple:cpu";
+
+/// Power of each domain over one `powermetrics` sample.
+#[derive(Debug, Default, PartialEq)]
+struct Sample {
+    elapsed_seconds: f64,
+    /// Watts by device name
+    watts: BTreeMap<&'static str, f64>,
+}
+
+/// Splits `powermetrics` text output into samples. A sample is complete
+/// once the header of the next one arrives.
+#[derive(Debug, Default)]
+struct SampleParser {
+    current: Option<Sample>,
+}
+
+impl SampleParser {
+    /// Feed one line, returning the sample it completes.
+    fn push_line(&mut self, line: &str) -> Option<Sample> {
+        if let Some(elapsed_seconds) = parse_sample_header(line) {
+            return self.current.replace(Sample {
+                elapsed_seconds,
+                ..Sample::default()
+            });
+        }
+        if let Some(sample) = &mut self.current
+            && let Some((device, watts)) = parse_power_line(line)
+        {
+            // GPU power is printed in both samplers; the first counts.
+            sample.watts.entry(device).or_insert(watts);
+        }
+        None
+    }
+}
+
+/// Elapsed seconds of a sample header such as
+/// `*** Sampled system activity (Thu Oct 16 10:00:00 2026 +0200) (1004.52ms elapsed) ***`.
+fn parse_sample_header(line: &str) -> Option<f64> {
+    let rest = line.trim().strip_prefix("*** Sampled system activity")?;
+    let (before, _) = rest.rsplit_once("ms elapsed)")?;
+    let (_, millis) = before.rsplit_once('(')?;
+    millis.trim().parse::<f64>().ok().map(|millis| millis / 1e3)
+}
+
+/// Device and watts of a line such as `CPU Power: 532 mW`.
+fn parse_power_line(line: &str) -> Option<(&'static str, f64)> {
+    let (key, value) = line.split_once(':')?;
+    let name = key.trim().strip_suffix(" Power")?;
+    let (_, device) = DOMAINS.iter().find(|(prefix, _)| *prefix == name)?;
+    let value = value.trim();
+    let watts = if let Some(milliwatts) = value.strip_suffix("mW") {
+        milliwatts.trim().parse::<f64>().ok()? / 1e3
+    } else {
+        value.strip_suffix('W')?.trim().parse::<f64>().ok()?
+    };
+    (watts.is_finite() && watts >= 0.0).then_some((*device, watts))
+}
+
+/// Energy integrated from the samples so far.
+#[derive(Debug, Default)]
+struct Counters {
+    /// Joules by device name
+    joules: BTreeMap<&'static str, f64>,
+    /// Set once `powermetrics` exited
+    exited: bool,
+}
+
+impl Counters {
+    fn add(&mut self, sample: &Sample) {
+        for (&device, &watts) in &sample.watts {
+            *self.joules.entry(device).or_default() += watts * sample.elapsed_seconds;
+        }
+    }
+}
+
+/// Previous read of the collector.
+#[derive(Debug, Default)]
+struct ReadState {
+    joules: BTreeMap<&'static str, f64>,
+    monotonic_ns: i64,
+    /// CPU time by pid, in nanoseconds
+    cpu_ns: HashMap<u32, u64>,
+}
+
+/// Apple Silicon energy collector using `powermetrics`.
+pub struct AppleSmc {
+    powermetrics: PathBuf,
+    sample_interval: Duration,
+    /// The running `powermetrics`, started on the first read
+    child: Mutex<Option<Child>>,
+    counters: Arc<Mutex<Counters>>,
+    /// PIDs to attribute CPU energy to
+    tracked_pids: Mutex<Vec<u32>>,
+    system: Mutex<System>,
+    state: Mutex<Option<ReadState>>,
+}
+
+impl AppleSmc {
+    /// Construct a collector running `powermetrics` from
+    /// `powermetrics_path`, by default `/usr/bin/powermetrics`.
+    pub fn new(powermetrics_path: Option<String>) -> Self {
+        Self {
+            powermetrics: PathBuf::from(
+                powermetrics_path.unwrap_or_else(|| DEFAULT_POWERMETRICS_PATH.to_string()),
+            ),
+            sample_interval: DEFAULT_SAMPLE_INTERVAL,
+            child: Mutex::new(None),
+            counters: Arc::new(Mutex::new(Counters::default())),
+            tracked_pids: Mutex::new(Vec::new()),
+            system: Mutex::new(System::new()),
+            state: Mutex::new(None),
+        }
+    }
+
+    /// Sample every `interval` instead of every 250 ms.
+    pub fn with_sample_interval(mut self, interval: Duration) -> Self {
+        self.sample_interval = interval;
+        self
+    }
+
+    /// Start `powermetrics` and the thread integrating its samples, unless
+    /// running.
+    fn ensure_started(&self) -> Result<(), CollectorError> {
+        let mut child = self.child.lock().unwrap();
+        if child.is_some() {
+            return Ok(());
+        }
+        let mut process = Command::new(&self.powermetrics)
+            .args(["--samplers", "cpu_power,gpu_power", "-i"])
+            .arg(self.sample_interval.as_millis().max(1).to_string())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| CollectorError::from_io(self.powermetrics.display(), &e))?;
+        let stdout = process.stdout.take().expect("stdout is piped");
+        let counters = Arc::clone(&self.counters);
+        std::thread::spawn(move || {
+            let mut parser = SampleParser::default();
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else { break };
+                if let Some(sample) = parser.push_line(&line) {
+                    counters.lock().unwrap().add(&sample);
+                }
+            }
+            counters.lock().unwrap().exited = true;
+        });
+        *child = Some(process);
+        Ok(())
+    }
+
+    /// CPU time of each of `pids` still running, in nanoseconds.
+    fn cpu_times(&self, pids: &[u32]) -> HashMap<u32, u64> {
+        let mut system = self.system.lock().unwrap();
+        let sys_pids: Vec<Pid> = pids.iter().map(|&pid| Pid::from_u32(pid)).collect();
+        system.refresh_processes_specifics(
+            ProcessesToUpdate::Some(&sys_pids),
+            true,
+            ProcessRefreshKind::nothing().with_cpu(),
+        );
+        pids.iter()
+            .filter_map(|&pid| {
+                let process = system.process(Pid::from_u32(pid))?;
+                Some((pid, process.accumulated_cpu_time() * 1_000_000))
+            })
+            .collect()
+    }
+}
+
+impl Default for AppleSmc {
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+
+impl Drop for AppleSmc {
+    fn drop(&mut self) {
+        if let Some(mut child) = self.child.lock().unwrap().take() {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
+
+fn logical_cpu_count() -> u64 {
+    std::thread::available_parallelism().map_or(1, |count| count.get() as u64)
+}
+
+/// Records for the energy integrated since `previous`, or none for the
+/// first read, which is a baseline.
+fn records_since(
+    previous: Option<&ReadState>,
+    current: &ReadState,
+    time: RecordTime,
+    cpu_count: u64,
+) -> Vec<EnergyRecord> {
+    let Some(previous) = previous else {
+        return Vec::new();
+    };
+    let elapsed_ns = (current.monotonic_ns - previous.monotonic_ns).max(0) as u64;
+    let mut records = Vec::new();
+    for (&device, &joules) in &current.joules {
+        let delta_joules = joules - previous.joules.get(device).copied().unwrap_or(0.0);
+        let (capacity_ns, busy_deltas) = if device == CPU_DEVICE {
+            let busy: Vec<(u32, u64)> = current
+                .cpu_ns
+                .iter()
+                .map(|(&pid, &cpu_ns)| {
+                    let before = previous.cpu_ns.get(&pid).copied().unwrap_or(cpu_ns);
+                    (pid, cpu_ns.saturating_sub(before))
+                })
+                .collect();
+            (elapsed_ns * cpu_count, busy)
+        } else {
+            (elapsed_ns, Vec::new())
+        };
+        records.extend(attribute_busy_share(
+            device,
+            delta_joules,
+            capacity_ns,
+            &busy_deltas,
+            time,
+            Some(joules),
+        ));
+    }
+    records
+}
+
+#[async_trait]
+impl EnergyCollector for AppleSmc {
+    fn set_tracked_pids(&self, pids: Vec<u32>) {
+        *self.tracked_pids.lock().unwrap() = pids;
+    }
+
+    async fn get_energy_trace(&self) -> Result<Vec<EnergyRecord>, CollectorError> {
+        self.ensure_started()?;
+        let (joules, exited) = {
+            let counters = self.counters.lock().unwrap();
+            (counters.joules.clone(), counters.exited)
+        };
+        if exited {
+            return Err(CollectorError::DeviceLost(format!(
+                "{} exited",
+                self.powermetrics.display()
+            )));
+        }
+        let pids = self.tracked_pids.lock().unwrap().clone();
+        let cpu_ns = self.cpu_times(&pids);
+        let time = RecordTime::now();
+        let current = ReadState {
+            joules,
+            monotonic_ns: time.monotonic_ns,
+            cpu_ns,
+        };
+
+        let mut state = self.state.lock().unwrap();
+        let records = records_since(state.as_ref(), &current, time, logical_cpu_count());
+        *state = Some(current);
+        Ok(records)
+    }
+
+    fn devices(&self) -> Vec<DeviceInfo> {
+        DOMAINS
+            .iter()
+            .map(|(_, device)| {
+                DeviceInfo::new(device.to_string())
+                    .with_vendor(Some("Apple".to_string()))
+                    .with_source(Some("powermetrics".to_string()))
+            })
+            .collect()
+    }
+
+    fn is_available() -> bool {
+        cfg!(target_os = "macos") && Path::new(DEFAULT_POWERMETRICS_PATH).exists()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OUTPUT: &str = "\
+Machine model: Mac14,2
+*** Sampled system activity (Thu Oct 16 10:00:00 2026 +0200) (500.00ms elapsed) ***
+
+**** Processor usage ****
+
+E-Cluster HW active frequency: 1020 MHz
+CPU Power: 1200 mW
+GPU Power: 40 mW
+ANE Power: 0 mW
+Combined Power (CPU + GPU + ANE): 1240 mW
+
+**** GPU usage ****
+
+GPU HW active frequency: 389 MHz
+GPU Power: 41 mW
+*** Sampled system activity (Thu Oct 16 10:00:00 2026 +0200) (250.00ms elapsed) ***
+CPU Power: 2.4 W
+";
+
+    #[test]
+    fn integrates_power_of_each_sample_over_its_elapsed_time() {
+        let mut parser = SampleParser::default();
+        let samples: Vec<Sample> = OUTPUT
+            .lines()
+            .filter_map(|line| parser.push_line(line))
+            .collect();
+        assert_eq!(
+            samples,
+            [Sample {
+                elapsed_seconds: 0.5,
+                watts: BTreeMap::from([
+                    ("apple:ane", 0.0),
+                    ("apple:cpu", 1.2),
+                    ("apple:gpu", 0.04),
+                ]),
+            }]
+        );
+
+        // The second sample completes with the next header.
+        let next = parser
+            .push_line("*** Sampled system activity (x) (250.00ms elapsed) ***")
+            .unwrap();
+        let mut counters = Counters::default();
+        counters.add(&samples[0]);
+        counters.add(&next);
+        assert!((counters.joules["apple:cpu"] - 1.2).abs() < 1e-12);
+        assert!((counters.joules["apple:gpu"] - 0.02).abs() < 1e-12);
+    }
+
+    #[test]
+    fn attributes_cpu_energy_by_cpu_time_and_the_rest_as_unattributed() {
+        let time = RecordTime {
+            timestamp_us: 1_000_000,
+            monotonic_ns: 1_000_000_000,
+        };
+        let previous = ReadState {
+            joules: BTreeMap::from([("apple:cpu", 10.0), ("apple:gpu", 1.0)]),
+            monotonic_ns: 0,
+            cpu_ns: HashMap::from([(42, 0)]),
+        };
+        // 42 kept one of two CPUs busy for the whole second.
+        let current = ReadState {
+            joules: BTreeMap::from([("apple:cpu", 14.0), ("apple:gpu", 3.0)]),
+            monotonic_ns: 1_000_000_000,
+            cpu_ns: HashMap::from([(42, 1_000_000_000)]),
+        };
+        assert!(records_since(None, &current, time, 2).is_empty());
+
+        let records = records_since(Some(&previous), &current, time, 2);
+        let energy: Vec<(u32, &str, f64)> = records
+            .iter()
+            .map(|record| (record.pid, record.device.as_str(), record.energy))
+            .collect();
+        assert_eq!(
+            energy,
+            [
+                (42, "apple:cpu", 2.0),
+                (0, "apple:cpu", 2.0),
+                (0, "apple:gpu", 2.0),
+            ]
+        );
+    }
+}
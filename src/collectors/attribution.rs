@@ -0,0 +1,259 @@
+/// Attribution Strategies
+///
+/// How the energy of a shared RAPL domain is split among the tracked
+/// processes. Each [`DeviceClass`] has its own [`AttributionStrategy`]:
+/// by default packages follow CPU usage ([`CpuShare`]), DRAM follows
+/// memory usage ([`MemShare`]) and PSYS is split evenly ([`EqualShare`]).
+/// A [`CustomShare`] wraps a closure for anything else.
+///
+/// A strategy returns a share of the domain's energy per process. Shares
+/// that are negative or not finite count as zero and shares adding up to
+/// more than one are scaled down, so the attributed energy never exceeds
+/// the energy read; what is left is recorded as unattributed.
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+/// A RAPL domain whose energy is attributed by its own strategy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeviceClass {
+    /// A socket's package
+    Package,
+    /// The DRAM domains, summed
+    Dram,
+    /// The platform domain
+    Psys,
+}
+
+impl DeviceClass {
+    pub const ALL: [Self; 3] = [Self::Package, Self::Dram, Self::Psys];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Package => "package",
+            Self::Dram => "dram",
+            Self::Psys => "psys",
+        }
+    }
+}
+
+impl std::str::FromStr for DeviceClass {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|class| class.as_str() == name)
+            .ok_or_else(|| {
+                format!("unknown device class '{name}' (expected package, dram or psys)")
+            })
+    }
+}
+
+/// Usage of the tracked processes over one read of a domain.
+#[derive(Debug, Clone, Copy)]
+pub struct ProcessUsage<'a> {
+    pub class: DeviceClass,
+    /// Socket of a package
+    pub socket: Option<u32>,
+    pub pids: &'a [u32],
+    /// Each process's share of the CPU time behind the domain: of its
+    /// socket for a package, of the system otherwise
+    pub cpu: &'a [(u32, f64)],
+    /// Each process's share of the memory of the tracked processes
+    pub memory: &'a [(u32, f64)],
+}
+
+/// Splits the energy of a domain among processes.
+pub trait AttributionStrategy: Send + Sync {
+    /// Share of the energy for each process; processes left out get none.
+    fn shares(&self, usage: &ProcessUsage) -> Vec<(u32, f64)>;
+}
+
+/// By CPU usage.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CpuShare;
+
+impl AttributionStrategy for CpuShare {
+    fn shares(&self, usage: &ProcessUsage) -> Vec<(u32, f64)> {
+        usage.cpu.to_vec()
+    }
+}
+
+/// By memory usage.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MemShare;
+
+impl AttributionStrategy for MemShare {
+    fn shares(&self, usage: &ProcessUsage) -> Vec<(u32, f64)> {
+        usage.memory.to_vec()
+    }
+}
+
+/// The same share for every process, leaving nothing unattributed.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EqualShare;
+
+impl AttributionStrategy for EqualShare {
+    fn shares(&self, usage: &ProcessUsage) -> Vec<(u32, f64)> {
+        let share = 1.0 / usage.pids.len() as f64;
+        usage.pids.iter().map(|&pid| (pid, share)).collect()
+    }
+}
+
+/// Shares computed by a closure.
+pub struct CustomShare<F>(pub F);
+
+impl<F> AttributionStrategy for CustomShare<F>
+where
+    F: Fn(&ProcessUsage) -> Vec<(u32, f64)> + Send + Sync,
+{
+    fn shares(&self, usage: &ProcessUsage) -> Vec<(u32, f64)> {
+        (self.0)(usage)
+    }
+}
+
+/// A built-in strategy, by name, for configuration files and Python.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AttributionShare {
+    Cpu,
+    Memory,
+    Equal,
+}
+
+impl AttributionShare {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Cpu => "cpu",
+            Self::Memory => "memory",
+            Self::Equal => "equal",
+        }
+    }
+
+    pub fn strategy(self) -> Arc<dyn AttributionStrategy> {
+        match self {
+            Self::Cpu => Arc::new(CpuShare),
+            Self::Memory => Arc::new(MemShare),
+            Self::Equal => Arc::new(EqualShare),
+        }
+    }
+}
+
+impl std::str::FromStr for AttributionShare {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        [Self::Cpu, Self::Memory, Self::Equal]
+            .into_iter()
+            .find(|share| share.as_str() == name)
+            .ok_or_else(|| format!("unknown share '{name}' (expected cpu, memory or equal)"))
+    }
+}
+
+/// The strategy of each device class.
+#[derive(Clone)]
+pub(crate) struct ClassStrategies(BTreeMap<DeviceClass, Arc<dyn AttributionStrategy>>);
+
+impl Default for ClassStrategies {
+    fn default() -> Self {
+        Self(BTreeMap::from([
+            (
+                DeviceClass::Package,
+                Arc::new(CpuShare) as Arc<dyn AttributionStrategy>,
+            ),
+            (DeviceClass::Dram, Arc::new(MemShare)),
+            (DeviceClass::Psys, Arc::new(EqualShare)),
+        ]))
+    }
+}
+
+impl ClassStrategies {
+    pub(crate) fn set(&mut self, class: DeviceClass, strategy: Arc<dyn AttributionStrategy>) {
+        self.0.insert(class, strategy);
+    }
+
+    /// Energy of each of `usage.pids` out of `energy`, and the unattributed
+    /// rest.
+    pub(crate) fn split(&self, energy: f64, usage: &ProcessUsage) -> (Vec<(u32, f64)>, f64) {
+        let shares = self.0[&usage.class].shares(usage);
+        let share_of = |pid: u32| {
+            shares
+                .iter()
+                .filter(|(p, share)| *p == pid && share.is_finite())
+                .map(|(_, share)| share.max(0.0))
+                .sum::<f64>()
+        };
+        let shares: Vec<(u32, f64)> = usage.pids.iter().map(|&pid| (pid, share_of(pid))).collect();
+        let total: f64 = shares.iter().map(|(_, share)| share).sum();
+        let scale = if total > 1.0 { energy / total } else { energy };
+        let split: Vec<(u32, f64)> = shares
+            .into_iter()
+            .map(|(pid, share)| (pid, share * scale))
+            .collect();
+        let attributed: f64 = split.iter().map(|(_, joules)| joules).sum();
+        (split, (energy - attributed).max(0.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usage(class: DeviceClass) -> ProcessUsage<'static> {
+        ProcessUsage {
+            class,
+            socket: Some(0),
+            pids: &[1, 2, 3],
+            cpu: &[(1, 0.5), (2, 0.25)],
+            memory: &[(1, 0.1), (2, 0.6), (3, 0.3)],
+        }
+    }
+
+    fn assert_sums_to(split: (Vec<(u32, f64)>, f64), energy: f64) {
+        let (per_pid, unattributed) = split;
+        let total: f64 = per_pid.iter().map(|(_, joules)| joules).sum::<f64>() + unattributed;
+        assert!((total - energy).abs() < 1e-9, "{total} != {energy}");
+        assert!(per_pid.iter().all(|(_, joules)| *joules >= 0.0));
+    }
+
+    #[test]
+    fn default_strategies_split_all_measured_energy() {
+        let strategies = ClassStrategies::default();
+        for class in DeviceClass::ALL {
+            assert_sums_to(strategies.split(10.0, &usage(class)), 10.0);
+        }
+
+        let (package, unattributed) = strategies.split(8.0, &usage(DeviceClass::Package));
+        assert_eq!(package, [(1, 4.0), (2, 2.0), (3, 0.0)]);
+        assert_eq!(unattributed, 2.0);
+        let (dram, _) = strategies.split(10.0, &usage(DeviceClass::Dram));
+        assert_eq!(dram[1], (2, 6.0));
+        let (psys, unattributed) = strategies.split(9.0, &usage(DeviceClass::Psys));
+        assert_eq!(psys, [(1, 3.0), (2, 3.0), (3, 3.0)]);
+        assert_eq!(unattributed, 0.0);
+    }
+
+    #[test]
+    fn custom_shares_are_clamped_to_the_measured_energy() {
+        let mut strategies = ClassStrategies::default();
+        // Shares over the budget, negative, non-finite and for untracked
+        // processes
+        strategies.set(
+            DeviceClass::Dram,
+            Arc::new(CustomShare(|usage: &ProcessUsage| {
+                assert_eq!(usage.class, DeviceClass::Dram);
+                vec![(1, 1.5), (2, 2.5), (3, -1.0), (3, f64::NAN), (99, 1.0)]
+            })),
+        );
+        let split = strategies.split(8.0, &usage(DeviceClass::Dram));
+        assert_eq!(split.0, [(1, 3.0), (2, 5.0), (3, 0.0)]);
+        assert_sums_to(split, 8.0);
+
+        strategies.set(DeviceClass::Package, AttributionShare::Equal.strategy());
+        assert_sums_to(strategies.split(6.0, &usage(DeviceClass::Package)), 6.0);
+        assert_eq!("memory".parse(), Ok(AttributionShare::Memory));
+        assert!("gpu".parse::<DeviceClass>().is_err());
+    }
+}
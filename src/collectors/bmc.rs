@@ -0,0 +1,266 @@
+/// BMC Collector
+///
+/// Reads the power of a whole server from its baseboard management
+/// controller: through `ipmitool dcmi power reading`, locally or over the
+/// network, or from the chassis `Power` resource of a Redfish service. The
+/// chassis power covers everything behind the power supplies, so set
+/// against RAPL it shows what the CPUs do not account for, such as fans,
+/// disks and conversion losses.
+///
+/// Readings are integrated like other power meters and recorded as
+/// `bmc:<name>:chassis`, unattributed.
+use super::meter::{MeterCollector, MeterReading, MeterSource};
+use crate::config::{BmcCollectorConfig, BmcSource, IpmiConfig, RedfishConfig};
+use crate::energy_group::{EnergyCollector, EnergyRecord};
+use crate::utils::errors::CollectorError;
+use async_trait::async_trait;
+use serde::Deserialize;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::process::Command;
+
+const CHASSIS_DEVICE: &str = "chassis";
+
+/// Collector reading the chassis power of the given BMCs.
+pub struct Bmc {
+    meters: MeterCollector,
+}
+
+impl Bmc {
+    pub fn new(configs: &[BmcCollectorConfig]) -> Self {
+        Self {
+            meters: MeterCollector::new(&[], &[]).with_bmc(configs),
+        }
+    }
+}
+
+#[async_trait]
+impl EnergyCollector for Bmc {
+    fn set_tracked_pids(&self, _pids: Vec<u32>) {}
+
+    async fn get_energy_trace(&self) -> Result<Vec<EnergyRecord>, CollectorError> {
+        self.meters.get_energy_trace().await
+    }
+
+    fn is_available() -> bool {
+        true
+    }
+}
+
+/// Meter source for `config`.
+pub(crate) fn bmc_meter(config: &BmcCollectorConfig) -> Result<Box<dyn MeterSource>, String> {
+    let interval = Duration::from_secs_f64(config.interval_secs);
+    Ok(match &config.source {
+        BmcSource::Ipmi(ipmi) => Box::new(IpmiMeter {
+            name: config.name.clone(),
+            interval,
+            ipmi: ipmi.clone(),
+        }),
+        BmcSource::Redfish(redfish) => Box::new(RedfishMeter::new(
+            config.name.clone(),
+            interval,
+            redfish.clone(),
+        )?),
+    })
+}
+
+/// A BMC read with `ipmitool`.
+struct IpmiMeter {
+    name: String,
+    interval: Duration,
+    ipmi: IpmiConfig,
+}
+
+impl IpmiMeter {
+    fn command(&self) -> Result<Command, String> {
+        let mut command = Command::new(&self.ipmi.ipmitool);
+        if let Some(host) = &self.ipmi.host {
+            // `-E` takes the password from IPMI_PASSWORD, keeping it out of
+            // the process list.
+            let password = std::env::var(&self.ipmi.password_env).map_err(|_| {
+                format!("environment variable {} is not set", self.ipmi.password_env)
+            })?;
+            command
+                .args(["-I", "lanplus", "-H", host, "-E"])
+                .env("IPMI_PASSWORD", password);
+            if let Some(username) = &self.ipmi.username {
+                command.args(["-U", username]);
+            }
+        }
+        command
+            .args(["dcmi", "power", "reading"])
+            .stdin(Stdio::null())
+            .kill_on_drop(true);
+        Ok(command)
+    }
+}
+
+#[async_trait]
+impl MeterSource for IpmiMeter {
+    fn device_prefix(&self) -> String {
+        format!("bmc:{}", self.name)
+    }
+
+    fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    async fn read(&self) -> Result<Vec<MeterReading>, String> {
+        let program = &self.ipmi.ipmitool;
+        let output = tokio::time::timeout(self.interval, self.command()?.output())
+            .await
+            .map_err(|_| format!("{program} did not finish within {:?}", self.interval))?
+            .map_err(|e| format!("failed to run {program}: {e}"))?;
+        if !output.status.success() {
+            return Err(format!(
+                "{program} failed ({}): {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        let watts = parse_dcmi_power_reading(&String::from_utf8_lossy(&output.stdout))?;
+        Ok(vec![chassis_reading(watts)])
+    }
+}
+
+/// Instantaneous power of `ipmitool dcmi power reading` output, in watts.
+fn parse_dcmi_power_reading(output: &str) -> Result<f64, String> {
+    let field = |name: &str| {
+        output.lines().find_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            (key.trim() == name).then(|| value.trim())
+        })
+    };
+    if field("Power reading state is") == Some("deactivated") {
+        return Err("DCMI power reading is deactivated".to_string());
+    }
+    field("Instantaneous power reading")
+        .and_then(|value| value.strip_suffix("Watts"))
+        .and_then(|watts| watts.trim().parse().ok())
+        .ok_or_else(|| "no instantaneous power reading".to_string())
+}
+
+/// A BMC read through Redfish.
+struct RedfishMeter {
+    name: String,
+    interval: Duration,
+    redfish: RedfishConfig,
+    client: reqwest::Client,
+}
+
+impl RedfishMeter {
+    fn new(name: String, interval: Duration, redfish: RedfishConfig) -> Result<Self, String> {
+        let builder = reqwest::Client::builder().timeout(interval);
+        #[cfg(feature = "redfish-https")]
+        let builder = builder.danger_accept_invalid_certs(redfish.insecure);
+        let client = builder.build().map_err(|e| e.to_string())?;
+        Ok(Self {
+            name,
+            interval,
+            redfish,
+            client,
+        })
+    }
+
+    async fn fetch(&self) -> Result<String, String> {
+        let mut request = self.client.get(&self.redfish.url);
+        if let Some(username) = &self.redfish.username {
+            let password = std::env::var(&self.redfish.password_env).map_err(|_| {
+                format!(
+                    "environment variable {} is not set",
+                    self.redfish.password_env
+                )
+            })?;
+            request = request.basic_auth(username, Some(password));
+        }
+        text(request)
+            .await
+            .map_err(|e| format!("reading {} failed: {e}", self.redfish.url))
+    }
+}
+
+async fn text(request: reqwest::RequestBuilder) -> reqwest::Result<String> {
+    request.send().await?.error_for_status()?.text().await
+}
+
+#[async_trait]
+impl MeterSource for RedfishMeter {
+    fn device_prefix(&self) -> String {
+        format!("bmc:{}", self.name)
+    }
+
+    fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    async fn read(&self) -> Result<Vec<MeterReading>, String> {
+        let watts = parse_redfish_power(&self.fetch().await?)?;
+        Ok(vec![chassis_reading(watts)])
+    }
+}
+
+/// `PowerConsumedWatts` of the first power control of a Redfish `Power`
+/// resource that reports one.
+fn parse_redfish_power(body: &str) -> Result<f64, String> {
+    #[derive(Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    struct Power {
+        #[serde(default)]
+        power_control: Vec<PowerControl>,
+    }
+    #[derive(Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    struct PowerControl {
+        power_consumed_watts: Option<f64>,
+    }
+    let power: Power = serde_json::from_str(body).map_err(|e| e.to_string())?;
+    power
+        .power_control
+        .iter()
+        .find_map(|control| control.power_consumed_watts)
+        .ok_or_else(|| "no PowerControl reports PowerConsumedWatts".to_string())
+}
+
+fn chassis_reading(watts: f64) -> MeterReading {
+    MeterReading {
+        device: CHASSIS_DEVICE.to_string(),
+        energy_j: None,
+        power_w: Some(watts),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_dcmi_power_readings() {
+        let output = "
+    Instantaneous power reading:                   226 Watts
+    Minimum during sampling period:                 48 Watts
+    Maximum during sampling period:                410 Watts
+    Average power reading over sample period:      215 Watts
+    IPMI timestamp:                           Fri Oct 16 10:00:00 2026
+    Sampling period:                          00000001 Seconds.
+    Power reading state is:                   activated
+";
+        assert_eq!(parse_dcmi_power_reading(output).unwrap(), 226.0);
+        let deactivated = output.replace("activated", "deactivated");
+        assert!(parse_dcmi_power_reading(&deactivated).is_err());
+        assert!(parse_dcmi_power_reading("").is_err());
+    }
+
+    #[test]
+    fn parses_redfish_power_control() {
+        let body = r#"{
+            "@odata.id": "/redfish/v1/Chassis/1/Power",
+            "PowerControl": [
+                {"MemberId": "0", "PowerConsumedWatts": null},
+                {"MemberId": "1", "PowerConsumedWatts": 312.5, "PowerCapacityWatts": 1600}
+            ]
+        }"#;
+        assert_eq!(parse_redfish_power(body).unwrap(), 312.5);
+        assert!(parse_redfish_power(r#"{"PowerControl": []}"#).is_err());
+        assert!(parse_redfish_power("<html>").is_err());
+    }
+}
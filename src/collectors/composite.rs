@@ -0,0 +1,229 @@
+/// Composite Collector
+///
+/// Reads several collectors as one, so a single `EnergyGroup` can sample
+/// RAPL, GPUs and meters together and keep their records in one trace.
+/// Every member sees the same tracked pids, cgroups and read schedule, and
+/// the records of a tick are the records of all members. Device names stay
+/// as each member reports them, so members must not share devices, for
+/// example two RAPL collectors reading the same powercap tree.
+///
+/// A member that fails to read is logged and skipped for that tick; the
+/// read fails, with the first member's error, only when every member fails.
+use crate::device_registry::DeviceInfo;
+use crate::energy_group::{DegradedDomain, EnergyCollector, EnergyRecord, MissedTickPolicy};
+use crate::read_schedule::ReadSchedule;
+use crate::utils::cgroup::TrackedCgroup;
+use crate::utils::errors::CollectorError;
+use async_trait::async_trait;
+
+struct Member {
+    name: String,
+    collector: Box<dyn EnergyCollector>,
+}
+
+/// Collector reading each of its members every tick.
+#[derive(Default)]
+pub struct CompositeCollector {
+    members: Vec<Member>,
+}
+
+impl CompositeCollector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `collector`, named `name` in logs and errors.
+    pub fn with(mut self, name: impl Into<String>, collector: impl EnergyCollector) -> Self {
+        self.push(name, Box::new(collector));
+        self
+    }
+
+    /// Add a boxed collector, named `name` in logs and errors.
+    pub fn push(&mut self, name: impl Into<String>, collector: Box<dyn EnergyCollector>) {
+        self.members.push(Member {
+            name: name.into(),
+            collector,
+        });
+    }
+
+    /// Names of the members, in the order they are read.
+    pub fn names(&self) -> Vec<&str> {
+        self.members
+            .iter()
+            .map(|member| member.name.as_str())
+            .collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.members.is_empty()
+    }
+}
+
+#[async_trait]
+impl EnergyCollector for CompositeCollector {
+    fn set_tracked_pids(&self, pids: Vec<u32>) {
+        for member in &self.members {
+            member.collector.set_tracked_pids(pids.clone());
+        }
+    }
+
+    fn set_tracked_cgroups(&self, cgroups: Vec<TrackedCgroup>) {
+        for member in &self.members {
+            member.collector.set_tracked_cgroups(cgroups.clone());
+        }
+    }
+
+    fn set_read_schedule(&self, schedule: ReadSchedule) {
+        for member in &self.members {
+            member.collector.set_read_schedule(schedule.clone());
+        }
+    }
+
+    fn set_missed_tick_policy(&self, policy: MissedTickPolicy) {
+        for member in &self.members {
+            member.collector.set_missed_tick_policy(policy);
+        }
+    }
+
+    async fn get_energy_trace(&self) -> Result<Vec<EnergyRecord>, CollectorError> {
+        let mut records = Vec::new();
+        let mut errors = Vec::new();
+        for member in &self.members {
+            match member.collector.get_energy_trace().await {
+                Ok(member_records) => records.extend(member_records),
+                Err(e) => {
+                    log::warn!("Collector {}: {}", member.name, e);
+                    errors.push(e);
+                }
+            }
+        }
+        if !self.members.is_empty() && errors.len() == self.members.len() {
+            return Err(errors.swap_remove(0));
+        }
+        Ok(records)
+    }
+
+    fn devices(&self) -> Vec<DeviceInfo> {
+        self.members
+            .iter()
+            .flat_map(|member| member.collector.devices())
+            .collect()
+    }
+
+    fn degraded_domains(&self) -> Vec<DegradedDomain> {
+        self.members
+            .iter()
+            .flat_map(|member| member.collector.degraded_domains())
+            .collect()
+    }
+
+    fn rediscover(&self) -> bool {
+        // Every member rediscovers, even after one reported a change.
+        let mut changed = false;
+        for member in &self.members {
+            changed |= member.collector.rediscover();
+        }
+        changed
+    }
+
+    fn is_available() -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::time::RecordTime;
+    use std::sync::{Arc, Mutex};
+
+    /// Collector reporting one record per tracked pid on `device`, or
+    /// failing when `device` is empty.
+    struct FixedCollector {
+        device: &'static str,
+        pids: Arc<Mutex<Vec<u32>>>,
+    }
+
+    impl FixedCollector {
+        fn new(device: &'static str) -> Self {
+            Self {
+                device,
+                pids: Arc::new(Mutex::new(Vec::new())),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl EnergyCollector for FixedCollector {
+        fn set_tracked_pids(&self, pids: Vec<u32>) {
+            *self.pids.lock().unwrap() = pids;
+        }
+
+        async fn get_energy_trace(&self) -> Result<Vec<EnergyRecord>, CollectorError> {
+            if self.device.is_empty() {
+                return Err(CollectorError::DeviceLost("unreadable".to_string()));
+            }
+            let time = RecordTime::now();
+            Ok(self
+                .pids
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|&pid| EnergyRecord {
+                    pid,
+                    timestamp_us: time.timestamp_us,
+                    monotonic_ns: time.monotonic_ns,
+                    device: self.device.to_string(),
+                    energy: 1.0,
+                    raw_counter: None,
+                })
+                .collect())
+        }
+    }
+
+    fn devices(records: &[EnergyRecord]) -> Vec<(u32, &str)> {
+        records
+            .iter()
+            .map(|record| (record.pid, record.device.as_str()))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn merges_the_records_of_every_member() {
+        let composite = CompositeCollector::new()
+            .with("rapl", FixedCollector::new("rapl:socket:0:package"))
+            .with("nvidia", FixedCollector::new("nvidia:gpu:0"));
+        composite.set_tracked_pids(vec![7, 8]);
+
+        let records = composite.get_energy_trace().await.unwrap();
+
+        assert_eq!(composite.names(), ["rapl", "nvidia"]);
+        assert_eq!(
+            devices(&records),
+            [
+                (7, "rapl:socket:0:package"),
+                (8, "rapl:socket:0:package"),
+                (7, "nvidia:gpu:0"),
+                (8, "nvidia:gpu:0"),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn fails_only_when_every_member_fails() {
+        let composite = CompositeCollector::new()
+            .with("rapl", FixedCollector::new("rapl:socket:0:package"))
+            .with("broken", FixedCollector::new(""));
+        composite.set_tracked_pids(vec![7]);
+        assert_eq!(
+            devices(&composite.get_energy_trace().await.unwrap()),
+            [(7, "rapl:socket:0:package")]
+        );
+
+        let broken = CompositeCollector::new().with("broken", FixedCollector::new(""));
+        assert_eq!(
+            broken.get_energy_trace().await.unwrap_err(),
+            CollectorError::DeviceLost("unreadable".to_string())
+        );
+    }
+}
@@ -0,0 +1,892 @@
+/// CPU Energy Module
+///
+/// Provides a single `CpuEnergy` collector that picks the best available CPU
+/// energy source at runtime. Backends are probed in order (by default
+/// powercap → perf_event → MSR → model) and the first one that passes its
+/// preflight check supplies the counters. Attribution to processes is shared
+/// with the `Rapl` collector, so every backend produces the same record shape.
+use crate::collectors::placement::cpu_package_map;
+use crate::collectors::rapl::{
+    DeltaReader, EnergyCounter, Rapl, RaplReaders, SocketReaders, SystemCpuTracker,
+};
+use crate::config::CpuEnergyConfig;
+use crate::device_registry::DeviceInfo;
+use crate::energy_group::{EnergyCollector, EnergyRecord};
+use crate::monitor::{DeviceSource, DeviceSources};
+use crate::read_schedule::ReadSchedule;
+use crate::utils::cgroup::TrackedCgroup;
+use crate::utils::errors::CollectorError;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+/// Where CPU energy counters are read from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CpuEnergyBackend {
+    /// RAPL zones under `/sys/class/powercap`.
+    Powercap,
+    /// RAPL events of the `power` perf PMU.
+    PerfEvent,
+    /// RAPL energy status MSRs via `/dev/cpu/*/msr`.
+    Msr,
+    /// Utilization-based estimate between configured idle and max power.
+    Model,
+}
+
+impl CpuEnergyBackend {
+    /// Default probe order, from most to least accurate.
+    pub const DEFAULT_ORDER: [Self; 4] = [Self::Powercap, Self::PerfEvent, Self::Msr, Self::Model];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Powercap => "powercap",
+            Self::PerfEvent => "perf_event",
+            Self::Msr => "msr",
+            Self::Model => "model",
+        }
+    }
+}
+
+/// Outcome of probing one backend.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BackendPreflight {
+    pub backend: CpuEnergyBackend,
+    pub available: bool,
+    /// What was found, or why the backend was rejected.
+    pub detail: String,
+}
+
+/// Filesystem locations probed by the backends.
+#[derive(Debug, Clone)]
+pub(crate) struct CpuEnergyPaths {
+    pub(crate) powercap: PathBuf,
+    pub(crate) perf_pmu: PathBuf,
+    pub(crate) cpu_sysfs: PathBuf,
+    pub(crate) msr_dev: PathBuf,
+}
+
+impl Default for CpuEnergyPaths {
+    fn default() -> Self {
+        Self {
+            powercap: PathBuf::from("/sys/class/powercap"),
+            perf_pmu: PathBuf::from("/sys/bus/event_source/devices/power"),
+            cpu_sysfs: PathBuf::from("/sys/devices/system/cpu"),
+            msr_dev: PathBuf::from("/dev/cpu"),
+        }
+    }
+}
+
+/// CPU energy collector with automatic backend selection.
+pub struct CpuEnergy {
+    backend: Option<CpuEnergyBackend>,
+    preflight: Vec<BackendPreflight>,
+    rapl: Rapl,
+    /// Kept to re-probe the selected backend on hotplug
+    config: CpuEnergyConfig,
+    paths: CpuEnergyPaths,
+    /// Domains of the last successful probe; counters are only reopened
+    /// when the domains found on rediscovery differ.
+    domains: Mutex<Option<Vec<String>>>,
+}
+
+impl CpuEnergy {
+    pub fn new(config: &CpuEnergyConfig) -> Self {
+        Self::with_paths(config, &CpuEnergyPaths::default())
+    }
+
+    pub(crate) fn with_paths(config: &CpuEnergyConfig, paths: &CpuEnergyPaths) -> Self {
+        let mut preflight = Vec::new();
+
+        for &backend in &config.backends {
+            match probe_backend(backend, config, paths) {
+                Ok(counters) => {
+                    log::info!(
+                        "CPU energy backend selected: {} ({} packages)",
+                        backend.as_str(),
+                        counters.package_count()
+                    );
+                    preflight.push(BackendPreflight {
+                        backend,
+                        available: true,
+                        detail: format!("{} package counters", counters.package_count()),
+                    });
+                    let prefix = if backend == CpuEnergyBackend::Model {
+                        "model"
+                    } else {
+                        "rapl"
+                    };
+                    return Self {
+                        backend: Some(backend),
+                        preflight,
+                        rapl: configured_rapl(counters, prefix, config),
+                        config: config.clone(),
+                        paths: paths.clone(),
+                        domains: Mutex::new(Some(probe_domains(backend, paths))),
+                    };
+                }
+                Err(detail) => {
+                    log::debug!(
+                        "CPU energy backend {} rejected: {}",
+                        backend.as_str(),
+                        detail
+                    );
+                    preflight.push(BackendPreflight {
+                        backend,
+                        available: false,
+                        detail,
+                    });
+                }
+            }
+        }
+
+        log::warn!("No CPU energy backend passed preflight");
+        Self {
+            backend: None,
+            preflight,
+            rapl: configured_rapl(RaplReaders::default(), "rapl", config),
+            config: config.clone(),
+            paths: paths.clone(),
+            domains: Mutex::new(None),
+        }
+    }
+
+    /// The backend supplying counters, if any passed preflight.
+    pub fn backend(&self) -> Option<CpuEnergyBackend> {
+        self.backend
+    }
+
+    /// Preflight results for every backend probed, in probe order.
+    pub fn preflight(&self) -> &[BackendPreflight] {
+        &self.preflight
+    }
+
+    pub fn device_sources(&self) -> DeviceSources {
+        match self.backend {
+            Some(CpuEnergyBackend::Model) => DeviceSources {
+                cpu: DeviceSource::Estimated,
+                dram: DeviceSource::Unavailable,
+                gpu: DeviceSource::Unavailable,
+            },
+            Some(_) => self.rapl.device_sources(),
+            None => DeviceSources::default(),
+        }
+    }
+}
+
+impl Default for CpuEnergy {
+    fn default() -> Self {
+        Self::new(&CpuEnergyConfig::default())
+    }
+}
+
+#[async_trait]
+impl EnergyCollector for CpuEnergy {
+    fn set_tracked_pids(&self, pids: Vec<u32>) {
+        self.rapl.set_tracked_pids(pids);
+    }
+
+    fn set_tracked_cgroups(&self, cgroups: Vec<TrackedCgroup>) {
+        self.rapl.set_tracked_cgroups(cgroups);
+    }
+
+    fn set_read_schedule(&self, schedule: ReadSchedule) {
+        self.rapl.set_read_schedule(schedule);
+    }
+
+    async fn get_energy_trace(&self) -> Result<Vec<EnergyRecord>, CollectorError> {
+        if self.backend.is_none() {
+            return Err(CollectorError::Unsupported(
+                "no CPU energy backend passed preflight".to_string(),
+            ));
+        }
+        self.rapl.get_energy_trace().await
+    }
+
+    fn devices(&self) -> Vec<DeviceInfo> {
+        self.rapl.devices()
+    }
+
+    fn rediscover(&self) -> bool {
+        // The utilization model has no hardware to gain or lose.
+        let Some(backend) = self
+            .backend
+            .filter(|&backend| backend != CpuEnergyBackend::Model)
+        else {
+            return false;
+        };
+        let domains = probe_domains(backend, &self.paths);
+        let mut known = self.domains.lock().unwrap();
+        if known.as_ref() == Some(&domains) {
+            // Reopening perf and MSR counters is not free, so only the CPU
+            // count is refreshed while the domains stay the same.
+            self.rapl.refresh_cpu_count();
+            return false;
+        }
+        match probe_backend(backend, &self.config, &self.paths) {
+            Ok(counters) => {
+                *known = Some(domains);
+                self.rapl.replace_readers(counters)
+            }
+            Err(detail) => {
+                *known = None;
+                let changed = self.rapl.replace_readers(RaplReaders::default());
+                if changed {
+                    log::warn!(
+                        "CPU energy backend {} lost its counters: {}",
+                        backend.as_str(),
+                        detail
+                    );
+                }
+                changed
+            }
+        }
+    }
+
+    fn is_available() -> bool {
+        // The model backend only needs /proc/stat, so the chain always has a
+        // candidate on Linux; per-instance preflight decides which one is used.
+        Path::new("/proc/stat").exists()
+    }
+}
+
+/// Collector over `readers` attributing energy as `config` asks.
+fn configured_rapl(readers: RaplReaders, prefix: &'static str, config: &CpuEnergyConfig) -> Rapl {
+    config.domain_attribution.iter().fold(
+        Rapl::from_readers(readers, prefix).with_attribution(config.attribution),
+        |rapl, (&class, share)| rapl.with_attribution_strategy(class, share.strategy()),
+    )
+}
+
+fn probe_backend(
+    backend: CpuEnergyBackend,
+    config: &CpuEnergyConfig,
+    paths: &CpuEnergyPaths,
+) -> Result<RaplReaders, String> {
+    let counters = match backend {
+        CpuEnergyBackend::Powercap => probe_powercap(&paths.powercap)?,
+        CpuEnergyBackend::PerfEvent => probe_perf_event(&paths.perf_pmu, &paths.cpu_sysfs)?,
+        CpuEnergyBackend::Msr => probe_msr(&paths.msr_dev, &paths.cpu_sysfs)?,
+        CpuEnergyBackend::Model => probe_model(config)?,
+    };
+
+    if counters.package_count() == 0 {
+        return Err("no readable package energy counter".to_string());
+    }
+    Ok(counters)
+}
+
+/// Domains `backend` opens counters for, listed without opening them.
+fn probe_domains(backend: CpuEnergyBackend, paths: &CpuEnergyPaths) -> Vec<String> {
+    let mut domains: Vec<String> = match backend {
+        CpuEnergyBackend::Powercap => fs::read_dir(&paths.powercap)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter(|entry| entry.path().join("energy_uj").exists())
+            .map(|entry| entry.file_name().to_string_lossy().into_owned())
+            .collect(),
+        CpuEnergyBackend::PerfEvent => {
+            let cpus = fs::read_to_string(paths.perf_pmu.join("cpumask"))
+                .map(|mask| parse_cpu_list(&mask))
+                .unwrap_or_default();
+            let events: Vec<_> = PERF_RAPL_EVENTS
+                .into_iter()
+                .filter(|event| paths.perf_pmu.join("events").join(event).exists())
+                .collect();
+            cpus.iter()
+                .flat_map(|cpu| events.iter().map(move |event| format!("{event} cpu{cpu}")))
+                .collect()
+        }
+        CpuEnergyBackend::Msr => {
+            let mut packages = package_first_cpus(&paths.cpu_sysfs);
+            if packages.is_empty() {
+                packages.insert(0, 0);
+            }
+            packages
+                .into_iter()
+                .filter(|(_, cpu)| paths.msr_dev.join(cpu.to_string()).join("msr").exists())
+                .map(|(socket_id, cpu)| format!("package{socket_id} cpu{cpu}"))
+                .collect()
+        }
+        CpuEnergyBackend::Model => Vec::new(),
+    };
+    domains.sort();
+    domains
+}
+
+fn probe_powercap(root: &Path) -> Result<RaplReaders, String> {
+    if !Rapl::powercap_has_readable_rapl_counter(root) {
+        return Err(format!(
+            "no readable RAPL energy_uj under {}",
+            root.display()
+        ));
+    }
+
+    Ok(Rapl::scan_powercap_entries(&root.to_string_lossy()))
+}
+
+// ─── perf_event backend ─────────────────────────────────────────────────────
+
+/// RAPL events of the `power` PMU that counters are opened for.
+const PERF_RAPL_EVENTS: [&str; 4] = ["energy-pkg", "energy-cores", "energy-ram", "energy-psys"];
+
+/// Parse the `config` value from a PMU event description such as `event=0x02`.
+fn parse_perf_event_config(contents: &str) -> Option<u64> {
+    contents.trim().split(',').find_map(|term| {
+        let value = term.trim().strip_prefix("event=")?;
+        match value.strip_prefix("0x") {
+            Some(hex) => u64::from_str_radix(hex, 16).ok(),
+            None => value.parse().ok(),
+        }
+    })
+}
+
+/// Parse a kernel CPU list such as `0,28` or `0-3,8`.
+fn parse_cpu_list(contents: &str) -> Vec<u32> {
+    contents
+        .trim()
+        .split(',')
+        .filter(|part| !part.is_empty())
+        .flat_map(|part| match part.split_once('-') {
+            Some((start, end)) => match (start.parse::<u32>(), end.parse::<u32>()) {
+                (Ok(start), Ok(end)) if start <= end => (start..=end).collect(),
+                _ => Vec::new(),
+            },
+            None => part.parse().map(|cpu| vec![cpu]).unwrap_or_default(),
+        })
+        .collect()
+}
+
+/// Map each physical package to the first logical CPU that belongs to it.
+fn package_first_cpus(cpu_sysfs: &Path) -> BTreeMap<u32, u32> {
+    let mut packages = BTreeMap::new();
+    for (cpu, package) in cpu_package_map(cpu_sysfs) {
+        packages
+            .entry(package)
+            .and_modify(|first: &mut u32| *first = (*first).min(cpu))
+            .or_insert(cpu);
+    }
+    packages
+}
+
+#[cfg(target_os = "linux")]
+mod perf {
+    use super::{CollectorError, CpuEnergyBackend, EnergyCounter};
+    use std::fs::File;
+    use std::io::{self, Read};
+    use std::os::fd::FromRawFd;
+
+    const PERF_ATTR_SIZE_VER0: u32 = 64;
+    const PERF_FLAG_FD_CLOEXEC: libc::c_ulong = 8;
+
+    /// First 64 bytes of `struct perf_event_attr` (PERF_ATTR_SIZE_VER0).
+    #[repr(C)]
+    #[derive(Default)]
+    struct PerfEventAttr {
+        type_: u32,
+        size: u32,
+        config: u64,
+        sample_period: u64,
+        sample_type: u64,
+        read_format: u64,
+        flags: u64,
+        wakeup_events: u32,
+        bp_type: u32,
+        config1: u64,
+    }
+
+    /// System-wide counter for one RAPL event on one CPU.
+    pub(super) struct PerfCounter {
+        file: File,
+        scale_joules: f64,
+        label: String,
+    }
+
+    impl PerfCounter {
+        pub(super) fn open(
+            pmu_type: u32,
+            config: u64,
+            cpu: u32,
+            scale_joules: f64,
+            label: String,
+        ) -> io::Result<Self> {
+            let attr = PerfEventAttr {
+                type_: pmu_type,
+                size: PERF_ATTR_SIZE_VER0,
+                config,
+                ..PerfEventAttr::default()
+            };
+            // SAFETY: attr is a valid, fully initialized perf_event_attr prefix
+            // whose size field matches its layout.
+            let fd = unsafe {
+                libc::syscall(
+                    libc::SYS_perf_event_open,
+                    &attr as *const PerfEventAttr,
+                    -1 as libc::pid_t,
+                    cpu as libc::c_int,
+                    -1 as libc::c_int,
+                    PERF_FLAG_FD_CLOEXEC,
+                )
+            };
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            // SAFETY: the syscall returned a new file descriptor that we own.
+            let file = unsafe { File::from_raw_fd(fd as libc::c_int) };
+            Ok(Self {
+                file,
+                scale_joules,
+                label,
+            })
+        }
+    }
+
+    impl EnergyCounter for PerfCounter {
+        fn read_microjoules(&self) -> Result<i64, CollectorError> {
+            let mut buf = [0_u8; 8];
+            (&self.file)
+                .read_exact(&mut buf)
+                .map_err(|e| CollectorError::from_io(&self.label, &e))?;
+            let count = u64::from_ne_bytes(buf);
+            Ok((count as f64 * self.scale_joules * 1e6) as i64)
+        }
+
+        fn label(&self) -> String {
+            self.label.clone()
+        }
+
+        fn source(&self) -> Option<String> {
+            Some(CpuEnergyBackend::PerfEvent.as_str().to_string())
+        }
+
+        fn resolution_joules(&self) -> Option<f64> {
+            Some(self.scale_joules)
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub(crate) fn probe_perf_event(pmu: &Path, cpu_sysfs: &Path) -> Result<RaplReaders, String> {
+    let read = |name: &str| fs::read_to_string(pmu.join(name)).ok();
+
+    let pmu_type: u32 = read("type")
+        .and_then(|value| value.trim().parse().ok())
+        .ok_or_else(|| format!("no perf PMU at {}", pmu.display()))?;
+    let cpus = read("cpumask")
+        .map(|mask| parse_cpu_list(&mask))
+        .filter(|cpus| !cpus.is_empty())
+        .unwrap_or_else(|| vec![0]);
+
+    let open_event = |event: &str, cpu: u32| -> Result<Option<DeltaReader>, String> {
+        let Some(config) =
+            read(&format!("events/{event}")).and_then(|c| parse_perf_event_config(&c))
+        else {
+            return Ok(None);
+        };
+        let scale = read(&format!("events/{event}.scale"))
+            .and_then(|value| value.trim().parse::<f64>().ok())
+            .unwrap_or(1.0);
+        let label = format!("perf power/{event}/ cpu{cpu}");
+        perf::PerfCounter::open(pmu_type, config, cpu, scale, label)
+            .map(|counter| Some(DeltaReader::from_counter(Arc::new(counter))))
+            .map_err(|e| format!("perf_event_open for {event} failed: {e}"))
+    };
+
+    let cpu_packages = cpu_package_map(cpu_sysfs);
+    let mut counters = RaplReaders::default();
+    for &cpu in &cpus {
+        let socket_id = cpu_packages.get(&cpu).copied().unwrap_or(cpu);
+        let package_reader = open_event("energy-pkg", cpu)?;
+        let core_reader = open_event("energy-cores", cpu).unwrap_or(None);
+        if let Some(dram) = open_event("energy-ram", cpu).unwrap_or(None) {
+            counters.dram_readers.push(dram);
+        }
+        counters.socket_readers.push(SocketReaders {
+            socket_id,
+            package_reader,
+            core_reader,
+            uncore_reader: None,
+        });
+    }
+    counters.psys_reader = open_event("energy-psys", cpus[0]).unwrap_or(None);
+    Ok(counters)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn probe_perf_event(_pmu: &Path, _cpu_sysfs: &Path) -> Result<RaplReaders, String> {
+    Err("perf_event is only supported on Linux".to_string())
+}
+
+// ─── MSR backend ────────────────────────────────────────────────────────────
+
+const MSR_RAPL_POWER_UNIT: u64 = 0x606;
+const MSR_PKG_ENERGY_STATUS: u64 = 0x611;
+const MSR_DRAM_ENERGY_STATUS: u64 = 0x619;
+const MSR_PP0_ENERGY_STATUS: u64 = 0x639;
+const MSR_AMD_RAPL_POWER_UNIT: u64 = 0xC001_0299;
+const MSR_AMD_CORE_ENERGY_STATUS: u64 = 0xC001_029A;
+const MSR_AMD_PKG_ENERGY_STATUS: u64 = 0xC001_029B;
+
+/// Registers used for one CPU vendor's RAPL MSR layout.
+struct MsrLayout {
+    power_unit: u64,
+    package: u64,
+    core: u64,
+    dram: Option<u64>,
+}
+
+const INTEL_MSR_LAYOUT: MsrLayout = MsrLayout {
+    power_unit: MSR_RAPL_POWER_UNIT,
+    package: MSR_PKG_ENERGY_STATUS,
+    core: MSR_PP0_ENERGY_STATUS,
+    dram: Some(MSR_DRAM_ENERGY_STATUS),
+};
+
+const AMD_MSR_LAYOUT: MsrLayout = MsrLayout {
+    power_unit: MSR_AMD_RAPL_POWER_UNIT,
+    package: MSR_AMD_PKG_ENERGY_STATUS,
+    core: MSR_AMD_CORE_ENERGY_STATUS,
+    dram: None,
+};
+
+/// Energy status unit in joules from a RAPL power unit register (bits 12:8).
+fn msr_energy_unit_joules(power_unit: u64) -> f64 {
+    0.5_f64.powi(((power_unit >> 8) & 0x1f) as i32)
+}
+
+#[cfg(unix)]
+fn read_msr(file: &fs::File, register: u64) -> std::io::Result<u64> {
+    use std::os::unix::fs::FileExt;
+
+    let mut buf = [0_u8; 8];
+    file.read_exact_at(&mut buf, register)?;
+    Ok(u64::from_ne_bytes(buf))
+}
+
+/// 32-bit RAPL energy status register read through the msr driver.
+#[cfg(unix)]
+struct MsrCounter {
+    file: Arc<fs::File>,
+    register: u64,
+    unit_joules: f64,
+    label: String,
+}
+
+#[cfg(unix)]
+impl EnergyCounter for MsrCounter {
+    fn read_microjoules(&self) -> Result<i64, CollectorError> {
+        let raw = read_msr(&self.file, self.register)
+            .map_err(|e| CollectorError::from_io(&self.label, &e))?;
+        Ok(((raw & 0xffff_ffff) as f64 * self.unit_joules * 1e6) as i64)
+    }
+
+    fn label(&self) -> String {
+        self.label.clone()
+    }
+
+    fn source(&self) -> Option<String> {
+        Some(CpuEnergyBackend::Msr.as_str().to_string())
+    }
+
+    fn resolution_joules(&self) -> Option<f64> {
+        Some(self.unit_joules)
+    }
+
+    fn max_range_microjoules(&self) -> Option<i64> {
+        Some((2f64.powi(32) * self.unit_joules * 1e6) as i64)
+    }
+}
+
+#[cfg(unix)]
+fn probe_msr(msr_dev: &Path, cpu_sysfs: &Path) -> Result<RaplReaders, String> {
+    let mut packages = package_first_cpus(cpu_sysfs);
+    if packages.is_empty() {
+        packages.insert(0, 0);
+    }
+
+    let mut counters = RaplReaders::default();
+    for (socket_id, cpu) in packages {
+        let path = msr_dev.join(cpu.to_string()).join("msr");
+        let file = Arc::new(
+            fs::File::open(&path).map_err(|e| format!("cannot open {}: {e}", path.display()))?,
+        );
+        let (layout, power_unit) = [INTEL_MSR_LAYOUT, AMD_MSR_LAYOUT]
+            .into_iter()
+            .find_map(|layout| {
+                read_msr(&file, layout.power_unit)
+                    .ok()
+                    .map(|unit| (layout, unit))
+            })
+            .ok_or_else(|| format!("no RAPL power unit MSR on cpu{cpu}"))?;
+        let unit_joules = msr_energy_unit_joules(power_unit);
+
+        let reader = |register: u64, domain: &str| -> Option<DeltaReader> {
+            read_msr(&file, register).ok()?;
+            Some(DeltaReader::from_counter(Arc::new(MsrCounter {
+                file: Arc::clone(&file),
+                register,
+                unit_joules,
+                label: format!("msr cpu{cpu} {domain}"),
+            })))
+        };
+
+        if let Some(dram) = layout.dram.and_then(|register| reader(register, "dram")) {
+            counters.dram_readers.push(dram);
+        }
+        counters.socket_readers.push(SocketReaders {
+            socket_id,
+            package_reader: reader(layout.package, "package"),
+            core_reader: reader(layout.core, "core"),
+            uncore_reader: None,
+        });
+    }
+    Ok(counters)
+}
+
+#[cfg(not(unix))]
+fn probe_msr(_msr_dev: &Path, _cpu_sysfs: &Path) -> Result<RaplReaders, String> {
+    Err("MSR access is only supported on Linux".to_string())
+}
+
+// ─── Model backend ──────────────────────────────────────────────────────────
+
+/// Linear power model: idle power plus a utilization-proportional dynamic part.
+fn model_power_watts(idle_watts: f64, max_watts: f64, utilization: f64) -> f64 {
+    idle_watts + (max_watts - idle_watts) * utilization.clamp(0.0, 1.0)
+}
+
+struct ModelState {
+    tracker: SystemCpuTracker,
+    last_read: Option<Instant>,
+    total_microjoules: f64,
+}
+
+/// Virtual counter that integrates modelled power over wall-clock time.
+struct ModelCounter {
+    idle_watts: f64,
+    max_watts: f64,
+    state: Mutex<ModelState>,
+}
+
+impl EnergyCounter for ModelCounter {
+    fn read_microjoules(&self) -> Result<i64, CollectorError> {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        let (cpu_percent, valid) = state.tracker.update();
+
+        if let Some(last_read) = state.last_read
+            && valid
+        {
+            let elapsed = now.duration_since(last_read).as_secs_f64();
+            let watts = model_power_watts(self.idle_watts, self.max_watts, cpu_percent / 100.0);
+            state.total_microjoules += watts * elapsed * 1e6;
+        }
+        state.last_read = Some(now);
+        Ok(state.total_microjoules as i64)
+    }
+
+    fn label(&self) -> String {
+        "cpu utilization model".to_string()
+    }
+
+    fn max_power_watts(&self) -> Option<f64> {
+        Some(self.max_watts)
+    }
+
+    fn source(&self) -> Option<String> {
+        Some(CpuEnergyBackend::Model.as_str().to_string())
+    }
+}
+
+fn probe_model(config: &CpuEnergyConfig) -> Result<RaplReaders, String> {
+    if fs::read_to_string("/proc/stat").is_err() {
+        return Err("cannot read system CPU usage from /proc/stat".to_string());
+    }
+    // First update establishes the baseline for utilization deltas.
+    let mut tracker = SystemCpuTracker::default();
+    tracker.update();
+
+    let counter = ModelCounter {
+        idle_watts: config.model_idle_watts,
+        max_watts: config.model_max_watts,
+        state: Mutex::new(ModelState {
+            tracker,
+            last_read: None,
+            total_microjoules: 0.0,
+        }),
+    };
+    Ok(RaplReaders {
+        socket_readers: vec![SocketReaders {
+            socket_id: 0,
+            package_reader: Some(DeltaReader::from_counter(Arc::new(counter))),
+            core_reader: None,
+            uncore_reader: None,
+        }],
+        ..RaplReaders::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn missing_paths(root: &Path) -> CpuEnergyPaths {
+        CpuEnergyPaths {
+            powercap: root.join("powercap"),
+            perf_pmu: root.join("power"),
+            cpu_sysfs: root.join("cpu"),
+            msr_dev: root.join("dev-cpu"),
+        }
+    }
+
+    fn write_zone(root: &Path, entry_name: &str, zone_name: &str) {
+        let zone_dir = root.join(entry_name);
+        fs::create_dir_all(&zone_dir).unwrap();
+        fs::write(zone_dir.join("name"), zone_name).unwrap();
+        fs::write(zone_dir.join("energy_uj"), "0").unwrap();
+    }
+
+    #[test]
+    fn powercap_is_preferred_when_readable() {
+        let dir = TempDir::new().unwrap();
+        let paths = missing_paths(dir.path());
+        write_zone(&paths.powercap, "intel-rapl:0", "package-0");
+        write_zone(&paths.powercap, "intel-rapl:0:0", "dram");
+
+        let cpu = CpuEnergy::with_paths(&CpuEnergyConfig::default(), &paths);
+
+        assert_eq!(cpu.backend(), Some(CpuEnergyBackend::Powercap));
+        assert_eq!(cpu.preflight().len(), 1);
+        assert_eq!(cpu.device_sources().cpu, DeviceSource::MeasuredPackage);
+        assert_eq!(cpu.device_sources().dram, DeviceSource::Measured);
+    }
+
+    #[test]
+    fn rediscovery_only_reprobes_when_domains_change() {
+        let dir = TempDir::new().unwrap();
+        let paths = missing_paths(dir.path());
+        write_zone(&paths.powercap, "intel-rapl:0", "package-0");
+        let cpu = CpuEnergy::with_paths(&CpuEnergyConfig::default(), &paths);
+        let devices = cpu.devices().len();
+
+        // A rescan would now skip the zone, so it must not happen.
+        fs::write(paths.powercap.join("intel-rapl:0/name"), "").unwrap();
+        assert!(!cpu.rediscover());
+        assert_eq!(cpu.devices().len(), devices);
+
+        write_zone(&paths.powercap, "intel-rapl:0", "package-0");
+        write_zone(&paths.powercap, "intel-rapl:1", "package-1");
+        assert!(cpu.rediscover());
+        assert!(cpu.devices().len() > devices);
+    }
+
+    #[test]
+    fn falls_back_through_chain_to_model() {
+        let dir = TempDir::new().unwrap();
+        let paths = missing_paths(dir.path());
+
+        let cpu = CpuEnergy::with_paths(&CpuEnergyConfig::default(), &paths);
+
+        assert_eq!(cpu.backend(), Some(CpuEnergyBackend::Model));
+        let probed: Vec<_> = cpu
+            .preflight()
+            .iter()
+            .map(|result| (result.backend, result.available))
+            .collect();
+        assert_eq!(
+            probed,
+            vec![
+                (CpuEnergyBackend::Powercap, false),
+                (CpuEnergyBackend::PerfEvent, false),
+                (CpuEnergyBackend::Msr, false),
+                (CpuEnergyBackend::Model, true),
+            ]
+        );
+        assert_eq!(cpu.device_sources().cpu, DeviceSource::Estimated);
+    }
+
+    #[tokio::test]
+    async fn no_backend_reports_error_from_energy_trace() {
+        let dir = TempDir::new().unwrap();
+        let config = CpuEnergyConfig {
+            backends: vec![CpuEnergyBackend::Powercap, CpuEnergyBackend::Msr],
+            ..CpuEnergyConfig::default()
+        };
+
+        let cpu = CpuEnergy::with_paths(&config, &missing_paths(dir.path()));
+        cpu.set_tracked_pids(vec![std::process::id()]);
+
+        assert_eq!(cpu.backend(), None);
+        assert_eq!(cpu.device_sources(), DeviceSources::default());
+        assert!(cpu.get_energy_trace().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn model_backend_emits_model_package_records() {
+        let dir = TempDir::new().unwrap();
+        let config = CpuEnergyConfig {
+            backends: vec![CpuEnergyBackend::Model],
+            ..CpuEnergyConfig::default()
+        };
+        let cpu = CpuEnergy::with_paths(&config, &missing_paths(dir.path()));
+        cpu.set_tracked_pids(vec![std::process::id()]);
+
+        cpu.get_energy_trace().await.unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        let records = cpu.get_energy_trace().await.unwrap();
+
+        assert!(!records.is_empty());
+        assert!(
+            records
+                .iter()
+                .all(|record| record.device == "model:socket:0:package")
+        );
+    }
+
+    #[test]
+    fn model_power_interpolates_between_idle_and_max() {
+        assert_eq!(model_power_watts(10.0, 60.0, 0.0), 10.0);
+        assert_eq!(model_power_watts(10.0, 60.0, 0.5), 35.0);
+        assert_eq!(model_power_watts(10.0, 60.0, 2.0), 60.0);
+    }
+
+    #[test]
+    fn parses_perf_event_descriptions_and_cpu_lists() {
+        assert_eq!(parse_perf_event_config("event=0x02\n"), Some(2));
+        assert_eq!(parse_perf_event_config("event=0x3b,umask=0x1"), Some(0x3b));
+        assert_eq!(parse_perf_event_config("umask=0x1"), None);
+
+        assert_eq!(parse_cpu_list("0,28\n"), vec![0, 28]);
+        assert_eq!(parse_cpu_list("0-2,8"), vec![0, 1, 2, 8]);
+        assert!(parse_cpu_list("").is_empty());
+    }
+
+    #[test]
+    fn msr_energy_unit_uses_power_unit_bits() {
+        // Typical Intel value 0xA0E03: energy status unit 0b01110 = 14.
+        let unit = msr_energy_unit_joules(0x000A_0E03);
+
+        assert!((unit - 0.5_f64.powi(14)).abs() < 1e-15);
+    }
+
+    #[test]
+    fn package_first_cpus_groups_by_physical_package() {
+        let dir = TempDir::new().unwrap();
+        for (cpu, package) in [(0, 0), (1, 1), (2, 0), (3, 1)] {
+            let topology = dir.path().join(format!("cpu{cpu}/topology"));
+            fs::create_dir_all(&topology).unwrap();
+            fs::write(topology.join("physical_package_id"), format!("{package}\n")).unwrap();
+        }
+        fs::create_dir_all(dir.path().join("cpufreq")).unwrap();
+
+        let packages = package_first_cpus(dir.path());
+
+        assert_eq!(packages, BTreeMap::from([(0, 0), (1, 1)]));
+    }
+}
@@ -0,0 +1,205 @@
+/// DRM Fdinfo Module
+///
+/// Per-client GPU engine usage from `/proc/<pid>/fdinfo`, as the kernel's
+/// DRM usage stats expose it. amdgpu and i915 report the busy time of each
+/// engine class as `drm-engine-<class>: <ns> ns`; xe reports the cycles a
+/// client ran on each class as `drm-cycles-<class>`, with the GPU timestamp
+/// they count against in `drm-total-cycles-<class>`.
+///
+/// A GPU's energy is split like RAPL splits a socket's: each process gets
+/// the share of the interval its engines were busy, and the rest is
+/// recorded as unattributed.
+use crate::energy_group::EnergyRecord;
+use crate::utils::time::RecordTime;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+pub(crate) const UNATTRIBUTED_PID: u32 = 0;
+
+/// The GPU client of one DRM file descriptor.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct DrmClient {
+    pub(crate) pdev: String,
+    pub(crate) client_id: u64,
+    pub(crate) usage: EngineUsage,
+}
+
+/// Cumulative engine usage of a DRM client, or of a process summed over
+/// its clients.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub(crate) struct EngineUsage {
+    /// Busy time summed over the engine classes, in nanoseconds
+    pub(crate) engine_ns: u64,
+    /// Cycles summed over the engine classes
+    pub(crate) cycles: u64,
+    /// GPU timestamp the cycles count against; every class shares it
+    pub(crate) total_cycles: u64,
+}
+
+impl EngineUsage {
+    fn add(&mut self, other: EngineUsage) {
+        self.engine_ns += other.engine_ns;
+        self.cycles += other.cycles;
+        self.total_cycles = self.total_cycles.max(other.total_cycles);
+    }
+
+    /// Busy time since `previous` within an interval of `elapsed_ns`, in
+    /// nanoseconds. Cycles count as the same fraction of the interval as
+    /// of the GPU timestamp.
+    pub(crate) fn busy_ns_since(&self, previous: &EngineUsage, elapsed_ns: u64) -> u64 {
+        let engine_ns = self.engine_ns.saturating_sub(previous.engine_ns);
+        let total_cycles = self.total_cycles.saturating_sub(previous.total_cycles);
+        let cycle_ns = if total_cycles > 0 {
+            let cycles = self.cycles.saturating_sub(previous.cycles);
+            (cycles as f64 / total_cycles as f64 * elapsed_ns as f64) as u64
+        } else {
+            0
+        };
+        engine_ns + cycle_ns
+    }
+}
+
+/// Parse a `/proc/<pid>/fdinfo/<fd>` file, returning `None` for file
+/// descriptors that are not DRM clients of one of `drivers`.
+pub(crate) fn parse_drm_fdinfo(contents: &str, drivers: &[&str]) -> Option<DrmClient> {
+    let mut driver = None;
+    let mut pdev = None;
+    let mut client_id = None;
+    let mut usage = EngineUsage::default();
+    for line in contents.lines() {
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        match key.trim() {
+            "drm-driver" => driver = Some(value),
+            "drm-pdev" => pdev = Some(value.to_string()),
+            "drm-client-id" => client_id = value.parse().ok(),
+            key if key.starts_with("drm-engine-") && !key.starts_with("drm-engine-capacity-") => {
+                usage.engine_ns += value
+                    .strip_suffix("ns")
+                    .and_then(|ns| ns.trim().parse::<u64>().ok())
+                    .unwrap_or(0);
+            }
+            key if key.starts_with("drm-cycles-") => {
+                usage.cycles += value.parse::<u64>().unwrap_or(0);
+            }
+            key if key.starts_with("drm-total-cycles-") => {
+                usage.total_cycles = usage.total_cycles.max(value.parse().unwrap_or(0));
+            }
+            _ => {}
+        }
+    }
+    drivers.contains(&driver?).then_some(DrmClient {
+        pdev: pdev?,
+        client_id: client_id?,
+        usage,
+    })
+}
+
+/// Engine usage of each of `pids` per GPU PCI address, from the fdinfo
+/// under `proc_dir`. A DRM client open through several file descriptors or
+/// shared by several processes counts once, for the first process it is
+/// found in.
+pub(crate) fn usage_by_slot(
+    proc_dir: &Path,
+    pids: &[u32],
+    drivers: &[&str],
+) -> HashMap<String, HashMap<u32, EngineUsage>> {
+    let mut seen_clients = HashSet::new();
+    let mut usage: HashMap<String, HashMap<u32, EngineUsage>> = HashMap::new();
+    for &pid in pids {
+        let Ok(entries) = fs::read_dir(proc_dir.join(pid.to_string()).join("fdinfo")) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let Ok(contents) = fs::read_to_string(entry.path()) else {
+                continue;
+            };
+            let Some(client) = parse_drm_fdinfo(&contents, drivers) else {
+                continue;
+            };
+            if seen_clients.insert((client.pdev.clone(), client.client_id)) {
+                usage
+                    .entry(client.pdev)
+                    .or_default()
+                    .entry(pid)
+                    .or_default()
+                    .add(client.usage);
+            }
+        }
+    }
+    usage
+}
+
+/// Split `delta_joules` of a card among tracked processes by the
+/// fraction of `elapsed_ns` their engines were busy, scaled down when
+/// the fractions add up to more than the whole interval.
+pub(crate) fn attribute_busy_share(
+    device: &str,
+    delta_joules: f64,
+    elapsed_ns: u64,
+    busy_deltas: &[(u32, u64)],
+    time: RecordTime,
+    raw_counter: Option<f64>,
+) -> Vec<EnergyRecord> {
+    if delta_joules <= 0.0 {
+        return Vec::new();
+    }
+    let record = |pid, energy| EnergyRecord {
+        pid,
+        timestamp_us: time.timestamp_us,
+        monotonic_ns: time.monotonic_ns,
+        device: device.to_string(),
+        energy,
+        raw_counter,
+    };
+
+    let total_busy: u64 = busy_deltas.iter().map(|(_, busy)| busy).sum();
+    let denominator = elapsed_ns.max(total_busy) as f64;
+    let mut records = Vec::new();
+    let mut attributed = 0.0;
+    if denominator > 0.0 {
+        for &(pid, busy) in busy_deltas {
+            if busy == 0 {
+                continue;
+            }
+            let energy = delta_joules * busy as f64 / denominator;
+            attributed += energy;
+            records.push(record(pid, energy));
+        }
+    }
+    let unattributed = (delta_joules - attributed).max(0.0);
+    if unattributed > 0.0 {
+        records.push(record(UNATTRIBUTED_PID, unattributed));
+    }
+    records
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_xe_cycles_and_turns_them_into_busy_time() {
+        let contents = "drm-driver:\txe\ndrm-client-id:\t9\ndrm-pdev:\t0000:03:00.0\n\
+                        drm-cycles-rcs:\t300\ndrm-total-cycles-rcs:\t1000\n\
+                        drm-cycles-ccs:\t200\ndrm-total-cycles-ccs:\t1000\n";
+        let client = parse_drm_fdinfo(contents, &["i915", "xe"]).unwrap();
+        assert_eq!(
+            client.usage,
+            EngineUsage {
+                engine_ns: 0,
+                cycles: 500,
+                total_cycles: 1000,
+            }
+        );
+        assert_eq!(parse_drm_fdinfo(contents, &["amdgpu"]), None);
+
+        // Half of the 1000 timestamp cycles since the baseline were busy.
+        let baseline = EngineUsage::default();
+        assert_eq!(client.usage.busy_ns_since(&baseline, 2_000), 1_000);
+        assert_eq!(client.usage.busy_ns_since(&client.usage, 2_000), 0);
+    }
+}
@@ -0,0 +1,134 @@
+/// Exec Meters
+///
+/// Reads a meter by running a command that prints its readings as JSON,
+/// one object per device:
+///
+/// ```json
+/// {"device": "outlet3", "energy_j": 51234.5}
+/// {"device": "fan", "power_w": 12.5}
+/// ```
+///
+/// The objects may come one per line or as one JSON array. `energy_j` is a
+/// cumulative counter; `power_w` is the current power.
+use super::meter::{MeterReading, MeterSource};
+use crate::config::ExecCollectorConfig;
+use async_trait::async_trait;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::process::Command;
+
+/// A meter read by running a command; see [`ExecCollectorConfig`].
+pub(crate) struct ExecMeter {
+    config: ExecCollectorConfig,
+}
+
+impl ExecMeter {
+    pub(crate) fn new(config: ExecCollectorConfig) -> Self {
+        Self { config }
+    }
+
+    /// Run the command, killing it once it outlasts the interval.
+    async fn run(&self) -> Result<String, String> {
+        let (program, args) = self
+            .config
+            .command
+            .split_first()
+            .ok_or_else(|| "no command configured".to_string())?;
+        let output = Command::new(program)
+            .args(args)
+            .stdin(Stdio::null())
+            .kill_on_drop(true)
+            .output();
+        let timeout = self.interval();
+        let output = tokio::time::timeout(timeout, output)
+            .await
+            .map_err(|_| format!("{program} did not finish within {timeout:?}"))?
+            .map_err(|e| format!("failed to run {program}: {e}"))?;
+        if !output.status.success() {
+            return Err(format!(
+                "{program} failed ({}): {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        String::from_utf8(output.stdout)
+            .map_err(|e| format!("{program} printed invalid UTF-8: {e}"))
+    }
+}
+
+#[async_trait]
+impl MeterSource for ExecMeter {
+    fn device_prefix(&self) -> String {
+        format!("exec:{}", self.config.name)
+    }
+
+    fn interval(&self) -> Duration {
+        Duration::from_secs_f64(self.config.interval_secs)
+    }
+
+    async fn read(&self) -> Result<Vec<MeterReading>, String> {
+        parse_readings(&self.run().await?)
+    }
+}
+
+/// Readings printed by a meter command: a JSON array of objects or one
+/// object per line.
+fn parse_readings(output: &str) -> Result<Vec<MeterReading>, String> {
+    let output = output.trim();
+    let readings: Vec<MeterReading> = if output.starts_with('[') {
+        serde_json::from_str(output).map_err(|e| e.to_string())?
+    } else {
+        output
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| serde_json::from_str(line).map_err(|e| e.to_string()))
+            .collect::<Result<_, _>>()?
+    };
+    readings.iter().try_for_each(MeterReading::validate)?;
+    Ok(readings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn meter(command: &[&str]) -> ExecMeter {
+        ExecMeter::new(ExecCollectorConfig {
+            name: "script".to_string(),
+            command: command.iter().map(|arg| arg.to_string()).collect(),
+            interval_secs: 5.0,
+        })
+    }
+
+    #[test]
+    fn parses_json_lines_and_arrays() {
+        let lines =
+            "{\"device\":\"outlet\",\"energy_j\":10.5}\n\n{\"device\":\"fan\",\"power_w\":3}\n";
+        let readings = parse_readings(lines).unwrap();
+        assert_eq!(readings.len(), 2);
+        assert_eq!(readings[0].energy_j, Some(10.5));
+        assert_eq!(readings[1].power_w, Some(3.0));
+        assert_eq!(
+            parse_readings(r#"[{"device":"outlet","energy_j":1}]"#)
+                .unwrap()
+                .len(),
+            1
+        );
+        assert!(parse_readings(r#"{"device":"outlet"}"#).is_err());
+        assert!(parse_readings(r#"{"device":"outlet","power_w":-1}"#).is_err());
+        assert!(parse_readings("not json").is_err());
+    }
+
+    #[tokio::test]
+    async fn runs_commands() {
+        let readings = meter(&["sh", "-c", r#"echo '{"device":"board","power_w":5}'"#])
+            .read()
+            .await
+            .unwrap();
+        assert_eq!(readings[0].device, "board");
+        assert_eq!(
+            meter(&["sh", "-c", "exit 3"]).read().await,
+            Err("sh failed (exit status: 3): ".to_string())
+        );
+    }
+}
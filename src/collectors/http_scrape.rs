@@ -0,0 +1,393 @@
+/// HTTP Scrape Meters
+///
+/// Reads a meter from an HTTP endpoint, so that existing exporters, such as
+/// a PDU exporter, become data sources. Responses are parsed as Prometheus
+/// text exposition or as JSON, and each configured series picks one value
+/// out of them: a sample selected by metric name and labels, or a JSONPath
+/// of keys and array indices.
+use super::meter::{MeterReading, MeterSource};
+use crate::config::{HttpCollectorConfig, HttpScrapeFormat, HttpSeriesConfig, MeterQuantity};
+use async_trait::async_trait;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// A meter read from an HTTP endpoint; see [`HttpCollectorConfig`].
+pub(crate) struct HttpMeter {
+    config: HttpCollectorConfig,
+    client: reqwest::Client,
+    /// JSONPath of each series, for the JSON format
+    paths: Vec<Option<JsonPath>>,
+}
+
+impl HttpMeter {
+    pub(crate) fn new(config: HttpCollectorConfig) -> Result<Self, String> {
+        let paths = config
+            .series
+            .iter()
+            .map(|series| series.path.as_deref().map(JsonPath::parse).transpose())
+            .collect::<Result<_, _>>()?;
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs_f64(config.interval_secs))
+            .build()
+            .map_err(|e| e.to_string())?;
+        Ok(Self {
+            config,
+            client,
+            paths,
+        })
+    }
+
+    async fn fetch(&self) -> Result<String, reqwest::Error> {
+        let body = self
+            .client
+            .get(&self.config.url)
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?;
+        Ok(String::from_utf8_lossy(&body).into_owned())
+    }
+
+    /// Readings of the configured series in `body`.
+    fn readings(&self, body: &str) -> Result<Vec<MeterReading>, String> {
+        let values: Vec<Option<f64>> = match self.config.format {
+            HttpScrapeFormat::Prometheus => {
+                let samples = parse_exposition(body)?;
+                self.config
+                    .series
+                    .iter()
+                    .map(|series| sample_value(&samples, series))
+                    .collect()
+            }
+            HttpScrapeFormat::Json => {
+                let document: Value = serde_json::from_str(body).map_err(|e| e.to_string())?;
+                self.paths
+                    .iter()
+                    .map(|path| path.as_ref()?.select(&document).and_then(json_number))
+                    .collect()
+            }
+        };
+        self.config
+            .series
+            .iter()
+            .zip(values)
+            .map(|(series, value)| {
+                let value = value.ok_or_else(|| format!("no value for '{}'", series.device))?
+                    * series.scale;
+                let reading = match series.quantity {
+                    MeterQuantity::Energy => MeterReading {
+                        device: series.device.clone(),
+                        energy_j: Some(value),
+                        power_w: None,
+                    },
+                    MeterQuantity::Power => MeterReading {
+                        device: series.device.clone(),
+                        energy_j: None,
+                        power_w: Some(value),
+                    },
+                };
+                reading.validate()?;
+                Ok(reading)
+            })
+            .collect()
+    }
+}
+
+#[async_trait]
+impl MeterSource for HttpMeter {
+    fn device_prefix(&self) -> String {
+        format!("http:{}", self.config.name)
+    }
+
+    fn interval(&self) -> Duration {
+        Duration::from_secs_f64(self.config.interval_secs)
+    }
+
+    async fn read(&self) -> Result<Vec<MeterReading>, String> {
+        let body = self
+            .fetch()
+            .await
+            .map_err(|e| format!("scraping {} failed: {e}", self.config.url))?;
+        self.readings(&body)
+    }
+}
+
+/// One sample of a Prometheus exposition.
+#[derive(Debug, Clone, PartialEq)]
+struct Sample {
+    name: String,
+    labels: BTreeMap<String, String>,
+    value: f64,
+}
+
+/// Value of the first sample of the series' metric carrying its labels.
+fn sample_value(samples: &[Sample], series: &HttpSeriesConfig) -> Option<f64> {
+    let metric = series.metric.as_deref()?;
+    samples
+        .iter()
+        .find(|sample| {
+            sample.name == metric
+                && series
+                    .labels
+                    .iter()
+                    .all(|(name, value)| sample.labels.get(name) == Some(value))
+        })
+        .map(|sample| sample.value)
+}
+
+/// Samples of a Prometheus text exposition; comments and blank lines are
+/// skipped.
+fn parse_exposition(text: &str) -> Result<Vec<Sample>, String> {
+    text.lines()
+        .enumerate()
+        .map(|(index, line)| (index, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(index, line)| {
+            parse_sample(line).ok_or_else(|| format!("malformed sample on line {}", index + 1))
+        })
+        .collect()
+}
+
+/// `name{label="value",...} value [timestamp]`
+fn parse_sample(line: &str) -> Option<Sample> {
+    let name_end = line
+        .find(|char: char| char == '{' || char.is_whitespace())
+        .unwrap_or(line.len());
+    let name = &line[..name_end];
+    let mut rest = &line[name_end..];
+    let mut labels = BTreeMap::new();
+    if let Some(label_text) = rest.strip_prefix('{') {
+        let (parsed, after) = parse_labels(label_text)?;
+        labels = parsed;
+        rest = after;
+    }
+    let value = rest.split_whitespace().next()?.parse().ok()?;
+    (!name.is_empty()).then(|| Sample {
+        name: name.to_string(),
+        labels,
+        value,
+    })
+}
+
+/// Labels up to the closing brace, and the text after it.
+fn parse_labels(mut text: &str) -> Option<(BTreeMap<String, String>, &str)> {
+    let mut labels = BTreeMap::new();
+    loop {
+        text = text.trim_start().trim_start_matches(',').trim_start();
+        if let Some(rest) = text.strip_prefix('}') {
+            return Some((labels, rest));
+        }
+        let (name, rest) = text.split_once('=')?;
+        let mut chars = rest.trim_start().strip_prefix('"')?.char_indices();
+        let mut value = String::new();
+        let end = loop {
+            match chars.next()? {
+                (index, '"') => break index,
+                (_, '\\') => value.push(match chars.next()?.1 {
+                    'n' => '\n',
+                    escaped => escaped,
+                }),
+                (_, char) => value.push(char),
+            }
+        };
+        labels.insert(name.trim().to_string(), value);
+        text = &rest.trim_start()[1 + end + 1..];
+    }
+}
+
+/// A number, or a string holding one, as exporters write either.
+fn json_number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(number) => number.as_f64(),
+        Value::String(text) => text.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+/// The subset of JSONPath selecting a single value: `$` followed by
+/// `.key`, `['key']` and `[index]` steps.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct JsonPath(Vec<JsonPathStep>);
+
+#[derive(Debug, Clone, PartialEq)]
+enum JsonPathStep {
+    Key(String),
+    Index(usize),
+}
+
+impl JsonPath {
+    pub(crate) fn parse(path: &str) -> Result<Self, String> {
+        let mut rest = path
+            .strip_prefix('$')
+            .ok_or_else(|| format!("'{path}' must start with $"))?;
+        let mut steps = Vec::new();
+        while !rest.is_empty() {
+            if let Some(after) = rest.strip_prefix('.') {
+                let end = after.find(['.', '[']).unwrap_or(after.len());
+                if end == 0 {
+                    return Err(format!("'{path}' has an empty key"));
+                }
+                steps.push(JsonPathStep::Key(after[..end].to_string()));
+                rest = &after[end..];
+            } else if let Some(after) = rest.strip_prefix('[') {
+                let (inner, after) = after
+                    .split_once(']')
+                    .ok_or_else(|| format!("'{path}' has an unclosed ["))?;
+                let quoted = inner
+                    .strip_prefix('\'')
+                    .and_then(|key| key.strip_suffix('\''))
+                    .or_else(|| inner.strip_prefix('"')?.strip_suffix('"'));
+                steps.push(match quoted {
+                    Some(key) => JsonPathStep::Key(key.to_string()),
+                    None => JsonPathStep::Index(
+                        inner
+                            .parse()
+                            .map_err(|_| format!("'{path}' has an invalid index [{inner}]"))?,
+                    ),
+                });
+                rest = after;
+            } else {
+                return Err(format!("'{path}' has an invalid step at '{rest}'"));
+            }
+        }
+        Ok(Self(steps))
+    }
+
+    fn select<'a>(&self, document: &'a Value) -> Option<&'a Value> {
+        self.0.iter().try_fold(document, |value, step| match step {
+            JsonPathStep::Key(key) => value.get(key),
+            JsonPathStep::Index(index) => value.get(index),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    fn series(device: &str, quantity: MeterQuantity) -> HttpSeriesConfig {
+        HttpSeriesConfig {
+            device: device.to_string(),
+            metric: None,
+            labels: BTreeMap::new(),
+            path: None,
+            quantity,
+            scale: 1.0,
+        }
+    }
+
+    fn meter(url: &str, format: HttpScrapeFormat, series: Vec<HttpSeriesConfig>) -> HttpMeter {
+        HttpMeter::new(HttpCollectorConfig {
+            name: "pdu".to_string(),
+            url: url.to_string(),
+            format,
+            interval_secs: 5.0,
+            series,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn parses_prometheus_exposition() {
+        let samples = parse_exposition(
+            "# HELP pdu_energy_kwh Outlet energy\n\
+             # TYPE pdu_energy_kwh counter\n\
+             pdu_energy_kwh{outlet=\"1\",name=\"rack \\\"A\\\"\"} 2.5 1700000000000\n\
+             \n\
+             pdu_power_watts 118\n",
+        )
+        .unwrap();
+        assert_eq!(samples.len(), 2);
+        assert_eq!(samples[0].labels["outlet"], "1");
+        assert_eq!(samples[0].labels["name"], "rack \"A\"");
+        assert_eq!(samples[0].value, 2.5);
+        assert_eq!(samples[1].name, "pdu_power_watts");
+        assert!(parse_exposition("pdu_power_watts{outlet=\"1\" 3\n").is_err());
+        assert!(parse_exposition("pdu_power_watts\n").is_err());
+    }
+
+    #[test]
+    fn parses_and_selects_json_paths() {
+        let document: Value =
+            serde_json::from_str(r#"{"outlets":[{"energy":"7.5"},{"energy":9}],"total w":3}"#)
+                .unwrap();
+        let select = |path| {
+            JsonPath::parse(path)
+                .unwrap()
+                .select(&document)
+                .and_then(json_number)
+        };
+        assert_eq!(select("$.outlets[1].energy"), Some(9.0));
+        assert_eq!(select("$['outlets'][0][\"energy\"]"), Some(7.5));
+        assert_eq!(select("$['total w']"), Some(3.0));
+        assert_eq!(select("$.outlets[5].energy"), None);
+        assert!(JsonPath::parse("outlets").is_err());
+        assert!(JsonPath::parse("$.outlets[x]").is_err());
+        assert!(JsonPath::parse("$..energy").is_err());
+    }
+
+    #[test]
+    fn series_become_scaled_readings() {
+        let mut energy = series("outlet1", MeterQuantity::Energy);
+        energy.metric = Some("pdu_energy_kwh".to_string());
+        energy.labels.insert("outlet".to_string(), "1".to_string());
+        energy.scale = 3_600_000.0;
+        let mut power = series("rack", MeterQuantity::Power);
+        power.metric = Some("pdu_power_watts".to_string());
+        let prometheus = meter(
+            "http://127.0.0.1:1/metrics",
+            HttpScrapeFormat::Prometheus,
+            vec![energy, power],
+        );
+
+        let readings = prometheus
+            .readings("pdu_energy_kwh{outlet=\"2\"} 9\npdu_energy_kwh{outlet=\"1\"} 0.5\npdu_power_watts 118\n")
+            .unwrap();
+        assert_eq!(readings[0].energy_j, Some(1_800_000.0));
+        assert_eq!(readings[1].power_w, Some(118.0));
+        assert!(prometheus.readings("pdu_power_watts 118\n").is_err());
+
+        let mut board = series("board", MeterQuantity::Power);
+        board.path = Some("$.power".to_string());
+        let json = meter("http://127.0.0.1:1/", HttpScrapeFormat::Json, vec![board]);
+        assert_eq!(
+            json.readings(r#"{"power": 12}"#).unwrap()[0].power_w,
+            Some(12.0)
+        );
+        assert!(json.readings(r#"{"power": -1}"#).is_err());
+    }
+
+    #[tokio::test]
+    async fn scrapes_endpoints() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/metrics", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buffer = [0u8; 4096];
+            let _ = stream.read(&mut buffer).await.unwrap();
+            let body = "pdu_power_watts 42\n";
+            stream
+                .write_all(
+                    format!(
+                        "HTTP/1.1 200 OK\r\ncontent-length: {}\r\n\r\n{body}",
+                        body.len()
+                    )
+                    .as_bytes(),
+                )
+                .await
+                .unwrap();
+        });
+        let mut power = series("rack", MeterQuantity::Power);
+        power.metric = Some("pdu_power_watts".to_string());
+
+        let readings = meter(&url, HttpScrapeFormat::Prometheus, vec![power])
+            .read()
+            .await
+            .unwrap();
+
+        assert_eq!(readings[0].power_w, Some(42.0));
+    }
+}
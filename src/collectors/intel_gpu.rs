@@ -0,0 +1,401 @@
+/// Intel GPU Collector
+///
+/// Reads Intel GPUs through the hwmon interface of the i915 and xe drivers
+/// under `/sys/class/drm/card*/device/hwmon`. The cumulative
+/// `energy1_input` counter, in microjoules, is differenced between reads;
+/// xe GPUs that only report the package counter `energy2_input` are read
+/// from that instead. Integrated GPUs expose no hwmon energy; their energy
+/// is part of the RAPL package and they are not listed.
+///
+/// Energy is attributed like the AMD collector attributes it, by each
+/// tracked process's engine busy time on the GPU: the `drm-engine-*` times
+/// (i915) or `drm-cycles-*` counts (xe) in its DRM fdinfo.
+use crate::collectors::drm_fdinfo::{EngineUsage, attribute_busy_share, usage_by_slot};
+use crate::device_registry::DeviceInfo;
+use crate::energy_group::{EnergyCollector, EnergyRecord};
+use crate::read_schedule::ReadSchedule;
+use crate::utils::errors::CollectorError;
+use crate::utils::time::RecordTime;
+use async_trait::async_trait;
+use log::{debug, warn};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, RwLock};
+use std::time::Instant;
+
+const DEFAULT_DRM_PATH: &str = "/sys/class/drm";
+const DEFAULT_PROC_PATH: &str = "/proc";
+/// PCI vendor id of Intel in `device/vendor`.
+const INTEL_PCI_VENDOR: &str = "0x8086";
+/// `drm-driver` of Intel clients in fdinfo.
+const INTEL_DRM_DRIVERS: [&str; 2] = ["i915", "xe"];
+/// hwmon energy files, card before package.
+const ENERGY_FILES: [&str; 2] = ["energy1_input", "energy2_input"];
+
+/// An Intel GPU found under the DRM class directory.
+#[derive(Debug, Clone, PartialEq)]
+struct IntelCard {
+    /// Number of the `card<N>` entry
+    index: u32,
+    /// PCI address, matched against `drm-pdev` in fdinfo
+    pci_slot: Option<String>,
+    /// Kernel driver bound to the card, `i915` or `xe`
+    driver: Option<String>,
+    /// The hwmon energy counter
+    energy_path: PathBuf,
+}
+
+impl IntelCard {
+    fn device_name(&self) -> String {
+        format!("intel:gpu:{}", self.index)
+    }
+
+    /// Read the card's cumulative energy in microjoules.
+    fn read(&self) -> Result<u64, CollectorError> {
+        let contents = fs::read_to_string(&self.energy_path)
+            .map_err(|e| CollectorError::from_io(self.energy_path.display(), &e))?;
+        contents
+            .trim()
+            .parse()
+            .map_err(|e| CollectorError::Parse(format!("{}: {}", self.energy_path.display(), e)))
+    }
+
+    fn hwmon_dir(&self) -> &Path {
+        self.energy_path.parent().unwrap_or(&self.energy_path)
+    }
+}
+
+/// Previous read of a card.
+#[derive(Debug, Default)]
+struct CardState {
+    microjoules: Option<u64>,
+    monotonic_ns: i64,
+    /// Cumulative engine usage per tracked pid
+    usage: HashMap<u32, EngineUsage>,
+}
+
+/// Intel GPU energy collector using sysfs hwmon and DRM fdinfo.
+pub struct IntelGpu {
+    drm_dir: PathBuf,
+    proc_dir: PathBuf,
+    /// GPUs as of the last discovery
+    cards: RwLock<Vec<IntelCard>>,
+    /// PIDs to attribute energy to
+    tracked_pids: Mutex<Vec<u32>>,
+    /// Previous read per card index
+    state: Mutex<HashMap<u32, CardState>>,
+    /// Offsets of the GPU reads within each tick
+    read_schedule: Mutex<ReadSchedule>,
+}
+
+impl IntelGpu {
+    /// Construct a collector for the Intel GPUs under `drm_path`, by default
+    /// `/sys/class/drm`.
+    pub fn new(drm_path: Option<String>) -> Self {
+        let drm_dir = PathBuf::from(drm_path.unwrap_or_else(|| DEFAULT_DRM_PATH.to_string()));
+        let cards = discover_cards(&drm_dir);
+        Self {
+            drm_dir,
+            proc_dir: PathBuf::from(DEFAULT_PROC_PATH),
+            cards: RwLock::new(cards),
+            tracked_pids: Mutex::new(Vec::new()),
+            state: Mutex::new(HashMap::new()),
+            read_schedule: Mutex::new(ReadSchedule::default()),
+        }
+    }
+}
+
+impl Default for IntelGpu {
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+
+fn read_trimmed(path: &Path) -> Option<String> {
+    fs::read_to_string(path)
+        .ok()
+        .map(|contents| contents.trim().to_string())
+        .filter(|contents| !contents.is_empty())
+}
+
+/// The Intel GPUs under `drm_dir` with a hwmon energy counter, by card
+/// number.
+fn discover_cards(drm_dir: &Path) -> Vec<IntelCard> {
+    let Ok(entries) = fs::read_dir(drm_dir) else {
+        return Vec::new();
+    };
+    let mut cards: Vec<IntelCard> = entries
+        .flatten()
+        .filter_map(|entry| {
+            // Connectors such as card0-DP-1 are skipped by the parse.
+            let index = entry
+                .file_name()
+                .to_str()?
+                .strip_prefix("card")?
+                .parse()
+                .ok()?;
+            let device_dir = entry.path().join("device");
+            if read_trimmed(&device_dir.join("vendor")).as_deref() != Some(INTEL_PCI_VENDOR) {
+                return None;
+            }
+            let mut hwmon_dirs: Vec<PathBuf> = fs::read_dir(device_dir.join("hwmon"))
+                .ok()?
+                .flatten()
+                .map(|hwmon| hwmon.path())
+                .filter(|path| {
+                    path.file_name()
+                        .and_then(|name| name.to_str())
+                        .is_some_and(|name| name.starts_with("hwmon"))
+                })
+                .collect();
+            hwmon_dirs.sort();
+            let energy_path = hwmon_dirs.iter().find_map(|hwmon_dir| {
+                ENERGY_FILES
+                    .iter()
+                    .map(|file| hwmon_dir.join(file))
+                    .find(|path| path.exists())
+            })?;
+            let uevent = read_trimmed(&device_dir.join("uevent"));
+            let uevent_value = |key: &str| {
+                uevent.as_deref().and_then(|uevent| {
+                    uevent
+                        .lines()
+                        .find_map(|line| line.strip_prefix(key))
+                        .map(str::to_string)
+                })
+            };
+            Some(IntelCard {
+                index,
+                pci_slot: uevent_value("PCI_SLOT_NAME="),
+                driver: uevent_value("DRIVER="),
+                energy_path,
+            })
+        })
+        .collect();
+    cards.sort_by_key(|card| card.index);
+    cards
+}
+
+#[async_trait]
+impl EnergyCollector for IntelGpu {
+    fn set_tracked_pids(&self, pids: Vec<u32>) {
+        *self.tracked_pids.lock().unwrap() = pids;
+    }
+
+    fn set_read_schedule(&self, schedule: ReadSchedule) {
+        *self.read_schedule.lock().unwrap() = schedule;
+    }
+
+    async fn get_energy_trace(&self) -> Result<Vec<EnergyRecord>, CollectorError> {
+        let tick_start = Instant::now();
+        let schedule = self.read_schedule.lock().unwrap().clone();
+        let cards = self.cards.read().unwrap().clone();
+        let pids = self.tracked_pids.lock().unwrap().clone();
+        let mut usage_by_slot = usage_by_slot(&self.proc_dir, &pids, &INTEL_DRM_DRIVERS);
+
+        let mut records = Vec::new();
+        for card in cards {
+            let device = card.device_name();
+            schedule.wait(tick_start, &device).await;
+            let microjoules = match card.read() {
+                Ok(microjoules) => microjoules,
+                Err(e) => {
+                    warn!("Failed to read Intel GPU {}: {}", card.index, e);
+                    continue;
+                }
+            };
+            let time = RecordTime::now();
+            let usage = card
+                .pci_slot
+                .as_ref()
+                .and_then(|slot| usage_by_slot.remove(slot))
+                .unwrap_or_default();
+
+            let mut state = self.state.lock().unwrap();
+            let state = state.entry(card.index).or_default();
+            let elapsed_ns = (time.monotonic_ns - state.monotonic_ns).max(0) as u64;
+            // The first read is a baseline.
+            let delta_joules = state.microjoules.map_or(0.0, |previous| {
+                microjoules.saturating_sub(previous) as f64 / 1e6
+            });
+            let busy_deltas: Vec<(u32, u64)> = usage
+                .iter()
+                .map(|(&pid, current)| {
+                    let previous = state.usage.get(&pid).unwrap_or(current);
+                    (pid, current.busy_ns_since(previous, elapsed_ns))
+                })
+                .collect();
+            state.microjoules = Some(microjoules);
+            state.monotonic_ns = time.monotonic_ns;
+            state.usage = usage;
+
+            records.extend(attribute_busy_share(
+                &device,
+                delta_joules,
+                elapsed_ns,
+                &busy_deltas,
+                time,
+                Some(microjoules as f64 / 1e6),
+            ));
+        }
+
+        debug!(
+            "Intel GPU energy trace collected: {} records",
+            records.len()
+        );
+        Ok(records)
+    }
+
+    fn devices(&self) -> Vec<DeviceInfo> {
+        self.cards
+            .read()
+            .unwrap()
+            .iter()
+            .map(|card| {
+                let max_power_watts = read_trimmed(&card.hwmon_dir().join("power1_rated_max"))
+                    .and_then(|microwatts| microwatts.parse::<u64>().ok())
+                    .filter(|&microwatts| microwatts > 0)
+                    .map(|microwatts| microwatts as f64 / 1e6);
+                DeviceInfo::new(card.device_name())
+                    .with_vendor(Some("Intel".to_string()))
+                    .with_max_power_watts(max_power_watts)
+                    .with_source(Some(
+                        card.driver
+                            .as_deref()
+                            .map_or("hwmon".to_string(), |driver| format!("{driver}-hwmon")),
+                    ))
+            })
+            .collect()
+    }
+
+    fn rediscover(&self) -> bool {
+        let cards = discover_cards(&self.drm_dir);
+        let mut current = self.cards.write().unwrap();
+        if *current == cards {
+            return false;
+        }
+        log::info!(
+            "Intel GPUs changed: {} -> {} devices",
+            current.len(),
+            cards.len()
+        );
+        // A card number may now belong to another GPU, so only cards found
+        // unchanged keep their baselines.
+        self.state.lock().unwrap().retain(|index, _| {
+            current
+                .iter()
+                .any(|card| card.index == *index && cards.contains(card))
+        });
+        *current = cards;
+        true
+    }
+
+    fn is_available() -> bool {
+        discover_cards(Path::new(DEFAULT_DRM_PATH))
+            .iter()
+            .any(|card| card.read().is_ok())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    /// A card with a hwmon directory holding `files`.
+    fn write_card(root: &Path, entry: &str, vendor: &str, slot: &str, files: &[(&str, &str)]) {
+        let device_dir = root.join(entry).join("device");
+        let hwmon_dir = device_dir.join("hwmon").join("hwmon4");
+        fs::create_dir_all(&hwmon_dir).unwrap();
+        fs::write(device_dir.join("vendor"), format!("{vendor}\n")).unwrap();
+        fs::write(
+            device_dir.join("uevent"),
+            format!("DRIVER=i915\nPCI_SLOT_NAME={slot}\n"),
+        )
+        .unwrap();
+        for (file, contents) in files {
+            fs::write(hwmon_dir.join(file), contents).unwrap();
+        }
+    }
+
+    fn fdinfo(slot: &str, client_id: u64, render_ns: u64) -> String {
+        format!(
+            "pos:\t0\nflags:\t02100002\ndrm-driver:\ti915\ndrm-client-id:\t{client_id}\n\
+             drm-pdev:\t{slot}\ndrm-engine-render:\t{render_ns} ns\ndrm-engine-copy:\t0 ns\n\
+             drm-engine-capacity-video:\t2\n"
+        )
+    }
+
+    #[test]
+    fn discovers_intel_cards_with_hwmon_energy() {
+        let drm = TempDir::new().unwrap();
+        write_card(
+            drm.path(),
+            "card1",
+            INTEL_PCI_VENDOR,
+            "0000:03:00.0",
+            &[("energy1_input", "5"), ("power1_rated_max", "190000000")],
+        );
+        // An integrated GPU without hwmon energy, and an AMD card
+        write_card(drm.path(), "card0", INTEL_PCI_VENDOR, "0000:00:02.0", &[]);
+        write_card(
+            drm.path(),
+            "card2",
+            "0x1002",
+            "0000:04:00.0",
+            &[("energy1_input", "5")],
+        );
+        fs::create_dir_all(drm.path().join("card1-DP-1")).unwrap();
+
+        let gpu = IntelGpu::new(Some(drm.path().to_string_lossy().into_owned()));
+        let cards = gpu.cards.read().unwrap().clone();
+
+        assert_eq!(cards.len(), 1);
+        assert_eq!(cards[0].index, 1);
+        assert_eq!(cards[0].pci_slot.as_deref(), Some("0000:03:00.0"));
+        assert_eq!(cards[0].read(), Ok(5));
+        let devices = gpu.devices();
+        assert_eq!(devices[0].name, "intel:gpu:1");
+        assert_eq!(devices[0].vendor.as_deref(), Some("Intel"));
+        assert_eq!(devices[0].max_power_watts, Some(190.0));
+        assert_eq!(devices[0].source.as_deref(), Some("i915-hwmon"));
+    }
+
+    #[tokio::test]
+    async fn differences_energy_and_attributes_it_to_tracked_clients() {
+        let drm = TempDir::new().unwrap();
+        let proc = TempDir::new().unwrap();
+        write_card(
+            drm.path(),
+            "card0",
+            INTEL_PCI_VENDOR,
+            "0000:03:00.0",
+            &[("energy1_input", "1000000")],
+        );
+        let fdinfo_dir = proc.path().join("42").join("fdinfo");
+        fs::create_dir_all(&fdinfo_dir).unwrap();
+        fs::write(fdinfo_dir.join("5"), fdinfo("0000:03:00.0", 3, 0)).unwrap();
+
+        let mut gpu = IntelGpu::new(Some(drm.path().to_string_lossy().into_owned()));
+        gpu.proc_dir = proc.path().to_path_buf();
+        gpu.set_tracked_pids(vec![42]);
+        assert!(gpu.get_energy_trace().await.unwrap().is_empty());
+
+        let energy_path = drm.path().join("card0/device/hwmon/hwmon4/energy1_input");
+        fs::write(&energy_path, "3000000").unwrap();
+        // Busy for longer than the test ran, so the process gets it all.
+        fs::write(
+            fdinfo_dir.join("5"),
+            fdinfo("0000:03:00.0", 3, 1_000_000_000_000),
+        )
+        .unwrap();
+        let records = gpu.get_energy_trace().await.unwrap();
+
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].pid, 42);
+        assert_eq!(records[0].device, "intel:gpu:0");
+        assert!((records[0].energy - 2.0).abs() < 1e-9);
+        assert_eq!(records[0].raw_counter, Some(3.0));
+    }
+}
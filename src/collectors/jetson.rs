@@ -0,0 +1,382 @@
+/// Jetson Collector
+///
+/// Reads the power rails of NVIDIA Jetson boards, which have no NVML energy
+/// counters, from the INA3221 monitors under
+/// `/sys/bus/i2c/devices/*/hwmon`. Each channel reports the bus voltage of
+/// its rail in `in<N>_input`, in millivolts, and the current in
+/// `curr<N>_input`, in milliamps; their product is integrated over the
+/// time since the previous read.
+///
+/// Rails are named after the components in their `in<N>_label` and
+/// recorded as `jetson:<rail>`: `cpu`, `gpu`, `soc`, `dram`, `input` for
+/// the whole module, or a combination such as `cpu_gpu_cv` on modules where
+/// one rail feeds several. CPU rail energy is attributed by each tracked
+/// process's CPU time, as a share of the time of all logical CPUs. The rest
+/// of it, and the energy of the other rails, is recorded as unattributed.
+use crate::collectors::drm_fdinfo::attribute_busy_share;
+use crate::device_registry::{DeviceInfo, DeviceKind};
+use crate::energy_group::{EnergyCollector, EnergyRecord};
+use crate::utils::errors::CollectorError;
+use crate::utils::time::RecordTime;
+use async_trait::async_trait;
+use log::warn;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use sysinfo::{Pid, ProcessRefreshKind, ProcessesToUpdate, System};
+
+const DEFAULT_I2C_PATH: &str = "/sys/bus/i2c/devices";
+/// hwmon `name` of INA3221 monitors.
+const INA3221_NAME: &str = "ina3221";
+const INA3221_CHANNELS: u32 = 3;
+const CPU_RAIL: &str = "cpu";
+
+/// A power rail measured by one INA3221 channel.
+#[derive(Debug, Clone, PartialEq)]
+struct Rail {
+    /// Name after the `jetson:` prefix
+    name: String,
+    /// hwmon directory of the monitor
+    hwmon_dir: PathBuf,
+    channel: u32,
+}
+
+impl Rail {
+    fn device_name(&self) -> String {
+        format!("jetson:{}", self.name)
+    }
+
+    fn kind(&self) -> DeviceKind {
+        match self.name.as_str() {
+            CPU_RAIL => DeviceKind::CpuPackage,
+            "gpu" => DeviceKind::Gpu,
+            "dram" => DeviceKind::Dram,
+            "input" => DeviceKind::Psys,
+            _ => DeviceKind::Other,
+        }
+    }
+
+    /// Power drawn through the rail, in watts.
+    fn read_watts(&self) -> Result<f64, String> {
+        let channel = self.channel;
+        let millivolts = read_u64(&self.hwmon_dir.join(format!("in{channel}_input")))?;
+        let milliamps = read_u64(&self.hwmon_dir.join(format!("curr{channel}_input")))?;
+        Ok(millivolts as f64 * milliamps as f64 / 1e6)
+    }
+}
+
+fn read_u64(path: &Path) -> Result<u64, String> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+    contents
+        .trim()
+        .parse()
+        .map_err(|e| format!("invalid value in {}: {}", path.display(), e))
+}
+
+fn read_trimmed(path: &Path) -> Option<String> {
+    fs::read_to_string(path)
+        .ok()
+        .map(|contents| contents.trim().to_string())
+        .filter(|contents| !contents.is_empty())
+}
+
+/// Rail of a channel label such as `VDD_CPU_GPU_CV` or `POM_5V_GPU`: the
+/// components it names, or the whole label when it names none.
+fn rail_name(label: &str) -> String {
+    let label = label.trim().to_ascii_lowercase();
+    let components: Vec<&str> = label
+        .split(['_', ' '])
+        .filter_map(|part| match part {
+            "cpu" | "gpu" | "cv" | "soc" => Some(part),
+            "ddr" | "dram" => Some("dram"),
+            "in" => Some("input"),
+            _ => None,
+        })
+        .collect();
+    if components.is_empty() {
+        label.replace(|c: char| !c.is_ascii_alphanumeric(), "_")
+    } else {
+        components.join("_")
+    }
+}
+
+/// The labelled, enabled channels of the INA3221 monitors on the I2C
+/// devices under `i2c_dir`.
+fn discover_rails(i2c_dir: &Path) -> Vec<Rail> {
+    let Ok(devices) = fs::read_dir(i2c_dir) else {
+        return Vec::new();
+    };
+    let mut hwmon_dirs: Vec<PathBuf> = devices
+        .flatten()
+        .filter_map(|device| fs::read_dir(device.path().join("hwmon")).ok())
+        .flat_map(|entries| entries.flatten().map(|entry| entry.path()))
+        .filter(|path| read_trimmed(&path.join("name")).as_deref() == Some(INA3221_NAME))
+        .collect();
+    hwmon_dirs.sort();
+
+    let mut rails = Vec::new();
+    for hwmon_dir in hwmon_dirs {
+        for channel in 1..=INA3221_CHANNELS {
+            let Some(label) = read_trimmed(&hwmon_dir.join(format!("in{channel}_label"))) else {
+                continue;
+            };
+            // Channels without a rail in the device tree are disabled.
+            let enable = read_trimmed(&hwmon_dir.join(format!("in{channel}_enable")));
+            if enable.as_deref() == Some("0") {
+                continue;
+            }
+            rails.push(Rail {
+                name: rail_name(&label),
+                hwmon_dir: hwmon_dir.clone(),
+                channel,
+            });
+        }
+    }
+    rails
+}
+
+/// Time and CPU usage of a read.
+#[derive(Debug, Default)]
+struct ReadState {
+    monotonic_ns: i64,
+    /// Cumulative CPU time per tracked pid, in nanoseconds
+    cpu_ns: HashMap<u32, u64>,
+}
+
+/// Records for rail power `watts` read at `time`, integrated over the time
+/// since `previous`.
+fn records_since(
+    previous: &ReadState,
+    current: &ReadState,
+    watts: &[(String, f64)],
+    time: RecordTime,
+    cpu_count: u64,
+) -> Vec<EnergyRecord> {
+    let elapsed_ns = (current.monotonic_ns - previous.monotonic_ns).max(0) as u64;
+    let cpu_deltas: Vec<(u32, u64)> = current
+        .cpu_ns
+        .iter()
+        .map(|(&pid, &cpu_ns)| {
+            let before = previous.cpu_ns.get(&pid).copied().unwrap_or(cpu_ns);
+            (pid, cpu_ns.saturating_sub(before))
+        })
+        .collect();
+    let mut records = Vec::new();
+    for (rail, watts) in watts {
+        let joules = watts * elapsed_ns as f64 / 1e9;
+        let (capacity_ns, busy_deltas) = if rail == CPU_RAIL {
+            (elapsed_ns * cpu_count, cpu_deltas.as_slice())
+        } else {
+            (elapsed_ns, &[][..])
+        };
+        records.extend(attribute_busy_share(
+            &format!("jetson:{rail}"),
+            joules,
+            capacity_ns,
+            busy_deltas,
+            time,
+            None,
+        ));
+    }
+    records
+}
+
+/// Jetson power rail collector using the INA3221 hwmon interface.
+pub struct JetsonIna {
+    rails: Vec<Rail>,
+    /// PIDs to attribute CPU energy to
+    tracked_pids: Mutex<Vec<u32>>,
+    system: Mutex<System>,
+    /// Previous read, the baseline of the next one
+    state: Mutex<Option<ReadState>>,
+}
+
+impl JetsonIna {
+    /// Construct a collector for the INA3221 monitors under `i2c_path`, by
+    /// default `/sys/bus/i2c/devices`.
+    pub fn new(i2c_path: Option<String>) -> Self {
+        let i2c_dir = PathBuf::from(i2c_path.unwrap_or_else(|| DEFAULT_I2C_PATH.to_string()));
+        Self {
+            rails: discover_rails(&i2c_dir),
+            tracked_pids: Mutex::new(Vec::new()),
+            system: Mutex::new(System::new()),
+            state: Mutex::new(None),
+        }
+    }
+
+    /// CPU time of each of `pids` still running, in nanoseconds.
+    fn cpu_times(&self, pids: &[u32]) -> HashMap<u32, u64> {
+        let mut system = self.system.lock().unwrap();
+        let sys_pids: Vec<Pid> = pids.iter().map(|&pid| Pid::from_u32(pid)).collect();
+        system.refresh_processes_specifics(
+            ProcessesToUpdate::Some(&sys_pids),
+            true,
+            ProcessRefreshKind::nothing().with_cpu(),
+        );
+        pids.iter()
+            .filter_map(|&pid| {
+                let process = system.process(Pid::from_u32(pid))?;
+                Some((pid, process.accumulated_cpu_time() * 1_000_000))
+            })
+            .collect()
+    }
+}
+
+impl Default for JetsonIna {
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+
+#[async_trait]
+impl EnergyCollector for JetsonIna {
+    fn set_tracked_pids(&self, pids: Vec<u32>) {
+        *self.tracked_pids.lock().unwrap() = pids;
+    }
+
+    async fn get_energy_trace(&self) -> Result<Vec<EnergyRecord>, CollectorError> {
+        let watts: Vec<(String, f64)> = self
+            .rails
+            .iter()
+            .filter_map(|rail| match rail.read_watts() {
+                Ok(watts) => Some((rail.name.clone(), watts)),
+                Err(e) => {
+                    warn!("Failed to read Jetson rail {}: {}", rail.name, e);
+                    None
+                }
+            })
+            .collect();
+        let pids = self.tracked_pids.lock().unwrap().clone();
+        let cpu_ns = self.cpu_times(&pids);
+        let time = RecordTime::now();
+        let current = ReadState {
+            monotonic_ns: time.monotonic_ns,
+            cpu_ns,
+        };
+
+        let mut state = self.state.lock().unwrap();
+        let cpu_count = std::thread::available_parallelism().map_or(1, |count| count.get() as u64);
+        let records = state.as_ref().map_or_else(Vec::new, |previous| {
+            records_since(previous, &current, &watts, time, cpu_count)
+        });
+        *state = Some(current);
+        Ok(records)
+    }
+
+    fn devices(&self) -> Vec<DeviceInfo> {
+        self.rails
+            .iter()
+            .map(|rail| DeviceInfo {
+                kind: rail.kind(),
+                ..DeviceInfo::new(rail.device_name())
+                    .with_vendor(Some("NVIDIA".to_string()))
+                    .with_source(Some(INA3221_NAME.to_string()))
+            })
+            .collect()
+    }
+
+    fn is_available() -> bool {
+        discover_rails(Path::new(DEFAULT_I2C_PATH))
+            .iter()
+            .any(|rail| rail.read_watts().is_ok())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collectors::drm_fdinfo::UNATTRIBUTED_PID;
+
+    /// An INA3221 at `address` with `channels` of label, millivolts and
+    /// milliamps.
+    fn write_ina3221(root: &Path, address: &str, channels: &[(u32, &str, u64, u64)]) -> PathBuf {
+        let hwmon_dir = root.join(address).join("hwmon").join("hwmon1");
+        fs::create_dir_all(&hwmon_dir).unwrap();
+        fs::write(hwmon_dir.join("name"), "ina3221\n").unwrap();
+        for (channel, label, millivolts, milliamps) in channels {
+            fs::write(hwmon_dir.join(format!("in{channel}_label")), label).unwrap();
+            fs::write(hwmon_dir.join(format!("in{channel}_enable")), "1\n").unwrap();
+            fs::write(
+                hwmon_dir.join(format!("in{channel}_input")),
+                millivolts.to_string(),
+            )
+            .unwrap();
+            fs::write(
+                hwmon_dir.join(format!("curr{channel}_input")),
+                milliamps.to_string(),
+            )
+            .unwrap();
+        }
+        hwmon_dir
+    }
+
+    #[test]
+    fn maps_rail_labels_to_components() {
+        assert_eq!(rail_name("VDD_IN"), "input");
+        assert_eq!(rail_name("POM_5V_GPU"), "gpu");
+        assert_eq!(rail_name("VDD_SYS_CPU"), "cpu");
+        assert_eq!(rail_name("VDD_SYS_DDR"), "dram");
+        assert_eq!(rail_name("VDD_CPU_GPU_CV\n"), "cpu_gpu_cv");
+        assert_eq!(rail_name("VDD_4V0_WIFI"), "vdd_4v0_wifi");
+    }
+
+    #[test]
+    fn discovers_enabled_ina3221_rails() {
+        let root = tempfile::tempdir().unwrap();
+        let hwmon_dir = write_ina3221(
+            root.path(),
+            "1-0040",
+            &[
+                (1, "VDD_IN", 5000, 1200),
+                (2, "VDD_CPU_GPU_CV", 5000, 400),
+                (3, "VDD_SOC", 5000, 300),
+            ],
+        );
+        fs::write(hwmon_dir.join("in3_enable"), "0\n").unwrap();
+        // A temperature sensor on the same bus
+        let other = root.path().join("1-004c/hwmon/hwmon0");
+        fs::create_dir_all(&other).unwrap();
+        fs::write(other.join("name"), "tmp451\n").unwrap();
+
+        let rails = discover_rails(root.path());
+        let names: Vec<String> = rails.iter().map(Rail::device_name).collect();
+        assert_eq!(names, ["jetson:input", "jetson:cpu_gpu_cv"]);
+        assert_eq!(rails[0].read_watts(), Ok(6.0));
+        assert_eq!(rails[0].kind(), DeviceKind::Psys);
+        assert_eq!(rails[1].kind(), DeviceKind::Other);
+    }
+
+    #[test]
+    fn integrates_rail_power_and_attributes_cpu_time() {
+        let previous = ReadState {
+            monotonic_ns: 1_000_000_000,
+            cpu_ns: HashMap::from([(7, 0)]),
+        };
+        let current = ReadState {
+            monotonic_ns: 1_500_000_000,
+            cpu_ns: HashMap::from([(7, 500_000_000)]),
+        };
+        let time = RecordTime {
+            timestamp_us: 1_500_000,
+            monotonic_ns: current.monotonic_ns,
+        };
+        let watts = [("cpu".to_string(), 4.0), ("gpu".to_string(), 10.0)];
+
+        // Half a second at 4 W and 10 W; pid 7 used one of four CPUs.
+        let records = records_since(&previous, &current, &watts, time, 4);
+        let energies: Vec<(u32, &str, f64)> = records
+            .iter()
+            .map(|r| (r.pid, r.device.as_str(), r.energy))
+            .collect();
+        assert_eq!(
+            energies,
+            [
+                (7, "jetson:cpu", 0.5),
+                (UNATTRIBUTED_PID, "jetson:cpu", 1.5),
+                (UNATTRIBUTED_PID, "jetson:gpu", 5.0),
+            ]
+        );
+    }
+}
@@ -0,0 +1,291 @@
+/// Meter Collector
+///
+/// Reads meters EMT has no native collector for, such as smart PDUs or lab
+/// power analyzers, each at its own interval: through commands printing
+/// JSON ([`exec`](super::exec)) or HTTP endpoints of existing exporters
+/// ([`http_scrape`](super::http_scrape)), and server BMCs
+/// ([`bmc`](super::bmc)).
+///
+/// A meter reports per device either a cumulative energy counter, which is
+/// differenced between reads, or the current power, which is integrated
+/// over the time since the previous read. Meters measure whole machines or
+/// components rather than processes, so their energy is recorded as
+/// unattributed.
+use super::bmc::bmc_meter;
+use super::exec::ExecMeter;
+use super::http_scrape::HttpMeter;
+use crate::config::{BmcCollectorConfig, ExecCollectorConfig, HttpCollectorConfig};
+use crate::energy_group::{EnergyCollector, EnergyRecord};
+use crate::utils::errors::CollectorError;
+use crate::utils::time::RecordTime;
+use async_trait::async_trait;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Records of meters carry no process.
+const UNATTRIBUTED_PID: u32 = 0;
+
+/// A source of meter readings.
+#[async_trait]
+pub(crate) trait MeterSource: Send + Sync {
+    /// Prefix of the meter's device names, such as `exec:pdu`.
+    fn device_prefix(&self) -> String;
+
+    /// Time between reads.
+    fn interval(&self) -> Duration;
+
+    /// Read every device of the meter.
+    async fn read(&self) -> Result<Vec<MeterReading>, String>;
+}
+
+/// One device reading of a meter.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub(crate) struct MeterReading {
+    pub(crate) device: String,
+    #[serde(default)]
+    pub(crate) energy_j: Option<f64>,
+    #[serde(default)]
+    pub(crate) power_w: Option<f64>,
+}
+
+impl MeterReading {
+    /// Check the reading names a device and has a non-negative energy or
+    /// power.
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        let value = self.energy_j.or(self.power_w);
+        if self.device.is_empty() || !value.is_some_and(|value| value.is_finite() && value >= 0.0) {
+            return Err(format!(
+                "reading of '{}' needs a non-negative energy_j or power_w",
+                self.device
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Previous reading of a device.
+#[derive(Debug, Clone, Copy)]
+enum LastReading {
+    Energy(f64),
+    Power { watts: f64, monotonic_ns: i64 },
+}
+
+#[derive(Debug, Default)]
+struct MeterState {
+    last_run: Option<Instant>,
+    readings: HashMap<String, LastReading>,
+}
+
+struct Meter {
+    source: Box<dyn MeterSource>,
+    state: Mutex<MeterState>,
+}
+
+impl Meter {
+    fn new(source: Box<dyn MeterSource>) -> Self {
+        Self {
+            source,
+            state: Mutex::new(MeterState::default()),
+        }
+    }
+
+    /// Whether the meter is due at `now`, marking it read if so.
+    fn start_read(&self, now: Instant) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let due = state
+            .last_run
+            .is_none_or(|last| now.duration_since(last) >= self.source.interval());
+        if due {
+            state.last_run = Some(now);
+        }
+        due
+    }
+
+    /// Records for `readings` taken at `time`, relative to the previous read.
+    fn records(&self, readings: Vec<MeterReading>, time: RecordTime) -> Vec<EnergyRecord> {
+        let prefix = self.source.device_prefix();
+        let mut state = self.state.lock().unwrap();
+        readings
+            .into_iter()
+            .filter_map(|reading| {
+                let (current, raw_counter) = match (reading.energy_j, reading.power_w) {
+                    (Some(joules), _) => (LastReading::Energy(joules), Some(joules)),
+                    (None, Some(watts)) => (
+                        LastReading::Power {
+                            watts,
+                            monotonic_ns: time.monotonic_ns,
+                        },
+                        None,
+                    ),
+                    (None, None) => return None,
+                };
+                let previous = state.readings.insert(reading.device.clone(), current);
+                Some(EnergyRecord {
+                    pid: UNATTRIBUTED_PID,
+                    timestamp_us: time.timestamp_us,
+                    monotonic_ns: time.monotonic_ns,
+                    device: format!("{prefix}:{}", reading.device),
+                    energy: energy_since(previous, current),
+                    raw_counter,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Joules between two readings of a device: the counter difference, or
+/// the mean power times the time between readings. A counter that went
+/// backwards was reset and counts as no energy.
+fn energy_since(previous: Option<LastReading>, current: LastReading) -> f64 {
+    match (previous, current) {
+        (Some(LastReading::Energy(before)), LastReading::Energy(now)) => (now - before).max(0.0),
+        (
+            Some(LastReading::Power {
+                watts: before,
+                monotonic_ns: then,
+            }),
+            LastReading::Power {
+                watts,
+                monotonic_ns,
+            },
+        ) => (before + watts) / 2.0 * (monotonic_ns - then).max(0) as f64 * 1e-9,
+        _ => 0.0,
+    }
+}
+
+/// Collector reading the configured `exec_collectors`, `http_collectors`
+/// and `bmc_collectors`, each at its own interval.
+pub struct MeterCollector {
+    meters: Vec<Meter>,
+}
+
+impl MeterCollector {
+    pub fn new(exec: &[ExecCollectorConfig], http: &[HttpCollectorConfig]) -> Self {
+        let mut meters: Vec<Meter> = exec
+            .iter()
+            .map(|config| Meter::new(Box::new(ExecMeter::new(config.clone()))))
+            .collect();
+        for config in http {
+            match HttpMeter::new(config.clone()) {
+                Ok(meter) => meters.push(Meter::new(Box::new(meter))),
+                Err(e) => log::error!("Meter '{}' disabled: {}", config.name, e),
+            }
+        }
+        Self { meters }
+    }
+
+    /// Also read the chassis power of `bmc`.
+    pub fn with_bmc(mut self, bmc: &[BmcCollectorConfig]) -> Self {
+        for config in bmc {
+            match bmc_meter(config) {
+                Ok(source) => self.meters.push(Meter::new(source)),
+                Err(e) => log::error!("Meter '{}' disabled: {}", config.name, e),
+            }
+        }
+        self
+    }
+}
+
+#[async_trait]
+impl EnergyCollector for MeterCollector {
+    fn set_tracked_pids(&self, _pids: Vec<u32>) {}
+
+    async fn get_energy_trace(&self) -> Result<Vec<EnergyRecord>, CollectorError> {
+        let mut records = Vec::new();
+        for meter in &self.meters {
+            if !meter.start_read(Instant::now()) {
+                continue;
+            }
+            match meter.source.read().await {
+                Ok(readings) => records.extend(meter.records(readings, RecordTime::now())),
+                Err(e) => log::warn!("Meter {}: {}", meter.source.device_prefix(), e),
+            }
+        }
+        Ok(records)
+    }
+
+    fn is_available() -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct FixedMeter;
+
+    #[async_trait]
+    impl MeterSource for FixedMeter {
+        fn device_prefix(&self) -> String {
+            "exec:pdu".to_string()
+        }
+
+        fn interval(&self) -> Duration {
+            Duration::from_secs(5)
+        }
+
+        async fn read(&self) -> Result<Vec<MeterReading>, String> {
+            Ok(Vec::new())
+        }
+    }
+
+    #[test]
+    fn validates_readings() {
+        let reading = |energy_j, power_w| MeterReading {
+            device: "outlet".to_string(),
+            energy_j,
+            power_w,
+        };
+        assert!(reading(Some(1.0), None).validate().is_ok());
+        assert!(reading(None, Some(0.0)).validate().is_ok());
+        assert!(reading(None, None).validate().is_err());
+        assert!(reading(None, Some(-1.0)).validate().is_err());
+        assert!(reading(Some(f64::NAN), None).validate().is_err());
+    }
+
+    #[test]
+    fn counters_are_differenced_and_power_integrated() {
+        let meter = Meter::new(Box::new(FixedMeter));
+        let at = |seconds: i64| RecordTime {
+            timestamp_us: seconds * 1_000_000,
+            monotonic_ns: seconds * 1_000_000_000,
+        };
+        let readings = |energy_j, power_w| {
+            vec![
+                MeterReading {
+                    device: "outlet".to_string(),
+                    energy_j: Some(energy_j),
+                    power_w: None,
+                },
+                MeterReading {
+                    device: "fan".to_string(),
+                    energy_j: None,
+                    power_w: Some(power_w),
+                },
+            ]
+        };
+
+        let first = meter.records(readings(100.0, 10.0), at(0));
+        assert_eq!(first[0].device, "exec:pdu:outlet");
+        assert_eq!((first[0].energy, first[1].energy), (0.0, 0.0));
+        let second = meter.records(readings(150.0, 30.0), at(2));
+        assert_eq!((second[0].energy, second[1].energy), (50.0, 40.0));
+        assert_eq!(second[0].raw_counter, Some(150.0));
+        assert_eq!(second[0].pid, UNATTRIBUTED_PID);
+        // The meter restarted its counter.
+        let third = meter.records(readings(5.0, 30.0), at(3));
+        assert_eq!(third[0].energy, 0.0);
+    }
+
+    #[test]
+    fn meters_are_read_at_their_interval() {
+        let meter = Meter::new(Box::new(FixedMeter));
+        let start = Instant::now();
+        assert!(meter.start_read(start));
+        assert!(!meter.start_read(start + Duration::from_secs(1)));
+        assert!(meter.start_read(start + Duration::from_secs(5)));
+    }
+}
@@ -1,4 +1,34 @@
+pub mod amd_gpu;
+#[cfg(feature = "macos")]
+pub mod apple_smc;
+pub mod attribution;
+pub mod bmc;
+pub mod composite;
+pub mod cpu_energy;
+pub(crate) mod drm_fdinfo;
+pub(crate) mod exec;
+pub(crate) mod http_scrape;
+pub mod intel_gpu;
+pub mod jetson;
+pub mod meter;
+pub mod network;
 pub mod nvidia_gpu;
+pub(crate) mod placement;
 pub mod rapl;
-pub use nvidia_gpu::NvidiaGpu;
+pub mod storage;
+pub use amd_gpu::AmdGpu;
+#[cfg(feature = "macos")]
+pub use apple_smc::AppleSmc;
+pub use attribution::{
+    AttributionStrategy, CpuShare, CustomShare, DeviceClass, EqualShare, MemShare,
+};
+pub use bmc::Bmc;
+pub use composite::CompositeCollector;
+pub use cpu_energy::CpuEnergy;
+pub use intel_gpu::IntelGpu;
+pub use jetson::JetsonIna;
+pub use meter::MeterCollector;
+pub use network::Network;
+pub use nvidia_gpu::{GpuUtilizationRecord, NvidiaGpu};
 pub use rapl::Rapl;
+pub use storage::Storage;
@@ -0,0 +1,295 @@
+/// Network Collector
+///
+/// Estimates the energy of network interfaces from the bytes they move,
+/// with a linear NIC model: `joules_per_byte` for every byte received or
+/// sent. Counters come from `/proc/<pid>/net/dev`, which reports the
+/// interfaces of the process's network namespace.
+///
+/// Interfaces are read in EMT's own namespace, by default the physical
+/// ones, and recorded as `net:<iface>`. Linux has no per-process byte
+/// counters outside eBPF, so traffic is attributed per namespace: a
+/// tracked process in a namespace of its own, such as a container, gets
+/// the traffic of that namespace as a share of the interfaces' energy,
+/// split evenly with other tracked processes in it. Traffic of processes
+/// sharing EMT's namespace cannot be told apart and stays unattributed.
+use crate::config::NetworkEnergyConfig;
+use crate::energy_group::{EnergyCollector, EnergyRecord};
+use crate::utils::errors::CollectorError;
+use crate::utils::pattern::glob_match;
+use crate::utils::time::RecordTime;
+use async_trait::async_trait;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+const UNATTRIBUTED_PID: u32 = 0;
+const LOOPBACK: &str = "lo";
+
+/// Bytes received and sent by each interface of `/proc/<pid>/net/dev`
+/// contents.
+fn parse_net_dev(contents: &str) -> BTreeMap<String, u64> {
+    contents
+        .lines()
+        .filter_map(|line| {
+            let (name, counters) = line.split_once(':')?;
+            let fields: Vec<u64> = counters
+                .split_whitespace()
+                .map(str::parse)
+                .collect::<Result<_, _>>()
+                .ok()?;
+            // Receive bytes, then seven receive fields before transmit bytes
+            let bytes = fields.first()? + fields.get(8)?;
+            Some((name.trim().to_string(), bytes))
+        })
+        .collect()
+}
+
+#[derive(Debug, Default)]
+struct NetworkState {
+    /// Latest byte count of each host interface
+    interfaces: HashMap<String, u64>,
+    /// Latest byte count of each namespace, by its inode
+    namespaces: HashMap<u64, u64>,
+}
+
+/// Collector estimating network interface energy; see [`NetworkEnergyConfig`].
+pub struct Network {
+    config: NetworkEnergyConfig,
+    proc_root: PathBuf,
+    net_class: PathBuf,
+    tracked_pids: Mutex<Vec<u32>>,
+    state: Mutex<NetworkState>,
+}
+
+impl Network {
+    pub fn new(config: NetworkEnergyConfig) -> Self {
+        Self::with_roots(config, "/proc", "/sys/class/net")
+    }
+
+    /// Read processes under `proc_root` and interfaces under `net_class`.
+    pub(crate) fn with_roots(
+        config: NetworkEnergyConfig,
+        proc_root: impl Into<PathBuf>,
+        net_class: impl Into<PathBuf>,
+    ) -> Self {
+        Self {
+            config,
+            proc_root: proc_root.into(),
+            net_class: net_class.into(),
+            tracked_pids: Mutex::new(Vec::new()),
+            state: Mutex::new(NetworkState::default()),
+        }
+    }
+
+    fn process_dir(&self, pid: Option<u32>) -> PathBuf {
+        match pid {
+            Some(pid) => self.proc_root.join(pid.to_string()),
+            None => self.proc_root.join("self"),
+        }
+    }
+
+    /// Inode of the network namespace of `pid`, or of EMT.
+    fn namespace(&self, pid: Option<u32>) -> Option<u64> {
+        let link = fs::read_link(self.process_dir(pid).join("ns/net")).ok()?;
+        link.to_str()?
+            .strip_prefix("net:[")?
+            .strip_suffix(']')?
+            .parse()
+            .ok()
+    }
+
+    fn net_dev(&self, pid: Option<u32>) -> Result<BTreeMap<String, u64>, CollectorError> {
+        let path = self.process_dir(pid).join("net/dev");
+        fs::read_to_string(&path)
+            .map(|contents| parse_net_dev(&contents))
+            .map_err(|e| CollectorError::from_io(path.display(), &e))
+    }
+
+    /// Whether `iface` of EMT's namespace is measured: it matches one of
+    /// the configured patterns, or is backed by a device when none are.
+    fn measures(&self, iface: &str) -> bool {
+        if self.config.interfaces.is_empty() {
+            iface != LOOPBACK && self.net_class.join(iface).join("device").exists()
+        } else {
+            self.config
+                .interfaces
+                .iter()
+                .any(|pattern| glob_match(pattern, iface))
+        }
+    }
+
+    /// Bytes each tracked process moved since the previous read, from the
+    /// namespaces other than EMT's.
+    fn process_bytes(&self, pids: &[u32], state: &mut NetworkState) -> Vec<(u32, f64)> {
+        let host = self.namespace(None);
+        let mut namespaces: BTreeMap<u64, Vec<u32>> = BTreeMap::new();
+        for &pid in pids {
+            if let Some(namespace) = self.namespace(Some(pid))
+                && Some(namespace) != host
+            {
+                namespaces.entry(namespace).or_default().push(pid);
+            }
+        }
+        let mut seen = HashMap::new();
+        let mut bytes = Vec::new();
+        for (namespace, members) in namespaces {
+            let Ok(counters) = self.net_dev(Some(members[0])) else {
+                continue;
+            };
+            let total: u64 = counters
+                .iter()
+                .filter(|(iface, _)| *iface != LOOPBACK)
+                .map(|(_, bytes)| bytes)
+                .sum();
+            seen.insert(namespace, total);
+            if let Some(previous) = state.namespaces.get(&namespace) {
+                let share = total.saturating_sub(*previous) as f64 / members.len() as f64;
+                bytes.extend(members.iter().map(|&pid| (pid, share)));
+            }
+        }
+        state.namespaces = seen;
+        bytes
+    }
+}
+
+impl Default for Network {
+    fn default() -> Self {
+        Self::new(NetworkEnergyConfig::default())
+    }
+}
+
+#[async_trait]
+impl EnergyCollector for Network {
+    fn set_tracked_pids(&self, pids: Vec<u32>) {
+        *self.tracked_pids.lock().unwrap() = pids;
+    }
+
+    async fn get_energy_trace(&self) -> Result<Vec<EnergyRecord>, CollectorError> {
+        let counters = self.net_dev(None)?;
+        let time = RecordTime::now();
+        let pids = self.tracked_pids.lock().unwrap().clone();
+        let mut state = self.state.lock().unwrap();
+
+        let interfaces: Vec<(String, u64)> = counters
+            .into_iter()
+            .filter(|(iface, _)| self.measures(iface))
+            .collect();
+        let deltas: Vec<(String, f64)> = interfaces
+            .iter()
+            .filter_map(|(iface, bytes)| {
+                let previous = state.interfaces.get(iface)?;
+                Some((iface.clone(), bytes.saturating_sub(*previous) as f64))
+            })
+            .collect();
+        state.interfaces = interfaces.into_iter().collect();
+        let process_bytes = self.process_bytes(&pids, &mut state);
+
+        // Each process's share of the bytes the interfaces moved, capped so
+        // together they never exceed it
+        let host_bytes: f64 = deltas.iter().map(|(_, bytes)| bytes).sum();
+        let attributed: f64 = process_bytes.iter().map(|(_, bytes)| bytes).sum();
+        let scale = if attributed > host_bytes {
+            1.0 / attributed
+        } else if host_bytes > 0.0 {
+            1.0 / host_bytes
+        } else {
+            0.0
+        };
+
+        let mut records = Vec::new();
+        for (iface, bytes) in deltas {
+            let device = format!("net:{iface}");
+            let energy = bytes * self.config.joules_per_byte;
+            let record = |pid, energy| EnergyRecord {
+                pid,
+                timestamp_us: time.timestamp_us,
+                monotonic_ns: time.monotonic_ns,
+                device: device.clone(),
+                energy,
+                raw_counter: None,
+            };
+            let mut unattributed = energy;
+            for &(pid, process_bytes) in &process_bytes {
+                let share = energy * process_bytes * scale;
+                if share > 0.0 {
+                    unattributed -= share;
+                    records.push(record(pid, share));
+                }
+            }
+            records.push(record(UNATTRIBUTED_PID, unattributed.max(0.0)));
+        }
+        Ok(records)
+    }
+
+    fn is_available() -> bool {
+        Path::new("/proc/self/net/dev").exists()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::symlink;
+
+    fn net_dev(interfaces: &[(&str, u64, u64)]) -> String {
+        let mut contents = "Inter-|   Receive                            |  Transmit\n face |bytes    packets errs drop fifo frame compressed multicast|bytes    packets errs drop fifo colls carrier compressed\n".to_string();
+        for (iface, rx, tx) in interfaces {
+            contents.push_str(&format!(
+                "{iface:>6}: {rx} 10 0 0 0 0 0 0 {tx} 10 0 0 0 0 0 0\n"
+            ));
+        }
+        contents
+    }
+
+    fn write_process(proc_root: &Path, dir: &str, namespace: u64, contents: &str) {
+        let dir = proc_root.join(dir);
+        fs::create_dir_all(dir.join("net")).unwrap();
+        fs::create_dir_all(dir.join("ns")).unwrap();
+        fs::write(dir.join("net/dev"), contents).unwrap();
+        let link = dir.join("ns/net");
+        let _ = fs::remove_file(&link);
+        symlink(format!("net:[{namespace}]"), link).unwrap();
+    }
+
+    #[test]
+    fn parses_receive_and_transmit_bytes() {
+        let counters = parse_net_dev(&net_dev(&[("lo", 5, 5), ("eth0", 1000, 24)]));
+        assert_eq!(counters["eth0"], 1024);
+        assert_eq!(counters["lo"], 10);
+        assert_eq!(counters.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn attributes_namespace_traffic_as_a_share_of_physical_interfaces() {
+        let root = tempfile::tempdir().unwrap();
+        let proc_root = root.path().join("proc");
+        let net_class = root.path().join("net");
+        fs::create_dir_all(net_class.join("eth0/device")).unwrap();
+        fs::create_dir_all(net_class.join("veth1")).unwrap();
+
+        let host = |eth0| net_dev(&[("lo", 0, 0), ("eth0", eth0, 0), ("veth1", eth0, 0)]);
+        write_process(&proc_root, "self", 1, &host(0));
+        // A container process, and one sharing the host namespace
+        write_process(&proc_root, "10", 2, &net_dev(&[("eth0", 0, 0)]));
+        write_process(&proc_root, "20", 1, &host(0));
+
+        let config = NetworkEnergyConfig {
+            joules_per_byte: 1e-3,
+            ..NetworkEnergyConfig::default()
+        };
+        let network = Network::with_roots(config, &proc_root, &net_class);
+        network.set_tracked_pids(vec![10, 20]);
+        assert!(network.get_energy_trace().await.unwrap().is_empty());
+
+        write_process(&proc_root, "self", 1, &host(4000));
+        write_process(&proc_root, "10", 2, &net_dev(&[("eth0", 500, 500)]));
+        let records = network.get_energy_trace().await.unwrap();
+
+        let energy: Vec<(u32, &str, f64)> = records
+            .iter()
+            .map(|r| (r.pid, r.device.as_str(), r.energy))
+            .collect();
+        assert_eq!(energy, [(10, "net:eth0", 1.0), (0, "net:eth0", 3.0)]);
+    }
+}
@@ -1,45 +1,72 @@
+use crate::device_registry::DeviceInfo;
 use crate::energy_group::{EnergyCollector, EnergyRecord};
+use crate::read_schedule::ReadSchedule;
+use crate::utils::errors::CollectorError;
+use crate::utils::time::RecordTime;
 use async_trait::async_trait;
-use chrono::Utc;
 use log::{debug, warn};
-use nvml_wrapper::Nvml;
 use nvml_wrapper::enums::device::UsedGpuMemory;
-use std::collections::{HashMap, HashSet};
-use std::sync::{Arc, Mutex};
+use nvml_wrapper::struct_wrappers::device::ProcessUtilizationSample;
+use nvml_wrapper::{Device, Nvml};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Instant;
 use tokio::task;
 
 const UNATTRIBUTED_PID: u32 = 0;
 
+/// GPU utilization sampled through NVML, for a whole device or one process.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GpuUtilizationRecord {
+    /// Process the sample belongs to; `None` for the whole device
+    pub pid: Option<u32>,
+    pub timestamp_us: i64,
+    pub monotonic_ns: i64,
+    pub device: String,
+    /// Percent of time the streaming multiprocessors were busy
+    pub sm_percent: f64,
+    /// Percent of time device memory was read or written
+    pub memory_percent: f64,
+}
+
 /// NVIDIA GPU energy collector using direct NVML library bindings.
 ///
 /// Replaces the previous `nvidia-smi` CLI-based approach with the `nvml-wrapper`
 /// crate for significantly lower overhead (no process spawning per sample).
 pub struct NvidiaGpu {
     /// NVML library handle. `None` when NVML is unavailable (graceful degradation).
-    nvml: Option<Arc<Nvml>>,
-    /// Number of GPU devices detected at construction time.
-    device_count: u32,
+    nvml: RwLock<Option<Arc<Nvml>>>,
+    /// UUID of each GPU by index, as of the last discovery.
+    device_uuids: RwLock<Vec<String>>,
     /// Optional device index filter. `None` means monitor all GPUs.
     device_filter: Option<Vec<u32>>,
     /// PIDs to attribute energy to.
     tracked_pids: Arc<Mutex<Vec<u32>>>,
     /// Previous cumulative energy reading (millijoules) per GPU index, used for delta computation.
     previous_energy_mj: Arc<Mutex<HashMap<u32, u64>>>,
+    /// Newest per-process utilization sample (NVML's microsecond clock) per
+    /// GPU index seen by energy attribution
+    last_attribution_sample_us: Arc<Mutex<HashMap<u32, u64>>>,
+    /// Same for [`NvidiaGpu::get_utilization_trace`]
+    last_utilization_sample_us: Arc<Mutex<HashMap<u32, u64>>>,
+    /// Offsets of the GPU reads within each tick
+    read_schedule: Mutex<ReadSchedule>,
 }
 
 impl NvidiaGpu {
     /// Construct a new collector that discovers all NVIDIA GPUs via NVML.
     pub fn new() -> Result<Self, String> {
-        let nvml = Nvml::init().map_err(|e| format!("Failed to initialize NVML: {}", e))?;
-        let device_count = nvml
-            .device_count()
-            .map_err(|e| format!("Failed to get device count: {}", e))?;
+        let (nvml, device_uuids) = Self::discover()?;
         Ok(Self {
-            nvml: Some(Arc::new(nvml)),
-            device_count,
+            nvml: RwLock::new(Some(Arc::new(nvml))),
+            device_uuids: RwLock::new(device_uuids),
             device_filter: None,
             tracked_pids: Arc::new(Mutex::new(Vec::new())),
             previous_energy_mj: Arc::new(Mutex::new(HashMap::new())),
+            last_attribution_sample_us: Arc::new(Mutex::new(HashMap::new())),
+            last_utilization_sample_us: Arc::new(Mutex::new(HashMap::new())),
+            read_schedule: Mutex::new(ReadSchedule::default()),
         })
     }
 
@@ -52,6 +79,36 @@ impl NvidiaGpu {
         Ok(collector)
     }
 
+    /// Initialize NVML and list the UUID of every GPU it enumerates. Devices
+    /// whose UUID cannot be read get an empty one.
+    fn discover() -> Result<(Nvml, Vec<String>), String> {
+        let nvml = Nvml::init().map_err(|e| format!("Failed to initialize NVML: {}", e))?;
+        let device_count = nvml
+            .device_count()
+            .map_err(|e| format!("Failed to get device count: {}", e))?;
+        let device_uuids = (0..device_count)
+            .map(|idx| {
+                nvml.device_by_index(idx)
+                    .and_then(|device| device.uuid())
+                    .unwrap_or_default()
+            })
+            .collect();
+        Ok((nvml, device_uuids))
+    }
+
+    /// Drop energy baselines of indices that now refer to a different GPU or
+    /// to none, so the next reading of a replaced device starts a new delta.
+    fn retain_unchanged_baselines(
+        previous_energy_mj: &mut HashMap<u32, u64>,
+        old_uuids: &[String],
+        new_uuids: &[String],
+    ) {
+        previous_energy_mj.retain(|&idx, _| {
+            let idx = idx as usize;
+            old_uuids.get(idx).is_some() && old_uuids.get(idx) == new_uuids.get(idx)
+        });
+    }
+
     /// Compute the energy delta in joules from two consecutive millijoule readings.
     ///
     /// Returns 0.0 when there is no previous reading (first sample) or when the
@@ -68,46 +125,80 @@ impl NvidiaGpu {
             .unwrap_or(0.0)
     }
 
-    /// Attribute a GPU energy delta to tracked processes by their GPU memory share.
+    /// Mean SM and memory utilization per process over `samples`.
+    fn mean_utilization_by_pid(samples: &[ProcessUtilizationSample]) -> BTreeMap<u32, (f64, f64)> {
+        let mut sums: BTreeMap<u32, (f64, f64, usize)> = BTreeMap::new();
+        for sample in samples {
+            let (sm, memory, count) = sums.entry(sample.pid).or_default();
+            *sm += sample.sm_util as f64;
+            *memory += sample.mem_util as f64;
+            *count += 1;
+        }
+        sums.into_iter()
+            .map(|(pid, (sm, memory, count))| (pid, (sm / count as f64, memory / count as f64)))
+            .collect()
+    }
+
+    /// Per-process utilization samples of `device` newer than the last ones
+    /// taken for `idx` from `last_sample_us`, which is advanced. NVML reports
+    /// an error when no process ran since, so errors read as no samples.
+    fn process_samples(
+        device: &Device,
+        idx: u32,
+        last_sample_us: &mut HashMap<u32, u64>,
+    ) -> Vec<ProcessUtilizationSample> {
+        match device.process_utilization_stats(last_sample_us.get(&idx).copied()) {
+            Ok(samples) => {
+                if let Some(newest) = samples.iter().map(|sample| sample.timestamp).max() {
+                    last_sample_us.insert(idx, newest);
+                }
+                samples
+            }
+            Err(e) => {
+                debug!("No process utilization samples on GPU {}: {}", idx, e);
+                Vec::new()
+            }
+        }
+    }
+
+    /// Attribute a GPU energy delta to tracked processes by their share of
+    /// `total_weight`, such as SM utilization or GPU memory. The rest of the
+    /// delta is recorded as unattributed.
     fn attribute_energy_for_processes(
         gpu_index: u32,
         delta_joules: f64,
-        total_used_memory_bytes: Option<u64>,
+        total_weight: Option<f64>,
         tracked_pid_set: &HashSet<u32>,
-        process_memories: &[(u32, u64)],
-        timestamp: i64,
+        process_weights: &[(u32, f64)],
+        time: RecordTime,
     ) -> Vec<EnergyRecord> {
         if delta_joules <= 0.0 {
             return Vec::new();
         }
 
-        let Some(total_used_memory_bytes) = total_used_memory_bytes.filter(|bytes| *bytes > 0)
-        else {
-            return vec![Self::unattributed_record(
-                gpu_index,
-                delta_joules,
-                timestamp,
-            )];
+        let Some(total_weight) = total_weight.filter(|weight| *weight > 0.0) else {
+            return vec![Self::unattributed_record(gpu_index, delta_joules, time)];
         };
 
-        let reported_process_memory_bytes: u64 = process_memories.iter().map(|(_, mem)| *mem).sum();
-        let attribution_denominator =
-            total_used_memory_bytes.max(reported_process_memory_bytes) as f64;
+        let reported_process_weight: f64 = process_weights.iter().map(|(_, weight)| *weight).sum();
+        let attribution_denominator = total_weight.max(reported_process_weight);
 
         let mut attributed_energy = 0.0;
         let mut records = Vec::new();
-        for (pid, process_memory_bytes) in process_memories {
-            if !tracked_pid_set.contains(pid) || *process_memory_bytes == 0 {
+        for (pid, process_weight) in process_weights {
+            if !tracked_pid_set.contains(pid) || *process_weight <= 0.0 {
                 continue;
             }
 
-            let energy = delta_joules * (*process_memory_bytes as f64 / attribution_denominator);
+            let energy = delta_joules * (process_weight / attribution_denominator);
             attributed_energy += energy;
             records.push(EnergyRecord {
                 pid: *pid,
-                timestamp,
+                timestamp_us: time.timestamp_us,
+                monotonic_ns: time.monotonic_ns,
                 device: format!("nvidia:gpu:{}", gpu_index),
                 energy,
+                raw_counter: None,
             });
         }
 
@@ -116,31 +207,125 @@ impl NvidiaGpu {
             records.push(Self::unattributed_record(
                 gpu_index,
                 unattributed_energy,
-                timestamp,
+                time,
             ));
         }
 
         records
     }
 
-    fn unattributed_record(gpu_index: u32, energy: f64, timestamp: i64) -> EnergyRecord {
+    /// Used memory of `device` and per-process GPU memory of its compute
+    /// processes, in bytes.
+    fn memory_weights(device: &Device, idx: u32) -> (Option<f64>, Vec<(u32, f64)>) {
+        // Get memory info for the total used memory on the device.
+        let total_used_memory = match device.memory_info() {
+            Ok(info) => Some(info.used as f64),
+            Err(e) => {
+                warn!("Failed to read memory info for GPU {}: {}", idx, e);
+                None
+            }
+        };
+
+        // Get per-process GPU memory for compute processes.
+        let process_memories = match device.running_compute_processes() {
+            Ok(procs) => procs
+                .iter()
+                .filter_map(|p| match p.used_gpu_memory {
+                    UsedGpuMemory::Used(bytes) => Some((p.pid, bytes as f64)),
+                    UsedGpuMemory::Unavailable => None,
+                })
+                .collect(),
+            Err(e) => {
+                // No compute processes is a normal state; only warn
+                // on unexpected errors.
+                debug!(
+                    "No compute processes on GPU {} ({}), recording GPU energy as unattributed",
+                    idx, e
+                );
+                Vec::new()
+            }
+        };
+        (total_used_memory, process_memories)
+    }
+
+    /// SM and memory utilization of every monitored GPU, and of each tracked
+    /// process on it over the samples NVML took since the previous call.
+    pub async fn get_utilization_trace(&self) -> Result<Vec<GpuUtilizationRecord>, String> {
+        let nvml = match &*self.nvml.read().unwrap() {
+            Some(nvml) => Arc::clone(nvml),
+            None => return Ok(Vec::new()),
+        };
+        let tracked_pid_set: HashSet<u32> =
+            self.tracked_pids.lock().unwrap().iter().copied().collect();
+        let device_indices = self.device_indices();
+        let last_sample_us = Arc::clone(&self.last_utilization_sample_us);
+
+        task::spawn_blocking(move || {
+            let mut last_sample_us = last_sample_us.lock().unwrap();
+            let mut records = Vec::new();
+            for idx in device_indices {
+                let device = match nvml.device_by_index(idx) {
+                    Ok(d) => d,
+                    Err(e) => {
+                        warn!("Failed to get NVIDIA device {}: {}", idx, e);
+                        continue;
+                    }
+                };
+                let device_name = format!("nvidia:gpu:{}", idx);
+                let record =
+                    |pid, sm_percent, memory_percent, time: RecordTime| GpuUtilizationRecord {
+                        pid,
+                        timestamp_us: time.timestamp_us,
+                        monotonic_ns: time.monotonic_ns,
+                        device: device_name.clone(),
+                        sm_percent,
+                        memory_percent,
+                    };
+                match device.utilization_rates() {
+                    Ok(rates) => records.push(record(
+                        None,
+                        rates.gpu as f64,
+                        rates.memory as f64,
+                        RecordTime::now(),
+                    )),
+                    Err(e) => warn!("Failed to read utilization of GPU {}: {}", idx, e),
+                }
+
+                let samples = Self::process_samples(&device, idx, &mut last_sample_us);
+                let time = RecordTime::now();
+                for (pid, (sm, memory)) in Self::mean_utilization_by_pid(&samples) {
+                    if tracked_pid_set.contains(&pid) {
+                        records.push(record(Some(pid), sm, memory, time));
+                    }
+                }
+            }
+            records
+        })
+        .await
+        .map_err(|e| format!("Failed to join NVML utilization task: {}", e))
+    }
+
+    fn unattributed_record(gpu_index: u32, energy: f64, time: RecordTime) -> EnergyRecord {
         EnergyRecord {
             pid: UNATTRIBUTED_PID,
-            timestamp,
+            timestamp_us: time.timestamp_us,
+            monotonic_ns: time.monotonic_ns,
             device: format!("nvidia:gpu:{}", gpu_index),
             energy,
+            raw_counter: None,
         }
     }
 
     /// Determine which device indices to iterate based on the optional filter.
     fn device_indices(&self) -> Vec<u32> {
+        let device_count = self.device_uuids.read().unwrap().len() as u32;
         match &self.device_filter {
             Some(filter) => filter
                 .iter()
-                .filter(|&&idx| idx < self.device_count)
+                .filter(|&&idx| idx < device_count)
                 .copied()
                 .collect(),
-            None => (0..self.device_count).collect(),
+            None => (0..device_count).collect(),
         }
     }
 }
@@ -154,11 +339,14 @@ impl Default for NvidiaGpu {
         match Self::new() {
             Ok(collector) => collector,
             Err(_) => Self {
-                nvml: None,
-                device_count: 0,
+                nvml: RwLock::new(None),
+                device_uuids: RwLock::new(Vec::new()),
                 device_filter: None,
                 tracked_pids: Arc::new(Mutex::new(Vec::new())),
                 previous_energy_mj: Arc::new(Mutex::new(HashMap::new())),
+                last_attribution_sample_us: Arc::new(Mutex::new(HashMap::new())),
+                last_utilization_sample_us: Arc::new(Mutex::new(HashMap::new())),
+                read_schedule: Mutex::new(ReadSchedule::default()),
             },
         }
     }
@@ -170,8 +358,14 @@ impl EnergyCollector for NvidiaGpu {
         *self.tracked_pids.lock().unwrap() = pids;
     }
 
-    async fn get_energy_trace(&self) -> Result<Vec<EnergyRecord>, String> {
-        let nvml = match &self.nvml {
+    fn set_read_schedule(&self, schedule: ReadSchedule) {
+        *self.read_schedule.lock().unwrap() = schedule;
+    }
+
+    async fn get_energy_trace(&self) -> Result<Vec<EnergyRecord>, CollectorError> {
+        let tick_start = Instant::now();
+        let schedule = self.read_schedule.lock().unwrap().clone();
+        let nvml = match &*self.nvml.read().unwrap() {
             Some(nvml) => Arc::clone(nvml),
             None => return Ok(Vec::new()),
         };
@@ -181,12 +375,13 @@ impl EnergyCollector for NvidiaGpu {
         let tracked_pid_set: HashSet<u32> = tracked_pids.into_iter().collect();
         let device_indices = self.device_indices();
         let previous_energy_mj = Arc::clone(&self.previous_energy_mj);
+        let last_sample_us = Arc::clone(&self.last_attribution_sample_us);
 
         // NVML calls are blocking; run them on a blocking thread to avoid
         // stalling the async runtime.
         let records = task::spawn_blocking(move || {
-            let timestamp = Utc::now().timestamp_millis();
             let mut previous = previous_energy_mj.lock().unwrap();
+            let mut last_sample_us = last_sample_us.lock().unwrap();
             let mut records = Vec::new();
 
             for idx in device_indices {
@@ -199,6 +394,7 @@ impl EnergyCollector for NvidiaGpu {
                 };
 
                 // Read cumulative energy consumption in millijoules.
+                schedule.wait_blocking(tick_start, &format!("nvidia:gpu:{}", idx));
                 let current_energy_mj = match device.total_energy_consumption() {
                     Ok(mj) => mj,
                     Err(e) => {
@@ -206,55 +402,52 @@ impl EnergyCollector for NvidiaGpu {
                         continue;
                     }
                 };
+                let time = RecordTime::now();
 
                 // Compute delta from previous sample.
                 let prev = previous.get(&idx).copied();
                 previous.insert(idx, current_energy_mj);
                 let delta_joules = Self::compute_delta_joules(prev, current_energy_mj);
 
-                // Get memory info for the total used memory on the device.
-                let total_used_memory = match device.memory_info() {
-                    Ok(info) => Some(info.used),
-                    Err(e) => {
-                        warn!("Failed to read memory info for GPU {}: {}", idx, e);
-                        None
-                    }
-                };
-
-                // Get per-process GPU memory for compute processes.
-                let process_memories: Vec<(u32, u64)> = match device.running_compute_processes() {
-                    Ok(procs) => procs
-                        .iter()
-                        .filter_map(|p| match p.used_gpu_memory {
-                            UsedGpuMemory::Used(bytes) => Some((p.pid, bytes)),
-                            UsedGpuMemory::Unavailable => None,
-                        })
-                        .collect(),
-                    Err(e) => {
-                        // No compute processes is a normal state; only warn
-                        // on unexpected errors.
-                        debug!(
-                            "No compute processes on GPU {} ({}), recording GPU energy as unattributed",
-                            idx, e
-                        );
-                        Vec::new()
-                    }
+                // Attribute by each process's share of SM time since the last
+                // read, from NVML's per-process accounting samples.
+                let samples = Self::process_samples(&device, idx, &mut last_sample_us);
+                let process_sm: Vec<(u32, f64)> = Self::mean_utilization_by_pid(&samples)
+                    .into_iter()
+                    .map(|(pid, (sm, _))| (pid, sm))
+                    .filter(|(_, sm)| *sm > 0.0)
+                    .collect();
+                let (total_weight, process_weights) = if !process_sm.is_empty() {
+                    let total_sm = process_sm.iter().map(|(_, sm)| sm).sum();
+                    (Some(total_sm), process_sm)
+                } else {
+                    // Without samples, e.g. on GPUs that do not keep them,
+                    // fall back to each process's share of used GPU memory.
+                    Self::memory_weights(&device, idx)
                 };
 
-                records.extend(Self::attribute_energy_for_processes(
-                    idx,
-                    delta_joules,
-                    total_used_memory,
-                    &tracked_pid_set,
-                    &process_memories,
-                    timestamp,
-                ));
+                let raw_counter = Some(current_energy_mj as f64 / 1000.0);
+                records.extend(
+                    Self::attribute_energy_for_processes(
+                        idx,
+                        delta_joules,
+                        total_weight,
+                        &tracked_pid_set,
+                        &process_weights,
+                        time,
+                    )
+                    .into_iter()
+                    .map(|record| EnergyRecord {
+                        raw_counter,
+                        ..record
+                    }),
+                );
             }
 
             records
         })
         .await
-        .map_err(|e| format!("Failed to join NVML collection task: {}", e))?;
+        .map_err(|e| CollectorError::Io(format!("NVML collection task failed: {}", e)))?;
 
         debug!(
             "NVIDIA GPU energy trace collected: {} records",
@@ -263,6 +456,57 @@ impl EnergyCollector for NvidiaGpu {
         Ok(records)
     }
 
+    fn devices(&self) -> Vec<DeviceInfo> {
+        let Some(nvml) = self.nvml.read().unwrap().clone() else {
+            return Vec::new();
+        };
+        self.device_indices()
+            .into_iter()
+            .map(|idx| {
+                let device = nvml.device_by_index(idx).ok();
+                let model = device.as_ref().and_then(|device| device.name().ok());
+                let max_power_watts = device
+                    .as_ref()
+                    .and_then(|device| device.power_management_limit_constraints().ok())
+                    .map(|limits| limits.max_limit as f64 / 1000.0);
+                DeviceInfo::new(format!("nvidia:gpu:{}", idx))
+                    .with_vendor(Some("NVIDIA".to_string()))
+                    .with_model(model)
+                    .with_max_power_watts(max_power_watts)
+            })
+            .collect()
+    }
+
+    fn rediscover(&self) -> bool {
+        // NVML enumerates GPUs once per initialization, so re-initialize to
+        // see devices added or removed since (vGPU changes, driver reloads).
+        let (nvml, device_uuids) = match Self::discover() {
+            Ok((nvml, device_uuids)) => (Some(Arc::new(nvml)), device_uuids),
+            Err(e) => {
+                debug!("NVIDIA GPU rediscovery failed: {}", e);
+                (None, Vec::new())
+            }
+        };
+
+        let mut current = self.device_uuids.write().unwrap();
+        if *current == device_uuids {
+            return false;
+        }
+        log::info!(
+            "NVIDIA GPUs changed: {} -> {} devices",
+            current.len(),
+            device_uuids.len()
+        );
+        Self::retain_unchanged_baselines(
+            &mut self.previous_energy_mj.lock().unwrap(),
+            &current,
+            &device_uuids,
+        );
+        *current = device_uuids;
+        *self.nvml.write().unwrap() = nvml;
+        true
+    }
+
     fn is_available() -> bool {
         Nvml::init()
             .and_then(|nvml| nvml.device_count().map(|count| count > 0))
@@ -293,6 +537,20 @@ mod tests {
         assert_eq!(delta, 0.0);
     }
 
+    #[test]
+    fn rediscovery_keeps_baselines_only_for_unchanged_gpus() {
+        let uuids = |ids: &[&str]| ids.iter().map(|id| id.to_string()).collect::<Vec<_>>();
+        let mut previous = HashMap::from([(0, 100), (1, 200), (2, 300)]);
+
+        NvidiaGpu::retain_unchanged_baselines(
+            &mut previous,
+            &uuids(&["GPU-a", "GPU-b", "GPU-c"]),
+            &uuids(&["GPU-a", "GPU-d"]),
+        );
+
+        assert_eq!(previous, HashMap::from([(0, 100)]));
+    }
+
     #[test]
     fn zero_delta_when_no_change() {
         let delta = NvidiaGpu::compute_delta_joules(Some(5000), 5000);
@@ -302,8 +560,11 @@ mod tests {
     #[test]
     fn attributes_fully_tracked_energy_by_process_memory_share() {
         let tracked: HashSet<u32> = HashSet::from([1001, 1002]);
-        let total_used = 100 * 1024 * 1024;
-        let process_memories = vec![(1001, 40 * 1024 * 1024_u64), (1002, 60 * 1024 * 1024_u64)];
+        let total_used = 100.0 * 1024.0 * 1024.0;
+        let process_memories = vec![
+            (1001, 40.0 * 1024.0 * 1024.0),
+            (1002, 60.0 * 1024.0 * 1024.0),
+        ];
 
         let records = NvidiaGpu::attribute_energy_for_processes(
             0,
@@ -311,7 +572,7 @@ mod tests {
             Some(total_used),
             &tracked,
             &process_memories,
-            42,
+            RecordTime::now(),
         );
 
         assert_eq!(records.len(), 2);
@@ -329,10 +590,10 @@ mod tests {
         let records = NvidiaGpu::attribute_energy_for_processes(
             0,
             10.0,
-            Some(100 * 1024 * 1024),
+            Some(100.0 * 1024.0 * 1024.0),
             &tracked,
             &process_memories,
-            42,
+            RecordTime::now(),
         );
 
         assert_eq!(records.len(), 1);
@@ -343,8 +604,11 @@ mod tests {
     #[test]
     fn attributes_partial_tracked_share_and_unattributed_remainder() {
         let tracked: HashSet<u32> = HashSet::from([1001]);
-        let total_used = 100 * 1024 * 1024;
-        let process_memories = vec![(1001, 40 * 1024 * 1024_u64), (9999, 60 * 1024 * 1024_u64)];
+        let total_used = 100.0 * 1024.0 * 1024.0;
+        let process_memories = vec![
+            (1001, 40.0 * 1024.0 * 1024.0),
+            (9999, 60.0 * 1024.0 * 1024.0),
+        ];
 
         let records = NvidiaGpu::attribute_energy_for_processes(
             0,
@@ -352,7 +616,7 @@ mod tests {
             Some(total_used),
             &tracked,
             &process_memories,
-            42,
+            RecordTime::now(),
         );
 
         assert_eq!(records.len(), 2);
@@ -365,15 +629,15 @@ mod tests {
     #[test]
     fn attribution_returns_empty_on_zero_delta() {
         let tracked: HashSet<u32> = HashSet::from([1001]);
-        let process_memories = vec![(1001, 1024_u64)];
+        let process_memories = vec![(1001, 1024.0)];
 
         let records = NvidiaGpu::attribute_energy_for_processes(
             0,
             0.0,
-            Some(4096),
+            Some(4096.0),
             &tracked,
             &process_memories,
-            42,
+            RecordTime::now(),
         );
 
         assert!(records.is_empty());
@@ -382,7 +646,7 @@ mod tests {
     #[test]
     fn attributes_full_delta_to_unattributed_without_memory_basis() {
         let tracked: HashSet<u32> = HashSet::from([1001]);
-        let process_memories = vec![(1001, 1024_u64)];
+        let process_memories = vec![(1001, 1024.0)];
 
         let records = NvidiaGpu::attribute_energy_for_processes(
             0,
@@ -390,7 +654,7 @@ mod tests {
             None,
             &tracked,
             &process_memories,
-            42,
+            RecordTime::now(),
         );
 
         assert_eq!(records.len(), 1);
@@ -398,23 +662,68 @@ mod tests {
         assert!((records[0].energy - 10.0).abs() < f64::EPSILON);
     }
 
+    #[test]
+    fn attributes_by_mean_sm_utilization_of_process_samples() {
+        let sample = |pid, sm_util, mem_util| ProcessUtilizationSample {
+            pid,
+            timestamp: 0,
+            sm_util,
+            mem_util,
+            enc_util: 0,
+            dec_util: 0,
+        };
+        let samples = [
+            sample(1001, 60, 10),
+            sample(1002, 20, 30),
+            sample(1001, 40, 20),
+        ];
+
+        let utilization = NvidiaGpu::mean_utilization_by_pid(&samples);
+        assert_eq!(
+            utilization,
+            BTreeMap::from([(1001, (50.0, 15.0)), (1002, (20.0, 30.0))])
+        );
+
+        let process_sm: Vec<(u32, f64)> = utilization
+            .into_iter()
+            .map(|(pid, (sm, _))| (pid, sm))
+            .collect();
+        let records = NvidiaGpu::attribute_energy_for_processes(
+            0,
+            7.0,
+            Some(70.0),
+            &HashSet::from([1001]),
+            &process_sm,
+            RecordTime::now(),
+        );
+
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].pid, 1001);
+        assert!((records[0].energy - 5.0).abs() < 1e-9);
+        assert_eq!(records[1].pid, UNATTRIBUTED_PID);
+        assert!((records[1].energy - 2.0).abs() < 1e-9);
+    }
+
     #[test]
     fn default_without_gpu_is_safe() {
         // Default constructor should not panic regardless of GPU availability.
         let collector = NvidiaGpu::default();
         // device_count should be 0 if no GPU, >0 if GPU present
         // Either way, no panic is the success criterion.
-        assert!(collector.device_count == 0 || collector.nvml.is_some());
+        assert!(collector.device_indices().is_empty() || collector.nvml.read().unwrap().is_some());
     }
 
     #[test]
     fn device_indices_with_no_filter() {
         let collector = NvidiaGpu {
-            nvml: None,
-            device_count: 3,
+            nvml: RwLock::new(None),
+            device_uuids: RwLock::new(vec![String::new(); 3]),
             device_filter: None,
             tracked_pids: Arc::new(Mutex::new(Vec::new())),
             previous_energy_mj: Arc::new(Mutex::new(HashMap::new())),
+            last_attribution_sample_us: Arc::new(Mutex::new(HashMap::new())),
+            last_utilization_sample_us: Arc::new(Mutex::new(HashMap::new())),
+            read_schedule: Mutex::new(ReadSchedule::default()),
         };
         assert_eq!(collector.device_indices(), vec![0, 1, 2]);
     }
@@ -422,11 +731,14 @@ mod tests {
     #[test]
     fn device_indices_with_filter() {
         let collector = NvidiaGpu {
-            nvml: None,
-            device_count: 4,
+            nvml: RwLock::new(None),
+            device_uuids: RwLock::new(vec![String::new(); 4]),
             device_filter: Some(vec![1, 3]),
             tracked_pids: Arc::new(Mutex::new(Vec::new())),
             previous_energy_mj: Arc::new(Mutex::new(HashMap::new())),
+            last_attribution_sample_us: Arc::new(Mutex::new(HashMap::new())),
+            last_utilization_sample_us: Arc::new(Mutex::new(HashMap::new())),
+            read_schedule: Mutex::new(ReadSchedule::default()),
         };
         assert_eq!(collector.device_indices(), vec![1, 3]);
     }
@@ -434,11 +746,14 @@ mod tests {
     #[test]
     fn device_indices_filter_excludes_out_of_range() {
         let collector = NvidiaGpu {
-            nvml: None,
-            device_count: 2,
+            nvml: RwLock::new(None),
+            device_uuids: RwLock::new(vec![String::new(); 2]),
             device_filter: Some(vec![0, 1, 5, 10]),
             tracked_pids: Arc::new(Mutex::new(Vec::new())),
             previous_energy_mj: Arc::new(Mutex::new(HashMap::new())),
+            last_attribution_sample_us: Arc::new(Mutex::new(HashMap::new())),
+            last_utilization_sample_us: Arc::new(Mutex::new(HashMap::new())),
+            read_schedule: Mutex::new(ReadSchedule::default()),
         };
         assert_eq!(collector.device_indices(), vec![0, 1]);
     }
@@ -453,16 +768,20 @@ mod tests {
     #[tokio::test]
     async fn get_energy_trace_returns_empty_when_no_nvml() {
         let collector = NvidiaGpu {
-            nvml: None,
-            device_count: 0,
+            nvml: RwLock::new(None),
+            device_uuids: RwLock::new(Vec::new()),
             device_filter: None,
             tracked_pids: Arc::new(Mutex::new(vec![1234])),
             previous_energy_mj: Arc::new(Mutex::new(HashMap::new())),
+            last_attribution_sample_us: Arc::new(Mutex::new(HashMap::new())),
+            last_utilization_sample_us: Arc::new(Mutex::new(HashMap::new())),
+            read_schedule: Mutex::new(ReadSchedule::default()),
         };
 
         let result = collector.get_energy_trace().await;
         assert!(result.is_ok());
         assert!(result.unwrap().is_empty());
+        assert_eq!(collector.get_utilization_trace().await, Ok(Vec::new()));
     }
 
     #[tokio::test]
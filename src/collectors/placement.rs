@@ -0,0 +1,424 @@
+/// Socket Placement Module
+///
+/// Tracks which socket each process ran on between energy reads. The kernel
+/// exposes the CPU a task last ran on as field 39 of `/proc/<pid>/stat`;
+/// sampling it periodically yields a visit distribution per process, which the
+/// RAPL collector uses to split a process's share across sockets instead of
+/// charging every socket by the same system-wide utilization ratio.
+///
+/// For `per_core` attribution, [`SocketCpuTime`] also accounts CPU time per
+/// socket: each process's utime and stime, placed on the sockets it ran on
+/// or may run on, against the busy jiffies of that socket's CPUs in
+/// `/proc/stat`.
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Index of `processor` (field 39) among the fields after the `comm` field.
+const STAT_PROCESSOR_INDEX: usize = 36;
+/// Index of `utime` (field 14) among the fields after the `comm` field;
+/// `stime` follows it.
+const STAT_UTIME_INDEX: usize = 11;
+
+/// Map each logical CPU to its physical package (socket) id.
+pub(crate) fn cpu_package_map(cpu_sysfs: &Path) -> BTreeMap<u32, u32> {
+    let mut packages = BTreeMap::new();
+    let Ok(entries) = fs::read_dir(cpu_sysfs) else {
+        return packages;
+    };
+
+    for entry in entries.flatten() {
+        let name = entry.file_name();
+        let Some(cpu) = name
+            .to_str()
+            .and_then(|name| name.strip_prefix("cpu"))
+            .and_then(|id| id.parse::<u32>().ok())
+        else {
+            continue;
+        };
+        let package = fs::read_to_string(entry.path().join("topology/physical_package_id"))
+            .ok()
+            .and_then(|contents| contents.trim().parse().ok());
+        if let Some(package) = package {
+            packages.insert(cpu, package);
+        }
+    }
+    packages
+}
+
+/// Parse the last-run CPU from the contents of `/proc/<pid>/stat`.
+pub(crate) fn parse_last_cpu(stat_content: &str) -> Option<u32> {
+    let comm_end = stat_content.rfind(')')?;
+    stat_content
+        .get(comm_end + 2..)?
+        .split_whitespace()
+        .nth(STAT_PROCESSOR_INDEX)?
+        .parse()
+        .ok()
+}
+
+/// Parse `utime + stime`, in clock ticks, from the contents of
+/// `/proc/<pid>/stat`.
+pub(crate) fn parse_cpu_ticks(stat_content: &str) -> Option<u64> {
+    let comm_end = stat_content.rfind(')')?;
+    let mut fields = stat_content
+        .get(comm_end + 2..)?
+        .split_whitespace()
+        .skip(STAT_UTIME_INDEX);
+    let utime: u64 = fields.next()?.parse().ok()?;
+    let stime: u64 = fields.next()?.parse().ok()?;
+    Some(utime + stime)
+}
+
+/// Busy clock ticks of each CPU from the `cpuN` lines of `/proc/stat`:
+/// every state but idle and iowait, with guest time counted once.
+pub(crate) fn parse_cpu_busy_ticks(stat_content: &str) -> BTreeMap<u32, u64> {
+    stat_content
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let cpu = fields.next()?.strip_prefix("cpu")?.parse().ok()?;
+            let ticks: Vec<u64> = fields.map_while(|field| field.parse().ok()).collect();
+            let busy = ticks
+                .iter()
+                .enumerate()
+                // idle, iowait, and guest time already counted in user
+                .filter(|(index, _)| !matches!(index, 3 | 4 | 8 | 9))
+                .map(|(_, ticks)| ticks)
+                .sum();
+            Some((cpu, busy))
+        })
+        .collect()
+}
+
+/// Parse the CPUs of the `Cpus_allowed_list` line of `/proc/<pid>/status`,
+/// written as ranges such as `0-3,8`.
+pub(crate) fn parse_allowed_cpus(status_content: &str) -> Option<BTreeSet<u32>> {
+    let list = status_content
+        .lines()
+        .find_map(|line| line.strip_prefix("Cpus_allowed_list:"))?;
+    let mut cpus = BTreeSet::new();
+    for range in list.trim().split(',') {
+        match range.split_once('-') {
+            Some((first, last)) => cpus.extend(first.parse::<u32>().ok()?..=last.parse().ok()?),
+            None => {
+                cpus.insert(range.parse().ok()?);
+            }
+        }
+    }
+    Some(cpus)
+}
+
+/// CPU time of tracked processes and of each socket's CPUs between energy
+/// reads, for attributing each socket's energy by the time spent on it.
+#[derive(Debug)]
+pub(crate) struct SocketCpuTime {
+    proc_root: PathBuf,
+    cpu_to_socket: BTreeMap<u32, u32>,
+    /// Busy ticks per CPU at the previous update
+    cpu_busy: BTreeMap<u32, u64>,
+    /// CPU ticks per tracked pid at the previous update
+    process_ticks: HashMap<u32, u64>,
+}
+
+impl SocketCpuTime {
+    pub(crate) fn new(cpu_to_socket: BTreeMap<u32, u32>) -> Self {
+        Self::with_proc_root(Path::new("/proc"), cpu_to_socket)
+    }
+
+    pub(crate) fn with_proc_root(proc_root: &Path, cpu_to_socket: BTreeMap<u32, u32>) -> Self {
+        Self {
+            proc_root: proc_root.to_path_buf(),
+            cpu_to_socket,
+            cpu_busy: BTreeMap::new(),
+            process_ticks: HashMap::new(),
+        }
+    }
+
+    /// Fraction of each socket's busy time spent by each of `pids` since the
+    /// last update, by socket id. A process's time is placed on sockets by
+    /// `placement` weights when it was sampled, otherwise spread over the
+    /// sockets its affinity allows. The first update only sets baselines.
+    /// `None` when the CPU topology is unknown.
+    pub(crate) fn update(
+        &mut self,
+        pids: &[u32],
+        placement: &HashMap<u32, BTreeMap<u32, f64>>,
+    ) -> Option<BTreeMap<u32, Vec<(u32, f64)>>> {
+        if self.cpu_to_socket.is_empty() {
+            return None;
+        }
+        let cpu_busy = fs::read_to_string(self.proc_root.join("stat"))
+            .map(|contents| parse_cpu_busy_ticks(&contents))
+            .unwrap_or_default();
+        let mut socket_busy: BTreeMap<u32, u64> = BTreeMap::new();
+        for (cpu, &busy) in &cpu_busy {
+            if let (Some(&socket), Some(&previous)) =
+                (self.cpu_to_socket.get(cpu), self.cpu_busy.get(cpu))
+            {
+                *socket_busy.entry(socket).or_default() += busy.saturating_sub(previous);
+            }
+        }
+        self.cpu_busy = cpu_busy;
+
+        let mut socket_ticks: BTreeMap<u32, Vec<(u32, f64)>> = BTreeMap::new();
+        let mut process_ticks = HashMap::with_capacity(pids.len());
+        for &pid in pids {
+            let Some(ticks) = fs::read_to_string(self.proc_root.join(format!("{pid}/stat")))
+                .ok()
+                .and_then(|contents| parse_cpu_ticks(&contents))
+            else {
+                continue;
+            };
+            process_ticks.insert(pid, ticks);
+            let Some(&previous) = self.process_ticks.get(&pid) else {
+                continue;
+            };
+            let delta = ticks.saturating_sub(previous) as f64;
+            for (socket, weight) in self.socket_weights(pid, placement) {
+                socket_ticks
+                    .entry(socket)
+                    .or_default()
+                    .push((pid, delta * weight));
+            }
+        }
+        self.process_ticks = process_ticks;
+
+        let fractions = socket_ticks
+            .into_iter()
+            .map(|(socket, ticks)| {
+                let busy = socket_busy.get(&socket).copied().unwrap_or(0) as f64;
+                let fractions = ticks
+                    .into_iter()
+                    .map(|(pid, ticks)| {
+                        let fraction = if busy > 0.0 {
+                            (ticks / busy).min(1.0)
+                        } else {
+                            0.0
+                        };
+                        (pid, fraction)
+                    })
+                    .collect();
+                (socket, fractions)
+            })
+            .collect();
+        Some(fractions)
+    }
+
+    /// Sockets `pid` ran on, by share of its time: the sampled placement,
+    /// else an even spread over the sockets of its allowed CPUs, else over
+    /// all sockets.
+    fn socket_weights(
+        &self,
+        pid: u32,
+        placement: &HashMap<u32, BTreeMap<u32, f64>>,
+    ) -> BTreeMap<u32, f64> {
+        if let Some(weights) = placement.get(&pid) {
+            return weights.clone();
+        }
+        let allowed = fs::read_to_string(self.proc_root.join(format!("{pid}/status")))
+            .ok()
+            .and_then(|contents| parse_allowed_cpus(&contents));
+        let sockets: BTreeSet<u32> = self
+            .cpu_to_socket
+            .iter()
+            .filter(|(cpu, _)| allowed.as_ref().is_none_or(|allowed| allowed.contains(cpu)))
+            .map(|(_, &socket)| socket)
+            .collect();
+        let share = 1.0 / sockets.len().max(1) as f64;
+        sockets.into_iter().map(|socket| (socket, share)).collect()
+    }
+}
+
+/// Per-process socket visit counts accumulated between energy reads.
+#[derive(Debug, Default)]
+pub(crate) struct SocketPlacement {
+    cpu_to_socket: BTreeMap<u32, u32>,
+    visits: HashMap<u32, BTreeMap<u32, u32>>,
+}
+
+impl SocketPlacement {
+    pub(crate) fn new(cpu_to_socket: BTreeMap<u32, u32>) -> Self {
+        Self {
+            cpu_to_socket,
+            visits: HashMap::new(),
+        }
+    }
+
+    /// Whether the host has more than one socket, i.e. whether sampling matters.
+    pub(crate) fn is_multi_socket(&self) -> bool {
+        let mut sockets = self.cpu_to_socket.values();
+        sockets
+            .next()
+            .is_some_and(|first| sockets.any(|socket| socket != first))
+    }
+
+    /// Record the current socket of every PID in `pids`.
+    pub(crate) fn sample(&mut self, pids: &[u32]) {
+        for &pid in pids {
+            let last_cpu = fs::read_to_string(format!("/proc/{pid}/stat"))
+                .ok()
+                .and_then(|contents| parse_last_cpu(&contents));
+            if let Some(cpu) = last_cpu {
+                self.record_visit(pid, cpu);
+            }
+        }
+    }
+
+    pub(crate) fn record_visit(&mut self, pid: u32, cpu: u32) {
+        if let Some(&socket) = self.cpu_to_socket.get(&cpu) {
+            *self
+                .visits
+                .entry(pid)
+                .or_default()
+                .entry(socket)
+                .or_default() += 1;
+        }
+    }
+
+    /// Drain the visit history and return per-PID socket weights.
+    ///
+    /// Weights for a PID sum to 1. PIDs without samples are absent, which
+    /// callers treat as an even spread over all sockets.
+    pub(crate) fn take_weights(&mut self) -> HashMap<u32, BTreeMap<u32, f64>> {
+        self.visits
+            .drain()
+            .filter_map(|(pid, visits)| {
+                let total: u32 = visits.values().sum();
+                (total > 0).then(|| {
+                    let weights = visits
+                        .into_iter()
+                        .map(|(socket, count)| (socket, count as f64 / total as f64))
+                        .collect();
+                    (pid, weights)
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn parse_last_cpu_reads_field_39_with_spaces_in_comm() {
+        let mut fields: Vec<String> = (3..=52).map(|field| field.to_string()).collect();
+        fields[STAT_PROCESSOR_INDEX] = "7".to_string();
+        let stat = format!("42 (my (odd) proc) {}", fields.join(" "));
+
+        assert_eq!(parse_last_cpu(&stat), Some(7));
+        assert_eq!(parse_last_cpu("42 (short) S 1 2"), None);
+    }
+
+    #[test]
+    fn take_weights_returns_visit_distribution_and_resets() {
+        let mut placement = SocketPlacement::new(BTreeMap::from([(0, 0), (1, 0), (2, 1)]));
+        placement.record_visit(10, 0);
+        placement.record_visit(10, 1);
+        placement.record_visit(10, 2);
+        placement.record_visit(10, 2);
+        placement.record_visit(11, 99);
+
+        let weights = placement.take_weights();
+
+        assert_eq!(weights[&10], BTreeMap::from([(0, 0.5), (1, 0.5)]));
+        assert!(!weights.contains_key(&11));
+        assert!(placement.take_weights().is_empty());
+    }
+
+    #[test]
+    fn parses_cpu_ticks_busy_ticks_and_allowed_cpus() {
+        let mut fields: Vec<String> = (3..=52).map(|field| field.to_string()).collect();
+        fields[STAT_UTIME_INDEX] = "120".to_string();
+        fields[STAT_UTIME_INDEX + 1] = "30".to_string();
+        let stat = format!("42 (my (odd) proc) {}", fields.join(" "));
+        assert_eq!(parse_cpu_ticks(&stat), Some(150));
+
+        let proc_stat = "cpu  10 0 10 100 5 0 0 0 0 0\n\
+                         cpu0 4 1 2 50 3 1 1 1 2 0\n\
+                         cpu1 6 0 8 50 2 0 0 0 0 0\n\
+                         intr 12345\n";
+        assert_eq!(
+            parse_cpu_busy_ticks(proc_stat),
+            BTreeMap::from([(0, 10), (1, 14)])
+        );
+
+        let status = "Name:\tworker\nCpus_allowed:\tff\nCpus_allowed_list:\t0-2,8\n";
+        assert_eq!(
+            parse_allowed_cpus(status),
+            Some(BTreeSet::from([0, 1, 2, 8]))
+        );
+        assert_eq!(parse_allowed_cpus("Name:\tworker\n"), None);
+    }
+
+    fn write_proc_stat(proc_root: &Path, busy: [u64; 4]) {
+        let cpus: Vec<String> = busy
+            .iter()
+            .enumerate()
+            .map(|(cpu, busy)| format!("cpu{cpu} {busy} 0 0 1000 0 0 0 0 0 0"))
+            .collect();
+        fs::write(proc_root.join("stat"), cpus.join("\n")).unwrap();
+    }
+
+    fn write_process(proc_root: &Path, pid: u32, ticks: u64, allowed: &str) {
+        let dir = proc_root.join(pid.to_string());
+        fs::create_dir_all(&dir).unwrap();
+        let mut fields: Vec<String> = (3..=52).map(|_| "0".to_string()).collect();
+        fields[STAT_UTIME_INDEX] = ticks.to_string();
+        fs::write(
+            dir.join("stat"),
+            format!("{pid} (worker) {}", fields.join(" ")),
+        )
+        .unwrap();
+        fs::write(
+            dir.join("status"),
+            format!("Cpus_allowed_list:\t{allowed}\n"),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn socket_cpu_time_charges_each_socket_by_the_time_spent_on_it() {
+        let dir = TempDir::new().unwrap();
+        let topology = BTreeMap::from([(0, 0), (1, 0), (2, 1), (3, 1)]);
+        let mut socket_time = SocketCpuTime::with_proc_root(dir.path(), topology);
+        write_proc_stat(dir.path(), [0; 4]);
+        write_process(dir.path(), 10, 0, "0-1");
+        write_process(dir.path(), 11, 0, "0-3");
+        let sampled = HashMap::from([(11, BTreeMap::from([(0, 0.25), (1, 0.75)]))]);
+        assert!(socket_time.update(&[10, 11], &sampled).unwrap().is_empty());
+
+        // Socket 0 was busy 100 ticks, socket 1 60 ticks.
+        write_proc_stat(dir.path(), [50, 50, 30, 30]);
+        write_process(dir.path(), 10, 40, "0-1");
+        write_process(dir.path(), 11, 80, "0-3");
+        let fractions = socket_time.update(&[10, 11], &sampled).unwrap();
+
+        // Pinned pid 10 only runs on socket 0; pid 11 by its placement.
+        assert_eq!(fractions[&0], [(10, 0.4), (11, 0.2)]);
+        assert_eq!(fractions[&1], [(11, 1.0)]);
+        assert!(
+            SocketCpuTime::with_proc_root(dir.path(), BTreeMap::new())
+                .update(&[10], &HashMap::new())
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn cpu_package_map_reads_sysfs_topology() {
+        let dir = TempDir::new().unwrap();
+        for (cpu, package) in [(0, 0), (1, 1)] {
+            let topology = dir.path().join(format!("cpu{cpu}/topology"));
+            fs::create_dir_all(&topology).unwrap();
+            fs::write(topology.join("physical_package_id"), format!("{package}\n")).unwrap();
+        }
+        fs::create_dir_all(dir.path().join("cpuidle")).unwrap();
+
+        let placement = SocketPlacement::new(cpu_package_map(dir.path()));
+
+        assert!(placement.is_multi_socket());
+        assert!(!SocketPlacement::new(BTreeMap::from([(0, 0), (1, 0)])).is_multi_socket());
+    }
+}
@@ -1,81 +1,310 @@
-use crate::energy_group::{EnergyCollector, EnergyRecord};
+use crate::collectors::attribution::{
+    AttributionStrategy, ClassStrategies, DeviceClass, ProcessUsage,
+};
+use crate::collectors::cpu_energy::{CpuEnergyPaths, probe_perf_event};
+use crate::collectors::placement::{SocketCpuTime, SocketPlacement, cpu_package_map};
+use crate::device_registry::DeviceInfo;
+use crate::energy_group::{DegradedDomain, EnergyCollector, EnergyRecord};
 use crate::monitor::{DeviceSource, DeviceSources};
+use crate::read_schedule::ReadSchedule;
+use crate::utils::cgroup::{CgroupCpuTracker, TrackedCgroup};
+use crate::utils::errors::CollectorError;
+use crate::utils::time::RecordTime;
 use async_trait::async_trait;
-use chrono::Utc;
 use log::warn;
-use std::collections::BTreeMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::fs;
-use std::io::Read;
 use std::path::{Path, PathBuf};
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, RwLock, Weak};
+use std::time::{Duration, Instant};
 
 const LINUX_PAGE_SIZE_BYTES: u64 = 4096;
+/// How often process placement is sampled between energy reads.
+const PLACEMENT_SAMPLE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Source of a cumulative RAPL energy counter.
+///
+/// Powercap zones, perf_event PMUs, MSRs and the utilization model all expose a
+/// monotonically increasing energy total; `DeltaReader` turns it into deltas.
+pub(crate) trait EnergyCounter: Send + Sync {
+    /// Read the cumulative counter value in micro-joules.
+    fn read_microjoules(&self) -> Result<i64, CollectorError>;
+
+    /// Human-readable counter location used in log messages.
+    fn label(&self) -> String;
+
+    /// Rated maximum power of the measured domain, when known.
+    fn max_power_watts(&self) -> Option<f64> {
+        None
+    }
+
+    /// Interface the counter is read through, recorded in device metadata.
+    fn source(&self) -> Option<String> {
+        None
+    }
+
+    /// Energy of one counter increment in joules, when known.
+    fn resolution_joules(&self) -> Option<f64> {
+        None
+    }
+
+    /// Value in micro-joules at which the counter wraps back to zero, when
+    /// known.
+    fn max_range_microjoules(&self) -> Option<i64> {
+        None
+    }
+}
+
+/// Powercap zone counter backed by `<zone>/energy_uj`.
+struct PowercapCounter {
+    zone_path: PathBuf,
+}
+
+impl EnergyCounter for PowercapCounter {
+    fn read_microjoules(&self) -> Result<i64, CollectorError> {
+        read_energy_file(&self.zone_path.join("energy_uj"))
+    }
+
+    fn label(&self) -> String {
+        self.zone_path.join("energy_uj").display().to_string()
+    }
+
+    fn max_power_watts(&self) -> Option<f64> {
+        fs::read_to_string(self.zone_path.join("constraint_0_max_power_uw"))
+            .ok()?
+            .trim()
+            .parse::<u64>()
+            .ok()
+            .filter(|microwatts| *microwatts > 0)
+            .map(|microwatts| microwatts as f64 * 1e-6)
+    }
+
+    /// The zone's control type, e.g. `intel-rapl` for the MSR-backed zones
+    /// or `intel-rapl-mmio` for the MMIO ones.
+    fn source(&self) -> Option<String> {
+        let name = self.zone_path.file_name()?.to_str()?;
+        Some(name.split(':').next()?.to_string())
+    }
+
+    fn resolution_joules(&self) -> Option<f64> {
+        // RAPL energy status registers are 32 bits wide, so the range a zone
+        // covers before wrapping is 2^32 energy units.
+        self.max_range_microjoules()
+            .map(|microjoules| microjoules as f64 * 1e-6 / 2f64.powi(32))
+    }
+
+    fn max_range_microjoules(&self) -> Option<i64> {
+        fs::read_to_string(self.zone_path.join("max_energy_range_uj"))
+            .ok()?
+            .trim()
+            .parse::<i64>()
+            .ok()
+            .filter(|microjoules| *microjoules > 0)
+    }
+}
 
-/// DeltaReader tracks energy deltas from RAPL MSR registers
-/// It reads the energy_uj file and computes the delta from the previous reading
+/// DeltaReader tracks energy deltas from a cumulative RAPL counter
+/// It reads the counter and computes the delta from the previous reading
 #[derive(Clone)]
-struct DeltaReader {
-    file_path: PathBuf,
+pub(crate) struct DeltaReader {
+    counter: Arc<dyn EnergyCounter>,
     previous_value: Arc<Mutex<Option<i64>>>,
+    /// Wrap-around value of the counter, read once at discovery
+    max_range_uj: Option<i64>,
 }
 
 impl DeltaReader {
     fn new(file_path: PathBuf) -> Self {
+        Self::from_counter(Arc::new(PowercapCounter {
+            zone_path: file_path,
+        }))
+    }
+
+    pub(crate) fn from_counter(counter: Arc<dyn EnergyCounter>) -> Self {
         Self {
-            file_path,
+            max_range_uj: counter.max_range_microjoules(),
+            counter,
             previous_value: Arc::new(Mutex::new(None)),
         }
     }
 
-    /// Read energy delta in joules from RAPL counter
-    /// Handles counter overflow by retrying multiple times
-    fn read_delta(&self) -> Result<f64, String> {
-        let energy_file = self.file_path.join("energy_uj");
-        let content = fs::read_to_string(&energy_file)
-            .map_err(|e| format!("Failed to read energy file: {}", e))?;
+    pub(crate) fn max_power_watts(&self) -> Option<f64> {
+        self.counter.max_power_watts()
+    }
 
-        let value: i64 = content
-            .trim()
-            .parse()
-            .map_err(|e| format!("Failed to parse energy value: {}", e))?;
+    pub(crate) fn source(&self) -> Option<String> {
+        self.counter.source()
+    }
+
+    /// Keep whichever of `current` and `candidate`, two counters of the same
+    /// domain, has the finer energy unit. MSR-backed zones win ties over
+    /// MMIO ones, and counters of unknown resolution lose to known ones.
+    fn prefer(current: &mut Option<DeltaReader>, candidate: DeltaReader) {
+        let Some(existing) = current.take() else {
+            *current = Some(candidate);
+            return;
+        };
+        let rank = |reader: &DeltaReader| {
+            (
+                reader.counter.resolution_joules().unwrap_or(f64::INFINITY),
+                reader
+                    .source()
+                    .is_some_and(|source| source.ends_with("-mmio")),
+            )
+        };
+        let (existing_rank, candidate_rank) = (rank(&existing), rank(&candidate));
+        let (kept, dropped) = match existing_rank
+            .0
+            .total_cmp(&candidate_rank.0)
+            .then(existing_rank.1.cmp(&candidate_rank.1))
+        {
+            std::cmp::Ordering::Greater => (candidate, existing),
+            _ => (existing, candidate),
+        };
+        log::debug!(
+            "Reading {} instead of {}, which measures the same domain",
+            kept.counter.label(),
+            dropped.counter.label()
+        );
+        *current = Some(kept);
+    }
+
+    /// The last counter value read, in joules.
+    fn counter_joules(&self) -> Option<f64> {
+        self.previous_value
+            .lock()
+            .unwrap()
+            .map(|microjoules| microjoules as f64 * 1e-6)
+    }
+
+    /// Read energy delta in joules from RAPL counter
+    /// A reading below the previous one is a wrapped counter: the delta runs
+    /// up to the counter's range and on from zero. Counters of unknown range
+    /// report no energy for that interval.
+    fn read_delta(&self) -> Result<f64, CollectorError> {
+        let value = self.counter.read_microjoules()?;
 
         let mut prev = self.previous_value.lock().unwrap();
 
         // First read, just store the value
-        if prev.is_none() {
-            *prev = Some(value);
+        let Some(previous) = prev.replace(value) else {
             return Ok(0.0);
-        }
+        };
 
-        let previous = prev.unwrap();
         let delta = value - previous;
-
-        // Check if delta is positive (no overflow)
         if delta >= 0 {
-            *prev = Some(value);
             // Convert from micro-joules to joules
             return Ok(delta as f64 * 1e-6);
         }
 
-        // If all retries failed, log warning and return 0
-        warn!("Energy counter overflow detected for: {:?}", &energy_file);
-        *prev = Some(value);
-        Ok(0.0)
+        match self.max_range_uj {
+            Some(max_range) if previous <= max_range => {
+                log::debug!("Energy counter wrapped for: {}", self.counter.label());
+                Ok((max_range - previous + value) as f64 * 1e-6)
+            }
+            _ => {
+                warn!(
+                    "Energy counter overflow detected for: {}",
+                    self.counter.label()
+                );
+                Ok(0.0)
+            }
+        }
     }
 }
 
 /// Per-socket RAPL energy readers organized by component type
 #[derive(Clone)]
-struct SocketReaders {
-    socket_id: u32,
-    package_reader: Option<DeltaReader>, // PKG: Total socket energy
-    core_reader: Option<DeltaReader>,    // PP0: Cores + L1/L2 caches
-    uncore_reader: Option<DeltaReader>,  // PP1: iGPU, L3, memory controller
+pub(crate) struct SocketReaders {
+    pub(crate) socket_id: u32,
+    pub(crate) package_reader: Option<DeltaReader>, // PKG: Total socket energy
+    pub(crate) core_reader: Option<DeltaReader>,    // PP0: Cores + L1/L2 caches
+    pub(crate) uncore_reader: Option<DeltaReader>,  // PP1: iGPU, L3, memory controller
 }
 
 type UtilizationSeries = Vec<(u32, f64)>;
 const UNATTRIBUTED_PID: u32 = 0;
 
+/// How a socket's package energy is split among tracked processes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CpuAttribution {
+    /// By each process's share of system-wide CPU utilization, spread over
+    /// the sockets it was seen running on
+    #[default]
+    System,
+    /// By each process's CPU time on a socket over the busy time of that
+    /// socket's CPUs, from `/proc/<pid>/stat` and per-CPU `/proc/stat`
+    PerCore,
+}
+
+impl CpuAttribution {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::System => "system",
+            Self::PerCore => "per_core",
+        }
+    }
+}
+
+impl std::str::FromStr for CpuAttribution {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        [Self::System, Self::PerCore]
+            .into_iter()
+            .find(|attribution| attribution.as_str() == name)
+            .ok_or_else(|| format!("unknown attribution '{name}' (expected system or per_core)"))
+    }
+}
+
+/// Where [`Rapl::with_backend`] reads the RAPL counters from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RaplBackend {
+    /// powercap when one of its counters is readable, else perf_event.
+    /// A custom powercap directory is always read as powercap.
+    #[default]
+    Auto,
+    /// `energy_uj` of the zones under the powercap directory, often
+    /// readable by root only
+    Powercap,
+    /// The `power/energy-*` events of the perf PMU, readable without root
+    /// when `kernel.perf_event_paranoid` is 0 or lower
+    PerfEvent,
+}
+
+impl RaplBackend {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Auto => "auto",
+            Self::Powercap => "powercap",
+            Self::PerfEvent => "perf_event",
+        }
+    }
+}
+
+impl std::str::FromStr for RaplBackend {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        [Self::Auto, Self::Powercap, Self::PerfEvent]
+            .into_iter()
+            .find(|backend| backend.as_str() == name)
+            .ok_or_else(|| {
+                format!("unknown RAPL backend '{name}' (expected auto, powercap or perf_event)")
+            })
+    }
+}
+
+/// Counters a collector built by [`Rapl::with_backend`] rescans on
+/// rediscovery.
+enum Rescan {
+    Powercap(String),
+    PerfEvent,
+}
+
 /// Tracks CPU times for a process to calculate CPU percentage accurately
 /// Similar to how psutil tracks cpu_percent internally
 #[derive(Clone, Default)]
@@ -157,29 +386,109 @@ impl ProcessCpuTracker {
     }
 }
 
-/// Main RAPL collector with per-socket energy attribution
-pub struct Rapl {
+/// Energy readers of one discovery pass, swapped as a whole on hotplug.
+#[derive(Clone, Default)]
+pub(crate) struct RaplReaders {
     /// Per-socket readers organized by socket ID
-    socket_readers: Vec<SocketReaders>,
+    pub(crate) socket_readers: Vec<SocketReaders>,
     /// DRAM energy readers discovered from RAPL subdomains
-    dram_readers: Vec<DeltaReader>,
+    pub(crate) dram_readers: Vec<DeltaReader>,
     /// System-level PSYS energy reader (platform/system-wide power)
-    psys_reader: Option<DeltaReader>,
+    pub(crate) psys_reader: Option<DeltaReader>,
+    /// Zones discovery found but could not use
+    pub(crate) skipped: Vec<DegradedDomain>,
+}
+
+impl RaplReaders {
+    pub(crate) fn package_count(&self) -> usize {
+        self.socket_readers
+            .iter()
+            .filter(|socket| socket.package_reader.is_some())
+            .count()
+    }
+
+    /// Counter labels, in a stable order, for detecting topology changes.
+    fn labels(&self) -> Vec<String> {
+        let mut labels: Vec<String> = self.readers().map(|r| r.counter.label()).collect();
+        labels.sort();
+        labels
+    }
+
+    fn readers(&self) -> impl Iterator<Item = &DeltaReader> {
+        self.socket_readers
+            .iter()
+            .flat_map(|socket| {
+                [
+                    &socket.package_reader,
+                    &socket.core_reader,
+                    &socket.uncore_reader,
+                ]
+            })
+            .chain([&self.psys_reader])
+            .flatten()
+            .chain(&self.dram_readers)
+    }
+
+    /// Swap in the reader already tracking the same counter, so its previous
+    /// value survives rediscovery and no energy is lost.
+    fn adopt_baselines(&mut self, previous: &RaplReaders) {
+        let known: HashMap<String, &DeltaReader> = previous
+            .readers()
+            .map(|reader| (reader.counter.label(), reader))
+            .collect();
+        let adopt = |reader: &mut DeltaReader| {
+            if let Some(&existing) = known.get(&reader.counter.label()) {
+                *reader = existing.clone();
+            }
+        };
+        for socket in &mut self.socket_readers {
+            socket.package_reader.iter_mut().for_each(adopt);
+            socket.core_reader.iter_mut().for_each(adopt);
+            socket.uncore_reader.iter_mut().for_each(adopt);
+        }
+        self.dram_readers.iter_mut().for_each(adopt);
+        self.psys_reader.iter_mut().for_each(adopt);
+    }
+}
+
+/// Main RAPL collector with per-socket energy attribution
+pub struct Rapl {
+    /// Energy readers, replaced when devices are hot-plugged
+    readers: RwLock<Arc<RaplReaders>>,
+    /// Counters to rescan, for collectors built by `Rapl::with_backend`
+    rescan: Option<Rescan>,
     /// Tracked process PIDs for per-process energy attribution
     tracked_pids: Arc<Mutex<Vec<u32>>>,
+    /// Cgroups attributed as a whole; when set, the tracked pids are unused
+    tracked_cgroups: Mutex<Vec<TrackedCgroup>>,
     /// Logical CPU count used to normalize process CPU percentages.
-    cpu_count: f64,
+    cpu_count: RwLock<f64>,
     /// Host total memory, used to normalize process RSS.
     total_memory_bytes: u64,
     /// Per-process CPU time trackers for accurate CPU percentage
     cpu_trackers: Mutex<std::collections::HashMap<u32, ProcessCpuTracker>>,
+    /// CPU time trackers of the tracked cgroups, by cgroup path
+    cgroup_trackers: Mutex<HashMap<PathBuf, CgroupCpuTracker>>,
     /// System-wide CPU tracker
     system_cpu_tracker: Mutex<SystemCpuTracker>,
+    /// Device name prefix for emitted records (`rapl` unless estimated)
+    device_prefix: &'static str,
+    /// Socket visit history of tracked processes since the last energy read
+    placement: Arc<Mutex<SocketPlacement>>,
+    attribution: CpuAttribution,
+    /// How the energy of each device class is split among processes
+    strategies: ClassStrategies,
+    /// Per-socket CPU time of tracked processes, for `per_core` attribution
+    socket_time: Mutex<SocketCpuTime>,
+    /// Offsets of the counter reads within each tick
+    read_schedule: Mutex<ReadSchedule>,
+    /// Devices whose latest read failed, skipped until they read again
+    read_failures: Mutex<BTreeMap<String, DegradedDomain>>,
 }
 
 /// Tracks system-wide CPU times
 #[derive(Clone, Default)]
-struct SystemCpuTracker {
+pub(crate) struct SystemCpuTracker {
     last_total: u64,
     last_idle: u64,
     last_timestamp_us: u64,
@@ -187,7 +496,7 @@ struct SystemCpuTracker {
 
 impl SystemCpuTracker {
     /// Read system CPU usage from /proc/stat
-    fn update(&mut self) -> (f64, bool) {
+    pub(crate) fn update(&mut self) -> (f64, bool) {
         let now_us = std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
             .map(|d| d.as_micros() as u64)
@@ -236,28 +545,181 @@ impl SystemCpuTracker {
 }
 
 impl Rapl {
+    /// Read powercap under `rapl_path`. Without a custom path, the perf PMU
+    /// is read instead when none of the sysfs counters is readable.
     pub fn new(rapl_path: Option<String>) -> Self {
+        Self::with_backend(rapl_path, RaplBackend::Auto)
+    }
+
+    /// Read the counters of `backend`; `rapl_path` is the powercap
+    /// directory.
+    pub fn with_backend(rapl_path: Option<String>, backend: RaplBackend) -> Self {
+        // Only the system powercap tree falls back to perf_event; a custom
+        // directory, such as a test fixture, is read as given.
+        let custom_path = rapl_path.is_some();
         let rapl_dir = rapl_path.unwrap_or_else(|| "/sys/class/powercap".to_string());
-        let (socket_readers, dram_readers, psys_reader) = Self::scan_powercap_entries(&rapl_dir);
+        let perf_readers = match backend {
+            RaplBackend::Powercap => None,
+            RaplBackend::Auto
+                if custom_path
+                    || Self::powercap_has_readable_rapl_counter(Path::new(&rapl_dir)) =>
+            {
+                None
+            }
+            RaplBackend::Auto | RaplBackend::PerfEvent => match probe_perf_readers() {
+                Ok(readers) => Some(readers),
+                Err(detail) => {
+                    log::debug!("RAPL perf_event counters unavailable: {detail}");
+                    (backend == RaplBackend::PerfEvent).then(RaplReaders::default)
+                }
+            },
+        };
+        match perf_readers {
+            Some(readers) => {
+                log::info!(
+                    "Reading RAPL through perf_event ({} packages)",
+                    readers.package_count()
+                );
+                let mut rapl = Self::from_readers(readers, "rapl");
+                rapl.rescan = Some(Rescan::PerfEvent);
+                rapl
+            }
+            None => {
+                let mut rapl = Self::from_readers(Self::scan_powercap_entries(&rapl_dir), "rapl");
+                rapl.rescan = Some(Rescan::Powercap(rapl_dir));
+                rapl
+            }
+        }
+    }
 
+    /// Build a collector from readers discovered by any CPU energy backend.
+    /// Attribution is identical regardless of where the counters come from.
+    pub(crate) fn from_readers(readers: RaplReaders, device_prefix: &'static str) -> Self {
         // Initialize CPU trackers with a warmup call
         let mut system_cpu_tracker = SystemCpuTracker::default();
         system_cpu_tracker.update(); // First call establishes baseline
 
+        let tracked_pids = Arc::new(Mutex::new(Vec::new()));
+        let cpu_to_socket = cpu_package_map(Path::new("/sys/devices/system/cpu"));
+        let socket_time = SocketCpuTime::new(cpu_to_socket.clone());
+        let placement = SocketPlacement::new(cpu_to_socket);
+        let sample_placement = readers.socket_readers.len() > 1 && placement.is_multi_socket();
+        let placement = Arc::new(Mutex::new(placement));
+        if sample_placement {
+            spawn_placement_sampler(Arc::downgrade(&placement), Arc::downgrade(&tracked_pids));
+        }
+
         Self {
-            socket_readers,
-            dram_readers,
-            psys_reader,
-            tracked_pids: Arc::new(Mutex::new(Vec::new())),
-            cpu_count: logical_cpu_count(),
+            readers: RwLock::new(Arc::new(readers)),
+            rescan: None,
+            tracked_pids,
+            tracked_cgroups: Mutex::new(Vec::new()),
+            cpu_count: RwLock::new(logical_cpu_count()),
             total_memory_bytes: read_total_memory_bytes(),
             cpu_trackers: Mutex::new(std::collections::HashMap::new()),
+            cgroup_trackers: Mutex::new(HashMap::new()),
             system_cpu_tracker: Mutex::new(system_cpu_tracker),
+            device_prefix,
+            placement,
+            attribution: CpuAttribution::default(),
+            strategies: ClassStrategies::default(),
+            socket_time: Mutex::new(socket_time),
+            read_schedule: Mutex::new(ReadSchedule::default()),
+            read_failures: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Split package energy among processes by `attribution`.
+    pub fn with_attribution(mut self, attribution: CpuAttribution) -> Self {
+        self.attribution = attribution;
+        self
+    }
+
+    /// Split the energy of `class` domains among processes by `strategy`.
+    pub fn with_attribution_strategy(
+        mut self,
+        class: DeviceClass,
+        strategy: Arc<dyn AttributionStrategy>,
+    ) -> Self {
+        self.strategies.set(class, strategy);
+        self
+    }
+
+    /// Records of `energy` read from `device` at `time`: one per tracked
+    /// process by the strategy of its class, and the unattributed rest.
+    fn attributed_records(
+        &self,
+        device: &str,
+        energy: f64,
+        usage: &ProcessUsage,
+        time: RecordTime,
+        raw_counter: Option<f64>,
+    ) -> Vec<EnergyRecord> {
+        let (split, unattributed) = self.strategies.split(energy, usage);
+        let record = |pid, energy| EnergyRecord {
+            pid,
+            timestamp_us: time.timestamp_us,
+            monotonic_ns: time.monotonic_ns,
+            device: device.to_string(),
+            energy,
+            raw_counter,
+        };
+        let mut records: Vec<EnergyRecord> = split
+            .into_iter()
+            .map(|(pid, energy)| record(pid, energy))
+            .collect();
+        if unattributed > 0.0 {
+            records.push(record(UNATTRIBUTED_PID, unattributed));
         }
+        records
+    }
+
+    /// The current reader set.
+    fn readers(&self) -> Arc<RaplReaders> {
+        Arc::clone(&self.readers.read().unwrap())
+    }
+
+    /// Re-read the logical CPU count used to normalize process utilization.
+    pub(crate) fn refresh_cpu_count(&self) {
+        let cpu_count = logical_cpu_count();
+        let previous_cpu_count =
+            std::mem::replace(&mut *self.cpu_count.write().unwrap(), cpu_count);
+        if cpu_count != previous_cpu_count {
+            log::info!("Logical CPU count changed from {previous_cpu_count} to {cpu_count}");
+        }
+    }
+
+    /// Replace the reader set after rediscovery and refresh the CPU count.
+    /// Returns whether the set of counters changed; readers of counters that
+    /// are still present keep their baselines.
+    pub(crate) fn replace_readers(&self, mut readers: RaplReaders) -> bool {
+        self.refresh_cpu_count();
+
+        let mut current = self.readers.write().unwrap();
+        if readers.labels() == current.labels() {
+            if readers.skipped != current.skipped {
+                let mut updated = RaplReaders::clone(&current);
+                updated.skipped = readers.skipped;
+                *current = Arc::new(updated);
+            }
+            return false;
+        }
+        log::info!(
+            "RAPL domains changed: {} -> {} sockets, {} -> {} DRAM domains",
+            current.socket_readers.len(),
+            readers.socket_readers.len(),
+            current.dram_readers.len(),
+            readers.dram_readers.len()
+        );
+        readers.adopt_baselines(&current);
+        *current = Arc::new(readers);
+        self.read_failures.lock().unwrap().clear();
+        true
     }
 
     pub fn device_sources(&self) -> DeviceSources {
-        let has_package_reader = self
+        let readers = self.readers();
+        let has_package_reader = readers
             .socket_readers
             .iter()
             .any(|socket| socket.package_reader.is_some());
@@ -268,7 +730,7 @@ impl Rapl {
             } else {
                 DeviceSource::Unavailable
             },
-            dram: if !self.dram_readers.is_empty() {
+            dram: if !readers.dram_readers.is_empty() {
                 DeviceSource::Measured
             } else if has_package_reader {
                 DeviceSource::IncludedInPackage
@@ -280,22 +742,36 @@ impl Rapl {
     }
 
     /// Discovers all RAPL sockets and their energy components in a single pass.
-    /// Returns socket readers, all separate DRAM readers, and an optional PSYS reader.
-    fn scan_powercap_entries(
-        rapl_dir: &str,
-    ) -> (Vec<SocketReaders>, Vec<DeltaReader>, Option<DeltaReader>) {
+    /// `intel-rapl-mmio` zones measure the same domains as the MSR-backed
+    /// `intel-rapl` ones, so each domain keeps the counter [`DeltaReader::prefer`]
+    /// picks. Zones with a malformed name, a missing or empty `name` file, or an
+    /// energy counter that cannot be read are skipped and listed in
+    /// [`RaplReaders::skipped`], so one bad zone does not hide the others.
+    pub(crate) fn scan_powercap_entries(rapl_dir: &str) -> RaplReaders {
         let mut socket_map: BTreeMap<u32, SocketReaders> = BTreeMap::new();
-        let mut dram_readers: Vec<DeltaReader> = Vec::new();
-        let mut psys_reader: Option<DeltaReader> = None;
+        let mut socket_dram: BTreeMap<u32, Option<DeltaReader>> = BTreeMap::new();
+        let mut readers = RaplReaders::default();
+        let mut skipped = Vec::new();
 
         let Ok(entries) = fs::read_dir(rapl_dir) else {
             warn!("Failed to read RAPL directory: {}", rapl_dir);
-            return (Vec::new(), Vec::new(), None);
+            return readers;
         };
 
         for entry in entries.flatten() {
             let path = entry.path();
+            let mut skip = |error: String| {
+                warn!("Skipping RAPL zone {}: {}", path.display(), error);
+                skipped.push(DegradedDomain {
+                    domain: path.display().to_string(),
+                    error,
+                    failed_reads: 0,
+                });
+            };
             let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+                if entry.file_name().to_string_lossy().contains("rapl") {
+                    skip("zone name is not valid UTF-8".to_string());
+                }
                 continue;
             };
 
@@ -304,85 +780,102 @@ impl Rapl {
                 continue;
             }
 
-            // Handle PSYS (system-wide power) separately
-            if name.contains("psys") {
-                if energy_counter_is_readable(&path.join("energy_uj")) {
-                    psys_reader = Some(DeltaReader::new(path.clone()));
+            // Handle PSYS (system-wide power) separately; it is a top-level
+            // zone like the packages, told apart by its name file
+            let colon_count = name.matches(':').count();
+            if name.contains("psys")
+                || (colon_count == 1 && Self::component_name(&path).as_deref() == Some("psys"))
+            {
+                match Self::parse_component(&path) {
+                    Ok(reader) => DeltaReader::prefer(&mut readers.psys_reader, reader),
+                    Err(error) => skip(error),
                 }
                 continue;
             }
 
-            let colon_count = name.matches(':').count();
-
             match colon_count {
                 // Socket-level entry: rapl:N (package energy at root level)
                 1 => {
-                    if let Some(socket_id) = Self::parse_socket_id(name) {
-                        // Check if this socket has readable energy_uj (package energy)
-                        if energy_counter_is_readable(&path.join("energy_uj")) {
-                            let package_reader = DeltaReader::new(path.clone());
-
-                            // Insert or update socket with package reader
-                            socket_map
-                                .entry(socket_id)
-                                .and_modify(|socket| {
-                                    socket.package_reader = Some(package_reader.clone());
-                                })
-                                .or_insert(SocketReaders {
-                                    socket_id,
-                                    package_reader: Some(package_reader),
-                                    core_reader: None,
-                                    uncore_reader: None,
-                                });
+                    let Some(socket_id) = Self::parse_socket_id(name) else {
+                        skip(format!("malformed zone name '{}'", name));
+                        continue;
+                    };
+                    // Check if this socket has readable energy_uj (package energy)
+                    let package_reader = match Self::parse_component(&path) {
+                        Ok(reader) => reader,
+                        Err(error) => {
+                            skip(error);
+                            continue;
                         }
-                    }
+                    };
+
+                    // Insert or update socket with package reader
+                    let socket = socket_map
+                        .entry(socket_id)
+                        .or_insert_with(|| SocketReaders {
+                            socket_id,
+                            package_reader: None,
+                            core_reader: None,
+                            uncore_reader: None,
+                        });
+                    DeltaReader::prefer(&mut socket.package_reader, package_reader);
                 }
                 // Component-level entry: rapl:N:M (core, uncore, etc.)
                 2 => {
-                    if let Some(reader) = Self::parse_component(&path, name) {
-                        let Some(component_name) = Self::component_name(&path) else {
-                            continue;
-                        };
-
-                        if Self::is_dram_component(&component_name) {
-                            dram_readers.push(reader);
+                    let Some(component_name) = Self::component_name(&path) else {
+                        skip("missing or empty name file".to_string());
+                        continue;
+                    };
+                    let reader = match Self::parse_component(&path) {
+                        Ok(reader) => reader,
+                        Err(error) => {
+                            skip(error);
                             continue;
                         }
+                    };
 
-                        if let Some(socket_id) = Self::parse_socket_id(name) {
-                            // Ensure socket exists before assigning component
-                            // Use or_insert_with to avoid overwriting existing entry
-                            socket_map
-                                .entry(socket_id)
-                                .or_insert_with(|| SocketReaders {
-                                    socket_id,
-                                    package_reader: None,
-                                    core_reader: None,
-                                    uncore_reader: None,
-                                });
-                            // Now get mutable reference and assign
-                            if let Some(socket) = socket_map.get_mut(&socket_id) {
-                                Self::assign_socket_component(socket, reader, &component_name);
-                            }
-                        } else {
-                            // System-level component (e.g., DRAM without socket association)
-                            Self::assign_system_component(
-                                &mut dram_readers,
+                    if Self::is_dram_component(&component_name) {
+                        match Self::parse_socket_id(name) {
+                            Some(socket_id) => DeltaReader::prefer(
+                                socket_dram.entry(socket_id).or_default(),
                                 reader,
-                                &component_name,
-                            );
+                            ),
+                            None => readers.dram_readers.push(reader),
                         }
+                        continue;
+                    }
+
+                    if let Some(socket_id) = Self::parse_socket_id(name) {
+                        // Ensure socket exists before assigning component
+                        // Use or_insert_with to avoid overwriting existing entry
+                        let socket = socket_map
+                            .entry(socket_id)
+                            .or_insert_with(|| SocketReaders {
+                                socket_id,
+                                package_reader: None,
+                                core_reader: None,
+                                uncore_reader: None,
+                            });
+                        Self::assign_socket_component(socket, reader, &component_name);
+                    } else {
+                        // System-level component (e.g., DRAM without socket association)
+                        Self::assign_system_component(
+                            &mut readers.dram_readers,
+                            reader,
+                            &component_name,
+                        );
                     }
                 }
                 _ => continue,
             }
         }
 
-        (
-            socket_map.into_values().collect(),
-            dram_readers,
-            psys_reader,
-        )
+        readers.socket_readers = socket_map.into_values().collect();
+        readers
+            .dram_readers
+            .extend(socket_dram.into_values().flatten());
+        readers.skipped = skipped;
+        readers
     }
 
     /// Extracts socket ID from RAPL socket entry name (e.g., "intel-rapl:0" -> 0)
@@ -390,20 +883,18 @@ impl Rapl {
         name.split(':').nth(1)?.parse().ok()
     }
 
-    /// Parses a component entry and returns a delta reader if valid
-    fn parse_component(path: &Path, _name: &str) -> Option<DeltaReader> {
-        // Verify energy_uj is readable, not just present.
-        if !energy_counter_is_readable(&path.join("energy_uj")) {
-            return None;
-        }
-
-        Some(DeltaReader::new(path.to_path_buf()))
+    /// Returns a delta reader for a zone whose energy counter reads as a
+    /// number, not just one that is present
+    fn parse_component(path: &Path) -> Result<DeltaReader, String> {
+        read_energy_file(&path.join("energy_uj")).map_err(|e| e.to_string())?;
+        Ok(DeltaReader::new(path.to_path_buf()))
     }
 
     fn component_name(path: &Path) -> Option<String> {
         fs::read_to_string(path.join("name"))
             .ok()
             .map(|name| name.trim().to_lowercase())
+            .filter(|name| !name.is_empty())
     }
 
     fn is_dram_component(component_name: &str) -> bool {
@@ -426,11 +917,11 @@ impl Rapl {
         // Note: package energy is at the socket root level, not here
         match component_name {
             "core" | "cores" => {
-                socket.core_reader = Some(reader);
+                DeltaReader::prefer(&mut socket.core_reader, reader);
                 log::debug!("Assigned core reader to socket {}", socket.socket_id);
             }
             "uncore" => {
-                socket.uncore_reader = Some(reader);
+                DeltaReader::prefer(&mut socket.uncore_reader, reader);
                 log::debug!("Assigned uncore reader to socket {}", socket.socket_id);
             }
             _ => {
@@ -462,7 +953,7 @@ impl Rapl {
         }
     }
 
-    fn powercap_has_readable_rapl_counter(root: &Path) -> bool {
+    pub(crate) fn powercap_has_readable_rapl_counter(root: &Path) -> bool {
         fs::read_dir(root)
             .ok()
             .map(|entries| {
@@ -471,41 +962,66 @@ impl Rapl {
                         .file_name()
                         .to_str()
                         .is_some_and(|name| name.contains("rapl"));
-                    name_matches && energy_counter_is_readable(&entry.path().join("energy_uj"))
+                    name_matches && read_energy_file(&entry.path().join("energy_uj")).is_ok()
                 })
             })
             .unwrap_or(false)
     }
 
+    /// Read one domain's energy delta, or `None` when the read fails so the
+    /// domain is skipped this tick instead of reporting zero.
+    fn read_domain(&self, reader: &DeltaReader, device: &str) -> Option<f64> {
+        let result = reader.read_delta();
+        self.record_read(device, result.as_ref().err().cloned());
+        result.ok()
+    }
+
+    /// Track the outcome of reading `device`: a failure is kept for
+    /// [`EnergyCollector::degraded_domains`] until a read succeeds again.
+    fn record_read(&self, device: &str, error: Option<CollectorError>) {
+        let mut failures = self.read_failures.lock().unwrap();
+        let Some(error) = error else {
+            if let Some(failure) = failures.remove(device) {
+                log::info!(
+                    "{} readable again after {} failed reads",
+                    device,
+                    failure.failed_reads
+                );
+            }
+            return;
+        };
+        let failure = failures
+            .entry(device.to_string())
+            .or_insert_with(|| DegradedDomain {
+                domain: device.to_string(),
+                error: String::new(),
+                failed_reads: 0,
+            });
+        if failure.failed_reads == 0 {
+            warn!("Skipping {} until it can be read: {}", device, error);
+        }
+        failure.failed_reads += 1;
+        failure.error = error.to_string();
+    }
+
     /// Calculate per-process utilization metrics (CPU and memory)
     /// Returns a tuple of (cpu_utilization, memory_utilization) for each tracked PID
     /// CPU utilization is normalized relative to system usage (matching Python EMT formula)
     /// Memory utilization is normalized relative to total process memory usage
-    fn get_utilization(
-        &self,
-        pids: &[u32],
-    ) -> Result<(UtilizationSeries, UtilizationSeries), String> {
+    fn get_utilization(&self, pids: &[u32]) -> (UtilizationSeries, UtilizationSeries) {
         // Get system CPU using our custom tracker (reads from /proc/stat)
         let (system_cpu, sys_valid) = {
-            let mut tracker = self
-                .system_cpu_tracker
-                .lock()
-                .map_err(|e| format!("Failed to lock system CPU tracker: {}", e))?;
+            let mut tracker = self.system_cpu_tracker.lock().unwrap();
             tracker.update()
         };
 
         // Get per-process CPU using custom trackers (reads from /proc/<pid>/stat)
         let mut process_cpus: Vec<(u32, f64)> = Vec::new();
         {
-            let mut trackers = self
-                .cpu_trackers
-                .lock()
-                .map_err(|e| format!("Failed to lock CPU trackers: {}", e))?;
+            let mut trackers = self.cpu_trackers.lock().unwrap();
 
             for &pid in pids {
-                let tracker = trackers
-                    .entry(pid)
-                    .or_insert_with(ProcessCpuTracker::default);
+                let tracker = trackers.entry(pid).or_default();
                 let (cpu_percent, is_valid) = tracker.update(pid);
                 // Only use valid readings (not the first call which establishes baseline)
                 let effective_cpu = if is_valid { cpu_percent } else { 0.0 };
@@ -530,7 +1046,7 @@ impl Rapl {
             pids.len()
         );
 
-        let cpu_count = self.cpu_count.max(1.0);
+        let cpu_count = self.cpu_count.read().unwrap().max(1.0);
         let total_memory = self.total_memory_bytes;
 
         // Calculate per-process memory utilization
@@ -585,10 +1101,108 @@ impl Rapl {
             .collect();
         let normalized_memory = normalize_fraction_budget(normalized_memory);
 
-        Ok((normalized_cpus, normalized_memory))
+        (normalized_cpus, normalized_memory)
+    }
+
+    /// Utilization of tracked cgroups, keyed by their pids, from `cpu.stat`
+    /// and `memory.current` and normalized like
+    /// [`get_utilization`](Self::get_utilization).
+    fn get_cgroup_utilization(
+        &self,
+        cgroups: &[TrackedCgroup],
+    ) -> (UtilizationSeries, UtilizationSeries) {
+        let (system_cpu, _) = self.system_cpu_tracker.lock().unwrap().update();
+        let cpu_count = self.cpu_count.read().unwrap().max(1.0);
+
+        let mut cpu = Vec::with_capacity(cgroups.len());
+        {
+            let mut trackers = self.cgroup_trackers.lock().unwrap();
+            trackers.retain(|path, _| cgroups.iter().any(|cgroup| cgroup.target.path() == path));
+            for cgroup in cgroups {
+                let cpu_percent = trackers
+                    .entry(cgroup.target.path().to_path_buf())
+                    .or_default()
+                    .update(&cgroup.target)
+                    .unwrap_or(0.0);
+                let normalized = if system_cpu > 0.0 {
+                    (cpu_percent / cpu_count / system_cpu).min(1.0)
+                } else {
+                    0.0
+                };
+                cpu.push((cgroup.pid, normalized));
+            }
+        }
+
+        let memory: Vec<(u32, f64)> = cgroups
+            .iter()
+            .map(|cgroup| {
+                let bytes = cgroup.target.memory_bytes().unwrap_or(0);
+                (cgroup.pid, bytes as f64)
+            })
+            .collect();
+        let total_memory: f64 = memory.iter().map(|(_, bytes)| bytes).sum();
+        let memory = memory
+            .into_iter()
+            .map(|(pid, bytes)| {
+                let normalized = if total_memory > 0.0 {
+                    bytes / total_memory
+                } else {
+                    0.0
+                };
+                (pid, normalized)
+            })
+            .collect();
+
+        (
+            normalize_fraction_budget(cpu),
+            normalize_fraction_budget(memory),
+        )
+    }
+}
+
+/// Sample tracked PIDs' last-run CPU in the background until the collector is
+/// dropped, so migrations between energy reads show up in the visit history.
+fn spawn_placement_sampler(placement: Weak<Mutex<SocketPlacement>>, pids: Weak<Mutex<Vec<u32>>>) {
+    let spawned = std::thread::Builder::new()
+        .name("emt-placement".to_string())
+        .spawn(move || {
+            loop {
+                std::thread::sleep(PLACEMENT_SAMPLE_INTERVAL);
+                let (Some(placement), Some(pids)) = (placement.upgrade(), pids.upgrade()) else {
+                    break;
+                };
+                let pids = pids.lock().unwrap().clone();
+                placement.lock().unwrap().sample(&pids);
+            }
+        });
+    if let Err(e) = spawned {
+        warn!("Failed to start socket placement sampler: {}", e);
     }
 }
 
+/// Per-PID attribution fractions for one socket.
+///
+/// A PID's system-wide CPU ratio is scaled by how much of its time it spent on
+/// `socket_id` relative to an even spread, then capped to the socket's budget.
+/// PIDs without placement history keep the even spread (scale 1).
+fn socket_cpu_fractions(
+    cpu_ratios: &[(u32, f64)],
+    socket_weights: &std::collections::HashMap<u32, BTreeMap<u32, f64>>,
+    socket_id: u32,
+    socket_count: usize,
+) -> UtilizationSeries {
+    let fractions = cpu_ratios
+        .iter()
+        .map(|&(pid, ratio)| {
+            let scale = socket_weights.get(&pid).map_or(1.0, |weights| {
+                weights.get(&socket_id).copied().unwrap_or(0.0) * socket_count as f64
+            });
+            (pid, ratio * scale)
+        })
+        .collect();
+    normalize_fraction_budget(fractions)
+}
+
 fn normalize_fraction_budget(series: UtilizationSeries) -> UtilizationSeries {
     let total: f64 = series.iter().map(|(_, value)| *value).sum();
     if total <= 1.0 || total <= f64::EPSILON {
@@ -628,6 +1242,17 @@ fn parse_memtotal_bytes(contents: &str) -> Option<u64> {
     })
 }
 
+/// Extract the CPU vendor and model name from `/proc/cpuinfo` contents.
+fn parse_cpu_identity(contents: &str) -> (Option<String>, Option<String>) {
+    let field = |key: &str| {
+        contents.lines().find_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            (name.trim() == key).then(|| value.trim().to_string())
+        })
+    };
+    (field("vendor_id"), field("model name"))
+}
+
 fn read_process_rss_bytes(pid: u32) -> u64 {
     fs::read_to_string(format!("/proc/{pid}/statm"))
         .ok()
@@ -656,12 +1281,28 @@ impl EnergyCollector for Rapl {
         *self.tracked_pids.lock().unwrap() = pids;
     }
 
-    async fn get_energy_trace(&self) -> Result<Vec<EnergyRecord>, String> {
-        let timestamp = Utc::now().timestamp_millis();
+    fn set_tracked_cgroups(&self, cgroups: Vec<TrackedCgroup>) {
+        *self.tracked_cgroups.lock().unwrap() = cgroups;
+    }
+
+    fn set_read_schedule(&self, schedule: ReadSchedule) {
+        *self.read_schedule.lock().unwrap() = schedule;
+    }
+
+    async fn get_energy_trace(&self) -> Result<Vec<EnergyRecord>, CollectorError> {
+        let tick_start = Instant::now();
+        let schedule = self.read_schedule.lock().unwrap().clone();
         let mut records = Vec::new();
 
-        // Get tracked PIDs for per-process attribution
-        let pids = self.tracked_pids.lock().unwrap().clone();
+        // Get tracked PIDs for per-process attribution; tracked cgroups stand
+        // in for their processes under the pid their records carry
+        let cgroups = self.tracked_cgroups.lock().unwrap().clone();
+        let pids = if cgroups.is_empty() {
+            self.tracked_pids.lock().unwrap().clone()
+        } else {
+            cgroups.iter().map(|cgroup| cgroup.pid).collect()
+        };
+        let readers = self.readers();
 
         if pids.is_empty() {
             // No tracked PIDs, skip producing records
@@ -671,15 +1312,40 @@ impl EnergyCollector for Rapl {
 
         log::debug!(
             "RAPL: Processing {} sockets with {} tracked PIDs",
-            self.socket_readers.len(),
+            readers.socket_readers.len(),
             pids.len()
         );
 
         // Calculate per-process utilization
-        let (cpu_utilization_ratio, memory_utilization_ratio) = self.get_utilization(&pids)?;
+        let (cpu_utilization_ratio, memory_utilization_ratio) = if cgroups.is_empty() {
+            self.get_utilization(&pids)
+        } else {
+            self.get_cgroup_utilization(&cgroups)
+        };
+
+        // Where each process ran since the last read, for per-socket attribution;
+        // cgroups are spread evenly
+        let socket_weights = if readers.socket_readers.len() > 1 && cgroups.is_empty() {
+            let mut placement = self.placement.lock().unwrap();
+            placement.sample(&pids);
+            placement.take_weights()
+        } else {
+            std::collections::HashMap::new()
+        };
+        // Each process's share of each socket's busy time, for per-core
+        // attribution; system attribution when the topology is unknown
+        let mut per_core_fractions =
+            if self.attribution == CpuAttribution::PerCore && cgroups.is_empty() {
+                self.socket_time
+                    .lock()
+                    .unwrap()
+                    .update(&pids, &socket_weights)
+            } else {
+                None
+            };
 
         // Collect per-socket energy readings
-        for socket in &self.socket_readers {
+        for socket in &readers.socket_readers {
             let socket_id = socket.socket_id;
 
             log::debug!(
@@ -691,17 +1357,13 @@ impl EnergyCollector for Rapl {
             );
 
             // Read package energy for this socket (total socket energy)
-            let package_energy = if let Some(reader) = &socket.package_reader {
-                reader.read_delta().unwrap_or_else(|e| {
-                    warn!(
-                        "Failed to read package energy for socket {}: {}",
-                        socket_id, e
-                    );
-                    0.0
-                })
-            } else {
-                0.0
-            };
+            let package_device = format!("{}:socket:{}:package", self.device_prefix, socket_id);
+            schedule.wait(tick_start, &package_device).await;
+            let package_energy = socket
+                .package_reader
+                .as_ref()
+                .and_then(|reader| self.read_domain(reader, &package_device));
+            let package_time = RecordTime::now();
 
             // Read core energy for this socket (PP0: cores + L1/L2)
             // Currently unused but read for debugging purposes
@@ -728,167 +1390,232 @@ impl EnergyCollector for Rapl {
                 0.0
             };
 
-            // Attribute energy to each tracked PID based on utilization
-            // NOTE: Package energy is the total socket energy and already includes core energy.
-            // We only attribute package energy to avoid double counting.
-            // Core and uncore are recorded separately for detailed breakdown but not summed into total.
-            let mut attributed_package_energy = 0.0;
-            for &pid in &pids {
-                let normalized_cpu = cpu_utilization_ratio
-                    .iter()
-                    .find(|(p, _)| *p == pid)
-                    .map(|(_, u)| *u)
-                    .unwrap_or(0.0);
+            let package_counter = socket
+                .package_reader
+                .as_ref()
+                .and_then(DeltaReader::counter_joules);
 
-                // Package energy (total socket) - this is the main energy attribution
-                // Package = Core + Uncore, so we only count package to avoid double counting
-                if socket.package_reader.is_some() {
-                    let package_attribution = package_energy * normalized_cpu;
-                    attributed_package_energy += package_attribution;
-                    log::trace!(
-                        "PID {} socket {}: package_energy={:.4}J × normalized_cpu={:.4} = {:.4}J",
-                        pid,
-                        socket_id,
-                        package_energy,
-                        normalized_cpu,
-                        package_attribution
-                    );
-                    records.push(EnergyRecord {
-                        pid,
-                        timestamp,
-                        device: format!("rapl:socket:{}:package", socket_id),
-                        energy: package_attribution,
-                    });
+            let socket_cpu_ratio = match &mut per_core_fractions {
+                Some(fractions) => {
+                    normalize_fraction_budget(fractions.remove(&socket_id).unwrap_or_default())
                 }
-            }
+                None => socket_cpu_fractions(
+                    &cpu_utilization_ratio,
+                    &socket_weights,
+                    socket_id,
+                    readers.socket_readers.len(),
+                ),
+            };
 
-            if socket.package_reader.is_some() {
-                let unattributed_package_energy =
-                    (package_energy - attributed_package_energy).max(0.0);
-                if unattributed_package_energy > 0.0 {
-                    records.push(EnergyRecord {
-                        pid: UNATTRIBUTED_PID,
-                        timestamp,
-                        device: format!("rapl:socket:{}:package", socket_id),
-                        energy: unattributed_package_energy,
-                    });
-                }
+            // Package energy is the total socket energy and already includes
+            // core and uncore, so only the package is attributed.
+            if let Some(package_energy) = package_energy {
+                let usage = ProcessUsage {
+                    class: DeviceClass::Package,
+                    socket: Some(socket_id),
+                    pids: &pids,
+                    cpu: &socket_cpu_ratio,
+                    memory: &memory_utilization_ratio,
+                };
+                records.extend(self.attributed_records(
+                    &package_device,
+                    package_energy,
+                    &usage,
+                    package_time,
+                    package_counter,
+                ));
             }
         }
 
         // Collect system-level energy readings (DRAM and PSYS)
         log::debug!(
             "System: dram={}, psys={}",
-            !self.dram_readers.is_empty(),
-            self.psys_reader.is_some()
+            !readers.dram_readers.is_empty(),
+            readers.psys_reader.is_some()
         );
 
         // Read separately measured DRAM energy from every discovered DRAM domain.
-        let dram_energy = self
-            .dram_readers
-            .iter()
-            .map(|reader| {
-                reader.read_delta().unwrap_or_else(|e| {
-                    warn!("Failed to read DRAM energy: {}", e);
-                    0.0
-                })
-            })
-            .sum::<f64>();
+        let dram_device = format!("{}:system:dram", self.device_prefix);
+        if !readers.dram_readers.is_empty() {
+            schedule.wait(tick_start, &dram_device).await;
+        }
+        // A domain that fails is left out of this tick; its counter keeps its
+        // baseline, so the energy is reported once it reads again.
+        let mut dram_energy = None;
+        let mut dram_error = None;
+        for reader in &readers.dram_readers {
+            match reader.read_delta() {
+                Ok(joules) => *dram_energy.get_or_insert(0.0) += joules,
+                Err(e) => dram_error = Some(e),
+            }
+        }
+        self.record_read(&dram_device, dram_error);
+        let dram_time = RecordTime::now();
 
         // Read PSYS energy (platform/system-wide)
-        let psys_energy = if let Some(reader) = &self.psys_reader {
-            reader.read_delta().unwrap_or_else(|e| {
-                warn!("Failed to read PSYS energy: {}", e);
-                0.0
-            })
-        } else {
-            0.0
-        };
-
-        // Attribute system-level energy to tracked PIDs
-        let mut attributed_dram_energy = 0.0;
-        let mut attributed_psys_energy = 0.0;
-        for &pid in &pids {
-            let normalized_mem = memory_utilization_ratio
-                .iter()
-                .find(|(p, _)| *p == pid)
-                .map(|(_, u)| *u)
-                .unwrap_or(0.0);
-
-            // DRAM energy attributed by memory usage
-            if !self.dram_readers.is_empty() {
-                let dram_attribution = dram_energy * normalized_mem;
-                attributed_dram_energy += dram_attribution;
-                records.push(EnergyRecord {
-                    pid,
-                    timestamp,
-                    device: "rapl:system:dram".to_string(),
-                    energy: dram_attribution,
-                });
+        let psys_device = format!("{}:system:psys", self.device_prefix);
+        let psys_energy = match &readers.psys_reader {
+            Some(reader) => {
+                schedule.wait(tick_start, &psys_device).await;
+                self.read_domain(reader, &psys_device)
             }
+            None => None,
+        };
+        let psys_time = RecordTime::now();
 
-            // PSYS energy distributed equally among processes
-            if self.psys_reader.is_some() {
-                let psys_attribution = psys_energy / pids.len() as f64;
-                attributed_psys_energy += psys_attribution;
-                records.push(EnergyRecord {
-                    pid,
-                    timestamp,
-                    device: "rapl:system:psys".to_string(),
-                    energy: psys_attribution,
-                });
-            }
-        }
+        // Raw readings: DRAM domains summed, when every one has been read
+        let dram_counter = readers
+            .dram_readers
+            .iter()
+            .map(DeltaReader::counter_joules)
+            .sum::<Option<f64>>();
+        let psys_counter = readers
+            .psys_reader
+            .as_ref()
+            .and_then(DeltaReader::counter_joules);
 
-        if !self.dram_readers.is_empty() {
-            let unattributed_dram_energy = (dram_energy - attributed_dram_energy).max(0.0);
-            if unattributed_dram_energy > 0.0 {
-                records.push(EnergyRecord {
-                    pid: UNATTRIBUTED_PID,
-                    timestamp,
-                    device: "rapl:system:dram".to_string(),
-                    energy: unattributed_dram_energy,
-                });
-            }
+        // Attribute system-level energy to tracked PIDs
+        let usage = |class| ProcessUsage {
+            class,
+            socket: None,
+            pids: &pids,
+            cpu: &cpu_utilization_ratio,
+            memory: &memory_utilization_ratio,
+        };
+        if let Some(dram_energy) = dram_energy {
+            records.extend(self.attributed_records(
+                &dram_device,
+                dram_energy,
+                &usage(DeviceClass::Dram),
+                dram_time,
+                dram_counter,
+            ));
         }
-        if self.psys_reader.is_some() {
-            let unattributed_psys_energy = (psys_energy - attributed_psys_energy).max(0.0);
-            if unattributed_psys_energy > 0.0 {
-                records.push(EnergyRecord {
-                    pid: UNATTRIBUTED_PID,
-                    timestamp,
-                    device: "rapl:system:psys".to_string(),
-                    energy: unattributed_psys_energy,
-                });
-            }
+        if let Some(psys_energy) = psys_energy {
+            records.extend(self.attributed_records(
+                &psys_device,
+                psys_energy,
+                &usage(DeviceClass::Psys),
+                psys_time,
+                psys_counter,
+            ));
         }
 
         log::debug!(
             "RAPL energy trace collected: {} records for {} processes across {} sockets",
             records.len(),
             pids.len(),
-            self.socket_readers.len()
+            readers.socket_readers.len()
         );
         Ok(records)
     }
 
+    fn devices(&self) -> Vec<DeviceInfo> {
+        let (vendor, model) = fs::read_to_string("/proc/cpuinfo")
+            .map(|contents| parse_cpu_identity(&contents))
+            .unwrap_or_default();
+        let cpu_device = |name: String, max_power_watts: Option<f64>, source: Option<String>| {
+            DeviceInfo::new(name)
+                .with_vendor(vendor.clone())
+                .with_model(model.clone())
+                .with_max_power_watts(max_power_watts)
+                .with_source(source)
+        };
+
+        let readers = self.readers();
+        let mut devices: Vec<DeviceInfo> = readers
+            .socket_readers
+            .iter()
+            .filter_map(|socket| {
+                let reader = socket.package_reader.as_ref()?;
+                Some(cpu_device(
+                    format!("{}:socket:{}:package", self.device_prefix, socket.socket_id),
+                    reader.max_power_watts(),
+                    reader.source(),
+                ))
+            })
+            .collect();
+        if !readers.dram_readers.is_empty() {
+            let limits: Option<Vec<f64>> = readers
+                .dram_readers
+                .iter()
+                .map(DeltaReader::max_power_watts)
+                .collect();
+            let sources: BTreeSet<String> = readers
+                .dram_readers
+                .iter()
+                .filter_map(DeltaReader::source)
+                .collect();
+            devices.push(cpu_device(
+                format!("{}:system:dram", self.device_prefix),
+                limits.map(|limits| limits.iter().sum()),
+                (!sources.is_empty()).then(|| Vec::from_iter(sources).join(",")),
+            ));
+        }
+        if let Some(reader) = &readers.psys_reader {
+            devices.push(cpu_device(
+                format!("{}:system:psys", self.device_prefix),
+                reader.max_power_watts(),
+                reader.source(),
+            ));
+        }
+        devices
+    }
+
+    fn degraded_domains(&self) -> Vec<DegradedDomain> {
+        let mut degraded = self.readers().skipped.clone();
+        degraded.extend(self.read_failures.lock().unwrap().values().cloned());
+        degraded
+    }
+
+    fn rediscover(&self) -> bool {
+        match &self.rescan {
+            Some(Rescan::Powercap(dir)) => self.replace_readers(Self::scan_powercap_entries(dir)),
+            Some(Rescan::PerfEvent) => {
+                self.replace_readers(probe_perf_readers().unwrap_or_default())
+            }
+            None => false,
+        }
+    }
+
     fn is_available() -> bool {
         Rapl::powercap_has_readable_rapl_counter(Path::new("/sys/class/powercap"))
+            || probe_perf_readers().is_ok()
     }
 }
 
-fn energy_counter_is_readable(path: &Path) -> bool {
-    fs::File::open(path)
-        .and_then(|mut file| {
-            let mut buf = [0; 1];
-            file.read(&mut buf).map(|_| ())
-        })
-        .is_ok()
+/// Package counters of the system's perf `power` PMU.
+fn probe_perf_readers() -> Result<RaplReaders, String> {
+    let paths = CpuEnergyPaths::default();
+    let readers = probe_perf_event(&paths.perf_pmu, &paths.cpu_sysfs)?;
+    if readers.package_count() == 0 {
+        return Err("no energy-pkg event".to_string());
+    }
+    Ok(readers)
+}
+
+/// Read a powercap energy counter in micro-joules. An empty file is an
+/// error rather than zero, so a failed read is never taken for no energy.
+fn read_energy_file(path: &Path) -> Result<i64, CollectorError> {
+    let content =
+        fs::read_to_string(path).map_err(|e| CollectorError::from_io(path.display(), &e))?;
+    let value = content.trim();
+    if value.is_empty() {
+        return Err(CollectorError::Parse(format!(
+            "{} is empty",
+            path.display()
+        )));
+    }
+    value
+        .parse()
+        .map_err(|e| CollectorError::Parse(format!("{}: {}", path.display(), e)))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::collectors::attribution::{CustomShare, EqualShare};
+    use crate::testing::FakePowercap;
     use std::time::{SystemTime, UNIX_EPOCH};
 
     struct TempTestDir {
@@ -969,6 +1696,62 @@ mod tests {
         assert_eq!(reader.read_delta().unwrap(), 0.0);
     }
 
+    #[test]
+    fn delta_reader_adds_the_counter_range_on_wraparound() {
+        let rapl_dir = TempTestDir::new("delta-wrap-range");
+        write_zone(&rapl_dir.path, "intel-rapl:0", "package-0");
+        let zone = rapl_dir.path.join("intel-rapl:0");
+        fs::write(zone.join("max_energy_range_uj"), "262143328850").unwrap();
+        fs::write(zone.join("energy_uj"), "262141328850").unwrap();
+        let rapl = Rapl::new(Some(rapl_dir.path.to_string_lossy().to_string()));
+        let reader = rapl.readers().socket_readers[0]
+            .package_reader
+            .clone()
+            .unwrap();
+
+        assert_eq!(reader.read_delta().unwrap(), 0.0);
+        // 2 J up to the end of the range, then 3 J from zero.
+        fs::write(zone.join("energy_uj"), "3000000").unwrap();
+        assert!((reader.read_delta().unwrap() - 5.0).abs() < 1e-9);
+        fs::write(zone.join("energy_uj"), "4000000").unwrap();
+        assert!((reader.read_delta().unwrap() - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn socket_cpu_fractions_follow_placement_history() {
+        let cpu_ratios = vec![(10, 0.4), (11, 0.4), (12, 0.2)];
+        let weights = std::collections::HashMap::from([
+            (10, BTreeMap::from([(0, 1.0)])),
+            (11, BTreeMap::from([(0, 0.25), (1, 0.75)])),
+        ]);
+
+        let socket0 = socket_cpu_fractions(&cpu_ratios, &weights, 0, 2);
+        let socket1 = socket_cpu_fractions(&cpu_ratios, &weights, 1, 2);
+
+        // Socket 0 is over budget (0.8 + 0.2 + 0.2) and gets scaled back to 1.
+        let expected0 = [(10, 0.8 / 1.2), (11, 0.2 / 1.2), (12, 0.2 / 1.2)];
+        let expected1 = [(10, 0.0), (11, 0.6), (12, 0.2)];
+        for (actual, expected) in [(socket0, expected0), (socket1, expected1)] {
+            for ((pid, fraction), (expected_pid, expected)) in actual.iter().zip(expected) {
+                assert_eq!(*pid, expected_pid);
+                assert!((fraction - expected).abs() < 1e-9, "{pid}: {fraction}");
+            }
+        }
+    }
+
+    #[test]
+    fn socket_cpu_fractions_cap_overloaded_socket() {
+        let cpu_ratios = vec![(10, 0.5), (11, 0.5)];
+        let weights = std::collections::HashMap::from([
+            (10, BTreeMap::from([(0, 1.0)])),
+            (11, BTreeMap::from([(0, 1.0)])),
+        ]);
+
+        let socket0 = socket_cpu_fractions(&cpu_ratios, &weights, 0, 2);
+
+        assert_eq!(socket0, vec![(10, 0.5), (11, 0.5)]);
+    }
+
     #[test]
     fn normalize_fraction_budget_preserves_under_budget_values() {
         let values = vec![(1, 0.25), (2, 0.5)];
@@ -996,8 +1779,12 @@ mod tests {
         write_zone(&rapl_dir.path, "intel-rapl:1", "package-1");
         write_zone(&rapl_dir.path, "intel-rapl:1:0", "core");
 
-        let (socket_readers, dram_readers, psys_reader) =
-            Rapl::scan_powercap_entries(rapl_dir.path.to_str().unwrap());
+        let RaplReaders {
+            socket_readers,
+            dram_readers,
+            psys_reader,
+            ..
+        } = Rapl::scan_powercap_entries(rapl_dir.path.to_str().unwrap());
 
         assert!(dram_readers.is_empty());
         assert!(psys_reader.is_none());
@@ -1029,8 +1816,12 @@ mod tests {
         write_zone(&rapl_dir.path, "intel-rapl:1", "package-1");
         write_zone(&rapl_dir.path, "intel-rapl:1:0", "ram");
 
-        let (socket_readers, dram_readers, psys_reader) =
-            Rapl::scan_powercap_entries(rapl_dir.path.to_str().unwrap());
+        let RaplReaders {
+            socket_readers,
+            dram_readers,
+            psys_reader,
+            ..
+        } = Rapl::scan_powercap_entries(rapl_dir.path.to_str().unwrap());
 
         assert_eq!(socket_readers.len(), 2);
         assert_eq!(dram_readers.len(), 2);
@@ -1044,14 +1835,142 @@ mod tests {
         write_unreadable_zone(&rapl_dir.path, "intel-rapl:0", "package-0");
         write_unreadable_zone(&rapl_dir.path, "intel-rapl:0:0", "dram");
 
-        let (socket_readers, dram_readers, psys_reader) =
-            Rapl::scan_powercap_entries(rapl_dir.path.to_str().unwrap());
+        let RaplReaders {
+            socket_readers,
+            dram_readers,
+            psys_reader,
+            ..
+        } = Rapl::scan_powercap_entries(rapl_dir.path.to_str().unwrap());
 
         assert!(socket_readers.is_empty());
         assert!(dram_readers.is_empty());
         assert!(psys_reader.is_none());
     }
 
+    #[test]
+    fn scan_powercap_entries_skips_malformed_zones_and_keeps_the_rest() {
+        let rapl_dir = TempTestDir::new("malformed-zones");
+
+        write_zone(&rapl_dir.path, "intel-rapl:0", "package-0");
+        write_zone(&rapl_dir.path, "intel-rapl:0:0", "dram");
+        write_zone(&rapl_dir.path, "intel-rapl:x", "package-x");
+        write_zone(&rapl_dir.path, "intel-rapl:0:1", "");
+        write_zone(&rapl_dir.path, "intel-rapl:1", "package-1");
+        fs::write(rapl_dir.path.join("intel-rapl:1/energy_uj"), "").unwrap();
+
+        let readers = Rapl::scan_powercap_entries(rapl_dir.path.to_str().unwrap());
+
+        assert_eq!(readers.package_count(), 1);
+        assert_eq!(readers.dram_readers.len(), 1);
+        let mut skipped: Vec<&str> = readers
+            .skipped
+            .iter()
+            .map(|domain| domain.domain.rsplit('/').next().unwrap())
+            .collect();
+        skipped.sort();
+        assert_eq!(skipped, ["intel-rapl:0:1", "intel-rapl:1", "intel-rapl:x"]);
+        assert!(
+            readers
+                .skipped
+                .iter()
+                .all(|domain| domain.failed_reads == 0)
+        );
+    }
+
+    #[tokio::test]
+    async fn failed_reads_skip_the_domain_until_it_reads_again() {
+        let rapl_dir = TempTestDir::new("transient-failure");
+        write_zone(&rapl_dir.path, "intel-rapl:0", "package-0");
+        write_zone(&rapl_dir.path, "intel-rapl:0:0", "dram");
+        let dram_counter = rapl_dir.path.join("intel-rapl:0:0/energy_uj");
+        let rapl = Rapl::new(Some(rapl_dir.path.to_string_lossy().to_string()));
+        rapl.set_tracked_pids(vec![std::process::id()]);
+        let dram_joules = |records: &[EnergyRecord]| -> Option<f64> {
+            let dram: Vec<f64> = records
+                .iter()
+                .filter(|record| record.device == "rapl:system:dram")
+                .map(|record| record.energy)
+                .collect();
+            (!dram.is_empty()).then(|| dram.iter().sum())
+        };
+
+        rapl.get_energy_trace().await.unwrap();
+        fs::remove_file(&dram_counter).unwrap();
+        let records = rapl.get_energy_trace().await.unwrap();
+
+        assert_eq!(dram_joules(&records), None);
+        assert!(
+            records
+                .iter()
+                .any(|record| record.device == "rapl:socket:0:package")
+        );
+        let degraded = rapl.degraded_domains();
+        assert_eq!(degraded.len(), 1);
+        assert_eq!(degraded[0].domain, "rapl:system:dram");
+        assert_eq!(degraded[0].failed_reads, 1);
+
+        // The baseline survives the failure, so no energy is lost.
+        fs::write(&dram_counter, "3000000").unwrap();
+        let records = rapl.get_energy_trace().await.unwrap();
+
+        assert!((dram_joules(&records).unwrap() - 3.0).abs() < 1e-9);
+        assert!(rapl.degraded_domains().is_empty());
+    }
+
+    #[test]
+    fn scan_powercap_entries_prefers_the_finer_of_msr_and_mmio_zones() {
+        let rapl_dir = TempTestDir::new("mmio");
+        let set_range = |entry: &str, microjoules: u64| {
+            fs::write(
+                rapl_dir.path.join(entry).join("max_energy_range_uj"),
+                microjoules.to_string(),
+            )
+            .unwrap();
+        };
+
+        // Socket 0: the MMIO zone has a 1 uJ unit, the MSR one 61 uJ.
+        write_zone(&rapl_dir.path, "intel-rapl:0", "package-0");
+        write_zone(&rapl_dir.path, "intel-rapl-mmio:0", "package-0");
+        set_range("intel-rapl:0", 262_143_328_850);
+        set_range("intel-rapl-mmio:0", 4_294_967_295);
+        // Socket 1: same unit, so the MSR zone is kept.
+        write_zone(&rapl_dir.path, "intel-rapl:1", "package-1");
+        write_zone(&rapl_dir.path, "intel-rapl-mmio:1", "package-1");
+        write_zone(&rapl_dir.path, "intel-rapl:1:0", "dram");
+        write_zone(&rapl_dir.path, "intel-rapl-mmio:1:0", "dram");
+        write_zone(&rapl_dir.path, "intel-rapl:2", "psys");
+
+        let readers = Rapl::scan_powercap_entries(rapl_dir.path.to_str().unwrap());
+
+        assert_eq!(readers.socket_readers.len(), 2);
+        assert_eq!(readers.dram_readers.len(), 1);
+        assert!(readers.psys_reader.is_some());
+        let sources: Vec<Option<String>> = readers
+            .socket_readers
+            .iter()
+            .map(|socket| socket.package_reader.as_ref().unwrap().source())
+            .collect();
+        assert_eq!(
+            sources,
+            [
+                Some("intel-rapl-mmio".to_string()),
+                Some("intel-rapl".to_string())
+            ]
+        );
+        assert_eq!(
+            readers.dram_readers[0].source().as_deref(),
+            Some("intel-rapl")
+        );
+
+        let rapl = Rapl::from_readers(readers, "rapl");
+        let package = rapl
+            .devices()
+            .into_iter()
+            .find(|device| device.name == "rapl:socket:0:package")
+            .unwrap();
+        assert_eq!(package.source.as_deref(), Some("intel-rapl-mmio"));
+    }
+
     #[test]
     fn device_sources_report_included_dram_when_only_package_is_measured() {
         let rapl_dir = TempTestDir::new("sources-package-only");
@@ -1079,6 +1998,233 @@ mod tests {
         assert_eq!(sources.gpu, DeviceSource::Unavailable);
     }
 
+    #[test]
+    fn rediscover_follows_hotplugged_sockets_and_keeps_baselines() {
+        let rapl_dir = TempTestDir::new("rediscover");
+        write_zone(&rapl_dir.path, "intel-rapl:0", "package-0");
+        let rapl = Rapl::new(Some(rapl_dir.path.to_string_lossy().to_string()));
+        let package = |rapl: &Rapl, socket: usize| {
+            rapl.readers().socket_readers[socket]
+                .package_reader
+                .clone()
+                .unwrap()
+        };
+        assert_eq!(package(&rapl, 0).read_delta().unwrap(), 0.0);
+
+        assert!(!rapl.rediscover());
+        write_zone(&rapl_dir.path, "intel-rapl:1", "package-1");
+        fs::write(rapl_dir.path.join("intel-rapl:0/energy_uj"), "5000000").unwrap();
+        assert!(rapl.rediscover());
+
+        let names: Vec<String> = rapl.devices().into_iter().map(|d| d.name).collect();
+        assert_eq!(names, ["rapl:socket:0:package", "rapl:socket:1:package"]);
+        assert_eq!(package(&rapl, 0).read_delta().unwrap(), 5.0);
+
+        fs::remove_dir_all(rapl_dir.path.join("intel-rapl:1")).unwrap();
+        assert!(rapl.rediscover());
+        assert_eq!(rapl.readers().socket_readers.len(), 1);
+    }
+
+    #[test]
+    fn an_explicit_powercap_backend_never_falls_back_to_perf_event() {
+        let rapl_dir = TempTestDir::new("powercap-only");
+        let path = Some(rapl_dir.path.to_string_lossy().to_string());
+
+        let rapl = Rapl::with_backend(path, RaplBackend::Powercap);
+
+        assert_eq!(rapl.readers().package_count(), 0);
+        assert!(matches!(rapl.rescan, Some(Rescan::Powercap(_))));
+        assert_eq!(
+            "perf_event".parse::<RaplBackend>(),
+            Ok(RaplBackend::PerfEvent)
+        );
+        assert!("msr".parse::<RaplBackend>().is_err());
+    }
+
+    #[test]
+    fn auto_reads_a_custom_powercap_directory_without_falling_back() {
+        let rapl_dir = TempTestDir::new("auto-custom");
+        let path = Some(rapl_dir.path.to_string_lossy().to_string());
+
+        let rapl = Rapl::with_backend(path, RaplBackend::Auto);
+
+        assert_eq!(rapl.readers().package_count(), 0);
+        assert!(matches!(rapl.rescan, Some(Rescan::Powercap(_))));
+    }
+
+    #[test]
+    fn devices_describe_discovered_domains_with_power_limits() {
+        let rapl_dir = TempTestDir::new("devices");
+        write_zone(&rapl_dir.path, "intel-rapl:0", "package-0");
+        write_zone(&rapl_dir.path, "intel-rapl:0:0", "dram");
+        fs::write(
+            rapl_dir.path.join("intel-rapl:0/constraint_0_max_power_uw"),
+            "165000000\n",
+        )
+        .unwrap();
+
+        let rapl = Rapl::new(Some(rapl_dir.path.to_string_lossy().to_string()));
+        let devices = rapl.devices();
+
+        assert_eq!(devices.len(), 2);
+        assert_eq!(devices[0].name, "rapl:socket:0:package");
+        assert_eq!(devices[0].socket, Some(0));
+        assert_eq!(devices[0].max_power_watts, Some(165.0));
+        assert_eq!(devices[1].name, "rapl:system:dram");
+        assert_eq!(devices[1].max_power_watts, None);
+    }
+
+    #[tokio::test]
+    async fn attributed_and_unattributed_records_add_up_to_each_domain() {
+        let rapl_dir = TempTestDir::new("strategies");
+        write_zone(&rapl_dir.path, "intel-rapl:0", "package-0");
+        write_zone(&rapl_dir.path, "intel-rapl:0:0", "dram");
+        write_zone(&rapl_dir.path, "intel-rapl:1", "psys");
+        let pid = std::process::id();
+        let rapl = Rapl::new(Some(rapl_dir.path.to_string_lossy().to_string()))
+            .with_attribution_strategy(DeviceClass::Dram, Arc::new(EqualShare))
+            .with_attribution_strategy(
+                DeviceClass::Psys,
+                Arc::new(CustomShare(move |_: &ProcessUsage| vec![(pid, 0.25)])),
+            );
+        rapl.set_tracked_pids(vec![pid]);
+        rapl.get_energy_trace().await.unwrap();
+
+        for (zone, microjoules) in [
+            ("intel-rapl:0", 6_000_000),
+            ("intel-rapl:0:0", 2_000_000),
+            ("intel-rapl:1", 12_000_000),
+        ] {
+            fs::write(
+                rapl_dir.path.join(zone).join("energy_uj"),
+                microjoules.to_string(),
+            )
+            .unwrap();
+        }
+        let records = rapl.get_energy_trace().await.unwrap();
+
+        let energy = |device: &str, pid: Option<u32>| -> f64 {
+            records
+                .iter()
+                .filter(|r| r.device == device && pid.is_none_or(|pid| r.pid == pid))
+                .map(|r| r.energy)
+                .sum()
+        };
+        assert!((energy("rapl:socket:0:package", None) - 6.0).abs() < 1e-9);
+        assert_eq!(energy("rapl:system:dram", Some(pid)), 2.0);
+        assert_eq!(energy("rapl:system:psys", Some(pid)), 3.0);
+        assert_eq!(energy("rapl:system:psys", Some(UNATTRIBUTED_PID)), 9.0);
+    }
+
+    #[tokio::test]
+    async fn fake_powercap_discovers_every_socket_and_its_subdomains() {
+        let mut powercap = FakePowercap::new()
+            .package(0, [0, 4_000_000])
+            .subzone(0, "core", [0, 3_000_000])
+            .subzone(0, "uncore", [0, 500_000])
+            .subzone(0, "dram", [0, 1_000_000])
+            .package(1, [0, 2_000_000])
+            .subzone(1, "core", [0, 1_000_000])
+            .subzone(1, "dram", [0, 1_000_000])
+            .build()
+            .unwrap();
+        let rapl = Rapl::new(Some(powercap.path_string()));
+
+        let names: Vec<String> = rapl.devices().into_iter().map(|d| d.name).collect();
+        assert_eq!(
+            names,
+            [
+                "rapl:socket:0:package",
+                "rapl:socket:1:package",
+                "rapl:system:dram"
+            ]
+        );
+        let readers = rapl.readers();
+        assert!(readers.socket_readers[0].uncore_reader.is_some());
+        assert!(readers.socket_readers[1].uncore_reader.is_none());
+
+        rapl.set_tracked_pids(vec![std::process::id()]);
+        rapl.get_energy_trace().await.unwrap();
+        powercap.advance().unwrap();
+        let records = rapl.get_energy_trace().await.unwrap();
+        let energy = |device: &str| -> f64 {
+            records
+                .iter()
+                .filter(|r| r.device == device)
+                .map(|r| r.energy)
+                .sum()
+        };
+        assert!((energy("rapl:socket:0:package") - 4.0).abs() < 1e-9);
+        assert!((energy("rapl:socket:1:package") - 2.0).abs() < 1e-9);
+        assert!((energy("rapl:system:dram") - 2.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn fake_powercap_reads_amd_zones_without_dram() {
+        // AMD Zen CPUs expose a package zone and a per-socket `core` zone
+        // through the same intel-rapl driver, but no DRAM domain.
+        let powercap = FakePowercap::new()
+            .package(0, [0])
+            .subzone(0, "core", [0])
+            .package(1, [0])
+            .subzone(1, "core", [0])
+            .build()
+            .unwrap();
+        let rapl = Rapl::new(Some(powercap.path_string()));
+
+        let readers = rapl.readers();
+        assert_eq!(readers.socket_readers.len(), 2);
+        assert!(
+            readers
+                .socket_readers
+                .iter()
+                .all(|socket| socket.core_reader.is_some())
+        );
+        let sources = rapl.device_sources();
+        assert_eq!(sources.cpu, DeviceSource::MeasuredPackage);
+        assert_eq!(sources.dram, DeviceSource::IncludedInPackage);
+    }
+
+    #[tokio::test]
+    async fn fake_powercap_counter_overflow_keeps_the_wrapped_energy() {
+        let mut powercap = FakePowercap::new()
+            .package(0, [65_000_000_000, 65_534_000_000, 1_000_000])
+            .max_energy_range(65_536_000_000)
+            .build()
+            .unwrap();
+        let rapl = Rapl::new(Some(powercap.path_string()));
+        let package_energy = |records: &[EnergyRecord]| -> f64 {
+            records
+                .iter()
+                .filter(|r| r.device == "rapl:socket:0:package")
+                .map(|r| r.energy)
+                .sum()
+        };
+
+        rapl.set_tracked_pids(vec![std::process::id()]);
+        rapl.get_energy_trace().await.unwrap();
+        powercap.advance().unwrap();
+        let records = rapl.get_energy_trace().await.unwrap();
+        assert!((package_energy(&records) - 534.0).abs() < 1e-6);
+        powercap.advance().unwrap();
+        let records = rapl.get_energy_trace().await.unwrap();
+        assert!((package_energy(&records) - 3.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn parse_cpu_identity_reads_vendor_and_model() {
+        let contents =
+            "processor\t: 0\nvendor_id\t: GenuineIntel\nmodel name\t: Intel(R) Xeon(R) Gold 6338\n";
+
+        assert_eq!(
+            parse_cpu_identity(contents),
+            (
+                Some("GenuineIntel".to_string()),
+                Some("Intel(R) Xeon(R) Gold 6338".to_string())
+            )
+        );
+    }
+
     #[test]
     fn parse_memtotal_bytes_reads_kib_value() {
         let contents = "MemFree: 1 kB\nMemTotal: 2048 kB\n";
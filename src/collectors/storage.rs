@@ -0,0 +1,314 @@
+/// Storage Collector
+///
+/// Estimates the energy of disks from the bytes they transfer, with a
+/// linear model per device type: every gigabyte read or written costs the
+/// `joules_per_gb` of NVMe, SSD or HDD devices. Device totals come from
+/// `/proc/diskstats`; NVMe devices are told apart by name and HDDs by
+/// `/sys/block/<dev>/queue/rotational`.
+///
+/// Devices are recorded as `storage:<dev>`, by default the physical disks
+/// only, since partitions and device-mapper volumes count the same bytes
+/// again. Each tracked process gets a share of every device's energy
+/// matching its share of the bytes transferred, from the `read_bytes` and
+/// `write_bytes` of `/proc/<pid>/io`; the rest stays unattributed.
+use crate::config::StorageEnergyConfig;
+use crate::energy_group::{EnergyCollector, EnergyRecord};
+use crate::utils::errors::CollectorError;
+use crate::utils::pattern::glob_match;
+use crate::utils::time::RecordTime;
+use async_trait::async_trait;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+const UNATTRIBUTED_PID: u32 = 0;
+/// `/proc/diskstats` counts 512-byte sectors whatever the device's own
+/// sector size.
+const SECTOR_BYTES: u64 = 512;
+const BYTES_PER_GB: f64 = 1e9;
+
+/// Bytes read and written by each device of `/proc/diskstats` contents.
+fn parse_diskstats(contents: &str) -> BTreeMap<String, u64> {
+    contents
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace().skip(2);
+            let name = fields.next()?;
+            let stats: Vec<u64> = fields
+                .take(7)
+                .map(|field| field.parse().ok())
+                .collect::<Option<_>>()?;
+            // Sectors read is the third field, sectors written the seventh
+            let sectors = stats.get(2)? + stats.get(6)?;
+            Some((name.to_string(), sectors * SECTOR_BYTES))
+        })
+        .collect()
+}
+
+/// Bytes a process caused to be read from or written to storage, from
+/// `/proc/<pid>/io` contents.
+fn parse_proc_io(contents: &str) -> Option<u64> {
+    let field = |name: &str| {
+        contents.lines().find_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            (key.trim() == name).then(|| value.trim().parse::<u64>().ok())?
+        })
+    };
+    Some(field("read_bytes")? + field("write_bytes")?)
+}
+
+/// Type of a storage device, which sets its energy per byte.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StorageKind {
+    Nvme,
+    Ssd,
+    Hdd,
+}
+
+#[derive(Debug, Default)]
+struct StorageState {
+    /// Latest byte count of each device
+    devices: HashMap<String, u64>,
+    /// Latest byte count of each tracked process
+    processes: HashMap<u32, u64>,
+}
+
+/// Collector estimating storage device energy; see [`StorageEnergyConfig`].
+pub struct Storage {
+    config: StorageEnergyConfig,
+    proc_root: PathBuf,
+    sys_block: PathBuf,
+    tracked_pids: Mutex<Vec<u32>>,
+    state: Mutex<StorageState>,
+}
+
+impl Storage {
+    pub fn new(config: StorageEnergyConfig) -> Self {
+        Self::with_roots(config, "/proc", "/sys/block")
+    }
+
+    /// Read processes and disk statistics under `proc_root` and devices
+    /// under `sys_block`.
+    pub(crate) fn with_roots(
+        config: StorageEnergyConfig,
+        proc_root: impl Into<PathBuf>,
+        sys_block: impl Into<PathBuf>,
+    ) -> Self {
+        Self {
+            config,
+            proc_root: proc_root.into(),
+            sys_block: sys_block.into(),
+            tracked_pids: Mutex::new(Vec::new()),
+            state: Mutex::new(StorageState::default()),
+        }
+    }
+
+    /// Whether `device` is measured: it matches one of the configured
+    /// patterns, or is a physical disk when none are.
+    fn measures(&self, device: &str) -> bool {
+        if self.config.devices.is_empty() {
+            self.sys_block.join(device).join("device").exists()
+        } else {
+            self.config
+                .devices
+                .iter()
+                .any(|pattern| glob_match(pattern, device))
+        }
+    }
+
+    fn kind(&self, device: &str) -> StorageKind {
+        let rotational = self.sys_block.join(device).join("queue/rotational");
+        if device.starts_with("nvme") {
+            StorageKind::Nvme
+        } else if fs::read_to_string(rotational).is_ok_and(|value| value.trim() == "1") {
+            StorageKind::Hdd
+        } else {
+            StorageKind::Ssd
+        }
+    }
+
+    fn joules_per_gb(&self, device: &str) -> f64 {
+        let coefficients = &self.config.joules_per_gb;
+        match self.kind(device) {
+            StorageKind::Nvme => coefficients.nvme,
+            StorageKind::Ssd => coefficients.ssd,
+            StorageKind::Hdd => coefficients.hdd,
+        }
+    }
+
+    /// Bytes each tracked process transferred since the previous read.
+    fn process_bytes(&self, pids: &[u32], state: &mut StorageState) -> Vec<(u32, f64)> {
+        let mut seen = HashMap::new();
+        let mut bytes = Vec::new();
+        for &pid in pids {
+            let path = self.proc_root.join(pid.to_string()).join("io");
+            // Other users' processes cannot be read without privileges
+            let Some(total) = fs::read_to_string(path)
+                .ok()
+                .and_then(|contents| parse_proc_io(&contents))
+            else {
+                continue;
+            };
+            seen.insert(pid, total);
+            if let Some(previous) = state.processes.get(&pid) {
+                bytes.push((pid, total.saturating_sub(*previous) as f64));
+            }
+        }
+        state.processes = seen;
+        bytes
+    }
+}
+
+impl Default for Storage {
+    fn default() -> Self {
+        Self::new(StorageEnergyConfig::default())
+    }
+}
+
+#[async_trait]
+impl EnergyCollector for Storage {
+    fn set_tracked_pids(&self, pids: Vec<u32>) {
+        *self.tracked_pids.lock().unwrap() = pids;
+    }
+
+    async fn get_energy_trace(&self) -> Result<Vec<EnergyRecord>, CollectorError> {
+        let path = self.proc_root.join("diskstats");
+        let counters = fs::read_to_string(&path)
+            .map(|contents| parse_diskstats(&contents))
+            .map_err(|e| CollectorError::from_io(path.display(), &e))?;
+        let time = RecordTime::now();
+        let pids = self.tracked_pids.lock().unwrap().clone();
+        let mut state = self.state.lock().unwrap();
+
+        let devices: Vec<(String, u64)> = counters
+            .into_iter()
+            .filter(|(device, _)| self.measures(device))
+            .collect();
+        let deltas: Vec<(String, f64)> = devices
+            .iter()
+            .filter_map(|(device, bytes)| {
+                let previous = state.devices.get(device)?;
+                Some((device.clone(), bytes.saturating_sub(*previous) as f64))
+            })
+            .collect();
+        state.devices = devices.into_iter().collect();
+        let process_bytes = self.process_bytes(&pids, &mut state);
+
+        // Each process's share of the bytes the devices transferred, capped
+        // so together they never exceed it
+        let device_bytes: f64 = deltas.iter().map(|(_, bytes)| bytes).sum();
+        let attributed: f64 = process_bytes.iter().map(|(_, bytes)| bytes).sum();
+        let total_bytes = attributed.max(device_bytes);
+
+        let mut records = Vec::new();
+        for (device, bytes) in deltas {
+            let energy = bytes / BYTES_PER_GB * self.joules_per_gb(&device);
+            let device = format!("storage:{device}");
+            let record = |pid, energy| EnergyRecord {
+                pid,
+                timestamp_us: time.timestamp_us,
+                monotonic_ns: time.monotonic_ns,
+                device: device.clone(),
+                energy,
+                raw_counter: None,
+            };
+            let mut unattributed = energy;
+            for &(pid, process_bytes) in &process_bytes {
+                let share = energy * process_bytes / total_bytes;
+                if share > 0.0 {
+                    unattributed -= share;
+                    records.push(record(pid, share));
+                }
+            }
+            records.push(record(UNATTRIBUTED_PID, unattributed.max(0.0)));
+        }
+        Ok(records)
+    }
+
+    fn is_available() -> bool {
+        Path::new("/proc/diskstats").exists()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::StorageCoefficients;
+
+    fn diskstats(devices: &[(&str, u64, u64)]) -> String {
+        devices
+            .iter()
+            .map(|(device, read, written)| {
+                format!(
+                    "   8       0 {device} 100 0 {read} 10 200 0 {written} 20 0 30 30 0 0 0 0\n"
+                )
+            })
+            .collect()
+    }
+
+    fn proc_io(read: u64, written: u64) -> String {
+        format!(
+            "rchar: 999\nwchar: 999\nsyscr: 1\nsyscw: 1\nread_bytes: {read}\nwrite_bytes: {written}\ncancelled_write_bytes: 0\n"
+        )
+    }
+
+    #[test]
+    fn parses_transferred_bytes() {
+        let devices = parse_diskstats(&diskstats(&[("sda", 2, 6), ("sda1", 2, 4)]));
+        assert_eq!(devices["sda"], 8 * SECTOR_BYTES);
+        assert_eq!(devices["sda1"], 6 * SECTOR_BYTES);
+        assert_eq!(parse_proc_io(&proc_io(4096, 1024)), Some(5120));
+        assert_eq!(parse_proc_io("rchar: 1\n"), None);
+    }
+
+    #[tokio::test]
+    async fn attributes_device_energy_by_process_io() {
+        let root = tempfile::tempdir().unwrap();
+        let proc_root = root.path().join("proc");
+        let sys_block = root.path().join("block");
+        fs::create_dir_all(proc_root.join("10")).unwrap();
+        fs::create_dir_all(sys_block.join("nvme0n1/device")).unwrap();
+        fs::create_dir_all(sys_block.join("sda/device")).unwrap();
+        fs::create_dir_all(sys_block.join("sda/queue")).unwrap();
+        fs::write(sys_block.join("sda/queue/rotational"), "1\n").unwrap();
+        fs::create_dir_all(sys_block.join("dm-0")).unwrap();
+
+        let write = |nvme: u64, sda: u64, process: u64| {
+            let stats = diskstats(&[("nvme0n1", nvme, 0), ("sda", 0, sda), ("dm-0", nvme, 0)]);
+            fs::write(proc_root.join("diskstats"), stats).unwrap();
+            fs::write(proc_root.join("10/io"), proc_io(process, 0)).unwrap();
+        };
+        let config = StorageEnergyConfig {
+            joules_per_gb: StorageCoefficients {
+                nvme: 2.0,
+                ssd: 5.0,
+                hdd: 40.0,
+            },
+            ..StorageEnergyConfig::default()
+        };
+        let storage = Storage::with_roots(config, &proc_root, &sys_block);
+        storage.set_tracked_pids(vec![10, 20]);
+        write(0, 0, 0);
+        assert!(storage.get_energy_trace().await.unwrap().is_empty());
+
+        // 3 GB read from the NVMe disk and 1 GB written to the HDD, half of
+        // it by the process
+        let gb = 1_000_000_000 / SECTOR_BYTES;
+        write(3 * gb, gb, 2 * gb * SECTOR_BYTES);
+        let records = storage.get_energy_trace().await.unwrap();
+        let energy: Vec<(u32, &str, f64)> = records
+            .iter()
+            .map(|r| (r.pid, r.device.as_str(), r.energy))
+            .collect();
+        assert_eq!(
+            energy,
+            [
+                (10, "storage:nvme0n1", 3.0),
+                (0, "storage:nvme0n1", 3.0),
+                (10, "storage:sda", 20.0),
+                (0, "storage:sda", 20.0),
+            ]
+        );
+    }
+}
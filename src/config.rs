@@ -1,5 +1,26 @@
+use crate::budgets::EnergyBudget;
+use crate::collectors::attribution::{AttributionShare, DeviceClass};
+use crate::collectors::cpu_energy::CpuEnergyBackend;
+use crate::collectors::http_scrape::JsonPath;
+use crate::collectors::rapl::CpuAttribution;
+use crate::derived::DerivedDevices;
+use crate::device_labels::{DeviceLabelRule, DeviceLabels};
+use crate::energy_group::{BackpressurePolicy, MissedTickPolicy, RecordOutput};
+use crate::idle::IdleConfig;
+use crate::query::Filter;
+use crate::read_schedule::ReadPhase;
+use crate::run::CollectorKind;
+use crate::shutdown_actions::ShutdownAction;
+use crate::smoothing::PowerSmoothing;
+use crate::spike_filter::SpikeFiltering;
+use crate::trace_recorder::{ParquetOptions, TraceFormat};
+use crate::units::{EnergyUnit, PowerUnit};
+use crate::utils::time::{ExportTimezone, parse_interval};
+use crate::utils::trace_rotation::DeviceRetention;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::path::Path;
+use std::time::Duration;
 
 /// Configuration for process discovery behavior.
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -15,10 +36,376 @@ pub struct DiscoveryConfig {
 pub struct CollectionConfig {
     /// Collection rate in Hz.
     pub rate_hz: f64,
+    /// Collectors to read: `cpu`, `nvidia`, `amd` and `meters`. Empty reads
+    /// every available one. The monitor always reads the CPU, which
+    /// processes are attributed by.
+    pub collectors: Vec<CollectorKind>,
+    /// Records batched before a group publishes them, for groups built with
+    /// `EnergyGroup::from_config`. Live monitors publish every tick.
+    pub batch_size: Option<usize>,
     /// Maximum trace retention in seconds before rotation.
     pub trace_retention_secs: u64,
-    /// Interval in seconds between trace recorder flushes.
+    /// Interval in seconds between trace recorder flushes; a crash loses at
+    /// most this much of the persisted trace.
     pub trace_flush_interval_secs: f64,
+    /// Directory the monitor persists its traces to, as CSV segments under
+    /// one subdirectory per collector group. Unset keeps traces in memory.
+    pub trace_dir: Option<String>,
+    /// Format of the files under `trace_dir`: `csv` segments or a `parquet`
+    /// dataset that restarts keep extending.
+    pub trace_format: TraceFormat,
+    /// zstd level (1-22) rotated `csv` segments are compressed at, as
+    /// `trace_N.csv.zst`. Unset keeps them uncompressed.
+    pub trace_compression_level: Option<i32>,
+    /// Partitioning (`day` or `hour`) and compression (`zstd`, `lz4`,
+    /// `snappy` or `uncompressed`) of a `parquet` trace.
+    pub parquet: ParquetOptions,
+    /// Directory for write-ahead logs (`cpu.wal`, `gpu.wal`) that every
+    /// batch is synced to before it reaches the trace, and which are
+    /// replayed on startup. Unset disables the logs.
+    pub wal_dir: Option<String>,
+    /// Per-device retention overrides (first matching pattern wins).
+    pub device_retention: Vec<DeviceRetention>,
+    /// Slower sampling while the system is idle.
+    pub idle: IdleConfig,
+    /// Interval in seconds between device rediscovery passes, which pick up
+    /// hot-plugged CPUs and GPUs.
+    pub rediscover_interval_secs: f64,
+    /// Capacity, in batches, of the channel from each collector to its group.
+    pub channel_capacity: usize,
+    /// What collectors do with batches while the channel is full: `block`,
+    /// `drop_oldest` or `drop_newest`.
+    pub backpressure: BackpressurePolicy,
+    /// When collectors read after a read overran the collection interval:
+    /// `skip` the missed ticks and keep the cadence, `burst` to make them
+    /// up, or `delay` to start the cadence again.
+    pub missed_ticks: MissedTickPolicy,
+    /// Correction of implausible readings at ingest. Unset keeps every
+    /// reading as read.
+    pub spike_filter: Option<SpikeFiltering>,
+    /// Offsets of device reads within each tick, as fractions of the
+    /// collection interval, so counters are not all read at once.
+    pub read_phases: Vec<ReadPhase>,
+}
+
+/// Configuration for CPU energy backend selection.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CpuEnergyConfig {
+    /// Backends probed in order; the first that passes preflight is used.
+    pub backends: Vec<CpuEnergyBackend>,
+    /// Whole-CPU power at 0% utilization assumed by the model backend.
+    pub model_idle_watts: f64,
+    /// Whole-CPU power at 100% utilization assumed by the model backend.
+    pub model_max_watts: f64,
+    /// How package energy is split among processes: `system` utilization
+    /// shares, or `per_core` CPU time on each socket.
+    pub attribution: CpuAttribution,
+    /// Share each device class is split by, where it differs from the
+    /// default `cpu` for packages, `memory` for DRAM and `equal` for PSYS.
+    pub domain_attribution: BTreeMap<DeviceClass, AttributionShare>,
+}
+
+/// A meter read by running a command, for hardware EMT has no collector
+/// for; see [`MeterCollector`](crate::collectors::MeterCollector).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExecCollectorConfig {
+    /// Meter name; devices are recorded as `exec:<name>:<device>`
+    pub name: String,
+    /// Program and its arguments, run without a shell
+    pub command: Vec<String>,
+    /// Seconds between runs; a run taking longer is killed
+    #[serde(default = "default_meter_interval_secs")]
+    pub interval_secs: f64,
+}
+
+/// A meter read from an HTTP endpoint, such as an existing exporter.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HttpCollectorConfig {
+    /// Meter name; devices are recorded as `http:<name>:<device>`
+    pub name: String,
+    /// `http://` URL scraped for readings
+    pub url: String,
+    #[serde(default)]
+    pub format: HttpScrapeFormat,
+    /// Seconds between scrapes; a scrape taking longer is abandoned
+    #[serde(default = "default_meter_interval_secs")]
+    pub interval_secs: f64,
+    /// Values of the response turned into device readings
+    pub series: Vec<HttpSeriesConfig>,
+}
+
+impl HttpCollectorConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.is_empty() || self.name.contains(':') {
+            return Err("name must be non-empty and without ':'".to_string());
+        }
+        if reqwest::Url::parse(&self.url).map_or(true, |url| url.scheme() != "http") {
+            return Err(format!("url '{}' must be an http:// URL", self.url));
+        }
+        if !(self.interval_secs.is_finite() && self.interval_secs > 0.0) {
+            return Err("interval_secs must be greater than 0".to_string());
+        }
+        if self.series.is_empty() {
+            return Err("series must list at least one device".to_string());
+        }
+        for series in &self.series {
+            if series.device.is_empty() {
+                return Err("series device must be non-empty".to_string());
+            }
+            if !(series.scale.is_finite() && series.scale > 0.0) {
+                return Err(format!(
+                    "scale of '{}' must be greater than 0",
+                    series.device
+                ));
+            }
+            match self.format {
+                HttpScrapeFormat::Prometheus if series.metric.is_none() => {
+                    return Err(format!("series '{}' needs a metric", series.device));
+                }
+                HttpScrapeFormat::Json => {
+                    let path = series
+                        .path
+                        .as_deref()
+                        .ok_or_else(|| format!("series '{}' needs a path", series.device))?;
+                    JsonPath::parse(path)
+                        .map_err(|e| format!("path of '{}': {e}", series.device))?;
+                }
+                HttpScrapeFormat::Prometheus => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Format of a scraped HTTP response.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HttpScrapeFormat {
+    /// Prometheus text exposition format
+    #[default]
+    Prometheus,
+    Json,
+}
+
+/// A value of a scraped response read as one device.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HttpSeriesConfig {
+    pub device: String,
+    /// Prometheus metric name, for the `prometheus` format
+    #[serde(default)]
+    pub metric: Option<String>,
+    /// Labels the Prometheus sample must have
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+    /// JSONPath of the value, such as `$.outlets[2].energy`, for the
+    /// `json` format
+    #[serde(default)]
+    pub path: Option<String>,
+    pub quantity: MeterQuantity,
+    /// Factor converting the value to joules or watts, such as 3600000
+    /// for kWh
+    #[serde(default = "default_series_scale")]
+    pub scale: f64,
+}
+
+/// What a meter value measures.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MeterQuantity {
+    /// Cumulative energy counter
+    Energy,
+    /// Current power
+    Power,
+}
+
+fn default_meter_interval_secs() -> f64 {
+    5.0
+}
+
+/// Linear energy model of the network interfaces; see
+/// [`Network`](crate::collectors::Network).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NetworkEnergyConfig {
+    /// Energy of each byte received or sent, in joules
+    pub joules_per_byte: f64,
+    /// Interface name patterns to measure; the physical interfaces when
+    /// empty
+    pub interfaces: Vec<String>,
+}
+
+impl Default for NetworkEnergyConfig {
+    fn default() -> Self {
+        Self {
+            joules_per_byte: 1e-8,
+            interfaces: Vec::new(),
+        }
+    }
+}
+
+/// Linear energy model of the storage devices; see
+/// [`Storage`](crate::collectors::Storage).
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StorageEnergyConfig {
+    pub joules_per_gb: StorageCoefficients,
+    /// Device name patterns to measure; the physical disks when empty
+    pub devices: Vec<String>,
+}
+
+/// Energy of each gigabyte read or written, in joules, by device type.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StorageCoefficients {
+    pub nvme: f64,
+    pub ssd: f64,
+    pub hdd: f64,
+}
+
+impl Default for StorageCoefficients {
+    fn default() -> Self {
+        Self {
+            nvme: 2.0,
+            ssd: 6.0,
+            hdd: 50.0,
+        }
+    }
+}
+
+/// The baseboard management controller of a server, read for the power of
+/// the whole chassis; see [`Bmc`](crate::collectors::Bmc).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BmcCollectorConfig {
+    /// Meter name; the chassis is recorded as `bmc:<name>:chassis`
+    pub name: String,
+    #[serde(flatten)]
+    pub source: BmcSource,
+    /// Seconds between reads; a read taking longer is abandoned
+    #[serde(default = "default_meter_interval_secs")]
+    pub interval_secs: f64,
+}
+
+/// How a BMC is read.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BmcSource {
+    /// `ipmitool dcmi power reading`
+    Ipmi(IpmiConfig),
+    /// A Redfish chassis `Power` resource
+    Redfish(RedfishConfig),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IpmiConfig {
+    /// BMC reached over the network with the `lanplus` interface; the
+    /// local BMC when unset
+    pub host: Option<String>,
+    pub username: Option<String>,
+    /// Environment variable holding the password of a remote BMC
+    pub password_env: String,
+    /// `ipmitool` program to run
+    pub ipmitool: String,
+}
+
+impl Default for IpmiConfig {
+    fn default() -> Self {
+        Self {
+            host: None,
+            username: None,
+            password_env: "IPMI_PASSWORD".to_string(),
+            ipmitool: "ipmitool".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RedfishConfig {
+    /// Chassis `Power` resource, such as
+    /// `https://bmc.example/redfish/v1/Chassis/1/Power`
+    pub url: String,
+    /// User for HTTP basic authentication
+    #[serde(default)]
+    pub username: Option<String>,
+    /// Environment variable holding the password
+    #[serde(default = "default_redfish_password_env")]
+    pub password_env: String,
+    /// Accept the self-signed certificates most BMCs ship with
+    #[serde(default)]
+    pub insecure: bool,
+}
+
+fn default_redfish_password_env() -> String {
+    "REDFISH_PASSWORD".to_string()
+}
+
+impl BmcCollectorConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.is_empty() || self.name.contains(':') {
+            return Err("name must be non-empty and without ':'".to_string());
+        }
+        if !(self.interval_secs.is_finite() && self.interval_secs > 0.0) {
+            return Err("interval_secs must be greater than 0".to_string());
+        }
+        match &self.source {
+            BmcSource::Ipmi(ipmi) if ipmi.ipmitool.is_empty() => {
+                Err("ipmi.ipmitool must name a program".to_string())
+            }
+            BmcSource::Ipmi(_) => Ok(()),
+            BmcSource::Redfish(redfish) => match reqwest::Url::parse(&redfish.url) {
+                Ok(url) if url.scheme() == "http" => Ok(()),
+                Ok(url) if url.scheme() == "https" && cfg!(feature = "redfish-https") => Ok(()),
+                Ok(url) if url.scheme() == "https" => Err(format!(
+                    "redfish url '{}' needs a build with the redfish-https feature",
+                    redfish.url
+                )),
+                _ => Err(format!(
+                    "redfish url '{}' must be an http:// or https:// URL",
+                    redfish.url
+                )),
+            },
+        }
+    }
+}
+
+fn default_series_scale() -> f64 {
+    1.0
+}
+
+/// A virtual device computed from other devices; see
+/// [`DerivedDevices`](crate::derived::DerivedDevices).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DerivedDeviceConfig {
+    /// Device name; recorded as `derived:<name>`
+    pub name: String,
+    /// Sum and differences of devices, such as
+    /// `rapl:system:psys - cpu_total - rapl:system:dram`
+    pub expression: String,
+}
+
+/// Priority rule for devices that measure the same physical domain.
+///
+/// `tiers` lists device name patterns (`*` and `?` globs) from highest to
+/// lowest priority. The highest tier seen at runtime is authoritative for
+/// totals; lower tiers are recorded as auxiliary readings.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DevicePriorityRule {
+    pub domain: String,
+    pub tiers: Vec<Vec<String>>,
+}
+
+/// Containment rule for a domain whose meter covers other devices.
+///
+/// `whole` and `parts` list device name patterns (`*` and `?` globs). The
+/// whole measures the entire domain, the parts measure components inside it,
+/// so overlap-aware totals count the domain once and report the whole's
+/// energy beyond its parts as `<domain>:other`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EnergyDomainRule {
+    pub domain: String,
+    pub whole: Vec<String>,
+    pub parts: Vec<String>,
 }
 
 /// Configuration for the interactive terminal UI.
@@ -31,6 +418,9 @@ pub struct TuiConfig {
     pub monitor_all_scan_interval_secs: f64,
     /// TUI render/input polling interval in milliseconds.
     pub render_interval_millis: u64,
+    /// Rolling smoothing for the power sparklines: `none`, `mean:<window>`
+    /// or `median:<window>`, with the window in samples (`5`) or seconds (`10s`).
+    pub power_smoothing: String,
 }
 
 /// Configuration for user-facing measurement units.
@@ -43,6 +433,189 @@ pub struct MeasurementUnitsConfig {
     pub power: String,
 }
 
+/// Configuration for the optional headless-mode sandbox.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SandboxConfig {
+    /// Apply Landlock and seccomp restrictions after initialization.
+    pub enabled: bool,
+    /// Extra paths readable in addition to the collector defaults.
+    pub read_paths: Vec<String>,
+    /// Paths writable by the sandboxed process (output directories).
+    pub write_paths: Vec<String>,
+    /// Keep `execve` available, e.g. for collectors that spawn helpers.
+    pub allow_exec: bool,
+}
+
+/// Configuration for at-rest encryption of exported files.
+///
+/// Keys are hex-encoded 256-bit AES keys. When both sources are set the key
+/// file wins.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EncryptionConfig {
+    /// Path to a file containing the key.
+    pub key_file: Option<String>,
+    /// Name of an environment variable containing the key.
+    pub key_env: Option<String>,
+}
+
+/// Configuration for exported files.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExportConfig {
+    /// Timezone for RFC 3339 timestamps: `UTC`, `local`, or an IANA name
+    /// such as `Europe/Berlin`.
+    pub timezone: String,
+    /// Optional per-record trace columns.
+    pub records: RecordOutput,
+    /// Exports run by `Monitor::shutdown`, in order. Each entry is a
+    /// single-key map such as `parquet: run.parquet`.
+    #[serde(with = "serde_yml::with::singleton_map_recursive")]
+    pub on_shutdown: Vec<ShutdownAction>,
+    /// Rows and columns kept by `on_shutdown` trace exports.
+    pub filter: ExportFilterConfig,
+    /// Bucket length, such as `10s` or `1m`, that `on_shutdown` trace
+    /// exports are resampled to; unset keeps every row.
+    pub resample: Option<String>,
+    /// Push the headless exporter's metrics to a Prometheus remote-write
+    /// endpoint, for hosts that cannot be scraped.
+    pub remote_write: Option<RemoteWriteConfig>,
+}
+
+/// Prometheus remote-write endpoint the headless exporter pushes to.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RemoteWriteConfig {
+    /// Receiver endpoint, such as `http://prometheus:9090/api/v1/write`
+    pub url: String,
+    /// Seconds between pushes; samples are taken at the exporter's update
+    /// interval and sent in batches.
+    #[serde(default = "default_remote_write_interval_secs")]
+    pub interval_secs: f64,
+    /// Attempts per batch before it is dropped
+    #[serde(default = "default_remote_write_max_attempts")]
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubled after each failed attempt
+    #[serde(default = "default_remote_write_backoff_secs")]
+    pub backoff_secs: f64,
+    /// User for HTTP basic authentication
+    #[serde(default)]
+    pub username: Option<String>,
+    /// Environment variable holding the basic authentication password
+    #[serde(default = "default_remote_write_password_env")]
+    pub password_env: String,
+    /// Environment variable holding a bearer token, sent when set
+    #[serde(default)]
+    pub bearer_token_env: Option<String>,
+}
+
+fn default_remote_write_interval_secs() -> f64 {
+    15.0
+}
+
+fn default_remote_write_max_attempts() -> u32 {
+    5
+}
+
+fn default_remote_write_backoff_secs() -> f64 {
+    1.0
+}
+
+fn default_remote_write_password_env() -> String {
+    "REMOTE_WRITE_PASSWORD".to_string()
+}
+
+impl RemoteWriteConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if reqwest::Url::parse(&self.url)
+            .map_or(true, |url| !matches!(url.scheme(), "http" | "https"))
+        {
+            return Err(format!(
+                "url '{}' must be an http:// or https:// URL",
+                self.url
+            ));
+        }
+        if !(self.interval_secs.is_finite() && self.interval_secs > 0.0) {
+            return Err("interval_secs must be positive".to_string());
+        }
+        if self.max_attempts == 0 {
+            return Err("max_attempts must be at least 1".to_string());
+        }
+        if !(self.backoff_secs.is_finite() && self.backoff_secs >= 0.0) {
+            return Err("backoff_secs must not be negative".to_string());
+        }
+        if self.username.is_some() && self.bearer_token_env.is_some() {
+            return Err("username and bearer_token_env are exclusive".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Rows and columns an export keeps. Empty lists keep everything.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExportFilterConfig {
+    /// Device name patterns (`*` and `?` globs).
+    pub devices: Vec<String>,
+    pub pids: Vec<u32>,
+    pub users: Vec<String>,
+    pub tasks: Vec<String>,
+    /// Columns written, in this order.
+    pub columns: Vec<String>,
+}
+
+impl ExportFilterConfig {
+    pub fn filter(&self) -> Filter {
+        Filter {
+            devices: self.devices.clone(),
+            pids: self.pids.clone(),
+            users: self.users.clone(),
+            tasks: self.tasks.clone(),
+        }
+    }
+}
+
+/// Factors for estimating the emissions and cost of measured energy.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FootprintConfig {
+    /// Grid carbon intensity in grams CO2e per kWh.
+    pub carbon_intensity_g_per_kwh: Option<f64>,
+    /// Energy price per kWh, in `currency`.
+    pub price_per_kwh: Option<f64>,
+    /// Currency label for costs, such as `EUR`.
+    pub currency: Option<String>,
+    /// Live grid intensity, replacing `carbon_intensity_g_per_kwh` once
+    /// fetched.
+    pub electricity_maps: Option<ElectricityMapsConfig>,
+}
+
+/// Grid carbon intensity from the Electricity Maps API; needs the
+/// `carbon-api` feature.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ElectricityMapsConfig {
+    /// Grid zone, such as `DE` or `US-CAL-CISO`.
+    pub zone: String,
+    /// Environment variable holding the API token.
+    pub token_env: String,
+    /// Latest carbon intensity endpoint.
+    pub url: String,
+    /// Seconds between intensity updates.
+    pub refresh_interval_secs: f64,
+}
+
+impl Default for ElectricityMapsConfig {
+    fn default() -> Self {
+        Self {
+            zone: String::new(),
+            token_env: "ELECTRICITYMAPS_TOKEN".to_string(),
+            url: "https://api.electricitymap.org/v3/carbon-intensity/latest".to_string(),
+            refresh_interval_secs: 900.0,
+        }
+    }
+}
+
 /// Top-level EMT configuration with layered resolution.
 ///
 /// Resolution precedence (highest wins):
@@ -51,13 +624,37 @@ pub struct MeasurementUnitsConfig {
 /// 3. User-level: `~/.config/emt/config.yaml`
 ///
 /// Missing files are silently skipped. Missing keys use compiled defaults.
-#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(default)]
 pub struct EmtConfig {
     pub discovery: DiscoveryConfig,
     pub collection: CollectionConfig,
+    pub cpu_energy: CpuEnergyConfig,
+    /// Meters read by running commands.
+    pub exec_collectors: Vec<ExecCollectorConfig>,
+    /// Meters read from HTTP endpoints.
+    pub http_collectors: Vec<HttpCollectorConfig>,
+    /// Chassis power read from server BMCs.
+    pub bmc_collectors: Vec<BmcCollectorConfig>,
+    /// Network interface energy estimated from traffic; off when unset.
+    pub network: Option<NetworkEnergyConfig>,
+    /// Storage device energy estimated from their I/O; off when unset.
+    pub storage: Option<StorageEnergyConfig>,
+    pub device_priority: Vec<DevicePriorityRule>,
+    /// Domains whose meter contains other devices, for overlap-aware totals.
+    pub energy_domains: Vec<EnergyDomainRule>,
+    /// Virtual devices computed every tick from other devices.
+    pub derived_devices: Vec<DerivedDeviceConfig>,
+    /// Aliases and labels of devices in exports and metrics.
+    pub device_labels: Vec<DeviceLabelRule>,
     pub tui: TuiConfig,
     pub measurement_units: MeasurementUnitsConfig,
+    pub sandbox: SandboxConfig,
+    pub encryption: EncryptionConfig,
+    pub export: ExportConfig,
+    /// Per-process and per-task energy budgets enforced by the monitor.
+    pub budgets: Vec<EnergyBudget>,
+    pub footprint: FootprintConfig,
 }
 
 /// Errors that can occur while loading configuration.
@@ -67,10 +664,65 @@ pub enum ConfigError {
     Io(#[from] std::io::Error),
     #[error("Failed to parse YAML: {0}")]
     Yaml(String),
+    #[error("Failed to parse TOML: {0}")]
+    Toml(String),
     #[error("Invalid config value: {0}")]
     Invalid(String),
 }
 
+impl Default for EmtConfig {
+    fn default() -> Self {
+        Self {
+            discovery: DiscoveryConfig::default(),
+            collection: CollectionConfig::default(),
+            cpu_energy: CpuEnergyConfig::default(),
+            exec_collectors: Vec::new(),
+            http_collectors: Vec::new(),
+            bmc_collectors: Vec::new(),
+            network: None,
+            storage: None,
+            device_priority: default_device_priority(),
+            energy_domains: default_energy_domains(),
+            derived_devices: Vec::new(),
+            device_labels: Vec::new(),
+            tui: TuiConfig::default(),
+            measurement_units: MeasurementUnitsConfig::default(),
+            sandbox: SandboxConfig::default(),
+            encryption: EncryptionConfig::default(),
+            export: ExportConfig::default(),
+            budgets: Vec::new(),
+            footprint: FootprintConfig::default(),
+        }
+    }
+}
+
+/// PSYS covers package and DRAM; prefer the per-device RAPL domains so the
+/// CPU/DRAM breakdown stays meaningful and PSYS is not added on top.
+fn default_device_priority() -> Vec<DevicePriorityRule> {
+    vec![DevicePriorityRule {
+        domain: "platform".to_string(),
+        tiers: vec![
+            vec![
+                "rapl:socket:*:package".to_string(),
+                "rapl:system:dram".to_string(),
+            ],
+            vec!["rapl:system:psys".to_string()],
+        ],
+    }]
+}
+
+/// PSYS meters the whole platform, including the packages and DRAM.
+pub(crate) fn default_energy_domains() -> Vec<EnergyDomainRule> {
+    vec![EnergyDomainRule {
+        domain: "platform".to_string(),
+        whole: vec!["rapl:system:psys".to_string()],
+        parts: vec![
+            "rapl:socket:*:package".to_string(),
+            "rapl:system:dram".to_string(),
+        ],
+    }]
+}
+
 impl Default for DiscoveryConfig {
     fn default() -> Self {
         Self {
@@ -83,8 +735,35 @@ impl Default for CollectionConfig {
     fn default() -> Self {
         Self {
             rate_hz: 10.0,
+            collectors: Vec::new(),
+            batch_size: None,
             trace_retention_secs: 3600,
             trace_flush_interval_secs: 5.0,
+            trace_dir: None,
+            trace_format: TraceFormat::Csv,
+            trace_compression_level: None,
+            parquet: ParquetOptions::default(),
+            wal_dir: None,
+            device_retention: Vec::new(),
+            idle: IdleConfig::default(),
+            rediscover_interval_secs: 10.0,
+            channel_capacity: 10,
+            backpressure: BackpressurePolicy::Block,
+            missed_ticks: MissedTickPolicy::Skip,
+            spike_filter: None,
+            read_phases: Vec::new(),
+        }
+    }
+}
+
+impl Default for CpuEnergyConfig {
+    fn default() -> Self {
+        Self {
+            backends: CpuEnergyBackend::DEFAULT_ORDER.to_vec(),
+            model_idle_watts: 10.0,
+            model_max_watts: 65.0,
+            attribution: CpuAttribution::default(),
+            domain_attribution: BTreeMap::new(),
         }
     }
 }
@@ -95,6 +774,7 @@ impl Default for TuiConfig {
             monitor_all_rate_hz: 0.1,
             monitor_all_scan_interval_secs: 30.0,
             render_interval_millis: 2000,
+            power_smoothing: "mean:10s".to_string(),
         }
     }
 }
@@ -108,41 +788,73 @@ impl Default for MeasurementUnitsConfig {
     }
 }
 
+impl Default for ExportConfig {
+    fn default() -> Self {
+        Self {
+            timezone: "UTC".to_string(),
+            records: RecordOutput::default(),
+            on_shutdown: Vec::new(),
+            filter: ExportFilterConfig::default(),
+            resample: None,
+            remote_write: None,
+        }
+    }
+}
+
+impl TuiConfig {
+    /// Parsed power smoothing; falls back to none for specs `validate` rejects.
+    pub fn power_smoothing(&self) -> PowerSmoothing {
+        self.power_smoothing.parse().unwrap_or_default()
+    }
+}
+
+impl ExportConfig {
+    /// Parsed export timezone; falls back to UTC for names `validate` rejects.
+    pub fn timezone(&self) -> ExportTimezone {
+        self.timezone.parse().unwrap_or_default()
+    }
+
+    /// Parsed resampling interval; `None` when unset or rejected by
+    /// `validate`.
+    pub fn resample(&self) -> Option<Duration> {
+        self.resample.as_deref().and_then(parse_interval)
+    }
+}
+
 impl MeasurementUnitsConfig {
+    /// Parsed energy unit; falls back to Joules for names `validate` rejects.
+    pub fn energy_unit(&self) -> EnergyUnit {
+        self.energy.parse().unwrap_or(EnergyUnit::Joules)
+    }
+
+    /// Parsed power unit; falls back to Watts for names `validate` rejects.
+    pub fn power_unit(&self) -> PowerUnit {
+        self.power.parse().unwrap_or(PowerUnit::Watts)
+    }
+
     /// Convert canonical Joules to the configured energy unit.
     pub fn convert_energy_from_joules(&self, joules: f64) -> f64 {
-        joules / energy_unit_factor_to_joules(&self.energy).unwrap_or(1.0)
+        self.energy_unit().from_joules(joules)
     }
 
     /// Convert canonical Watts to the configured power unit.
     pub fn convert_power_from_watts(&self, watts: f64) -> f64 {
-        watts / power_unit_factor_to_watts(&self.power).unwrap_or(1.0)
+        self.power_unit().from_watts(watts)
     }
 
     /// Convert a value in the configured energy unit back to Joules.
     pub fn convert_energy_to_joules(&self, value: f64) -> f64 {
-        value * energy_unit_factor_to_joules(&self.energy).unwrap_or(1.0)
+        self.energy_unit().to_joules(value)
     }
-}
 
-fn energy_unit_factor_to_joules(unit: &str) -> Option<f64> {
-    match unit {
-        "Joules" => Some(1.0),
-        "kJ" => Some(1_000.0),
-        "\u{03bc}J" | "uJ" => Some(1e-6),
-        "mJ" => Some(1e-3),
-        "Wh" => Some(3_600.0),
-        "kWh" => Some(3_600_000.0),
-        _ => None,
+    /// Canonical Joules in the configured unit, such as `0.01250 kWh`.
+    pub fn format_energy(&self, joules: f64) -> String {
+        self.energy_unit().format(joules)
     }
-}
 
-fn power_unit_factor_to_watts(unit: &str) -> Option<f64> {
-    match unit {
-        "Watts" => Some(1.0),
-        "kW" => Some(1_000.0),
-        "mW" => Some(1e-3),
-        _ => None,
+    /// Canonical Watts in the configured unit, such as `250.0 Watts`.
+    pub fn format_power(&self, watts: f64) -> String {
+        self.power_unit().format(watts)
     }
 }
 
@@ -150,43 +862,54 @@ impl EmtConfig {
     /// Load config with layered resolution.
     ///
     /// Reads user-level config first (`~/.config/emt/config.yaml`), then overlays
-    /// project-local config (`./emt.yaml`) on top. Missing files are silently
-    /// skipped and defaults are returned.
+    /// project-local config (`./emt.yaml`) on top. Each file may also be
+    /// written in TOML (`config.toml`, `emt.toml`), which overlays the YAML
+    /// file of the same layer. Missing files are silently skipped and
+    /// defaults are returned.
     pub fn load() -> Self {
         let mut base = serde_yml::to_value(EmtConfig::default())
             .unwrap_or(serde_yml::Value::Mapping(serde_yml::Mapping::new()));
 
-        // Layer 1: user-level config
-        if let Some(user_path) = Self::user_config_path() {
-            if let Ok(content) = std::fs::read_to_string(&user_path) {
-                if let Ok(user_value) = serde_yml::from_str::<serde_yml::Value>(&content) {
-                    base = merge_yaml(base, user_value);
+        // User-level config, then project-local config (highest file priority)
+        let layers = Self::user_config_path()
+            .into_iter()
+            .chain([Path::new("./emt.yaml").to_path_buf()]);
+        for yaml_path in layers {
+            let toml_path = yaml_path.with_extension("toml");
+            for path in [yaml_path, toml_path] {
+                if let Ok(content) = std::fs::read_to_string(&path)
+                    && let Ok(value) = parse_value(&path, &content)
+                {
+                    base = merge_yaml(base, value);
                 }
             }
         }
 
-        // Layer 2: project-local config (highest file priority)
-        let local_path = Path::new("./emt.yaml");
-        if let Ok(content) = std::fs::read_to_string(local_path) {
-            if let Ok(local_value) = serde_yml::from_str::<serde_yml::Value>(&content) {
-                base = merge_yaml(base, local_value);
-            }
-        }
-
         serde_yml::from_value::<Self>(base)
             .map(Self::validated_or_default)
             .unwrap_or_default()
     }
 
-    /// Load configuration from a specific YAML file path.
+    /// Load configuration from a specific file path, read as TOML when its
+    /// extension is `.toml` and as YAML otherwise.
     pub fn from_file(path: &Path) -> Result<Self, ConfigError> {
         let content = std::fs::read_to_string(path)?;
-        let config: Self =
-            serde_yml::from_str(&content).map_err(|e| ConfigError::Yaml(e.to_string()))?;
+        let config: Self = serde_yml::from_value(parse_value(path, &content)?).map_err(|e| {
+            if is_toml(path) {
+                ConfigError::Toml(e.to_string())
+            } else {
+                ConfigError::Yaml(e.to_string())
+            }
+        })?;
         config.validate()?;
         Ok(config)
     }
 
+    /// Resolver for the configured `device_labels`.
+    pub fn device_labels(&self) -> DeviceLabels {
+        DeviceLabels::new(self.device_labels.clone())
+    }
+
     /// Validate config values that are later used to build durations or rates.
     pub fn validate(&self) -> Result<(), ConfigError> {
         validate_positive_finite(
@@ -198,21 +921,230 @@ impl EmtConfig {
             "collection.trace_flush_interval_secs",
             self.collection.trace_flush_interval_secs,
         )?;
+        validate_positive_finite(
+            "collection.rediscover_interval_secs",
+            self.collection.rediscover_interval_secs,
+        )?;
+        validate_positive_finite("collection.idle.rate_hz", self.collection.idle.rate_hz)?;
+        validate_positive_finite(
+            "collection.idle.after_secs",
+            self.collection.idle.after_secs,
+        )?;
         validate_positive_finite("tui.monitor_all_rate_hz", self.tui.monitor_all_rate_hz)?;
         validate_positive_finite(
             "tui.monitor_all_scan_interval_secs",
             self.tui.monitor_all_scan_interval_secs,
         )?;
+        if let Some(filter) = &self.collection.spike_filter {
+            filter
+                .validate()
+                .map_err(|err| ConfigError::Invalid(format!("collection.spike_filter.{err}")))?;
+        }
+        for phase in &self.collection.read_phases {
+            phase
+                .validate()
+                .map_err(|err| ConfigError::Invalid(format!("collection.read_phases: {err}")))?;
+        }
+        if self.collection.batch_size == Some(0) {
+            return Err(ConfigError::Invalid(
+                "collection.batch_size must be greater than 0".to_string(),
+            ));
+        }
+        if self.collection.channel_capacity == 0 {
+            return Err(ConfigError::Invalid(
+                "collection.channel_capacity must be greater than 0".to_string(),
+            ));
+        }
         if self.tui.render_interval_millis == 0 {
             return Err(ConfigError::Invalid(
                 "tui.render_interval_millis must be greater than 0".to_string(),
             ));
         }
+        if self.cpu_energy.backends.is_empty() {
+            return Err(ConfigError::Invalid(
+                "cpu_energy.backends must list at least one backend".to_string(),
+            ));
+        }
+        validate_positive_finite(
+            "cpu_energy.model_max_watts",
+            self.cpu_energy.model_max_watts,
+        )?;
+        if !(self.cpu_energy.model_idle_watts.is_finite()
+            && (0.0..=self.cpu_energy.model_max_watts).contains(&self.cpu_energy.model_idle_watts))
+        {
+            return Err(ConfigError::Invalid(
+                "cpu_energy.model_idle_watts must be between 0 and cpu_energy.model_max_watts"
+                    .to_string(),
+            ));
+        }
+        for (index, meter) in self.exec_collectors.iter().enumerate() {
+            if meter.name.is_empty() || meter.name.contains(':') {
+                return Err(ConfigError::Invalid(format!(
+                    "exec_collectors[{index}].name must be non-empty and without ':'"
+                )));
+            }
+            if meter.command.is_empty() {
+                return Err(ConfigError::Invalid(format!(
+                    "exec_collectors '{}' must name a command",
+                    meter.name
+                )));
+            }
+            validate_positive_finite(
+                &format!("exec_collectors '{}' interval_secs", meter.name),
+                meter.interval_secs,
+            )?;
+        }
+        for (index, meter) in self.http_collectors.iter().enumerate() {
+            meter.validate().map_err(|e| {
+                ConfigError::Invalid(format!("http_collectors[{index}] '{}': {e}", meter.name))
+            })?;
+        }
+        for (index, meter) in self.bmc_collectors.iter().enumerate() {
+            meter.validate().map_err(|e| {
+                ConfigError::Invalid(format!("bmc_collectors[{index}] '{}': {e}", meter.name))
+            })?;
+        }
+        if let Some(network) = &self.network {
+            validate_positive_finite("network.joules_per_byte", network.joules_per_byte)?;
+        }
+        if let Some(storage) = &self.storage {
+            let coefficients = &storage.joules_per_gb;
+            validate_positive_finite("storage.joules_per_gb.nvme", coefficients.nvme)?;
+            validate_positive_finite("storage.joules_per_gb.ssd", coefficients.ssd)?;
+            validate_positive_finite("storage.joules_per_gb.hdd", coefficients.hdd)?;
+        }
+        for rule in &self.device_priority {
+            if rule.tiers.is_empty() || rule.tiers.iter().any(Vec::is_empty) {
+                return Err(ConfigError::Invalid(format!(
+                    "device_priority domain '{}' must have non-empty tiers",
+                    rule.domain
+                )));
+            }
+        }
+        for rule in &self.energy_domains {
+            if rule.whole.is_empty() || rule.parts.is_empty() {
+                return Err(ConfigError::Invalid(format!(
+                    "energy_domains domain '{}' must have non-empty whole and parts",
+                    rule.domain
+                )));
+            }
+        }
+        DerivedDevices::new(&self.derived_devices).map_err(ConfigError::Invalid)?;
+        for rule in &self.device_labels {
+            rule.validate()
+                .map_err(|e| ConfigError::Invalid(format!("device_labels: {e}")))?;
+        }
+        if let Some(rule) = self
+            .collection
+            .device_retention
+            .iter()
+            .find(|rule| rule.retention_seconds <= 0)
+        {
+            return Err(ConfigError::Invalid(format!(
+                "collection.device_retention for '{}' must be greater than 0",
+                rule.device
+            )));
+        }
         if self.collection.trace_retention_secs == 0 {
             return Err(ConfigError::Invalid(
                 "collection.trace_retention_secs must be greater than 0".to_string(),
             ));
         }
+        if let Some(level) = self.collection.trace_compression_level
+            && !(1..=22).contains(&level)
+        {
+            return Err(ConfigError::Invalid(format!(
+                "collection.trace_compression_level must be between 1 and 22, got {level}"
+            )));
+        }
+        // Rows must outlive one flush interval or they leave memory unsaved.
+        let shortest_retention = self
+            .collection
+            .device_retention
+            .iter()
+            .map(|rule| rule.retention_seconds as f64)
+            .fold(self.collection.trace_retention_secs as f64, f64::min);
+        if self.collection.trace_dir.is_some()
+            && shortest_retention < self.collection.trace_flush_interval_secs
+        {
+            return Err(ConfigError::Invalid(
+                "collection.trace_flush_interval_secs must not exceed the trace retention when collection.trace_dir is set".to_string(),
+            ));
+        }
+        self.measurement_units
+            .energy
+            .parse::<EnergyUnit>()
+            .map_err(|e| ConfigError::Invalid(format!("measurement_units.energy: {e}")))?;
+        self.measurement_units
+            .power
+            .parse::<PowerUnit>()
+            .map_err(|e| ConfigError::Invalid(format!("measurement_units.power: {e}")))?;
+        self.export
+            .timezone
+            .parse::<ExportTimezone>()
+            .map_err(|e| ConfigError::Invalid(format!("export.timezone: {e}")))?;
+        if let Some(resample) = &self.export.resample
+            && parse_interval(resample).is_none()
+        {
+            return Err(ConfigError::Invalid(format!(
+                "export.resample '{resample}' must be a positive interval such as 10s or 1m"
+            )));
+        }
+        for action in &self.export.on_shutdown {
+            if let ShutdownAction::PushMetrics(url) = action
+                && reqwest::Url::parse(url).map_or(true, |url| url.scheme() != "http")
+            {
+                return Err(ConfigError::Invalid(format!(
+                    "export.on_shutdown push_metrics URL '{url}' must be an http:// URL"
+                )));
+            }
+        }
+        if let Some(remote_write) = &self.export.remote_write {
+            remote_write
+                .validate()
+                .map_err(|e| ConfigError::Invalid(format!("export.remote_write: {e}")))?;
+        }
+        self.tui
+            .power_smoothing
+            .parse::<PowerSmoothing>()
+            .map_err(|e| ConfigError::Invalid(format!("tui.power_smoothing: {e}")))?;
+        for budget in &self.budgets {
+            budget.validate().map_err(|e| {
+                ConfigError::Invalid(format!("budget for {}: {e}", budget.target()))
+            })?;
+        }
+        for (name, factor) in [
+            (
+                "footprint.carbon_intensity_g_per_kwh",
+                self.footprint.carbon_intensity_g_per_kwh,
+            ),
+            ("footprint.price_per_kwh", self.footprint.price_per_kwh),
+        ] {
+            if factor.is_some_and(|factor| !(factor.is_finite() && factor >= 0.0)) {
+                return Err(ConfigError::Invalid(format!(
+                    "{name} must be a finite value of at least 0"
+                )));
+            }
+        }
+        if let Some(maps) = &self.footprint.electricity_maps {
+            if cfg!(not(feature = "carbon-api")) {
+                return Err(ConfigError::Invalid(
+                    "footprint.electricity_maps needs a build with the carbon-api feature"
+                        .to_string(),
+                ));
+            }
+            if maps.zone.is_empty() {
+                return Err(ConfigError::Invalid(
+                    "footprint.electricity_maps.zone must be set".to_string(),
+                ));
+            }
+            if !(maps.refresh_interval_secs.is_finite() && maps.refresh_interval_secs > 0.0) {
+                return Err(ConfigError::Invalid(
+                    "footprint.electricity_maps.refresh_interval_secs must be greater than 0"
+                        .to_string(),
+                ));
+            }
+        }
         Ok(())
     }
 
@@ -230,6 +1162,23 @@ impl EmtConfig {
     }
 }
 
+/// The contents of a config file as a YAML value, so YAML and TOML files
+/// merge and deserialize alike.
+fn parse_value(path: &Path, content: &str) -> Result<serde_yml::Value, ConfigError> {
+    if is_toml(path) {
+        let value: toml::Value =
+            toml::from_str(content).map_err(|e| ConfigError::Toml(e.to_string()))?;
+        serde_yml::to_value(value).map_err(|e| ConfigError::Toml(e.to_string()))
+    } else {
+        serde_yml::from_str(content).map_err(|e| ConfigError::Yaml(e.to_string()))
+    }
+}
+
+fn is_toml(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension == "toml")
+}
+
 fn validate_positive_finite(name: &str, value: f64) -> Result<(), ConfigError> {
     if value.is_finite() && value > 0.0 {
         Ok(())
@@ -262,6 +1211,9 @@ fn merge_yaml(base: serde_yml::Value, overlay: serde_yml::Value) -> serde_yml::V
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::budgets::BudgetAction;
+    use crate::smoothing::SmoothingWindow;
+    use crate::trace_recorder::{ParquetCodec, ParquetPartitioning};
     use std::io::Write;
     use tempfile::TempDir;
 
@@ -277,6 +1229,8 @@ mod tests {
         assert_eq!(config.tui.render_interval_millis, 2000);
         assert_eq!(config.measurement_units.energy, "Joules");
         assert_eq!(config.measurement_units.power, "Watts");
+        assert!(!config.sandbox.enabled);
+        assert!(config.sandbox.write_paths.is_empty());
     }
 
     #[test]
@@ -320,6 +1274,44 @@ mod tests {
         assert_eq!(config.collection.trace_flush_interval_secs, 5.0);
     }
 
+    #[test]
+    fn from_file_reads_toml_by_extension() {
+        let dir = TempDir::new().unwrap();
+        let file_path = dir.path().join("emt.toml");
+        std::fs::write(
+            &file_path,
+            "[collection]\nrate_hz = 25.0\ncollectors = [\"rapl\", \"meters\"]\nbatch_size = 50\n\n\
+             [[exec_collectors]]\nname = \"pdu\"\ncommand = [\"echo\"]\n",
+        )
+        .unwrap();
+
+        let config = EmtConfig::from_file(&file_path).unwrap();
+        assert_eq!(config.collection.rate_hz, 25.0);
+        assert_eq!(
+            config.collection.collectors,
+            [CollectorKind::Cpu, CollectorKind::Meters]
+        );
+        assert_eq!(config.collection.batch_size, Some(50));
+        assert_eq!(config.exec_collectors[0].name, "pdu");
+        assert_eq!(config.collection.trace_retention_secs, 3600);
+
+        std::fs::write(&file_path, "[collection]\nrate_hz = [").unwrap();
+        assert!(matches!(
+            EmtConfig::from_file(&file_path),
+            Err(ConfigError::Toml(_))
+        ));
+        std::fs::write(&file_path, "[collection]\nrate_hz = \"fast\"\n").unwrap();
+        assert!(matches!(
+            EmtConfig::from_file(&file_path),
+            Err(ConfigError::Toml(_))
+        ));
+        std::fs::write(&file_path, "[collection]\nbatch_size = 0\n").unwrap();
+        assert!(matches!(
+            EmtConfig::from_file(&file_path),
+            Err(ConfigError::Invalid(_))
+        ));
+    }
+
     #[test]
     fn from_file_rejects_non_positive_rate() {
         let dir = TempDir::new().unwrap();
@@ -349,6 +1341,124 @@ mod tests {
         let mut config = EmtConfig::default();
         config.tui.render_interval_millis = 0;
         assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+
+        let mut config = EmtConfig::default();
+        config.cpu_energy.model_idle_watts = 100.0;
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+
+        let mut config = EmtConfig::default();
+        config.cpu_energy.backends.clear();
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+
+        let mut config = EmtConfig::default();
+        config.collection.spike_filter = Some(SpikeFiltering {
+            limit_factor: 0.5,
+            ..SpikeFiltering::default()
+        });
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+    }
+
+    #[test]
+    fn device_retention_parses_from_yaml_and_rejects_non_positive_windows() {
+        let yaml = "collection:\n  device_retention:\n    - device: \"rapl:system:psys\"\n      retention_seconds: 604800\n";
+        let config: EmtConfig = serde_yml::from_str(yaml).unwrap();
+        config.validate().unwrap();
+        assert_eq!(
+            config.collection.device_retention,
+            vec![DeviceRetention {
+                device: "rapl:system:psys".to_string(),
+                retention_seconds: 604_800,
+            }]
+        );
+
+        let mut config = config;
+        config.collection.device_retention[0].retention_seconds = 0;
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+    }
+
+    #[test]
+    fn export_timezone_parses_from_yaml_and_rejects_unknown_names() {
+        let config: EmtConfig =
+            serde_yml::from_str("export:\n  timezone: Europe/Berlin\n").unwrap();
+        config.validate().unwrap();
+        assert_eq!(
+            config.export.timezone(),
+            ExportTimezone::Named(chrono_tz::Europe::Berlin)
+        );
+        assert_eq!(EmtConfig::default().export.timezone(), ExportTimezone::Utc);
+
+        let mut config = config;
+        config.export.timezone = "Nowhere/Special".to_string();
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+    }
+
+    #[test]
+    fn tui_power_smoothing_parses_and_rejects_malformed_specs() {
+        let config: EmtConfig = serde_yml::from_str("tui:\n  power_smoothing: median:5\n").unwrap();
+        config.validate().unwrap();
+        assert_eq!(
+            config.tui.power_smoothing(),
+            PowerSmoothing::Median(SmoothingWindow::Samples(5))
+        );
+
+        let mut config = config;
+        config.tui.power_smoothing = "mean".to_string();
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+    }
+
+    #[test]
+    fn budgets_parse_with_defaults_and_reject_missing_targets() {
+        let yaml = "budgets:\n  - task: \"train*\"\n    joules: 5000\n    action: duty_cycle\n";
+        let config: EmtConfig = serde_yml::from_str(yaml).unwrap();
+        config.validate().unwrap();
+        assert_eq!(config.budgets[0].action, BudgetAction::DutyCycle);
+        assert_eq!(config.budgets[0].duty_cycle, 0.5);
+
+        let mut config = config;
+        config.budgets[0].task = None;
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+    }
+
+    #[test]
+    fn device_priority_defaults_prefer_package_over_psys_and_can_be_overridden() {
+        let config = EmtConfig::default();
+        assert_eq!(config.device_priority.len(), 1);
+        assert_eq!(
+            config.device_priority[0].tiers[1],
+            vec!["rapl:system:psys".to_string()]
+        );
+
+        let yaml = "device_priority:\n  - domain: platform\n    tiers:\n      - [\"rapl:system:psys\"]\n      - [\"rapl:socket:*:package\"]\n";
+        let config: EmtConfig = serde_yml::from_str(yaml).unwrap();
+        assert_eq!(config.device_priority[0].tiers[0][0], "rapl:system:psys");
+        config.validate().unwrap();
+
+        let yaml = "device_priority:\n  - domain: empty\n    tiers: []\n";
+        let config: EmtConfig = serde_yml::from_str(yaml).unwrap();
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+    }
+
+    #[test]
+    fn cpu_energy_backends_parse_from_yaml() {
+        let yaml = "cpu_energy:\n  backends: [perf_event, model]\n  model_max_watts: 120.0\n";
+        let config: EmtConfig = serde_yml::from_str(yaml).unwrap();
+
+        assert_eq!(
+            config.cpu_energy.backends,
+            vec![CpuEnergyBackend::PerfEvent, CpuEnergyBackend::Model]
+        );
+        assert_eq!(config.cpu_energy.model_max_watts, 120.0);
+        assert_eq!(config.cpu_energy.model_idle_watts, 10.0);
+        assert_eq!(config.cpu_energy.attribution, CpuAttribution::System);
+        config.validate().unwrap();
+
+        let yaml = "cpu_energy:\n  attribution: per_core\n  domain_attribution: {psys: cpu}\n";
+        let config: EmtConfig = serde_yml::from_str(yaml).unwrap();
+        assert_eq!(config.cpu_energy.attribution, CpuAttribution::PerCore);
+        assert_eq!(
+            config.cpu_energy.domain_attribution,
+            BTreeMap::from([(DeviceClass::Psys, AttributionShare::Cpu)])
+        );
     }
 
     #[test]
@@ -409,6 +1519,169 @@ mod tests {
         assert_eq!(config.collection.trace_flush_interval_secs, 5.0);
     }
 
+    #[test]
+    fn backpressure_policy_is_read_from_yaml() {
+        let yaml = "collection:\n  channel_capacity: 4\n  backpressure: drop_oldest\n";
+        let config: EmtConfig = serde_yml::from_str(yaml).unwrap();
+
+        assert_eq!(config.collection.channel_capacity, 4);
+        assert_eq!(
+            config.collection.backpressure,
+            BackpressurePolicy::DropOldest
+        );
+        assert_eq!(
+            EmtConfig::default().collection.backpressure,
+            BackpressurePolicy::Block
+        );
+
+        let mut config = EmtConfig::default();
+        config.collection.channel_capacity = 0;
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn trace_dir_requires_retention_longer_than_flush_interval() {
+        let yaml = "collection:\n  trace_dir: /var/lib/emt/traces\n  trace_format: parquet\n  trace_flush_interval_secs: 30\n";
+        let mut config: EmtConfig = serde_yml::from_str(yaml).unwrap();
+
+        assert_eq!(
+            config.collection.trace_dir.as_deref(),
+            Some("/var/lib/emt/traces")
+        );
+        assert_eq!(config.collection.trace_format, TraceFormat::Parquet);
+        assert!(config.validate().is_ok());
+        config.collection.device_retention = vec![DeviceRetention {
+            device: "nvidia:*".to_string(),
+            retention_seconds: 10,
+        }];
+        assert!(config.validate().is_err());
+        config.collection.trace_dir = None;
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn parquet_options_are_read_from_yaml() {
+        let yaml = "collection:\n  trace_format: parquet\n  parquet:\n    partitioning: hour\n";
+        let config: EmtConfig = serde_yml::from_str(yaml).unwrap();
+
+        assert_eq!(
+            config.collection.parquet,
+            ParquetOptions {
+                partitioning: ParquetPartitioning::Hour,
+                compression: ParquetCodec::Zstd,
+            }
+        );
+        assert_eq!(
+            EmtConfig::default().collection.parquet,
+            ParquetOptions::default()
+        );
+    }
+
+    #[test]
+    fn trace_compression_level_is_read_and_range_checked() {
+        let yaml = "collection:\n  trace_dir: /var/lib/emt/traces\n  trace_compression_level: 3\n";
+        let mut config: EmtConfig = serde_yml::from_str(yaml).unwrap();
+
+        assert_eq!(config.collection.trace_compression_level, Some(3));
+        assert!(config.validate().is_ok());
+        assert_eq!(
+            EmtConfig::default().collection.trace_compression_level,
+            None
+        );
+        config.collection.trace_compression_level = Some(0);
+        assert!(config.validate().is_err());
+        config.collection.trace_compression_level = Some(23);
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn shutdown_actions_are_read_from_yaml() {
+        let yaml = "export:\n  on_shutdown:\n    - parquet: run.parquet\n    - summary_json: summary.json\n    - push_metrics: http://localhost:9091/metrics/job/emt\n";
+        let mut config: EmtConfig = serde_yml::from_str(yaml).unwrap();
+
+        assert_eq!(
+            config.export.on_shutdown,
+            [
+                ShutdownAction::Parquet("run.parquet".to_string()),
+                ShutdownAction::SummaryJson("summary.json".to_string()),
+                ShutdownAction::PushMetrics("http://localhost:9091/metrics/job/emt".to_string()),
+            ]
+        );
+        assert!(config.validate().is_ok());
+        assert!(EmtConfig::default().export.on_shutdown.is_empty());
+        assert_eq!(config.export.filter, ExportFilterConfig::default());
+
+        config.export.on_shutdown = vec![ShutdownAction::PushMetrics(
+            "https://localhost:9091/metrics/job/emt".to_string(),
+        )];
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn remote_write_is_read_from_yaml() {
+        let yaml = "export:\n  remote_write:\n    url: https://metrics.example/api/v1/write\n    username: emt\n";
+        let config: EmtConfig = serde_yml::from_str(yaml).unwrap();
+
+        let remote_write = config.export.remote_write.as_ref().unwrap();
+        assert_eq!(remote_write.interval_secs, 15.0);
+        assert_eq!(remote_write.max_attempts, 5);
+        assert_eq!(remote_write.password_env, "REMOTE_WRITE_PASSWORD");
+        assert!(config.validate().is_ok());
+        assert!(EmtConfig::default().export.remote_write.is_none());
+
+        let mut invalid = config.clone();
+        invalid.export.remote_write.as_mut().unwrap().url = "localhost:9090".to_string();
+        assert!(invalid.validate().is_err());
+        let mut invalid = config.clone();
+        invalid.export.remote_write.as_mut().unwrap().max_attempts = 0;
+        assert!(invalid.validate().is_err());
+        let mut invalid = config;
+        invalid
+            .export
+            .remote_write
+            .as_mut()
+            .unwrap()
+            .bearer_token_env = Some("TOKEN".to_string());
+        assert!(invalid.validate().is_err());
+    }
+
+    #[test]
+    fn export_filter_is_read_from_yaml() {
+        let yaml = "export:\n  filter:\n    devices: [\"rapl:*\"]\n    users: [alice]\n    columns: [timestamp, pid, energy]\n";
+        let config: EmtConfig = serde_yml::from_str(yaml).unwrap();
+
+        let filter = config.export.filter.filter();
+        assert_eq!(filter.devices, ["rapl:*"]);
+        assert_eq!(filter.users, ["alice"]);
+        assert!(filter.pids.is_empty());
+        assert_eq!(config.export.filter.columns, ["timestamp", "pid", "energy"]);
+    }
+
+    #[test]
+    fn export_resample_must_be_a_positive_interval() {
+        let mut config: EmtConfig = serde_yml::from_str("export:\n  resample: 1m\n").unwrap();
+
+        assert_eq!(config.export.resample(), Some(Duration::from_secs(60)));
+        assert!(config.validate().is_ok());
+        assert_eq!(EmtConfig::default().export.resample(), None);
+
+        config.export.resample = Some("0s".to_string());
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn footprint_factors_must_not_be_negative() {
+        let yaml = "footprint:\n  carbon_intensity_g_per_kwh: 350\n  price_per_kwh: 0.3\n  currency: EUR\n";
+        let mut config: EmtConfig = serde_yml::from_str(yaml).unwrap();
+
+        assert_eq!(config.footprint.carbon_intensity_g_per_kwh, Some(350.0));
+        assert_eq!(config.footprint.currency.as_deref(), Some("EUR"));
+        assert!(config.validate().is_ok());
+
+        config.footprint.price_per_kwh = Some(-0.1);
+        assert!(config.validate().is_err());
+    }
+
     #[test]
     fn missing_files_return_defaults() {
         // load() with no files on disk should return defaults without panicking
@@ -419,6 +1692,147 @@ mod tests {
         assert_eq!(config.discovery.scan_interval_secs, 2.0);
     }
 
+    #[test]
+    fn parses_and_validates_exec_collectors() {
+        let config: EmtConfig = serde_yml::from_str(
+            "exec_collectors:\n  - name: pdu\n    command: [read-pdu, --outlet, '3']\n",
+        )
+        .unwrap();
+        assert_eq!(config.exec_collectors[0].command.len(), 3);
+        assert_eq!(config.exec_collectors[0].interval_secs, 5.0);
+        assert!(config.validate().is_ok());
+
+        let mut invalid = config.clone();
+        invalid.exec_collectors[0].name = "pdu:3".to_string();
+        assert!(invalid.validate().is_err());
+        let mut invalid = config.clone();
+        invalid.exec_collectors[0].command.clear();
+        assert!(invalid.validate().is_err());
+        let mut invalid = config;
+        invalid.exec_collectors[0].interval_secs = 0.0;
+        assert!(invalid.validate().is_err());
+    }
+
+    #[test]
+    fn parses_and_validates_http_collectors() {
+        let yaml = "http_collectors:
+  - name: pdu
+    url: http://pdu.local:9100/metrics
+    series:
+      - device: outlet1
+        metric: pdu_energy_kwh
+        labels: {outlet: '1'}
+        quantity: energy
+        scale: 3600000
+  - name: board
+    url: http://10.0.0.5/status
+    format: json
+    interval_secs: 1
+    series:
+      - {device: board, path: '$.power.watts', quantity: power}
+";
+        let config: EmtConfig = serde_yml::from_str(yaml).unwrap();
+        assert_eq!(
+            config.http_collectors[0].format,
+            HttpScrapeFormat::Prometheus
+        );
+        assert_eq!(config.http_collectors[0].series[0].labels["outlet"], "1");
+        assert_eq!(config.http_collectors[1].series[0].scale, 1.0);
+        assert!(config.validate().is_ok());
+
+        let mut invalid = config.clone();
+        invalid.http_collectors[0].url = "https://pdu.local/metrics".to_string();
+        assert!(invalid.validate().is_err());
+        let mut invalid = config.clone();
+        invalid.http_collectors[0].series[0].metric = None;
+        assert!(invalid.validate().is_err());
+        let mut invalid = config;
+        invalid.http_collectors[1].series[0].path = Some("power.watts".to_string());
+        assert!(invalid.validate().is_err());
+    }
+
+    #[test]
+    fn parses_and_validates_bmc_collectors() {
+        let yaml = "bmc_collectors:
+  - name: local
+    ipmi: {}
+  - name: node7
+    interval_secs: 10
+    redfish:
+      url: http://10.0.0.7/redfish/v1/Chassis/1/Power
+      username: monitor
+";
+        let config: EmtConfig = serde_yml::from_str(yaml).unwrap();
+        assert_eq!(
+            config.bmc_collectors[0].source,
+            BmcSource::Ipmi(IpmiConfig::default())
+        );
+        let BmcSource::Redfish(redfish) = &config.bmc_collectors[1].source else {
+            panic!("expected a Redfish BMC");
+        };
+        assert_eq!(redfish.password_env, "REDFISH_PASSWORD");
+        assert!(!redfish.insecure);
+        assert!(config.validate().is_ok());
+
+        let mut invalid = config.clone();
+        invalid.bmc_collectors[0].name = "rack:1".to_string();
+        assert!(invalid.validate().is_err());
+        let mut invalid = config.clone();
+        invalid.bmc_collectors[1].source = BmcSource::Redfish(RedfishConfig {
+            url: "bmc.local/redfish".to_string(),
+            ..redfish.clone()
+        });
+        assert!(invalid.validate().is_err());
+    }
+
+    #[test]
+    fn network_energy_is_opt_in() {
+        assert_eq!(EmtConfig::default().network, None);
+        let config: EmtConfig = serde_yml::from_str("network: {}").unwrap();
+        assert_eq!(config.network, Some(NetworkEnergyConfig::default()));
+
+        let mut invalid = config.clone();
+        invalid.network = Some(NetworkEnergyConfig {
+            joules_per_byte: f64::NAN,
+            ..NetworkEnergyConfig::default()
+        });
+        assert!(invalid.validate().is_err());
+    }
+
+    #[test]
+    fn storage_coefficients_default_per_device_type() {
+        let yaml = "storage:
+  joules_per_gb: {hdd: 35}
+  devices: [\"sd*\"]
+";
+        let config: EmtConfig = serde_yml::from_str(yaml).unwrap();
+        let storage = config.storage.as_ref().unwrap();
+        assert_eq!(storage.joules_per_gb.hdd, 35.0);
+        assert_eq!(storage.joules_per_gb.nvme, 2.0);
+        assert!(config.validate().is_ok());
+
+        let mut invalid = config.clone();
+        invalid.storage.as_mut().unwrap().joules_per_gb.ssd = -1.0;
+        assert!(invalid.validate().is_err());
+    }
+
+    #[test]
+    fn validates_derived_devices() {
+        let yaml = "derived_devices:
+  - name: cpu_total
+    expression: sum(rapl:socket:*:package)
+  - name: other
+    expression: rapl:system:psys - cpu_total - rapl:system:dram
+";
+        let config: EmtConfig = serde_yml::from_str(yaml).unwrap();
+        assert_eq!(config.derived_devices.len(), 2);
+        assert!(config.validate().is_ok());
+
+        let mut invalid = config;
+        invalid.derived_devices[1].expression = "rapl:system:psys -".to_string();
+        assert!(invalid.validate().is_err());
+    }
+
     #[test]
     fn user_config_path_is_under_config_dir() {
         if let Some(path) = EmtConfig::user_config_path() {
@@ -446,4 +1860,15 @@ mod tests {
 
         assert!((units.convert_energy_from_joules(1.0) - 1_000_000.0).abs() < 1e-9);
     }
+
+    #[test]
+    fn rejects_unknown_measurement_units() {
+        let mut config = EmtConfig::default();
+        config.measurement_units.energy = "J".to_string();
+        assert!(config.validate().is_err());
+
+        let mut config = EmtConfig::default();
+        config.measurement_units.power = "hp".to_string();
+        assert!(config.validate().is_err());
+    }
 }
@@ -0,0 +1,416 @@
+/// Container Module
+///
+/// Recognizes processes of Docker, Podman, containerd, CRI-O and LXC/LXD
+/// containers from their cgroup path, including rootless Podman below
+/// `user@<uid>.service`, and looks up the name and image each runtime keeps
+/// in its state on disk. Containers of Kubernetes pods carry the pod, whose
+/// uid the kubelet puts in the cgroup path and whose namespace and name it
+/// puts in the pod's log directory.
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+use users::os::unix::UserExt;
+
+/// Characters of the id shown for containers without a name.
+const SHORT_ID_LEN: usize = 12;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContainerRuntime {
+    Docker,
+    Podman,
+    Containerd,
+    CriO,
+    Lxc,
+}
+
+impl ContainerRuntime {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Docker => "docker",
+            Self::Podman => "podman",
+            Self::Containerd => "containerd",
+            Self::CriO => "cri_o",
+            Self::Lxc => "lxc",
+        }
+    }
+}
+
+/// Container a process runs in.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ContainerInfo {
+    pub runtime: ContainerRuntime,
+    /// Container id; the container name for LXC
+    pub id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+    /// User running a rootless container
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rootless_uid: Option<u32>,
+    /// Kubernetes pod as `namespace/name`, or its uid while the name is
+    /// unknown
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pod: Option<String>,
+}
+
+impl ContainerInfo {
+    fn new(runtime: ContainerRuntime, id: &str) -> Self {
+        Self {
+            runtime,
+            id: id.to_string(),
+            name: None,
+            image: None,
+            rootless_uid: None,
+            pod: None,
+        }
+    }
+
+    /// Grouping key, unique per runtime and container.
+    pub fn key(&self) -> String {
+        format!("{}:{}", self.runtime.as_str(), self.id)
+    }
+
+    /// The container name, else the runtime and a short id.
+    pub fn label(&self) -> String {
+        match &self.name {
+            Some(name) => name.clone(),
+            None => {
+                let short: String = self.id.chars().take(SHORT_ID_LEN).collect();
+                format!("{} {short}", self.runtime.as_str())
+            }
+        }
+    }
+}
+
+/// The outermost container in `cgroup_path`, with the path segment naming
+/// it. Name and image are not looked up.
+pub(crate) fn detect_container(cgroup_path: &str) -> Option<(&str, ContainerInfo)> {
+    let segments: Vec<&str> = cgroup_path
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .collect();
+    let rootless_uid = segments.iter().find_map(|segment| {
+        segment
+            .strip_prefix("user@")?
+            .strip_suffix(".service")?
+            .parse()
+            .ok()
+    });
+    let pod = segments.iter().find_map(|segment| pod_uid(segment));
+    segments.iter().enumerate().find_map(|(index, &segment)| {
+        let mut container = container_segment(segment, segments.get(index + 1).copied())?;
+        if container.runtime == ContainerRuntime::Podman {
+            container.rootless_uid = rootless_uid;
+        }
+        container.pod = pod.clone();
+        Some((segment, container))
+    })
+}
+
+/// Uid of the pod named by `segment`: `kubepods-burstable-pod<uid>.slice`
+/// with the systemd driver, where the uid's dashes become underscores, or
+/// `pod<uid>` with the cgroupfs driver.
+fn pod_uid(segment: &str) -> Option<String> {
+    let unit = segment.strip_suffix(".slice").unwrap_or(segment);
+    let uid = unit
+        .strip_prefix("pod")
+        .or_else(|| unit.rsplit_once("-pod").map(|(_, uid)| uid))?;
+    let is_uid = uid.len() >= SHORT_ID_LEN
+        && uid
+            .chars()
+            .all(|char| char.is_ascii_hexdigit() || char == '_' || char == '-');
+    is_uid.then(|| uid.replace('_', "-"))
+}
+
+/// Container named by `segment`; cgroupfs layouts name it in `next`.
+fn container_segment(segment: &str, next: Option<&str>) -> Option<ContainerInfo> {
+    const SCOPES: [(&str, ContainerRuntime); 4] = [
+        ("docker-", ContainerRuntime::Docker),
+        ("libpod-", ContainerRuntime::Podman),
+        ("cri-containerd-", ContainerRuntime::Containerd),
+        ("crio-", ContainerRuntime::CriO),
+    ];
+    let unit = segment.strip_suffix(".scope").unwrap_or(segment);
+    for (prefix, runtime) in SCOPES {
+        if let Some(id) = unit.strip_prefix(prefix)
+            && is_container_id(id)
+        {
+            return Some(ContainerInfo::new(runtime, id));
+        }
+    }
+    if let Some(name) = segment.strip_prefix("lxc.payload.")
+        && !name.is_empty()
+    {
+        return Some(lxc(name));
+    }
+    match (segment, next) {
+        ("docker", Some(id)) if is_container_id(id) => {
+            Some(ContainerInfo::new(ContainerRuntime::Docker, id))
+        }
+        ("lxc", Some(name)) => Some(lxc(name)),
+        _ => None,
+    }
+}
+
+fn lxc(name: &str) -> ContainerInfo {
+    ContainerInfo {
+        name: Some(name.to_string()),
+        ..ContainerInfo::new(ContainerRuntime::Lxc, name)
+    }
+}
+
+/// Hex ids of at least [`SHORT_ID_LEN`] digits; conmon monitors are not
+/// containers.
+fn is_container_id(id: &str) -> bool {
+    id.len() >= SHORT_ID_LEN && id.chars().all(|char| char.is_ascii_hexdigit())
+}
+
+/// Where the runtimes keep container state.
+#[derive(Debug, Clone)]
+pub struct ContainerMetadata {
+    docker_root: PathBuf,
+    podman_root: PathBuf,
+    /// Kubelet log directory, with a `<namespace>_<name>_<uid>` directory
+    /// per pod
+    pods_root: PathBuf,
+}
+
+impl Default for ContainerMetadata {
+    fn default() -> Self {
+        Self {
+            docker_root: PathBuf::from("/var/lib/docker"),
+            podman_root: PathBuf::from("/var/lib/containers/storage"),
+            pods_root: PathBuf::from("/var/log/pods"),
+        }
+    }
+}
+
+impl ContainerMetadata {
+    /// State under other data roots than the runtimes' defaults; rootless
+    /// Podman storage is always found in the user's home.
+    pub fn with_roots(docker_root: &Path, podman_root: &Path) -> Self {
+        Self {
+            docker_root: docker_root.to_path_buf(),
+            podman_root: podman_root.to_path_buf(),
+            ..Self::default()
+        }
+    }
+
+    /// Look pods up under `pods_root` instead of `/var/log/pods`.
+    pub fn with_pods_root(mut self, pods_root: &Path) -> Self {
+        self.pods_root = pods_root.to_path_buf();
+        self
+    }
+
+    /// Fill in the name and image of `container` where its runtime records
+    /// them, and the name of its pod. containerd and CRI-O keep container
+    /// names behind their APIs, so their containers stay unnamed.
+    pub fn resolve(&self, container: &mut ContainerInfo) {
+        if let Some(pod) = &container.pod
+            && let Some(name) = self.pod_name(pod)
+        {
+            container.pod = Some(name);
+        }
+        let found = match container.runtime {
+            ContainerRuntime::Docker => fs::read_to_string(
+                self.docker_root
+                    .join("containers")
+                    .join(&container.id)
+                    .join("config.v2.json"),
+            )
+            .ok()
+            .and_then(|contents| docker_metadata(&contents)),
+            ContainerRuntime::Podman => self
+                .podman_storage(container.rootless_uid)
+                .and_then(|storage| {
+                    fs::read_to_string(storage.join("overlay-containers/containers.json")).ok()
+                })
+                .and_then(|contents| podman_metadata(&contents, &container.id)),
+            _ => None,
+        };
+        if let Some((name, image)) = found {
+            container.name = name.or(container.name.take());
+            container.image = image;
+        }
+    }
+
+    /// `namespace/name` of the pod with `uid`.
+    fn pod_name(&self, uid: &str) -> Option<String> {
+        fs::read_dir(&self.pods_root)
+            .ok()?
+            .flatten()
+            .find_map(|entry| {
+                let directory = entry.file_name().into_string().ok()?;
+                let (namespace, rest) = directory.split_once('_')?;
+                let name = rest.strip_suffix(uid)?.strip_suffix('_')?;
+                Some(format!("{namespace}/{name}"))
+            })
+    }
+
+    /// Podman's storage: per user for rootless containers.
+    fn podman_storage(&self, rootless_uid: Option<u32>) -> Option<PathBuf> {
+        match rootless_uid {
+            None => Some(self.podman_root.clone()),
+            Some(uid) => {
+                let user = users::get_user_by_uid(uid)?;
+                Some(user.home_dir().join(".local/share/containers/storage"))
+            }
+        }
+    }
+}
+
+type NameAndImage = (Option<String>, Option<String>);
+
+/// Name and image of a Docker `config.v2.json`.
+fn docker_metadata(contents: &str) -> Option<NameAndImage> {
+    let config: Value = serde_json::from_str(contents).ok()?;
+    let name = config["Name"]
+        .as_str()
+        .map(|name| name.trim_start_matches('/').to_string());
+    let image = config["Config"]["Image"].as_str().map(str::to_string);
+    Some((name, image))
+}
+
+/// Name and image of container `id` in Podman's `containers.json`.
+fn podman_metadata(contents: &str, id: &str) -> Option<NameAndImage> {
+    let containers: Vec<Value> = serde_json::from_str(contents).ok()?;
+    let container = containers
+        .iter()
+        .find(|container| container["id"].as_str() == Some(id))?;
+    // Podman nests its own metadata as a JSON string.
+    let metadata: Value = container["metadata"]
+        .as_str()
+        .and_then(|metadata| serde_json::from_str(metadata).ok())
+        .unwrap_or_default();
+    let name = metadata["name"]
+        .as_str()
+        .or_else(|| container["names"][0].as_str())
+        .map(str::to_string);
+    let image = metadata["image-name"].as_str().map(str::to_string);
+    Some((name, image))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ID: &str = "0123456789abcdef0123456789abcdef";
+
+    fn detected(path: &str) -> Option<ContainerInfo> {
+        detect_container(path).map(|(_, container)| container)
+    }
+
+    #[test]
+    fn detects_runtimes_from_cgroup_paths() {
+        let docker = detected(&format!("/system.slice/docker-{ID}.scope")).unwrap();
+        assert_eq!(
+            (docker.runtime, docker.id.as_str()),
+            (ContainerRuntime::Docker, ID)
+        );
+        assert_eq!(docker.label(), "docker 0123456789ab");
+        assert_eq!(
+            detected(&format!("/docker/{ID}")).unwrap().runtime,
+            ContainerRuntime::Docker
+        );
+
+        let rootless = detected(&format!(
+            "/user.slice/user-1000.slice/user@1000.service/user.slice/libpod-{ID}.scope/container"
+        ))
+        .unwrap();
+        assert_eq!(rootless.runtime, ContainerRuntime::Podman);
+        assert_eq!(rootless.rootless_uid, Some(1000));
+        assert_eq!(
+            detected(&format!("/machine.slice/libpod-{ID}.scope"))
+                .unwrap()
+                .rootless_uid,
+            None
+        );
+        assert_eq!(
+            detected(&format!("/machine.slice/libpod-conmon-{ID}.scope")),
+            None
+        );
+
+        let (segment, lxc) =
+            detect_container("/lxc.payload.web/system.slice/nginx.service").unwrap();
+        assert_eq!(segment, "lxc.payload.web");
+        assert_eq!(lxc.key(), "lxc:web");
+        assert_eq!(lxc.label(), "web");
+        assert_eq!(detected("/lxc/db/init.scope").unwrap().id, "db");
+        assert_eq!(
+            detected(&format!(
+                "/kubepods.slice/kubepods-pod1.slice/crio-{ID}.scope"
+            ))
+            .unwrap()
+            .runtime,
+            ContainerRuntime::CriO
+        );
+        assert_eq!(detected("/system.slice/nginx.service"), None);
+    }
+
+    #[test]
+    fn resolves_the_pod_of_kubernetes_containers() {
+        let uid = "5f3c2a10-8b7e-4d21-9f00-0123456789ab";
+        let systemd = detected(&format!(
+            "/kubepods.slice/kubepods-burstable.slice/kubepods-burstable-pod{}.slice/cri-containerd-{ID}.scope",
+            uid.replace('-', "_")
+        ))
+        .unwrap();
+        assert_eq!(systemd.pod.as_deref(), Some(uid));
+        let docker = detected(&format!("/kubepods/pod{uid}/docker/{ID}")).unwrap();
+        assert_eq!(docker.pod.as_deref(), Some(uid));
+        assert_eq!(
+            detected(&format!("/system.slice/docker-{ID}.scope"))
+                .unwrap()
+                .pod,
+            None
+        );
+
+        let root = tempfile::tempdir().unwrap();
+        fs::create_dir_all(root.path().join(format!("ml_train-7c9f_{uid}"))).unwrap();
+        fs::create_dir_all(root.path().join("kube-system_coredns_other")).unwrap();
+        let resolver = ContainerMetadata::default().with_pods_root(root.path());
+        let mut container = systemd;
+        resolver.resolve(&mut container);
+        assert_eq!(container.pod.as_deref(), Some("ml/train-7c9f"));
+    }
+
+    #[test]
+    fn resolves_docker_and_podman_names_and_images() {
+        let root = tempfile::tempdir().unwrap();
+        let docker_root = root.path().join("docker");
+        let podman_root = root.path().join("podman");
+        fs::create_dir_all(docker_root.join("containers").join(ID)).unwrap();
+        fs::write(
+            docker_root
+                .join("containers")
+                .join(ID)
+                .join("config.v2.json"),
+            r#"{"Name":"/web","Config":{"Image":"nginx:1.27"}}"#,
+        )
+        .unwrap();
+        fs::create_dir_all(podman_root.join("overlay-containers")).unwrap();
+        let metadata = r#"{\"image-name\":\"docker.io/library/redis:7\",\"name\":\"cache\"}"#;
+        fs::write(
+            podman_root.join("overlay-containers/containers.json"),
+            format!(r#"[{{"id":"{ID}","names":["cache"],"metadata":"{metadata}"}}]"#),
+        )
+        .unwrap();
+        let resolver = ContainerMetadata::with_roots(&docker_root, &podman_root);
+
+        let mut docker = ContainerInfo::new(ContainerRuntime::Docker, ID);
+        resolver.resolve(&mut docker);
+        let mut podman = ContainerInfo::new(ContainerRuntime::Podman, ID);
+        resolver.resolve(&mut podman);
+        let mut unknown = ContainerInfo::new(ContainerRuntime::Podman, "feedfacefeedface");
+        resolver.resolve(&mut unknown);
+
+        assert_eq!(docker.name.as_deref(), Some("web"));
+        assert_eq!(docker.image.as_deref(), Some("nginx:1.27"));
+        assert_eq!(podman.label(), "cache");
+        assert_eq!(podman.image.as_deref(), Some("docker.io/library/redis:7"));
+        assert_eq!(unknown.name, None);
+    }
+}
@@ -0,0 +1,762 @@
+/// Convert Module
+///
+/// Reads traces in any format EMT writes and writes them in another, with
+/// optional filtering and resampling, so a trace is not tied to the format
+/// its run happened to use. The format follows the file extension:
+///
+/// - `.csv`: versioned trace CSV as written by the CSV recorder; compressed
+///   segments (`.csv.zst`) are read too.
+/// - `.parquet`
+/// - `.jsonl` or `.ndjson`: one JSON object per row.
+/// - `.arrow`, `.ipc` or `.feather`: Arrow IPC files.
+/// - `.sqlite` or `.sqlite3`: a SQLite database with one `trace` table,
+///   whose timestamps are text as in CSV.
+///
+/// Sealed files (`.enc`) are opened with the configured key, and outputs are
+/// sealed when a key is configured. An input directory is read as recorder
+/// output: its `trace_*.csv` segments and any other supported files below
+/// it, such as the parts of a Parquet dataset.
+use crate::device_labels::DeviceLabels;
+use crate::device_registry::DEVICE_ID_COLUMN;
+use crate::encryption::{ENCRYPTED_EXTENSION, EncryptionError, TraceCipher, is_encrypted};
+use crate::energy_group::{
+    CO2_COLUMN, CUMULATIVE_ENERGY_COLUMN, ORIGINAL_ENERGY_COLUMN, QUALITY_COLUMN,
+    RAW_COUNTER_COLUMN, SAMPLE_DURATION_COLUMN,
+};
+use crate::query::{Filter, TimeRange, any_of};
+use crate::schema::{SchemaError, read_trace_csv_with_cipher, trace_csv_version_line};
+use crate::spike_filter::RecordQuality;
+use crate::utils::pattern::matches_any;
+use crate::utils::time::{
+    ExportTimezone, MONOTONIC_COLUMN, TIMESTAMP_COLUMN, monotonic_dtype, normalize_timestamps,
+    parse_export_timestamp_micros, timestamp_dtype, timestamp_micros,
+};
+use polars::prelude::*;
+use rusqlite::types::Value;
+use rusqlite::{Connection, params_from_iter};
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::{Cursor, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use thiserror::Error;
+
+/// Columns summed when resampling.
+const SUMMED_COLUMNS: [&str; 3] = ["energy", CO2_COLUMN, SAMPLE_DURATION_COLUMN];
+/// Columns averaged when resampling are utilization readings, whose names
+/// contain this.
+const MEAN_COLUMN_MARKER: &str = "utilization";
+/// Columns that keep the value of the last row of a bucket when
+/// resampling.
+const LAST_COLUMNS: [&str; 4] = [
+    TIMESTAMP_COLUMN,
+    MONOTONIC_COLUMN,
+    CUMULATIVE_ENERGY_COLUMN,
+    RAW_COUNTER_COLUMN,
+];
+const BUCKET_COLUMN: &str = "__bucket";
+/// Table holding the trace in SQLite databases.
+pub const SQLITE_TABLE: &str = "trace";
+
+/// Trace file format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceFileFormat {
+    Csv,
+    Parquet,
+    JsonLines,
+    ArrowIpc,
+    Sqlite,
+}
+
+impl TraceFileFormat {
+    /// Format named by the extension of `path`, looking past `.enc` and, for
+    /// CSV, `.zst`.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_str()?.to_ascii_lowercase();
+        let name = name
+            .strip_suffix(&format!(".{ENCRYPTED_EXTENSION}"))
+            .unwrap_or(&name);
+        if name.ends_with(".csv.zst") {
+            return Some(Self::Csv);
+        }
+        match name.rsplit_once('.')?.1 {
+            "csv" => Some(Self::Csv),
+            "parquet" => Some(Self::Parquet),
+            "jsonl" | "ndjson" => Some(Self::JsonLines),
+            "arrow" | "ipc" | "feather" => Some(Self::ArrowIpc),
+            "sqlite" | "sqlite3" => Some(Self::Sqlite),
+            _ => None,
+        }
+    }
+}
+
+/// Errors raised while converting a trace.
+#[derive(Debug, Error)]
+pub enum ConvertError {
+    #[error("{0}: unsupported trace format")]
+    UnsupportedFormat(PathBuf),
+    #[error("no trace files found in {0}")]
+    NoTraces(PathBuf),
+    #[error("resampling interval must be at least one microsecond")]
+    InvalidInterval,
+    #[error("trace has no column '{0}'")]
+    UnknownColumn(String),
+    #[error(transparent)]
+    Schema(#[from] SchemaError),
+    #[error("Polars error: {0}")]
+    Polars(#[from] PolarsError),
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Encryption(#[from] EncryptionError),
+    #[error("SQLite error: {0}")]
+    Sqlite(#[from] rusqlite::Error),
+}
+
+/// What [`convert_trace`] keeps and how it writes it.
+#[derive(Debug, Clone, Default)]
+pub struct ConvertOptions {
+    pub range: TimeRange,
+    /// Device patterns match the `device` column; user and task filters need
+    /// `user` and `task` columns, as in joined exports.
+    pub filter: Filter,
+    /// Resample into buckets of this length, see [`resample_trace`].
+    pub resample: Option<Duration>,
+    /// Columns written, in this order; empty writes every column
+    pub columns: Vec<String>,
+    /// Time zone of CSV and JSON Lines timestamps
+    pub timezone: ExportTimezone,
+    /// Aliases and labels written for devices
+    pub device_labels: DeviceLabels,
+}
+
+/// Convert the trace at `input` (a file or directory) to `output`, whose
+/// extension picks the format. Returns the number of rows written.
+pub fn convert_trace(
+    input: &Path,
+    output: &Path,
+    options: &ConvertOptions,
+    cipher: Option<&TraceCipher>,
+) -> Result<usize, ConvertError> {
+    // Fail before reading when the output format is unknown.
+    format_of(output)?;
+    if options.resample.is_some_and(|every| every.as_micros() == 0) {
+        return Err(ConvertError::InvalidInterval);
+    }
+
+    let trace = read_trace(input, cipher)?;
+    let mut trace = filter_trace(trace, options.range, &options.filter)?;
+    if let Some(every) = options.resample {
+        trace = resample_trace(trace, every)?;
+    }
+    let trace = options.device_labels.apply_to_trace(trace)?;
+    let mut trace = project_trace(trace, &options.columns)?;
+    write_trace(&mut trace, output, options.timezone, cipher)?;
+    Ok(trace.height())
+}
+
+/// Read a trace file, or every trace file below a directory, ordered by
+/// timestamp.
+pub fn read_trace(path: &Path, cipher: Option<&TraceCipher>) -> Result<DataFrame, ConvertError> {
+    if !path.is_dir() {
+        return read_trace_file(path, format_of(path)?, cipher);
+    }
+
+    let mut files = Vec::new();
+    collect_trace_files(path, &mut files)?;
+    if files.is_empty() {
+        return Err(ConvertError::NoTraces(path.to_path_buf()));
+    }
+    files.sort_by(|a, b| a.0.cmp(&b.0));
+    let frames = files
+        .iter()
+        .map(|(file, format)| read_trace_file(file, *format, cipher).map(IntoLazy::lazy))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(sort_by_timestamp(concat(frames, UnionArgs::default())?).collect()?)
+}
+
+fn format_of(path: &Path) -> Result<TraceFileFormat, ConvertError> {
+    TraceFileFormat::from_path(path)
+        .ok_or_else(|| ConvertError::UnsupportedFormat(path.to_path_buf()))
+}
+
+fn collect_trace_files(
+    dir: &Path,
+    files: &mut Vec<(PathBuf, TraceFileFormat)>,
+) -> std::io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_trace_files(&path, files)?;
+            continue;
+        }
+        let Some(format) = TraceFileFormat::from_path(&path) else {
+            continue;
+        };
+        // Skip the device, region and event tables next to CSV segments.
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        if format == TraceFileFormat::Csv && !name.starts_with("trace_") {
+            continue;
+        }
+        files.push((path, format));
+    }
+    Ok(())
+}
+
+fn read_trace_file(
+    path: &Path,
+    format: TraceFileFormat,
+    cipher: Option<&TraceCipher>,
+) -> Result<DataFrame, ConvertError> {
+    let frame = match format {
+        TraceFileFormat::Csv => return Ok(read_trace_csv_with_cipher(path, cipher)?),
+        TraceFileFormat::Parquet => ParquetReader::new(open_file(path, cipher)?).finish()?,
+        TraceFileFormat::ArrowIpc => IpcReader::new(open_file(path, cipher)?).finish()?,
+        TraceFileFormat::JsonLines => restore_text_columns(
+            JsonReader::new(open_file(path, cipher)?)
+                .with_json_format(JsonFormat::JsonLines)
+                .finish()?,
+        )?,
+        TraceFileFormat::Sqlite => restore_text_columns(read_sqlite(open_file(path, cipher)?)?)?,
+    };
+    Ok(normalize_timestamps(frame)?)
+}
+
+fn open_file(path: &Path, cipher: Option<&TraceCipher>) -> Result<Cursor<Vec<u8>>, ConvertError> {
+    let mut bytes = fs::read(path)?;
+    if is_encrypted(&bytes) {
+        bytes = cipher.ok_or(SchemaError::KeyRequired)?.open(&bytes)?;
+    }
+    Ok(Cursor::new(bytes))
+}
+
+/// Render timestamps in `timezone` and monotonic readings as integer
+/// nanoseconds, for text formats.
+fn text_columns(trace: &DataFrame, timezone: ExportTimezone) -> PolarsResult<DataFrame> {
+    let mut trace = trace.clone();
+    if trace.column(TIMESTAMP_COLUMN).is_ok() {
+        let formatted = timestamp_micros(&trace)?
+            .iter()
+            .map(|ts| ts.map(|ts| timezone.format_micros(ts)))
+            .collect::<Vec<_>>();
+        trace.with_column(Column::new(TIMESTAMP_COLUMN.into(), formatted))?;
+    }
+    if let Ok(monotonic) = trace.column(MONOTONIC_COLUMN) {
+        let monotonic = monotonic.cast(&DataType::Int64)?;
+        trace.with_column(monotonic)?;
+    }
+    Ok(trace)
+}
+
+/// Inverse of [`text_columns`], also restoring the id column types JSON
+/// does not carry.
+fn restore_text_columns(mut trace: DataFrame) -> PolarsResult<DataFrame> {
+    if let Ok(timestamps) = trace.column(TIMESTAMP_COLUMN)
+        && let Ok(values) = timestamps.str()
+    {
+        let parsed = values
+            .iter()
+            .map(|value| value.and_then(parse_export_timestamp_micros))
+            .collect::<Vec<_>>();
+        trace
+            .with_column(Column::new(TIMESTAMP_COLUMN.into(), parsed).cast(&timestamp_dtype())?)?;
+    }
+    for (name, dtype) in [
+        (MONOTONIC_COLUMN, monotonic_dtype()),
+        ("pid", DataType::UInt32),
+        (DEVICE_ID_COLUMN, DataType::UInt32),
+    ] {
+        if let Ok(column) = trace.column(name) {
+            let column = column.cast(&dtype)?;
+            trace.with_column(column)?;
+        }
+    }
+    Ok(trace)
+}
+
+/// Write `trace` to `path` in the format its extension names. CSV and JSON
+/// Lines timestamps are rendered in `timezone`.
+pub fn write_trace(
+    trace: &mut DataFrame,
+    path: &Path,
+    timezone: ExportTimezone,
+    cipher: Option<&TraceCipher>,
+) -> Result<(), ConvertError> {
+    let mut contents = Vec::new();
+    match format_of(path)? {
+        TraceFileFormat::Csv => {
+            contents = trace_csv_version_line().into_bytes();
+            CsvWriter::new(&mut contents).finish(&mut text_columns(trace, timezone)?)?;
+        }
+        TraceFileFormat::JsonLines => write_json_lines(trace, &mut contents, timezone)?,
+        TraceFileFormat::Parquet => {
+            ParquetWriter::new(&mut contents).finish(trace)?;
+        }
+        TraceFileFormat::ArrowIpc => IpcWriter::new(&mut contents).finish(trace)?,
+        TraceFileFormat::Sqlite => contents = write_sqlite(&text_columns(trace, timezone)?)?,
+    }
+    if let Some(cipher) = cipher {
+        contents = cipher.seal(&contents)?;
+    }
+    fs::write(path, contents)?;
+    Ok(())
+}
+
+/// Write `trace` to `writer` as JSON Lines, in the layout of `.jsonl` traces.
+pub fn write_json_lines(
+    trace: &DataFrame,
+    writer: impl Write,
+    timezone: ExportTimezone,
+) -> Result<(), ConvertError> {
+    JsonWriter::new(writer)
+        .with_json_format(JsonFormat::JsonLines)
+        .finish(&mut text_columns(trace, timezone)?)?;
+    Ok(())
+}
+
+/// `trace` as the image of a SQLite database holding it in a
+/// [`SQLITE_TABLE`] table. Integer and boolean columns are stored as
+/// `INTEGER`, floats as `REAL` and anything else as `TEXT`.
+fn write_sqlite(trace: &DataFrame) -> Result<Vec<u8>, ConvertError> {
+    let mut definitions = Vec::with_capacity(trace.width());
+    let mut columns = Vec::with_capacity(trace.width());
+    for column in trace.get_columns() {
+        let dtype = column.dtype();
+        let (sql_type, values) = if dtype.is_integer() || dtype.is_bool() {
+            let values = column.cast(&DataType::Int64)?;
+            let values = values
+                .i64()?
+                .iter()
+                .map(|v| v.map_or(Value::Null, Value::Integer));
+            ("INTEGER", values.collect::<Vec<_>>())
+        } else if dtype.is_float() {
+            let values = column.cast(&DataType::Float64)?;
+            let values = values
+                .f64()?
+                .iter()
+                .map(|v| v.map_or(Value::Null, Value::Real));
+            ("REAL", values.collect())
+        } else {
+            let values = column.cast(&DataType::String)?;
+            let values = values.str()?.iter().map(|v| match v {
+                Some(v) => Value::Text(v.to_string()),
+                None => Value::Null,
+            });
+            ("TEXT", values.collect())
+        };
+        definitions.push(format!("{} {sql_type}", sqlite_name(column.name())));
+        columns.push(values);
+    }
+
+    let mut connection = Connection::open_in_memory()?;
+    connection.execute(
+        &format!("CREATE TABLE {SQLITE_TABLE} ({})", definitions.join(", ")),
+        [],
+    )?;
+    let transaction = connection.transaction()?;
+    {
+        let placeholders = vec!["?"; columns.len()].join(", ");
+        let mut insert = transaction.prepare(&format!(
+            "INSERT INTO {SQLITE_TABLE} VALUES ({placeholders})"
+        ))?;
+        for row in 0..trace.height() {
+            insert.execute(params_from_iter(columns.iter().map(|values| &values[row])))?;
+        }
+    }
+    transaction.commit()?;
+    Ok(connection.serialize("main")?.to_vec())
+}
+
+/// The [`SQLITE_TABLE`] table of the SQLite database image in `database`,
+/// with column types following the declared ones.
+fn read_sqlite(mut database: Cursor<Vec<u8>>) -> Result<DataFrame, ConvertError> {
+    let size = database.get_ref().len();
+    let mut connection = Connection::open_in_memory()?;
+    connection.deserialize_read_exact("main", &mut database, size, true)?;
+    let declared = connection
+        .prepare(&format!(
+            "SELECT name, type FROM pragma_table_info('{SQLITE_TABLE}')"
+        ))?
+        .query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut values: Vec<Vec<Value>> = vec![Vec::new(); declared.len()];
+    let mut select = connection.prepare(&format!("SELECT * FROM {SQLITE_TABLE}"))?;
+    let mut rows = select.query([])?;
+    while let Some(row) = rows.next()? {
+        for (index, column) in values.iter_mut().enumerate() {
+            column.push(row.get(index)?);
+        }
+    }
+
+    let columns = declared
+        .into_iter()
+        .zip(values)
+        .map(|((name, sql_type), values)| {
+            let name = PlSmallStr::from(name);
+            match sql_type.to_ascii_uppercase().as_str() {
+                "INTEGER" => Column::new(
+                    name,
+                    values
+                        .iter()
+                        .map(|value| match value {
+                            Value::Integer(v) => Some(*v),
+                            _ => None,
+                        })
+                        .collect::<Vec<_>>(),
+                ),
+                "REAL" => Column::new(
+                    name,
+                    values
+                        .iter()
+                        .map(|value| match value {
+                            Value::Real(v) => Some(*v),
+                            Value::Integer(v) => Some(*v as f64),
+                            _ => None,
+                        })
+                        .collect::<Vec<_>>(),
+                ),
+                _ => Column::new(
+                    name,
+                    values
+                        .into_iter()
+                        .map(|value| match value {
+                            Value::Text(v) => Some(v),
+                            _ => None,
+                        })
+                        .collect::<Vec<_>>(),
+                ),
+            }
+        })
+        .collect();
+    Ok(DataFrame::new(columns)?)
+}
+
+/// `name` quoted as a SQLite identifier.
+fn sqlite_name(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+fn require_column(trace: &DataFrame, name: &'static str) -> Result<(), SchemaError> {
+    trace
+        .column(name)
+        .map(drop)
+        .map_err(|_| SchemaError::MissingColumn(name))
+}
+
+/// Rows of `trace` within `range` that pass `filter`. Device patterns match
+/// the `device` column; user and task filters need `user` and `task`
+/// columns, as in joined exports.
+pub fn filter_trace(
+    trace: DataFrame,
+    range: TimeRange,
+    filter: &Filter,
+) -> Result<DataFrame, ConvertError> {
+    let mut predicates = vec![range.predicate()];
+    if !filter.devices.is_empty() {
+        require_column(&trace, "device")?;
+        let devices = trace
+            .column("device")?
+            .str()?
+            .iter()
+            .flatten()
+            .filter(|name| matches_any(&filter.devices, name))
+            .collect::<BTreeSet<_>>();
+        predicates.push(Some(any_of("device", devices.into_iter().map(lit))));
+    }
+    if !filter.pids.is_empty() {
+        require_column(&trace, "pid")?;
+        predicates.push(Some(any_of("pid", filter.pids.iter().map(|&pid| lit(pid)))));
+    }
+    for (name, values) in [("user", &filter.users), ("task", &filter.tasks)] {
+        if !values.is_empty() {
+            require_column(&trace, name)?;
+            predicates.push(Some(any_of(
+                name,
+                values.iter().map(|value| lit(value.as_str())),
+            )));
+        }
+    }
+
+    match predicates.into_iter().flatten().reduce(Expr::and) {
+        Some(predicate) => Ok(trace.lazy().filter(predicate).collect()?),
+        None => Ok(trace),
+    }
+}
+
+/// `trace` with only `columns`, in that order; empty keeps every column.
+pub fn project_trace(trace: DataFrame, columns: &[String]) -> Result<DataFrame, ConvertError> {
+    if columns.is_empty() {
+        return Ok(trace);
+    }
+    if let Some(unknown) = columns.iter().find(|name| trace.column(name).is_err()) {
+        return Err(ConvertError::UnknownColumn(unknown.clone()));
+    }
+    Ok(trace.select(columns.iter().map(String::as_str))?)
+}
+
+/// One row per distinct combination of the other columns (such as pid and
+/// device) per `every` bucket of wall-clock time. Energy and emissions are summed,
+/// utilization averaged, and timestamps and counters keep the last value in
+/// the bucket. A bucket with corrected rows keeps the first correction's
+/// quality flag and, as original energy, the sum of what its rows read.
+pub fn resample_trace(mut trace: DataFrame, every: Duration) -> Result<DataFrame, ConvertError> {
+    let every_us = match i64::try_from(every.as_micros()) {
+        Ok(0) => return Err(ConvertError::InvalidInterval),
+        Ok(every_us) => every_us,
+        Err(_) => i64::MAX,
+    };
+    let buckets = timestamp_micros(&trace)?
+        .apply_values(|ts| ts - ts.rem_euclid(every_us))
+        .with_name(BUCKET_COLUMN.into());
+    let names = trace.get_column_names_owned();
+    trace.with_column(buckets.into_series())?;
+
+    let mut keys = Vec::new();
+    let mut aggregations = Vec::new();
+    for name in &names {
+        if SUMMED_COLUMNS.contains(&name.as_str()) {
+            aggregations.push(col(name.clone()).sum());
+        } else if name.contains(MEAN_COLUMN_MARKER) {
+            aggregations.push(col(name.clone()).mean());
+        } else if LAST_COLUMNS.contains(&name.as_str()) {
+            aggregations.push(col(name.clone()).last());
+        } else if name == QUALITY_COLUMN {
+            let corrected = col(QUALITY_COLUMN).neq(lit(RecordQuality::Ok.as_str()));
+            aggregations.push(
+                col(QUALITY_COLUMN)
+                    .filter(corrected)
+                    .first()
+                    .fill_null(lit(RecordQuality::Ok.as_str())),
+            );
+        } else if name == ORIGINAL_ENERGY_COLUMN {
+            aggregations.push(
+                when(col(ORIGINAL_ENERGY_COLUMN).is_not_null().any(true))
+                    .then(col(ORIGINAL_ENERGY_COLUMN).fill_null(col("energy")).sum())
+                    .otherwise(lit(NULL).cast(DataType::Float64)),
+            );
+        } else {
+            keys.push(col(name.clone()));
+        }
+    }
+    keys.push(col(BUCKET_COLUMN));
+
+    let downsampled = sort_by_timestamp(trace.lazy())
+        .group_by_stable(keys)
+        .agg(aggregations)
+        .select(names.into_iter().map(col).collect::<Vec<_>>());
+    Ok(sort_by_timestamp(downsampled).collect()?)
+}
+
+pub(crate) fn sort_by_timestamp(trace: LazyFrame) -> LazyFrame {
+    trace.sort(
+        [TIMESTAMP_COLUMN],
+        SortMultipleOptions::default().with_maintain_order(true),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn trace() -> DataFrame {
+        let timestamps = Column::new(
+            TIMESTAMP_COLUMN.into(),
+            [1_000_000i64, 1_000_000, 1_500_000, 2_500_000],
+        )
+        .cast(&timestamp_dtype())
+        .unwrap();
+        let monotonic = Column::new(MONOTONIC_COLUMN.into(), [10i64, 10, 20, 30])
+            .cast(&monotonic_dtype())
+            .unwrap();
+        DataFrame::new(vec![
+            Column::new("pid".into(), [1u32, 2, 1, 1]),
+            timestamps,
+            Column::new(DEVICE_ID_COLUMN.into(), [0u32, 1, 0, 0]),
+            Column::new(
+                "device".into(),
+                ["rapl:package", "nvidia:0", "rapl:package", "rapl:package"],
+            ),
+            Column::new("energy".into(), [1.0, 4.0, 2.0, 3.0]),
+            monotonic,
+        ])
+        .unwrap()
+    }
+
+    #[test]
+    fn formats_are_named_by_extension() {
+        for (name, format) in [
+            ("trace_0.csv.zst.enc", Some(TraceFileFormat::Csv)),
+            ("part-1.parquet.enc", Some(TraceFileFormat::Parquet)),
+            ("run.ndjson", Some(TraceFileFormat::JsonLines)),
+            ("run.feather", Some(TraceFileFormat::ArrowIpc)),
+            ("run.parquet.zst", None),
+            ("run.sqlite", Some(TraceFileFormat::Sqlite)),
+            ("run.db", None),
+        ] {
+            assert_eq!(
+                TraceFileFormat::from_path(Path::new(name)),
+                format,
+                "{name}"
+            );
+        }
+    }
+
+    #[test]
+    fn traces_round_trip_through_every_format() {
+        let dir = TempDir::new().unwrap();
+        let mut source = trace();
+        let input = dir.path().join("run.parquet");
+        write_trace(&mut source, &input, ExportTimezone::Utc, None).unwrap();
+
+        for name in ["run.csv", "run.jsonl", "run.arrow", "run.sqlite"] {
+            let output = dir.path().join(name);
+            let rows = convert_trace(&input, &output, &ConvertOptions::default(), None).unwrap();
+            assert_eq!(rows, 4);
+            assert_eq!(read_trace(&output, None).unwrap(), source, "{name}");
+        }
+    }
+
+    #[test]
+    fn sqlite_exports_are_queryable_and_sealed_with_a_key() {
+        let dir = TempDir::new().unwrap();
+        let output = dir.path().join("run.sqlite");
+        write_trace(&mut trace(), &output, ExportTimezone::Utc, None).unwrap();
+
+        let connection = Connection::open(&output).unwrap();
+        let package: f64 = connection
+            .query_row(
+                "SELECT SUM(energy) FROM trace WHERE device = 'rapl:package'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(package, 6.0);
+
+        let cipher = TraceCipher::from_hex_key(&"11".repeat(32)).unwrap();
+        let sealed = dir.path().join("run.sqlite.enc");
+        write_trace(&mut trace(), &sealed, ExportTimezone::Utc, Some(&cipher)).unwrap();
+        assert!(is_encrypted(&fs::read(&sealed).unwrap()));
+        assert_eq!(read_trace(&sealed, Some(&cipher)).unwrap(), trace());
+    }
+
+    #[test]
+    fn conversion_filters_and_downsamples() {
+        let dir = TempDir::new().unwrap();
+        let segments = dir.path().join("cpu");
+        fs::create_dir(&segments).unwrap();
+        for (offset, name) in [(0, "trace_0.csv"), (2, "trace_1.csv")] {
+            write_trace(
+                &mut trace().slice(offset, 2),
+                &segments.join(name),
+                ExportTimezone::Utc,
+                None,
+            )
+            .unwrap();
+        }
+        fs::write(segments.join("regions.csv"), "region,start\n").unwrap();
+        let output = dir.path().join("rapl.parquet");
+        let options = ConvertOptions {
+            range: TimeRange::between(1_000, 2_000),
+            filter: Filter {
+                devices: vec!["rapl:*".to_string()],
+                ..Filter::default()
+            },
+            resample: Some(Duration::from_secs(1)),
+            columns: vec![TIMESTAMP_COLUMN.to_string(), "energy".to_string()],
+            ..ConvertOptions::default()
+        };
+
+        let rows = convert_trace(&segments, &output, &options, None).unwrap();
+
+        assert_eq!(rows, 1);
+        let converted = read_trace(&output, None).unwrap();
+        assert_eq!(converted.get_column_names(), [TIMESTAMP_COLUMN, "energy"]);
+        assert_eq!(
+            converted.column("energy").unwrap().f64().unwrap().get(0),
+            Some(3.0)
+        );
+        assert_eq!(
+            timestamp_micros(&converted).unwrap().get(0),
+            Some(1_500_000)
+        );
+    }
+
+    #[test]
+    fn resampling_averages_utilization() {
+        let mut sampled = trace().slice(0, 3);
+        sampled
+            .with_column(Column::new("cpu_utilization".into(), [0.2, 0.5, 0.6]))
+            .unwrap();
+
+        let resampled = resample_trace(sampled, Duration::from_secs(10)).unwrap();
+
+        assert_eq!(resampled.height(), 2);
+        let pid = resampled.column("pid").unwrap().u32().unwrap();
+        let utilization = resampled.column("cpu_utilization").unwrap().f64().unwrap();
+        let first = (0..2).find(|&row| pid.get(row) == Some(1)).unwrap();
+        assert!((utilization.get(first).unwrap() - 0.4).abs() < 1e-9);
+        assert!(matches!(
+            resample_trace(trace().clear(), Duration::from_nanos(10)),
+            Err(ConvertError::InvalidInterval)
+        ));
+    }
+
+    #[test]
+    fn resampling_keeps_corrections_auditable() {
+        let mut screened = trace();
+        screened
+            .with_column(Column::new(
+                QUALITY_COLUMN.into(),
+                ["ok", "ok", "clamped", "ok"],
+            ))
+            .unwrap();
+        screened
+            .with_column(Column::new(
+                ORIGINAL_ENERGY_COLUMN.into(),
+                [None, None, Some(9.0), None],
+            ))
+            .unwrap();
+
+        let resampled = resample_trace(screened, Duration::from_secs(10)).unwrap();
+
+        assert_eq!(resampled.height(), 2);
+        let pid = resampled.column("pid").unwrap().u32().unwrap();
+        let quality = resampled.column(QUALITY_COLUMN).unwrap().str().unwrap();
+        let original = resampled
+            .column(ORIGINAL_ENERGY_COLUMN)
+            .unwrap()
+            .f64()
+            .unwrap();
+        let first = (0..2).find(|&row| pid.get(row) == Some(1)).unwrap();
+        assert_eq!(quality.get(first), Some("clamped"));
+        assert_eq!(original.get(first), Some(13.0));
+        assert_eq!(quality.get(1 - first), Some("ok"));
+        assert_eq!(original.get(1 - first), None);
+    }
+
+    #[test]
+    fn user_filter_requires_joined_metadata() {
+        let dir = TempDir::new().unwrap();
+        let input = dir.path().join("run.arrow");
+        write_trace(&mut trace(), &input, ExportTimezone::Utc, None).unwrap();
+        let options = ConvertOptions {
+            filter: Filter {
+                users: vec!["alice".to_string()],
+                ..Filter::default()
+            },
+            ..ConvertOptions::default()
+        };
+
+        let result = convert_trace(&input, &dir.path().join("out.csv"), &options, None);
+
+        assert!(matches!(
+            result,
+            Err(ConvertError::Schema(SchemaError::MissingColumn("user")))
+        ));
+        let projected = project_trace(trace(), &["host".to_string()]);
+        assert!(matches!(projected, Err(ConvertError::UnknownColumn(name)) if name == "host"));
+    }
+}
@@ -0,0 +1,522 @@
+/// Daemon Module
+///
+/// Control API of `emt daemon`. One privileged process monitors every
+/// process and answers JSON-RPC 2.0 requests on a Unix socket, one JSON
+/// object per line each way. Clients without powercap access scope their
+/// own measurements through it:
+///
+/// - `start-session` with `pids` (and an optional `name`) notes their
+///   energy so far and returns a session id;
+/// - `stop-session` with that `session` returns the energy they used since;
+/// - `query` with a `pid` returns that process's energy since the daemon
+///   started;
+/// - `snapshot` returns the whole [`MetricsSnapshot`].
+///
+/// A pid that roots a workload counts with its children; any other pid
+/// counts on its own.
+use crate::monitor::{DeviceEnergy, MetricsSnapshot};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::future::Future;
+use std::io;
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use thiserror::Error;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+
+pub const DEFAULT_SOCKET_PATH: &str = "/run/emt/emt.sock";
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+/// The request was well formed but names a session or pid the daemon does
+/// not know.
+const UNKNOWN_TARGET: i64 = -32001;
+
+const METHODS: [&str; 4] = ["start-session", "stop-session", "query", "snapshot"];
+
+/// Errors raised by a client of the daemon.
+#[derive(Debug, Error)]
+pub enum DaemonError {
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    #[error("invalid JSON: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("daemon error {code}: {message}")]
+    Rpc { code: i64, message: String },
+    #[error("daemon closed the connection")]
+    Closed,
+}
+
+/// A method of the control API with its parameters.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "method", content = "params", rename_all = "kebab-case")]
+pub enum Request {
+    StartSession {
+        pids: Vec<u32>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        name: Option<String>,
+    },
+    StopSession {
+        session: u64,
+    },
+    Query {
+        pid: u32,
+    },
+    Snapshot,
+}
+
+/// Result of `start-session`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionStarted {
+    pub session: u64,
+    /// Unix milliseconds of the snapshot the session counts from
+    pub started_at: i64,
+}
+
+/// Result of `stop-session`: the energy the session's processes used while
+/// it was open.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionReport {
+    pub session: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub pids: Vec<u32>,
+    pub started_at: i64,
+    pub stopped_at: i64,
+    pub energy: DeviceEnergy,
+    pub total_joules: f64,
+    /// Total energy over the session's duration; 0 for an empty session
+    pub average_watts: f64,
+}
+
+/// Result of `query`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProcessQuery {
+    pub pid: u32,
+    pub name: String,
+    pub energy: DeviceEnergy,
+    pub power_watts: f64,
+}
+
+/// An error returned in place of a result.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct RpcRequest {
+    jsonrpc: String,
+    #[serde(default)]
+    id: Value,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+struct Session {
+    name: Option<String>,
+    pids: Vec<u32>,
+    started_at: i64,
+    baseline: DeviceEnergy,
+}
+
+/// Sessions open on the daemon, shared by all its connections.
+#[derive(Default)]
+pub struct Sessions {
+    next_id: u64,
+    open: HashMap<u64, Session>,
+}
+
+impl Sessions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Answer one request line against `snapshot`, returning the response
+    /// line without its newline.
+    pub fn respond(&mut self, line: &str, snapshot: &MetricsSnapshot) -> String {
+        let (id, outcome) = match serde_json::from_str::<RpcRequest>(line) {
+            Ok(request) => (request.id.clone(), self.dispatch(request, snapshot)),
+            Err(e) => match serde_json::from_str::<Value>(line) {
+                Ok(value) => (
+                    value.get("id").cloned().unwrap_or(Value::Null),
+                    Err(RpcError::new(INVALID_REQUEST, e.to_string())),
+                ),
+                Err(e) => (Value::Null, Err(RpcError::new(PARSE_ERROR, e.to_string()))),
+            },
+        };
+        let response = match outcome {
+            Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+            Err(error) => json!({"jsonrpc": "2.0", "id": id, "error": error}),
+        };
+        response.to_string()
+    }
+
+    fn dispatch(
+        &mut self,
+        request: RpcRequest,
+        snapshot: &MetricsSnapshot,
+    ) -> Result<Value, RpcError> {
+        if request.jsonrpc != "2.0" {
+            return Err(RpcError::new(INVALID_REQUEST, "jsonrpc must be \"2.0\""));
+        }
+        if !METHODS.contains(&request.method.as_str()) {
+            return Err(RpcError::new(
+                METHOD_NOT_FOUND,
+                format!("unknown method '{}'", request.method),
+            ));
+        }
+        let method = json!({"method": request.method, "params": request.params});
+        let request: Request = serde_json::from_value(method)
+            .map_err(|e| RpcError::new(INVALID_PARAMS, e.to_string()))?;
+        let result = match request {
+            Request::StartSession { pids, name } => json!(self.start(pids, name, snapshot)),
+            Request::StopSession { session } => json!(self.stop(session, snapshot)?),
+            Request::Query { pid } => json!(query(pid, snapshot)?),
+            Request::Snapshot => json!(snapshot),
+        };
+        Ok(result)
+    }
+
+    fn start(
+        &mut self,
+        pids: Vec<u32>,
+        name: Option<String>,
+        snapshot: &MetricsSnapshot,
+    ) -> SessionStarted {
+        self.next_id += 1;
+        let session = Session {
+            baseline: session_energy(&pids, snapshot),
+            name,
+            pids,
+            started_at: snapshot.timestamp,
+        };
+        self.open.insert(self.next_id, session);
+        SessionStarted {
+            session: self.next_id,
+            started_at: snapshot.timestamp,
+        }
+    }
+
+    fn stop(&mut self, id: u64, snapshot: &MetricsSnapshot) -> Result<SessionReport, RpcError> {
+        let session = self
+            .open
+            .remove(&id)
+            .ok_or_else(|| RpcError::new(UNKNOWN_TARGET, format!("no open session {id}")))?;
+        let energy = session_energy(&session.pids, snapshot).saturating_sub(&session.baseline);
+        let total_joules = energy.total();
+        let seconds = (snapshot.timestamp - session.started_at) as f64 / 1000.0;
+        Ok(SessionReport {
+            session: id,
+            name: session.name,
+            pids: session.pids,
+            started_at: session.started_at,
+            stopped_at: snapshot.timestamp,
+            energy,
+            total_joules,
+            average_watts: if seconds > 0.0 {
+                total_joules / seconds
+            } else {
+                0.0
+            },
+        })
+    }
+}
+
+/// Energy of `pid` so far: its workload's when it roots one, else its own.
+fn pid_energy(pid: u32, snapshot: &MetricsSnapshot) -> Option<ProcessQuery> {
+    if let Some(workload) = snapshot.workloads.iter().find(|w| w.root_pid == pid) {
+        return Some(ProcessQuery {
+            pid,
+            name: workload.name.clone(),
+            energy: workload.energy.clone(),
+            power_watts: workload.power_watts,
+        });
+    }
+    snapshot
+        .workloads
+        .iter()
+        .flat_map(|workload| &workload.processes)
+        .find(|process| process.pid == pid)
+        .map(|process| ProcessQuery {
+            pid,
+            name: process.name.clone(),
+            energy: process.energy.clone(),
+            power_watts: process.power_watts,
+        })
+}
+
+/// Energy of `pids` so far, counting a process once when its workload's
+/// root is among them too. Pids the monitor has not seen count as 0.
+fn session_energy(pids: &[u32], snapshot: &MetricsSnapshot) -> DeviceEnergy {
+    let counted_with_root = |pid: u32| {
+        snapshot.workloads.iter().any(|workload| {
+            workload.root_pid != pid
+                && pids.contains(&workload.root_pid)
+                && workload.processes.iter().any(|p| p.pid == pid)
+        })
+    };
+    let mut total = DeviceEnergy::default();
+    for &pid in pids {
+        if counted_with_root(pid) {
+            continue;
+        }
+        if let Some(process) = pid_energy(pid, snapshot) {
+            total.cpu_joules += process.energy.cpu_joules;
+            total.dram_joules += process.energy.dram_joules;
+            total.gpu_joules += process.energy.gpu_joules;
+        }
+    }
+    total
+}
+
+fn query(pid: u32, snapshot: &MetricsSnapshot) -> Result<ProcessQuery, RpcError> {
+    pid_energy(pid, snapshot)
+        .ok_or_else(|| RpcError::new(UNKNOWN_TARGET, format!("pid {pid} is not being monitored")))
+}
+
+/// Bind the control socket at `path` with permissions `mode`, creating its
+/// directory and replacing a socket left behind by a previous daemon.
+pub fn bind_socket(path: &Path, mode: u32) -> io::Result<std::os::unix::net::UnixListener> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => std::fs::remove_file(path)?,
+        Ok(_) => {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} exists and is not a socket", path.display()),
+            ));
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+    let listener = std::os::unix::net::UnixListener::bind(path)?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+    listener.set_nonblocking(true)?;
+    Ok(listener)
+}
+
+/// Answer connections on `listener` against the latest of `snapshot`, such
+/// as `MonitorHandle::snapshot`, until `shutdown` completes.
+pub async fn serve<S>(listener: UnixListener, snapshot: S, shutdown: impl Future<Output = ()>)
+where
+    S: Fn() -> MetricsSnapshot + Clone + Send + 'static,
+{
+    let sessions = Arc::new(Mutex::new(Sessions::new()));
+    tokio::pin!(shutdown);
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok((stream, _)) => {
+                    tokio::spawn(serve_connection(stream, snapshot.clone(), Arc::clone(&sessions)));
+                }
+                Err(e) => log::warn!("Failed to accept a control connection: {e}"),
+            },
+            _ = &mut shutdown => return,
+        }
+    }
+}
+
+async fn serve_connection<S>(stream: UnixStream, snapshot: S, sessions: Arc<Mutex<Sessions>>)
+where
+    S: Fn() -> MetricsSnapshot,
+{
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let mut response = sessions.lock().unwrap().respond(&line, &snapshot());
+        response.push('\n');
+        if writer.write_all(response.as_bytes()).await.is_err() {
+            return;
+        }
+    }
+}
+
+/// A connection to a running daemon.
+pub struct DaemonClient {
+    stream: BufReader<UnixStream>,
+    next_id: u64,
+}
+
+impl DaemonClient {
+    pub async fn connect(path: &Path) -> Result<Self, DaemonError> {
+        Ok(Self {
+            stream: BufReader::new(UnixStream::connect(path).await?),
+            next_id: 0,
+        })
+    }
+
+    /// Send `request` and return its result.
+    pub async fn call(&mut self, request: &Request) -> Result<Value, DaemonError> {
+        self.next_id += 1;
+        let mut message = serde_json::to_value(request)?;
+        message["jsonrpc"] = json!("2.0");
+        message["id"] = json!(self.next_id);
+        let mut line = message.to_string();
+        line.push('\n');
+        self.stream.get_mut().write_all(line.as_bytes()).await?;
+
+        let mut response = String::new();
+        if self.stream.read_line(&mut response).await? == 0 {
+            return Err(DaemonError::Closed);
+        }
+        let mut response: Value = serde_json::from_str(&response)?;
+        if let Some(error) = response.get("error") {
+            let error: RpcError = serde_json::from_value(error.clone())?;
+            return Err(DaemonError::Rpc {
+                code: error.code,
+                message: error.message,
+            });
+        }
+        Ok(response["result"].take())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::monitor::{ProcessEnergySnapshot, WorkloadSnapshot};
+
+    fn energy(cpu_joules: f64) -> DeviceEnergy {
+        DeviceEnergy {
+            cpu_joules,
+            ..DeviceEnergy::default()
+        }
+    }
+
+    fn snapshot(timestamp: i64, root: f64, child: f64) -> MetricsSnapshot {
+        let process = |pid, joules| ProcessEnergySnapshot {
+            pid,
+            name: format!("p{pid}"),
+            energy: energy(joules),
+            power_watts: 0.0,
+        };
+        MetricsSnapshot {
+            timestamp,
+            workloads: vec![WorkloadSnapshot {
+                root_pid: 10,
+                group_id: "10".to_string(),
+                name: "job".to_string(),
+                user: "alice".to_string(),
+                processes: vec![process(10, root), process(11, child)],
+                is_live: true,
+                energy: energy(root + child),
+                power_watts: 0.0,
+                percentage_of_system: 0.0,
+                container: None,
+            }],
+            ..MetricsSnapshot::default()
+        }
+    }
+
+    fn result(response: &str) -> Value {
+        let mut response: Value = serde_json::from_str(response).unwrap();
+        response["result"].take()
+    }
+
+    #[test]
+    fn a_session_reports_the_energy_used_while_it_was_open() {
+        let mut sessions = Sessions::new();
+        let started = sessions.respond(
+            r#"{"jsonrpc":"2.0","id":1,"method":"start-session","params":{"pids":[10,11],"name":"build"}}"#,
+            &snapshot(1_000, 5.0, 1.0),
+        );
+        assert_eq!(result(&started), json!({"session": 1, "started_at": 1_000}));
+
+        let stopped = sessions.respond(
+            r#"{"jsonrpc":"2.0","id":2,"method":"stop-session","params":{"session":1}}"#,
+            &snapshot(3_000, 15.0, 7.0),
+        );
+        let report: SessionReport = serde_json::from_value(result(&stopped)).unwrap();
+        // The child counts once, with the workload its root is in.
+        assert_eq!(report.total_joules, 16.0);
+        assert_eq!(report.average_watts, 8.0);
+        assert_eq!(report.name.as_deref(), Some("build"));
+
+        let again = sessions.respond(
+            r#"{"jsonrpc":"2.0","id":3,"method":"stop-session","params":{"session":1}}"#,
+            &snapshot(4_000, 15.0, 7.0),
+        );
+        let again: Value = serde_json::from_str(&again).unwrap();
+        assert_eq!(again["error"]["code"], UNKNOWN_TARGET);
+        assert_eq!(again["id"], 3);
+    }
+
+    #[test]
+    fn queries_a_child_on_its_own_and_rejects_bad_requests() {
+        let mut sessions = Sessions::new();
+        let snapshot = snapshot(1_000, 5.0, 1.0);
+
+        let child = sessions.respond(
+            r#"{"jsonrpc":"2.0","id":"q","method":"query","params":{"pid":11}}"#,
+            &snapshot,
+        );
+        let child: ProcessQuery = serde_json::from_value(result(&child)).unwrap();
+        assert_eq!(child.energy.cpu_joules, 1.0);
+
+        let mut code = |line: &str| {
+            let response: Value = serde_json::from_str(&sessions.respond(line, &snapshot)).unwrap();
+            response["error"]["code"].as_i64().unwrap()
+        };
+        assert_eq!(code("{not json"), PARSE_ERROR);
+        assert_eq!(
+            code(r#"{"jsonrpc":"2.0","id":1,"method":"reboot"}"#),
+            METHOD_NOT_FOUND
+        );
+        assert_eq!(
+            code(r#"{"jsonrpc":"2.0","id":1,"method":"query"}"#),
+            INVALID_PARAMS
+        );
+        assert_eq!(
+            code(r#"{"jsonrpc":"2.0","id":1,"method":"query","params":{"pid":99}}"#),
+            UNKNOWN_TARGET
+        );
+    }
+
+    #[tokio::test]
+    async fn a_client_reads_a_snapshot_over_the_socket() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("run").join("emt.sock");
+        let listener = UnixListener::from_std(bind_socket(&path, 0o600).unwrap()).unwrap();
+        let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+        let latest = || snapshot(1_000, 5.0, 1.0);
+        let server = tokio::spawn(serve(listener, latest, async {
+            let _ = stopped.await;
+        }));
+
+        let mut client = DaemonClient::connect(&path).await.unwrap();
+        let latest = client.call(&Request::Snapshot).await.unwrap();
+        assert_eq!(latest["workloads"][0]["root_pid"], 10);
+        assert!(matches!(
+            client.call(&Request::StopSession { session: 7 }).await,
+            Err(DaemonError::Rpc {
+                code: UNKNOWN_TARGET,
+                ..
+            })
+        ));
+
+        stop.send(()).unwrap();
+        server.await.unwrap();
+    }
+}
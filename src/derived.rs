@@ -0,0 +1,246 @@
+/// Derived Devices
+///
+/// Virtual devices computed every tick from the energy of other devices,
+/// such as the sum of all package domains or the platform energy that
+/// package and DRAM do not cover:
+///
+/// ```yaml
+/// derived_devices:
+///   - name: cpu_total
+///     expression: sum(rapl:socket:*:package)
+///   - name: other
+///     expression: rapl:system:psys - cpu_total - rapl:system:dram
+/// ```
+///
+/// An expression adds and subtracts operands separated by spaces, since
+/// device names may contain `-`. An operand is an earlier derived device,
+/// or a device name pattern, bare or as `sum(pattern)`, standing for the
+/// energy of all matching devices. Derived devices are recorded as
+/// `derived:<name>` like measured ones, but never count towards totals
+/// since they count their inputs' energy again.
+use crate::config::DerivedDeviceConfig;
+use crate::energy_group::EnergyRecord;
+use crate::utils::pattern::glob_match;
+use std::collections::HashMap;
+
+/// Prefix of derived device names.
+pub const DERIVED_PREFIX: &str = "derived:";
+
+/// Records of derived devices carry no process.
+const UNATTRIBUTED_PID: u32 = 0;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Operand {
+    /// Energy of the devices matching a pattern
+    Devices(String),
+    /// An earlier derived device, by index
+    Derived(usize),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct DerivedDevice {
+    name: String,
+    /// Operands with their signs
+    terms: Vec<(f64, Operand)>,
+}
+
+/// The configured derived devices, parsed.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DerivedDevices {
+    devices: Vec<DerivedDevice>,
+}
+
+impl DerivedDevices {
+    pub fn new(configs: &[DerivedDeviceConfig]) -> Result<Self, String> {
+        let mut devices: Vec<DerivedDevice> = Vec::new();
+        for config in configs {
+            if config.name.is_empty() || config.name.contains(':') {
+                return Err(format!(
+                    "derived device name '{}' must be non-empty and without ':'",
+                    config.name
+                ));
+            }
+            if devices.iter().any(|device| device.name == config.name) {
+                return Err(format!("derived device '{}' is defined twice", config.name));
+            }
+            let terms = parse_expression(&config.expression, &devices)
+                .map_err(|e| format!("derived device '{}': {e}", config.name))?;
+            devices.push(DerivedDevice {
+                name: config.name.clone(),
+                terms,
+            });
+        }
+        Ok(Self { devices })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.devices.is_empty()
+    }
+
+    /// Records of every derived device whose inputs reported in `records`,
+    /// one tick's worth, stamped with the latest input time. Results below
+    /// zero, as readings of different devices are not simultaneous, are
+    /// recorded as zero.
+    pub fn evaluate(&self, records: &[EnergyRecord]) -> Vec<EnergyRecord> {
+        let mut energy_by_device: HashMap<&str, f64> = HashMap::new();
+        for record in records {
+            if !record.device.starts_with(DERIVED_PREFIX) {
+                *energy_by_device.entry(&record.device).or_default() += record.energy;
+            }
+        }
+        let Some(latest) = records.iter().max_by_key(|record| record.monotonic_ns) else {
+            return Vec::new();
+        };
+
+        let mut values: Vec<Option<f64>> = Vec::with_capacity(self.devices.len());
+        for device in &self.devices {
+            let mut value = None;
+            for (sign, operand) in &device.terms {
+                let energy = match operand {
+                    Operand::Devices(pattern) => energy_by_device
+                        .iter()
+                        .filter(|(name, _)| glob_match(pattern, name))
+                        .map(|(_, energy)| *energy)
+                        .reduce(|sum, energy| sum + energy),
+                    Operand::Derived(index) => values[*index],
+                };
+                if let Some(energy) = energy {
+                    *value.get_or_insert(0.0) += sign * energy;
+                }
+            }
+            values.push(value);
+        }
+
+        self.devices
+            .iter()
+            .zip(values)
+            .filter_map(|(device, value)| {
+                Some(EnergyRecord {
+                    pid: UNATTRIBUTED_PID,
+                    timestamp_us: latest.timestamp_us,
+                    monotonic_ns: latest.monotonic_ns,
+                    device: format!("{DERIVED_PREFIX}{}", device.name),
+                    energy: value?.max(0.0),
+                    raw_counter: None,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Terms of `operand (+|-) operand ...`, resolving names of `earlier`
+/// derived devices.
+fn parse_expression(
+    expression: &str,
+    earlier: &[DerivedDevice],
+) -> Result<Vec<(f64, Operand)>, String> {
+    let mut terms = Vec::new();
+    let mut sign = Some(1.0);
+    for token in expression.split_whitespace() {
+        match (sign, token) {
+            (None, "+") => sign = Some(1.0),
+            (None, "-") => sign = Some(-1.0),
+            (None, _) => return Err(format!("expected + or - before '{token}'")),
+            (Some(_), "+" | "-") => return Err(format!("expected a device before '{token}'")),
+            (Some(current), _) => {
+                terms.push((current, parse_operand(token, earlier)?));
+                sign = None;
+            }
+        }
+    }
+    if terms.is_empty() || sign.is_some() {
+        return Err(format!("'{expression}' must end with a device"));
+    }
+    Ok(terms)
+}
+
+fn parse_operand(token: &str, earlier: &[DerivedDevice]) -> Result<Operand, String> {
+    if let Some(pattern) = token.strip_prefix("sum(") {
+        let pattern = pattern
+            .strip_suffix(')')
+            .filter(|pattern| !pattern.is_empty())
+            .ok_or_else(|| format!("'{token}' must be sum(<device pattern>)"))?;
+        return Ok(Operand::Devices(pattern.to_string()));
+    }
+    Ok(earlier
+        .iter()
+        .position(|device| device.name == token)
+        .map_or_else(|| Operand::Devices(token.to_string()), Operand::Derived))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn derived(configs: &[(&str, &str)]) -> Result<DerivedDevices, String> {
+        DerivedDevices::new(
+            &configs
+                .iter()
+                .map(|(name, expression)| DerivedDeviceConfig {
+                    name: name.to_string(),
+                    expression: expression.to_string(),
+                })
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    fn record(pid: u32, device: &str, energy: f64, monotonic_ns: i64) -> EnergyRecord {
+        EnergyRecord {
+            pid,
+            timestamp_us: monotonic_ns / 1000,
+            monotonic_ns,
+            device: device.to_string(),
+            energy,
+            raw_counter: None,
+        }
+    }
+
+    #[test]
+    fn parses_expressions() {
+        assert!(derived(&[("cpu_total", "sum(rapl:socket:*:package)")]).is_ok());
+        assert!(
+            derived(&[
+                ("cpu_total", "sum(rapl:socket:*:package)"),
+                ("other", "rapl:system:psys - cpu_total - rapl:system:dram"),
+            ])
+            .is_ok()
+        );
+        assert!(derived(&[("bad", "")]).is_err());
+        assert!(derived(&[("bad", "rapl:system:psys -")]).is_err());
+        assert!(derived(&[("bad", "rapl:system:psys rapl:system:dram")]).is_err());
+        assert!(derived(&[("bad", "sum()")]).is_err());
+        assert!(derived(&[("a:b", "nvidia:0")]).is_err());
+        assert!(derived(&[("gpu", "nvidia:0"), ("gpu", "nvidia:1")]).is_err());
+    }
+
+    #[test]
+    fn evaluates_sums_and_differences_of_devices() {
+        let devices = derived(&[
+            ("cpu_total", "sum(rapl:socket:*:package)"),
+            ("other", "rapl:system:psys - cpu_total - rapl:system:dram"),
+            ("gpu", "nvidia:*"),
+        ])
+        .unwrap();
+        let records = [
+            record(10, "rapl:socket:0:package", 4.0, 100),
+            record(11, "rapl:socket:0:package", 1.0, 100),
+            record(0, "rapl:socket:1:package", 3.0, 200),
+            record(0, "rapl:system:dram", 2.0, 200),
+            record(0, "rapl:system:psys", 12.0, 300),
+        ];
+
+        let derived = devices.evaluate(&records);
+
+        assert_eq!(derived.len(), 2);
+        assert_eq!(derived[0].device, "derived:cpu_total");
+        assert_eq!(derived[0].energy, 8.0);
+        assert_eq!(derived[0].pid, UNATTRIBUTED_PID);
+        assert_eq!(derived[0].monotonic_ns, 300);
+        assert_eq!(derived[1].device, "derived:other");
+        assert_eq!(derived[1].energy, 2.0);
+
+        let skewed = devices.evaluate(&[record(0, "rapl:system:psys", 1.0, 0), records[0].clone()]);
+        assert_eq!(skewed[1].energy, 0.0);
+        assert!(devices.evaluate(&[]).is_empty());
+    }
+}
@@ -0,0 +1,250 @@
+/// Device Labels
+///
+/// Friendly aliases and key/value labels for devices, configured under
+/// `device_labels`, since sysfs-derived names such as
+/// `rapl:socket:0:package` read poorly on dashboards. Trace exports write
+/// the alias in the `device` column and each label as a `label_<key>`
+/// column; Prometheus metrics naming a device carry the alias and the
+/// labels.
+use crate::utils::pattern::glob_match;
+use polars::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Prefix of the trace columns holding labels.
+pub const LABEL_COLUMN_PREFIX: &str = "label_";
+
+/// Prometheus labels EMT sets itself.
+const RESERVED_LABELS: [&str; 5] = ["device", "scope", "socket", "workload", "workload_name"];
+
+/// Alias and labels of the devices matching `device`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeviceLabelRule {
+    /// Device name pattern (`*` and `?` globs); exact when `alias` is set
+    pub device: String,
+    #[serde(default)]
+    pub alias: Option<String>,
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+}
+
+impl DeviceLabelRule {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.alias.is_some() && self.device.contains(['*', '?']) {
+            return Err(format!(
+                "alias for '{}' needs an exact device name",
+                self.device
+            ));
+        }
+        if self.alias.as_deref() == Some("") {
+            return Err(format!("alias for '{}' must be non-empty", self.device));
+        }
+        for name in self.labels.keys() {
+            if !is_label_name(name) || RESERVED_LABELS.contains(&name.as_str()) {
+                return Err(format!(
+                    "label '{name}' of '{}' must be a Prometheus label name other than {}",
+                    self.device,
+                    RESERVED_LABELS.join(", ")
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `[a-zA-Z_][a-zA-Z0-9_]*`, without the `__` prefix Prometheus reserves.
+fn is_label_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|first| first.is_ascii_alphabetic() || first == '_')
+        && chars.all(|char| char.is_ascii_alphanumeric() || char == '_')
+        && !name.starts_with("__")
+}
+
+/// Resolves the configured aliases and labels of devices. The first rule
+/// matching a device sets its alias, and the first setting a label key
+/// sets that label.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DeviceLabels {
+    rules: Vec<DeviceLabelRule>,
+}
+
+impl DeviceLabels {
+    pub fn new(rules: Vec<DeviceLabelRule>) -> Self {
+        Self { rules }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// The alias of `device`, else its name.
+    pub fn alias<'a>(&'a self, device: &'a str) -> &'a str {
+        self.matching(device)
+            .find_map(|rule| rule.alias.as_deref())
+            .unwrap_or(device)
+    }
+
+    /// Labels of `device`.
+    pub fn labels(&self, device: &str) -> BTreeMap<&str, &str> {
+        let mut labels = BTreeMap::new();
+        for rule in self.matching(device) {
+            for (name, value) in &rule.labels {
+                labels.entry(name.as_str()).or_insert(value.as_str());
+            }
+        }
+        labels
+    }
+
+    fn matching<'a, 'b>(
+        &'a self,
+        device: &'b str,
+    ) -> impl Iterator<Item = &'a DeviceLabelRule> + use<'a, 'b> {
+        self.rules
+            .iter()
+            .filter(move |rule| glob_match(&rule.device, device))
+    }
+
+    /// `trace` with aliases in its `device` column and a `label_<key>`
+    /// column per configured label key, null for devices without it.
+    /// Traces without a `device` column are returned unchanged.
+    pub fn apply_to_trace(&self, mut trace: DataFrame) -> PolarsResult<DataFrame> {
+        if self.is_empty() {
+            return Ok(trace);
+        }
+        let Ok(devices) = trace.column("device") else {
+            return Ok(trace);
+        };
+        let devices: Vec<Option<String>> = devices
+            .str()?
+            .iter()
+            .map(|device| device.map(str::to_string))
+            .collect();
+
+        let names: BTreeSet<&str> = self
+            .rules
+            .iter()
+            .flat_map(|rule| rule.labels.keys().map(String::as_str))
+            .collect();
+        for name in names {
+            let values: Vec<Option<&str>> = devices
+                .iter()
+                .map(|device| self.labels(device.as_deref()?).get(name).copied())
+                .collect();
+            trace.with_column(Column::new(
+                format!("{LABEL_COLUMN_PREFIX}{name}").into(),
+                values,
+            ))?;
+        }
+        let aliases: Vec<Option<&str>> = devices
+            .iter()
+            .map(|device| device.as_deref().map(|device| self.alias(device)))
+            .collect();
+        trace.with_column(Column::new("device".into(), aliases))?;
+        Ok(trace)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(device: &str, alias: Option<&str>, labels: &[(&str, &str)]) -> DeviceLabelRule {
+        DeviceLabelRule {
+            device: device.to_string(),
+            alias: alias.map(str::to_string),
+            labels: labels
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+        }
+    }
+
+    fn labels() -> DeviceLabels {
+        DeviceLabels::new(vec![
+            rule("rapl:socket:0:package", Some("cpu0"), &[("rack", "R3")]),
+            rule("rapl:*", None, &[("rack", "R1"), ("source", "rapl")]),
+        ])
+    }
+
+    #[test]
+    fn validates_rules() {
+        assert!(
+            rule("nvidia:0", Some("gpu0"), &[("rack", "R3")])
+                .validate()
+                .is_ok()
+        );
+        assert!(rule("nvidia:*", Some("gpu"), &[]).validate().is_err());
+        assert!(rule("nvidia:0", Some(""), &[]).validate().is_err());
+        assert!(
+            rule("nvidia:*", None, &[("rack-id", "3")])
+                .validate()
+                .is_err()
+        );
+        assert!(
+            rule("nvidia:*", None, &[("__name", "x")])
+                .validate()
+                .is_err()
+        );
+        assert!(
+            rule("nvidia:*", None, &[("device", "x")])
+                .validate()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn first_matching_rule_wins() {
+        let labels = labels();
+
+        assert_eq!(labels.alias("rapl:socket:0:package"), "cpu0");
+        assert_eq!(labels.alias("rapl:system:dram"), "rapl:system:dram");
+        assert_eq!(
+            labels.labels("rapl:socket:0:package"),
+            BTreeMap::from([("rack", "R3"), ("source", "rapl")])
+        );
+        assert!(labels.labels("nvidia:0").is_empty());
+    }
+
+    #[test]
+    fn traces_get_aliases_and_label_columns() {
+        let trace = df!(
+            "pid" => [1u32, 2, 3],
+            "device" => ["rapl:socket:0:package", "rapl:system:dram", "nvidia:0"],
+        )
+        .unwrap();
+
+        let labeled = labels().apply_to_trace(trace.clone()).unwrap();
+
+        let column = |name: &str| -> Vec<Option<String>> {
+            labeled
+                .column(name)
+                .unwrap()
+                .str()
+                .unwrap()
+                .iter()
+                .map(|value| value.map(str::to_string))
+                .collect()
+        };
+        assert_eq!(
+            column("device"),
+            [
+                Some("cpu0".into()),
+                Some("rapl:system:dram".into()),
+                Some("nvidia:0".into())
+            ]
+        );
+        assert_eq!(
+            column("label_rack"),
+            [Some("R3".into()), Some("R1".into()), None]
+        );
+        assert_eq!(column("label_source")[2], None);
+        assert_eq!(
+            DeviceLabels::default()
+                .apply_to_trace(trace.clone())
+                .unwrap(),
+            trace
+        );
+    }
+}
@@ -0,0 +1,170 @@
+/// Device Priority Module
+///
+/// Resolves conflicts between collectors that measure the same physical domain
+/// (for example RAPL PSYS vs. PKG+DRAM, or a BMC reading vs. RAPL). Each
+/// configured domain lists tiers of device patterns in priority order. The
+/// highest tier that has produced records is authoritative and counts towards
+/// totals; devices from lower tiers are kept as auxiliary readings only.
+use crate::config::DevicePriorityRule;
+use crate::derived::DERIVED_PREFIX;
+use crate::energy_group::EnergyRecord;
+use crate::utils::pattern::matches_any;
+
+/// How a device's records contribute to reported totals.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceRole {
+    /// Counted in system, workload and process totals.
+    Authoritative,
+    /// Recorded separately and excluded from totals.
+    Auxiliary,
+}
+
+/// Stateful resolver for configured device priority rules.
+///
+/// The active tier of a domain only ever moves up: once a higher-priority
+/// source has been seen it stays authoritative, so a source that skips a tick
+/// does not cause the lower tier to be counted in the meantime.
+#[derive(Debug, Clone, Default)]
+pub struct DevicePriority {
+    rules: Vec<DevicePriorityRule>,
+    active_tiers: Vec<Option<usize>>,
+}
+
+impl DevicePriority {
+    pub fn new(rules: Vec<DevicePriorityRule>) -> Self {
+        let active_tiers = vec![None; rules.len()];
+        Self {
+            rules,
+            active_tiers,
+        }
+    }
+
+    /// Update the active tier of every domain from a batch of records.
+    pub fn observe(&mut self, records: &[EnergyRecord]) {
+        for record in records {
+            self.observe_device(&record.device);
+        }
+    }
+
+    /// Update the active tier of every domain from a device that reported
+    /// energy.
+    pub fn observe_device(&mut self, device: &str) {
+        for (rule, active) in self.rules.iter().zip(self.active_tiers.iter_mut()) {
+            if let Some(tier) = matching_tier(rule, device)
+                && active.is_none_or(|current| tier < current)
+            {
+                log::info!(
+                    "Device priority: '{}' is authoritative for domain '{}'",
+                    device,
+                    rule.domain
+                );
+                *active = Some(tier);
+            }
+        }
+    }
+
+    /// Role of `device` given the tiers observed so far.
+    ///
+    /// Devices outside every configured domain are authoritative, except
+    /// derived devices, which count the energy of other devices again.
+    pub fn role(&self, device: &str) -> DeviceRole {
+        if device.starts_with(DERIVED_PREFIX) {
+            return DeviceRole::Auxiliary;
+        }
+        for (rule, active) in self.rules.iter().zip(&self.active_tiers) {
+            let Some(tier) = matching_tier(rule, device) else {
+                continue;
+            };
+            if active.is_some_and(|active| tier > active) {
+                return DeviceRole::Auxiliary;
+            }
+        }
+        DeviceRole::Authoritative
+    }
+}
+
+fn matching_tier(rule: &DevicePriorityRule, device: &str) -> Option<usize> {
+    rule.tiers
+        .iter()
+        .position(|patterns| matches_any(patterns, device))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(device: &str) -> EnergyRecord {
+        EnergyRecord {
+            pid: 1,
+            timestamp_us: 0,
+            monotonic_ns: 0,
+            device: device.to_string(),
+            energy: 1.0,
+            raw_counter: None,
+        }
+    }
+
+    fn platform_rule() -> DevicePriorityRule {
+        DevicePriorityRule {
+            domain: "platform".to_string(),
+            tiers: vec![
+                vec!["ipmi:*".to_string()],
+                vec![
+                    "rapl:socket:*:package".to_string(),
+                    "rapl:system:dram".to_string(),
+                ],
+                vec!["rapl:system:psys".to_string()],
+            ],
+        }
+    }
+
+    #[test]
+    fn lower_tiers_become_auxiliary_once_higher_tier_is_seen() {
+        let mut priority = DevicePriority::new(vec![platform_rule()]);
+        priority.observe(&[record("rapl:system:psys")]);
+
+        assert_eq!(priority.role("rapl:system:psys"), DeviceRole::Authoritative);
+
+        priority.observe(&[record("rapl:socket:0:package"), record("rapl:system:psys")]);
+
+        assert_eq!(
+            priority.role("rapl:socket:0:package"),
+            DeviceRole::Authoritative
+        );
+        assert_eq!(priority.role("rapl:system:dram"), DeviceRole::Authoritative);
+        assert_eq!(priority.role("rapl:system:psys"), DeviceRole::Auxiliary);
+    }
+
+    #[test]
+    fn active_tier_does_not_fall_back_when_source_skips_a_batch() {
+        let mut priority = DevicePriority::new(vec![platform_rule()]);
+        priority.observe(&[record("ipmi:chassis")]);
+        priority.observe(&[record("rapl:socket:0:package")]);
+
+        assert_eq!(priority.role("ipmi:chassis"), DeviceRole::Authoritative);
+        assert_eq!(
+            priority.role("rapl:socket:0:package"),
+            DeviceRole::Auxiliary
+        );
+    }
+
+    #[test]
+    fn devices_outside_configured_domains_are_authoritative() {
+        let mut priority = DevicePriority::new(vec![platform_rule()]);
+        priority.observe(&[record("rapl:socket:0:package")]);
+
+        assert_eq!(priority.role("nvidia:gpu:0"), DeviceRole::Authoritative);
+        assert_eq!(
+            DevicePriority::default().role("rapl:system:psys"),
+            DeviceRole::Authoritative
+        );
+    }
+
+    #[test]
+    fn derived_devices_are_auxiliary() {
+        assert_eq!(
+            DevicePriority::default().role("derived:cpu_total"),
+            DeviceRole::Auxiliary
+        );
+    }
+}
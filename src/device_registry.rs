@@ -0,0 +1,336 @@
+/// Device Registry Module
+///
+/// Assigns every energy device a compact `device_id` and keeps its metadata in
+/// a `devices` table. Collectors describe their devices at discovery; the energy
+/// trace and trace exports then reference devices by id, so joins use a small
+/// integer key instead of free-form names such as `rapl:socket:0:package`.
+///
+/// Ids are derived from the device name, so the same device keeps its id
+/// across runs and registries regardless of discovery order, and traces
+/// written by a restarted daemon join against one `devices` table. Devices
+/// that show up in records without having been announced are registered on
+/// first use with metadata inferred from their name.
+use polars::prelude::*;
+use std::collections::{BTreeMap, HashMap};
+
+/// Column names of the `devices` table.
+pub const DEVICE_ID_COLUMN: &str = "device_id";
+const NAME_COLUMN: &str = "device";
+const KIND_COLUMN: &str = "kind";
+const SOCKET_COLUMN: &str = "socket";
+const VENDOR_COLUMN: &str = "vendor";
+const MODEL_COLUMN: &str = "model";
+const MAX_POWER_COLUMN: &str = "max_power_watts";
+const SOURCE_COLUMN: &str = "source";
+
+/// Physical category of an energy device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceKind {
+    CpuPackage,
+    CpuCore,
+    CpuUncore,
+    Dram,
+    Psys,
+    Gpu,
+    Other,
+}
+
+impl DeviceKind {
+    /// Infer the kind from an EMT device name.
+    pub fn from_device_name(name: &str) -> Self {
+        match name.rsplit(':').next().unwrap_or_default() {
+            "package" => Self::CpuPackage,
+            "core" => Self::CpuCore,
+            "uncore" => Self::CpuUncore,
+            "dram" => Self::Dram,
+            "psys" => Self::Psys,
+            _ if name.contains(":gpu:") => Self::Gpu,
+            _ => Self::Other,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::CpuPackage => "cpu_package",
+            Self::CpuCore => "cpu_core",
+            Self::CpuUncore => "cpu_uncore",
+            Self::Dram => "dram",
+            Self::Psys => "psys",
+            Self::Gpu => "gpu",
+            Self::Other => "other",
+        }
+    }
+}
+
+/// Metadata describing one energy device.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeviceInfo {
+    /// Device name as it appears in energy records.
+    pub name: String,
+    pub kind: DeviceKind,
+    /// CPU socket, for per-socket devices.
+    pub socket: Option<u32>,
+    pub vendor: Option<String>,
+    pub model: Option<String>,
+    /// Rated maximum power draw, when the hardware reports one.
+    pub max_power_watts: Option<f64>,
+    /// Interface the energy is read through, such as the `intel-rapl` or
+    /// `intel-rapl-mmio` powercap zones, when a device has several.
+    pub source: Option<String>,
+}
+
+impl DeviceInfo {
+    /// Describe `name` with the kind and socket inferred from the name.
+    pub fn new(name: impl Into<String>) -> Self {
+        let name = name.into();
+        let socket = name
+            .split(':')
+            .skip_while(|part| *part != "socket")
+            .nth(1)
+            .and_then(|id| id.parse().ok());
+        Self {
+            kind: DeviceKind::from_device_name(&name),
+            socket,
+            vendor: None,
+            model: None,
+            max_power_watts: None,
+            source: None,
+            name,
+        }
+    }
+
+    pub fn with_vendor(mut self, vendor: Option<String>) -> Self {
+        self.vendor = vendor;
+        self
+    }
+
+    pub fn with_model(mut self, model: Option<String>) -> Self {
+        self.model = model;
+        self
+    }
+
+    pub fn with_max_power_watts(mut self, max_power_watts: Option<f64>) -> Self {
+        self.max_power_watts = max_power_watts;
+        self
+    }
+
+    pub fn with_source(mut self, source: Option<String>) -> Self {
+        self.source = source;
+        self
+    }
+}
+
+/// Registry of known devices, indexed by `device_id`.
+#[derive(Debug, Clone, Default)]
+pub struct DeviceRegistry {
+    devices: BTreeMap<u32, DeviceInfo>,
+    ids: HashMap<String, u32>,
+}
+
+/// Id of the device `name`: the 32-bit FNV-1a hash of the name, which is
+/// stable across platforms and compiler versions.
+fn name_id(name: &str) -> u32 {
+    name.bytes().fold(0x811c_9dc5, |hash, byte| {
+        (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193)
+    })
+}
+
+impl DeviceRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register `info` and return its id. Registering a known name keeps the
+    /// existing id and fills in any metadata that was missing.
+    pub fn register(&mut self, info: DeviceInfo) -> u32 {
+        if let Some(&id) = self.ids.get(&info.name) {
+            let known = self.devices.get_mut(&id).expect("registered id");
+            known.socket = known.socket.or(info.socket);
+            known.vendor = known.vendor.take().or(info.vendor);
+            known.model = known.model.take().or(info.model);
+            known.max_power_watts = known.max_power_watts.or(info.max_power_watts);
+            known.source = known.source.take().or(info.source);
+            return id;
+        }
+        // Two names sharing a hash are told apart by probing for a free id.
+        let mut id = name_id(&info.name);
+        while self.devices.contains_key(&id) {
+            id = id.wrapping_add(1);
+        }
+        self.ids.insert(info.name.clone(), id);
+        self.devices.insert(id, info);
+        id
+    }
+
+    /// Id of `name`, registering it with inferred metadata if unknown.
+    pub fn id_or_register(&mut self, name: &str) -> u32 {
+        match self.ids.get(name) {
+            Some(&id) => id,
+            None => self.register(DeviceInfo::new(name)),
+        }
+    }
+
+    pub fn id(&self, name: &str) -> Option<u32> {
+        self.ids.get(name).copied()
+    }
+
+    pub fn get(&self, device_id: u32) -> Option<&DeviceInfo> {
+        self.devices.get(&device_id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.devices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.devices.is_empty()
+    }
+
+    /// Devices with their ids, in id order.
+    pub fn iter(&self) -> impl Iterator<Item = (u32, &DeviceInfo)> {
+        self.devices.iter().map(|(&id, info)| (id, info))
+    }
+
+    /// The `devices` table:
+    /// `device_id | device | kind | socket | vendor | model | max_power_watts |
+    /// source`.
+    pub fn to_dataframe(&self) -> PolarsResult<DataFrame> {
+        DataFrame::new(vec![
+            Column::new(
+                DEVICE_ID_COLUMN.into(),
+                self.devices.keys().copied().collect::<Vec<_>>(),
+            ),
+            Column::new(
+                NAME_COLUMN.into(),
+                self.devices
+                    .values()
+                    .map(|d| d.name.as_str())
+                    .collect::<Vec<_>>(),
+            ),
+            Column::new(
+                KIND_COLUMN.into(),
+                self.devices
+                    .values()
+                    .map(|d| d.kind.as_str())
+                    .collect::<Vec<_>>(),
+            ),
+            Column::new(
+                SOCKET_COLUMN.into(),
+                self.devices.values().map(|d| d.socket).collect::<Vec<_>>(),
+            ),
+            Column::new(
+                VENDOR_COLUMN.into(),
+                self.devices
+                    .values()
+                    .map(|d| d.vendor.as_deref())
+                    .collect::<Vec<_>>(),
+            ),
+            Column::new(
+                MODEL_COLUMN.into(),
+                self.devices
+                    .values()
+                    .map(|d| d.model.as_deref())
+                    .collect::<Vec<_>>(),
+            ),
+            Column::new(
+                MAX_POWER_COLUMN.into(),
+                self.devices
+                    .values()
+                    .map(|d| d.max_power_watts)
+                    .collect::<Vec<_>>(),
+            ),
+            Column::new(
+                SOURCE_COLUMN.into(),
+                self.devices
+                    .values()
+                    .map(|d| d.source.as_deref())
+                    .collect::<Vec<_>>(),
+            ),
+        ])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn register_assigns_stable_ids_and_merges_metadata() {
+        let mut registry = DeviceRegistry::new();
+
+        let package = registry.register(DeviceInfo::new("rapl:socket:1:package"));
+        let gpu = registry.id_or_register("nvidia:gpu:0");
+        let again = registry.register(
+            DeviceInfo::new("rapl:socket:1:package")
+                .with_max_power_watts(Some(165.0))
+                .with_source(Some("intel-rapl".to_string())),
+        );
+
+        assert_eq!(again, package);
+        assert_ne!(gpu, package);
+        let info = registry.get(package).unwrap();
+        assert_eq!(info.kind, DeviceKind::CpuPackage);
+        assert_eq!(info.socket, Some(1));
+        assert_eq!(info.max_power_watts, Some(165.0));
+        assert_eq!(info.source.as_deref(), Some("intel-rapl"));
+        assert_eq!(registry.get(gpu).unwrap().kind, DeviceKind::Gpu);
+    }
+
+    #[test]
+    fn ids_do_not_depend_on_registration_order() {
+        let names = [
+            "rapl:socket:0:package",
+            "rapl:socket:0:dram",
+            "nvidia:gpu:0",
+        ];
+        let mut first = DeviceRegistry::new();
+        let mut second = DeviceRegistry::new();
+        for name in names {
+            first.id_or_register(name);
+        }
+        for name in names.iter().rev() {
+            second.id_or_register(name);
+        }
+
+        for name in names {
+            assert_eq!(first.id(name), second.id(name));
+        }
+        assert_eq!(first.id("nvidia:gpu:0"), Some(name_id("nvidia:gpu:0")));
+    }
+
+    #[test]
+    fn colliding_names_get_distinct_ids() {
+        let mut registry = DeviceRegistry::new();
+        let taken = name_id("rapl:system:psys");
+        registry.devices.insert(taken, DeviceInfo::new("other"));
+
+        let id = registry.id_or_register("rapl:system:psys");
+
+        assert_eq!(id, taken.wrapping_add(1));
+        assert_eq!(registry.get(id).unwrap().name, "rapl:system:psys");
+    }
+
+    #[test]
+    fn to_dataframe_lists_devices_in_id_order() {
+        let mut registry = DeviceRegistry::new();
+        let dram = registry.register(
+            DeviceInfo::new("rapl:system:dram").with_vendor(Some("GenuineIntel".to_string())),
+        );
+        let psys = registry.id_or_register("rapl:system:psys");
+
+        let table = registry.to_dataframe().unwrap();
+
+        assert_eq!(table.height(), 2);
+        let ids = table.column(DEVICE_ID_COLUMN).unwrap().u32().unwrap();
+        let kinds = table.column("kind").unwrap().str().unwrap();
+        let (first, second) = if dram < psys {
+            ("dram", "psys")
+        } else {
+            ("psys", "dram")
+        };
+        assert_eq!(ids.get(0), Some(dram.min(psys)));
+        assert_eq!(kinds.get(0), Some(first));
+        assert_eq!(kinds.get(1), Some(second));
+        assert_eq!(table.column("vendor").unwrap().null_count(), 1);
+    }
+}
@@ -0,0 +1,310 @@
+/// Encryption Module
+///
+/// Optional at-rest encryption for exported files. Per-process and per-user
+/// energy data can be sensitive telemetry, so trace segments and JSON exports
+/// may be sealed with AES-256-GCM using a key supplied through a file or an
+/// environment variable.
+///
+/// Encrypted files use a small self-describing envelope:
+/// `EMTENC1\0` magic, a random 96-bit nonce, then the ciphertext and tag.
+/// Files that are appended to, such as the active trace segment, are
+/// instead a stream: `EMTENC2\0` magic followed by frames, each a 32-bit
+/// little-endian length, a random nonce, then the ciphertext and tag. Each
+/// frame is authenticated with its position, so frames cannot be reordered,
+/// and a frame cut short by a crash is ignored. Streams carry no end marker,
+/// so a stream truncated after any whole frame opens as the frames before
+/// the cut; only frames from its middle cannot be dropped unnoticed.
+use crate::config::EncryptionConfig;
+use ring::aead::{AES_256_GCM, Aad, LessSafeKey, NONCE_LEN, Nonce, UnboundKey};
+use ring::rand::{SecureRandom, SystemRandom};
+use std::fs;
+use std::path::Path;
+
+/// Magic bytes identifying an EMT-encrypted file.
+const MAGIC: &[u8; 8] = b"EMTENC1\0";
+/// Magic bytes starting an EMT-encrypted stream of frames.
+pub const STREAM_MAGIC: &[u8; 8] = b"EMTENC2\0";
+/// Length prefix of a stream frame.
+const FRAME_LENGTH_LEN: usize = 4;
+/// Extension appended to encrypted trace segments.
+pub const ENCRYPTED_EXTENSION: &str = "enc";
+
+/// Errors that can occur while loading keys or sealing/opening files.
+#[derive(Debug, thiserror::Error)]
+pub enum EncryptionError {
+    #[error("Failed to read key: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Environment variable {0} is not set")]
+    MissingEnv(String),
+    #[error("Key must be 64 hex characters (256 bits)")]
+    InvalidKey,
+    #[error("Not an EMT-encrypted file")]
+    NotEncrypted,
+    #[error("Decryption failed: wrong key or corrupted data")]
+    Decrypt,
+    #[error("Encryption failed")]
+    Encrypt,
+}
+
+/// AES-256-GCM cipher for exported files.
+pub struct TraceCipher {
+    key: LessSafeKey,
+    rng: SystemRandom,
+}
+
+impl std::fmt::Debug for TraceCipher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TraceCipher").finish_non_exhaustive()
+    }
+}
+
+impl TraceCipher {
+    /// Build a cipher from a hex-encoded 256-bit key.
+    pub fn from_hex_key(hex_key: &str) -> Result<Self, EncryptionError> {
+        let bytes = hex::decode(hex_key.trim()).map_err(|_| EncryptionError::InvalidKey)?;
+        let key = UnboundKey::new(&AES_256_GCM, &bytes).map_err(|_| EncryptionError::InvalidKey)?;
+        Ok(Self {
+            key: LessSafeKey::new(key),
+            rng: SystemRandom::new(),
+        })
+    }
+
+    /// Resolve the configured key source. Returns `None` when encryption is
+    /// not configured. A key file takes precedence over an environment variable.
+    pub fn from_config(config: &EncryptionConfig) -> Result<Option<Self>, EncryptionError> {
+        if let Some(path) = config.key_file.as_deref() {
+            return Self::from_hex_key(&fs::read_to_string(path)?).map(Some);
+        }
+        if let Some(var) = config.key_env.as_deref() {
+            let key =
+                std::env::var(var).map_err(|_| EncryptionError::MissingEnv(var.to_string()))?;
+            return Self::from_hex_key(&key).map(Some);
+        }
+        Ok(None)
+    }
+
+    /// Encrypt `plaintext` into the EMT envelope.
+    pub fn seal(&self, plaintext: &[u8]) -> Result<Vec<u8>, EncryptionError> {
+        let mut nonce = [0u8; NONCE_LEN];
+        self.rng
+            .fill(&mut nonce)
+            .map_err(|_| EncryptionError::Encrypt)?;
+
+        let mut in_out = plaintext.to_vec();
+        self.key
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(MAGIC),
+                &mut in_out,
+            )
+            .map_err(|_| EncryptionError::Encrypt)?;
+
+        let mut sealed = Vec::with_capacity(MAGIC.len() + NONCE_LEN + in_out.len());
+        sealed.extend_from_slice(MAGIC);
+        sealed.extend_from_slice(&nonce);
+        sealed.extend_from_slice(&in_out);
+        Ok(sealed)
+    }
+
+    /// Encrypt `plaintext` as frame `index` of a stream, which starts with
+    /// [`STREAM_MAGIC`] and is read back by [`TraceCipher::open`].
+    pub fn seal_frame(&self, index: u64, plaintext: &[u8]) -> Result<Vec<u8>, EncryptionError> {
+        let mut nonce = [0u8; NONCE_LEN];
+        self.rng
+            .fill(&mut nonce)
+            .map_err(|_| EncryptionError::Encrypt)?;
+
+        let mut in_out = plaintext.to_vec();
+        self.key
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(frame_aad(index)),
+                &mut in_out,
+            )
+            .map_err(|_| EncryptionError::Encrypt)?;
+
+        let length =
+            u32::try_from(NONCE_LEN + in_out.len()).map_err(|_| EncryptionError::Encrypt)?;
+        let mut frame = Vec::with_capacity(FRAME_LENGTH_LEN + length as usize);
+        frame.extend_from_slice(&length.to_le_bytes());
+        frame.extend_from_slice(&nonce);
+        frame.extend_from_slice(&in_out);
+        Ok(frame)
+    }
+
+    /// Decrypt an EMT envelope produced by [`TraceCipher::seal`], or a
+    /// stream of frames produced by [`TraceCipher::seal_frame`].
+    pub fn open(&self, sealed: &[u8]) -> Result<Vec<u8>, EncryptionError> {
+        if let Some(frames) = sealed.strip_prefix(STREAM_MAGIC.as_slice()) {
+            return self.open_stream(frames);
+        }
+        let body = sealed
+            .strip_prefix(MAGIC.as_slice())
+            .ok_or(EncryptionError::NotEncrypted)?;
+        if body.len() < NONCE_LEN {
+            return Err(EncryptionError::Decrypt);
+        }
+        let (nonce, ciphertext) = body.split_at(NONCE_LEN);
+        let nonce =
+            Nonce::try_assume_unique_for_key(nonce).map_err(|_| EncryptionError::Decrypt)?;
+
+        let mut in_out = ciphertext.to_vec();
+        let plaintext = self
+            .key
+            .open_in_place(nonce, Aad::from(MAGIC), &mut in_out)
+            .map_err(|_| EncryptionError::Decrypt)?;
+        Ok(plaintext.to_vec())
+    }
+
+    /// Decrypt and concatenate the frames of a stream. A trailing frame
+    /// shorter than its length prefix was cut short while being written,
+    /// and is left out. Whole frames missing from the end are not detected.
+    fn open_stream(&self, mut frames: &[u8]) -> Result<Vec<u8>, EncryptionError> {
+        let mut plaintext = Vec::new();
+        let mut index = 0;
+        while frames.len() >= FRAME_LENGTH_LEN {
+            let (length, rest) = frames.split_at(FRAME_LENGTH_LEN);
+            let length = u32::from_le_bytes(length.try_into().expect("4-byte prefix")) as usize;
+            if rest.len() < length {
+                break;
+            }
+            let (frame, rest) = rest.split_at(length);
+            if frame.len() < NONCE_LEN {
+                return Err(EncryptionError::Decrypt);
+            }
+            let (nonce, ciphertext) = frame.split_at(NONCE_LEN);
+            let nonce =
+                Nonce::try_assume_unique_for_key(nonce).map_err(|_| EncryptionError::Decrypt)?;
+            let mut in_out = ciphertext.to_vec();
+            let opened = self
+                .key
+                .open_in_place(nonce, Aad::from(frame_aad(index)), &mut in_out)
+                .map_err(|_| EncryptionError::Decrypt)?;
+            plaintext.extend_from_slice(opened);
+            frames = rest;
+            index += 1;
+        }
+        Ok(plaintext)
+    }
+
+    /// Encrypt the file at `path` into `<path>.enc` and remove the plain file.
+    pub fn seal_file(&self, path: &Path) -> Result<(), EncryptionError> {
+        let sealed = self.seal(&fs::read(path)?)?;
+        let mut sealed_path = path.as_os_str().to_owned();
+        sealed_path.push(".");
+        sealed_path.push(ENCRYPTED_EXTENSION);
+        fs::write(sealed_path, sealed)?;
+        fs::remove_file(path)?;
+        Ok(())
+    }
+}
+
+/// Whether `bytes` start with the EMT encryption envelope or stream.
+pub fn is_encrypted(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC) || bytes.starts_with(STREAM_MAGIC)
+}
+
+/// Additional data binding a stream frame to its position.
+fn frame_aad(index: u64) -> [u8; 16] {
+    let mut aad = [0u8; 16];
+    aad[..8].copy_from_slice(STREAM_MAGIC);
+    aad[8..].copy_from_slice(&index.to_le_bytes());
+    aad
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    const KEY: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+
+    #[test]
+    fn seal_and_open_round_trip() {
+        let cipher = TraceCipher::from_hex_key(KEY).unwrap();
+
+        let sealed = cipher.seal(b"pid,timestamp,device,energy\n").unwrap();
+
+        assert!(is_encrypted(&sealed));
+        assert_eq!(
+            cipher.open(&sealed).unwrap(),
+            b"pid,timestamp,device,energy\n"
+        );
+    }
+
+    #[test]
+    fn open_rejects_wrong_key_and_tampering() {
+        let cipher = TraceCipher::from_hex_key(KEY).unwrap();
+        let other = TraceCipher::from_hex_key(&KEY.replace("00", "ff")).unwrap();
+        let mut sealed = cipher.seal(b"energy").unwrap();
+
+        assert!(matches!(other.open(&sealed), Err(EncryptionError::Decrypt)));
+        *sealed.last_mut().unwrap() ^= 1;
+        assert!(matches!(
+            cipher.open(&sealed),
+            Err(EncryptionError::Decrypt)
+        ));
+        assert!(matches!(
+            cipher.open(b"plain"),
+            Err(EncryptionError::NotEncrypted)
+        ));
+    }
+
+    #[test]
+    fn streams_open_frame_by_frame_and_ignore_a_torn_tail() {
+        let cipher = TraceCipher::from_hex_key(KEY).unwrap();
+        let mut stream = STREAM_MAGIC.to_vec();
+        stream.extend(cipher.seal_frame(0, b"pid,energy\n").unwrap());
+        stream.extend(cipher.seal_frame(1, b"1,2.5\n").unwrap());
+
+        assert!(is_encrypted(&stream));
+        assert!(
+            !stream
+                .windows(b"energy".len())
+                .any(|window| window == b"energy")
+        );
+        assert_eq!(cipher.open(&stream).unwrap(), b"pid,energy\n1,2.5\n");
+
+        let torn = cipher.seal_frame(2, b"2,1.0\n").unwrap();
+        stream.extend_from_slice(&torn[..torn.len() - 3]);
+        assert_eq!(cipher.open(&stream).unwrap(), b"pid,energy\n1,2.5\n");
+
+        let mut reordered = STREAM_MAGIC.to_vec();
+        reordered.extend(cipher.seal_frame(1, b"1,2.5\n").unwrap());
+        assert!(matches!(
+            cipher.open(&reordered),
+            Err(EncryptionError::Decrypt)
+        ));
+    }
+
+    #[test]
+    fn key_sources_resolve_from_file_and_reject_bad_keys() {
+        let dir = TempDir::new().unwrap();
+        let key_file = dir.path().join("emt.key");
+        fs::write(&key_file, format!("{KEY}\n")).unwrap();
+
+        let from_file = TraceCipher::from_config(&EncryptionConfig {
+            key_file: Some(key_file.display().to_string()),
+            key_env: None,
+        })
+        .unwrap();
+
+        assert!(from_file.is_some());
+        assert!(
+            TraceCipher::from_config(&EncryptionConfig::default())
+                .unwrap()
+                .is_none()
+        );
+        assert!(matches!(
+            TraceCipher::from_hex_key("abcd"),
+            Err(EncryptionError::InvalidKey)
+        ));
+        assert!(matches!(
+            TraceCipher::from_config(&EncryptionConfig {
+                key_file: None,
+                key_env: Some("EMT_TEST_KEY_THAT_IS_NOT_SET".to_string()),
+            }),
+            Err(EncryptionError::MissingEnv(_))
+        ));
+    }
+}
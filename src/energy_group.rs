@@ -1,13 +1,56 @@
-use crate::trace_recorder::TraceRecorder;
-use crate::utils::errors::MonitoringError;
-use crate::utils::trace_rotation::RotatingTrace;
+use crate::baseline::IdleBaseline;
+use crate::carbon::{self, CarbonAccount, CarbonFootprint, CarbonIntensityProvider};
+use crate::collectors::cpu_energy::CpuEnergy;
+use crate::config::{
+    ConfigError, CpuEnergyConfig, EmtConfig, EnergyDomainRule, default_energy_domains,
+};
+use crate::device_registry::{DEVICE_ID_COLUMN, DeviceInfo, DeviceRegistry};
+use crate::encryption::TraceCipher;
+use crate::energy_totals::EnergyTotals;
+use crate::forecast::{
+    Completion, EnergyForecast, ForecastError, ForecastMethod, ForecastScope, forecaster_from_trace,
+};
+use crate::metric_channels::{MetricError, join_metrics, metric_frame};
+use crate::peak_events::{PeakDetection, PeakDetector, PeakEvent};
+use crate::power_stats::{PowerGrouping, PowerHistogram, PowerPoint, PowerStats};
+use crate::process_watcher::ProcessWatcher;
+use crate::query::{Filter, TimeRange, join_metadata, query_trace};
+use crate::read_schedule::{ReadPhase, ReadSchedule};
+use crate::regions::{Region, RegionEnergy, RegionError, RegionMarkers, energy_by_region};
+use crate::sampler::{SamplerSubscription, shared_cpu_sampler};
+use crate::schema::SchemaError;
+use crate::sessions::{
+    SessionError, SessionMarkers, SessionSummary, session_summary, session_trace,
+};
+use crate::sinks::TraceSink;
+use crate::smoothing::PowerSmoothing;
+use crate::spike_filter::{RecordScreening, SpikeFilter, SpikeFiltering};
+use crate::suspend::{SuspendDetector, SuspendGap};
+use crate::trace_recorder::{
+    CsvTraceRecorder, ParquetOptions, ParquetTraceRecorder, TraceFormat, TraceRecorder,
+};
+use crate::trace_summary::{self, TraceSummary};
+use crate::utils::cgroup::TrackedCgroup;
+use crate::utils::errors::{CollectorError, MonitoringError};
+use crate::utils::psutils::ProcessGroup;
+use crate::utils::time::{
+    MONOTONIC_COLUMN, RecordTime, TIMESTAMP_COLUMN, monotonic_dtype, now_micros, timestamp_dtype,
+};
+use crate::utils::trace_rotation::{DeviceRetention, RotatingTrace};
+use crate::wal::{WalError, WriteAheadLog};
+use crate::work::{
+    Efficiency, WorkCounters, WorkError, WorkEvent, efficiency_by_process, efficiency_by_region,
+};
 use async_trait::async_trait;
 use polars::prelude::*;
-use std::collections::HashMap;
-use std::sync::Arc;
-use std::sync::atomic::{AtomicBool, Ordering};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Weak};
 use std::time::{Duration, Instant};
-use tokio::sync::mpsc;
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tokio::sync::{Mutex as AsyncMutex, Notify};
 use tokio::task::JoinHandle;
 
 #[derive(Debug)]
@@ -17,12 +60,73 @@ pub enum EnergyCollectorType {
     Dummy,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EnergyRecord {
     pub pid: u32,
-    pub timestamp: i64,
+    /// Wall-clock time of the device read in Unix microseconds (UTC).
+    pub timestamp_us: i64,
+    /// Monotonic time captured with `timestamp_us`; see
+    /// [`RecordTime`](crate::utils::time::RecordTime).
+    pub monotonic_ns: i64,
     pub device: String,
     pub energy: f64,
+    /// Reading of the device's cumulative counter, in joules, that `energy`
+    /// was differenced from; `None` when the collector has no such counter.
+    pub raw_counter: Option<f64>,
+}
+
+/// A device domain a collector is skipping because it could not be
+/// discovered or read, while its other domains keep reporting.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DegradedDomain {
+    /// Device name, or the counter location when discovery failed before
+    /// the domain could be named
+    pub domain: String,
+    /// Most recent error
+    pub error: String,
+    /// Consecutive failed reads; 0 for domains skipped at discovery
+    pub failed_reads: u64,
+}
+
+/// Trace column with the running total of `energy` per pid and device.
+pub const CUMULATIVE_ENERGY_COLUMN: &str = "cumulative_energy";
+/// Trace column with [`EnergyRecord::raw_counter`].
+pub const RAW_COUNTER_COLUMN: &str = "raw_counter";
+/// Trace column with the [`RecordQuality`] flag of each record.
+pub const QUALITY_COLUMN: &str = "quality";
+/// Trace column with the energy as read of records the spike filter
+/// corrected, null for the others.
+pub const ORIGINAL_ENERGY_COLUMN: &str = "original_energy";
+/// Trace column with the energy above the calibrated idle power, added once
+/// [`EnergyGroup::calibrate_baseline`] has run.
+pub const MARGINAL_ENERGY_COLUMN: &str = "marginal_energy";
+/// Trace column with the emissions of each record in grams CO2e, null
+/// while no carbon intensity is known.
+pub const CO2_COLUMN: &str = "co2";
+/// Trace column with the time between the read of each record's device and
+/// the read before, in milliseconds, that its energy was used over; null
+/// for a device's first read.
+pub const SAMPLE_DURATION_COLUMN: &str = "sample_duration_ms";
+/// How long shutdown waits for the background task to deliver its final
+/// batch before aborting it, such as when a collector read hangs.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Optional trace columns next to the per-interval `energy`, for consumers
+/// that compute rates themselves.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RecordOutput {
+    /// Add [`CUMULATIVE_ENERGY_COLUMN`]
+    pub cumulative_energy: bool,
+    /// Add [`RAW_COUNTER_COLUMN`]
+    pub raw_counter: bool,
+    /// Add [`QUALITY_COLUMN`] and [`ORIGINAL_ENERGY_COLUMN`]; always on
+    /// while spike filtering is enabled
+    pub quality: bool,
+    /// Add [`CO2_COLUMN`]
+    pub co2: bool,
+    /// Add [`SAMPLE_DURATION_COLUMN`]
+    pub sample_duration: bool,
 }
 
 #[derive(Debug, Clone)]
@@ -33,6 +137,160 @@ pub struct UtilizationRecord {
     pub utilization: f64,
 }
 
+/// What the background task does with a batch while the channel to the group
+/// is full.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackpressurePolicy {
+    /// Wait until the group drains the channel, delaying the next read
+    #[default]
+    Block,
+    /// Discard the oldest queued batch to make room
+    DropOldest,
+    /// Discard the new batch
+    DropNewest,
+}
+
+/// When the background task reads after reads overran the collection
+/// interval.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MissedTickPolicy {
+    /// Read back to back until the missed ticks are made up
+    Burst,
+    /// Drop the missed ticks and read on the next tick of the cadence
+    #[default]
+    Skip,
+    /// Start the cadence again from the late read
+    Delay,
+}
+
+impl From<MissedTickPolicy> for tokio::time::MissedTickBehavior {
+    fn from(policy: MissedTickPolicy) -> Self {
+        match policy {
+            MissedTickPolicy::Burst => Self::Burst,
+            MissedTickPolicy::Skip => Self::Skip,
+            MissedTickPolicy::Delay => Self::Delay,
+        }
+    }
+}
+
+/// Receiving half of the collector-to-group channel, shared between the group,
+/// the auto-drain task and `DropOldest` senders.
+type BatchReceiver = AsyncMutex<mpsc::Receiver<Vec<EnergyRecord>>>;
+
+/// Records taken from the channel ahead of the group's next poll, by the
+/// auto-drain task or while waiting for data.
+#[derive(Default)]
+struct DrainedRecords {
+    records: Mutex<Vec<EnergyRecord>>,
+    /// Set once the channel has closed, i.e. the collector stopped
+    closed: AtomicBool,
+    /// Signalled after every drained batch and once the channel closes
+    updated: Notify,
+    /// Sinks every batch is streamed to as it leaves the channel
+    sinks: Mutex<Vec<Box<dyn TraceSink>>>,
+}
+
+impl DrainedRecords {
+    /// Stream `batch` to the sinks and move it aside for the next poll.
+    fn receive(&self, batch: Vec<EnergyRecord>) {
+        self.write_sinks(&batch);
+        self.records.lock().unwrap().extend(batch);
+    }
+
+    fn write_sinks(&self, records: &[EnergyRecord]) {
+        if records.is_empty() {
+            return;
+        }
+        for sink in self.sinks.lock().unwrap().iter_mut() {
+            if let Err(e) = sink.write_batch(records) {
+                log::error!("Failed to write to trace sink: {}", e);
+            }
+        }
+    }
+}
+
+/// Sending half of the collector-to-group channel, applying the
+/// [`BackpressurePolicy`] when it is full.
+struct BatchSender {
+    tx: mpsc::Sender<Vec<EnergyRecord>>,
+    /// The group's receiver, so `DropOldest` can discard queued batches. Weak,
+    /// so the channel still closes when the group goes away.
+    rx: Weak<BatchReceiver>,
+    policy: BackpressurePolicy,
+    dropped_batches: Arc<AtomicU64>,
+}
+
+impl BatchSender {
+    /// Send `batch`, returning `false` once the receiver is gone.
+    async fn send(&self, mut batch: Vec<EnergyRecord>) -> bool {
+        if self.policy == BackpressurePolicy::Block {
+            // Waits while the channel is full, slowing down collection
+            let send_start = Instant::now();
+            if self.tx.send(batch).await.is_err() {
+                return false;
+            }
+            let send_duration = send_start.elapsed();
+            if send_duration.as_millis() > 100 {
+                log::warn!(
+                    "Channel send blocked for {:?} - receiver may be slow!",
+                    send_duration
+                );
+            }
+            return true;
+        }
+        loop {
+            match self.tx.try_send(batch) {
+                Ok(()) => return true,
+                Err(TrySendError::Closed(_)) => return false,
+                Err(TrySendError::Full(full)) => {
+                    if self.policy == BackpressurePolicy::DropNewest {
+                        self.record_drop();
+                        return true;
+                    }
+                    // The group may have drained the channel in the meantime,
+                    // in which case nothing is dropped and the retry succeeds.
+                    let Some(rx) = self.rx.upgrade() else {
+                        return false;
+                    };
+                    match rx.try_lock() {
+                        Ok(mut rx) => {
+                            if rx.try_recv().is_ok() {
+                                self.record_drop();
+                            }
+                        }
+                        // Held by the auto-drain task, which is emptying
+                        // the channel anyway
+                        Err(_) => tokio::task::yield_now().await,
+                    }
+                    batch = full;
+                }
+            }
+        }
+    }
+
+    fn record_drop(&self) {
+        let dropped = self.dropped_batches.fetch_add(1, Ordering::SeqCst) + 1;
+        if dropped.is_power_of_two() {
+            log::warn!(
+                "Channel full, {} batches dropped ({:?}) - receiver may be slow!",
+                dropped,
+                self.policy
+            );
+        }
+    }
+}
+
+/// Device rediscovery settings shared with the background task.
+#[derive(Clone)]
+struct Rediscovery {
+    /// How often the collector re-runs device discovery
+    interval: Duration,
+    /// Set by the background task when rediscovery changed the devices
+    devices_changed: Arc<AtomicBool>,
+}
+
 /// Generic Energy Monitor
 /// # Type Parameters
 /// * `T` - An energy collector type that implements `EnergyCollector`
@@ -41,24 +299,95 @@ pub struct EnergyGroup<T: EnergyCollector> {
     rate: f64,
     /// Number of iterations to batch before sending data back from the collector
     batch_size: usize,
-    /// Rotating trace: pid | timestamp | device | energy
+    /// Rotating trace: pid | device | device_id | energy | timestamp | monotonic_ns
     energy_trace: RotatingTrace,
+    /// Devices referenced by the trace's `device_id` column
+    devices: DeviceRegistry,
+    /// Tracked processes: pid | user | task
+    tracked_processes: DataFrame,
+    /// Named region markers
+    regions: RegionMarkers,
+    /// Named session markers
+    sessions: SessionMarkers,
+    /// Work reported by the workload
+    work: WorkCounters,
+    /// Application metrics: name | value | timestamp | monotonic_ns
+    metric_trace: RotatingTrace,
+    /// Peak power detector, when enabled
+    peak_detector: Option<PeakDetector>,
+    /// Corrects implausible readings before they reach the trace
+    spike_filter: Option<SpikeFilter>,
+    /// Suspend/resume cycles detected by the background task
+    suspend_gaps: Arc<Mutex<Vec<SuspendGap>>>,
+    /// Device rediscovery run by the background task
+    rediscovery: Rediscovery,
+    /// Tracked pids, followed into their children when enabled
+    process_watcher: Arc<Mutex<ProcessWatcher>>,
     /// Underlying collector instance
     energy_collector: Arc<T>,
     /// Flag indicating if the collector is running
     is_running: Arc<AtomicBool>,
+    /// Wakes the background task from its sleep between ticks on shutdown
+    stop: Arc<Notify>,
     /// Handle to the background monitoring task
     task_handle: Option<JoinHandle<()>>,
     /// Receiver for collected energy data from the background task
-    data_receiver: Option<mpsc::Receiver<Vec<EnergyRecord>>>,
+    data_receiver: Option<Arc<BatchReceiver>>,
+    /// Whether a background task drains the channel as batches arrive
+    auto_drain: bool,
+    /// Records drained by the auto-drain task since the last poll
+    drained: Arc<DrainedRecords>,
+    /// Capacity of the channel, in batches
+    channel_capacity: usize,
+    /// What the background task does with batches while the channel is full
+    backpressure: BackpressurePolicy,
+    /// Batches discarded by the backpressure policy
+    dropped_batches: Arc<AtomicU64>,
+    /// When reads are made after one overran the collection interval
+    missed_ticks: MissedTickPolicy,
     /// Per-PID cumulative energy accumulator
     consumed_energy: HashMap<u32, f64>,
+    /// Cumulative energy per device, for overlap-aware totals
+    device_energy: BTreeMap<String, f64>,
+    /// Domains whose meter contains other devices
+    energy_domains: Vec<EnergyDomainRule>,
+    /// Optional trace columns
+    record_output: RecordOutput,
+    /// Running energy per pid and device, for the cumulative column
+    cumulative_energy: HashMap<(u32, String), f64>,
+    /// Monotonic time of the latest read of each device and the
+    /// milliseconds since the read before, for the sample duration column
+    sample_times: HashMap<String, (i64, Option<f64>)>,
     /// Registered trace recorders for persistent storage
     recorders: Vec<Box<dyn TraceRecorder>>,
     /// Cadence for periodic trace recorder flushes.
     recorder_flush_interval: Duration,
     /// Last time registered trace recorders were flushed.
     last_recorder_flush: Instant,
+    /// Log every polled batch is written to before it reaches the trace
+    wal: Option<WriteAheadLog>,
+    /// Idle power subtracted into the marginal energy column, once calibrated
+    baseline: Option<IdleBaseline>,
+    /// Emissions per device at the current grid intensity
+    carbon: CarbonAccount,
+    /// Source of the grid intensity and how often it is asked
+    carbon_provider: Option<(Arc<dyn CarbonIntensityProvider>, Duration)>,
+    /// Task refreshing the grid intensity while running
+    carbon_refresh: Option<JoinHandle<()>>,
+}
+
+impl EnergyGroup<SamplerSubscription<CpuEnergy>> {
+    /// Create a CPU energy group subscribed to the process-wide
+    /// [`shared_cpu_sampler`], so CPU groups and monitors in one process
+    /// reading at the same rate read the counters once per tick between
+    /// them.
+    pub fn cpu(config: &CpuEnergyConfig, rate: f64, batch_size: Option<usize>) -> Self {
+        Self::new(
+            shared_cpu_sampler(config, rate).subscribe(),
+            rate,
+            batch_size,
+        )
+    }
 }
 
 impl<T: EnergyCollector> EnergyGroup<T> {
@@ -66,25 +395,130 @@ impl<T: EnergyCollector> EnergyGroup<T> {
     pub fn new(collector: T, rate: f64, batch_size: Option<usize>) -> Self {
         // Create rotating trace with 1 hour default retention
         let energy_trace = RotatingTrace::new(3600);
+        let mut devices = DeviceRegistry::new();
+        for info in collector.devices() {
+            devices.register(info);
+        }
 
         Self {
             rate,
             batch_size: batch_size.unwrap_or(1000),
             energy_trace,
+            devices,
+            tracked_processes: tracked_processes_frame(&[]),
+            regions: RegionMarkers::new(),
+            sessions: SessionMarkers::new(),
+            work: WorkCounters::new(),
+            metric_trace: RotatingTrace::new(3600),
+            peak_detector: None,
+            spike_filter: None,
+            suspend_gaps: Arc::new(Mutex::new(Vec::new())),
+            rediscovery: Rediscovery {
+                interval: Duration::from_secs(10),
+                devices_changed: Arc::new(AtomicBool::new(false)),
+            },
+            process_watcher: Arc::new(Mutex::new(ProcessWatcher::default())),
             energy_collector: Arc::new(collector),
             is_running: Arc::new(AtomicBool::new(false)),
+            stop: Arc::new(Notify::new()),
             task_handle: None,
             data_receiver: None,
+            auto_drain: false,
+            drained: Arc::new(DrainedRecords::default()),
+            channel_capacity: 10,
+            backpressure: BackpressurePolicy::Block,
+            dropped_batches: Arc::new(AtomicU64::new(0)),
+            missed_ticks: MissedTickPolicy::default(),
             consumed_energy: HashMap::new(),
+            device_energy: BTreeMap::new(),
+            energy_domains: default_energy_domains(),
+            record_output: RecordOutput::default(),
+            cumulative_energy: HashMap::new(),
+            sample_times: HashMap::new(),
             recorders: Vec::new(),
             recorder_flush_interval: Duration::from_secs(5),
             last_recorder_flush: Instant::now(),
+            wal: None,
+            baseline: None,
+            carbon: CarbonAccount::default(),
+            carbon_provider: None,
+            carbon_refresh: None,
+        }
+    }
+
+    /// Create an EnergyGroup configured by the YAML or TOML file at `path`:
+    /// its `collection` rate, batch size, retention, trace directory and
+    /// write-ahead log, and its energy domains. `name` names the group's
+    /// trace subdirectory and log, as `cpu` and `gpu` do for the monitor.
+    pub fn from_config(
+        collector: T,
+        path: impl AsRef<Path>,
+        name: &str,
+    ) -> Result<Self, ConfigError> {
+        let config = EmtConfig::from_file(path.as_ref())?;
+        Ok(Self::with_config(collector, &config, name))
+    }
+
+    /// Create an EnergyGroup configured by `config`; see
+    /// [`from_config`](Self::from_config).
+    pub fn with_config(collector: T, config: &EmtConfig, name: &str) -> Self {
+        let mut group = Self::new(
+            collector,
+            config.collection.rate_hz,
+            config.collection.batch_size,
+        );
+        group.apply_config(config, name);
+        group
+    }
+
+    /// Apply the collection settings of `config` to this group, as the
+    /// monitor does to each of its groups, and attach the trace recorder
+    /// and write-ahead log of the group `name`.
+    pub fn apply_config(&mut self, config: &EmtConfig, name: &str) {
+        self.set_trace_retention(config.collection.trace_retention_secs as i64);
+        self.set_device_retention(config.collection.device_retention.clone());
+        self.set_recorder_flush_interval(Duration::from_secs_f64(
+            config.collection.trace_flush_interval_secs,
+        ));
+        self.set_rediscover_interval(Duration::from_secs_f64(
+            config.collection.rediscover_interval_secs,
+        ));
+        self.set_record_output(config.export.records);
+        self.set_energy_domains(config.energy_domains.clone());
+        if let Some(intensity) = config.footprint.carbon_intensity_g_per_kwh {
+            self.set_carbon_intensity(intensity);
+        }
+        #[cfg(feature = "carbon-api")]
+        if let Some(maps) = &config.footprint.electricity_maps {
+            match crate::carbon::ElectricityMaps::from_config(maps) {
+                Ok(provider) => self.set_carbon_provider(
+                    Arc::new(provider),
+                    Duration::from_secs_f64(maps.refresh_interval_secs),
+                ),
+                Err(e) => log::warn!("Not fetching carbon intensity for {}: {}", name, e),
+            }
         }
+        self.set_read_phases(config.collection.read_phases.clone());
+        if let Some(filter) = &config.collection.spike_filter {
+            self.set_spike_filter(filter.clone());
+        }
+        self.set_backpressure(
+            config.collection.channel_capacity,
+            config.collection.backpressure,
+        );
+        self.set_missed_tick_policy(config.collection.missed_ticks);
+        if let Some(recorder) = trace_recorder(config, name) {
+            self.add_recorder(recorder);
+        }
+        open_write_ahead_log(self, config, name);
     }
 
-    /// Update the tracked PIDs by delegating to the collector.
+    /// Update the tracked PIDs by delegating to the collector, with their
+    /// descendants when following children.
     pub fn update_tracked_pids(&self, pids: Vec<u32>) {
-        self.energy_collector.set_tracked_pids(pids);
+        let mut watcher = self.process_watcher.lock().unwrap();
+        self.energy_collector
+            .set_tracked_pids(watcher.set_roots(pids));
     }
 
     /// Set the tracked PIDs by delegating to the collector.
@@ -92,21 +526,437 @@ impl<T: EnergyCollector> EnergyGroup<T> {
         self.update_tracked_pids(pids);
     }
 
+    /// Update the tracked cgroups by delegating to the collector.
+    pub fn update_tracked_cgroups(&self, cgroups: Vec<TrackedCgroup>) {
+        self.energy_collector.set_tracked_cgroups(cgroups);
+    }
+
+    /// Track the pids of `groups` and remember their user and task for
+    /// [`EnergyGroup::joined_trace`].
+    pub fn set_tracked_processes(&mut self, groups: &[ProcessGroup]) {
+        self.tracked_processes = tracked_processes_frame(groups);
+        let pids = groups
+            .iter()
+            .flat_map(|group| group.pids.iter().map(|&pid| pid as u32))
+            .collect();
+        self.set_tracked_pids(pids);
+    }
+
+    /// Tracked processes as a `pid | user | task | container | pod` frame;
+    /// container and pod are null outside containers
+    pub fn tracked_processes(&self) -> &DataFrame {
+        &self.tracked_processes
+    }
+
     /// Register a trace recorder for persistent storage of energy data.
     pub fn add_recorder(&mut self, recorder: Box<dyn TraceRecorder>) {
         self.recorders.push(recorder);
     }
 
+    /// Register a sink that every record is streamed to as it arrives from
+    /// the collector, ahead of the in-memory trace.
+    pub fn add_sink(&mut self, sink: Box<dyn TraceSink>) {
+        self.drained.sinks.lock().unwrap().push(sink);
+    }
+
     /// Set the cadence for periodic trace recorder flushes.
     pub fn set_recorder_flush_interval(&mut self, interval: Duration) {
         self.recorder_flush_interval = interval;
     }
 
+    /// Write every batch to the write-ahead log at `path` before applying
+    /// it to the trace, after replaying the batches a previous run left
+    /// there. The log is emptied whenever recorders flush, or cut back to
+    /// the retention window if there are none. Returns the number of
+    /// replayed records.
+    pub fn set_write_ahead_log(&mut self, path: impl Into<PathBuf>) -> Result<usize, WalError> {
+        let (wal, batches) = WriteAheadLog::open(path)?;
+        let records: Vec<EnergyRecord> = batches.into_iter().flatten().collect();
+        self.apply_records(&records);
+        self.wal = Some(wal);
+        Ok(records.len())
+    }
+
+    /// Set how often the collector re-runs device discovery to pick up
+    /// hot-plugged CPUs and GPUs.
+    pub fn set_rediscover_interval(&mut self, interval: Duration) {
+        self.rediscovery.interval = interval;
+    }
+
+    /// Also track the descendants of the tracked PIDs, re-scanning `/proc`
+    /// every `interval` while collecting so workers forked later are
+    /// attributed and exited ones are dropped. `None` tracks the given PIDs
+    /// only, which is the default.
+    pub fn follow_children(&self, interval: Option<Duration>) {
+        let mut watcher = self.process_watcher.lock().unwrap();
+        self.energy_collector
+            .set_tracked_pids(watcher.set_interval(interval));
+    }
+
+    /// PIDs the collector currently attributes energy to
+    pub fn tracked_pids(&self) -> Vec<u32> {
+        self.process_watcher.lock().unwrap().tracked_pids().to_vec()
+    }
+
+    /// Set the capacity of the channel from the background task, in batches,
+    /// and what happens to batches while it is full. Call before collecting.
+    pub fn set_backpressure(&mut self, capacity: usize, policy: BackpressurePolicy) {
+        self.channel_capacity = capacity.max(1);
+        self.backpressure = policy;
+    }
+
+    /// Choose when reads are made after one overran the collection
+    /// interval. Call before collecting.
+    pub fn set_missed_tick_policy(&mut self, policy: MissedTickPolicy) {
+        self.missed_ticks = policy;
+        self.energy_collector.set_missed_tick_policy(policy);
+    }
+
+    /// Drain the collector channel continuously in a background task, so the
+    /// collector never waits for [`poll_data`](Self::poll_data) to be
+    /// called. Drained records are held until the next poll, which appends
+    /// them to the trace. Call before collecting.
+    pub fn set_auto_drain(&mut self, enabled: bool) {
+        self.auto_drain = enabled;
+    }
+
+    /// The collector, for readings it offers beyond energy, such as
+    /// [`NvidiaGpu::get_utilization_trace`](crate::collectors::NvidiaGpu::get_utilization_trace)
+    pub fn collector(&self) -> &T {
+        &self.energy_collector
+    }
+
+    /// Batches discarded by the backpressure policy since creation
+    pub fn dropped_batches(&self) -> u64 {
+        self.dropped_batches.load(Ordering::SeqCst)
+    }
+
+    /// Choose the optional trace columns. Call before collecting, since all
+    /// rows of the trace share one schema.
+    pub fn set_record_output(&mut self, output: RecordOutput) {
+        self.record_output = RecordOutput {
+            quality: output.quality || self.spike_filter.is_some(),
+            ..output
+        };
+    }
+
     /// Get a reference to the energy trace data (as DataFrame)
     pub fn energy_trace(&self) -> &DataFrame {
         self.energy_trace.data()
     }
 
+    /// Write the energy trace to a Parquet dataset at `dir`, laid out like
+    /// the parts of a [`ParquetTraceRecorder`] and added as a new session
+    /// next to any already there. Returns the number of rows written.
+    pub fn write_parquet(
+        &self,
+        dir: impl Into<PathBuf>,
+        options: ParquetOptions,
+    ) -> Result<usize, SchemaError> {
+        ParquetTraceRecorder::new(dir.into())
+            .with_options(options)
+            .write(self.energy_trace())
+    }
+
+    /// Devices referenced by the energy trace's `device_id` column
+    pub fn devices(&self) -> &DeviceRegistry {
+        &self.devices
+    }
+
+    /// Energy trace joined with the tracked processes (on `pid`) and the
+    /// device table (on `device_id`), evaluated lazily:
+    /// `pid | device | device_id | energy | timestamp | monotonic_ns | user |
+    /// task | kind | socket | vendor | model | max_power_watts | source`.
+    ///
+    /// Records of untracked pids or unknown devices keep null metadata.
+    pub fn joined_trace(&self) -> PolarsResult<LazyFrame> {
+        join_metadata(
+            self.trace_or_empty().lazy(),
+            &self.tracked_processes,
+            &self.devices,
+        )
+    }
+
+    /// Rows within `range` that pass `filter`, from memory and from the
+    /// segments registered recorders have written, in the layout of
+    /// [`joined_trace`](Self::joined_trace). Rows present in both are
+    /// returned once, ordered by timestamp.
+    pub fn query(&self, range: TimeRange, filter: &Filter) -> PolarsResult<LazyFrame> {
+        let mut stored = Vec::new();
+        for recorder in &self.recorders {
+            stored.extend(
+                recorder
+                    .stored_trace()
+                    .map_err(|e| PolarsError::ComputeError(e.to_string().into()))?,
+            );
+        }
+        query_trace(
+            self.trace_or_empty(),
+            stored,
+            &self.tracked_processes,
+            &self.devices,
+            range,
+            filter,
+        )
+    }
+
+    fn trace_or_empty(&self) -> DataFrame {
+        if self.energy_trace().width() == 0 {
+            DataFrame::empty_with_schema(&energy_trace_schema(
+                self.record_output,
+                self.baseline.is_some(),
+            ))
+        } else {
+            self.energy_trace().clone()
+        }
+    }
+
+    /// Open a named region now. Regions may nest, overlap and repeat.
+    pub fn begin_region(&mut self, name: &str) {
+        self.regions.begin(name);
+    }
+
+    /// Close the most recently opened region named `name`.
+    pub fn end_region(&mut self, name: &str) -> Result<(), RegionError> {
+        self.regions.end(name)
+    }
+
+    /// All region markers in the order they were opened
+    pub fn regions(&self) -> &[Region] {
+        self.regions.regions()
+    }
+
+    /// Energy, duration and average power per region, optionally split by
+    /// device. Open regions are measured up to now. Only records still in the
+    /// rotating trace are counted, so regions older than the retention window
+    /// under-report.
+    pub fn energy_by_region(&self, per_device: bool) -> PolarsResult<Vec<RegionEnergy>> {
+        energy_by_region(
+            self.energy_trace(),
+            self.regions.regions(),
+            per_device,
+            RecordTime::now(),
+        )
+    }
+
+    /// Begin a named session now. Fails while another session is active.
+    pub fn begin_session(&mut self, name: &str) -> Result<(), SessionError> {
+        self.sessions.begin(name)
+    }
+
+    /// End the active session, returning its name.
+    pub fn end_session(&mut self) -> Result<String, SessionError> {
+        self.sessions.end()
+    }
+
+    /// All sessions in the order they were begun
+    pub fn sessions(&self) -> &[Region] {
+        self.sessions.sessions()
+    }
+
+    /// Energy, duration and average power of the sessions named `name`,
+    /// with their energy per device. The energy counts each domain of the
+    /// group's energy domains once. Like regions, only records still in the
+    /// rotating trace are counted.
+    pub fn session_summary(&self, name: &str) -> Result<SessionSummary, SessionError> {
+        session_summary(
+            self.energy_trace(),
+            self.sessions.sessions(),
+            name,
+            &self.energy_domains,
+            RecordTime::now(),
+        )
+    }
+
+    /// Energy trace with a `session` column and a marker row at the begin
+    /// and end of every session; see
+    /// [`session_trace`](crate::sessions::session_trace).
+    pub fn session_trace(&self) -> PolarsResult<DataFrame> {
+        session_trace(
+            &self.trace_or_empty(),
+            self.sessions.sessions(),
+            RecordTime::now(),
+        )
+    }
+
+    /// Record the current value of an application metric, such as batch
+    /// loss or queue depth. Metrics are kept for the trace retention window.
+    pub fn record_metric(&mut self, name: &str, value: f64) -> Result<(), MetricError> {
+        let frame = metric_frame(name, value, RecordTime::now())?;
+        self.metric_trace.append(&frame)?;
+        Ok(())
+    }
+
+    /// Recorded application metrics: `name | value | timestamp |
+    /// monotonic_ns`
+    pub fn metric_trace(&self) -> &DataFrame {
+        self.metric_trace.data()
+    }
+
+    /// Energy trace with a `metric_<name>` column per application metric,
+    /// holding the latest value recorded at or before each row.
+    pub fn energy_trace_with_metrics(&self) -> PolarsResult<DataFrame> {
+        join_metrics(self.energy_trace(), self.metric_trace())
+    }
+
+    /// Count `count` units of work, such as requests or tokens, done now,
+    /// by `pid` if given.
+    pub fn add_work(&mut self, unit: &str, count: f64, pid: Option<u32>) -> Result<(), WorkError> {
+        self.work.add(unit, count, pid)
+    }
+
+    /// All work reports in the order they were made
+    pub fn work(&self) -> &[WorkEvent] {
+        self.work.events()
+    }
+
+    /// Energy per unit of work of each region, from the work reported
+    /// while it was open, followed by each process work was reported for.
+    /// Regions share the retention caveat of
+    /// [`energy_by_region`](Self::energy_by_region).
+    pub fn efficiency(&self) -> PolarsResult<Vec<Efficiency>> {
+        let now = RecordTime::now();
+        let regions = energy_by_region(self.energy_trace(), self.regions.regions(), false, now)?;
+        let mut efficiency =
+            efficiency_by_region(&regions, self.regions.regions(), self.work.events(), now);
+        efficiency.extend(efficiency_by_process(
+            &self.consumed_energy,
+            self.work.events(),
+        ));
+        Ok(efficiency)
+    }
+
+    /// Energy of a process or region projected to `completion`, from its
+    /// power in the trace smoothed with `method`. Processes count their
+    /// energy since they were first seen; regions only count records still
+    /// in the trace. `None` until the trace holds enough of the scope to
+    /// derive its power.
+    pub fn forecast(
+        &self,
+        scope: &ForecastScope,
+        completion: Completion,
+        method: ForecastMethod,
+    ) -> Result<Option<EnergyForecast>, ForecastError> {
+        let total_joules = match scope {
+            ForecastScope::Process(pid) => self.consumed_energy.get(pid).copied(),
+            ForecastScope::Region(_) => None,
+        };
+        let forecaster = forecaster_from_trace(
+            self.energy_trace(),
+            scope,
+            self.regions.regions(),
+            method,
+            total_joules,
+            RecordTime::now(),
+        )?;
+        forecaster.forecast(completion)
+    }
+
+    /// Detect peak power episodes in records collected from now on.
+    pub fn set_peak_detection(&mut self, config: PeakDetection) {
+        self.peak_detector = Some(PeakDetector::new(config));
+    }
+
+    /// Spread device reads across each collection interval: devices matching
+    /// a phase are read that fraction of the interval into the tick.
+    pub fn set_read_phases(&mut self, phases: Vec<ReadPhase>) {
+        let interval = Duration::from_secs_f64(1.0 / self.rate);
+        self.energy_collector
+            .set_read_schedule(ReadSchedule::new(interval, phases));
+    }
+
+    /// Correct implausible readings in records collected from now on,
+    /// keeping the originals in the trace's quality columns. Call before
+    /// collecting, since all rows of the trace share one schema.
+    pub fn set_spike_filter(&mut self, config: SpikeFiltering) {
+        self.spike_filter = Some(SpikeFilter::new(config));
+        self.record_output.quality = true;
+    }
+
+    /// Peak power episodes detected so far; empty unless enabled with
+    /// [`EnergyGroup::set_peak_detection`].
+    pub fn peak_events(&self) -> Vec<PeakEvent> {
+        self.peak_detector
+            .as_ref()
+            .map(PeakDetector::events)
+            .unwrap_or_default()
+    }
+
+    /// Suspend/resume cycles detected so far. The trace rows at each gap's
+    /// end carry zero energy.
+    pub fn suspend_gaps(&self) -> Vec<SuspendGap> {
+        self.suspend_gaps.lock().unwrap().clone()
+    }
+
+    /// p50/p95/p99 and peak power per device or process, over records whose
+    /// wall-clock time (Unix ms) lies within `start..=end`.
+    pub fn power_stats(
+        &self,
+        grouping: PowerGrouping,
+        start: Option<i64>,
+        end: Option<i64>,
+    ) -> PolarsResult<Vec<PowerStats>> {
+        crate::power_stats::power_stats(self.energy_trace(), grouping, start, end)
+    }
+
+    /// Power over time per device or process, smoothed with `smoothing`,
+    /// over the same range as [`EnergyGroup::power_stats`].
+    pub fn power_series(
+        &self,
+        grouping: PowerGrouping,
+        start: Option<i64>,
+        end: Option<i64>,
+        smoothing: PowerSmoothing,
+    ) -> PolarsResult<BTreeMap<String, Vec<PowerPoint>>> {
+        crate::power_stats::smoothed_power_series(
+            self.energy_trace(),
+            grouping,
+            start,
+            end,
+            smoothing,
+        )
+    }
+
+    /// Mean power per pid and device in windows of `resample`; see
+    /// [`power_trace`](crate::power_stats::power_trace).
+    pub fn power_trace(&self, resample: std::time::Duration) -> PolarsResult<DataFrame> {
+        crate::power_stats::power_trace(self.energy_trace(), resample)
+    }
+
+    /// Power histograms with `bins` bins per device or process, over the same
+    /// range as [`EnergyGroup::power_stats`].
+    pub fn power_histograms(
+        &self,
+        grouping: PowerGrouping,
+        start: Option<i64>,
+        end: Option<i64>,
+        bins: usize,
+    ) -> PolarsResult<Vec<PowerHistogram>> {
+        crate::power_stats::power_histograms(self.energy_trace(), grouping, start, end, bins)
+    }
+
+    /// Energy of the trace in joules, each energy domain counted once as in
+    /// [`EnergyGroup::energy_totals`]; see
+    /// [`trace_summary`](crate::trace_summary) for what is counted.
+    pub fn total_energy(&self) -> PolarsResult<f64> {
+        trace_summary::total_energy(&self.trace_or_empty(), &self.energy_domains)
+    }
+
+    /// Energy of each device in the trace, in joules.
+    pub fn total_energy_by_device(&self) -> PolarsResult<BTreeMap<String, f64>> {
+        trace_summary::total_energy_by_device(&self.trace_or_empty())
+    }
+
+    /// Energy attributed to each pid in the trace, in joules.
+    pub fn total_energy_by_pid(&self) -> PolarsResult<BTreeMap<u32, f64>> {
+        trace_summary::total_energy_by_pid(&self.trace_or_empty(), &self.energy_domains)
+    }
+
+    /// Energy, duration, mean and peak power of the trace.
+    pub fn summary(&self) -> PolarsResult<TraceSummary> {
+        trace_summary::summary(&self.trace_or_empty(), &self.energy_domains)
+    }
+
     /// Get a mutable reference to the energy trace for advanced operations
     pub fn energy_trace_mut(&mut self) -> &mut RotatingTrace {
         &mut self.energy_trace
@@ -115,6 +965,12 @@ impl<T: EnergyCollector> EnergyGroup<T> {
     /// Set the retention window for all traces (in seconds)
     pub fn set_trace_retention(&mut self, retention_seconds: i64) {
         self.energy_trace.set_retention_seconds(retention_seconds);
+        self.metric_trace.set_retention_seconds(retention_seconds);
+    }
+
+    /// Set per-device retention overrides for the energy trace
+    pub fn set_device_retention(&mut self, device_retention: Vec<DeviceRetention>) {
+        self.energy_trace.set_device_retention(device_retention);
     }
 
     /// Get memory usage statistics for energy trace
@@ -122,26 +978,83 @@ impl<T: EnergyCollector> EnergyGroup<T> {
         TraceMemoryStats {
             energy_trace_rows: self.energy_trace.row_count(),
             energy_trace_stats: self.energy_trace.stats(),
+            metric_trace_stats: self.metric_trace.stats(),
         }
     }
 
+    /// Domains the collector is currently skipping; see
+    /// [`EnergyCollector::degraded_domains`].
+    pub fn degraded_domains(&self) -> Vec<DegradedDomain> {
+        self.energy_collector.degraded_domains()
+    }
+
     /// Get the per-PID cumulative energy accumulator
     pub fn consumed_energy_by_pid(&self) -> &HashMap<u32, f64> {
         &self.consumed_energy
     }
 
-    /// Get total consumed energy across all tracked PIDs
+    /// Get total consumed energy, counting each energy domain once
     pub fn total_consumed_energy(&self) -> f64 {
-        self.consumed_energy.values().sum()
+        self.energy_totals().total_joules
+    }
+
+    /// Overlap-aware totals of the energy collected so far: the platform
+    /// figure, energy per domain and a non-overlapping breakdown.
+    pub fn energy_totals(&self) -> EnergyTotals {
+        EnergyTotals::from_devices(&self.energy_domains, &self.device_energy)
+    }
+
+    /// Convert energy to emissions at `g_per_kwh` grams CO2e per kWh from
+    /// now on.
+    pub fn set_carbon_intensity(&mut self, g_per_kwh: f64) {
+        self.carbon.intensity.set(g_per_kwh);
+    }
+
+    /// Ask `provider` for the grid intensity when collection starts and
+    /// every `refresh_interval` while it runs.
+    pub fn set_carbon_provider(
+        &mut self,
+        provider: Arc<dyn CarbonIntensityProvider>,
+        refresh_interval: Duration,
+    ) {
+        self.carbon_provider = Some((provider, refresh_interval));
+    }
+
+    /// Emissions of the energy collected since an intensity was known, each
+    /// energy domain counted once; `None` without an intensity.
+    pub fn carbon_footprint(&self) -> Option<CarbonFootprint> {
+        self.carbon.footprint(&self.energy_domains)
+    }
+
+    /// Replace the containment rules used by [`EnergyGroup::energy_totals`].
+    pub fn set_energy_domains(&mut self, rules: Vec<EnergyDomainRule>) {
+        self.energy_domains = rules;
     }
 
     /// Add energy records to the energy trace
-    fn append_energy_records(&mut self, records: &[EnergyRecord]) -> Result<(), MonitoringError> {
+    fn append_energy_records(
+        &mut self,
+        records: &[EnergyRecord],
+        screening: &[RecordScreening],
+    ) -> Result<(), MonitoringError> {
         if records.is_empty() {
             return Ok(());
         }
 
-        let data = DataFrame::new(vec![
+        let data = self.energy_records_frame(records, screening)?;
+        self.energy_trace.append(&data)?;
+
+        Ok(())
+    }
+
+    /// `records` in the layout of the energy trace, registering new devices.
+    /// Records without an entry in `screening` were taken as read.
+    fn energy_records_frame(
+        &mut self,
+        records: &[EnergyRecord],
+        screening: &[RecordScreening],
+    ) -> Result<DataFrame, MonitoringError> {
+        let mut data = DataFrame::new(vec![
             Column::new(
                 "pid".into(),
                 records.iter().map(|r| r.pid).collect::<Vec<_>>(),
@@ -150,43 +1063,179 @@ impl<T: EnergyCollector> EnergyGroup<T> {
                 "device".into(),
                 records.iter().map(|r| r.device.clone()).collect::<Vec<_>>(),
             ),
+            Column::new(
+                DEVICE_ID_COLUMN.into(),
+                records
+                    .iter()
+                    .map(|r| self.devices.id_or_register(&r.device))
+                    .collect::<Vec<_>>(),
+            ),
             Column::new(
                 "energy".into(),
                 records.iter().map(|r| r.energy).collect::<Vec<_>>(),
             ),
             Column::new(
-                "timestamp".into(),
-                records.iter().map(|r| r.timestamp).collect::<Vec<_>>(),
-            ),
+                TIMESTAMP_COLUMN.into(),
+                records.iter().map(|r| r.timestamp_us).collect::<Vec<_>>(),
+            )
+            .cast(&timestamp_dtype())
+            .map_err(|err| MonitoringError::Other(err.to_string()))?,
+            Column::new(
+                MONOTONIC_COLUMN.into(),
+                records.iter().map(|r| r.monotonic_ns).collect::<Vec<_>>(),
+            )
+            .cast(&monotonic_dtype())
+            .map_err(|err| MonitoringError::Other(err.to_string()))?,
         ])
         .map_err(|err| MonitoringError::Other(err.to_string()))?;
+        if self.record_output.cumulative_energy {
+            let cumulative: Vec<f64> = records
+                .iter()
+                .map(|r| {
+                    let total = self
+                        .cumulative_energy
+                        .entry((r.pid, r.device.clone()))
+                        .or_insert(0.0);
+                    *total += r.energy;
+                    *total
+                })
+                .collect();
+            data.with_column(Column::new(CUMULATIVE_ENERGY_COLUMN.into(), cumulative))
+                .map_err(|err| MonitoringError::Other(err.to_string()))?;
+        }
+        if self.record_output.raw_counter {
+            let raw: Vec<Option<f64>> = records.iter().map(|r| r.raw_counter).collect();
+            data.with_column(Column::new(RAW_COUNTER_COLUMN.into(), raw))
+                .map_err(|err| MonitoringError::Other(err.to_string()))?;
+        }
+        if self.record_output.quality {
+            let screening = |index| screening.get(index).copied().unwrap_or_default();
+            let quality: Vec<&str> = (0..records.len())
+                .map(|index| screening(index).quality.as_str())
+                .collect();
+            let original: Vec<Option<f64>> = (0..records.len())
+                .map(|index| screening(index).original_energy)
+                .collect();
+            data.with_column(Column::new(QUALITY_COLUMN.into(), quality))
+                .and_then(|data| {
+                    data.with_column(Column::new(ORIGINAL_ENERGY_COLUMN.into(), original))
+                })
+                .map_err(|err| MonitoringError::Other(err.to_string()))?;
+        }
+        if self.record_output.co2 {
+            let co2: Vec<Option<f64>> = records
+                .iter()
+                .map(|r| self.carbon.grams(r.energy))
+                .collect();
+            data.with_column(Column::new(CO2_COLUMN.into(), co2))
+                .map_err(|err| MonitoringError::Other(err.to_string()))?;
+        }
+        if self.record_output.sample_duration {
+            let durations: Vec<Option<f64>> = records
+                .iter()
+                .map(|r| {
+                    // Records of one read share its time
+                    let read = self
+                        .sample_times
+                        .entry(r.device.clone())
+                        .or_insert((r.monotonic_ns, None));
+                    if r.monotonic_ns > read.0 {
+                        *read = (r.monotonic_ns, Some((r.monotonic_ns - read.0) as f64 / 1e6));
+                    }
+                    read.1
+                })
+                .collect();
+            data.with_column(Column::new(SAMPLE_DURATION_COLUMN.into(), durations))
+                .map_err(|err| MonitoringError::Other(err.to_string()))?;
+        }
+        if let Some(baseline) = &mut self.baseline {
+            data.with_column(Column::new(
+                MARGINAL_ENERGY_COLUMN.into(),
+                baseline.marginal(records),
+            ))
+            .map_err(|err| MonitoringError::Other(err.to_string()))?;
+        }
 
-        self.energy_trace.append(&data)?;
+        Ok(data)
+    }
 
-        Ok(())
+    /// Screen records for spikes, append them to the trace and feed totals
+    /// and peak detection.
+    fn apply_records(&mut self, records: &[EnergyRecord]) {
+        if records.is_empty() {
+            return;
+        }
+        let mut records = records.to_vec();
+        let screening = self.screen_spikes(&mut records);
+        if let Err(e) = self.append_energy_records(&records, &screening) {
+            log::error!("Failed to append energy records to trace: {}", e);
+        }
+        self.accumulate_energy(&records);
+        if let Some(detector) = &mut self.peak_detector {
+            detector.observe(&records, &self.devices);
+        }
+    }
+
+    /// Correct implausible readings in place; empty while spike filtering
+    /// is disabled.
+    fn screen_spikes(&mut self, records: &mut [EnergyRecord]) -> Vec<RecordScreening> {
+        match &mut self.spike_filter {
+            Some(filter) => filter.screen(records, &self.devices),
+            None => Vec::new(),
+        }
     }
 
     /// Accumulate energy records into the per-PID HashMap
     fn accumulate_energy(&mut self, records: &[EnergyRecord]) {
         for record in records {
             *self.consumed_energy.entry(record.pid).or_insert(0.0) += record.energy;
+            *self.device_energy.entry(record.device.clone()).or_default() += record.energy;
+            self.carbon.add(&record.device, record.energy);
         }
     }
 
     fn flush_recorders(&mut self) {
+        let peak_events = self.peak_events();
+        let suspend_gaps = self.suspend_gaps();
         for recorder in &mut self.recorders {
+            recorder.record_devices(&self.devices);
+            recorder.record_regions(self.regions.regions());
+            recorder.record_work(self.work.events());
+            recorder.record_peak_events(&peak_events);
+            recorder.record_suspend_gaps(&suspend_gaps);
             recorder.flush(&self.energy_trace);
         }
         self.last_recorder_flush = Instant::now();
+        if self.recorders.iter().any(|recorder| recorder.persists()) {
+            self.discard_wal_before(i64::MAX);
+        } else if !self.recorders.is_empty() {
+            self.discard_wal_beyond_retention();
+        }
     }
 
     fn flush_recorders_if_due(&mut self) {
-        if self.recorders.is_empty() {
+        if self.last_recorder_flush.elapsed() < self.recorder_flush_interval {
             return;
         }
-
-        if self.last_recorder_flush.elapsed() >= self.recorder_flush_interval {
+        if !self.recorders.is_empty() {
             self.flush_recorders();
+        } else if self.wal.is_some() {
+            self.discard_wal_beyond_retention();
+            self.last_recorder_flush = Instant::now();
+        }
+    }
+
+    /// Nothing persists the trace, so the log keeps what memory keeps.
+    fn discard_wal_beyond_retention(&mut self) {
+        let retention_us = self.energy_trace.longest_retention_seconds() * 1_000_000;
+        self.discard_wal_before(now_micros() - retention_us);
+    }
+
+    fn discard_wal_before(&mut self, cutoff_us: i64) {
+        if let Some(wal) = &mut self.wal
+            && let Err(e) = wal.discard_before(cutoff_us)
+        {
+            log::error!("Failed to cut back {}: {}", wal.path().display(), e);
         }
     }
 
@@ -205,26 +1254,94 @@ impl<T: EnergyCollector> EnergyGroup<T> {
         self.batch_size
     }
 
+    /// Auto-drain task: moves batches from the channel into `drained` as
+    /// they arrive, until the channel closes or the group is dropped.
+    async fn run_drain_loop(rx: Arc<BatchReceiver>, drained: Weak<DrainedRecords>) {
+        let mut rx = rx.lock().await;
+        while let Some(batch) = rx.recv().await {
+            let Some(drained) = drained.upgrade() else {
+                return;
+            };
+            drained.receive(batch);
+            drained.updated.notify_waiters();
+        }
+        if let Some(drained) = drained.upgrade() {
+            drained.closed.store(true, Ordering::SeqCst);
+            drained.updated.notify_waiters();
+        }
+    }
+
     /// Background monitoring task that collects data at a specified rate and sends batches
+    #[allow(clippy::too_many_arguments)]
     async fn run_monitoring_loop<C: EnergyCollector>(
         collector: Arc<C>,
-        tx: mpsc::Sender<Vec<EnergyRecord>>,
+        tx: BatchSender,
         is_monitoring_active: Arc<AtomicBool>,
+        stop: Arc<Notify>,
         rate: f64,
+        missed_ticks: MissedTickPolicy,
         batch_size: usize,
+        suspend_gaps: Arc<Mutex<Vec<SuspendGap>>>,
+        rediscovery: Rediscovery,
+        process_watcher: Arc<Mutex<ProcessWatcher>>,
     ) {
-        let interval = tokio::time::Duration::from_secs_f64(1.0 / rate);
+        // Ticks keep a fixed cadence however long reads take, so the rate
+        // does not drift with collection latency.
+        let mut ticker = tokio::time::interval(Duration::from_secs_f64(1.0 / rate));
+        ticker.set_missed_tick_behavior(missed_ticks.into());
+        // The first tick is immediate
+        ticker.tick().await;
         let mut iteration = 0;
         let mut collected_energy_records = Vec::new();
+        let mut suspend_detector = SuspendDetector::new();
+        let mut last_rediscover = Instant::now();
+        // Set when a read reports a lost device, to rediscover on the next
+        // tick rather than wait out the interval
+        let mut device_lost = false;
 
         while is_monitoring_active.load(Ordering::SeqCst) {
             iteration += 1;
             log::trace!("Background monitoring iteration {}", iteration);
 
-            match collector.get_energy_trace().await {
-                Ok(energy_records) => {
+            if device_lost || last_rediscover.elapsed() >= rediscovery.interval {
+                device_lost = false;
+                last_rediscover = Instant::now();
+                if collector.rediscover() {
+                    rediscovery.devices_changed.store(true, Ordering::SeqCst);
+                }
+            }
+
+            {
+                // Set under the lock so a concurrent update is not undone.
+                let mut watcher = process_watcher.lock().unwrap();
+                if let Some(pids) = watcher.poll() {
+                    log::debug!("Tracked processes changed to {:?}", pids);
+                    collector.set_tracked_pids(pids);
+                }
+            }
+
+            let result = collector.get_energy_trace().await;
+            let suspend_gap = suspend_detector.observe();
+            if let Some(gap) = &suspend_gap {
+                log::warn!(
+                    "Resumed after {:.1} s of suspend; discarding energy read across the gap",
+                    gap.suspended_seconds
+                );
+                suspend_gaps.lock().unwrap().push(gap.clone());
+            }
+
+            match result {
+                Ok(mut energy_records) => {
                     log::debug!("Collected {} energy records", energy_records.len(),);
 
+                    // Counters read across a suspend are meaningless; keep
+                    // the rows as the new baseline and gap marker.
+                    if suspend_gap.is_some() {
+                        for record in &mut energy_records {
+                            record.energy = 0.0;
+                        }
+                    }
+
                     // Add to batch
                     collected_energy_records.extend(energy_records);
 
@@ -235,35 +1352,22 @@ impl<T: EnergyCollector> EnergyGroup<T> {
                             collected_energy_records.len(),
                         );
 
-                        // Use send().await for bounded channel (provides backpressure)
-                        // This will wait if the channel is full, slowing down collection
-                        let send_start = std::time::Instant::now();
-                        match tx.send(collected_energy_records.clone()).await {
-                            Ok(_) => {
-                                let send_duration = send_start.elapsed();
-                                if send_duration.as_millis() > 100 {
-                                    log::warn!(
-                                        "Channel send blocked for {:?} - receiver may be slow!",
-                                        send_duration
-                                    );
-                                }
-                            }
-                            Err(_) => {
-                                log::error!("Failed to send data - receiver dropped");
-                                break;
-                            }
+                        if !tx.send(std::mem::take(&mut collected_energy_records)).await {
+                            log::error!("Failed to send data - receiver dropped");
+                            break;
                         }
-
-                        // Clear the batch
-                        collected_energy_records.clear();
                     }
                 }
                 Err(e) => {
                     log::error!("Error collecting data: {}", e);
+                    device_lost = matches!(e, CollectorError::DeviceLost(_));
                 }
             }
 
-            tokio::time::sleep(interval).await;
+            tokio::select! {
+                _ = ticker.tick() => {}
+                _ = stop.notified() => {}
+            }
         }
 
         // Send any remaining records in the batch before stopping
@@ -272,7 +1376,7 @@ impl<T: EnergyCollector> EnergyGroup<T> {
                 "Sending final batch of {} energy records",
                 collected_energy_records.len(),
             );
-            let _ = tx.send(collected_energy_records).await;
+            tx.send(collected_energy_records).await;
         }
 
         log::debug!(
@@ -281,6 +1385,63 @@ impl<T: EnergyCollector> EnergyGroup<T> {
         );
     }
 
+    /// Measure each device's idle power for `duration` before collection
+    /// starts, and from then on add [`MARGINAL_ENERGY_COLUMN`] with the
+    /// energy above it to the trace. Run it while the system idles and
+    /// before [`commence`](Self::commence). Returns the baseline of each
+    /// device, in watts.
+    pub async fn calibrate_baseline(
+        &mut self,
+        duration: Duration,
+    ) -> Result<BTreeMap<String, f64>, MonitoringError> {
+        if self.is_running() {
+            return Err(MonitoringError::Other(
+                "the baseline must be calibrated before collection commences".to_string(),
+            ));
+        }
+        // Collectors only report devices while they attribute to a process
+        let tracked_pids = self.tracked_pids();
+        let collector = &self.energy_collector;
+        if tracked_pids.is_empty() {
+            collector.set_tracked_pids(vec![std::process::id()]);
+        }
+
+        let interval = Duration::from_secs_f64(1.0 / self.rate);
+        let mut energy = BTreeMap::new();
+        // The first read sets the counters' starting point
+        let mut result = collector.get_energy_trace().await.map(drop);
+        let start = Instant::now();
+        while result.is_ok() && start.elapsed() < duration {
+            tokio::time::sleep(interval.min(duration.saturating_sub(start.elapsed()))).await;
+            result = collector.get_energy_trace().await.map(|records| {
+                for record in records {
+                    *energy.entry(record.device).or_insert(0.0) += record.energy;
+                }
+            });
+        }
+        if tracked_pids.is_empty() {
+            collector.set_tracked_pids(Vec::new());
+        }
+        result?;
+        if energy.is_empty() {
+            return Err(MonitoringError::Other(
+                "no device reported energy during calibration".to_string(),
+            ));
+        }
+
+        let baseline = IdleBaseline::from_energy(energy, start.elapsed().as_secs_f64(), self.rate);
+        let watts = baseline.watts().clone();
+        log::info!("Calibrated idle baseline: {watts:?}");
+        self.baseline = Some(baseline);
+        Ok(watts)
+    }
+
+    /// Idle power of each device in watts, once
+    /// [`calibrate_baseline`](Self::calibrate_baseline) has run.
+    pub fn baseline_watts(&self) -> Option<&BTreeMap<String, f64>> {
+        self.baseline.as_ref().map(IdleBaseline::watts)
+    }
+
     pub async fn commence(&mut self) -> Result<(), MonitoringError> {
         // Check if collector is already running
         if self.is_running() {
@@ -297,32 +1458,67 @@ impl<T: EnergyCollector> EnergyGroup<T> {
         // Set running state before starting
         self.is_running.store(true, Ordering::SeqCst);
 
-        // Collect initial energy data
-        let energy_records = self
-            .energy_collector
-            .get_energy_trace()
-            .await
-            .map_err(|e| MonitoringError::Other(format!("Failed to get energy trace: {}", e)))?;
-
-        // Append and accumulate initial data
-        self.append_energy_records(&energy_records)?;
-        self.accumulate_energy(&energy_records);
+        // Know the grid intensity before the first read is converted
+        if let Some((provider, interval)) = &self.carbon_provider {
+            self.carbon.intensity.refresh(provider.as_ref()).await;
+            self.carbon_refresh = Some(tokio::spawn(carbon::run_refresh_loop(
+                Arc::clone(provider),
+                self.carbon.intensity.clone(),
+                *interval,
+                Arc::clone(&self.is_running),
+            )));
+        }
 
-        // Create bounded channel for background task to send data back
-        // Channel capacity: allow a reasonable buffer (e.g., 10 batches)
-        // This provides backpressure if receiver is slow
-        let channel_capacity = 10;
-        let (tx, rx) = mpsc::channel(channel_capacity);
+        // The initial read is handled like any batch from the background
+        // task, so it goes through the WAL, spike filter and peak detection
+        // on the next poll
+        let energy_records = self.energy_collector.get_energy_trace().await?;
+        self.drained.receive(energy_records);
+
+        // Create bounded channel for background task to send data back;
+        // the backpressure policy decides what happens when it fills up
+        let (tx, rx) = mpsc::channel(self.channel_capacity);
+        let rx = Arc::new(AsyncMutex::new(rx));
+        self.drained.closed.store(false, Ordering::SeqCst);
+        if self.auto_drain {
+            tokio::spawn(Self::run_drain_loop(
+                Arc::clone(&rx),
+                Arc::downgrade(&self.drained),
+            ));
+        }
+        let tx = BatchSender {
+            tx,
+            rx: Arc::downgrade(&rx),
+            policy: self.backpressure,
+            dropped_batches: Arc::clone(&self.dropped_batches),
+        };
         self.data_receiver = Some(rx);
 
         // Spawn background task for continuous monitoring
         let rate = self.rate;
+        let missed_ticks = self.missed_ticks;
         let batch_size = self.batch_size;
         let is_running = Arc::clone(&self.is_running);
+        // A fresh signal, so a stop left over from a previous run is ignored
+        self.stop = Arc::new(Notify::new());
+        let stop = Arc::clone(&self.stop);
         let collector = Arc::clone(&self.energy_collector);
 
+        let suspend_gaps = Arc::clone(&self.suspend_gaps);
+        let rediscovery = self.rediscovery.clone();
+        let process_watcher = Arc::clone(&self.process_watcher);
+
         let handle = tokio::spawn(Self::run_monitoring_loop(
-            collector, tx, is_running, rate, batch_size,
+            collector,
+            tx,
+            is_running,
+            stop,
+            rate,
+            missed_ticks,
+            batch_size,
+            suspend_gaps,
+            rediscovery,
+            process_watcher,
         ));
 
         // Store the task handle
@@ -335,33 +1531,126 @@ impl<T: EnergyCollector> EnergyGroup<T> {
     /// Poll the channel, append received data to the energy trace, and accumulate per-PID energy.
     /// Returns all energy records drained from the channel.
     pub fn poll_data(&mut self) -> Vec<EnergyRecord> {
-        // Collect all available messages first
-        let mut all_energy_records = Vec::new();
+        let all_energy_records = self.take_pending();
 
-        if let Some(rx) = &mut self.data_receiver {
-            while let Ok(energy_records) = rx.try_recv() {
-                all_energy_records.extend(energy_records);
+        self.ingest(&all_energy_records);
+        self.flush_recorders_if_due();
+
+        all_energy_records
+    }
+
+    /// Add records computed outside the collector, such as derived devices,
+    /// as if the collector had reported them.
+    pub fn ingest(&mut self, records: &[EnergyRecord]) {
+        if let Some(wal) = &mut self.wal
+            && let Err(e) = wal.append(records)
+        {
+            log::error!("Failed to write batch to {}: {}", wal.path().display(), e);
+        }
+        self.apply_records(records);
+    }
+
+    /// Wait for the next batch and return it as frames instead of adding it
+    /// to the trace: the records in the trace layout, and the device table
+    /// their `device_id`s refer to. The trace, energy totals, peak detection
+    /// and recorders are left untouched, for callers that keep their own
+    /// storage. Returns `None` once the collector has stopped and every batch
+    /// was returned.
+    pub async fn next_batch(&mut self) -> Option<(DataFrame, DataFrame)> {
+        loop {
+            self.wait_for_data().await;
+            let mut records = self.take_pending();
+            if records.is_empty() {
+                if !self.is_running() || self.drained.closed.load(Ordering::SeqCst) {
+                    return None;
+                }
+                continue;
+            }
+            let screening = self.screen_spikes(&mut records);
+            let frames = self
+                .energy_records_frame(&records, &screening)
+                .and_then(|batch| {
+                    let devices = self
+                        .devices
+                        .to_dataframe()
+                        .map_err(|err| MonitoringError::Other(err.to_string()))?;
+                    Ok((batch, devices))
+                });
+            match frames {
+                Ok(frames) => return Some(frames),
+                Err(e) => log::error!("Failed to build energy batch frame: {}", e),
             }
         }
+    }
 
-        // Append to trace and accumulate
-        if !all_energy_records.is_empty() {
-            if let Err(e) = self.append_energy_records(&all_energy_records) {
-                log::error!("Failed to append energy records to trace: {}", e);
+    /// Records received since the last call, registering hot-plugged devices.
+    fn take_pending(&mut self) -> Vec<EnergyRecord> {
+        // Register hot-plugged devices; vanished ones keep their ids so
+        // older rows stay resolvable.
+        if self
+            .rediscovery
+            .devices_changed
+            .swap(false, Ordering::SeqCst)
+        {
+            for info in self.energy_collector.devices() {
+                self.devices.register(info);
             }
-            self.accumulate_energy(&all_energy_records);
-            self.flush_recorders_if_due();
         }
 
-        all_energy_records
+        // Collect all available messages first; the auto-drain task, when
+        // running, holds the receiver and has moved them aside already
+        if let Some(rx) = &self.data_receiver
+            && let Ok(mut rx) = rx.try_lock()
+        {
+            while let Ok(energy_records) = rx.try_recv() {
+                self.drained.receive(energy_records);
+            }
+        }
+        std::mem::take(&mut *self.drained.records.lock().unwrap())
     }
 
-    pub fn shutdown(&mut self) -> Result<(), MonitoringError> {
-        self.shutdown_and_drain().map(|_| ())
+    /// Like [`poll_data`](Self::poll_data), but first waits for the next
+    /// batch when none is pending. Returns right away when the collector is
+    /// not running.
+    pub async fn poll_data_async(&mut self) -> Vec<EnergyRecord> {
+        self.wait_for_data().await;
+        self.poll_data()
+    }
+
+    /// Wait until a batch is pending or the collector has stopped.
+    async fn wait_for_data(&self) {
+        if !self.auto_drain {
+            if let Some(rx) = &self.data_receiver {
+                match rx.lock().await.recv().await {
+                    Some(batch) => self.drained.receive(batch),
+                    None => self.drained.closed.store(true, Ordering::SeqCst),
+                }
+            }
+            return;
+        }
+        loop {
+            let updated = self.drained.updated.notified();
+            if !self.is_running()
+                || self.drained.closed.load(Ordering::SeqCst)
+                || !self.drained.records.lock().unwrap().is_empty()
+            {
+                return;
+            }
+            updated.await;
+        }
+    }
+
+    pub async fn shutdown(&mut self) -> Result<(), MonitoringError> {
+        self.shutdown_and_drain().await.map(|_| ())
     }
 
     /// Shut down the collector and return all final records drained from the channel.
-    pub fn shutdown_and_drain(&mut self) -> Result<Vec<EnergyRecord>, MonitoringError> {
+    ///
+    /// The background task finishes the read in progress and delivers its
+    /// final, possibly partial, batch before it stops, so no collected
+    /// records are lost. It is aborted only if it does not stop within
+    /// [`SHUTDOWN_TIMEOUT`].
+    pub async fn shutdown_and_drain(&mut self) -> Result<Vec<EnergyRecord>, MonitoringError> {
         log::info!("Shutdown requested");
 
         // if not running, nothing to do
@@ -370,12 +1659,43 @@ impl<T: EnergyCollector> EnergyGroup<T> {
             return Ok(Vec::new());
         }
 
-        // Signal the background task to stop
+        // Signal the background task to stop, waking it if it sleeps
         self.is_running.store(false, Ordering::SeqCst);
+        self.stop.notify_one();
+        if let Some(refresh) = self.carbon_refresh.take() {
+            refresh.abort();
+        }
 
-        // Give the background task time to send its final batch
-        // This is necessary because the task may be in the middle of collecting data
-        std::thread::sleep(std::time::Duration::from_millis(200));
+        if let Some(mut handle) = self.task_handle.take() {
+            let deadline = tokio::time::sleep(SHUTDOWN_TIMEOUT);
+            tokio::pin!(deadline);
+            loop {
+                tokio::select! {
+                    biased;
+                    _ = &mut handle => break,
+                    _ = &mut deadline => {
+                        log::warn!(
+                            "Collector did not stop within {:?}; aborting it",
+                            SHUTDOWN_TIMEOUT
+                        );
+                        handle.abort();
+                        break;
+                    }
+                    // Keep the channel moving so a final send blocked on a
+                    // full channel completes.
+                    _ = self.receive_batch() => {}
+                }
+            }
+        }
+        // The task is gone with its sender, so the channel closes once the
+        // auto-drain task has moved the final batch aside.
+        if self.auto_drain
+            && tokio::time::timeout(SHUTDOWN_TIMEOUT, self.wait_for_close())
+                .await
+                .is_err()
+        {
+            log::warn!("Channel did not close within {:?}", SHUTDOWN_TIMEOUT);
+        }
 
         // Poll any remaining data from the channel
         let final_records = self.poll_data();
@@ -383,15 +1703,94 @@ impl<T: EnergyCollector> EnergyGroup<T> {
         // Final flush to all registered recorders
         self.flush_recorders();
 
-        // Now abort the background task (it should already be stopped)
-        if let Some(handle) = self.task_handle.take() {
-            handle.abort();
-        }
-
         // Drop the receiver to signal completion
         self.data_receiver = None;
         Ok(final_records)
     }
+
+    /// Move the next batch aside for the next poll. Never completes once
+    /// the channel has closed, or when the auto-drain task receives batches.
+    async fn receive_batch(&self) {
+        if !self.auto_drain
+            && let Some(rx) = &self.data_receiver
+        {
+            match rx.lock().await.recv().await {
+                Some(batch) => {
+                    self.drained.receive(batch);
+                    return;
+                }
+                None => self.drained.closed.store(true, Ordering::SeqCst),
+            }
+        }
+        std::future::pending().await
+    }
+
+    /// Wait until the auto-drain task has seen the channel close.
+    async fn wait_for_close(&self) {
+        loop {
+            let updated = self.drained.updated.notified();
+            if self.drained.closed.load(Ordering::SeqCst) {
+                return;
+            }
+            updated.await;
+        }
+    }
+}
+
+/// Columns of the energy trace, for frames that have not seen any records.
+fn energy_trace_schema(output: RecordOutput, marginal: bool) -> Schema {
+    let mut schema = Schema::from_iter([
+        Field::new("pid".into(), DataType::UInt32),
+        Field::new("device".into(), DataType::String),
+        Field::new(DEVICE_ID_COLUMN.into(), DataType::UInt32),
+        Field::new("energy".into(), DataType::Float64),
+        Field::new(TIMESTAMP_COLUMN.into(), timestamp_dtype()),
+        Field::new(MONOTONIC_COLUMN.into(), monotonic_dtype()),
+    ]);
+    if output.cumulative_energy {
+        schema.with_column(CUMULATIVE_ENERGY_COLUMN.into(), DataType::Float64);
+    }
+    if output.raw_counter {
+        schema.with_column(RAW_COUNTER_COLUMN.into(), DataType::Float64);
+    }
+    if output.quality {
+        schema.with_column(QUALITY_COLUMN.into(), DataType::String);
+        schema.with_column(ORIGINAL_ENERGY_COLUMN.into(), DataType::Float64);
+    }
+    if output.co2 {
+        schema.with_column(CO2_COLUMN.into(), DataType::Float64);
+    }
+    if output.sample_duration {
+        schema.with_column(SAMPLE_DURATION_COLUMN.into(), DataType::Float64);
+    }
+    if marginal {
+        schema.with_column(MARGINAL_ENERGY_COLUMN.into(), DataType::Float64);
+    }
+    schema
+}
+
+/// One `pid | user | task | container | pod` row per pid of `groups`.
+fn tracked_processes_frame(groups: &[ProcessGroup]) -> DataFrame {
+    let rows = groups
+        .iter()
+        .flat_map(|group| group.pids.iter().map(move |&pid| (pid as u32, group)));
+    let (mut pids, mut users, mut tasks) = (Vec::new(), Vec::new(), Vec::new());
+    let (mut containers, mut pods) = (Vec::new(), Vec::new());
+    for (pid, group) in rows {
+        pids.push(pid);
+        users.push(group.user.as_str());
+        tasks.push(group.task.as_str());
+        containers.push(group.container.as_deref());
+        pods.push(group.pod.as_deref());
+    }
+    DataFrame::new(vec![
+        Column::new("pid".into(), pids),
+        Column::new("user".into(), users),
+        Column::new("task".into(), tasks),
+        Column::new("container".into(), containers),
+        Column::new("pod".into(), pods),
+    ])
+    .expect("columns have equal length")
 }
 
 #[async_trait]
@@ -399,11 +1798,46 @@ pub trait EnergyCollector: Send + Sync + 'static {
     /// Set the list of tracked process PIDs for energy attribution
     fn set_tracked_pids(&self, pids: Vec<u32>);
 
+    /// Attribute whole cgroups instead of the tracked pids, with each
+    /// cgroup's records carrying its pid. Collectors that attribute by
+    /// process keep the default and go on using the tracked pids.
+    fn set_tracked_cgroups(&self, _cgroups: Vec<TrackedCgroup>) {}
+
+    /// Spread device reads across each tick. Collectors that read all
+    /// devices at once keep the default.
+    fn set_read_schedule(&self, _schedule: ReadSchedule) {}
+
+    /// Adopt the group's missed tick policy. Only collectors that read on a
+    /// cadence of their own, such as a shared sampler, use it.
+    fn set_missed_tick_policy(&self, _policy: MissedTickPolicy) {}
+
     /// Get energy trace data
-    async fn get_energy_trace(&self) -> Result<Vec<EnergyRecord>, String>;
+    async fn get_energy_trace(&self) -> Result<Vec<EnergyRecord>, CollectorError>;
+
+    /// Devices this collector reports on, with whatever metadata the hardware
+    /// exposes. Devices missing here are registered on first use.
+    fn devices(&self) -> Vec<DeviceInfo> {
+        Vec::new()
+    }
+
+    /// Domains currently skipped because discovery or the latest read
+    /// failed. Collectors without per-domain failures keep the default.
+    fn degraded_domains(&self) -> Vec<DegradedDomain> {
+        Vec::new()
+    }
+
+    /// Re-run device discovery after CPUs or GPUs were added or removed,
+    /// returning whether the set of devices changed. Collectors with a fixed
+    /// device set keep the default.
+    fn rediscover(&self) -> bool {
+        false
+    }
 
     /// Check if this collector type is available on the system
-    fn is_available() -> bool {
+    fn is_available() -> bool
+    where
+        Self: Sized,
+    {
         unimplemented!()
     }
 }
@@ -415,6 +1849,81 @@ pub struct TraceMemoryStats {
     pub energy_trace_rows: usize,
     /// Energy trace statistics
     pub energy_trace_stats: crate::utils::trace_rotation::TraceStats,
+    /// Application metric trace statistics
+    pub metric_trace_stats: crate::utils::trace_rotation::TraceStats,
+}
+
+impl TraceMemoryStats {
+    /// Estimated heap size of both traces in bytes
+    pub fn estimated_bytes(&self) -> usize {
+        self.energy_trace_stats.estimated_bytes + self.metric_trace_stats.estimated_bytes
+    }
+
+    /// Sum of each trace's high watermark in bytes; an upper bound, since
+    /// the two need not have peaked at the same time
+    pub fn peak_bytes(&self) -> usize {
+        self.energy_trace_stats.peak_bytes + self.metric_trace_stats.peak_bytes
+    }
+}
+
+/// Recorder persisting a collector group's trace under
+/// `collection.trace_dir/<group>` in `collection.trace_format`, compressed
+/// at `collection.trace_compression_level` and sealed when an encryption
+/// key is configured. `None` without a trace directory or
+/// when the key cannot be loaded, so traces are never written unencrypted
+/// by mistake.
+fn trace_recorder(config: &EmtConfig, group: &str) -> Option<Box<dyn TraceRecorder>> {
+    let dir = Path::new(config.collection.trace_dir.as_deref()?).join(group);
+    let cipher = match TraceCipher::from_config(&config.encryption) {
+        Ok(cipher) => cipher,
+        Err(e) => {
+            log::error!(
+                "Not persisting the {} trace to {}: {}",
+                group,
+                dir.display(),
+                e
+            );
+            return None;
+        }
+    };
+    Some(match config.collection.trace_format {
+        TraceFormat::Csv => {
+            let mut recorder =
+                CsvTraceRecorder::new(dir, None, None).with_timezone(config.export.timezone());
+            if let Some(level) = config.collection.trace_compression_level {
+                recorder = recorder.with_zstd_compression(level);
+            }
+            match cipher {
+                Some(cipher) => Box::new(recorder.with_encryption(cipher)),
+                None => Box::new(recorder),
+            }
+        }
+        TraceFormat::Parquet => {
+            let recorder = ParquetTraceRecorder::new(dir).with_options(config.collection.parquet);
+            match cipher {
+                Some(cipher) => Box::new(recorder.with_encryption(cipher)),
+                None => Box::new(recorder),
+            }
+        }
+    })
+}
+
+/// Log `group`'s batches to `collection.wal_dir/<name>.wal`, replaying what
+/// a previous run left there.
+fn open_write_ahead_log<T: EnergyCollector>(
+    group: &mut EnergyGroup<T>,
+    config: &EmtConfig,
+    name: &str,
+) {
+    let Some(dir) = config.collection.wal_dir.as_deref() else {
+        return;
+    };
+    let path = Path::new(dir).join(format!("{name}.wal"));
+    match group.set_write_ahead_log(&path) {
+        Ok(0) => {}
+        Ok(replayed) => log::info!("Replayed {} records from {}", replayed, path.display()),
+        Err(e) => log::error!("Write-ahead log {} disabled: {}", path.display(), e),
+    }
 }
 
 #[cfg(test)]
@@ -454,16 +1963,18 @@ mod tests {
             *self.pids.lock().unwrap() = pids;
         }
 
-        async fn get_energy_trace(&self) -> Result<Vec<EnergyRecord>, String> {
+        async fn get_energy_trace(&self) -> Result<Vec<EnergyRecord>, CollectorError> {
             let sequence = self.sequence.fetch_add(1, Ordering::SeqCst) as f64;
             let pids = self.pids.lock().unwrap().clone();
             Ok(pids
                 .into_iter()
                 .map(|pid| EnergyRecord {
                     pid,
-                    timestamp: sequence as i64,
+                    timestamp_us: sequence as i64,
+                    monotonic_ns: sequence as i64,
                     device: "test:device".to_string(),
                     energy: 1.0 + sequence,
+                    raw_counter: None,
                 })
                 .collect())
         }
@@ -484,6 +1995,69 @@ mod tests {
         assert_eq!(*group.energy_collector.pids.lock().unwrap(), vec![321]);
     }
 
+    #[tokio::test]
+    async fn calibrated_baseline_adds_the_marginal_energy_column() {
+        let mut group = EnergyGroup::new(TestCollector::new(123), 100.0, Some(1));
+        group.set_tracked_pids(vec![123]);
+        assert!(group.baseline_watts().is_none());
+
+        let watts = group
+            .calibrate_baseline(Duration::from_millis(50))
+            .await
+            .unwrap();
+        assert!(watts["test:device"] > 0.0);
+        assert_eq!(group.baseline_watts(), Some(&watts));
+
+        group.commence().await.unwrap();
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        group.poll_data();
+        assert!(group.calibrate_baseline(Duration::ZERO).await.is_err());
+        group.shutdown().await.unwrap();
+
+        let trace = group.energy_trace();
+        let energy = trace.column("energy").unwrap().f64().unwrap();
+        let marginal = trace.column(MARGINAL_ENERGY_COLUMN).unwrap().f64().unwrap();
+        for (energy, marginal) in energy.into_no_null_iter().zip(marginal.into_no_null_iter()) {
+            assert!((0.0..=energy).contains(&marginal));
+        }
+    }
+
+    #[tokio::test]
+    async fn follow_children_tracks_processes_forked_after_commence() {
+        let mut shell = std::process::Command::new("sh")
+            .args(["-c", "sleep 0.1; sleep 30; :"])
+            .spawn()
+            .unwrap();
+        let shell_pid = shell.id();
+        let mut group = EnergyGroup::new(TestCollector::new(123), 50.0, Some(1));
+        group.set_tracked_pids(vec![shell_pid]);
+        group.follow_children(Some(Duration::ZERO));
+        group.commence().await.unwrap();
+
+        // The first `sleep` may still run when the second is forked.
+        let mut tracked = Vec::new();
+        for _ in 0..100 {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            tracked = group.energy_collector.pids.lock().unwrap().clone();
+            if tracked.len() == 2 && !tracked.contains(&123) {
+                break;
+            }
+        }
+        group.shutdown().await.unwrap();
+        shell.kill().unwrap();
+        shell.wait().unwrap();
+        for pid in tracked.iter().filter(|&&pid| pid != shell_pid) {
+            std::process::Command::new("kill")
+                .arg(pid.to_string())
+                .status()
+                .unwrap();
+        }
+
+        assert!(tracked.contains(&shell_pid));
+        assert_eq!(tracked.len(), 2);
+        assert_eq!(group.tracked_pids(), tracked);
+    }
+
     #[tokio::test]
     async fn poll_data_flushes_recorders_when_cadence_is_due() {
         let flush_count = Arc::new(AtomicUsize::new(0));
@@ -500,7 +2074,66 @@ mod tests {
         assert!(!records.is_empty());
         assert!(flush_count.load(Ordering::SeqCst) >= 1);
 
-        group.shutdown().unwrap();
+        group.shutdown().await.unwrap();
+    }
+
+    #[test]
+    fn poll_data_flushes_when_due_even_without_new_records() {
+        let flush_count = Arc::new(AtomicUsize::new(0));
+        let mut group = EnergyGroup::new(TestCollector::new(123), 50.0, Some(1));
+        group.set_recorder_flush_interval(Duration::from_secs(0));
+        group.add_recorder(Box::new(CountingRecorder {
+            flush_count: Arc::clone(&flush_count),
+        }));
+
+        assert!(group.poll_data().is_empty());
+        assert_eq!(flush_count.load(Ordering::SeqCst), 1);
+
+        group.set_recorder_flush_interval(Duration::from_secs(3600));
+        group.poll_data();
+        assert_eq!(flush_count.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn write_ahead_log_restores_unflushed_batches() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("cpu.wal");
+        let mut group = EnergyGroup::new(TestCollector::new(5), 50.0, Some(1));
+        assert_eq!(group.set_write_ahead_log(&path).unwrap(), 0);
+        group.commence().await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let mut records = group.poll_data();
+        records.extend(group.shutdown_and_drain().await.unwrap());
+        assert!(!records.is_empty());
+        drop(group);
+
+        // A restarted group picks the batches up again.
+        let mut group = EnergyGroup::new(TestCollector::new(5), 50.0, Some(1));
+        assert_eq!(group.set_write_ahead_log(&path).unwrap(), records.len());
+        assert_eq!(group.energy_trace().height(), records.len());
+
+        // Once a recorder has persisted them, they are not replayed again.
+        group.add_recorder(Box::new(CountingRecorder {
+            flush_count: Arc::new(AtomicUsize::new(0)),
+        }));
+        group.flush_recorders();
+        let mut group = EnergyGroup::new(TestCollector::new(5), 50.0, Some(1));
+        assert_eq!(group.set_write_ahead_log(&path).unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn write_ahead_log_holds_the_initial_read() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("cpu.wal");
+        let mut group = EnergyGroup::new(TestCollector::new(5), 50.0, Some(1));
+        group.set_write_ahead_log(&path).unwrap();
+        group.commence().await.unwrap();
+        group.shutdown_and_drain().await.unwrap();
+        let height = group.energy_trace().height();
+        drop(group);
+
+        let mut group = EnergyGroup::new(TestCollector::new(5), 50.0, Some(1));
+        assert_eq!(group.set_write_ahead_log(&path).unwrap(), height);
     }
 
     #[tokio::test]
@@ -513,9 +2146,830 @@ mod tests {
 
         group.commence().await.unwrap();
         tokio::time::sleep(Duration::from_millis(80)).await;
-        let final_records = group.shutdown_and_drain().unwrap();
+        let final_records = group.shutdown_and_drain().await.unwrap();
 
         assert!(!final_records.is_empty());
         assert_eq!(flush_count.load(Ordering::SeqCst), 1);
     }
+
+    #[tokio::test]
+    async fn carbon_provider_fills_the_co2_column_and_footprint() {
+        let mut group = EnergyGroup::new(TestCollector::new(456), 50.0, Some(1));
+        group.set_record_output(RecordOutput {
+            co2: true,
+            ..RecordOutput::default()
+        });
+        assert_eq!(group.carbon_footprint(), None);
+        group.set_carbon_provider(
+            Arc::new(crate::carbon::StaticIntensity(360.0)),
+            Duration::from_secs(60),
+        );
+
+        group.commence().await.unwrap();
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        group.shutdown().await.unwrap();
+
+        // 360 g/kWh is 1e-4 g per joule.
+        let trace = group.energy_trace();
+        let energy = trace.column("energy").unwrap().f64().unwrap();
+        let co2 = trace.column(CO2_COLUMN).unwrap().f64().unwrap();
+        for (energy, co2) in energy.into_iter().zip(co2) {
+            assert!((co2.unwrap() - energy.unwrap() * 1e-4).abs() < 1e-12);
+        }
+        let footprint = group.carbon_footprint().unwrap();
+        assert_eq!(footprint.intensity_g_per_kwh, 360.0);
+        assert!((footprint.grams_co2e - group.total_consumed_energy() * 1e-4).abs() < 1e-12);
+    }
+
+    #[tokio::test]
+    async fn sinks_receive_every_record_once_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("trace.jsonl");
+        let mut group = EnergyGroup::new(TestCollector::new(456), 50.0, Some(2));
+        group.add_sink(crate::sinks::open_sink(&path).unwrap());
+
+        group.commence().await.unwrap();
+        tokio::time::sleep(Duration::from_millis(80)).await;
+        group.poll_data();
+        group.shutdown().await.unwrap();
+
+        let reads: Vec<i64> = std::fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .map(|line| {
+                serde_json::from_str::<EnergyRecord>(line)
+                    .unwrap()
+                    .monotonic_ns
+            })
+            .collect();
+        assert!(reads.len() > 2);
+        assert!(reads.iter().enumerate().all(|(i, &read)| read == i as i64));
+    }
+
+    #[tokio::test]
+    async fn shutdown_delivers_the_partial_batch_without_waiting_for_the_next_tick() {
+        // One tick every 10 s, published every 1000 ticks
+        let mut group = EnergyGroup::new(TestCollector::new(456), 0.1, Some(1000));
+
+        group.commence().await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let started = Instant::now();
+        let final_records = group.shutdown_and_drain().await.unwrap();
+
+        assert!(started.elapsed() < Duration::from_secs(2));
+        // The initial read and the first tick's partial batch
+        assert_eq!(final_records.len(), 2);
+        assert!(!group.is_running());
+    }
+
+    #[tokio::test]
+    async fn shutdown_unblocks_a_send_on_a_full_channel() {
+        for auto_drain in [false, true] {
+            let mut group = EnergyGroup::new(TestCollector::new(7), 200.0, Some(1));
+            group.set_backpressure(1, BackpressurePolicy::Block);
+            group.set_auto_drain(auto_drain);
+
+            group.commence().await.unwrap();
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            let final_records = group.shutdown_and_drain().await.unwrap();
+
+            assert!(final_records.len() >= 2, "{} records", final_records.len());
+            assert_eq!(group.dropped_batches(), 0);
+        }
+    }
+
+    /// Collector whose device goes away after the first read and is back
+    /// once rediscovered.
+    struct HotUnplugCollector {
+        reads: AtomicUsize,
+        rediscovered: AtomicBool,
+    }
+
+    #[async_trait]
+    impl EnergyCollector for HotUnplugCollector {
+        fn set_tracked_pids(&self, _pids: Vec<u32>) {}
+
+        async fn get_energy_trace(&self) -> Result<Vec<EnergyRecord>, CollectorError> {
+            if self.reads.fetch_add(1, Ordering::SeqCst) > 0
+                && !self.rediscovered.load(Ordering::SeqCst)
+            {
+                return Err(CollectorError::DeviceLost("test:device".to_string()));
+            }
+            let time = RecordTime::now();
+            Ok(vec![EnergyRecord {
+                pid: 9,
+                timestamp_us: time.timestamp_us,
+                monotonic_ns: time.monotonic_ns,
+                device: "test:device".to_string(),
+                energy: 1.0,
+                raw_counter: None,
+            }])
+        }
+
+        fn rediscover(&self) -> bool {
+            !self.rediscovered.swap(true, Ordering::SeqCst)
+        }
+
+        fn is_available() -> bool {
+            true
+        }
+    }
+
+    #[tokio::test]
+    async fn a_lost_device_is_rediscovered_on_the_next_tick() {
+        let collector = HotUnplugCollector {
+            reads: AtomicUsize::new(0),
+            rediscovered: AtomicBool::new(false),
+        };
+        let mut group = EnergyGroup::new(collector, 100.0, Some(1));
+        group.set_rediscover_interval(Duration::from_secs(3600));
+
+        group.commence().await.unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let records = group.shutdown_and_drain().await.unwrap();
+
+        assert!(group.energy_collector.rediscovered.load(Ordering::SeqCst));
+        assert!(!records.is_empty());
+    }
+
+    #[tokio::test]
+    async fn auto_drain_keeps_collecting_without_polls() {
+        let mut group = EnergyGroup::new(TestCollector::new(7), 200.0, Some(1));
+        group.set_backpressure(1, BackpressurePolicy::Block);
+        group.set_auto_drain(true);
+
+        group.commence().await.unwrap();
+        let first = group.poll_data_async().await;
+        assert!(!first.is_empty());
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let records = group.poll_data();
+
+        // A channel of one batch would have stalled the collector after two
+        // iterations without the drain task.
+        assert!(records.len() >= 5, "{} records", records.len());
+        assert_eq!(group.dropped_batches(), 0);
+        group.shutdown().await.unwrap();
+        assert!(group.poll_data_async().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn poll_data_async_waits_for_the_next_batch() {
+        let mut group = EnergyGroup::new(TestCollector::new(8), 50.0, Some(1));
+
+        assert!(group.poll_data_async().await.is_empty());
+        group.commence().await.unwrap();
+        let records = group.poll_data_async().await;
+
+        assert!(!records.is_empty());
+        assert!(records.iter().all(|record| record.pid == 8));
+        group.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn next_batch_yields_frames_without_growing_the_trace() {
+        let mut group = EnergyGroup::new(TestCollector::new(9), 50.0, Some(1));
+        assert!(group.next_batch().await.is_none());
+
+        group.commence().await.unwrap();
+        let trace_height = group.energy_trace().height();
+        let (batch, devices) = group.next_batch().await.unwrap();
+
+        assert!(batch.height() > 0);
+        assert_eq!(group.energy_trace().height(), trace_height);
+        assert_eq!(
+            batch.get_column_names_str(),
+            [
+                "pid",
+                "device",
+                DEVICE_ID_COLUMN,
+                "energy",
+                "timestamp",
+                "monotonic_ns"
+            ]
+        );
+        let device_id = batch
+            .column(DEVICE_ID_COLUMN)
+            .unwrap()
+            .u32()
+            .unwrap()
+            .get(0);
+        assert_eq!(
+            devices
+                .column(DEVICE_ID_COLUMN)
+                .unwrap()
+                .u32()
+                .unwrap()
+                .get(0),
+            device_id
+        );
+
+        group.shutdown().await.unwrap();
+        assert!(group.next_batch().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn backpressure_policies_drop_and_count_batches() {
+        let batch = |energy| {
+            vec![EnergyRecord {
+                pid: 1,
+                timestamp_us: 0,
+                monotonic_ns: 0,
+                device: "test:device".to_string(),
+                energy,
+                raw_counter: None,
+            }]
+        };
+        for (policy, kept) in [
+            (BackpressurePolicy::DropOldest, 3.0),
+            (BackpressurePolicy::DropNewest, 1.0),
+        ] {
+            let (tx, rx) = mpsc::channel(1);
+            let rx = Arc::new(AsyncMutex::new(rx));
+            let sender = BatchSender {
+                tx,
+                rx: Arc::downgrade(&rx),
+                policy,
+                dropped_batches: Arc::new(AtomicU64::new(0)),
+            };
+            for energy in [1.0, 2.0, 3.0] {
+                assert!(sender.send(batch(energy)).await);
+            }
+
+            let received = rx.lock().await.try_recv().unwrap();
+            assert_eq!(received[0].energy, kept);
+            assert_eq!(sender.dropped_batches.load(Ordering::SeqCst), 2);
+            drop(rx);
+            assert!(!sender.send(batch(4.0)).await);
+        }
+    }
+
+    #[test]
+    fn record_output_adds_cumulative_energy_and_raw_counter_columns() {
+        let mut group = EnergyGroup::new(TestCollector::new(1), 50.0, Some(1));
+        group.set_record_output(RecordOutput {
+            cumulative_energy: true,
+            raw_counter: true,
+            quality: false,
+            co2: false,
+            sample_duration: false,
+        });
+        let record = |pid: u32, energy: f64, raw_counter: Option<f64>| EnergyRecord {
+            pid,
+            timestamp_us: 1_700_000_000_000_000,
+            monotonic_ns: 0,
+            device: "rapl:socket:0:package".to_string(),
+            energy,
+            raw_counter,
+        };
+
+        group
+            .append_energy_records(
+                &[record(1, 2.0, Some(100.0)), record(2, 1.0, Some(100.0))],
+                &[],
+            )
+            .unwrap();
+        group
+            .append_energy_records(&[record(1, 3.0, Some(104.0)), record(2, 1.0, None)], &[])
+            .unwrap();
+
+        let trace = group.energy_trace();
+        let cumulative = trace
+            .column(CUMULATIVE_ENERGY_COLUMN)
+            .unwrap()
+            .f64()
+            .unwrap();
+        assert_eq!(
+            cumulative.into_no_null_iter().collect::<Vec<_>>(),
+            [2.0, 1.0, 5.0, 2.0]
+        );
+        let raw = trace.column(RAW_COUNTER_COLUMN).unwrap().f64().unwrap();
+        assert_eq!(
+            raw.iter().collect::<Vec<_>>(),
+            [Some(100.0), Some(100.0), Some(104.0), None]
+        );
+        assert!(
+            EnergyGroup::new(TestCollector::new(1), 50.0, Some(1))
+                .joined_trace()
+                .unwrap()
+                .collect()
+                .unwrap()
+                .column(RAW_COUNTER_COLUMN)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn record_output_adds_sample_duration_of_each_device_read() {
+        let mut group = EnergyGroup::new(TestCollector::new(1), 50.0, Some(1));
+        group.set_record_output(RecordOutput {
+            sample_duration: true,
+            ..RecordOutput::default()
+        });
+        let record = |pid: u32, device: &str, monotonic_ms: i64| EnergyRecord {
+            pid,
+            timestamp_us: 1_700_000_000_000_000,
+            monotonic_ns: monotonic_ms * 1_000_000,
+            device: device.to_string(),
+            energy: 1.0,
+            raw_counter: None,
+        };
+
+        // Two reads of the CPU in one batch, and a GPU read late
+        group
+            .append_energy_records(
+                &[
+                    record(1, "cpu", 0),
+                    record(2, "cpu", 0),
+                    record(1, "gpu", 5),
+                    record(1, "cpu", 20),
+                    record(2, "cpu", 20),
+                ],
+                &[],
+            )
+            .unwrap();
+        group
+            .append_energy_records(&[record(1, "cpu", 45), record(1, "gpu", 65)], &[])
+            .unwrap();
+
+        let durations = group
+            .energy_trace()
+            .column(SAMPLE_DURATION_COLUMN)
+            .unwrap()
+            .f64()
+            .unwrap()
+            .iter()
+            .collect::<Vec<_>>();
+        assert_eq!(
+            durations,
+            [
+                None,
+                None,
+                None,
+                Some(20.0),
+                Some(20.0),
+                Some(25.0),
+                Some(60.0)
+            ]
+        );
+    }
+
+    #[test]
+    fn joined_trace_attaches_process_and_device_metadata() {
+        let mut group = EnergyGroup::new(TestCollector::new(1), 50.0, Some(1));
+        group.set_tracked_processes(&[ProcessGroup {
+            user: "alice".to_string(),
+            task: "train".to_string(),
+            pids: vec![10, 11],
+            container: Some("trainer".to_string()),
+            pod: Some("ml/train-0".to_string()),
+        }]);
+        assert_eq!(*group.energy_collector.pids.lock().unwrap(), vec![10, 11]);
+
+        let record = |pid: u32, device: &str| EnergyRecord {
+            pid,
+            timestamp_us: 1_700_000_000_000_000,
+            monotonic_ns: 0,
+            device: device.to_string(),
+            energy: 1.0,
+            raw_counter: None,
+        };
+        group
+            .append_energy_records(
+                &[
+                    record(10, "rapl:socket:0:package"),
+                    record(99, "nvidia:gpu:0"),
+                ],
+                &[],
+            )
+            .unwrap();
+
+        let joined = group.joined_trace().unwrap().collect().unwrap();
+
+        assert_eq!(joined.height(), 2);
+        let users = joined.column("user").unwrap().str().unwrap();
+        assert_eq!(users.get(0), Some("alice"));
+        assert_eq!(users.get(1), None);
+        let pods = joined.column("pod").unwrap().str().unwrap();
+        assert_eq!(pods.get(0), Some("ml/train-0"));
+        assert_eq!(pods.get(1), None);
+        let kinds = joined.column("kind").unwrap().str().unwrap();
+        assert_eq!(kinds.get(0), Some("cpu_package"));
+        assert_eq!(kinds.get(1), Some("gpu"));
+        assert_eq!(
+            joined.column("socket").unwrap().u32().unwrap().get(0),
+            Some(0)
+        );
+    }
+
+    #[test]
+    fn joined_trace_of_empty_trace_has_full_schema() {
+        let group = EnergyGroup::new(TestCollector::new(1), 50.0, Some(1));
+
+        let joined = group.joined_trace().unwrap().collect().unwrap();
+
+        assert_eq!(joined.height(), 0);
+        for name in [
+            "pid",
+            "energy",
+            "user",
+            "task",
+            "container",
+            "pod",
+            "kind",
+            "max_power_watts",
+        ] {
+            assert!(joined.column(name).is_ok(), "missing {name}");
+        }
+    }
+
+    #[test]
+    fn query_filters_memory_and_spilled_segments() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let mut group = EnergyGroup::new(TestCollector::new(1), 50.0, Some(1));
+        group.add_recorder(Box::new(CsvTraceRecorder::new(
+            tmp_dir.path().to_path_buf(),
+            None,
+            None,
+        )));
+        group.set_tracked_processes(&[ProcessGroup {
+            user: "alice".to_string(),
+            task: "train".to_string(),
+            pids: vec![10],
+            ..ProcessGroup::default()
+        }]);
+        let start_us = now_micros() - 10_000_000;
+        let record = |pid: u32, device: &str, offset_ms: i64| EnergyRecord {
+            pid,
+            timestamp_us: start_us + offset_ms * 1000,
+            monotonic_ns: offset_ms * 1_000_000,
+            device: device.to_string(),
+            energy: 1.0,
+            raw_counter: None,
+        };
+        group
+            .append_energy_records(
+                &[
+                    record(10, "rapl:socket:0:package", 0),
+                    record(11, "nvidia:gpu:0", 1000),
+                ],
+                &[],
+            )
+            .unwrap();
+        group.flush_recorders();
+        group
+            .append_energy_records(&[record(10, "nvidia:gpu:0", 2000)], &[])
+            .unwrap();
+        let query = |group: &EnergyGroup<TestCollector>, range, filter: &Filter| {
+            let frame = group.query(range, filter).unwrap().collect().unwrap();
+            frame
+                .column("pid")
+                .unwrap()
+                .u32()
+                .unwrap()
+                .into_no_null_iter()
+                .collect::<Vec<_>>()
+        };
+
+        // Flushed rows still in memory are returned once.
+        assert_eq!(
+            query(&group, TimeRange::all(), &Filter::default()),
+            [10, 11, 10]
+        );
+        let start_ms = start_us / 1000;
+        assert_eq!(
+            query(
+                &group,
+                TimeRange::between(start_ms + 1000, start_ms + 1000),
+                &Filter::default()
+            ),
+            [11]
+        );
+        assert_eq!(
+            query(&group, TimeRange::since(start_ms + 1), &Filter::default()),
+            [11, 10]
+        );
+        let gpus = Filter {
+            devices: vec!["nvidia:*".to_string()],
+            ..Filter::default()
+        };
+        assert_eq!(query(&group, TimeRange::all(), &gpus), [11, 10]);
+        let alice = Filter {
+            users: vec!["alice".to_string()],
+            ..Filter::default()
+        };
+        assert_eq!(query(&group, TimeRange::all(), &alice), [10, 10]);
+        let pid_11 = Filter {
+            pids: vec![11],
+            tasks: vec!["train".to_string()],
+            ..Filter::default()
+        };
+        assert!(query(&group, TimeRange::all(), &pid_11).is_empty());
+
+        // Rows that left memory are still read back from disk.
+        group.energy_trace.clear();
+        assert_eq!(query(&group, TimeRange::all(), &gpus), [11]);
+    }
+
+    #[test]
+    fn energy_by_region_counts_records_between_markers() {
+        let mut group = EnergyGroup::new(TestCollector::new(1), 50.0, Some(1));
+        let record = |energy: f64| EnergyRecord {
+            pid: 1,
+            timestamp_us: 1_700_000_000_000_000,
+            monotonic_ns: RecordTime::now().monotonic_ns,
+            device: "cpu".to_string(),
+            energy,
+            raw_counter: None,
+        };
+
+        group.append_energy_records(&[record(1.0)], &[]).unwrap();
+        group.begin_region("step");
+        std::thread::sleep(Duration::from_millis(2));
+        group.append_energy_records(&[record(2.0)], &[]).unwrap();
+        group.end_region("step").unwrap();
+        std::thread::sleep(Duration::from_millis(2));
+        group.append_energy_records(&[record(4.0)], &[]).unwrap();
+
+        let totals = group.energy_by_region(false).unwrap();
+
+        assert_eq!(totals.len(), 1);
+        assert_eq!(totals[0].energy_joules, 2.0);
+        assert!(totals[0].duration_seconds > 0.0);
+        assert!(group.end_region("step").is_err());
+    }
+
+    #[test]
+    fn sessions_attribute_records_to_the_active_session() {
+        let mut group = EnergyGroup::new(TestCollector::new(1), 50.0, Some(1));
+        let record = |energy: f64| EnergyRecord {
+            pid: 1,
+            timestamp_us: 1_700_000_000_000_000,
+            monotonic_ns: RecordTime::now().monotonic_ns,
+            device: "cpu".to_string(),
+            energy,
+            raw_counter: None,
+        };
+
+        group.append_energy_records(&[record(1.0)], &[]).unwrap();
+        group.begin_session("epoch_1").unwrap();
+        std::thread::sleep(Duration::from_millis(2));
+        group.append_energy_records(&[record(2.0)], &[]).unwrap();
+        assert!(group.begin_session("epoch_2").is_err());
+        assert_eq!(group.end_session().unwrap(), "epoch_1");
+        std::thread::sleep(Duration::from_millis(2));
+        group.append_energy_records(&[record(4.0)], &[]).unwrap();
+
+        let summary = group.session_summary("epoch_1").unwrap();
+        assert_eq!(summary.energy_joules, 2.0);
+        assert_eq!(summary.devices["cpu"], 2.0);
+        assert!(summary.end.is_some());
+        let trace = group.session_trace().unwrap();
+        assert_eq!(trace.height(), 5);
+        assert_eq!(
+            trace
+                .column(crate::sessions::SESSION_COLUMN)
+                .unwrap()
+                .null_count(),
+            2
+        );
+        assert!(group.end_session().is_err());
+    }
+
+    #[test]
+    fn spike_filter_corrects_totals_and_keeps_originals_in_the_trace() {
+        let mut group = EnergyGroup::new(TestCollector::new(1), 50.0, Some(1));
+        group.set_spike_filter(SpikeFiltering {
+            max_power_watts: Some(50.0),
+            ..SpikeFiltering::default()
+        });
+        group.set_record_output(RecordOutput::default());
+        let record = |second: i64, energy: f64| EnergyRecord {
+            pid: 7,
+            timestamp_us: 1_700_000_000_000_000 + second * 1_000_000,
+            monotonic_ns: second * 1_000_000_000,
+            device: "cpu".to_string(),
+            energy,
+            raw_counter: None,
+        };
+
+        group.apply_records(&[record(0, 0.0), record(1, 20.0)]);
+        group.ingest(&[record(2, 4_000.0)]);
+
+        assert_eq!(group.total_consumed_energy(), 70.0);
+        let trace = group.energy_trace();
+        let quality = trace.column(QUALITY_COLUMN).unwrap().str().unwrap();
+        assert_eq!(
+            quality.into_no_null_iter().collect::<Vec<_>>(),
+            ["ok", "ok", "clamped"]
+        );
+        let original = trace.column(ORIGINAL_ENERGY_COLUMN).unwrap().f64().unwrap();
+        assert_eq!(
+            original.iter().collect::<Vec<_>>(),
+            [None, None, Some(4_000.0)]
+        );
+    }
+
+    #[test]
+    fn total_counts_psys_once_alongside_package_and_dram() {
+        let mut group = EnergyGroup::new(TestCollector::new(1), 50.0, Some(1));
+        let record = |pid: u32, device: &str, energy: f64| EnergyRecord {
+            pid,
+            timestamp_us: 1_700_000_000_000_000,
+            monotonic_ns: 1_000_000_000,
+            device: device.to_string(),
+            energy,
+            raw_counter: None,
+        };
+
+        group.apply_records(&[
+            record(7, "rapl:socket:0:package", 30.0),
+            record(0, "rapl:socket:0:package", 10.0),
+            record(7, "rapl:system:dram", 5.0),
+            record(7, "rapl:system:psys", 30.0),
+            record(0, "rapl:system:psys", 30.0),
+        ]);
+
+        assert_eq!(group.total_consumed_energy(), 60.0);
+        let totals = group.energy_totals();
+        assert_eq!(totals.domains["platform"], 60.0);
+        assert_eq!(totals.components["rapl:socket:0:package"], 40.0);
+        assert_eq!(totals.components["platform:other"], 15.0);
+
+        group.set_energy_domains(Vec::new());
+        assert_eq!(group.total_consumed_energy(), 105.0);
+    }
+
+    #[test]
+    fn efficiency_divides_energy_by_reported_work() {
+        let mut group = EnergyGroup::new(TestCollector::new(1), 50.0, Some(1));
+        let record = |pid: u32, energy: f64| EnergyRecord {
+            pid,
+            timestamp_us: 1_700_000_000_000_000,
+            monotonic_ns: RecordTime::now().monotonic_ns,
+            device: "cpu".to_string(),
+            energy,
+            raw_counter: None,
+        };
+
+        group.begin_region("batch");
+        std::thread::sleep(Duration::from_millis(2));
+        group.apply_records(&[record(7, 6.0), record(8, 2.0)]);
+        group.add_work("images", 4.0, Some(7)).unwrap();
+        group.end_region("batch").unwrap();
+        assert!(group.add_work("images", -1.0, None).is_err());
+
+        let efficiency = group.efficiency().unwrap();
+
+        assert_eq!(efficiency.len(), 2);
+        assert_eq!(efficiency[0].region.as_deref(), Some("batch"));
+        assert_eq!(efficiency[0].joules_per_unit, Some(2.0));
+        assert_eq!(efficiency[1].pid, Some(7));
+        assert_eq!(efficiency[1].joules_per_unit, Some(1.5));
+        assert_eq!(group.work().len(), 1);
+    }
+
+    #[test]
+    fn write_parquet_adds_the_trace_to_a_dataset() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let mut group = EnergyGroup::new(TestCollector::new(1), 50.0, Some(1));
+        let record = |pid: u32| EnergyRecord {
+            pid,
+            timestamp_us: 1_700_000_000_000_000,
+            monotonic_ns: RecordTime::now().monotonic_ns,
+            device: "cpu".to_string(),
+            energy: 1.0,
+            raw_counter: None,
+        };
+        group.apply_records(&[record(7), record(8)]);
+
+        let options = ParquetOptions::default();
+        assert_eq!(group.write_parquet(tmp_dir.path(), options).unwrap(), 2);
+        assert_eq!(group.write_parquet(tmp_dir.path(), options).unwrap(), 2);
+
+        let stored = ParquetTraceRecorder::new(tmp_dir.path().to_path_buf())
+            .stored_trace()
+            .unwrap();
+        assert_eq!(stored.len(), 2);
+        assert_eq!(
+            stored[0].column("pid").unwrap().u32().unwrap().get(1),
+            Some(8)
+        );
+    }
+
+    #[test]
+    fn from_config_builds_the_group_from_a_config_file() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let path = tmp_dir.path().join("emt.toml");
+        std::fs::write(
+            &path,
+            "[collection]\nrate_hz = 20.0\nbatch_size = 5\ntrace_retention_secs = 60\n",
+        )
+        .unwrap();
+
+        let group = EnergyGroup::from_config(TestCollector::new(1), &path, "cpu").unwrap();
+
+        assert_eq!(group.rate, 20.0);
+        assert_eq!(group.batch_size(), 5);
+        assert_eq!(group.energy_trace.retention_seconds(), 60);
+        assert!(matches!(
+            EnergyGroup::from_config(
+                TestCollector::new(1),
+                tmp_dir.path().join("none.toml"),
+                "cpu"
+            ),
+            Err(ConfigError::Io(_))
+        ));
+    }
+
+    #[test]
+    fn forecast_projects_process_energy_from_its_power() {
+        let mut group = EnergyGroup::new(TestCollector::new(1), 50.0, Some(1));
+        let start = RecordTime::now().monotonic_ns - 10_000_000_000;
+        let record = |second: i64| EnergyRecord {
+            pid: 7,
+            timestamp_us: 1_700_000_000_000_000 + second * 1_000_000,
+            monotonic_ns: start + second * 1_000_000_000,
+            device: "cpu".to_string(),
+            energy: 50.0,
+            raw_counter: None,
+        };
+        let scope = ForecastScope::Process(7);
+        let method = ForecastMethod::default();
+
+        group.apply_records(&[record(0)]);
+        assert_eq!(
+            group
+                .forecast(&scope, Completion::Remaining(60.0), method)
+                .unwrap(),
+            None
+        );
+        for second in 1..=4 {
+            group.apply_records(&[record(second)]);
+        }
+        let forecast = group
+            .forecast(&scope, Completion::Progress(0.5), method)
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(forecast.energy_joules, 250.0);
+        assert_eq!(forecast.power_watts, 50.0);
+        assert_eq!(forecast.remaining_seconds, 4.0);
+        assert_eq!(forecast.total_joules, 450.0);
+    }
+
+    #[test]
+    fn recorded_metrics_join_the_energy_trace() {
+        let mut group = EnergyGroup::new(TestCollector::new(1), 50.0, Some(1));
+        let record = |energy: f64| EnergyRecord {
+            pid: 1,
+            timestamp_us: 1_700_000_000_000_000,
+            monotonic_ns: RecordTime::now().monotonic_ns,
+            device: "cpu".to_string(),
+            energy,
+            raw_counter: None,
+        };
+
+        group.append_energy_records(&[record(1.0)], &[]).unwrap();
+        group.record_metric("loss", 0.25).unwrap();
+        group.append_energy_records(&[record(2.0)], &[]).unwrap();
+        assert!(group.record_metric("loss", f64::INFINITY).is_err());
+
+        assert_eq!(group.metric_trace().height(), 1);
+        let joined = group.energy_trace_with_metrics().unwrap();
+        let loss: Vec<_> = joined
+            .column("metric_loss")
+            .unwrap()
+            .f64()
+            .unwrap()
+            .iter()
+            .collect();
+        assert_eq!(loss, [None, Some(0.25)]);
+    }
+
+    #[test]
+    fn trace_recorder_compresses_segments_at_the_configured_level() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut config = EmtConfig::default();
+        config.collection.trace_dir = Some(dir.path().to_string_lossy().into_owned());
+        config.collection.trace_compression_level = Some(3);
+        let mut trace = crate::utils::trace_rotation::RotatingTrace::new(3600);
+        trace
+            .append(
+                &polars::df![
+                    "pid" => [1u32],
+                    "timestamp" => [chrono::Utc::now().timestamp()],
+                    "device" => ["cpu"],
+                    "energy" => [10.0],
+                ]
+                .unwrap(),
+            )
+            .unwrap();
+
+        let mut recorder = trace_recorder(&config, "cpu").unwrap();
+        recorder.flush(&trace);
+        drop(recorder);
+
+        assert!(dir.path().join("cpu/trace_0.csv.zst").exists());
+        assert!(!dir.path().join("cpu/trace_0.csv").exists());
+    }
 }
@@ -0,0 +1,148 @@
+/// Energy Totals Module
+///
+/// Sums device energy without counting a domain twice when one meter
+/// contains others, such as RAPL PSYS, which covers the packages and DRAM.
+/// For each configured [`EnergyDomainRule`] the domain counts once, as the
+/// larger of its whole and the sum of its parts, and the breakdown lists the
+/// parts plus `<domain>:other`, the whole's energy beyond them. Devices
+/// outside every domain are added as they are; derived devices are skipped
+/// because they count the energy of other devices again.
+use crate::config::EnergyDomainRule;
+use crate::derived::DERIVED_PREFIX;
+use crate::utils::pattern::matches_any;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Overlap-aware energy figures.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct EnergyTotals {
+    /// Energy of the platform, each domain counted once
+    pub total_joules: f64,
+    /// Energy per configured domain that reported energy
+    pub domains: BTreeMap<String, f64>,
+    /// Non-overlapping breakdown summing to `total_joules`
+    pub components: BTreeMap<String, f64>,
+}
+
+impl EnergyTotals {
+    /// Totals of cumulative joules per device under `rules`. A device
+    /// matching several rules belongs to the first.
+    pub fn from_devices(rules: &[EnergyDomainRule], devices: &BTreeMap<String, f64>) -> Self {
+        let mut totals = Self::default();
+        let mut claimed: BTreeSet<&str> = BTreeSet::new();
+        for rule in rules {
+            let mut wholes = Vec::new();
+            let mut parts = Vec::new();
+            for (device, joules) in devices {
+                if claimed.contains(device.as_str()) {
+                    continue;
+                }
+                if matches_any(&rule.whole, device) {
+                    wholes.push((device.as_str(), *joules));
+                } else if matches_any(&rule.parts, device) {
+                    parts.push((device.as_str(), *joules));
+                } else {
+                    continue;
+                }
+                claimed.insert(device);
+            }
+            if wholes.is_empty() && parts.is_empty() {
+                continue;
+            }
+
+            let whole_joules: f64 = wholes.iter().map(|(_, joules)| joules).sum();
+            let parts_joules: f64 = parts.iter().map(|(_, joules)| joules).sum();
+            let domain_joules = if parts.is_empty() {
+                // Only the whole reported, so it is its own breakdown.
+                parts = wholes;
+                whole_joules
+            } else if wholes.is_empty() {
+                parts_joules
+            } else {
+                let other = (whole_joules - parts_joules).max(0.0);
+                totals
+                    .components
+                    .insert(format!("{}:other", rule.domain), other);
+                parts_joules + other
+            };
+            for (device, joules) in parts {
+                totals.components.insert(device.to_string(), joules);
+            }
+            totals.domains.insert(rule.domain.clone(), domain_joules);
+        }
+
+        for (device, joules) in devices {
+            if !claimed.contains(device.as_str()) && !device.starts_with(DERIVED_PREFIX) {
+                totals.components.insert(device.clone(), *joules);
+            }
+        }
+        totals.total_joules = totals.components.values().sum();
+        totals
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::default_energy_domains;
+
+    fn devices(entries: &[(&str, f64)]) -> BTreeMap<String, f64> {
+        entries
+            .iter()
+            .map(|(device, joules)| (device.to_string(), *joules))
+            .collect()
+    }
+
+    #[test]
+    fn psys_counts_once_with_its_remainder_as_a_component() {
+        let devices = devices(&[
+            ("rapl:system:psys", 100.0),
+            ("rapl:socket:0:package", 50.0),
+            ("rapl:socket:1:package", 20.0),
+            ("rapl:system:dram", 10.0),
+            ("nvidia:gpu:0", 40.0),
+            ("derived:rest", 20.0),
+        ]);
+
+        let totals = EnergyTotals::from_devices(&default_energy_domains(), &devices);
+
+        assert_eq!(totals.total_joules, 140.0);
+        assert_eq!(totals.domains, BTreeMap::from([("platform".into(), 100.0)]));
+        assert_eq!(
+            totals.components,
+            BTreeMap::from([
+                ("nvidia:gpu:0".into(), 40.0),
+                ("platform:other".into(), 20.0),
+                ("rapl:socket:0:package".into(), 50.0),
+                ("rapl:socket:1:package".into(), 20.0),
+                ("rapl:system:dram".into(), 10.0),
+            ])
+        );
+    }
+
+    #[test]
+    fn a_domain_without_its_whole_or_parts_falls_back_to_what_reported() {
+        let rules = default_energy_domains();
+
+        let parts_only = devices(&[("rapl:socket:0:package", 30.0), ("rapl:system:dram", 5.0)]);
+        let totals = EnergyTotals::from_devices(&rules, &parts_only);
+        assert_eq!(totals.total_joules, 35.0);
+        assert!(!totals.components.contains_key("platform:other"));
+
+        let whole_only = devices(&[("rapl:system:psys", 60.0)]);
+        let totals = EnergyTotals::from_devices(&rules, &whole_only);
+        assert_eq!(totals.total_joules, 60.0);
+        assert_eq!(totals.components, whole_only);
+
+        // Parts reading above the whole, e.g. from counter jitter.
+        let noisy = devices(&[("rapl:system:psys", 30.0), ("rapl:socket:0:package", 32.0)]);
+        let totals = EnergyTotals::from_devices(&rules, &noisy);
+        assert_eq!(totals.total_joules, 32.0);
+        assert_eq!(totals.components["platform:other"], 0.0);
+
+        assert_eq!(
+            EnergyTotals::from_devices(&rules, &BTreeMap::new()),
+            EnergyTotals::default()
+        );
+    }
+}
@@ -0,0 +1,6 @@
+#[cfg(feature = "prometheus-exporter")]
+pub mod prometheus;
+#[cfg(feature = "remote-write")]
+pub mod remote_write;
+#[cfg(feature = "prometheus-exporter")]
+pub use prometheus::{ProcessExporter, process_metrics_router};
@@ -0,0 +1,347 @@
+/// Prometheus Exporter Module
+///
+/// Publishes per-process series from `EnergyGroup` traces on an HTTP
+/// `/metrics` endpoint. Each group feeds the exporter through a trace
+/// recorder, so the series follow the trace as it is flushed:
+///
+/// - `emt_process_energy_joules_total{pid, device}`: energy attributed to
+///   the process on the device, with pid `0` holding unattributed energy;
+/// - `emt_process_utilization_ratio{pid, device}`: the process's share of
+///   the device's energy over the latest flush, which follows the
+///   utilization its energy is attributed by.
+///
+/// A series per process is high-cardinality, so the module is built only
+/// with the `prometheus-exporter` feature, and series of processes that stop
+/// appearing in the trace are dropped after a while. The snapshot metrics of
+/// [`metrics_sink`](crate::metrics_sink) stay the low-cardinality default.
+use crate::device_labels::DeviceLabels;
+use crate::metrics_sink::{LockUnpoisoned, MetricSample, device_metric_labels, metric_family};
+use crate::trace_recorder::TraceRecorder;
+use crate::utils::time::timestamp_micros;
+use crate::utils::trace_rotation::RotatingTrace;
+use axum::Router;
+use axum::extract::State;
+use axum::http::{StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use prometheus::core::{Collector, Desc};
+use prometheus::proto::{MetricFamily, MetricType};
+use prometheus::{Encoder, Registry, TextEncoder};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const PROCESS_ENERGY_METRIC: &str = "emt_process_energy_joules_total";
+const PROCESS_UTILIZATION_METRIC: &str = "emt_process_utilization_ratio";
+const PROCESS_ENERGY_HELP: &str =
+    "Cumulative energy in joules attributed to a process on a device; pid 0 is unattributed.";
+const PROCESS_UTILIZATION_HELP: &str =
+    "Share of a device's energy attributed to a process over the latest trace flush.";
+/// Default time after which the series of a process missing from the trace
+/// are dropped.
+const DEFAULT_STALE_AFTER: Duration = Duration::from_secs(300);
+
+#[derive(Debug, Clone, Copy, Default)]
+struct ProcessSeries {
+    joules: f64,
+    utilization: f64,
+    /// Trace timestamp of the newest record, in Unix microseconds
+    last_seen_us: i64,
+}
+
+#[derive(Debug)]
+struct ExporterState {
+    series: BTreeMap<(u32, String), ProcessSeries>,
+    stale_after: Duration,
+    device_labels: DeviceLabels,
+}
+
+impl ExporterState {
+    /// Add the energy of one flush window, given per pid and device, and
+    /// drop series not seen for `stale_after` before `newest_us`.
+    fn record_window(&mut self, window: HashMap<(u32, String), (f64, i64)>, newest_us: i64) {
+        let mut device_joules: HashMap<String, f64> = HashMap::new();
+        for ((_, device), (joules, _)) in &window {
+            *device_joules.entry(device.clone()).or_default() += joules;
+        }
+        // Processes missing from the window did not use its devices.
+        for ((_, device), series) in self.series.iter_mut() {
+            if device_joules.contains_key(device) {
+                series.utilization = 0.0;
+            }
+        }
+        for ((pid, device), (joules, last_seen_us)) in window {
+            let device_total = device_joules[&device];
+            let series = self.series.entry((pid, device)).or_default();
+            series.joules += joules;
+            series.utilization = if device_total > 0.0 {
+                joules / device_total
+            } else {
+                0.0
+            };
+            series.last_seen_us = series.last_seen_us.max(last_seen_us);
+        }
+
+        let cutoff_us = newest_us.saturating_sub(self.stale_after.as_micros() as i64);
+        self.series
+            .retain(|_, series| series.last_seen_us >= cutoff_us);
+    }
+
+    fn collect(&self) -> Vec<MetricFamily> {
+        let mut energy = Vec::new();
+        let mut utilization = Vec::new();
+        for ((pid, device), series) in &self.series {
+            let mut labels = vec![("pid".into(), pid.to_string())];
+            labels.extend(device_metric_labels(device, &self.device_labels));
+            energy.push(MetricSample {
+                value: series.joules,
+                labels: labels.clone(),
+            });
+            utilization.push(MetricSample {
+                value: series.utilization,
+                labels,
+            });
+        }
+        vec![
+            metric_family(
+                PROCESS_ENERGY_METRIC,
+                PROCESS_ENERGY_HELP,
+                MetricType::COUNTER,
+                &energy,
+            ),
+            metric_family(
+                PROCESS_UTILIZATION_METRIC,
+                PROCESS_UTILIZATION_HELP,
+                MetricType::GAUGE,
+                &utilization,
+            ),
+        ]
+    }
+}
+
+/// Per-process Prometheus series fed by the traces of energy groups.
+#[derive(Clone)]
+pub struct ProcessExporter {
+    state: Arc<Mutex<ExporterState>>,
+}
+
+impl Default for ProcessExporter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ProcessExporter {
+    pub fn new() -> Self {
+        Self {
+            state: Arc::new(Mutex::new(ExporterState {
+                series: BTreeMap::new(),
+                stale_after: DEFAULT_STALE_AFTER,
+                device_labels: DeviceLabels::default(),
+            })),
+        }
+    }
+
+    /// Drop the series of a process once the trace has had no record of it
+    /// for `stale_after` (default 5 minutes).
+    pub fn set_stale_after(&self, stale_after: Duration) {
+        self.state.lock_unpoisoned().stale_after = stale_after;
+    }
+
+    /// Name devices by their alias and add their labels, as the snapshot
+    /// metrics do.
+    pub fn set_device_labels(&self, device_labels: DeviceLabels) {
+        self.state.lock_unpoisoned().device_labels = device_labels;
+    }
+
+    /// A recorder that feeds this exporter from one group's trace, for
+    /// [`EnergyGroup::add_recorder`](crate::energy_group::EnergyGroup::add_recorder).
+    pub fn recorder(&self) -> Box<dyn TraceRecorder> {
+        Box::new(ProcessRecorder {
+            state: Arc::clone(&self.state),
+            last_flushed_timestamp: None,
+        })
+    }
+
+    /// Serve the series from `registry` too, such as the registry of a
+    /// [`PrometheusSink`](crate::metrics_sink::PrometheusSink).
+    pub fn register(&self, registry: &Registry) -> Result<(), prometheus::Error> {
+        registry.register(Box::new(self.clone()))
+    }
+
+    pub fn encode_text(&self) -> Result<String, prometheus::Error> {
+        TextEncoder::new().encode_to_string(&self.collect())
+    }
+}
+
+impl Collector for ProcessExporter {
+    fn desc(&self) -> Vec<&Desc> {
+        Vec::new()
+    }
+
+    fn collect(&self) -> Vec<MetricFamily> {
+        self.state.lock_unpoisoned().collect()
+    }
+}
+
+/// Recorder adding the rows of a trace not seen yet to the exporter.
+struct ProcessRecorder {
+    state: Arc<Mutex<ExporterState>>,
+    last_flushed_timestamp: Option<i64>,
+}
+
+impl TraceRecorder for ProcessRecorder {
+    fn flush(&mut self, trace: &RotatingTrace) {
+        let df = trace.data();
+        if df.is_empty() {
+            return;
+        }
+        let columns = (|| {
+            Ok::<_, polars::prelude::PolarsError>((
+                df.column("pid")?.u32()?.clone(),
+                df.column("device")?.str()?.clone(),
+                df.column("energy")?.f64()?.clone(),
+                timestamp_micros(df)?,
+            ))
+        })();
+        let (pids, devices, energies, timestamps) = match columns {
+            Ok(columns) => columns,
+            Err(e) => {
+                log::error!("Failed to read the trace for Prometheus: {}", e);
+                return;
+            }
+        };
+
+        let mut window: HashMap<(u32, String), (f64, i64)> = HashMap::new();
+        let mut newest = self.last_flushed_timestamp;
+        for row in 0..df.height() {
+            let (Some(pid), Some(device), Some(energy), Some(timestamp)) = (
+                pids.get(row),
+                devices.get(row),
+                energies.get(row),
+                timestamps.get(row),
+            ) else {
+                continue;
+            };
+            if self
+                .last_flushed_timestamp
+                .is_some_and(|last| timestamp <= last)
+            {
+                continue;
+            }
+            let (joules, last_seen) = window.entry((pid, device.to_string())).or_default();
+            *joules += energy;
+            *last_seen = (*last_seen).max(timestamp);
+            newest = newest.max(Some(timestamp));
+        }
+        if let Some(newest) = newest
+            && !window.is_empty()
+        {
+            self.state.lock_unpoisoned().record_window(window, newest);
+        }
+        self.last_flushed_timestamp = newest;
+    }
+
+    fn persists(&self) -> bool {
+        false
+    }
+}
+
+/// Router serving the exporter's series at `/metrics`.
+pub fn process_metrics_router(exporter: ProcessExporter) -> Router {
+    Router::new()
+        .route("/metrics", get(metrics_handler))
+        .with_state(exporter)
+}
+
+async fn metrics_handler(State(exporter): State<ProcessExporter>) -> Response {
+    match exporter.encode_text() {
+        Ok(body) => (
+            [(header::CONTENT_TYPE, TextEncoder::new().format_type())],
+            body,
+        )
+            .into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use polars::df;
+
+    /// Trace timestamps in seconds, as the trace takes them.
+    const T0: i64 = 1_700_000_000;
+
+    fn trace(rows: &[(u32, &str, f64, i64)]) -> RotatingTrace {
+        let mut trace = RotatingTrace::new(i64::MAX);
+        let data = df![
+            "pid" => rows.iter().map(|row| row.0).collect::<Vec<_>>(),
+            "timestamp" => rows.iter().map(|row| row.3).collect::<Vec<_>>(),
+            "device" => rows.iter().map(|row| row.1.to_string()).collect::<Vec<_>>(),
+            "energy" => rows.iter().map(|row| row.2).collect::<Vec<_>>(),
+        ]
+        .unwrap();
+        trace.append(&data).unwrap();
+        trace
+    }
+
+    fn series(exporter: &ProcessExporter) -> Vec<(u32, String, f64, f64)> {
+        exporter
+            .state
+            .lock_unpoisoned()
+            .series
+            .iter()
+            .map(|((pid, device), series)| {
+                (*pid, device.clone(), series.joules, series.utilization)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn accumulates_energy_and_utilization_per_process_and_device() {
+        let exporter = ProcessExporter::new();
+        let mut recorder = exporter.recorder();
+
+        recorder.flush(&trace(&[
+            (7, "rapl:socket:0:package", 3.0, T0),
+            (0, "rapl:socket:0:package", 1.0, T0),
+            (7, "nvidia:gpu:0", 2.0, T0),
+        ]));
+        // Rows flushed before are not counted again.
+        recorder.flush(&trace(&[
+            (7, "rapl:socket:0:package", 3.0, T0),
+            (8, "rapl:socket:0:package", 2.0, T0 + 1),
+            (0, "rapl:socket:0:package", 2.0, T0 + 1),
+        ]));
+
+        assert_eq!(
+            series(&exporter),
+            [
+                (0, "rapl:socket:0:package".to_string(), 3.0, 0.5),
+                (7, "nvidia:gpu:0".to_string(), 2.0, 1.0),
+                (7, "rapl:socket:0:package".to_string(), 3.0, 0.0),
+                (8, "rapl:socket:0:package".to_string(), 2.0, 0.5),
+            ]
+        );
+        let text = exporter.encode_text().unwrap();
+        assert!(
+            text.contains("emt_process_energy_joules_total{device=\"nvidia:gpu:0\",pid=\"7\"} 2")
+        );
+        assert!(text.contains("# TYPE emt_process_utilization_ratio gauge"));
+    }
+
+    #[test]
+    fn drops_processes_missing_from_the_trace_for_longer_than_stale_after() {
+        let exporter = ProcessExporter::new();
+        exporter.set_stale_after(Duration::from_secs(10));
+        let mut recorder = exporter.recorder();
+
+        recorder.flush(&trace(&[(7, "cpu", 1.0, T0)]));
+        recorder.flush(&trace(&[(8, "cpu", 1.0, T0 + 4)]));
+        assert_eq!(series(&exporter).len(), 2);
+        recorder.flush(&trace(&[(8, "cpu", 1.0, T0 + 11)]));
+
+        assert_eq!(series(&exporter), [(8, "cpu".to_string(), 2.0, 1.0)]);
+    }
+}
@@ -0,0 +1,428 @@
+/// Remote Write Module
+///
+/// Pushes the snapshot metrics of [`metrics_sink`](crate::metrics_sink) to a
+/// Prometheus remote-write endpoint, for fleets whose hosts cannot be
+/// scraped. [`RemoteWriteSink`] is a [`MetricsSink`] fed the same snapshots
+/// as the `/metrics` endpoint: each snapshot adds one sample per series,
+/// timestamped with the snapshot, to the pending batch. [`RemoteWriter`]
+/// sends a batch as a snappy-compressed protobuf `WriteRequest` (remote-write
+/// 1.0), retrying network errors, `5xx` and `429` answers with exponential
+/// backoff. A batch still failing after the configured attempts is dropped,
+/// so an unreachable endpoint cannot grow the exporter's memory.
+///
+/// Built with the `remote-write` feature.
+use crate::config::RemoteWriteConfig;
+use crate::device_labels::DeviceLabels;
+use crate::metrics_sink::{MetricsSink, PrometheusSink};
+use crate::monitor::{MetricsSnapshot, MonitorHandle};
+use prometheus::proto::MetricType;
+use prost::Message;
+use reqwest::StatusCode;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+
+/// Media type of a remote-write request body.
+pub const REMOTE_WRITE_CONTENT_TYPE: &str = "application/x-protobuf";
+const REMOTE_WRITE_VERSION: &str = "0.1.0";
+const PUSH_TIMEOUT: Duration = Duration::from_secs(10);
+/// Longest delay between two attempts at a batch.
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+const NAME_LABEL: &str = "__name__";
+
+/// `prometheus.WriteRequest` of the remote-write protocol.
+#[derive(Clone, PartialEq, Message)]
+pub struct WriteRequest {
+    #[prost(message, repeated, tag = "1")]
+    pub timeseries: Vec<TimeSeries>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct TimeSeries {
+    /// Labels sorted by name, including `__name__`
+    #[prost(message, repeated, tag = "1")]
+    pub labels: Vec<Label>,
+    /// Samples in increasing timestamp order
+    #[prost(message, repeated, tag = "2")]
+    pub samples: Vec<Sample>,
+}
+
+#[derive(Clone, PartialEq, Eq, Message)]
+pub struct Label {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(string, tag = "2")]
+    pub value: String,
+}
+
+#[derive(Clone, Copy, PartialEq, Message)]
+pub struct Sample {
+    #[prost(double, tag = "1")]
+    pub value: f64,
+    /// Unix milliseconds
+    #[prost(int64, tag = "2")]
+    pub timestamp: i64,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RemoteWriteError {
+    #[error("remote-write request failed: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("remote-write endpoint answered {status}: {body}")]
+    Status { status: StatusCode, body: String },
+    #[error("environment variable {0} is not set")]
+    MissingEnv(String),
+    #[error("failed to compress remote-write request: {0}")]
+    Compression(#[from] snap::Error),
+}
+
+impl RemoteWriteError {
+    /// Whether sending the batch again may succeed. Other client errors
+    /// mean the endpoint will never accept it.
+    fn is_retryable(&self) -> bool {
+        match self {
+            Self::Http(_) => true,
+            Self::Status { status, .. } => {
+                status.is_server_error() || *status == StatusCode::TOO_MANY_REQUESTS
+            }
+            Self::MissingEnv(_) | Self::Compression(_) => false,
+        }
+    }
+}
+
+/// Batches snapshot samples for remote write.
+pub struct RemoteWriteSink {
+    prometheus: PrometheusSink,
+    /// Pending samples per series, keyed by the sorted labels
+    series: BTreeMap<Vec<(String, String)>, Vec<Sample>>,
+    last_timestamp: Option<i64>,
+}
+
+impl RemoteWriteSink {
+    pub fn new() -> Result<Self, prometheus::Error> {
+        Ok(Self {
+            prometheus: PrometheusSink::new()?,
+            series: BTreeMap::new(),
+            last_timestamp: None,
+        })
+    }
+
+    pub fn set_device_labels(&mut self, device_labels: DeviceLabels) {
+        self.prometheus.set_device_labels(device_labels);
+    }
+
+    /// Number of samples waiting to be sent.
+    pub fn pending_samples(&self) -> usize {
+        self.series.values().map(Vec::len).sum()
+    }
+
+    /// Take the pending samples as a request, or `None` when there are none.
+    pub fn take_request(&mut self) -> Option<WriteRequest> {
+        if self.series.is_empty() {
+            return None;
+        }
+        let timeseries = std::mem::take(&mut self.series)
+            .into_iter()
+            .map(|(labels, samples)| TimeSeries {
+                labels: labels
+                    .into_iter()
+                    .map(|(name, value)| Label { name, value })
+                    .collect(),
+                samples,
+            })
+            .collect();
+        Some(WriteRequest { timeseries })
+    }
+}
+
+impl MetricsSink for RemoteWriteSink {
+    fn update(&mut self, snapshot: &MetricsSnapshot) {
+        // Receivers reject out-of-order samples, and a monitor that has not
+        // ticked since the last sample repeats its snapshot.
+        if self
+            .last_timestamp
+            .is_some_and(|last| snapshot.timestamp <= last)
+        {
+            return;
+        }
+        self.last_timestamp = Some(snapshot.timestamp);
+        self.prometheus.update(snapshot);
+
+        for family in self.prometheus.gather() {
+            let counter = family.get_field_type() == MetricType::COUNTER;
+            for metric in family.get_metric() {
+                let mut labels: Vec<(String, String)> = metric
+                    .get_label()
+                    .iter()
+                    .map(|label| (label.name().to_string(), label.value().to_string()))
+                    .collect();
+                labels.push((NAME_LABEL.to_string(), family.name().to_string()));
+                labels.sort();
+                let value = if counter {
+                    metric.counter.value()
+                } else {
+                    metric.gauge.value()
+                };
+                self.series.entry(labels).or_default().push(Sample {
+                    value,
+                    timestamp: snapshot.timestamp,
+                });
+            }
+        }
+    }
+}
+
+/// Protobuf-encode and snappy-compress `request`, as remote write sends it.
+pub fn encode_request(request: &WriteRequest) -> Result<Vec<u8>, RemoteWriteError> {
+    Ok(snap::raw::Encoder::new().compress_vec(&request.encode_to_vec())?)
+}
+
+/// Sends batches to a remote-write endpoint.
+pub struct RemoteWriter {
+    client: reqwest::Client,
+    config: RemoteWriteConfig,
+}
+
+impl RemoteWriter {
+    pub fn new(config: RemoteWriteConfig) -> Result<Self, RemoteWriteError> {
+        let client = reqwest::Client::builder().timeout(PUSH_TIMEOUT).build()?;
+        Ok(Self { client, config })
+    }
+
+    /// Send `request`, retrying with exponential backoff until it is
+    /// accepted, fails permanently or runs out of attempts.
+    pub async fn push(&self, request: &WriteRequest) -> Result<(), RemoteWriteError> {
+        let body = encode_request(request)?;
+        let mut backoff = Duration::from_secs_f64(self.config.backoff_secs).min(MAX_BACKOFF);
+        let mut attempt = 1;
+        loop {
+            match self.send(body.clone()).await {
+                Ok(()) => return Ok(()),
+                Err(e) if e.is_retryable() && attempt < self.config.max_attempts => {
+                    log::debug!(
+                        "Remote-write attempt {attempt} failed, retrying in {backoff:?}: {e}"
+                    );
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(MAX_BACKOFF);
+                    attempt += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    async fn send(&self, body: Vec<u8>) -> Result<(), RemoteWriteError> {
+        let mut request = self
+            .client
+            .post(&self.config.url)
+            .header(reqwest::header::CONTENT_ENCODING, "snappy")
+            .header(reqwest::header::CONTENT_TYPE, REMOTE_WRITE_CONTENT_TYPE)
+            .header("X-Prometheus-Remote-Write-Version", REMOTE_WRITE_VERSION)
+            .body(body);
+        if let Some(username) = &self.config.username {
+            let password = std::env::var(&self.config.password_env)
+                .map_err(|_| RemoteWriteError::MissingEnv(self.config.password_env.clone()))?;
+            request = request.basic_auth(username, Some(password));
+        }
+        if let Some(token_env) = &self.config.bearer_token_env {
+            let token = std::env::var(token_env)
+                .map_err(|_| RemoteWriteError::MissingEnv(token_env.clone()))?;
+            request = request.bearer_auth(token);
+        }
+        let response = request.send().await?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(());
+        }
+        let body = response.text().await.unwrap_or_default();
+        Err(RemoteWriteError::Status { status, body })
+    }
+}
+
+/// Sample the snapshots of `handle` every `sample_interval` into `sink` and
+/// push a batch every `config.interval_secs` until the returned sender
+/// fires, then push what is left. One push is in flight at a time; samples
+/// taken meanwhile join the next batch.
+pub fn spawn_remote_write(
+    mut sink: RemoteWriteSink,
+    config: RemoteWriteConfig,
+    handle: MonitorHandle,
+    sample_interval: Duration,
+) -> Result<(JoinHandle<()>, oneshot::Sender<()>), RemoteWriteError> {
+    let push_interval = Duration::from_secs_f64(config.interval_secs);
+    let writer = Arc::new(RemoteWriter::new(config)?);
+    let (stop, mut stopped) = oneshot::channel();
+    let task = tokio::spawn(async move {
+        let mut sample = tokio::time::interval(sample_interval);
+        let mut push =
+            tokio::time::interval_at(tokio::time::Instant::now() + push_interval, push_interval);
+        let mut in_flight: Option<JoinHandle<()>> = None;
+        loop {
+            tokio::select! {
+                _ = sample.tick() => sink.update(&handle.snapshot()),
+                _ = push.tick() => {
+                    if in_flight.as_ref().is_some_and(|push| !push.is_finished()) {
+                        continue;
+                    }
+                    if let Some(request) = sink.take_request() {
+                        let writer = Arc::clone(&writer);
+                        in_flight = Some(tokio::spawn(async move {
+                            push_batch(&writer, &request).await;
+                        }));
+                    }
+                }
+                _ = &mut stopped => break,
+            }
+        }
+        if let Some(push) = in_flight {
+            let _ = push.await;
+        }
+        sink.update(&handle.snapshot());
+        if let Some(request) = sink.take_request() {
+            push_batch(&writer, &request).await;
+        }
+    });
+    Ok((task, stop))
+}
+
+async fn push_batch(writer: &RemoteWriter, request: &WriteRequest) {
+    if let Err(e) = writer.push(request).await {
+        let samples: usize = request.timeseries.iter().map(|s| s.samples.len()).sum();
+        log::warn!("Dropped {samples} remote-write samples: {e}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::monitor::DeviceEnergy;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    fn snapshot(timestamp: i64, cpu_joules: f64) -> MetricsSnapshot {
+        MetricsSnapshot {
+            timestamp,
+            system_total: DeviceEnergy {
+                cpu_joules,
+                ..DeviceEnergy::default()
+            },
+            ..MetricsSnapshot::default()
+        }
+    }
+
+    fn config(url: String) -> RemoteWriteConfig {
+        RemoteWriteConfig {
+            url,
+            interval_secs: 15.0,
+            max_attempts: 3,
+            backoff_secs: 0.0,
+            username: None,
+            password_env: "REMOTE_WRITE_PASSWORD".to_string(),
+            bearer_token_env: None,
+        }
+    }
+
+    #[test]
+    fn sink_batches_one_sample_per_series_and_snapshot() {
+        let mut sink = RemoteWriteSink::new().unwrap();
+        assert!(sink.take_request().is_none());
+
+        sink.update(&snapshot(1_000, 10.0));
+        sink.update(&snapshot(2_000, 25.0));
+        sink.update(&snapshot(2_000, 25.0));
+        let request = sink.take_request().unwrap();
+
+        assert!(request.timeseries.iter().all(|series| {
+            series.samples.len() == 2
+                && series.labels.is_sorted_by(|a, b| a.name <= b.name)
+                && series.labels.iter().any(|label| label.name == NAME_LABEL)
+        }));
+        let energy = request
+            .timeseries
+            .iter()
+            .find(|series| {
+                series.labels.iter().any(|label| {
+                    label.name == NAME_LABEL && label.value == "emt_energy_joules_total"
+                })
+            })
+            .expect("energy series");
+        assert_eq!(energy.samples[1].timestamp, 2_000);
+        assert_eq!(sink.pending_samples(), 0);
+    }
+
+    #[test]
+    fn requests_round_trip_through_snappy_and_protobuf() {
+        let request = WriteRequest {
+            timeseries: vec![TimeSeries {
+                labels: vec![Label {
+                    name: NAME_LABEL.to_string(),
+                    value: "emt_power_watts".to_string(),
+                }],
+                samples: vec![Sample {
+                    value: 42.5,
+                    timestamp: 1_700_000_000_000,
+                }],
+            }],
+        };
+
+        let body = encode_request(&request).unwrap();
+        let decoded = snap::raw::Decoder::new().decompress_vec(&body).unwrap();
+
+        assert_eq!(WriteRequest::decode(decoded.as_slice()).unwrap(), request);
+    }
+
+    /// Serve `statuses` in order, one per connection, counting requests.
+    async fn endpoint(statuses: &'static [&'static str]) -> (String, Arc<AtomicUsize>) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/api/v1/write", listener.local_addr().unwrap());
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counted = Arc::clone(&requests);
+        tokio::spawn(async move {
+            for status in statuses {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buffer = [0; 4096];
+                let _ = stream.read(&mut buffer).await.unwrap();
+                counted.fetch_add(1, Ordering::SeqCst);
+                let response =
+                    format!("HTTP/1.1 {status}\r\ncontent-length: 0\r\nconnection: close\r\n\r\n");
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        (url, requests)
+    }
+
+    #[tokio::test]
+    async fn writer_retries_server_errors_until_accepted() {
+        let (url, requests) = endpoint(&["503 Service Unavailable", "204 No Content"]).await;
+        let mut sink = RemoteWriteSink::new().unwrap();
+        sink.update(&snapshot(1_000, 10.0));
+
+        RemoteWriter::new(config(url))
+            .unwrap()
+            .push(&sink.take_request().unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn writer_does_not_retry_rejected_batches() {
+        let (url, requests) = endpoint(&["400 Bad Request", "204 No Content"]).await;
+        let mut sink = RemoteWriteSink::new().unwrap();
+        sink.update(&snapshot(1_000, 10.0));
+
+        let result = RemoteWriter::new(config(url))
+            .unwrap()
+            .push(&sink.take_request().unwrap())
+            .await;
+
+        assert!(matches!(
+            result,
+            Err(RemoteWriteError::Status { status, .. }) if status == StatusCode::BAD_REQUEST
+        ));
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,447 @@
+/// Forecast Module
+///
+/// Short-horizon projections of energy to completion, to estimate the
+/// footprint of a long run, such as a training job, early on. Power is
+/// smoothed with an exponentially weighted moving average, or with Holt's
+/// linear trend method, which also follows a steady rise or fall, and the
+/// smoothed power is integrated over the time left.
+///
+/// Methods are written as `ewma[:<alpha>]` or `holt[:<alpha>,<beta>]`. The
+/// time left is either given directly or derived from the fraction of the
+/// work done so far, assuming the rest runs at the same pace.
+///
+/// Forecasts are made from cumulative energy observed at least
+/// [`MIN_INTERVAL_SECS`] apart, so devices read at slightly different
+/// instants do not show up as spikes. Projected power never drops below
+/// zero.
+use crate::regions::Region;
+use crate::utils::time::{MONOTONIC_COLUMN, RecordTime};
+use polars::prelude::*;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// Shortest interval between observations power is derived from.
+pub const MIN_INTERVAL_SECS: f64 = 1.0;
+
+const DEFAULT_ALPHA: f64 = 0.3;
+const DEFAULT_BETA: f64 = 0.1;
+
+/// Errors raised when forecasting.
+#[derive(Debug, thiserror::Error)]
+pub enum ForecastError {
+    #[error("progress must be in (0, 1], got {0}")]
+    InvalidProgress(f64),
+    #[error("remaining time must be zero or more seconds, got {0}")]
+    InvalidRemaining(f64),
+    #[error("Polars error: {0}")]
+    Polars(#[from] PolarsError),
+}
+
+/// How power is smoothed before it is projected.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ForecastMethod {
+    /// Exponentially weighted moving average with smoothing factor `alpha`
+    Ewma { alpha: f64 },
+    /// Holt's linear trend method: `alpha` smooths the level and `beta` the
+    /// trend
+    Holt { alpha: f64, beta: f64 },
+}
+
+impl Default for ForecastMethod {
+    fn default() -> Self {
+        Self::Holt {
+            alpha: DEFAULT_ALPHA,
+            beta: DEFAULT_BETA,
+        }
+    }
+}
+
+impl FromStr for ForecastMethod {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let spec = spec.trim();
+        let invalid = || {
+            format!(
+                "invalid forecast method '{spec}': expected ewma[:<alpha>] or holt[:<alpha>,<beta>] with factors in (0, 1]"
+            )
+        };
+        let factor = |value: &str| {
+            value
+                .parse::<f64>()
+                .ok()
+                .filter(|factor| *factor > 0.0 && *factor <= 1.0)
+                .ok_or_else(invalid)
+        };
+        let (method, factors) = spec.split_once(':').unwrap_or((spec, ""));
+        match (method, factors) {
+            ("ewma", "") => Ok(Self::Ewma {
+                alpha: DEFAULT_ALPHA,
+            }),
+            ("ewma", alpha) => Ok(Self::Ewma {
+                alpha: factor(alpha)?,
+            }),
+            ("holt", "") => Ok(Self::default()),
+            ("holt", factors) => {
+                let (alpha, beta) = factors.split_once(',').ok_or_else(invalid)?;
+                Ok(Self::Holt {
+                    alpha: factor(alpha)?,
+                    beta: factor(beta)?,
+                })
+            }
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl fmt::Display for ForecastMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Ewma { alpha } => write!(f, "ewma:{alpha}"),
+            Self::Holt { alpha, beta } => write!(f, "holt:{alpha},{beta}"),
+        }
+    }
+}
+
+/// When the forecast work completes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Completion {
+    /// After this many more seconds
+    Remaining(f64),
+    /// Once as much time again has passed as this fraction of the work took
+    Progress(f64),
+}
+
+impl Completion {
+    fn validate(self) -> Result<(), ForecastError> {
+        match self {
+            Self::Remaining(seconds) if !(seconds.is_finite() && seconds >= 0.0) => {
+                Err(ForecastError::InvalidRemaining(seconds))
+            }
+            Self::Progress(fraction) if !(fraction > 0.0 && fraction <= 1.0) => {
+                Err(ForecastError::InvalidProgress(fraction))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Energy projected to completion.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct EnergyForecast {
+    /// Energy consumed so far
+    pub energy_joules: f64,
+    /// Time covered by the observations
+    pub elapsed_seconds: f64,
+    /// Smoothed current power
+    pub power_watts: f64,
+    /// Change of power per second; zero for EWMA
+    pub trend_watts_per_second: f64,
+    pub remaining_seconds: f64,
+    pub remaining_joules: f64,
+    /// `energy_joules + remaining_joules`
+    pub total_joules: f64,
+}
+
+/// Smooths power from cumulative energy observations and projects it.
+#[derive(Debug, Clone, Default)]
+pub struct EnergyForecaster {
+    method: ForecastMethod,
+    /// First observation, `(seconds, joules)`
+    first: Option<(f64, f64)>,
+    /// Latest observation power was derived from
+    last: Option<(f64, f64)>,
+    /// Latest observation, possibly too close to `last` to use yet
+    latest: Option<(f64, f64)>,
+    level: Option<f64>,
+    trend: f64,
+}
+
+impl EnergyForecaster {
+    pub fn new(method: ForecastMethod) -> Self {
+        Self {
+            method,
+            ..Self::default()
+        }
+    }
+
+    /// Observe `joules` consumed in total by `at_secs`. Observations must
+    /// come in time order; earlier ones are ignored.
+    pub fn observe(&mut self, at_secs: f64, joules: f64) {
+        let Some((last_at, last_joules)) = self.last else {
+            self.first = Some((at_secs, joules));
+            self.last = self.first;
+            self.latest = self.first;
+            return;
+        };
+        if self
+            .latest
+            .is_some_and(|(latest_at, _)| at_secs < latest_at)
+        {
+            return;
+        }
+        self.latest = Some((at_secs, joules));
+        let interval = at_secs - last_at;
+        if interval < MIN_INTERVAL_SECS {
+            return;
+        }
+        let power = ((joules - last_joules) / interval).max(0.0);
+        self.last = Some((at_secs, joules));
+        let Some(level) = self.level else {
+            self.level = Some(power);
+            return;
+        };
+        match self.method {
+            ForecastMethod::Ewma { alpha } => {
+                self.level = Some(alpha * power + (1.0 - alpha) * level);
+            }
+            ForecastMethod::Holt { alpha, beta } => {
+                let predicted = level + self.trend * interval;
+                let smoothed = alpha * power + (1.0 - alpha) * predicted;
+                self.trend = beta * (smoothed - level) / interval + (1.0 - beta) * self.trend;
+                self.level = Some(smoothed);
+            }
+        }
+    }
+
+    /// Energy projected to `completion`; `None` until power has been
+    /// derived from two observations.
+    pub fn forecast(
+        &self,
+        completion: Completion,
+    ) -> Result<Option<EnergyForecast>, ForecastError> {
+        completion.validate()?;
+        let (Some(level), Some((first_at, _)), Some((latest_at, latest_joules))) =
+            (self.level, self.first, self.latest)
+        else {
+            return Ok(None);
+        };
+        let elapsed_seconds = latest_at - first_at;
+        let remaining_seconds = match completion {
+            Completion::Remaining(seconds) => seconds,
+            Completion::Progress(fraction) => elapsed_seconds * (1.0 - fraction) / fraction,
+        };
+        let remaining_joules = integrate(level, self.trend, remaining_seconds);
+        Ok(Some(EnergyForecast {
+            energy_joules: latest_joules,
+            elapsed_seconds,
+            power_watts: level,
+            trend_watts_per_second: self.trend,
+            remaining_seconds,
+            remaining_joules,
+            total_joules: latest_joules + remaining_joules,
+        }))
+    }
+}
+
+/// Energy of power `level + trend * t`, floored at zero, over `seconds`.
+fn integrate(level: f64, trend: f64, seconds: f64) -> f64 {
+    let level = level.max(0.0);
+    let end = level + trend * seconds;
+    if end >= 0.0 {
+        return (level + end) / 2.0 * seconds;
+    }
+    // Power reaches zero at -level / trend and stays there.
+    level * (-level / trend) / 2.0
+}
+
+/// What a forecast covers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ForecastScope {
+    /// Energy attributed to one pid
+    Process(u32),
+    /// Energy recorded inside the spans of a region
+    Region(String),
+}
+
+/// Forecaster fed with the cumulative energy of `scope` in `trace`
+/// (`pid | device | energy | monotonic_ns`). `total_joules`, when known,
+/// is the scope's energy including records that rotated out of the trace,
+/// such as a process's energy since it was first seen. Open regions extend
+/// to `now`.
+pub fn forecaster_from_trace(
+    trace: &DataFrame,
+    scope: &ForecastScope,
+    regions: &[Region],
+    method: ForecastMethod,
+    total_joules: Option<f64>,
+    now: RecordTime,
+) -> PolarsResult<EnergyForecaster> {
+    let mut forecaster = EnergyForecaster::new(method);
+    if trace.height() == 0 {
+        return Ok(forecaster);
+    }
+    let pids = trace.column("pid")?.u32()?;
+    let energies = trace.column("energy")?.f64()?;
+    let monotonic = trace.column(MONOTONIC_COLUMN)?.cast(&DataType::Int64)?;
+    let monotonic = monotonic.i64()?;
+
+    let mut energy: BTreeMap<i64, f64> = BTreeMap::new();
+    for ((pid, joules), monotonic_ns) in pids.iter().zip(energies).zip(monotonic) {
+        let (Some(pid), Some(joules), Some(monotonic_ns)) = (pid, joules, monotonic_ns) else {
+            continue;
+        };
+        let matches = match scope {
+            ForecastScope::Process(target) => pid == *target,
+            ForecastScope::Region(name) => regions
+                .iter()
+                .any(|region| region.name == *name && region.contains(monotonic_ns, now)),
+        };
+        if matches {
+            *energy.entry(monotonic_ns).or_insert(0.0) += joules;
+        }
+    }
+
+    let traced: f64 = energy.values().sum();
+    let mut cumulative = total_joules.map_or(0.0, |total| (total - traced).max(0.0));
+    // Relative to the first record, so seconds keep their precision.
+    let start = energy.keys().next().copied().unwrap_or_default();
+    for (monotonic_ns, joules) in energy {
+        cumulative += joules;
+        forecaster.observe((monotonic_ns - start) as f64 / 1e9, cumulative);
+    }
+    Ok(forecaster)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::regions::RegionMarkers;
+    use crate::utils::time::monotonic_dtype;
+
+    fn at(seconds: i64) -> RecordTime {
+        RecordTime {
+            timestamp_us: 1_700_000_000_000_000 + seconds * 1_000_000,
+            monotonic_ns: seconds * 1_000_000_000,
+        }
+    }
+
+    /// Feed `power(t)` watts for `seconds` seconds, observed every second.
+    fn feed(forecaster: &mut EnergyForecaster, seconds: u32, power: impl Fn(f64) -> f64) {
+        let mut joules = 0.0;
+        forecaster.observe(0.0, 0.0);
+        for second in 1..=seconds {
+            joules += power(f64::from(second));
+            forecaster.observe(f64::from(second), joules);
+        }
+    }
+
+    #[test]
+    fn parses_methods() {
+        assert_eq!("ewma".parse(), Ok(ForecastMethod::Ewma { alpha: 0.3 }));
+        assert_eq!(
+            "holt:0.5,0.2".parse(),
+            Ok(ForecastMethod::Holt {
+                alpha: 0.5,
+                beta: 0.2
+            })
+        );
+        assert_eq!("holt".parse(), Ok(ForecastMethod::default()));
+        assert!("ewma:0".parse::<ForecastMethod>().is_err());
+        assert!("holt:0.5".parse::<ForecastMethod>().is_err());
+        assert!("arima".parse::<ForecastMethod>().is_err());
+        assert_eq!(ForecastMethod::default().to_string(), "holt:0.3,0.1");
+    }
+
+    #[test]
+    fn projects_constant_power_to_completion() {
+        let mut forecaster = EnergyForecaster::new(ForecastMethod::Ewma { alpha: 0.5 });
+        assert_eq!(
+            forecaster.forecast(Completion::Remaining(1.0)).unwrap(),
+            None
+        );
+        feed(&mut forecaster, 100, |_| 200.0);
+
+        let forecast = forecaster
+            .forecast(Completion::Progress(0.25))
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(forecast.energy_joules, 20_000.0);
+        assert_eq!(forecast.elapsed_seconds, 100.0);
+        assert_eq!(forecast.power_watts, 200.0);
+        assert_eq!(forecast.remaining_seconds, 300.0);
+        assert_eq!(forecast.remaining_joules, 60_000.0);
+        assert_eq!(forecast.total_joules, 80_000.0);
+        assert!(matches!(
+            forecaster.forecast(Completion::Progress(0.0)),
+            Err(ForecastError::InvalidProgress(_))
+        ));
+    }
+
+    #[test]
+    fn holt_follows_a_rising_trend() {
+        let mut holt = EnergyForecaster::new("holt:0.5,0.3".parse().unwrap());
+        let mut ewma = EnergyForecaster::new("ewma:0.5".parse().unwrap());
+        feed(&mut holt, 200, |t| 100.0 + t);
+        feed(&mut ewma, 200, |t| 100.0 + t);
+
+        let holt = holt
+            .forecast(Completion::Remaining(100.0))
+            .unwrap()
+            .unwrap();
+        let ewma = ewma
+            .forecast(Completion::Remaining(100.0))
+            .unwrap()
+            .unwrap();
+
+        assert!((holt.trend_watts_per_second - 1.0).abs() < 0.01);
+        assert!((holt.power_watts - 300.0).abs() < 1.0);
+        // Power rises from 300 W to 400 W over the next 100 s.
+        assert!((holt.remaining_joules - 35_000.0).abs() < 200.0);
+        assert!(ewma.remaining_joules < holt.remaining_joules);
+        assert_eq!(integrate(100.0, -1.0, 1_000.0), 5_000.0);
+    }
+
+    #[test]
+    fn forecasts_processes_and_regions_from_the_trace() {
+        let mut trace = df!(
+            "pid" => [1u32, 2, 1, 2, 1, 2, 1],
+            "device" => ["cpu"; 7],
+            "energy" => [10.0, 1.0, 10.0, 1.0, 10.0, 1.0, 10.0],
+            MONOTONIC_COLUMN => [0i64, 0, 1, 1, 2, 2, 3].map(|s| at(s).monotonic_ns),
+        )
+        .unwrap();
+        trace
+            .apply(MONOTONIC_COLUMN, |column| {
+                column.cast(&monotonic_dtype()).unwrap()
+            })
+            .unwrap();
+        let mut markers = RegionMarkers::new();
+        markers.begin_at("epoch", at(0));
+        markers.end_at("epoch", at(2)).unwrap();
+
+        let process = forecaster_from_trace(
+            &trace,
+            &ForecastScope::Process(1),
+            &[],
+            ForecastMethod::default(),
+            Some(140.0),
+            at(3),
+        )
+        .unwrap()
+        .forecast(Completion::Remaining(10.0))
+        .unwrap()
+        .unwrap();
+        assert_eq!(process.energy_joules, 140.0);
+        assert!((process.remaining_joules - 100.0).abs() < 1e-9);
+
+        let region = forecaster_from_trace(
+            &trace,
+            &ForecastScope::Region("epoch".to_string()),
+            markers.regions(),
+            ForecastMethod::default(),
+            None,
+            at(3),
+        )
+        .unwrap()
+        .forecast(Completion::Progress(0.5))
+        .unwrap()
+        .unwrap();
+        assert_eq!(region.energy_joules, 22.0);
+        assert_eq!(region.remaining_seconds, 1.0);
+        assert!((region.remaining_joules - 11.0).abs() < 1e-9);
+    }
+}
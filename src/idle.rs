@@ -0,0 +1,149 @@
+/// Idle Module
+///
+/// Detects sustained system idleness so long-running monitors can sample an
+/// idle machine at a low rate instead of the full collection rate. The system
+/// is idle once power and CPU utilization have both stayed below their
+/// thresholds for `after_secs`, and active again as soon as either rises above
+/// them.
+///
+/// Energy counters are cumulative, so sampling less often while idle loses no
+/// energy; it only coarsens the timeline.
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Thresholds for idle detection, under `collection.idle`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IdleConfig {
+    pub enabled: bool,
+    /// System power at or below which the machine may be idle.
+    pub power_watts: f64,
+    /// System CPU utilization (0-100) at or below which the machine may be
+    /// idle.
+    pub cpu_percent: f64,
+    /// How long both must stay below their thresholds.
+    pub after_secs: f64,
+    /// Collection rate while idle.
+    pub rate_hz: f64,
+}
+
+impl Default for IdleConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            power_watts: 20.0,
+            cpu_percent: 5.0,
+            after_secs: 60.0,
+            rate_hz: 0.2,
+        }
+    }
+}
+
+impl IdleConfig {
+    /// Sampling interval while idle.
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs_f64(1.0 / self.rate_hz)
+    }
+
+    /// Whether CPU utilization alone rules out idleness; checked between idle
+    /// samples to resume without waiting for the next energy reading.
+    pub fn is_active_cpu(&self, cpu_percent: f64) -> bool {
+        cpu_percent > self.cpu_percent
+    }
+}
+
+/// Tracks how long the system has been below the idle thresholds.
+#[derive(Debug, Clone)]
+pub struct IdleDetector {
+    config: IdleConfig,
+    /// Unix milliseconds since which the system has been quiet.
+    quiet_since: Option<i64>,
+    idle: bool,
+}
+
+impl IdleDetector {
+    pub fn new(config: IdleConfig) -> Self {
+        Self {
+            config,
+            quiet_since: None,
+            idle: false,
+        }
+    }
+
+    pub fn config(&self) -> &IdleConfig {
+        &self.config
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.idle
+    }
+
+    /// Record one observation at `now` (Unix ms) and return whether the
+    /// system is now idle. Detection is off when the config is disabled.
+    pub fn observe(&mut self, now: i64, power_watts: f64, cpu_percent: f64) -> bool {
+        if !self.config.enabled {
+            return false;
+        }
+        let quiet =
+            power_watts <= self.config.power_watts && !self.config.is_active_cpu(cpu_percent);
+        if !quiet {
+            if self.idle {
+                log::info!("System active again ({power_watts:.1} W, {cpu_percent:.1}% CPU)");
+            }
+            self.quiet_since = None;
+            self.idle = false;
+            return false;
+        }
+        let since = *self.quiet_since.get_or_insert(now);
+        if !self.idle && (now - since) as f64 >= self.config.after_secs * 1000.0 {
+            log::info!(
+                "System idle, sampling at {} Hz until activity resumes",
+                self.config.rate_hz
+            );
+            self.idle = true;
+        }
+        self.idle
+    }
+
+    /// Leave the idle state immediately, e.g. after activity was seen
+    /// between samples.
+    pub fn wake(&mut self) {
+        self.quiet_since = None;
+        self.idle = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detector() -> IdleDetector {
+        IdleDetector::new(IdleConfig {
+            enabled: true,
+            after_secs: 10.0,
+            ..IdleConfig::default()
+        })
+    }
+
+    #[test]
+    fn idle_after_sustained_quiet_and_active_on_any_spike() {
+        let mut detector = detector();
+
+        assert!(!detector.observe(0, 10.0, 1.0));
+        assert!(!detector.observe(9_000, 10.0, 1.0));
+        assert!(detector.observe(10_000, 10.0, 1.0));
+        assert!(!detector.observe(11_000, 50.0, 1.0));
+        assert!(!detector.observe(15_000, 10.0, 1.0));
+        assert!(detector.observe(25_000, 10.0, 1.0));
+        assert!(!detector.observe(26_000, 10.0, 30.0));
+    }
+
+    #[test]
+    fn disabled_detector_never_reports_idle() {
+        let mut detector = IdleDetector::new(IdleConfig::default());
+
+        assert!(!detector.observe(0, 0.0, 0.0));
+        assert!(!detector.observe(1_000_000, 0.0, 0.0));
+        assert_eq!(IdleConfig::default().interval(), Duration::from_secs(5));
+    }
+}
@@ -1,19 +1,107 @@
+//! Energy Monitoring Tool
+//!
+//! Measures the energy of CPUs, GPUs and other devices and attributes it
+//! to processes. The `emt` binary and the Python extension are built on
+//! this crate, and other Rust programs can embed it the same way.
+//!
+//! An [`EnergyGroup`] reads one [`EnergyCollector`] at a fixed rate and
+//! keeps the [`EnergyRecord`]s in a trace. The collectors are in
+//! [`collectors`]. A [`Monitor`] runs every available collector for a
+//! whole host, configured by an [`EmtConfig`]. Reads fail with a
+//! [`CollectorError`] and groups with a [`MonitoringError`].
+//!
+//! ```no_run
+//! use emt::EnergyGroup;
+//! use emt::config::CpuEnergyConfig;
+//! use std::time::Duration;
+//!
+//! # async fn measure() -> Result<(), emt::MonitoringError> {
+//! let mut group = EnergyGroup::cpu(&CpuEnergyConfig::default(), 10.0, None);
+//! group.set_tracked_pids(vec![std::process::id()]);
+//! group.commence().await?;
+//! tokio::time::sleep(Duration::from_secs(5)).await;
+//! group.shutdown().await?;
+//! println!("{}", group.energy_trace());
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The root re-exports are the stable API. The modules are public for
+//! the binary and for advanced use, and may change between minor
+//! versions.
+pub mod arrow_stream;
+pub mod baseline;
+pub mod budgets;
+pub mod carbon;
 pub mod collectors;
 pub mod config;
+pub mod container;
+pub mod convert;
+#[cfg(unix)]
+pub mod daemon;
+pub mod derived;
+pub mod device_labels;
+pub mod device_priority;
+pub mod device_registry;
+pub mod encryption;
 pub mod energy_group;
+pub mod energy_totals;
+#[cfg(any(feature = "prometheus-exporter", feature = "remote-write"))]
+pub mod exporters;
+pub mod forecast;
+pub mod idle;
+pub mod live;
+pub mod merge;
+pub mod metric_channels;
 pub mod metrics_sink;
 pub mod monitor;
+pub mod overhead;
+pub mod peak_events;
+pub mod power_limits;
+pub mod power_stats;
 pub mod process;
 pub mod process_aggregation;
+pub mod process_watcher;
+pub mod query;
+pub mod read_schedule;
+pub mod regions;
+pub mod remote;
+pub mod report;
+pub mod run;
+pub mod sampler;
+pub mod sandbox;
+pub mod schema;
+pub mod server;
+pub mod sessions;
+pub mod shutdown_actions;
+pub mod sinks;
+pub mod smoothing;
+pub mod spike_filter;
+pub mod suspend;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 pub mod trace_recorder;
+pub mod trace_summary;
 pub mod tui;
+pub mod units;
+pub mod validate;
+pub mod wal;
+pub mod work;
 
 pub mod utils {
+    pub mod cgroup;
     pub mod errors;
     pub mod logger;
+    pub mod pattern;
     pub mod psutils;
+    pub mod time;
     pub mod trace_rotation;
 }
 
 #[cfg(feature = "pyo3")]
 mod python;
+
+pub use config::EmtConfig;
+pub use energy_group::{EnergyCollector, EnergyGroup, EnergyRecord};
+pub use monitor::{Monitor, MonitorHandle};
+pub use utils::errors::{CollectorError, MonitoringError};
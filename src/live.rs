@@ -0,0 +1,228 @@
+/// Live Module
+///
+/// Client side of `emt live`: polls the `/snapshot` endpoint of a running
+/// headless exporter and turns consecutive snapshots into the power drawn by
+/// each device and by the busiest workloads, printed like `tail -f` as text
+/// lines or JSON Lines.
+use crate::config::MeasurementUnitsConfig;
+use crate::monitor::MetricsSnapshot;
+use crate::utils::time::ExportTimezone;
+use serde::Serialize;
+use std::time::Duration;
+use thiserror::Error;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Errors raised while following an exporter.
+#[derive(Debug, Error)]
+pub enum LiveError {
+    #[error("request failed: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("invalid snapshot: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+/// Polls the snapshots of the exporter at `base_url`, such as
+/// `http://127.0.0.1:9101`.
+pub struct SnapshotClient {
+    client: reqwest::Client,
+    url: String,
+}
+
+impl SnapshotClient {
+    pub fn new(base_url: &str) -> Result<Self, LiveError> {
+        Ok(Self {
+            client: reqwest::Client::builder()
+                .timeout(REQUEST_TIMEOUT)
+                .build()?,
+            url: format!("{}/snapshot", base_url.trim_end_matches('/')),
+        })
+    }
+
+    pub async fn fetch(&self) -> Result<MetricsSnapshot, LiveError> {
+        let body = self
+            .client
+            .get(&self.url)
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?;
+        Ok(serde_json::from_slice(&body)?)
+    }
+}
+
+/// Power between two snapshots.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LiveUpdate {
+    /// Unix milliseconds of the newer snapshot
+    pub timestamp: i64,
+    pub total_watts: f64,
+    pub cpu_watts: f64,
+    /// Absent when DRAM energy is not measured separately
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dram_watts: Option<f64>,
+    /// Absent without a GPU
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gpu_watts: Option<f64>,
+    /// Live workloads drawing the most power
+    pub top: Vec<LiveConsumer>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LiveConsumer {
+    pub root_pid: u32,
+    pub name: String,
+    pub user: String,
+    pub power_watts: f64,
+}
+
+impl LiveUpdate {
+    /// Power from `previous` to `current`, listing the `top` live workloads
+    /// by power. `None` when the exporter has not sampled in between.
+    pub fn between(
+        previous: &MetricsSnapshot,
+        current: &MetricsSnapshot,
+        top: usize,
+    ) -> Option<Self> {
+        let elapsed_seconds = (current.timestamp - previous.timestamp) as f64 / 1_000.0;
+        if elapsed_seconds <= 0.0 {
+            return None;
+        }
+        let energy = current.system_total.saturating_sub(&previous.system_total);
+        let mut consumers: Vec<_> = current
+            .workloads
+            .iter()
+            .filter(|workload| workload.is_live)
+            .map(|workload| LiveConsumer {
+                root_pid: workload.root_pid,
+                name: workload.name.clone(),
+                user: workload.user.clone(),
+                power_watts: workload.power_watts,
+            })
+            .collect();
+        consumers.sort_by(|a, b| {
+            b.power_watts
+                .total_cmp(&a.power_watts)
+                .then(a.root_pid.cmp(&b.root_pid))
+        });
+        consumers.truncate(top);
+
+        Some(Self {
+            timestamp: current.timestamp,
+            total_watts: energy.total() / elapsed_seconds,
+            cpu_watts: energy.cpu_joules / elapsed_seconds,
+            dram_watts: current
+                .sources
+                .reports_dram_energy()
+                .then(|| energy.dram_joules / elapsed_seconds),
+            gpu_watts: current
+                .gpu_available
+                .then(|| energy.gpu_joules / elapsed_seconds),
+            top: consumers,
+        })
+    }
+
+    /// One line with power in the configured unit.
+    pub fn render_text(&self, units: &MeasurementUnitsConfig, timezone: ExportTimezone) -> String {
+        let mut line = format!(
+            "{}  total {}  cpu {}",
+            timezone.format_millis(self.timestamp),
+            units.format_power(self.total_watts),
+            units.format_power(self.cpu_watts)
+        );
+        if let Some(watts) = self.dram_watts {
+            line.push_str(&format!("  dram {}", units.format_power(watts)));
+        }
+        if let Some(watts) = self.gpu_watts {
+            line.push_str(&format!("  gpu {}", units.format_power(watts)));
+        }
+        if !self.top.is_empty() {
+            let consumers: Vec<String> = self
+                .top
+                .iter()
+                .map(|consumer| {
+                    format!(
+                        "{}[{}] {}",
+                        consumer.name,
+                        consumer.root_pid,
+                        units.format_power(consumer.power_watts)
+                    )
+                })
+                .collect();
+            line.push_str(&format!("  | {}", consumers.join(", ")));
+        }
+        line
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::monitor::{DeviceEnergy, WorkloadSnapshot};
+
+    fn workload(root_pid: u32, name: &str, power_watts: f64, is_live: bool) -> WorkloadSnapshot {
+        WorkloadSnapshot {
+            root_pid,
+            group_id: format!("pid:{root_pid}"),
+            name: name.to_string(),
+            user: "alice".to_string(),
+            processes: Vec::new(),
+            is_live,
+            energy: DeviceEnergy::default(),
+            power_watts,
+            percentage_of_system: 0.0,
+            container: None,
+        }
+    }
+
+    fn snapshot(
+        timestamp: i64,
+        cpu_joules: f64,
+        workloads: Vec<WorkloadSnapshot>,
+    ) -> MetricsSnapshot {
+        MetricsSnapshot {
+            timestamp,
+            system_total: DeviceEnergy {
+                cpu_joules,
+                ..DeviceEnergy::default()
+            },
+            workloads,
+            ..MetricsSnapshot::default()
+        }
+    }
+
+    #[test]
+    fn update_reports_power_and_top_live_workloads() {
+        let previous = snapshot(1_700_000_000_000, 10.0, Vec::new());
+        let current = snapshot(
+            1_700_000_002_000,
+            50.0,
+            vec![
+                workload(10, "idle", 1.0, true),
+                workload(20, "train", 15.0, true),
+                workload(30, "exited", 30.0, false),
+                workload(40, "eval", 4.0, true),
+            ],
+        );
+
+        let update = LiveUpdate::between(&previous, &current, 2).unwrap();
+
+        assert_eq!(update.total_watts, 20.0);
+        assert_eq!(update.cpu_watts, 20.0);
+        assert_eq!(update.dram_watts, None);
+        assert_eq!(
+            update
+                .top
+                .iter()
+                .map(|consumer| consumer.name.as_str())
+                .collect::<Vec<_>>(),
+            ["train", "eval"]
+        );
+        assert_eq!(
+            update.render_text(&MeasurementUnitsConfig::default(), ExportTimezone::Utc),
+            "2023-11-14T22:13:22.000Z  total 20.00 Watts  cpu 20.00 Watts  | train[20] 15.00 Watts, eval[40] 4.000 Watts"
+        );
+        assert_eq!(LiveUpdate::between(&current, &current, 2), None);
+    }
+}
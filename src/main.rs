@@ -1,29 +1,78 @@
-use clap::{Parser, ValueEnum};
+use clap::{Parser, Subcommand, ValueEnum};
+use emt::budgets::{BudgetAction, CGROUP_ROOT, RunLimits};
 use emt::config::{EmtConfig, MeasurementUnitsConfig};
+use emt::container::ContainerInfo;
+use emt::convert::{
+    ConvertError, ConvertOptions, convert_trace, filter_trace, project_trace, resample_trace,
+    write_trace,
+};
+#[cfg(unix)]
+use emt::daemon::{DEFAULT_SOCKET_PATH, DaemonClient, Request, bind_socket, serve};
+use emt::device_labels::DeviceLabels;
+use emt::encryption::TraceCipher;
+use emt::energy_totals::EnergyTotals;
+use emt::live::{LiveError, LiveUpdate, SnapshotClient};
+use emt::merge::{MergeInput, merge_traces};
 use emt::metrics_sink::{MetricsSink, PrometheusSink, SharedPrometheusSink, prometheus_router};
 use emt::monitor::{
     DeviceEnergy, DeviceSources, MetricsSnapshot, Monitor, MonitorDiagnostics, MonitorHandle,
 };
+use emt::overhead::{OverheadOptions, measure_overhead};
+use emt::query::{Filter, TimeRange};
+use emt::remote::{AgentStream, RemoteHost, RemoteOptions, collect_remote};
+use emt::report::{ReportOptions, build_report, report_trace};
+use emt::run::{CollectorKind, RunOptions, list_collectors, record_trace, render_collectors};
+use emt::sandbox::{self, FilesystemEnforcement, SandboxPolicy};
+use emt::schema::SCHEMA_VERSION;
 use emt::tui::{self, App};
+use emt::utils::cgroup::{CgroupAttributor, CgroupTarget};
+use emt::utils::time::{self, ExportTimezone, parse_export_timestamp_micros};
+use emt::validate::{ValidateError, ValidationOptions, ValidationSource, validate_sources};
+use polars::prelude::{DataFrame, LazyFrame};
 use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
 use std::fs::File;
 use std::io::Write;
 use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
 const DEFAULT_BATCH_DURATION_SECS: u64 = 10;
 const DEFAULT_PROMETHEUS_PORT: u16 = 9101;
 const TUI_INPUT_POLL_INTERVAL: Duration = Duration::from_millis(500);
+/// Exit status of a batch run that exceeded `--max-energy-j` or
+/// `--max-avg-watts`.
+const EXIT_LIMIT_EXCEEDED: i32 = 3;
+/// Processes and users listed in the end-of-run summary.
+const RUN_SUMMARY_TOP: usize = 5;
 
 #[derive(Parser, Debug)]
 #[command(name = "emt")]
 #[command(about = "Monitor energy consumption of processes")]
+#[command(args_conflicts_with_subcommands = true)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Process ID to monitor (if not specified, monitors all root processes)
     #[arg(short, long)]
     pid: Option<u32>,
 
+    /// Cgroup v2 directory to monitor as a whole instead of processes; repeatable
+    #[arg(long, value_name = "PATH", conflicts_with = "pid")]
+    cgroup: Vec<PathBuf>,
+
+    /// systemd unit or slice to monitor through its cgroup, such as nginx.service; repeatable
+    #[arg(long, value_name = "UNIT", conflicts_with = "pid")]
+    unit: Vec<String>,
+
+    /// Cgroup v2 directory whose child cgroups, such as the services of system.slice or the
+    /// containers of kubepods.slice, are each monitored as a whole, including ones created later;
+    /// repeatable
+    #[arg(long, value_name = "PATH", conflicts_with = "pid")]
+    cgroup_children: Vec<PathBuf>,
+
     /// Duration to monitor in seconds (JSON output mode only)
     #[arg(short, long)]
     duration: Option<u64>,
@@ -60,9 +109,517 @@ struct Args {
     #[arg(long, default_value = "0.0.0.0")]
     bind: IpAddr,
 
+    /// Also serve the gRPC API on this TCP port in headless mode
+    #[arg(long = "grpc-port", requires = "headless")]
+    grpc_port: Option<u16>,
+
+    /// Also serve the HTTP API on this TCP port in headless mode
+    #[arg(long = "http-port", requires = "headless")]
+    http_port: Option<u16>,
+
     /// Run once and write JSON results to PATH
     #[arg(long = "json-out", value_name = "PATH", conflicts_with_all = ["tui", "headless"])]
     json_out: Option<String>,
+
+    /// Exit with status 3 when the run uses more than JOULES in total
+    #[arg(long = "max-energy-j", value_name = "JOULES", requires = "json_out", value_parser = parse_limit)]
+    max_energy_j: Option<f64>,
+
+    /// Exit with status 3 when the run's average power exceeds WATTS
+    #[arg(long = "max-avg-watts", value_name = "WATTS", requires = "json_out", value_parser = parse_limit)]
+    max_avg_watts: Option<f64>,
+
+    /// Write whether the run stayed within its limits as JSON to PATH
+    #[arg(long = "verdict-out", value_name = "PATH", requires = "json_out")]
+    verdict_out: Option<String>,
+
+    /// Write a Markdown summary of the run, for job summaries or tickets, to PATH
+    #[arg(long = "markdown-out", value_name = "PATH", requires = "json_out")]
+    markdown_out: Option<String>,
+
+    /// Restrict filesystem and syscall access once the exporter is initialized
+    #[arg(long, requires = "headless")]
+    sandbox: bool,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Convert a trace between CSV, Parquet, JSON Lines, Arrow IPC and SQLite
+    #[command(visible_alias = "export")]
+    Convert(ConvertArgs),
+    /// Merge traces from several hosts into one, with a `host` column
+    Merge(MergeArgs),
+    /// Summarize a trace: energy per device, top processes and users,
+    /// regions, emissions and cost
+    Report(ReportArgs),
+    /// Print the power of a running headless exporter
+    Live(LiveArgs),
+    /// Measure hosts over SSH and merge their traces, with a `host` column
+    Remote(RemoteArgs),
+    /// Stream this machine's trace to stdout as JSON Lines, for `emt remote`
+    #[command(hide = true)]
+    Agent(AgentArgs),
+    /// Read two collectors of the same domain side by side and report how
+    /// far they disagree
+    Validate(ValidateArgs),
+    /// Read the configured collectors at a sweep of rates and report what
+    /// collection costs EMT itself at each
+    Overhead(OverheadArgs),
+    /// Record a trace of chosen collectors for a set time and write it to a
+    /// file
+    Run(RunArgs),
+    /// Print which collectors can be read on this host
+    ListCollectors(ListCollectorsArgs),
+    /// Monitor every process and answer session, query and snapshot
+    /// requests on a Unix socket
+    #[cfg(unix)]
+    Daemon(DaemonArgs),
+    /// Send a request to a running daemon and print its JSON result
+    #[cfg(unix)]
+    Ctl(CtlArgs),
+}
+
+#[derive(clap::Args, Debug)]
+struct ConvertArgs {
+    /// Trace file, or a directory of trace segments or Parquet parts
+    input: PathBuf,
+
+    /// Output file; the format follows its extension (.csv, .parquet,
+    /// .jsonl, .arrow, .sqlite)
+    output: PathBuf,
+
+    #[command(flatten)]
+    selection: SelectionArgs,
+}
+
+/// Rows and columns an export keeps, and how finely.
+#[derive(clap::Args, Debug)]
+struct SelectionArgs {
+    /// Keep rows at or after TIME (RFC 3339 or Unix milliseconds)
+    #[arg(long, value_name = "TIME", value_parser = parse_time_millis)]
+    start: Option<i64>,
+
+    /// Keep rows at or before TIME (RFC 3339 or Unix milliseconds)
+    #[arg(long, value_name = "TIME", value_parser = parse_time_millis)]
+    end: Option<i64>,
+
+    /// Keep devices matching PATTERN (`*` and `?` globs; repeatable)
+    #[arg(long = "device", value_name = "PATTERN")]
+    devices: Vec<String>,
+
+    /// Keep rows of process PID (repeatable)
+    #[arg(long = "pid", value_name = "PID")]
+    pids: Vec<u32>,
+
+    /// Keep rows of processes run by USER (repeatable; needs joined traces)
+    #[arg(long = "user", value_name = "USER")]
+    users: Vec<String>,
+
+    /// Keep rows of processes with task TASK (repeatable; needs joined traces)
+    #[arg(long = "task", value_name = "TASK")]
+    tasks: Vec<String>,
+
+    /// Write only these columns, in this order
+    #[arg(long, value_name = "NAME,...", value_delimiter = ',')]
+    columns: Vec<String>,
+
+    /// Sum energy and average utilization into one row per process and
+    /// device per INTERVAL (such as 10s or 1m)
+    #[arg(long, visible_alias = "every", value_name = "INTERVAL", value_parser = parse_interval)]
+    resample: Option<Duration>,
+}
+
+impl SelectionArgs {
+    fn range(&self) -> TimeRange {
+        TimeRange {
+            start: self.start,
+            end: self.end,
+        }
+    }
+
+    fn filter(&self) -> Filter {
+        Filter {
+            devices: self.devices.clone(),
+            pids: self.pids.clone(),
+            users: self.users.clone(),
+            tasks: self.tasks.clone(),
+        }
+    }
+
+    /// The rows and columns of `trace` to export, resampled, with device
+    /// aliases and labels.
+    fn apply(
+        &self,
+        trace: DataFrame,
+        device_labels: &DeviceLabels,
+    ) -> Result<DataFrame, ConvertError> {
+        let mut trace = filter_trace(trace, self.range(), &self.filter())?;
+        if let Some(every) = self.resample {
+            trace = resample_trace(trace, every)?;
+        }
+        project_trace(device_labels.apply_to_trace(trace)?, &self.columns)
+    }
+}
+
+fn parse_limit(value: &str) -> Result<f64, String> {
+    value
+        .parse::<f64>()
+        .ok()
+        .filter(|limit| limit.is_finite() && *limit >= 0.0)
+        .ok_or_else(|| format!("invalid limit '{value}'"))
+}
+
+fn parse_time_millis(value: &str) -> Result<i64, String> {
+    parse_export_timestamp_micros(value)
+        .map(|micros| micros.div_euclid(1000))
+        .ok_or_else(|| format!("invalid time '{value}'"))
+}
+
+fn parse_interval(value: &str) -> Result<Duration, String> {
+    time::parse_interval(value).ok_or_else(|| format!("invalid interval '{value}'"))
+}
+
+impl ConvertArgs {
+    fn options(&self, timezone: ExportTimezone) -> ConvertOptions {
+        ConvertOptions {
+            range: self.selection.range(),
+            filter: self.selection.filter(),
+            resample: self.selection.resample,
+            columns: self.selection.columns.clone(),
+            timezone,
+            device_labels: DeviceLabels::default(),
+        }
+    }
+}
+
+#[derive(clap::Args, Debug)]
+struct MergeArgs {
+    /// Trace directory or file of each host; HOST overrides its host.json
+    #[arg(required = true, value_name = "[HOST=]PATH", value_parser = parse_merge_input)]
+    inputs: Vec<MergeInput>,
+
+    /// Output file; the format follows its extension
+    #[arg(short, long, value_name = "PATH")]
+    output: PathBuf,
+
+    /// Milliseconds HOST's clock runs ahead of the reference (repeatable;
+    /// overrides host.json)
+    #[arg(long = "clock-offset", value_name = "HOST=MS", value_parser = parse_clock_offset)]
+    clock_offsets: Vec<(String, i64)>,
+
+    #[command(flatten)]
+    selection: SelectionArgs,
+}
+
+fn parse_merge_input(value: &str) -> Result<MergeInput, String> {
+    match value.split_once('=') {
+        Some((host, path)) if !host.is_empty() && !host.contains(std::path::MAIN_SEPARATOR) => {
+            Ok(MergeInput {
+                path: path.into(),
+                host: Some(host.to_string()),
+            })
+        }
+        _ => Ok(MergeInput {
+            path: value.into(),
+            host: None,
+        }),
+    }
+}
+
+fn parse_clock_offset(value: &str) -> Result<(String, i64), String> {
+    value
+        .split_once('=')
+        .and_then(|(host, millis)| Some((host.to_string(), millis.trim().parse().ok()?)))
+        .ok_or_else(|| format!("expected HOST=MS, got '{value}'"))
+}
+
+#[derive(clap::Args, Debug)]
+struct ReportArgs {
+    /// Trace file, or a directory of trace segments or Parquet parts
+    input: PathBuf,
+
+    /// Number of processes and users listed
+    #[arg(long, default_value_t = 10, value_name = "N")]
+    top: usize,
+
+    /// Print the report as JSON
+    #[arg(long)]
+    json: bool,
+
+    /// Print the report as Markdown
+    #[arg(long, conflicts_with = "json")]
+    markdown: bool,
+}
+
+#[derive(clap::Args, Debug)]
+struct LiveArgs {
+    /// Exporter to read [default: http://127.0.0.1:9101]
+    #[arg(long, value_name = "URL")]
+    url: Option<String>,
+
+    /// Keep printing updates until interrupted
+    #[arg(short, long)]
+    follow: bool,
+
+    /// Time between updates, such as 500ms or 2s
+    #[arg(long, value_name = "INTERVAL", default_value = "1s", value_parser = parse_interval)]
+    interval: Duration,
+
+    /// Number of workloads listed
+    #[arg(long, default_value_t = 5, value_name = "N")]
+    top: usize,
+
+    /// Print updates as JSON Lines
+    #[arg(long)]
+    jsonl: bool,
+}
+
+impl LiveArgs {
+    fn url(&self) -> String {
+        self.url
+            .clone()
+            .unwrap_or_else(|| format!("http://127.0.0.1:{DEFAULT_PROMETHEUS_PORT}"))
+    }
+}
+
+#[derive(clap::Args, Debug)]
+struct RemoteArgs {
+    /// SSH destination to measure, such as user@node (repeatable)
+    #[arg(long = "host", required = true, value_name = "DESTINATION")]
+    hosts: Vec<String>,
+
+    /// Output file; the format follows its extension
+    #[arg(short, long, value_name = "PATH")]
+    output: PathBuf,
+
+    /// Seconds to monitor each host
+    #[arg(short, long, default_value_t = DEFAULT_BATCH_DURATION_SECS)]
+    duration: u64,
+
+    /// Collection rate in Hz on the hosts (overrides their config files)
+    #[arg(short, long)]
+    rate: Option<f64>,
+
+    /// emt executable on the hosts
+    #[arg(long = "emt-path", value_name = "PATH", default_value = "emt")]
+    emt_path: String,
+
+    /// Copy this emt executable to each host for the run instead
+    #[arg(long, conflicts_with = "emt_path")]
+    upload: bool,
+
+    /// Extra ssh option, such as Port=2222 (repeatable)
+    #[arg(long = "ssh-option", value_name = "OPTION")]
+    ssh_options: Vec<String>,
+
+    #[command(flatten)]
+    selection: SelectionArgs,
+}
+
+impl RemoteArgs {
+    fn options(&self) -> RemoteOptions {
+        RemoteOptions {
+            duration: Duration::from_secs(self.duration),
+            rate_hz: self.rate,
+            agent_path: self.emt_path.clone(),
+            upload: self.upload,
+            ssh_options: self.ssh_options.clone(),
+        }
+    }
+}
+
+#[derive(clap::Args, Debug)]
+struct AgentArgs {
+    /// Seconds to monitor
+    #[arg(short, long, default_value_t = DEFAULT_BATCH_DURATION_SECS)]
+    duration: u64,
+
+    /// Collection rate in Hz (overrides config file)
+    #[arg(short, long)]
+    rate: Option<f64>,
+
+    /// Time between batches, such as 500ms or 2s
+    #[arg(long, value_name = "INTERVAL", default_value = "1s", value_parser = parse_interval)]
+    interval: Duration,
+}
+
+#[cfg(unix)]
+#[derive(clap::Args, Debug)]
+struct DaemonArgs {
+    /// Control socket to listen on
+    #[arg(long, value_name = "PATH", default_value = DEFAULT_SOCKET_PATH)]
+    socket: PathBuf,
+
+    /// Permissions of the control socket, in octal; 660 lets the socket's
+    /// group send requests
+    #[arg(long, value_name = "MODE", default_value = "660", value_parser = parse_socket_mode)]
+    socket_mode: u32,
+
+    /// Collection rate in Hz (overrides config file)
+    #[arg(short, long)]
+    rate: Option<f64>,
+}
+
+#[cfg(unix)]
+fn parse_socket_mode(value: &str) -> Result<u32, String> {
+    u32::from_str_radix(value, 8)
+        .ok()
+        .filter(|mode| *mode <= 0o777)
+        .ok_or_else(|| format!("'{value}' is not an octal file mode such as 660"))
+}
+
+#[cfg(unix)]
+#[derive(clap::Args, Debug)]
+struct CtlArgs {
+    /// Control socket of the daemon
+    #[arg(long, value_name = "PATH", default_value = DEFAULT_SOCKET_PATH)]
+    socket: PathBuf,
+
+    #[command(subcommand)]
+    request: CtlRequest,
+}
+
+#[cfg(unix)]
+#[derive(Subcommand, Debug)]
+enum CtlRequest {
+    /// Start measuring processes and print the session id
+    StartSession {
+        /// Processes to measure, each with its children when it roots a
+        /// workload
+        #[arg(long, value_name = "PID,...", value_delimiter = ',', required = true)]
+        pids: Vec<u32>,
+
+        /// Name reported back when the session stops
+        #[arg(long)]
+        name: Option<String>,
+    },
+    /// Stop a session and print the energy its processes used
+    StopSession {
+        /// Id printed by start-session
+        session: u64,
+    },
+    /// Print the energy of a process since the daemon started
+    Query {
+        #[arg(long)]
+        pid: u32,
+    },
+    /// Print the daemon's current metrics snapshot
+    Snapshot,
+}
+
+#[cfg(unix)]
+impl CtlRequest {
+    fn request(&self) -> Request {
+        match self {
+            Self::StartSession { pids, name } => Request::StartSession {
+                pids: pids.clone(),
+                name: name.clone(),
+            },
+            Self::StopSession { session } => Request::StopSession { session: *session },
+            Self::Query { pid } => Request::Query { pid: *pid },
+            Self::Snapshot => Request::Snapshot,
+        }
+    }
+}
+
+#[derive(clap::Args, Debug)]
+struct ValidateArgs {
+    /// First source: a CPU energy backend (powercap, perf_event, msr,
+    /// model) or a configured meter (exec:NAME, http:NAME, bmc:NAME)
+    #[arg(value_name = "A", value_parser = parse_validation_source)]
+    source_a: ValidationSource,
+
+    /// Second source, compared against the first
+    #[arg(value_name = "B", value_parser = parse_validation_source)]
+    source_b: ValidationSource,
+
+    /// How long to compare, such as 30s or 5m
+    #[arg(short, long, value_name = "DURATION", default_value = "60s", value_parser = parse_interval)]
+    duration: Duration,
+
+    /// Time between reads; differences are compared per interval
+    #[arg(long, value_name = "INTERVAL", default_value = "1s", value_parser = parse_interval)]
+    interval: Duration,
+
+    /// Sum A's devices matching PATTERN into one domain instead of pairing
+    /// devices by name (repeatable)
+    #[arg(long = "a-device", value_name = "PATTERN")]
+    devices_a: Vec<String>,
+
+    /// Sum B's devices matching PATTERN into one domain (repeatable)
+    #[arg(long = "b-device", value_name = "PATTERN")]
+    devices_b: Vec<String>,
+
+    /// Print the report as JSON
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(clap::Args, Debug)]
+struct OverheadArgs {
+    /// Sampling rates to measure, in Hz
+    #[arg(
+        long,
+        value_name = "HZ,...",
+        value_delimiter = ',',
+        default_value = "1,10,50,100"
+    )]
+    rates: Vec<f64>,
+
+    /// How long to sample at each rate, such as 10s or 1m
+    #[arg(short, long, value_name = "DURATION", default_value = "10s", value_parser = parse_interval)]
+    duration: Duration,
+
+    /// Print the report as JSON
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(clap::Args, Debug)]
+struct RunArgs {
+    /// Collectors to read: cpu (or rapl), nvidia, amd, intel, jetson, meters,
+    /// network, storage; every available one when omitted
+    #[arg(long = "collector", value_name = "NAME,...", value_delimiter = ',')]
+    collectors: Vec<CollectorKind>,
+
+    /// Collection rate, in Hz
+    #[arg(long, value_name = "HZ", default_value_t = 10.0)]
+    rate: f64,
+
+    /// Attribute energy to these processes and the children they fork
+    #[arg(long, value_name = "PID,...", value_delimiter = ',')]
+    pids: Vec<u32>,
+
+    /// How long to record, such as 60s or 5m
+    #[arg(short, long, value_name = "DURATION", value_parser = parse_interval)]
+    duration: Duration,
+
+    /// Output file; the format follows its extension (.csv, .parquet,
+    /// .jsonl, .arrow, .sqlite)
+    #[arg(short, long, value_name = "PATH")]
+    output: PathBuf,
+}
+
+#[derive(clap::Args, Debug)]
+struct ListCollectorsArgs {
+    /// Print the collectors as JSON
+    #[arg(long)]
+    json: bool,
+}
+
+impl ValidateArgs {
+    fn options(&self) -> ValidationOptions {
+        ValidationOptions {
+            duration: self.duration,
+            interval: self.interval,
+            devices_a: self.devices_a.clone(),
+            devices_b: self.devices_b.clone(),
+        }
+    }
+}
+
+fn parse_validation_source(value: &str) -> Result<ValidationSource, String> {
+    value.parse().map_err(|e: ValidateError| e.to_string())
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
@@ -94,6 +651,9 @@ fn validate_args(args: &Args) -> Result<(), &'static str> {
     if selected_mode(args) == Mode::Headless && args.export != Some(ExportMode::Prometheus) {
         return Err("--headless requires --export prometheus");
     }
+    if args.grpc_port.is_some() && !cfg!(feature = "grpc") {
+        return Err("--grpc-port requires a build with the grpc feature");
+    }
     Ok(())
 }
 
@@ -111,7 +671,11 @@ mod tests {
     #[test]
     fn cli_output_uses_configured_units_and_unit_neutral_fields() {
         let args = Args {
+            command: None,
             pid: Some(123),
+            cgroup: Vec::new(),
+            unit: Vec::new(),
+            cgroup_children: Vec::new(),
             duration: Some(10),
             rate: None,
             scan_interval: None,
@@ -121,7 +685,14 @@ mod tests {
             export: None,
             port: DEFAULT_PROMETHEUS_PORT,
             bind: "0.0.0.0".parse().unwrap(),
+            grpc_port: None,
+            http_port: None,
             json_out: Some("results.json".to_string()),
+            sandbox: false,
+            max_energy_j: None,
+            max_avg_watts: None,
+            verdict_out: None,
+            markdown_out: None,
         };
         let units = MeasurementUnitsConfig {
             energy: "kWh".to_string(),
@@ -154,9 +725,17 @@ mod tests {
                 },
                 power_watts: 360.0,
                 percentage_of_system: 100.0,
+                container: None,
             }],
             unattributed: DeviceEnergy::default(),
             tracked_pids: vec![123],
+            totals: EnergyTotals::from_devices(
+                &[],
+                &BTreeMap::from([
+                    ("rapl:socket:0:package".to_string(), 2_700.0),
+                    ("rapl:system:dram".to_string(), 900.0),
+                ]),
+            ),
             ..MetricsSnapshot::default()
         };
 
@@ -168,6 +747,7 @@ mod tests {
         assert!((output.power - 360_000.0).abs() < 1e-9);
         assert!((output.devices.cpu - 0.00075).abs() < 1e-9);
         assert!((output.devices.dram.unwrap() - 0.00025).abs() < 1e-9);
+        assert!((output.components["rapl:system:dram"] - 0.00025).abs() < 1e-9);
         assert_eq!(output.workloads[0].root_pid, 123);
         assert_eq!(output.workloads[0].group_id, "pid:123");
         assert_eq!(output.workloads[0].name, "work");
@@ -180,7 +760,11 @@ mod tests {
     #[test]
     fn cli_output_omits_dram_device_when_dram_is_included_in_package() {
         let args = Args {
+            command: None,
             pid: Some(123),
+            cgroup: Vec::new(),
+            unit: Vec::new(),
+            cgroup_children: Vec::new(),
             duration: Some(10),
             rate: None,
             scan_interval: None,
@@ -190,7 +774,14 @@ mod tests {
             export: None,
             port: DEFAULT_PROMETHEUS_PORT,
             bind: "0.0.0.0".parse().unwrap(),
+            grpc_port: None,
+            http_port: None,
             json_out: Some("results.json".to_string()),
+            sandbox: false,
+            max_energy_j: None,
+            max_avg_watts: None,
+            verdict_out: None,
+            markdown_out: None,
         };
         let snapshot = MetricsSnapshot {
             sources: DeviceSources {
@@ -250,6 +841,7 @@ mod tests {
                 },
                 power_watts: 2.0,
                 percentage_of_system: 10.0,
+                container: None,
             }],
             unattributed: DeviceEnergy {
                 cpu_joules: 38.0,
@@ -260,8 +852,12 @@ mod tests {
             ..MetricsSnapshot::default()
         };
 
-        let value = serde_json::to_value(build_snapshot_output(&snapshot)).unwrap();
+        let value =
+            serde_json::to_value(build_snapshot_output(&snapshot, ExportTimezone::Utc)).unwrap();
 
+        assert_eq!(value["schema_version"], SCHEMA_VERSION);
+        assert_eq!(value["time"], "1970-01-01T00:00:00.000Z");
+        assert_eq!(emt::schema::migrate_json(value.clone()).unwrap(), value);
         assert_eq!(value["sources"]["dram"], "included_in_package");
         assert!(value["system_total"].get("dram_joules").is_none());
         assert!(value["unattributed"].get("dram_joules").is_none());
@@ -289,7 +885,8 @@ mod tests {
             ..MetricsSnapshot::default()
         };
 
-        let value = serde_json::to_value(build_snapshot_output(&snapshot)).unwrap();
+        let value =
+            serde_json::to_value(build_snapshot_output(&snapshot, ExportTimezone::Utc)).unwrap();
 
         assert_eq!(value["sources"]["dram"], "measured");
         assert_eq!(value["system_total"]["dram_joules"], 9.0);
@@ -298,7 +895,11 @@ mod tests {
     #[test]
     fn cli_rate_override_wins_over_loaded_config() {
         let args = Args {
+            command: None,
             pid: None,
+            cgroup: Vec::new(),
+            unit: Vec::new(),
+            cgroup_children: Vec::new(),
             duration: None,
             rate: Some(5.0),
             scan_interval: None,
@@ -308,7 +909,14 @@ mod tests {
             export: None,
             port: DEFAULT_PROMETHEUS_PORT,
             bind: "0.0.0.0".parse().unwrap(),
+            grpc_port: None,
+            http_port: None,
             json_out: None,
+            sandbox: false,
+            max_energy_j: None,
+            max_avg_watts: None,
+            verdict_out: None,
+            markdown_out: None,
         };
         let mut config = EmtConfig::default();
         config.collection.rate_hz = 0.0;
@@ -407,6 +1015,38 @@ mod tests {
         assert!(validate_args(&args).is_ok());
     }
 
+    #[test]
+    fn cli_sandbox_requires_headless_mode() {
+        let result = Args::try_parse_from(["emt", "--sandbox"]);
+        assert!(result.is_err());
+
+        let args = Args::parse_from(["emt", "--headless", "--export", "prometheus", "--sandbox"]);
+        let config = EmtConfig::default();
+        assert!(sandbox_requested(&args, &config));
+    }
+
+    #[test]
+    fn sandbox_policy_allows_snapshot_output_directory() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let snapshot = dir.path().join("snapshot.json");
+        let snapshot = snapshot.to_str().unwrap();
+        let args = Args::parse_from([
+            "emt",
+            "--headless",
+            "--export",
+            "prometheus",
+            "--snapshot-out",
+            snapshot,
+        ]);
+        let mut config = EmtConfig::default();
+        config.sandbox.enabled = true;
+
+        let policy = sandbox_policy(&args, &config);
+
+        assert!(sandbox_requested(&args, &config));
+        assert!(policy.write_paths.contains(&dir.path().to_path_buf()));
+    }
+
     #[test]
     fn cli_requires_headless_for_export() {
         let result = Args::try_parse_from(["emt", "--export", "prometheus"]);
@@ -435,10 +1075,267 @@ mod tests {
 
         assert!(validate_args(&args).is_err());
     }
+
+    #[test]
+    fn cli_parses_convert_options() {
+        let args = Args::parse_from([
+            "emt",
+            "convert",
+            "traces/cpu",
+            "cpu.parquet",
+            "--start",
+            "2023-11-14T22:13:20Z",
+            "--device",
+            "rapl:*",
+            "--pid",
+            "42",
+            "--resample",
+            "500ms",
+            "--columns",
+            "timestamp,pid,energy",
+        ]);
+
+        let Some(Command::Convert(convert)) = args.command else {
+            panic!("expected the convert subcommand");
+        };
+        let options = convert.options(ExportTimezone::Utc);
+        assert_eq!(options.range.start, Some(1_700_000_000_000));
+        assert_eq!(options.filter.devices, ["rapl:*"]);
+        assert_eq!(options.filter.pids, [42]);
+        assert_eq!(options.resample, Some(Duration::from_millis(500)));
+        assert_eq!(options.columns, ["timestamp", "pid", "energy"]);
+        assert!(Args::try_parse_from(["emt", "--tui", "convert", "a.csv", "b.csv"]).is_err());
+        assert!(
+            Args::try_parse_from(["emt", "convert", "a.csv", "b.csv", "--every", "0"]).is_err()
+        );
+    }
+
+    #[test]
+    fn cli_parses_merge_inputs_and_clock_offsets() {
+        let args = Args::parse_from([
+            "emt",
+            "merge",
+            "node-a=/data/a",
+            "/data/b",
+            "-o",
+            "merged.parquet",
+            "--clock-offset",
+            "node-b=-12",
+            "--user",
+            "alice",
+        ]);
+
+        let Some(Command::Merge(merge)) = args.command else {
+            panic!("expected the merge subcommand");
+        };
+        assert_eq!(merge.inputs[0].host.as_deref(), Some("node-a"));
+        assert_eq!(merge.inputs[0].path, Path::new("/data/a"));
+        assert_eq!(merge.inputs[1].host, None);
+        assert_eq!(merge.clock_offsets, [("node-b".to_string(), -12)]);
+        assert_eq!(merge.selection.filter().users, ["alice"]);
+        assert!(Args::try_parse_from(["emt", "merge", "-o", "merged.parquet"]).is_err());
+    }
+
+    #[test]
+    fn cli_parses_report_options() {
+        let args = Args::parse_from(["emt", "report", "traces", "--top", "3", "--json"]);
+
+        let Some(Command::Report(report)) = args.command else {
+            panic!("expected the report subcommand");
+        };
+        assert_eq!(report.input, Path::new("traces"));
+        assert_eq!(report.top, 3);
+        assert!(report.json);
+        let Some(Command::Report(report)) = Args::parse_from(["emt", "report", "t.csv"]).command
+        else {
+            panic!("expected the report subcommand");
+        };
+        assert_eq!(report.top, 10);
+        assert!(!report.json);
+        assert!(Args::try_parse_from(["emt", "report", "t.csv", "--json", "--markdown"]).is_err());
+    }
+
+    #[test]
+    fn cli_cgroup_targets_repeat_and_exclude_pid() {
+        let args = Args::parse_from([
+            "emt",
+            "--cgroup",
+            "/sys/fs/cgroup/system.slice/nginx.service",
+            "--cgroup",
+            "user.slice",
+        ]);
+
+        assert_eq!(
+            args.cgroup,
+            [
+                PathBuf::from("/sys/fs/cgroup/system.slice/nginx.service"),
+                PathBuf::from("user.slice")
+            ]
+        );
+        assert!(Args::try_parse_from(["emt", "--pid", "1", "--cgroup", "user.slice"]).is_err());
+        let args = Args::parse_from(["emt", "--unit", "nginx.service", "--cgroup", "user.slice"]);
+        assert_eq!(args.unit, ["nginx.service"]);
+        assert!(Args::try_parse_from(["emt", "--pid", "1", "--unit", "nginx.service"]).is_err());
+        let args = Args::parse_from(["emt", "--cgroup-children", "kubepods.slice"]);
+        assert_eq!(args.cgroup_children, [PathBuf::from("kubepods.slice")]);
+        assert!(
+            Args::try_parse_from(["emt", "--pid", "1", "--cgroup-children", "system.slice"])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn cli_run_limits_require_json_output() {
+        let args = Args::parse_from([
+            "emt",
+            "--json-out",
+            "run.json",
+            "--max-energy-j",
+            "500",
+            "--max-avg-watts",
+            "45.5",
+            "--verdict-out",
+            "verdict.json",
+        ]);
+
+        assert_eq!(args.max_energy_j, Some(500.0));
+        assert_eq!(args.max_avg_watts, Some(45.5));
+        assert_eq!(args.verdict_out.as_deref(), Some("verdict.json"));
+        assert!(Args::try_parse_from(["emt", "--max-energy-j", "500"]).is_err());
+        assert!(Args::try_parse_from(["emt", "--markdown-out", "summary.md"]).is_err());
+        assert!(
+            Args::try_parse_from(["emt", "--json-out", "r.json", "--max-avg-watts", "-1"]).is_err()
+        );
+    }
+
+    #[test]
+    fn cli_parses_live_options() {
+        let args = Args::parse_from(["emt", "live", "-f", "--interval", "2.5", "--jsonl"]);
+
+        let Some(Command::Live(live)) = args.command else {
+            panic!("expected the live subcommand");
+        };
+        assert!(live.follow);
+        assert!(live.jsonl);
+        assert_eq!(live.interval, Duration::from_millis(2500));
+        assert_eq!(live.top, 5);
+        assert_eq!(live.url(), "http://127.0.0.1:9101");
+    }
+
+    #[test]
+    fn cli_parses_validate_sources_and_device_patterns() {
+        let args = Args::parse_from([
+            "emt",
+            "validate",
+            "powercap",
+            "exec:ipmi",
+            "--duration",
+            "5m",
+            "--a-device",
+            "rapl:*:package",
+            "--b-device",
+            "exec:ipmi:cpu*",
+        ]);
+
+        let Some(Command::Validate(validate)) = args.command else {
+            panic!("expected the validate subcommand");
+        };
+        assert_eq!(
+            validate.source_b,
+            ValidationSource::Exec("ipmi".to_string())
+        );
+        assert_eq!(validate.duration, Duration::from_secs(300));
+        assert_eq!(validate.interval, Duration::from_secs(1));
+        assert_eq!(validate.options().devices_a, ["rapl:*:package"]);
+        assert!(Args::try_parse_from(["emt", "validate", "powercap", "rapl"]).is_err());
+    }
+
+    #[test]
+    fn cli_parses_overhead_rate_sweep() {
+        let args = Args::parse_from(["emt", "overhead", "--rates", "5,20", "-d", "3s"]);
+
+        let Some(Command::Overhead(overhead)) = args.command else {
+            panic!("expected the overhead subcommand");
+        };
+        assert_eq!(overhead.rates, [5.0, 20.0]);
+        assert_eq!(overhead.duration, Duration::from_secs(3));
+
+        let args = Args::parse_from(["emt", "overhead"]);
+        let Some(Command::Overhead(overhead)) = args.command else {
+            panic!("expected the overhead subcommand");
+        };
+        assert_eq!(overhead.rates, [1.0, 10.0, 50.0, 100.0]);
+    }
+
+    #[test]
+    fn cli_parses_run_list_collectors_and_export() {
+        let args = Args::parse_from([
+            "emt",
+            "run",
+            "--collector",
+            "rapl,nvidia",
+            "--rate",
+            "5",
+            "--pids",
+            "123,456",
+            "--duration",
+            "60s",
+            "--output",
+            "trace.parquet",
+        ]);
+
+        let Some(Command::Run(run)) = args.command else {
+            panic!("expected the run subcommand");
+        };
+        assert_eq!(run.collectors, [CollectorKind::Cpu, CollectorKind::Nvidia]);
+        assert_eq!(run.rate, 5.0);
+        assert_eq!(run.pids, [123, 456]);
+        assert_eq!(run.duration, Duration::from_secs(60));
+        assert_eq!(run.output, PathBuf::from("trace.parquet"));
+        assert!(Args::try_parse_from(["emt", "run", "-o", "t.csv"]).is_err());
+
+        let args = Args::parse_from(["emt", "list-collectors", "--json"]);
+        assert!(matches!(
+            args.command,
+            Some(Command::ListCollectors(ListCollectorsArgs { json: true }))
+        ));
+        let args = Args::parse_from(["emt", "export", "trace.csv", "trace.parquet"]);
+        assert!(matches!(args.command, Some(Command::Convert(_))));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn cli_parses_daemon_and_ctl_requests() {
+        let args = Args::parse_from(["emt", "daemon", "--socket-mode", "600"]);
+        let Some(Command::Daemon(daemon)) = args.command else {
+            panic!("expected the daemon subcommand");
+        };
+        assert_eq!(daemon.socket, PathBuf::from(DEFAULT_SOCKET_PATH));
+        assert_eq!(daemon.socket_mode, 0o600);
+        assert!(Args::try_parse_from(["emt", "daemon", "--socket-mode", "rw"]).is_err());
+
+        let args = Args::parse_from(["emt", "ctl", "start-session", "--pids", "1,2"]);
+        let Some(Command::Ctl(ctl)) = args.command else {
+            panic!("expected the ctl subcommand");
+        };
+        assert_eq!(
+            ctl.request.request(),
+            Request::StartSession {
+                pids: vec![1, 2],
+                name: None,
+            }
+        );
+        let args = Args::parse_from(["emt", "ctl", "query", "--pid", "42"]);
+        let Some(Command::Ctl(ctl)) = args.command else {
+            panic!("expected the ctl subcommand");
+        };
+        assert_eq!(ctl.request.request(), Request::Query { pid: 42 });
+    }
 }
 
 #[derive(Serialize)]
 struct CliOutput {
+    schema_version: u32,
     pid: Option<u32>,
     duration_seconds: f64,
     total_energy: f64,
@@ -446,6 +1343,8 @@ struct CliOutput {
     power: f64,
     power_unit: String,
     devices: DeviceBreakdown,
+    /// Non-overlapping parts of `total_energy`
+    components: BTreeMap<String, f64>,
     workloads: Vec<WorkloadOutput>,
 }
 
@@ -470,12 +1369,17 @@ struct WorkloadOutput {
 
 #[derive(Serialize)]
 struct SnapshotOutput<'a> {
+    schema_version: u32,
     timestamp: i64,
+    time: String,
     gpu_available: bool,
     sources: &'a DeviceSources,
     system_total: SnapshotDeviceEnergy,
     workloads: Vec<SnapshotWorkloadOutput<'a>>,
     unattributed: SnapshotDeviceEnergy,
+    auxiliary: &'a BTreeMap<String, f64>,
+    devices: &'a BTreeMap<String, f64>,
+    totals: &'a EnergyTotals,
     tracked_pids: &'a [u32],
     diagnostics: &'a MonitorDiagnostics,
 }
@@ -499,6 +1403,8 @@ struct SnapshotWorkloadOutput<'a> {
     energy: SnapshotDeviceEnergy,
     power_watts: f64,
     percentage_of_system: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    container: Option<&'a ContainerInfo>,
 }
 
 #[derive(Serialize)]
@@ -532,7 +1438,10 @@ impl SnapshotDeviceEnergy {
     }
 }
 
-fn build_snapshot_output(snapshot: &MetricsSnapshot) -> SnapshotOutput<'_> {
+fn build_snapshot_output(
+    snapshot: &MetricsSnapshot,
+    timezone: ExportTimezone,
+) -> SnapshotOutput<'_> {
     let sources = &snapshot.sources;
     let workloads = snapshot
         .workloads
@@ -556,16 +1465,22 @@ fn build_snapshot_output(snapshot: &MetricsSnapshot) -> SnapshotOutput<'_> {
             energy: SnapshotDeviceEnergy::from_energy(&workload.energy, sources),
             power_watts: workload.power_watts,
             percentage_of_system: workload.percentage_of_system,
+            container: workload.container.as_ref(),
         })
         .collect();
 
     SnapshotOutput {
+        schema_version: SCHEMA_VERSION,
         timestamp: snapshot.timestamp,
+        time: timezone.format_millis(snapshot.timestamp),
         gpu_available: snapshot.gpu_available,
         sources,
         system_total: SnapshotDeviceEnergy::from_energy(&snapshot.system_total, sources),
         workloads,
         unattributed: SnapshotDeviceEnergy::from_energy(&snapshot.unattributed, sources),
+        auxiliary: &snapshot.auxiliary,
+        devices: &snapshot.devices,
+        totals: &snapshot.totals,
         tracked_pids: &snapshot.tracked_pids,
         diagnostics: &snapshot.diagnostics,
     }
@@ -577,7 +1492,7 @@ fn build_cli_output(
     snapshot: &MetricsSnapshot,
     units: &MeasurementUnitsConfig,
 ) -> CliOutput {
-    let total_energy_joules = snapshot.system_total.total();
+    let total_energy_joules = snapshot.totals.total_joules;
     let power_watts = if duration > 0.0 {
         total_energy_joules / duration
     } else {
@@ -603,6 +1518,7 @@ fn build_cli_output(
         .collect();
 
     CliOutput {
+        schema_version: SCHEMA_VERSION,
         pid: args.pid,
         duration_seconds: duration,
         total_energy: units.convert_energy_from_joules(total_energy_joules),
@@ -610,6 +1526,14 @@ fn build_cli_output(
         power: units.convert_power_from_watts(power_watts),
         power_unit: units.power.clone(),
         devices: DeviceBreakdown::from_snapshot(snapshot, units),
+        components: snapshot
+            .totals
+            .components
+            .iter()
+            .map(|(component, joules)| {
+                (component.clone(), units.convert_energy_from_joules(*joules))
+            })
+            .collect(),
         workloads,
     }
 }
@@ -624,7 +1548,12 @@ fn apply_cli_overrides(config: &mut EmtConfig, args: &Args) {
 }
 
 fn apply_mode_defaults(config: &mut EmtConfig, args: &Args) {
-    if selected_mode(args) != Mode::Tui || args.pid.is_some() {
+    if selected_mode(args) != Mode::Tui
+        || args.pid.is_some()
+        || !args.cgroup.is_empty()
+        || !args.unit.is_empty()
+        || !args.cgroup_children.is_empty()
+    {
         return;
     }
 
@@ -646,8 +1575,59 @@ fn tui_render_interval(config: &EmtConfig) -> Duration {
     Duration::from_millis(config.tui.render_interval_millis)
 }
 
-#[tokio::main]
-async fn main() {
+fn sandbox_requested(args: &Args, config: &EmtConfig) -> bool {
+    selected_mode(args) == Mode::Headless && (args.sandbox || config.sandbox.enabled)
+}
+
+fn sandbox_policy(args: &Args, config: &EmtConfig) -> SandboxPolicy {
+    let mut policy = SandboxPolicy::for_monitor(config);
+    if let Some(path) = args.snapshot_out.as_deref() {
+        policy.allow_output_file(Path::new(path));
+    }
+    let cgroup_root = PathBuf::from(CGROUP_ROOT);
+    if config
+        .budgets
+        .iter()
+        .any(|budget| budget.action == BudgetAction::Throttle)
+        && cgroup_root.exists()
+        && !policy.write_paths.contains(&cgroup_root)
+    {
+        policy.write_paths.push(cgroup_root);
+    }
+    policy
+}
+
+fn apply_sandbox(policy: &SandboxPolicy) {
+    match sandbox::apply(policy) {
+        Ok(status) => {
+            if status.filesystem != FilesystemEnforcement::Full {
+                eprintln!(
+                    "Warning: Landlock filesystem restrictions are {:?} on this kernel",
+                    status.filesystem
+                );
+            }
+            eprintln!(
+                "Sandbox enabled: {} readable paths, {} writable paths, {} syscalls denied",
+                policy.read_paths.len(),
+                policy.write_paths.len(),
+                status.denied_syscalls
+            );
+        }
+        Err(e) => {
+            eprintln!("Failed to apply sandbox: {e}");
+            std::process::exit(1);
+        }
+    }
+}
+
+fn build_runtime() -> tokio::runtime::Runtime {
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .expect("Failed to build Tokio runtime")
+}
+
+fn main() {
     let args = Args::parse();
     if let Err(message) = validate_args(&args) {
         eprintln!("{message}");
@@ -662,18 +1642,72 @@ async fn main() {
         eprintln!("Invalid configuration: {e}");
         std::process::exit(2);
     }
-
-    match mode {
-        Mode::Tui => run_tui(config, args.pid, args.snapshot_out.as_deref()).await,
-        Mode::Headless => {
-            run_prometheus_export(
-                config,
-                args.pid,
-                args.bind,
-                args.port,
+    let cipher = match TraceCipher::from_config(&config.encryption) {
+        Ok(cipher) => cipher,
+        Err(e) => {
+            eprintln!("Invalid encryption key: {e}");
+            std::process::exit(2);
+        }
+    };
+    let device_labels = config.device_labels();
+    let export = ExportOptions {
+        cipher: cipher.as_ref(),
+        timezone: config.export.timezone(),
+        device_labels: &device_labels,
+    };
+
+    match &args.command {
+        Some(Command::Convert(convert)) => return run_convert(convert, export),
+        Some(Command::Merge(merge)) => return run_merge(merge, export),
+        Some(Command::Report(report)) => return run_report(report, &config, export),
+        Some(Command::Live(live)) => return run_live(live, &config.measurement_units, export),
+        Some(Command::Remote(remote)) => return run_remote(remote, export),
+        Some(Command::Agent(agent)) => return run_agent(agent, config),
+        Some(Command::Validate(validate)) => return run_validate(validate, &config),
+        Some(Command::Overhead(overhead)) => return run_overhead(overhead, &config),
+        Some(Command::Run(run)) => return run_record(run, &config, export),
+        Some(Command::ListCollectors(list)) => return run_list_collectors(list, &config),
+        #[cfg(unix)]
+        Some(Command::Daemon(daemon)) => return run_daemon(daemon, config),
+        #[cfg(unix)]
+        Some(Command::Ctl(ctl)) => return run_ctl(ctl),
+        None => {}
+    }
+
+    let cgroups = match cgroup_attributor(&args) {
+        Ok(cgroups) => cgroups,
+        Err(e) => {
+            eprintln!("Invalid monitoring target: {e}");
+            std::process::exit(2);
+        }
+    };
+
+    match mode {
+        Mode::Tui => build_runtime().block_on(run_tui(
+            config,
+            args.pid,
+            cgroups,
+            args.snapshot_out.as_deref(),
+            export,
+        )),
+        Mode::Headless => {
+            // The sandbox must be in place before the runtime spawns its worker
+            // threads, so collectors and the listener are set up synchronously.
+            let sandbox_policy =
+                sandbox_requested(&args, &config).then(|| sandbox_policy(&args, &config));
+            let exporter =
+                PrometheusExporter::prepare(config, args.pid, cgroups, args.bind, args.port);
+            #[cfg(feature = "grpc")]
+            let exporter = exporter.with_grpc(args.bind, args.grpc_port);
+            let exporter = exporter.with_http(args.bind, args.http_port);
+            if let Some(policy) = sandbox_policy.as_ref() {
+                apply_sandbox(policy);
+            }
+            build_runtime().block_on(run_prometheus_export(
+                exporter,
                 args.snapshot_out.as_deref(),
-            )
-            .await
+                export,
+            ))
         }
         Mode::JsonOut => {
             let duration = batch_duration_seconds(&args);
@@ -681,22 +1715,57 @@ async fn main() {
                 .json_out
                 .as_deref()
                 .expect("json_out is present in JsonOut mode");
-            run_json_out(
+            build_runtime().block_on(run_json_out(
                 config,
                 &args,
+                cgroups,
                 duration,
                 path.to_string(),
                 args.snapshot_out.as_deref(),
-            )
-            .await;
+                export,
+            ));
         }
     }
 }
 
-async fn run_tui(config: EmtConfig, pid: Option<u32>, snapshot_out: Option<&str>) {
+/// Cgroups of `--cgroup`, `--unit` and `--cgroup-children`.
+fn cgroup_attributor(args: &Args) -> std::io::Result<CgroupAttributor> {
+    let targets = args
+        .cgroup
+        .iter()
+        .map(CgroupTarget::open)
+        .chain(
+            args.unit
+                .iter()
+                .map(|unit| CgroupTarget::for_systemd_unit(unit)),
+        )
+        .collect::<Result<Vec<_>, _>>()?;
+    args.cgroup_children
+        .iter()
+        .try_fold(CgroupAttributor::new(targets), |attributor, parent| {
+            Ok(attributor.with_children_of(CgroupTarget::open(parent)?))
+        })
+}
+
+/// Monitor for `--cgroup` targets, else for `--pid` or every process.
+fn new_monitor(config: EmtConfig, pid: Option<u32>, cgroups: CgroupAttributor) -> Monitor {
+    if cgroups.is_empty() {
+        Monitor::new(config, pid.map(|p| vec![p]))
+    } else {
+        Monitor::for_cgroups(config, cgroups)
+    }
+}
+
+async fn run_tui(
+    config: EmtConfig,
+    pid: Option<u32>,
+    cgroups: CgroupAttributor,
+    snapshot_out: Option<&str>,
+    export: ExportOptions<'_>,
+) {
     let tick_rate = tui_render_interval(&config);
-    let root_pids = pid.map(|p| vec![p]);
-    let mut monitor = Monitor::new(config, root_pids);
+    let power_smoothing = config.tui.power_smoothing();
+    let mut monitor = new_monitor(config, pid, cgroups);
 
     let handle = match monitor.commence().await {
         Ok(h) => h,
@@ -722,7 +1791,7 @@ async fn run_tui(config: EmtConfig, pid: Option<u32>, snapshot_out: Option<&str>
     let backend = ratatui::backend::CrosstermBackend::new(stdout);
     let mut terminal = ratatui::Terminal::new(backend).expect("Failed to create terminal");
 
-    let mut app = App::new(handle);
+    let mut app = App::new(handle).with_power_smoothing(power_smoothing);
     let mut last_draw = std::time::Instant::now() - tick_rate;
     #[cfg(debug_assertions)]
     let force_panic_after_first_draw =
@@ -791,19 +1860,22 @@ async fn run_tui(config: EmtConfig, pid: Option<u32>, snapshot_out: Option<&str>
         eprintln!("Warning: Shutdown error: {e}");
     }
     app.refresh();
-    write_snapshot_if_requested(snapshot_out, &app.snapshot());
+    write_snapshot_if_requested(snapshot_out, &app.snapshot(), export);
 }
 
 async fn run_json_out(
     config: EmtConfig,
     args: &Args,
+    cgroups: CgroupAttributor,
     duration_secs: u64,
     output_path: String,
     snapshot_out: Option<&str>,
+    export: ExportOptions<'_>,
 ) {
     let measurement_units = config.measurement_units.clone();
-    let root_pids = args.pid.map(|p| vec![p]);
-    let mut monitor = Monitor::new(config, root_pids);
+    let device_priority = config.device_priority.clone();
+    let footprint = config.footprint.clone();
+    let mut monitor = new_monitor(config, args.pid, cgroups);
 
     let handle = match monitor.commence().await {
         Ok(h) => h,
@@ -813,35 +1885,292 @@ async fn run_json_out(
         }
     };
 
-    tokio::time::sleep(tokio::time::Duration::from_secs(duration_secs)).await;
+    // A signal ends the run early; the output covers the time collected.
+    let started = std::time::Instant::now();
+    tokio::select! {
+        _ = tokio::time::sleep(tokio::time::Duration::from_secs(duration_secs)) => {},
+        _ = shutdown_signal() => eprintln!("Interrupted, writing results collected so far"),
+    }
+    let duration = started.elapsed().as_secs_f64().min(duration_secs as f64);
 
     if let Err(e) = monitor.shutdown().await {
         eprintln!("Warning: Shutdown error: {e}");
     }
+    let summary = ReportOptions {
+        top: RUN_SUMMARY_TOP,
+        device_priority: &device_priority,
+        footprint: &footprint,
+        timezone: export.timezone,
+    };
+    print_run_summary(
+        &monitor,
+        summary,
+        &measurement_units,
+        args.markdown_out.as_deref(),
+        export,
+    )
+    .await;
 
     let snapshot = handle.snapshot();
-    write_snapshot_if_requested(snapshot_out, &snapshot);
-    let duration = duration_secs as f64;
+    write_snapshot_if_requested(snapshot_out, &snapshot, export);
     let cli_output = build_cli_output(args, duration, &snapshot, &measurement_units);
 
     let json_output =
         serde_json::to_string_pretty(&cli_output).expect("Failed to serialize output");
-    let mut file = File::create(&output_path).expect("Failed to create JSON output file");
-    file.write_all(json_output.as_bytes())
+    write_export(&output_path, json_output.into_bytes(), export.cipher)
         .expect("Failed to write JSON output");
     eprintln!("JSON results written to: {output_path}");
+    enforce_run_limits(args, duration, &snapshot, export);
+}
+
+/// Check the run against `--max-energy-j` and `--max-avg-watts`, write the
+/// verdict, and exit with [`EXIT_LIMIT_EXCEEDED`] when a limit was exceeded.
+fn enforce_run_limits(
+    args: &Args,
+    duration: f64,
+    snapshot: &MetricsSnapshot,
+    export: ExportOptions<'_>,
+) {
+    let limits = RunLimits {
+        max_energy_joules: args.max_energy_j,
+        max_average_watts: args.max_avg_watts,
+    };
+    if limits.is_empty() && args.verdict_out.is_none() {
+        return;
+    }
+    let verdict = limits.check(snapshot.system_total.total(), duration);
+    if let Some(path) = args.verdict_out.as_deref() {
+        let result: Result<(), Box<dyn std::error::Error>> = (|| {
+            let mut contents = serde_json::to_vec_pretty(&verdict)?;
+            contents.push(b'\n');
+            write_export(path, contents, export.cipher)
+        })();
+        match result {
+            Ok(()) => eprintln!("Verdict written to: {path}"),
+            Err(e) => eprintln!("Warning: failed to write verdict to {path}: {e}"),
+        }
+    }
+    if !verdict.passed {
+        eprintln!("Run exceeded {}", verdict.exceeded.join(" and "));
+        std::process::exit(EXIT_LIMIT_EXCEEDED);
+    }
+}
+
+/// Print per-device energy and power, the top processes and users, and
+/// emissions and cost of the run to stderr, and write the same summary as
+/// Markdown to `markdown_out`.
+async fn print_run_summary(
+    monitor: &Monitor,
+    options: ReportOptions<'_>,
+    units: &MeasurementUnitsConfig,
+    markdown_out: Option<&str>,
+    export: ExportOptions<'_>,
+) {
+    let report = monitor
+        .joined_trace()
+        .await
+        .and_then(LazyFrame::collect)
+        .and_then(|trace| build_report(&trace, &[], &[], options));
+    let report = match report {
+        Ok(report) => report,
+        Err(e) => {
+            eprintln!("Warning: failed to summarize the run: {e}");
+            return;
+        }
+    };
+    eprint!("\n{}", report.render_text(units));
+    if let Some(path) = markdown_out {
+        let markdown = report.render_markdown(units).into_bytes();
+        match write_export(path, markdown, export.cipher) {
+            Ok(()) => eprintln!("Markdown summary written to: {path}"),
+            Err(e) => eprintln!("Warning: failed to write Markdown summary to {path}: {e}"),
+        }
+    }
+}
+
+/// Headless exporter state created before the async runtime starts.
+struct PrometheusExporter {
+    monitor: Monitor,
+    listener: std::net::TcpListener,
+    address: SocketAddr,
+    update_interval: Duration,
+    /// Listener of the HTTP API, when requested
+    http_listener: Option<std::net::TcpListener>,
+    /// Listener of the gRPC API, when requested
+    #[cfg(feature = "grpc")]
+    grpc_listener: Option<std::net::TcpListener>,
+    /// Endpoint the metrics are also pushed to, when configured
+    #[cfg(feature = "remote-write")]
+    remote_write: Option<emt::config::RemoteWriteConfig>,
+}
+
+impl PrometheusExporter {
+    fn prepare(
+        config: EmtConfig,
+        pid: Option<u32>,
+        cgroups: CgroupAttributor,
+        bind: IpAddr,
+        port: u16,
+    ) -> Self {
+        let update_interval = Duration::from_secs_f64((1.0 / config.collection.rate_hz).max(0.1));
+        #[cfg(feature = "remote-write")]
+        let remote_write = config.export.remote_write.clone();
+        #[cfg(not(feature = "remote-write"))]
+        if config.export.remote_write.is_some() {
+            eprintln!("export.remote_write requires a build with the remote-write feature");
+            std::process::exit(1);
+        }
+        let monitor = new_monitor(config, pid, cgroups);
+
+        let address = SocketAddr::new(bind, port);
+        let listener = match std::net::TcpListener::bind(address)
+            .and_then(|listener| listener.set_nonblocking(true).map(|()| listener))
+        {
+            Ok(listener) => listener,
+            Err(e) => {
+                eprintln!("Failed to bind Prometheus exporter on {address}: {e}");
+                std::process::exit(1);
+            }
+        };
+
+        Self {
+            monitor,
+            listener,
+            address,
+            update_interval,
+            http_listener: None,
+            #[cfg(feature = "grpc")]
+            grpc_listener: None,
+            #[cfg(feature = "remote-write")]
+            remote_write,
+        }
+    }
+
+    /// Bind the HTTP API on `port`, if given, alongside the exporter.
+    fn with_http(mut self, bind: IpAddr, port: Option<u16>) -> Self {
+        let Some(port) = port else {
+            return self;
+        };
+        let address = SocketAddr::new(bind, port);
+        match std::net::TcpListener::bind(address)
+            .and_then(|listener| listener.set_nonblocking(true).map(|()| listener))
+        {
+            Ok(listener) => self.http_listener = Some(listener),
+            Err(e) => {
+                eprintln!("Failed to bind HTTP API on {address}: {e}");
+                std::process::exit(1);
+            }
+        }
+        self
+    }
+
+    /// Bind the gRPC API on `port`, if given, alongside the exporter.
+    #[cfg(feature = "grpc")]
+    fn with_grpc(mut self, bind: IpAddr, port: Option<u16>) -> Self {
+        let Some(port) = port else {
+            return self;
+        };
+        let address = SocketAddr::new(bind, port);
+        match std::net::TcpListener::bind(address)
+            .and_then(|listener| listener.set_nonblocking(true).map(|()| listener))
+        {
+            Ok(listener) => self.grpc_listener = Some(listener),
+            Err(e) => {
+                eprintln!("Failed to bind gRPC API on {address}: {e}");
+                std::process::exit(1);
+            }
+        }
+        self
+    }
+}
+
+/// Serve the HTTP API of `monitor` on `listener` until `stop` fires.
+async fn spawn_http_server(
+    monitor: &Monitor,
+    handle: MonitorHandle,
+    listener: std::net::TcpListener,
+) -> Result<
+    (
+        tokio::task::JoinHandle<()>,
+        tokio::sync::oneshot::Sender<()>,
+    ),
+    std::io::Error,
+> {
+    let listener = tokio::net::TcpListener::from_std(listener)?;
+    let address = listener.local_addr()?;
+    let api = emt::server::HttpApi::new(handle, monitor.process_tracker(), monitor.trace_reader());
+    let (stop, stopped) = tokio::sync::oneshot::channel();
+    let task = tokio::spawn(async move {
+        let shutdown = async {
+            let _ = stopped.await;
+        };
+        if let Err(e) = api.serve(listener, shutdown).await {
+            eprintln!("HTTP API error: {e}");
+        }
+    });
+    eprintln!("HTTP API listening on http://{address}");
+    Ok((task, stop))
+}
+
+/// Serve the gRPC API of `monitor` on `listener` until `stop` fires.
+#[cfg(feature = "grpc")]
+async fn spawn_grpc_server(
+    monitor: &Monitor,
+    handle: MonitorHandle,
+    listener: std::net::TcpListener,
+) -> Result<
+    (
+        tokio::task::JoinHandle<()>,
+        tokio::sync::oneshot::Sender<()>,
+    ),
+    std::io::Error,
+> {
+    let listener = tokio::net::TcpListener::from_std(listener)?;
+    let address = listener.local_addr()?;
+    let service = emt::server::EnergyService::new(handle);
+    monitor.add_sinks(|_| service.sink()).await;
+    let (stop, stopped) = tokio::sync::oneshot::channel();
+    let task = tokio::spawn(async move {
+        let shutdown = async {
+            let _ = stopped.await;
+        };
+        if let Err(e) = service.serve(listener, shutdown).await {
+            eprintln!("gRPC API error: {e}");
+        }
+    });
+    eprintln!("gRPC API listening on {address}");
+    Ok((task, stop))
 }
 
 async fn run_prometheus_export(
-    config: EmtConfig,
-    pid: Option<u32>,
-    bind: IpAddr,
-    port: u16,
+    exporter: PrometheusExporter,
     snapshot_out: Option<&str>,
+    export: ExportOptions<'_>,
 ) {
-    let update_interval = Duration::from_secs_f64((1.0 / config.collection.rate_hz).max(0.1));
-    let root_pids = pid.map(|p| vec![p]);
-    let mut monitor = Monitor::new(config, root_pids);
+    let PrometheusExporter {
+        mut monitor,
+        listener,
+        address,
+        update_interval,
+        http_listener,
+        #[cfg(feature = "grpc")]
+        grpc_listener,
+        #[cfg(feature = "remote-write")]
+        remote_write,
+    } = exporter;
+
+    let mut sink = PrometheusSink::new().expect("Failed to create Prometheus sink");
+    sink.set_device_labels(export.device_labels.clone());
+    #[cfg(feature = "prometheus-exporter")]
+    {
+        // Per-process series, served on the same endpoint
+        let processes = emt::exporters::ProcessExporter::new();
+        processes.set_device_labels(export.device_labels.clone());
+        processes
+            .register(sink.registry())
+            .expect("Failed to register per-process Prometheus series");
+        monitor.add_recorders(|_| processes.recorder()).await;
+    }
 
     let handle = match monitor.commence().await {
         Ok(h) => h,
@@ -851,17 +2180,64 @@ async fn run_prometheus_export(
         }
     };
 
-    let sink = Arc::new(Mutex::new(
-        PrometheusSink::new().expect("Failed to create Prometheus sink"),
-    ));
+    let http = match http_listener {
+        Some(listener) => match spawn_http_server(&monitor, handle.clone(), listener).await {
+            Ok(server) => Some(server),
+            Err(e) => {
+                eprintln!("Failed to start HTTP API: {e}");
+                let _ = monitor.shutdown().await;
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+    #[cfg(feature = "grpc")]
+    let grpc = match grpc_listener {
+        Some(listener) => match spawn_grpc_server(&monitor, handle.clone(), listener).await {
+            Ok(server) => Some(server),
+            Err(e) => {
+                eprintln!("Failed to start gRPC API: {e}");
+                let _ = monitor.shutdown().await;
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+    #[cfg(feature = "remote-write")]
+    let remote_write = match remote_write {
+        Some(config) => {
+            let url = config.url.clone();
+            let mut sink = emt::exporters::remote_write::RemoteWriteSink::new()
+                .expect("Failed to create remote-write sink");
+            sink.set_device_labels(export.device_labels.clone());
+            match emt::exporters::remote_write::spawn_remote_write(
+                sink,
+                config,
+                handle.clone(),
+                update_interval,
+            ) {
+                Ok(task) => {
+                    eprintln!("Pushing metrics to {url}");
+                    Some(task)
+                }
+                Err(e) => {
+                    eprintln!("Failed to start remote write: {e}");
+                    let _ = monitor.shutdown().await;
+                    std::process::exit(1);
+                }
+            }
+        }
+        None => None,
+    };
+
+    let sink = Arc::new(Mutex::new(sink));
     update_prometheus_sink(&sink, &handle.snapshot());
 
     let app = prometheus_router(Arc::clone(&sink));
-    let address = SocketAddr::new(bind, port);
-    let listener = match tokio::net::TcpListener::bind(address).await {
+    let listener = match tokio::net::TcpListener::from_std(listener) {
         Ok(listener) => listener,
         Err(e) => {
-            eprintln!("Failed to bind Prometheus exporter on {address}: {e}");
+            eprintln!("Failed to register Prometheus listener on {address}: {e}");
             let _ = monitor.shutdown().await;
             std::process::exit(1);
         }
@@ -880,11 +2256,25 @@ async fn run_prometheus_export(
 
     update_task.abort();
     let _ = update_task.await;
+    if let Some((task, stop)) = http {
+        let _ = stop.send(());
+        let _ = task.await;
+    }
+    #[cfg(feature = "grpc")]
+    if let Some((task, stop)) = grpc {
+        let _ = stop.send(());
+        let _ = task.await;
+    }
+    #[cfg(feature = "remote-write")]
+    if let Some((task, stop)) = remote_write {
+        let _ = stop.send(());
+        let _ = task.await;
+    }
 
     if let Err(e) = monitor.shutdown().await {
         eprintln!("Warning: Shutdown error: {e}");
     }
-    write_snapshot_if_requested(snapshot_out, &handle.snapshot());
+    write_snapshot_if_requested(snapshot_out, &handle.snapshot(), export);
 
     if let Err(e) = serve_result {
         eprintln!("Prometheus exporter error: {e}");
@@ -909,17 +2299,395 @@ fn update_prometheus_sink(sink: &SharedPrometheusSink, snapshot: &MetricsSnapsho
         .update(snapshot);
 }
 
-fn write_snapshot_if_requested(path: Option<&str>, snapshot: &MetricsSnapshot) {
+fn run_convert(args: &ConvertArgs, export: ExportOptions<'_>) {
+    let options = ConvertOptions {
+        device_labels: export.device_labels.clone(),
+        ..args.options(export.timezone)
+    };
+    match convert_trace(&args.input, &args.output, &options, export.cipher) {
+        Ok(rows) => eprintln!("Wrote {rows} rows to: {}", args.output.display()),
+        Err(e) => {
+            eprintln!("Failed to convert {}: {e}", args.input.display());
+            std::process::exit(1);
+        }
+    }
+}
+
+fn run_merge(args: &MergeArgs, export: ExportOptions<'_>) {
+    let clock_offsets: HashMap<String, i64> = args.clock_offsets.iter().cloned().collect();
+    let selection = &args.selection;
+    let result = merge_traces(&args.inputs, &clock_offsets, export.cipher)
+        .map_err(|e| e.to_string())
+        .and_then(|merged| {
+            selection
+                .apply(merged, export.device_labels)
+                .map_err(|e| e.to_string())
+        })
+        .and_then(|mut merged| {
+            write_trace(&mut merged, &args.output, export.timezone, export.cipher)
+                .map(|()| merged.height())
+                .map_err(|e| e.to_string())
+        });
+    match result {
+        Ok(rows) => eprintln!("Wrote {rows} rows to: {}", args.output.display()),
+        Err(e) => {
+            eprintln!("Failed to merge traces: {e}");
+            std::process::exit(1);
+        }
+    }
+}
+
+fn run_report(args: &ReportArgs, config: &EmtConfig, export: ExportOptions<'_>) {
+    let options = ReportOptions {
+        top: args.top,
+        device_priority: &config.device_priority,
+        footprint: &config.footprint,
+        timezone: export.timezone,
+    };
+    let report = match report_trace(&args.input, options, export.cipher) {
+        Ok(report) => report,
+        Err(e) => {
+            eprintln!("Failed to read trace: {e}");
+            std::process::exit(1);
+        }
+    };
+    if args.json {
+        match serde_json::to_string_pretty(&report) {
+            Ok(json) => println!("{json}"),
+            Err(e) => {
+                eprintln!("Failed to serialize report: {e}");
+                std::process::exit(1);
+            }
+        }
+    } else if args.markdown {
+        print!("{}", report.render_markdown(&config.measurement_units));
+    } else {
+        print!("{}", report.render_text(&config.measurement_units));
+    }
+}
+
+fn run_live(args: &LiveArgs, units: &MeasurementUnitsConfig, export: ExportOptions<'_>) {
+    let result = build_runtime().block_on(async {
+        tokio::select! {
+            result = follow_exporter(args, units, export.timezone) => result,
+            () = shutdown_signal() => Ok(()),
+        }
+    });
+    if let Err(e) = result {
+        eprintln!("Failed to read {}: {e}", args.url());
+        std::process::exit(1);
+    }
+}
+
+fn run_remote(args: &RemoteArgs, export: ExportOptions<'_>) {
+    let hosts: Vec<RemoteHost> = args.hosts.iter().map(RemoteHost::new).collect();
+    let spool_dir = std::env::temp_dir().join(format!("emt-remote-{}", std::process::id()));
+    let stop = async {
+        shutdown_signal().await;
+        eprintln!("Interrupted, merging rows received so far");
+    };
+    let remote =
+        build_runtime().block_on(collect_remote(&hosts, &args.options(), &spool_dir, stop));
+    let remote = match remote {
+        Ok(remote) => remote,
+        Err(e) => {
+            eprintln!("Failed to measure remote hosts: {e}");
+            std::process::exit(1);
+        }
+    };
+    for failure in &remote.failures {
+        eprintln!("Warning: {failure}");
+    }
+    let result = args
+        .selection
+        .apply(remote.trace, export.device_labels)
+        .and_then(|mut trace| {
+            write_trace(&mut trace, &args.output, export.timezone, export.cipher)
+                .map(|()| trace.height())
+        });
+    match result {
+        Ok(rows) => eprintln!("Wrote {rows} rows to: {}", args.output.display()),
+        Err(e) => {
+            eprintln!("Failed to write {}: {e}", args.output.display());
+            std::process::exit(1);
+        }
+    }
+}
+
+fn run_validate(args: &ValidateArgs, config: &EmtConfig) {
+    eprintln!(
+        "Comparing {} with {} for {:.0?}",
+        args.source_a, args.source_b, args.duration
+    );
+    let report = build_runtime().block_on(validate_sources(
+        &args.source_a,
+        &args.source_b,
+        config,
+        &args.options(),
+    ));
+    let report = match report {
+        Ok(report) => report,
+        Err(e) => {
+            eprintln!("Validation failed: {e}");
+            std::process::exit(1);
+        }
+    };
+    if args.json {
+        match serde_json::to_string_pretty(&report) {
+            Ok(json) => println!("{json}"),
+            Err(e) => {
+                eprintln!("Failed to serialize report: {e}");
+                std::process::exit(1);
+            }
+        }
+    } else {
+        print!("{}", report.render_text());
+    }
+}
+
+fn run_overhead(args: &OverheadArgs, config: &EmtConfig) {
+    let options = OverheadOptions {
+        rates: args.rates.clone(),
+        duration: args.duration,
+    };
+    eprintln!(
+        "Sampling for {:.0?} at each of {} rates",
+        args.duration,
+        args.rates.len()
+    );
+    let report = match build_runtime().block_on(measure_overhead(config, &options)) {
+        Ok(report) => report,
+        Err(e) => {
+            eprintln!("Overhead measurement failed: {e}");
+            std::process::exit(1);
+        }
+    };
+    if args.json {
+        match serde_json::to_string_pretty(&report) {
+            Ok(json) => println!("{json}"),
+            Err(e) => {
+                eprintln!("Failed to serialize report: {e}");
+                std::process::exit(1);
+            }
+        }
+    } else {
+        print!("{}", report.render_text());
+    }
+}
+
+fn run_record(args: &RunArgs, config: &EmtConfig, export: ExportOptions<'_>) {
+    let options = RunOptions {
+        collectors: args.collectors.clone(),
+        rate: args.rate,
+        pids: args.pids.clone(),
+        duration: args.duration,
+    };
+    eprintln!("Recording for {:.0?}", args.duration);
+    let result = build_runtime()
+        .block_on(record_trace(config, &options))
+        .map_err(|e| e.to_string())
+        .and_then(|mut trace| {
+            write_trace(&mut trace, &args.output, export.timezone, export.cipher)
+                .map(|()| trace.height())
+                .map_err(|e| e.to_string())
+        });
+    match result {
+        Ok(rows) => eprintln!("Wrote {rows} rows to: {}", args.output.display()),
+        Err(e) => {
+            eprintln!("Recording failed: {e}");
+            std::process::exit(1);
+        }
+    }
+}
+
+fn run_list_collectors(args: &ListCollectorsArgs, config: &EmtConfig) {
+    let statuses = list_collectors(config);
+    if args.json {
+        match serde_json::to_string_pretty(&statuses) {
+            Ok(json) => println!("{json}"),
+            Err(e) => {
+                eprintln!("Failed to serialize collectors: {e}");
+                std::process::exit(1);
+            }
+        }
+    } else {
+        print!("{}", render_collectors(&statuses));
+    }
+}
+
+#[cfg(unix)]
+fn run_daemon(args: &DaemonArgs, mut config: EmtConfig) {
+    if let Some(rate) = args.rate {
+        config.collection.rate_hz = rate;
+    }
+    if let Err(e) = config.validate() {
+        eprintln!("Invalid configuration: {e}");
+        std::process::exit(2);
+    }
+    let listener = match bind_socket(&args.socket, args.socket_mode) {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("Failed to bind {}: {e}", args.socket.display());
+            std::process::exit(1);
+        }
+    };
+    build_runtime().block_on(serve_daemon(args, config, listener));
+}
+
+/// Monitor every process and answer requests on `listener` until SIGINT or
+/// SIGTERM.
+#[cfg(unix)]
+async fn serve_daemon(
+    args: &DaemonArgs,
+    config: EmtConfig,
+    listener: std::os::unix::net::UnixListener,
+) {
+    let listener = match tokio::net::UnixListener::from_std(listener) {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("Failed to register {}: {e}", args.socket.display());
+            std::process::exit(1);
+        }
+    };
+    let mut monitor = Monitor::new(config, None);
+    let handle = match monitor.commence().await {
+        Ok(handle) => handle,
+        Err(e) => {
+            eprintln!("Failed to start monitoring: {e}");
+            std::process::exit(1);
+        }
+    };
+    eprintln!("Daemon listening on {}", args.socket.display());
+    serve(listener, move || handle.snapshot(), shutdown_signal()).await;
+
+    if let Err(e) = monitor.shutdown().await {
+        eprintln!("Warning: Shutdown error: {e}");
+    }
+    if let Err(e) = std::fs::remove_file(&args.socket) {
+        eprintln!("Warning: failed to remove {}: {e}", args.socket.display());
+    }
+}
+
+#[cfg(unix)]
+fn run_ctl(args: &CtlArgs) {
+    let result = build_runtime().block_on(async {
+        let mut client = DaemonClient::connect(&args.socket).await?;
+        client.call(&args.request.request()).await
+    });
+    match result.map(|value| serde_json::to_string_pretty(&value)) {
+        Ok(Ok(json)) => println!("{json}"),
+        Ok(Err(e)) => {
+            eprintln!("Failed to serialize the result: {e}");
+            std::process::exit(1);
+        }
+        Err(e) => {
+            eprintln!("Request to {} failed: {e}", args.socket.display());
+            std::process::exit(1);
+        }
+    }
+}
+
+fn run_agent(args: &AgentArgs, mut config: EmtConfig) {
+    if let Some(rate) = args.rate {
+        config.collection.rate_hz = rate;
+    }
+    if let Err(e) = config.validate() {
+        eprintln!("Invalid configuration: {e}");
+        std::process::exit(2);
+    }
+    build_runtime().block_on(stream_agent(args, config));
+}
+
+/// Monitor every process for `--duration`, sending new rows every
+/// `--interval`. Stops early once the reader goes away.
+async fn stream_agent(args: &AgentArgs, config: EmtConfig) {
+    let mut monitor = Monitor::new(config, None);
+    if let Err(e) = monitor.commence().await {
+        eprintln!("Failed to start monitoring: {e}");
+        std::process::exit(1);
+    }
+    let mut stream = AgentStream::new(std::io::stdout());
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(args.duration);
+    let mut connected = true;
+    while connected && tokio::time::Instant::now() < deadline {
+        tokio::select! {
+            _ = tokio::time::sleep(args.interval.min(deadline - tokio::time::Instant::now())) => {}
+            _ = shutdown_signal() => break,
+        }
+        if let Err(e) = stream.send_new_rows(&monitor).await {
+            eprintln!("Stopped streaming: {e}");
+            connected = false;
+        }
+    }
+    if let Err(e) = monitor.shutdown().await {
+        eprintln!("Warning: Shutdown error: {e}");
+    }
+    if connected && let Err(e) = stream.send_new_rows(&monitor).await {
+        eprintln!("Failed to send the last rows: {e}");
+        std::process::exit(1);
+    }
+}
+
+async fn follow_exporter(
+    args: &LiveArgs,
+    units: &MeasurementUnitsConfig,
+    timezone: ExportTimezone,
+) -> Result<(), LiveError> {
+    let client = SnapshotClient::new(&args.url())?;
+    let mut previous = client.fetch().await?;
+    loop {
+        tokio::time::sleep(args.interval).await;
+        let current = client.fetch().await?;
+        let Some(update) = LiveUpdate::between(&previous, &current, args.top) else {
+            continue;
+        };
+        if args.jsonl {
+            println!("{}", serde_json::to_string(&update)?);
+        } else {
+            println!("{}", update.render_text(units, timezone));
+        }
+        if !args.follow {
+            return Ok(());
+        }
+        previous = current;
+    }
+}
+
+/// Settings shared by every file export.
+#[derive(Clone, Copy)]
+struct ExportOptions<'a> {
+    cipher: Option<&'a TraceCipher>,
+    timezone: ExportTimezone,
+    device_labels: &'a DeviceLabels,
+}
+
+/// Write an export file, encrypting it first when a key is configured.
+fn write_export(
+    path: &str,
+    mut contents: Vec<u8>,
+    cipher: Option<&TraceCipher>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(cipher) = cipher {
+        contents = cipher.seal(&contents)?;
+    }
+    File::create(path)?.write_all(&contents)?;
+    Ok(())
+}
+
+fn write_snapshot_if_requested(
+    path: Option<&str>,
+    snapshot: &MetricsSnapshot,
+    export: ExportOptions<'_>,
+) {
     let Some(path) = path else {
         return;
     };
 
     let result: Result<(), Box<dyn std::error::Error>> = (|| {
-        let mut file = File::create(path)?;
-        let output = build_snapshot_output(snapshot);
-        serde_json::to_writer_pretty(&mut file, &output)?;
-        file.write_all(b"\n")?;
-        Ok(())
+        let output = build_snapshot_output(snapshot, export.timezone);
+        let mut contents = serde_json::to_vec_pretty(&output)?;
+        contents.push(b'\n');
+        write_export(path, contents, export.cipher)
     })();
 
     match result {
@@ -0,0 +1,285 @@
+/// Merge Module
+///
+/// Combines traces recorded on several hosts into one frame, the offline
+/// side of multi-host monitoring behind `emt merge`. Each input is one
+/// host's trace directory (or file). Rows gain a `host` column, and
+/// timestamps are corrected by the host's clock offset so rows from
+/// different machines line up.
+///
+/// The monitor writes a [`HOST_FILE_NAME`] file into `collection.trace_dir`
+/// naming the host. EMT does not measure clock offsets itself: record one
+/// taken from NTP or chrony as `clock_offset_ms` in that file, or pass it to
+/// [`merge_traces`].
+use crate::convert::{ConvertError, read_trace, sort_by_timestamp};
+use crate::encryption::TraceCipher;
+use crate::query::align_columns;
+use crate::schema::{HOST_FILE_NAME, SCHEMA_VERSION, SCHEMA_VERSION_KEY};
+use crate::utils::time::{TIMESTAMP_COLUMN, timestamp_dtype, timestamp_micros};
+use polars::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use sysinfo::System;
+use thiserror::Error;
+
+/// Column naming the host a merged row was recorded on.
+pub const HOST_COLUMN: &str = "host";
+
+/// Errors raised while merging traces.
+#[derive(Debug, Error)]
+pub enum MergeError {
+    #[error("no traces to merge")]
+    NoInputs,
+    #[error(transparent)]
+    Convert(#[from] ConvertError),
+    #[error("Invalid {HOST_FILE_NAME}: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Polars error: {0}")]
+    Polars(#[from] PolarsError),
+}
+
+/// Contents of a [`HOST_FILE_NAME`] file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HostInfo {
+    pub host: String,
+    /// Milliseconds the host's clock runs ahead of the reference clock
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clock_offset_ms: Option<i64>,
+}
+
+impl HostInfo {
+    /// This machine, without a clock offset.
+    pub fn local() -> Self {
+        Self {
+            host: System::host_name().unwrap_or_else(|| "localhost".to_string()),
+            clock_offset_ms: None,
+        }
+    }
+
+    /// Read the host file in `dir`, if there is one.
+    pub fn read(dir: &Path) -> Result<Option<Self>, MergeError> {
+        match fs::read(dir.join(HOST_FILE_NAME)) {
+            Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Write the host file into `dir` unless one exists, so an offset
+    /// recorded there is kept.
+    pub fn write_if_missing(&self, dir: &Path) -> Result<(), MergeError> {
+        fs::create_dir_all(dir)?;
+        let mut value = serde_json::to_value(self)?;
+        if let Some(fields) = value.as_object_mut() {
+            fields.insert(SCHEMA_VERSION_KEY.to_string(), SCHEMA_VERSION.into());
+        }
+        let mut contents = serde_json::to_vec_pretty(&value)?;
+        contents.push(b'\n');
+        match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(dir.join(HOST_FILE_NAME))
+        {
+            Ok(mut file) => Ok(file.write_all(&contents)?),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// One host's trace.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MergeInput {
+    /// Trace directory or file
+    pub path: PathBuf,
+    /// Host name, overriding the host file
+    pub host: Option<String>,
+}
+
+/// Merge `inputs` into one trace ordered by corrected timestamp.
+///
+/// An input's host is its explicit name, else the one in its host file. A
+/// file input without either keeps its `host` column when it has one, as
+/// earlier merge outputs do, and is otherwise named after its file stem.
+/// Offsets in `clock_offsets` (by host, in milliseconds) take precedence
+/// over host files. Columns missing from some inputs are null there.
+pub fn merge_traces(
+    inputs: &[MergeInput],
+    clock_offsets: &HashMap<String, i64>,
+    cipher: Option<&TraceCipher>,
+) -> Result<DataFrame, MergeError> {
+    if inputs.is_empty() {
+        return Err(MergeError::NoInputs);
+    }
+    let mut frames = Vec::with_capacity(inputs.len());
+    for input in inputs {
+        let info = if input.path.is_dir() {
+            HostInfo::read(&input.path)?
+        } else {
+            None
+        };
+        let mut trace = read_trace(&input.path, cipher)?;
+        let host = input
+            .host
+            .clone()
+            .or_else(|| info.as_ref().map(|info| info.host.clone()));
+        let host = match host {
+            Some(host) => host,
+            None if trace.column(HOST_COLUMN).is_ok() => {
+                frames.push(trace);
+                continue;
+            }
+            None => input
+                .path
+                .file_stem()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into_owned(),
+        };
+
+        let offset_ms = clock_offsets
+            .get(&host)
+            .copied()
+            .or(info.and_then(|info| info.clock_offset_ms))
+            .unwrap_or(0);
+        if offset_ms != 0 {
+            let corrected = timestamp_micros(&trace)?
+                .apply_values(|ts| ts - offset_ms * 1000)
+                .into_series()
+                .cast(&timestamp_dtype())?
+                .with_name(TIMESTAMP_COLUMN.into());
+            trace.with_column(corrected)?;
+        }
+        let hosts = Column::new(HOST_COLUMN.into(), vec![host.as_str(); trace.height()]);
+        if trace.column(HOST_COLUMN).is_ok() {
+            trace.with_column(hosts)?;
+        } else {
+            trace.insert_column(0, hosts)?;
+        }
+        frames.push(trace);
+    }
+
+    let mut schema = Schema::default();
+    for frame in &frames {
+        for (name, dtype) in frame.schema().iter() {
+            if !schema.contains(name) {
+                schema.with_column(name.clone(), dtype.clone());
+            }
+        }
+    }
+    let frames = frames
+        .iter()
+        .map(|frame| align_columns(frame, &schema).map(IntoLazy::lazy))
+        .collect::<PolarsResult<Vec<_>>>()?;
+    Ok(sort_by_timestamp(concat(frames, UnionArgs::default())?).collect()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::convert::write_trace;
+    use crate::utils::time::ExportTimezone;
+    use polars::df;
+    use tempfile::TempDir;
+
+    const START_MS: i64 = 1_700_000_000_000;
+
+    /// Write a two-row trace at `offsets_ms` after [`START_MS`].
+    fn write_host_trace(dir: &Path, offsets_ms: [i64; 2], extra: Option<&str>) {
+        let mut trace = df!(
+            "pid" => [1u32, 1],
+            TIMESTAMP_COLUMN => offsets_ms.map(|offset| START_MS + offset),
+            "energy" => [1.0, 2.0],
+        )
+        .unwrap();
+        if let Some(name) = extra {
+            trace
+                .with_column(Column::new(name.into(), [0.5, 0.5]))
+                .unwrap();
+        }
+        fs::create_dir_all(dir.join("cpu")).unwrap();
+        write_trace(
+            &mut trace,
+            &dir.join("cpu").join("part-0.parquet"),
+            ExportTimezone::Utc,
+            None,
+        )
+        .unwrap();
+    }
+
+    fn column<'a>(frame: &'a DataFrame, name: &str) -> &'a Column {
+        frame.column(name).unwrap()
+    }
+
+    #[test]
+    fn host_file_is_kept_once_written() {
+        let dir = TempDir::new().unwrap();
+        let recorded = HostInfo {
+            host: "node-a".to_string(),
+            clock_offset_ms: Some(250),
+        };
+        recorded.write_if_missing(dir.path()).unwrap();
+
+        HostInfo::local().write_if_missing(dir.path()).unwrap();
+
+        assert_eq!(HostInfo::read(dir.path()).unwrap(), Some(recorded));
+        assert_eq!(HostInfo::read(&dir.path().join("missing")).unwrap(), None);
+    }
+
+    #[test]
+    fn hosts_are_tagged_and_clocks_corrected() {
+        let dir = TempDir::new().unwrap();
+        let (a, b) = (dir.path().join("a"), dir.path().join("b"));
+        write_host_trace(&a, [1_000, 3_000], None);
+        write_host_trace(&b, [2_500, 4_500], Some("raw_counter"));
+        HostInfo {
+            host: "node-b".to_string(),
+            clock_offset_ms: Some(250),
+        }
+        .write_if_missing(&b)
+        .unwrap();
+        let inputs = [
+            MergeInput {
+                path: a,
+                host: Some("node-a".to_string()),
+            },
+            MergeInput {
+                path: b,
+                host: None,
+            },
+        ];
+
+        let merged = merge_traces(&inputs, &HashMap::new(), None).unwrap();
+
+        let hosts = column(&merged, HOST_COLUMN).str().unwrap();
+        assert_eq!(
+            hosts.into_iter().flatten().collect::<Vec<_>>(),
+            ["node-a", "node-b", "node-a", "node-b"]
+        );
+        let timestamps = timestamp_micros(&merged).unwrap();
+        assert_eq!(
+            timestamps
+                .into_iter()
+                .flatten()
+                .map(|ts| ts / 1000 - START_MS)
+                .collect::<Vec<_>>(),
+            [1_000, 2_250, 3_000, 4_250]
+        );
+        assert_eq!(column(&merged, "raw_counter").null_count(), 2);
+
+        let overridden = merge_traces(
+            &inputs[1..],
+            &HashMap::from([("node-b".to_string(), 0)]),
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            timestamp_micros(&overridden).unwrap().get(0),
+            Some((START_MS + 2_500) * 1000)
+        );
+    }
+}
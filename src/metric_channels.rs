@@ -0,0 +1,157 @@
+/// Metric Channels
+///
+/// Named numeric values an application pushes while it runs, such as batch
+/// loss or queue depth, kept next to the energy trace so energy can be read
+/// against application behavior in one dataset. Values are stamped like
+/// trace records, `name | value | timestamp | monotonic_ns`, and share the
+/// trace's retention window.
+///
+/// [`join_metrics`] merges them into the trace as one `metric_<name>` column
+/// per metric holding, for each row, the latest value recorded at or before
+/// the row.
+use crate::utils::errors::MonitoringError;
+use crate::utils::time::{
+    MONOTONIC_COLUMN, RecordTime, TIMESTAMP_COLUMN, monotonic_dtype, timestamp_dtype,
+};
+use polars::prelude::*;
+use std::collections::BTreeMap;
+
+/// Prefix of the trace columns holding metrics.
+pub const METRIC_COLUMN_PREFIX: &str = "metric_";
+
+/// Errors raised when recording a metric.
+#[derive(Debug, thiserror::Error)]
+pub enum MetricError {
+    #[error("metric name must be non-empty")]
+    EmptyName,
+    #[error("value of metric '{0}' must be finite")]
+    NotFinite(String),
+    #[error("Polars error: {0}")]
+    Polars(#[from] PolarsError),
+    #[error(transparent)]
+    Trace(#[from] MonitoringError),
+}
+
+/// One recorded value as a metric frame row.
+pub fn metric_frame(name: &str, value: f64, time: RecordTime) -> Result<DataFrame, MetricError> {
+    if name.is_empty() {
+        return Err(MetricError::EmptyName);
+    }
+    if !value.is_finite() {
+        return Err(MetricError::NotFinite(name.to_string()));
+    }
+    Ok(DataFrame::new(vec![
+        Column::new("name".into(), [name]),
+        Column::new("value".into(), [value]),
+        Column::new(TIMESTAMP_COLUMN.into(), [time.timestamp_us]).cast(&timestamp_dtype())?,
+        Column::new(MONOTONIC_COLUMN.into(), [time.monotonic_ns]).cast(&monotonic_dtype())?,
+    ])?)
+}
+
+/// `trace` with a `metric_<name>` column per metric in `metrics`, holding
+/// the latest value recorded at or before each row's `monotonic_ns`, null
+/// before the first. Rows and metrics are matched on monotonic time, so
+/// wall-clock adjustments do not shift them.
+pub fn join_metrics(trace: &DataFrame, metrics: &DataFrame) -> PolarsResult<DataFrame> {
+    let mut trace = trace.clone();
+    if metrics.height() == 0 || trace.width() == 0 {
+        return Ok(trace);
+    }
+    let names = metrics.column("name")?.str()?;
+    let values = metrics.column("value")?.f64()?;
+    let times = metrics.column(MONOTONIC_COLUMN)?.cast(&DataType::Int64)?;
+    let mut series: BTreeMap<&str, Vec<(i64, f64)>> = BTreeMap::new();
+    for ((name, value), time) in names.iter().zip(values).zip(times.i64()?) {
+        if let (Some(name), Some(value), Some(time)) = (name, value, time) {
+            series.entry(name).or_default().push((time, value));
+        }
+    }
+
+    let rows = trace.column(MONOTONIC_COLUMN)?.cast(&DataType::Int64)?;
+    let rows = rows.i64()?;
+    for (name, mut points) in series {
+        // Stable, so the later of two values recorded at once wins.
+        points.sort_by_key(|(time, _)| *time);
+        let column: Vec<Option<f64>> = rows
+            .iter()
+            .map(|row| {
+                let row = row?;
+                let seen = points.partition_point(|(time, _)| *time <= row);
+                seen.checked_sub(1).map(|index| points[index].1)
+            })
+            .collect();
+        trace.with_column(Column::new(
+            format!("{METRIC_COLUMN_PREFIX}{name}").into(),
+            column,
+        ))?;
+    }
+    Ok(trace)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(seconds: i64) -> RecordTime {
+        RecordTime {
+            timestamp_us: 1_700_000_000_000_000 + seconds * 1_000_000,
+            monotonic_ns: seconds * 1_000_000_000,
+        }
+    }
+
+    fn metrics(points: &[(&str, f64, i64)]) -> DataFrame {
+        let mut frames = points
+            .iter()
+            .map(|(name, value, seconds)| metric_frame(name, *value, at(*seconds)).unwrap());
+        let mut metrics = frames.next().unwrap();
+        for frame in frames {
+            metrics.vstack_mut(&frame).unwrap();
+        }
+        metrics
+    }
+
+    #[test]
+    fn rejects_unnamed_and_non_finite_values() {
+        assert!(matches!(
+            metric_frame("", 1.0, at(0)),
+            Err(MetricError::EmptyName)
+        ));
+        assert!(matches!(
+            metric_frame("loss", f64::NAN, at(0)),
+            Err(MetricError::NotFinite(_))
+        ));
+        assert_eq!(metric_frame("loss", 0.5, at(0)).unwrap().height(), 1);
+    }
+
+    #[test]
+    fn trace_rows_get_the_latest_value_of_each_metric() {
+        let trace = DataFrame::new(vec![
+            Column::new("energy".into(), [1.0, 2.0, 3.0, 4.0]),
+            Column::new(
+                MONOTONIC_COLUMN.into(),
+                [0i64, 1, 2, 3].map(|s| at(s).monotonic_ns),
+            )
+            .cast(&monotonic_dtype())
+            .unwrap(),
+        ])
+        .unwrap();
+        let metrics = metrics(&[
+            ("loss", 0.9, 1),
+            ("queue_depth", 4.0, 0),
+            ("loss", 0.7, 3),
+            ("loss", 0.8, 2),
+        ]);
+
+        let joined = join_metrics(&trace, &metrics).unwrap();
+
+        let column = |name: &str| -> Vec<Option<f64>> {
+            joined.column(name).unwrap().f64().unwrap().iter().collect()
+        };
+        assert_eq!(
+            column("metric_loss"),
+            [None, Some(0.9), Some(0.8), Some(0.7)]
+        );
+        assert_eq!(column("metric_queue_depth"), [Some(4.0); 4]);
+        assert_eq!(join_metrics(&trace, &DataFrame::empty()).unwrap(), trace);
+    }
+}
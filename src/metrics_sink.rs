@@ -1,20 +1,39 @@
+use crate::device_labels::DeviceLabels;
+use crate::energy_group::DegradedDomain;
 use crate::monitor::{DeviceEnergy, DeviceSources, MetricsSnapshot, WorkloadSnapshot};
-use axum::Router;
 use axum::extract::State;
 use axum::http::{StatusCode, header};
 use axum::response::{IntoResponse, Response};
 use axum::routing::get;
+use axum::{Json, Router};
 use prometheus::core::{Collector, Desc};
 use prometheus::proto::{Counter, Gauge, LabelPair, Metric, MetricFamily, MetricType};
 use prometheus::{Encoder, Registry, TextEncoder};
+use serde::Serialize;
+use std::borrow::Cow;
 use std::collections::{HashMap, HashSet};
 use std::sync::{Arc, Mutex, MutexGuard};
 
 const SOCKET_LABEL: &str = "0";
 const ENERGY_METRIC: &str = "emt_energy_joules_total";
 const POWER_METRIC: &str = "emt_power_watts";
+const AUXILIARY_ENERGY_METRIC: &str = "emt_auxiliary_energy_joules_total";
+const DROPPED_BATCHES_METRIC: &str = "emt_dropped_batches_total";
+const TRACE_MEMORY_METRIC: &str = "emt_trace_memory_bytes";
+const TRACE_MEMORY_PEAK_METRIC: &str = "emt_trace_memory_peak_bytes";
+const DEGRADED_DOMAINS_METRIC: &str = "emt_degraded_domains";
 const ENERGY_HELP: &str = "Cumulative EMT energy attribution in joules.";
 const POWER_HELP: &str = "EMT attributed power in watts.";
+const AUXILIARY_ENERGY_HELP: &str =
+    "Cumulative energy in joules from auxiliary devices, excluded from EMT totals.";
+const DROPPED_BATCHES_HELP: &str =
+    "Collector batches discarded by the backpressure policy because the monitor fell behind.";
+
+const TRACE_MEMORY_HELP: &str = "Estimated memory held by EMT's in-memory traces in bytes.";
+const TRACE_MEMORY_PEAK_HELP: &str =
+    "High watermark of the memory held by EMT's in-memory traces in bytes.";
+const DEGRADED_DOMAINS_HELP: &str =
+    "Device domains skipped because they could not be discovered or read.";
 
 pub type SharedPrometheusSink = Arc<Mutex<PrometheusSink>>;
 
@@ -58,6 +77,17 @@ impl PrometheusSink {
     pub fn encode_text(&self) -> Result<String, prometheus::Error> {
         TextEncoder::new().encode_to_string(&self.gather())
     }
+
+    /// Name devices by their aliases and add their labels, in metrics that
+    /// name devices.
+    pub fn set_device_labels(&mut self, device_labels: DeviceLabels) {
+        self.state.lock_unpoisoned().device_labels = device_labels;
+    }
+
+    /// The snapshot most recently passed to [`MetricsSink::update`].
+    pub fn latest_snapshot(&self) -> MetricsSnapshot {
+        self.state.lock_unpoisoned().latest.clone()
+    }
 }
 
 impl MetricsSink for PrometheusSink {
@@ -70,11 +100,41 @@ pub fn prometheus_router(sink: SharedPrometheusSink) -> Router {
     Router::new()
         .route("/metrics", get(metrics_handler))
         .route("/health", get(health_handler))
+        .route("/snapshot", get(snapshot_handler))
         .with_state(sink)
 }
 
-async fn health_handler() -> StatusCode {
-    StatusCode::OK
+/// Body of `/health`. The monitor keeps serving while some domains are
+/// degraded, so the status code stays 200 and the body says which.
+#[derive(Serialize)]
+struct HealthReport {
+    status: &'static str,
+    degraded_domains: Vec<DegradedDomain>,
+}
+
+async fn health_handler(State(sink): State<SharedPrometheusSink>) -> Json<HealthReport> {
+    let degraded_domains = sink
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .latest_snapshot()
+        .diagnostics
+        .degraded_domains;
+    Json(HealthReport {
+        status: if degraded_domains.is_empty() {
+            "ok"
+        } else {
+            "degraded"
+        },
+        degraded_domains,
+    })
+}
+
+async fn snapshot_handler(State(sink): State<SharedPrometheusSink>) -> Json<MetricsSnapshot> {
+    Json(
+        sink.lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .latest_snapshot(),
+    )
 }
 
 async fn metrics_handler(State(sink): State<SharedPrometheusSink>) -> Response {
@@ -102,14 +162,27 @@ async fn metrics_handler(State(sink): State<SharedPrometheusSink>) -> Response {
 
 #[derive(Debug, Default)]
 struct PrometheusState {
+    latest: MetricsSnapshot,
     previous: Option<PreviousSnapshot>,
     energy_samples: Vec<MetricSample>,
     power_samples: Vec<MetricSample>,
+    auxiliary_samples: Vec<MetricSample>,
+    dropped_batches: u64,
+    trace_bytes: u64,
+    trace_peak_bytes: u64,
+    degraded_domains: usize,
+    device_labels: DeviceLabels,
 }
 
 impl PrometheusState {
     fn update(&mut self, snapshot: &MetricsSnapshot) {
+        self.latest = snapshot.clone();
         self.energy_samples = energy_samples(snapshot);
+        self.auxiliary_samples = auxiliary_samples(snapshot, &self.device_labels);
+        self.dropped_batches = snapshot.diagnostics.dropped_batches;
+        self.trace_bytes = snapshot.diagnostics.trace_bytes;
+        self.trace_peak_bytes = snapshot.diagnostics.trace_peak_bytes;
+        self.degraded_domains = snapshot.diagnostics.degraded_domains.len();
 
         let Some(previous) = self.previous.as_ref() else {
             self.power_samples = zero_power_samples(snapshot);
@@ -129,7 +202,7 @@ impl PrometheusState {
     }
 
     fn collect(&self) -> Vec<MetricFamily> {
-        vec![
+        let mut families = vec![
             metric_family(
                 ENERGY_METRIC,
                 ENERGY_HELP,
@@ -142,7 +215,52 @@ impl PrometheusState {
                 MetricType::GAUGE,
                 &self.power_samples,
             ),
-        ]
+            metric_family(
+                DROPPED_BATCHES_METRIC,
+                DROPPED_BATCHES_HELP,
+                MetricType::COUNTER,
+                &[MetricSample {
+                    value: self.dropped_batches as f64,
+                    labels: Vec::new(),
+                }],
+            ),
+            metric_family(
+                TRACE_MEMORY_METRIC,
+                TRACE_MEMORY_HELP,
+                MetricType::GAUGE,
+                &[MetricSample {
+                    value: self.trace_bytes as f64,
+                    labels: Vec::new(),
+                }],
+            ),
+            metric_family(
+                TRACE_MEMORY_PEAK_METRIC,
+                TRACE_MEMORY_PEAK_HELP,
+                MetricType::GAUGE,
+                &[MetricSample {
+                    value: self.trace_peak_bytes as f64,
+                    labels: Vec::new(),
+                }],
+            ),
+            metric_family(
+                DEGRADED_DOMAINS_METRIC,
+                DEGRADED_DOMAINS_HELP,
+                MetricType::GAUGE,
+                &[MetricSample {
+                    value: self.degraded_domains as f64,
+                    labels: Vec::new(),
+                }],
+            ),
+        ];
+        if !self.auxiliary_samples.is_empty() {
+            families.push(metric_family(
+                AUXILIARY_ENERGY_METRIC,
+                AUXILIARY_ENERGY_HELP,
+                MetricType::COUNTER,
+                &self.auxiliary_samples,
+            ));
+        }
+        families
     }
 }
 
@@ -168,9 +286,9 @@ impl From<&MetricsSnapshot> for PreviousSnapshot {
 }
 
 #[derive(Debug, Clone, PartialEq)]
-struct MetricSample {
-    value: f64,
-    labels: Vec<(&'static str, String)>,
+pub(crate) struct MetricSample {
+    pub(crate) value: f64,
+    pub(crate) labels: Vec<(Cow<'static, str>, String)>,
 }
 
 #[derive(Clone)]
@@ -188,7 +306,7 @@ impl Collector for SnapshotCollector {
     }
 }
 
-trait LockUnpoisoned<T> {
+pub(crate) trait LockUnpoisoned<T> {
     fn lock_unpoisoned(&self) -> MutexGuard<'_, T>;
 }
 
@@ -221,6 +339,36 @@ fn energy_samples(snapshot: &MetricsSnapshot) -> Vec<MetricSample> {
     samples
 }
 
+fn auxiliary_samples(
+    snapshot: &MetricsSnapshot,
+    device_labels: &DeviceLabels,
+) -> Vec<MetricSample> {
+    snapshot
+        .auxiliary
+        .iter()
+        .map(|(device, joules)| MetricSample {
+            value: *joules,
+            labels: device_metric_labels(device, device_labels),
+        })
+        .collect()
+}
+
+/// The `device` label of `device`, by its alias, followed by its
+/// configured labels.
+pub(crate) fn device_metric_labels(
+    device: &str,
+    device_labels: &DeviceLabels,
+) -> Vec<(Cow<'static, str>, String)> {
+    let mut labels = vec![("device".into(), device_labels.alias(device).to_string())];
+    labels.extend(
+        device_labels
+            .labels(device)
+            .into_iter()
+            .map(|(name, value)| (name.to_string().into(), value.to_string())),
+    );
+    labels
+}
+
 fn power_samples(
     snapshot: &MetricsSnapshot,
     previous: Option<&PreviousSnapshot>,
@@ -444,16 +592,16 @@ fn metric_sample(
     value: f64,
 ) -> MetricSample {
     let mut labels = vec![
-        ("scope", scope.to_string()),
-        ("device", device.to_string()),
-        ("socket", SOCKET_LABEL.to_string()),
+        ("scope".into(), scope.to_string()),
+        ("device".into(), device.to_string()),
+        ("socket".into(), SOCKET_LABEL.to_string()),
     ];
 
     if let Some(workload_id) = workload_id {
-        labels.push(("workload", workload_id.to_string()));
+        labels.push(("workload".into(), workload_id.to_string()));
     }
     if let Some(workload_name) = workload_name {
-        labels.push(("workload_name", workload_name.to_string()));
+        labels.push(("workload_name".into(), workload_name.to_string()));
     }
 
     MetricSample { value, labels }
@@ -475,7 +623,7 @@ fn workload_name(workload: &WorkloadSnapshot) -> Option<&str> {
     }
 }
 
-fn metric_family(
+pub(crate) fn metric_family(
     name: &str,
     help: &str,
     metric_type: MetricType,
@@ -512,12 +660,12 @@ fn metric(metric_type: MetricType, sample: &MetricSample) -> Metric {
     metric
 }
 
-fn label_pairs(labels: &[(&'static str, String)]) -> Vec<LabelPair> {
+fn label_pairs(labels: &[(Cow<'static, str>, String)]) -> Vec<LabelPair> {
     let mut pairs: Vec<LabelPair> = labels
         .iter()
         .map(|(name, value)| {
             let mut label_pair = LabelPair::default();
-            label_pair.set_name((*name).to_string());
+            label_pair.set_name(name.to_string());
             label_pair.set_value(value.clone());
             label_pair
         })
@@ -529,9 +677,11 @@ fn label_pairs(labels: &[(&'static str, String)]) -> Vec<LabelPair> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::device_labels::DeviceLabelRule;
     use crate::monitor::DeviceSource;
     use axum::body::{Body, to_bytes};
     use axum::http::Request;
+    use std::collections::BTreeMap;
     use tower::ServiceExt;
 
     fn assert_metric_value(exposition: &str, prefix: &str, expected: f64) {
@@ -609,6 +759,69 @@ mod tests {
         assert!(!exposition.contains("pid=\""));
     }
 
+    #[test]
+    fn prometheus_sink_exports_auxiliary_devices_separately() {
+        let mut sink = PrometheusSink::new().unwrap();
+        let empty = sink_snapshot_with_auxiliary(BTreeMap::new());
+        sink.update(&empty);
+        assert!(!sink.encode_text().unwrap().contains("emt_auxiliary"));
+
+        sink.update(&sink_snapshot_with_auxiliary(BTreeMap::from([(
+            "rapl:system:psys".to_string(),
+            7.5,
+        )])));
+        let exposition = sink.encode_text().unwrap();
+
+        assert_metric_value(
+            &exposition,
+            "emt_auxiliary_energy_joules_total{device=\"rapl:system:psys\"}",
+            7.5,
+        );
+        assert!(!exposition.contains("emt_energy_joules_total{device=\"rapl"));
+    }
+
+    #[test]
+    fn prometheus_sink_names_devices_by_alias_with_labels() {
+        let mut sink = PrometheusSink::new().unwrap();
+        sink.set_device_labels(DeviceLabels::new(vec![DeviceLabelRule {
+            device: "rapl:system:psys".to_string(),
+            alias: Some("platform".to_string()),
+            labels: BTreeMap::from([("rack".to_string(), "R3".to_string())]),
+        }]));
+
+        sink.update(&sink_snapshot_with_auxiliary(BTreeMap::from([(
+            "rapl:system:psys".to_string(),
+            7.5,
+        )])));
+
+        assert_metric_value(
+            &sink.encode_text().unwrap(),
+            "emt_auxiliary_energy_joules_total{device=\"platform\",rack=\"R3\"}",
+            7.5,
+        );
+    }
+
+    #[test]
+    fn prometheus_sink_exports_collection_diagnostics() {
+        let mut sink = PrometheusSink::new().unwrap();
+        let mut snapshot = snapshot(1_000, DeviceEnergy::default(), DeviceEnergy::default());
+        snapshot.diagnostics.dropped_batches = 3;
+        snapshot.diagnostics.trace_bytes = 4_096;
+        snapshot.diagnostics.trace_peak_bytes = 8_192;
+        snapshot.diagnostics.degraded_domains = vec![DegradedDomain {
+            domain: "rapl:system:psys".to_string(),
+            error: "permission denied".to_string(),
+            failed_reads: 2,
+        }];
+        sink.update(&snapshot);
+
+        let text = sink.encode_text().unwrap();
+        assert_metric_value(&text, "emt_dropped_batches_total", 3.0);
+        assert_metric_value(&text, "emt_trace_memory_bytes", 4_096.0);
+        assert_metric_value(&text, "emt_trace_memory_peak_bytes", 8_192.0);
+        assert_metric_value(&text, "emt_degraded_domains", 1.0);
+    }
+
     #[test]
     fn prometheus_sink_exports_zero_power_on_first_snapshot() {
         let mut sink = PrometheusSink::new().unwrap();
@@ -819,7 +1032,7 @@ mod tests {
             energy(1.0, 0.0, 0.0),
             energy(0.5, 0.0, 0.0),
         ));
-        let app = prometheus_router(sink);
+        let app = prometheus_router(Arc::clone(&sink));
 
         let metrics_response = app
             .clone()
@@ -846,16 +1059,59 @@ mod tests {
         assert!(metrics_text.contains("# TYPE emt_energy_joules_total counter"));
         assert!(metrics_text.contains("emt_energy_joules_total"));
 
-        let health_response = app
+        let health = async |app: Router| {
+            let response = app
+                .oneshot(
+                    Request::builder()
+                        .uri("/health")
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap()
+        };
+        assert_eq!(health(app.clone()).await["status"], "ok");
+
+        let mut degraded = snapshot(2_000, energy(1.0, 0.0, 0.0), energy(0.5, 0.0, 0.0));
+        degraded.diagnostics.degraded_domains = vec![DegradedDomain {
+            domain: "rapl:system:dram".to_string(),
+            error: "No such file or directory".to_string(),
+            failed_reads: 1,
+        }];
+        sink.lock().unwrap().update(&degraded);
+        let report = health(app).await;
+        assert_eq!(report["status"], "degraded");
+        assert_eq!(report["degraded_domains"][0]["domain"], "rapl:system:dram");
+    }
+
+    #[tokio::test]
+    async fn prometheus_router_serves_latest_snapshot() {
+        let sink = Arc::new(Mutex::new(PrometheusSink::new().unwrap()));
+        sink.lock().unwrap().update(&snapshot(
+            2_000,
+            energy(3.0, 1.0, 0.0),
+            energy(2.0, 0.0, 0.0),
+        ));
+        let app = prometheus_router(sink);
+
+        let response = app
             .oneshot(
                 Request::builder()
-                    .uri("/health")
+                    .uri("/snapshot")
                     .body(Body::empty())
                     .unwrap(),
             )
             .await
             .unwrap();
-        assert_eq!(health_response.status(), StatusCode::OK);
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let served: MetricsSnapshot = serde_json::from_slice(&body).unwrap();
+        assert_eq!(served.timestamp, 2_000);
+        assert_eq!(served.system_total.dram_joules, 1.0);
+        assert_eq!(served.workloads[0].name, "render");
     }
 
     fn energy(cpu: f64, dram: f64, gpu: f64) -> DeviceEnergy {
@@ -878,6 +1134,13 @@ mod tests {
         .with_system_total(system_total)
     }
 
+    fn sink_snapshot_with_auxiliary(auxiliary: BTreeMap<String, f64>) -> MetricsSnapshot {
+        MetricsSnapshot {
+            auxiliary,
+            ..snapshot(1_000, DeviceEnergy::default(), DeviceEnergy::default())
+        }
+    }
+
     fn multi_workload_snapshot(
         timestamp: i64,
         workloads: Vec<WorkloadSnapshot>,
@@ -932,6 +1195,7 @@ mod tests {
             energy,
             power_watts: 0.0,
             percentage_of_system: 0.0,
+            container: None,
         }
     }
 
@@ -1,24 +1,53 @@
-use crate::collectors::{NvidiaGpu, Rapl};
-use crate::config::EmtConfig;
-use crate::energy_group::{EnergyCollector, EnergyGroup, EnergyRecord};
+use crate::budgets::BudgetEnforcer;
+use crate::collectors::cpu_energy::CpuEnergyBackend;
+use crate::collectors::rapl::SystemCpuTracker;
+use crate::collectors::{
+    AmdGpu, CpuEnergy, IntelGpu, JetsonIna, MeterCollector, Network, NvidiaGpu, Storage,
+};
+use crate::config::{EmtConfig, EnergyDomainRule};
+use crate::container::{ContainerInfo, ContainerMetadata, detect_container};
+use crate::convert::{ConvertError, filter_trace, project_trace, resample_trace};
+use crate::derived::DerivedDevices;
+use crate::device_priority::DevicePriority;
+use crate::encryption::TraceCipher;
+use crate::energy_group::{DegradedDomain, EnergyCollector, EnergyGroup, EnergyRecord};
+use crate::energy_totals::EnergyTotals;
+use crate::idle::IdleDetector;
+use crate::merge::HostInfo;
+use crate::metrics_sink::MetricsSink;
 use crate::process::{
-    ProcessGroup, group_processes, pid_to_group_map, scan_processes, tracked_pids,
+    ProcessGroup, group_processes, pid_to_group_map, resolve_containers, scan_processes,
+    tracked_pids,
+};
+use crate::process_aggregation::{
+    GroupedEnergyTick, aggregate_energy_records_with_priority, percentage_of_system,
 };
-use crate::process_aggregation::{aggregate_energy_records, percentage_of_system};
+use crate::query::{Filter, TimeRange};
+use crate::run::CollectorKind;
+use crate::sampler::SamplerSubscription;
+use crate::schema::HOST_FILE_NAME;
+use crate::shutdown_actions::{ShutdownArtifacts, run_shutdown_action};
+use crate::sinks::TraceSink;
+use crate::trace_recorder::TraceRecorder;
+use crate::utils::cgroup::{CgroupAttributor, CgroupTarget, TrackedCgroup};
 use crate::utils::errors::MonitoringError;
-use crate::utils::psutils::{ProcessRoot, walk_child_pids};
-use serde::Serialize;
-use std::collections::{HashMap, HashSet};
+use crate::utils::pattern::matches_any;
+use crate::utils::psutils::{self, ProcessRoot, walk_child_pids};
+use async_trait::async_trait;
+use polars::prelude::{DataFrame, IntoLazy, LazyFrame, PolarsResult, UnionArgs, concat};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::Path;
 use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::{Arc, RwLock};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tokio::sync::Mutex;
 use tokio::task::JoinHandle;
 
 // ─── MetricsSnapshot data structures ────────────────────────────────────────
 
 /// Energy source/provenance for a device in public outputs.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum DeviceSource {
     /// Energy is measured by a dedicated device/domain counter.
@@ -27,6 +56,8 @@ pub enum DeviceSource {
     MeasuredPackage,
     /// The device has no separate counter but is included in package energy.
     IncludedInPackage,
+    /// Energy is estimated from utilization rather than read from a counter.
+    Estimated,
     /// No usable measurement source is available.
     Unavailable,
 }
@@ -43,13 +74,14 @@ impl DeviceSource {
             Self::Measured => "measured",
             Self::MeasuredPackage => "measured_package",
             Self::IncludedInPackage => "included_in_package",
+            Self::Estimated => "estimated",
             Self::Unavailable => "unavailable",
         }
     }
 }
 
 /// Device source/provenance metadata attached to monitor snapshots.
-#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct DeviceSources {
     pub cpu: DeviceSource,
     pub dram: DeviceSource,
@@ -73,7 +105,7 @@ impl DeviceSources {
 }
 
 /// Energy breakdown by device type (CPU, DRAM, GPU).
-#[derive(Debug, Clone, Default, Serialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct DeviceEnergy {
     pub cpu_joules: f64,
     pub dram_joules: f64,
@@ -97,7 +129,7 @@ impl DeviceEnergy {
 }
 
 /// Per-process energy and power snapshot nested under a workload group.
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProcessEnergySnapshot {
     pub pid: u32,
     pub name: String,
@@ -106,7 +138,7 @@ pub struct ProcessEnergySnapshot {
 }
 
 /// Per-workload (root process) energy and power snapshot.
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WorkloadSnapshot {
     pub root_pid: u32,
     pub group_id: String,
@@ -117,10 +149,13 @@ pub struct WorkloadSnapshot {
     pub energy: DeviceEnergy,
     pub power_watts: f64,
     pub percentage_of_system: f64,
+    /// Container the workload runs in, with its name and image when known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub container: Option<ContainerInfo>,
 }
 
 /// Full metrics snapshot shared via MonitorHandle.
-#[derive(Debug, Clone, Default, Serialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct MetricsSnapshot {
     pub timestamp: i64,
     pub gpu_available: bool,
@@ -129,25 +164,96 @@ pub struct MetricsSnapshot {
     pub workloads: Vec<WorkloadSnapshot>,
     pub unattributed: DeviceEnergy,
     pub tracked_pids: Vec<u32>,
+    /// Cumulative joules per auxiliary device, excluded from all totals.
+    pub auxiliary: BTreeMap<String, f64>,
+    /// Cumulative joules per authoritative device.
+    #[serde(default)]
+    pub devices: BTreeMap<String, f64>,
+    /// Platform total counting each energy domain once, with a
+    /// non-overlapping breakdown.
+    #[serde(default)]
+    pub totals: EnergyTotals,
     pub diagnostics: MonitorDiagnostics,
 }
 
-#[derive(Debug, Clone, Default, Serialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct MonitorDiagnostics {
     pub collection_ticks: u64,
     pub process_scans: u64,
     pub process_groups: usize,
     pub tracked_pids: usize,
+    /// Whether sampling is slowed down because the system is idle.
+    pub idle: bool,
+    /// Collector batches discarded because the monitor fell behind.
+    pub dropped_batches: u64,
+    /// Estimated memory held by the in-memory traces, in bytes.
+    #[serde(default)]
+    pub trace_bytes: u64,
+    /// Sum of the traces' high watermarks, in bytes.
+    #[serde(default)]
+    pub trace_peak_bytes: u64,
+    /// Device domains collectors are skipping because they could not be
+    /// discovered or read; the other domains keep reporting.
+    #[serde(default)]
+    pub degraded_domains: Vec<DegradedDomain>,
 }
 
 // ─── Snapshot helpers ───────────────────────────────────────────────────────
 
+/// Sleep for the idle sampling interval, checking CPU utilization at the
+/// normal `interval` so sampling resumes as soon as the system gets busy.
+async fn sleep_while_idle(
+    idle: &mut IdleDetector,
+    cpu_tracker: &mut SystemCpuTracker,
+    interval: Duration,
+    is_running: &AtomicBool,
+) {
+    let deadline = Instant::now() + idle.config().interval();
+    while Instant::now() < deadline && is_running.load(Ordering::SeqCst) {
+        tokio::time::sleep(interval.min(deadline - Instant::now())).await;
+        let (cpu_percent, _) = cpu_tracker.update();
+        if idle.config().is_active_cpu(cpu_percent) {
+            log::info!("System active again ({cpu_percent:.1}% CPU)");
+            idle.wake();
+            return;
+        }
+    }
+}
+
 fn add_device_energy(total: &mut DeviceEnergy, delta: &DeviceEnergy) {
     total.cpu_joules += delta.cpu_joules;
     total.dram_joules += delta.dram_joules;
     total.gpu_joules += delta.gpu_joules;
 }
 
+fn add_energy_by_device(total: &mut BTreeMap<String, f64>, delta: &HashMap<String, f64>) {
+    for (device, joules) in delta {
+        *total.entry(device.clone()).or_default() += joules;
+    }
+}
+
+/// Add a tick's per-device energy to `snap` and refresh its overlap-aware
+/// totals. Auxiliary devices inside an energy domain take part, since the
+/// domain rules keep them from being counted twice.
+fn update_energy_totals(
+    snap: &mut MetricsSnapshot,
+    tick: &GroupedEnergyTick,
+    energy_domains: &[EnergyDomainRule],
+) {
+    add_energy_by_device(&mut snap.auxiliary, &tick.auxiliary);
+    add_energy_by_device(&mut snap.devices, &tick.devices);
+    let mut devices = snap.devices.clone();
+    for (device, joules) in &snap.auxiliary {
+        if energy_domains
+            .iter()
+            .any(|rule| matches_any(&rule.whole, device) || matches_any(&rule.parts, device))
+        {
+            devices.insert(device.clone(), *joules);
+        }
+    }
+    snap.totals = EnergyTotals::from_devices(energy_domains, &devices);
+}
+
 fn system_total_from_workloads(
     workloads: &[WorkloadSnapshot],
     unattributed: &DeviceEnergy,
@@ -194,6 +300,7 @@ fn workload_snapshot(
         energy,
         power_watts,
         percentage_of_system: 0.0,
+        container: group.container.clone(),
     }
 }
 
@@ -320,6 +427,14 @@ fn resolve_process_roots(pids: &[u32]) -> Vec<ProcessRoot> {
         .collect()
 }
 
+/// Groups of all running processes, containers named after their runtime's
+/// records.
+fn discover_process_groups() -> Vec<ProcessGroup> {
+    let mut groups = group_processes(&scan_processes());
+    resolve_containers(&mut groups, &ContainerMetadata::default());
+    groups
+}
+
 fn explicit_pid_groups(root_pids: &[u32]) -> Vec<ProcessGroup> {
     let roots = resolve_process_roots(root_pids);
 
@@ -347,11 +462,126 @@ fn explicit_pid_groups(root_pids: &[u32]) -> Vec<ProcessGroup> {
                 },
                 representative_pid: root.pid,
                 pids,
+                container: None,
+            })
+        })
+        .collect()
+}
+
+/// Container `cgroup` belongs to, with its name and image looked up.
+fn cgroup_container(cgroup: &CgroupTarget) -> Option<ContainerInfo> {
+    let (_, mut container) = detect_container(&cgroup.path().to_string_lossy())?;
+    ContainerMetadata::default().resolve(&mut container);
+    Some(container)
+}
+
+/// One group per cgroup with processes in it. A group keeps the pid its
+/// records carry, its user and its container while that process stays in
+/// the cgroup.
+fn cgroup_groups(
+    cgroups: &[CgroupTarget],
+    known_groups: &HashMap<String, ProcessGroup>,
+) -> Vec<ProcessGroup> {
+    cgroups
+        .iter()
+        .filter_map(|cgroup| {
+            let mut pids = cgroup.pids();
+            pids.sort_unstable();
+            let id = cgroup_group_id(cgroup);
+            let known = known_groups
+                .get(&id)
+                .filter(|group| pids.binary_search(&group.representative_pid).is_ok());
+            let (representative_pid, user, container) = match known {
+                Some(group) => (
+                    group.representative_pid,
+                    group.user.clone(),
+                    group.container.clone(),
+                ),
+                None => {
+                    let root = resolve_process_roots(&[*pids.first()?]).pop()?;
+                    let user = if root.user.is_empty() {
+                        "unknown".to_string()
+                    } else {
+                        root.user
+                    };
+                    (root.pid, user, cgroup_container(cgroup))
+                }
+            };
+            Some(ProcessGroup {
+                id,
+                name: cgroup.name(),
+                user,
+                pids,
+                representative_pid,
+                container,
+            })
+        })
+        .collect()
+}
+
+fn cgroup_group_id(cgroup: &CgroupTarget) -> String {
+    format!("cgroup:{}", cgroup.path().display())
+}
+
+/// The `cgroups` that have a group, each under its group's pid.
+fn tracked_cgroups(cgroups: &[CgroupTarget], groups: &[ProcessGroup]) -> Vec<TrackedCgroup> {
+    cgroups
+        .iter()
+        .filter_map(|cgroup| {
+            let id = cgroup_group_id(cgroup);
+            let group = groups.iter().find(|group| group.id == id)?;
+            Some(TrackedCgroup {
+                pid: group.representative_pid,
+                target: cgroup.clone(),
             })
         })
         .collect()
 }
 
+/// Trace metadata of cgroup groups: their current members and every pid
+/// their records have carried, with the group's user and, as task, the name
+/// of its cgroup or systemd unit.
+fn cgroup_process_metadata(
+    groups: &[ProcessGroup],
+    carried: &mut HashMap<u32, (String, String)>,
+) -> Vec<psutils::ProcessGroup> {
+    for group in groups {
+        carried.insert(
+            group.representative_pid,
+            (group.user.clone(), group.name.clone()),
+        );
+    }
+    let members: HashSet<u32> = groups
+        .iter()
+        .flat_map(|group| group.pids.iter().copied())
+        .collect();
+    let mut metadata: Vec<_> = groups
+        .iter()
+        .map(|group| psutils::ProcessGroup {
+            user: group.user.clone(),
+            task: group.name.clone(),
+            pids: group.pids.iter().map(|&pid| pid as usize).collect(),
+            container: group.container.as_ref().map(ContainerInfo::label),
+            pod: group
+                .container
+                .as_ref()
+                .and_then(|container| container.pod.clone()),
+        })
+        .collect();
+    metadata.extend(
+        carried
+            .iter()
+            .filter(|(pid, _)| !members.contains(pid))
+            .map(|(&pid, (user, task))| psutils::ProcessGroup {
+                user: user.clone(),
+                task: task.clone(),
+                pids: vec![pid as usize],
+                ..psutils::ProcessGroup::default()
+            }),
+    );
+    metadata
+}
+
 fn refresh_explicit_pid_groups(cached_groups: &[ProcessGroup]) -> Vec<ProcessGroup> {
     cached_groups
         .iter()
@@ -387,6 +617,7 @@ fn cached_explicit_pid_groups(
                     user: "unknown".to_string(),
                     pids: vec![*pid],
                     representative_pid: *pid,
+                    container: None,
                 })
         })
         .collect()
@@ -424,15 +655,24 @@ pub struct MonitorHandle {
 }
 
 impl MonitorHandle {
+    /// A handle always reading `snapshot`.
+    #[cfg(test)]
+    pub(crate) fn from_snapshot(snapshot: MetricsSnapshot) -> Self {
+        Self {
+            snapshot: Arc::new(RwLock::new(snapshot)),
+        }
+    }
+
     /// Returns a clone of the current snapshot.
     pub fn snapshot(&self) -> MetricsSnapshot {
         self.snapshot.read().unwrap().clone()
     }
 
-    /// Returns the total consumed energy in joules across all device types.
+    /// Returns the total consumed energy in joules across all device types,
+    /// counting each energy domain once.
     pub fn total_consumed_energy(&self) -> f64 {
         let snap = self.snapshot.read().unwrap();
-        snap.system_total.total()
+        snap.totals.total_joules
     }
 
     /// Returns a per-PID energy map (sum of all device types per PID).
@@ -447,6 +687,79 @@ impl MonitorHandle {
     }
 }
 
+/// Adds root processes to a running monitor of explicit pids, from
+/// [`Monitor::process_tracker`].
+#[derive(Clone)]
+pub struct ProcessTracker {
+    root_pids: Arc<RwLock<Vec<u32>>>,
+    known_groups: Arc<RwLock<HashMap<String, ProcessGroup>>>,
+}
+
+impl ProcessTracker {
+    /// Track `pid` and the children it forks from the next collection tick.
+    /// Returns whether it was not tracked before, or `None` when it is not
+    /// running. Reads the process table, so async callers should run it on
+    /// a blocking thread.
+    pub fn track(&self, pid: u32) -> Option<bool> {
+        if self.root_pids.read().unwrap().contains(&pid) {
+            return Some(false);
+        }
+        let group = explicit_pid_groups(&[pid]).pop()?;
+        self.known_groups
+            .write()
+            .unwrap()
+            .insert(group.id.clone(), group);
+        let mut root_pids = self.root_pids.write().unwrap();
+        if root_pids.contains(&pid) {
+            return Some(false);
+        }
+        root_pids.push(pid);
+        Some(true)
+    }
+
+    /// Root processes currently tracked.
+    pub fn root_pids(&self) -> Vec<u32> {
+        self.root_pids.read().unwrap().clone()
+    }
+}
+
+/// Queries the traces of a running monitor's collector groups, from
+/// [`Monitor::trace_reader`].
+#[derive(Clone, Default)]
+pub struct TraceReader {
+    groups: Vec<Arc<dyn GroupQuery>>,
+}
+
+impl TraceReader {
+    /// Also query `group`.
+    pub fn add_group<C: EnergyCollector>(&mut self, group: Arc<Mutex<EnergyGroup<C>>>) {
+        self.groups.push(group);
+    }
+
+    /// Rows of every group within `range` that pass `filter`, as returned by
+    /// [`EnergyGroup::query`], one frame per group.
+    pub async fn query(&self, range: TimeRange, filter: &Filter) -> PolarsResult<Vec<DataFrame>> {
+        let mut frames = Vec::with_capacity(self.groups.len());
+        for group in &self.groups {
+            frames.push(group.query(range, filter).await?);
+        }
+        Ok(frames)
+    }
+}
+
+/// [`EnergyGroup::query`] behind the group's lock, for any collector.
+#[async_trait]
+trait GroupQuery: Send + Sync {
+    async fn query(&self, range: TimeRange, filter: &Filter) -> PolarsResult<DataFrame>;
+}
+
+#[async_trait]
+impl<C: EnergyCollector> GroupQuery for Mutex<EnergyGroup<C>> {
+    async fn query(&self, range: TimeRange, filter: &Filter) -> PolarsResult<DataFrame> {
+        self.lock().await.query(range, filter)?.collect()
+    }
+}
+
 // ─── Internal state for power computation ───────────────────────────────────
 
 /// Tracks cumulative state for computing power (watts).
@@ -476,9 +789,26 @@ impl Default for TickState {
 /// Central coordinator that owns all collectors, process discovery, and runs autonomously.
 pub struct Monitor {
     config: EmtConfig,
-    rapl_group: Arc<Mutex<EnergyGroup<Rapl>>>,
+    cpu_group: Arc<Mutex<EnergyGroup<SamplerSubscription<CpuEnergy>>>>,
+    /// CPU energy backend chosen by preflight, if any.
+    cpu_backend: Option<CpuEnergyBackend>,
     gpu_group: Option<Arc<Mutex<EnergyGroup<NvidiaGpu>>>>,
-    root_pids: Option<Vec<u32>>,
+    amd_gpu_group: Option<Arc<Mutex<EnergyGroup<AmdGpu>>>>,
+    intel_gpu_group: Option<Arc<Mutex<EnergyGroup<IntelGpu>>>>,
+    /// Power rails of Jetson boards, which have no NVML energy counters.
+    jetson_group: Option<Arc<Mutex<EnergyGroup<JetsonIna>>>>,
+    /// Meters read by the configured `exec_collectors`, `http_collectors`
+    /// and `bmc_collectors`.
+    meter_group: Option<Arc<Mutex<EnergyGroup<MeterCollector>>>>,
+    /// Network interfaces, when `network` configures their energy model.
+    network_group: Option<Arc<Mutex<EnergyGroup<Network>>>>,
+    /// Storage devices, when `storage` configures their energy model.
+    storage_group: Option<Arc<Mutex<EnergyGroup<Storage>>>>,
+    /// Root processes tracked with their children, shared with
+    /// [`ProcessTracker`]s; every process when `None`.
+    root_pids: Option<Arc<RwLock<Vec<u32>>>>,
+    /// Cgroups monitored as a whole instead of processes, when not empty.
+    cgroups: CgroupAttributor,
     /// Shared state for scan task results in monitor-all mode.
     discovered_groups: Arc<RwLock<Vec<ProcessGroup>>>,
     /// Group metadata retained for cumulative reporting.
@@ -491,6 +821,12 @@ pub struct Monitor {
     process_scan_count: Arc<AtomicU64>,
     /// Device source/provenance metadata for public outputs.
     sources: DeviceSources,
+    /// Virtual devices computed every tick, stored in the CPU group's trace.
+    derived_devices: Arc<DerivedDevices>,
+    /// Authoritative/auxiliary resolution for overlapping devices.
+    device_priority: Arc<RwLock<DevicePriority>>,
+    /// Enforces configured energy budgets on every snapshot.
+    budgets: Arc<std::sync::Mutex<BudgetEnforcer>>,
     /// Internal task handles
     tick_handle: Option<JoinHandle<()>>,
     scan_handle: Option<JoinHandle<()>>,
@@ -504,49 +840,130 @@ impl Monitor {
     /// If `root_pids` is None, the monitor will use a background scan task
     /// to discover all root processes on the system.
     pub fn new(config: EmtConfig, root_pids: Option<Vec<u32>>) -> Self {
+        if let Some(dir) = config.collection.trace_dir.as_deref()
+            && let Err(e) = HostInfo::local().write_if_missing(Path::new(dir))
+        {
+            log::warn!("Failed to write {} to {}: {}", HOST_FILE_NAME, dir, e);
+        }
         let rate = config.collection.rate_hz;
         // Live monitors publish every collection tick. Batching remains available
         // at the lower EnergyGroup layer for explicit callers.
         let batch_size = Some(1);
-        let rapl = Rapl::default();
-        let mut sources = rapl.device_sources();
-        let mut rapl_group = EnergyGroup::new(rapl, rate, batch_size);
-        rapl_group.set_trace_retention(config.collection.trace_retention_secs as i64);
-        rapl_group.set_recorder_flush_interval(Duration::from_secs_f64(
-            config.collection.trace_flush_interval_secs,
-        ));
+        // CPU counters are read through the process-wide sampler, shared
+        // with other monitors and CPU groups.
+        let mut cpu_group = EnergyGroup::cpu(&config.cpu_energy, rate, batch_size);
+        let cpu = cpu_group.collector().sampler().collector();
+        let cpu_backend = cpu.backend();
+        let mut sources = cpu.device_sources();
+        cpu_group.apply_config(&config, "cpu");
 
         // Auto-detect GPU availability
+        let enabled = |kind| {
+            config.collection.collectors.is_empty() || config.collection.collectors.contains(&kind)
+        };
+        let gpus_enabled = std::env::var_os("EMT_DISABLE_GPU").is_none();
         let gpu_group =
-            if std::env::var_os("EMT_DISABLE_GPU").is_none() && NvidiaGpu::is_available() {
+            if gpus_enabled && enabled(CollectorKind::Nvidia) && NvidiaGpu::is_available() {
                 let mut group = EnergyGroup::new(NvidiaGpu::default(), rate, batch_size);
-                group.set_trace_retention(config.collection.trace_retention_secs as i64);
-                group.set_recorder_flush_interval(Duration::from_secs_f64(
-                    config.collection.trace_flush_interval_secs,
-                ));
+                group.apply_config(&config, "gpu");
                 Some(Arc::new(Mutex::new(group)))
             } else {
                 None
             };
+        let amd_gpu_group = (gpus_enabled && enabled(CollectorKind::Amd) && AmdGpu::is_available())
+            .then(|| {
+                let mut group = EnergyGroup::new(AmdGpu::default(), rate, batch_size);
+                group.apply_config(&config, "amd_gpu");
+                Arc::new(Mutex::new(group))
+            });
+        let intel_gpu_group = (gpus_enabled
+            && enabled(CollectorKind::Intel)
+            && IntelGpu::is_available())
+        .then(|| {
+            let mut group = EnergyGroup::new(IntelGpu::default(), rate, batch_size);
+            group.apply_config(&config, "intel_gpu");
+            Arc::new(Mutex::new(group))
+        });
+        let jetson_group =
+            (enabled(CollectorKind::Jetson) && JetsonIna::is_available()).then(|| {
+                let mut group = EnergyGroup::new(JetsonIna::default(), rate, batch_size);
+                group.apply_config(&config, "jetson");
+                Arc::new(Mutex::new(group))
+            });
+
+        let has_meters = enabled(CollectorKind::Meters)
+            && !(config.exec_collectors.is_empty()
+                && config.http_collectors.is_empty()
+                && config.bmc_collectors.is_empty());
+        let meter_group = has_meters.then(|| {
+            let collector = MeterCollector::new(&config.exec_collectors, &config.http_collectors)
+                .with_bmc(&config.bmc_collectors);
+            let mut group = EnergyGroup::new(collector, rate, batch_size);
+            group.apply_config(&config, "meters");
+            Arc::new(Mutex::new(group))
+        });
+        let network_group = config
+            .network
+            .clone()
+            .filter(|_| enabled(CollectorKind::Network) && Network::is_available())
+            .map(|network| {
+                let mut group = EnergyGroup::new(Network::new(network), rate, batch_size);
+                group.apply_config(&config, "network");
+                Arc::new(Mutex::new(group))
+            });
+        let storage_group = config
+            .storage
+            .clone()
+            .filter(|_| enabled(CollectorKind::Storage) && Storage::is_available())
+            .map(|storage| {
+                let mut group = EnergyGroup::new(Storage::new(storage), rate, batch_size);
+                group.apply_config(&config, "storage");
+                Arc::new(Mutex::new(group))
+            });
 
-        let gpu_available = gpu_group.is_some();
+        let gpu_available =
+            gpu_group.is_some() || amd_gpu_group.is_some() || intel_gpu_group.is_some();
         sources.gpu = if gpu_available {
             DeviceSource::Measured
         } else {
             DeviceSource::Unavailable
         };
 
+        let device_priority = Arc::new(RwLock::new(DevicePriority::new(
+            config.device_priority.clone(),
+        )));
+
+        let derived_devices = DerivedDevices::new(&config.derived_devices).unwrap_or_else(|e| {
+            log::error!("Derived devices disabled: {}", e);
+            DerivedDevices::default()
+        });
+
+        let budgets = Arc::new(std::sync::Mutex::new(BudgetEnforcer::new(
+            config.budgets.clone(),
+        )));
+
         Self {
             config,
-            rapl_group: Arc::new(Mutex::new(rapl_group)),
+            cpu_group: Arc::new(Mutex::new(cpu_group)),
+            cpu_backend,
             gpu_group,
-            root_pids,
+            amd_gpu_group,
+            intel_gpu_group,
+            jetson_group,
+            meter_group,
+            network_group,
+            storage_group,
+            root_pids: root_pids.map(|pids| Arc::new(RwLock::new(pids))),
+            cgroups: CgroupAttributor::default(),
             discovered_groups: Arc::new(RwLock::new(Vec::new())),
             known_groups: Arc::new(RwLock::new(HashMap::new())),
             last_pid_to_group: Arc::new(RwLock::new(HashMap::new())),
             start_timestamp: Arc::new(RwLock::new(0)),
             process_scan_count: Arc::new(AtomicU64::new(0)),
             sources: sources.clone(),
+            derived_devices: Arc::new(derived_devices),
+            device_priority,
+            budgets,
             tick_handle: None,
             scan_handle: None,
             snapshot: Arc::new(RwLock::new(MetricsSnapshot {
@@ -558,6 +975,74 @@ impl Monitor {
         }
     }
 
+    /// Create a Monitor that attributes energy to each of `cgroups` as a
+    /// whole from its own CPU and memory accounting, kernel time included.
+    /// Processes entering or leaving a cgroup are followed without scanning
+    /// for them, and so are the child cgroups of a [`CgroupAttributor`]'s
+    /// parents.
+    pub fn for_cgroups(config: EmtConfig, cgroups: impl Into<CgroupAttributor>) -> Self {
+        let mut monitor = Self::new(config, None);
+        monitor.cgroups = cgroups.into();
+        monitor
+    }
+
+    /// Whether processes are discovered by scanning the whole system.
+    fn monitors_all(&self) -> bool {
+        self.root_pids.is_none() && self.cgroups.is_empty()
+    }
+
+    /// A reader querying the traces of every collector group while the
+    /// monitor runs.
+    pub fn trace_reader(&self) -> TraceReader {
+        let mut reader = TraceReader::default();
+        reader.add_group(Arc::clone(&self.cpu_group));
+        if let Some(gpu) = &self.gpu_group {
+            reader.add_group(Arc::clone(gpu));
+        }
+        if let Some(gpu) = &self.amd_gpu_group {
+            reader.add_group(Arc::clone(gpu));
+        }
+        if let Some(gpu) = &self.intel_gpu_group {
+            reader.add_group(Arc::clone(gpu));
+        }
+        if let Some(jetson) = &self.jetson_group {
+            reader.add_group(Arc::clone(jetson));
+        }
+        if let Some(meters) = &self.meter_group {
+            reader.add_group(Arc::clone(meters));
+        }
+        if let Some(network) = &self.network_group {
+            reader.add_group(Arc::clone(network));
+        }
+        if let Some(storage) = &self.storage_group {
+            reader.add_group(Arc::clone(storage));
+        }
+        reader
+    }
+
+    /// A tracker adding root processes while the monitor runs, unless it
+    /// monitors every process or cgroups.
+    pub fn process_tracker(&self) -> Option<ProcessTracker> {
+        let root_pids = self
+            .root_pids
+            .as_ref()
+            .filter(|_| self.cgroups.is_empty())?;
+        Some(ProcessTracker {
+            root_pids: Arc::clone(root_pids),
+            known_groups: Arc::clone(&self.known_groups),
+        })
+    }
+
+    /// Whether an NVIDIA or AMD GPU is monitored.
+    fn gpu_available(&self) -> bool {
+        self.gpu_group.is_some() || self.amd_gpu_group.is_some() || self.intel_gpu_group.is_some()
+    }
+
+    /// CPU energy backend selected by the preflight chain, if any.
+    pub fn cpu_backend(&self) -> Option<CpuEnergyBackend> {
+        self.cpu_backend
+    }
+
     /// Start the monitor and return a handle for reading state.
     /// If already running, returns a new handle to the existing shared snapshot.
     pub async fn commence(&mut self) -> Result<MonitorHandle, MonitoringError> {
@@ -573,23 +1058,35 @@ impl Monitor {
         *self.start_timestamp.write().unwrap() = 0;
         self.process_scan_count.store(0, Ordering::SeqCst);
         *self.snapshot.write().unwrap() = MetricsSnapshot {
-            gpu_available: self.gpu_group.is_some(),
+            gpu_available: self.gpu_available(),
             sources: self.sources.clone(),
             ..MetricsSnapshot::default()
         };
 
-        let initial_groups = if let Some(root_pids) = &self.root_pids {
-            let pids = root_pids.clone();
+        let mut initial_cgroups = Vec::new();
+        let initial_groups = if !self.cgroups.is_empty() {
+            let attributor = self.cgroups.clone();
+            let groups;
+            (initial_cgroups, groups) = tokio::task::spawn_blocking(move || {
+                let cgroups = attributor.cgroups();
+                let groups = cgroup_groups(&cgroups, &HashMap::new());
+                (cgroups, groups)
+            })
+            .await
+            .unwrap_or_default();
+            groups
+        } else if let Some(root_pids) = &self.root_pids {
+            let pids = root_pids.read().unwrap().clone();
             tokio::task::spawn_blocking(move || explicit_pid_groups(&pids))
                 .await
                 .unwrap_or_default()
         } else {
-            tokio::task::spawn_blocking(|| group_processes(&scan_processes()))
+            tokio::task::spawn_blocking(discover_process_groups)
                 .await
                 .unwrap_or_default()
         };
 
-        if self.root_pids.is_none() {
+        if self.monitors_all() {
             *self.discovered_groups.write().unwrap() = initial_groups.clone();
             self.process_scan_count.store(1, Ordering::SeqCst);
         }
@@ -609,11 +1106,12 @@ impl Monitor {
 
         // Start collector background tasks
         {
-            let mut rapl = self.rapl_group.lock().await;
+            let mut cpu = self.cpu_group.lock().await;
             if !initial_tracked_pids.is_empty() {
-                rapl.update_tracked_pids(initial_tracked_pids.clone());
+                cpu.update_tracked_pids(initial_tracked_pids.clone());
             }
-            rapl.commence().await?;
+            cpu.update_tracked_cgroups(tracked_cgroups(&initial_cgroups, &initial_groups));
+            cpu.commence().await?;
         }
         if let Some(gpu) = &self.gpu_group {
             let mut gpu_lock = gpu.lock().await;
@@ -622,9 +1120,47 @@ impl Monitor {
             }
             gpu_lock.commence().await?;
         }
+        if let Some(gpu) = &self.amd_gpu_group {
+            let mut gpu_lock = gpu.lock().await;
+            if !initial_tracked_pids.is_empty() {
+                gpu_lock.update_tracked_pids(initial_tracked_pids.clone());
+            }
+            gpu_lock.commence().await?;
+        }
+        if let Some(gpu) = &self.intel_gpu_group {
+            let mut gpu_lock = gpu.lock().await;
+            if !initial_tracked_pids.is_empty() {
+                gpu_lock.update_tracked_pids(initial_tracked_pids.clone());
+            }
+            gpu_lock.commence().await?;
+        }
+        if let Some(jetson) = &self.jetson_group {
+            let mut jetson_lock = jetson.lock().await;
+            if !initial_tracked_pids.is_empty() {
+                jetson_lock.update_tracked_pids(initial_tracked_pids.clone());
+            }
+            jetson_lock.commence().await?;
+        }
+        if let Some(meters) = &self.meter_group {
+            meters.lock().await.commence().await?;
+        }
+        if let Some(network) = &self.network_group {
+            let mut network_lock = network.lock().await;
+            if !initial_tracked_pids.is_empty() {
+                network_lock.update_tracked_pids(initial_tracked_pids.clone());
+            }
+            network_lock.commence().await?;
+        }
+        if let Some(storage) = &self.storage_group {
+            let mut storage_lock = storage.lock().await;
+            if !initial_tracked_pids.is_empty() {
+                storage_lock.update_tracked_pids(initial_tracked_pids.clone());
+            }
+            storage_lock.commence().await?;
+        }
 
-        // If no specific root_pids, spawn scan task for automatic discovery
-        if self.root_pids.is_none() {
+        // If no specific root_pids or cgroups, spawn scan task for automatic discovery
+        if self.monitors_all() {
             self.spawn_scan_task();
         }
 
@@ -637,8 +1173,11 @@ impl Monitor {
     }
 
     /// Shut down the monitor, stopping all background tasks and collectors.
+    ///
+    /// A monitor that was running then runs the configured
+    /// `export.on_shutdown` actions.
     pub async fn shutdown(&mut self) -> Result<(), MonitoringError> {
-        self.is_running.store(false, Ordering::SeqCst);
+        let was_running = self.is_running.swap(false, Ordering::SeqCst);
 
         // Abort background tasks
         if let Some(handle) = self.tick_handle.take() {
@@ -651,19 +1190,179 @@ impl Monitor {
         // Shutdown collector groups and collect their final buffered batches.
         let mut final_records = Vec::new();
         {
-            let mut rapl = self.rapl_group.lock().await;
-            final_records.extend(rapl.shutdown_and_drain()?);
+            let mut cpu = self.cpu_group.lock().await;
+            final_records.extend(cpu.shutdown_and_drain().await?);
         }
         if let Some(gpu) = &self.gpu_group {
             let mut gpu_lock = gpu.lock().await;
-            final_records.extend(gpu_lock.shutdown_and_drain()?);
+            final_records.extend(gpu_lock.shutdown_and_drain().await?);
+        }
+        if let Some(gpu) = &self.amd_gpu_group {
+            final_records.extend(gpu.lock().await.shutdown_and_drain().await?);
+        }
+        if let Some(gpu) = &self.intel_gpu_group {
+            final_records.extend(gpu.lock().await.shutdown_and_drain().await?);
+        }
+        if let Some(jetson) = &self.jetson_group {
+            final_records.extend(jetson.lock().await.shutdown_and_drain().await?);
+        }
+        if let Some(meters) = &self.meter_group {
+            final_records.extend(meters.lock().await.shutdown_and_drain().await?);
+        }
+        if let Some(network) = &self.network_group {
+            final_records.extend(network.lock().await.shutdown_and_drain().await?);
+        }
+        if let Some(storage) = &self.storage_group {
+            final_records.extend(storage.lock().await.shutdown_and_drain().await?);
         }
 
         self.apply_final_records_to_snapshot(&final_records);
+        self.budgets.lock().unwrap().release();
+        if was_running {
+            self.run_shutdown_actions().await;
+        }
 
         Ok(())
     }
 
+    /// Register a recorder built by `recorder` with every collector group,
+    /// which it is given the name of, as used under `collection.trace_dir`.
+    pub async fn add_recorders(&self, recorder: impl Fn(&str) -> Box<dyn TraceRecorder>) {
+        self.cpu_group.lock().await.add_recorder(recorder("cpu"));
+        if let Some(gpu) = &self.gpu_group {
+            gpu.lock().await.add_recorder(recorder("gpu"));
+        }
+        if let Some(gpu) = &self.amd_gpu_group {
+            gpu.lock().await.add_recorder(recorder("amd_gpu"));
+        }
+        if let Some(gpu) = &self.intel_gpu_group {
+            gpu.lock().await.add_recorder(recorder("intel_gpu"));
+        }
+        if let Some(jetson) = &self.jetson_group {
+            jetson.lock().await.add_recorder(recorder("jetson"));
+        }
+        if let Some(meters) = &self.meter_group {
+            meters.lock().await.add_recorder(recorder("meters"));
+        }
+        if let Some(network) = &self.network_group {
+            network.lock().await.add_recorder(recorder("network"));
+        }
+        if let Some(storage) = &self.storage_group {
+            storage.lock().await.add_recorder(recorder("storage"));
+        }
+    }
+
+    /// Register a sink built by `sink` with every collector group, which it
+    /// is given the name of, so it receives the records of all of them.
+    pub async fn add_sinks(&self, sink: impl Fn(&str) -> Box<dyn TraceSink>) {
+        self.cpu_group.lock().await.add_sink(sink("cpu"));
+        if let Some(gpu) = &self.gpu_group {
+            gpu.lock().await.add_sink(sink("gpu"));
+        }
+        if let Some(gpu) = &self.amd_gpu_group {
+            gpu.lock().await.add_sink(sink("amd_gpu"));
+        }
+        if let Some(gpu) = &self.intel_gpu_group {
+            gpu.lock().await.add_sink(sink("intel_gpu"));
+        }
+        if let Some(jetson) = &self.jetson_group {
+            jetson.lock().await.add_sink(sink("jetson"));
+        }
+        if let Some(meters) = &self.meter_group {
+            meters.lock().await.add_sink(sink("meters"));
+        }
+        if let Some(network) = &self.network_group {
+            network.lock().await.add_sink(sink("network"));
+        }
+        if let Some(storage) = &self.storage_group {
+            storage.lock().await.add_sink(sink("storage"));
+        }
+    }
+
+    /// Energy trace of all collector groups, joined with process and device
+    /// metadata as in [`EnergyGroup::joined_trace`].
+    pub async fn joined_trace(&self) -> PolarsResult<LazyFrame> {
+        let mut traces = vec![self.cpu_group.lock().await.joined_trace()?];
+        if let Some(gpu) = &self.gpu_group {
+            traces.push(gpu.lock().await.joined_trace()?);
+        }
+        if let Some(gpu) = &self.amd_gpu_group {
+            traces.push(gpu.lock().await.joined_trace()?);
+        }
+        if let Some(gpu) = &self.intel_gpu_group {
+            traces.push(gpu.lock().await.joined_trace()?);
+        }
+        if let Some(jetson) = &self.jetson_group {
+            traces.push(jetson.lock().await.joined_trace()?);
+        }
+        if let Some(meters) = &self.meter_group {
+            traces.push(meters.lock().await.joined_trace()?);
+        }
+        if let Some(network) = &self.network_group {
+            traces.push(network.lock().await.joined_trace()?);
+        }
+        if let Some(storage) = &self.storage_group {
+            traces.push(storage.lock().await.joined_trace()?);
+        }
+        concat(traces, UnionArgs::default())
+    }
+
+    /// [`joined_trace`](Self::joined_trace) with the rows and columns kept
+    /// by `export.filter`, resampled to `export.resample`.
+    async fn export_trace(&self) -> Result<LazyFrame, ConvertError> {
+        let selection = &self.config.export.filter;
+        let mut trace = filter_trace(
+            self.joined_trace().await?.collect()?,
+            TimeRange::all(),
+            &selection.filter(),
+        )?;
+        if let Some(every) = self.config.export.resample() {
+            trace = resample_trace(trace, every)?;
+        }
+        let trace = self.config.device_labels().apply_to_trace(trace)?;
+        Ok(project_trace(trace, &selection.columns)?.lazy())
+    }
+
+    /// Run each `export.on_shutdown` action in order. Failures are logged and
+    /// do not stop the remaining actions.
+    async fn run_shutdown_actions(&self) {
+        let actions = &self.config.export.on_shutdown;
+        if actions.is_empty() {
+            return;
+        }
+        let cipher = match TraceCipher::from_config(&self.config.encryption) {
+            Ok(cipher) => cipher,
+            Err(e) => {
+                log::error!(
+                    "Skipping shutdown exports, encryption key unavailable: {}",
+                    e
+                );
+                return;
+            }
+        };
+        let trace = match self.export_trace().await {
+            Ok(trace) => trace,
+            Err(e) => {
+                log::error!("Skipping shutdown exports, failed to build trace: {}", e);
+                return;
+            }
+        };
+        let snapshot = self.snapshot.read().unwrap().clone();
+        let artifacts = ShutdownArtifacts {
+            trace,
+            snapshot: &snapshot,
+            timezone: self.config.export.timezone(),
+            cipher: cipher.as_ref(),
+            device_labels: self.config.device_labels(),
+        };
+        for action in actions {
+            match run_shutdown_action(action, &artifacts).await {
+                Ok(()) => log::info!("Shutdown export written to {}", action.target()),
+                Err(e) => log::error!("Shutdown export to {} failed: {}", action.target(), e),
+            }
+        }
+    }
+
     fn apply_final_records_to_snapshot(&self, final_records: &[EnergyRecord]) {
         if final_records.is_empty() {
             return;
@@ -675,8 +1374,15 @@ impl Monitor {
         };
         let current_pid_to_group = self.last_pid_to_group.read().unwrap().clone();
         let pid_to_group = merge_pid_group_maps(&current_pid_to_group, &retained_pid_to_group);
-        let tick = aggregate_energy_records(final_records, &pid_to_group);
-        if tick.system_total.total() <= 0.0 && tick.group_energy.is_empty() {
+        let tick = {
+            let mut priority = self.device_priority.write().unwrap();
+            priority.observe(final_records);
+            aggregate_energy_records_with_priority(final_records, &pid_to_group, &priority)
+        };
+        if tick.system_total.total() <= 0.0
+            && tick.group_energy.is_empty()
+            && tick.auxiliary.is_empty()
+        {
             return;
         }
 
@@ -713,6 +1419,7 @@ impl Monitor {
                         user: "unknown".to_string(),
                         pids,
                         representative_pid,
+                        container: None,
                     },
                 );
             }
@@ -760,6 +1467,7 @@ impl Monitor {
             add_device_energy(entry, tick_energy);
         }
         add_device_energy(&mut snap.unattributed, &tick.unattributed);
+        update_energy_totals(&mut snap, &tick, &self.config.energy_domains);
 
         let mut workloads = workload_snapshots_for_known_groups(
             &known_groups_snapshot,
@@ -781,7 +1489,7 @@ impl Monitor {
         apply_workload_percentages(&mut workloads, &system_total);
 
         snap.timestamp = current_timestamp;
-        snap.gpu_available = self.gpu_group.is_some();
+        snap.gpu_available = self.gpu_available();
         snap.sources = self.sources.clone();
         snap.workloads = workloads;
         snap.system_total = system_total;
@@ -790,29 +1498,59 @@ impl Monitor {
     /// Spawn the tick task that runs the core polling and update loop.
     fn spawn_tick_task(&mut self) {
         let interval = Duration::from_secs_f64(1.0 / self.config.collection.rate_hz);
-        let rapl_group = Arc::clone(&self.rapl_group);
+        let cpu_group = Arc::clone(&self.cpu_group);
         let gpu_group = self.gpu_group.clone();
-        let gpu_available = gpu_group.is_some();
+        let amd_gpu_group = self.amd_gpu_group.clone();
+        let intel_gpu_group = self.intel_gpu_group.clone();
+        let gpu_available =
+            gpu_group.is_some() || amd_gpu_group.is_some() || intel_gpu_group.is_some();
+        let jetson_group = self.jetson_group.clone();
+        let meter_group = self.meter_group.clone();
+        let network_group = self.network_group.clone();
+        let storage_group = self.storage_group.clone();
         let root_pids = self.root_pids.clone();
+        let attributor = self.cgroups.clone();
         let discovered_groups = Arc::clone(&self.discovered_groups);
         let known_groups = Arc::clone(&self.known_groups);
         let last_pid_to_group = Arc::clone(&self.last_pid_to_group);
         let start_timestamp = Arc::clone(&self.start_timestamp);
         let process_scan_count = Arc::clone(&self.process_scan_count);
         let sources = self.sources.clone();
+        let derived_devices = Arc::clone(&self.derived_devices);
+        let device_priority = Arc::clone(&self.device_priority);
+        let energy_domains = self.config.energy_domains.clone();
+        let budgets = Arc::clone(&self.budgets);
         let snapshot = Arc::clone(&self.snapshot);
         let is_running = Arc::clone(&self.is_running);
+        let mut idle = IdleDetector::new(self.config.collection.idle.clone());
 
         self.tick_handle = Some(tokio::spawn(async move {
             let mut tick_state = TickState::default();
+            let mut carried_cgroup_pids = HashMap::new();
             let mut collection_ticks = 0_u64;
+            let mut cpu_tracker = SystemCpuTracker::default();
+            let mut last_tick: Option<Instant> = None;
 
             while is_running.load(Ordering::SeqCst) {
                 collection_ticks += 1;
-                let groups = if let Some(ref pids) = root_pids {
+                let mut cgroups = Vec::new();
+                let groups = if !attributor.is_empty() {
+                    let known = known_groups.read().unwrap().clone();
+                    let attributor = attributor.clone();
+                    let groups;
+                    (cgroups, groups) = tokio::task::spawn_blocking(move || {
+                        let cgroups = attributor.cgroups();
+                        let groups = cgroup_groups(&cgroups, &known);
+                        (cgroups, groups)
+                    })
+                    .await
+                    .unwrap_or_default();
+                    groups
+                } else if let Some(ref pids) = root_pids {
                     let cached_groups = {
+                        let pids = pids.read().unwrap();
                         let known = known_groups.read().unwrap();
-                        cached_explicit_pid_groups(pids, &known)
+                        cached_explicit_pid_groups(&pids, &known)
                     };
                     tokio::task::spawn_blocking(move || refresh_explicit_pid_groups(&cached_groups))
                         .await
@@ -834,26 +1572,180 @@ impl Monitor {
                 let active_pid_to_group =
                     merge_pid_group_maps(&current_pid_to_group, &previous_pid_to_group);
 
-                let rapl_records;
+                // Cgroup groups name their processes in the trace's task column
+                let cgroup_metadata = (!attributor.is_empty())
+                    .then(|| cgroup_process_metadata(&groups, &mut carried_cgroup_pids));
+
+                let cpu_records;
+                let mut dropped_batches;
+                let trace_memory;
+                let mut degraded_domains;
                 {
-                    let mut rapl = rapl_group.lock().await;
-                    rapl.update_tracked_pids(expanded_pids.clone());
-                    rapl_records = rapl.poll_data();
+                    let mut cpu = cpu_group.lock().await;
+                    match &cgroup_metadata {
+                        Some(metadata) => cpu.set_tracked_processes(metadata),
+                        None => cpu.update_tracked_pids(expanded_pids.clone()),
+                    }
+                    cpu.update_tracked_cgroups(tracked_cgroups(&cgroups, &groups));
+                    cpu_records = cpu.poll_data();
+                    dropped_batches = cpu.dropped_batches();
+                    trace_memory = cpu.trace_stats();
+                    degraded_domains = cpu.degraded_domains();
                 }
+                let mut trace_bytes = trace_memory.estimated_bytes() as u64;
+                let mut trace_peak_bytes = trace_memory.peak_bytes() as u64;
 
                 let gpu_records = if let Some(ref gpu) = gpu_group {
                     let mut gpu_lock = gpu.lock().await;
-                    gpu_lock.update_tracked_pids(expanded_pids.clone());
-                    gpu_lock.poll_data()
+                    match &cgroup_metadata {
+                        Some(metadata) => gpu_lock.set_tracked_processes(metadata),
+                        None => gpu_lock.update_tracked_pids(expanded_pids.clone()),
+                    }
+                    dropped_batches += gpu_lock.dropped_batches();
+                    degraded_domains.extend(gpu_lock.degraded_domains());
+                    let records = gpu_lock.poll_data();
+                    let memory = gpu_lock.trace_stats();
+                    trace_bytes += memory.estimated_bytes() as u64;
+                    trace_peak_bytes += memory.peak_bytes() as u64;
+                    records
+                } else {
+                    Vec::new()
+                };
+
+                let amd_gpu_records = if let Some(ref gpu) = amd_gpu_group {
+                    let mut gpu_lock = gpu.lock().await;
+                    match &cgroup_metadata {
+                        Some(metadata) => gpu_lock.set_tracked_processes(metadata),
+                        None => gpu_lock.update_tracked_pids(expanded_pids.clone()),
+                    }
+                    dropped_batches += gpu_lock.dropped_batches();
+                    degraded_domains.extend(gpu_lock.degraded_domains());
+                    let records = gpu_lock.poll_data();
+                    let memory = gpu_lock.trace_stats();
+                    trace_bytes += memory.estimated_bytes() as u64;
+                    trace_peak_bytes += memory.peak_bytes() as u64;
+                    records
+                } else {
+                    Vec::new()
+                };
+
+                let intel_gpu_records = if let Some(ref gpu) = intel_gpu_group {
+                    let mut gpu_lock = gpu.lock().await;
+                    match &cgroup_metadata {
+                        Some(metadata) => gpu_lock.set_tracked_processes(metadata),
+                        None => gpu_lock.update_tracked_pids(expanded_pids.clone()),
+                    }
+                    dropped_batches += gpu_lock.dropped_batches();
+                    degraded_domains.extend(gpu_lock.degraded_domains());
+                    let records = gpu_lock.poll_data();
+                    let memory = gpu_lock.trace_stats();
+                    trace_bytes += memory.estimated_bytes() as u64;
+                    trace_peak_bytes += memory.peak_bytes() as u64;
+                    records
                 } else {
                     Vec::new()
                 };
 
-                let mut all_records = rapl_records;
+                let jetson_records = if let Some(ref jetson) = jetson_group {
+                    let mut jetson_lock = jetson.lock().await;
+                    match &cgroup_metadata {
+                        Some(metadata) => jetson_lock.set_tracked_processes(metadata),
+                        None => jetson_lock.update_tracked_pids(expanded_pids.clone()),
+                    }
+                    dropped_batches += jetson_lock.dropped_batches();
+                    degraded_domains.extend(jetson_lock.degraded_domains());
+                    let records = jetson_lock.poll_data();
+                    let memory = jetson_lock.trace_stats();
+                    trace_bytes += memory.estimated_bytes() as u64;
+                    trace_peak_bytes += memory.peak_bytes() as u64;
+                    records
+                } else {
+                    Vec::new()
+                };
+
+                let meter_records = if let Some(ref meters) = meter_group {
+                    let mut meter_lock = meters.lock().await;
+                    dropped_batches += meter_lock.dropped_batches();
+                    degraded_domains.extend(meter_lock.degraded_domains());
+                    let records = meter_lock.poll_data();
+                    let memory = meter_lock.trace_stats();
+                    trace_bytes += memory.estimated_bytes() as u64;
+                    trace_peak_bytes += memory.peak_bytes() as u64;
+                    records
+                } else {
+                    Vec::new()
+                };
+
+                let network_records = if let Some(ref network) = network_group {
+                    let mut network_lock = network.lock().await;
+                    match &cgroup_metadata {
+                        Some(metadata) => network_lock.set_tracked_processes(metadata),
+                        None => network_lock.update_tracked_pids(expanded_pids.clone()),
+                    }
+                    dropped_batches += network_lock.dropped_batches();
+                    degraded_domains.extend(network_lock.degraded_domains());
+                    let records = network_lock.poll_data();
+                    let memory = network_lock.trace_stats();
+                    trace_bytes += memory.estimated_bytes() as u64;
+                    trace_peak_bytes += memory.peak_bytes() as u64;
+                    records
+                } else {
+                    Vec::new()
+                };
+
+                let storage_records = if let Some(ref storage) = storage_group {
+                    let mut storage_lock = storage.lock().await;
+                    match &cgroup_metadata {
+                        Some(metadata) => storage_lock.set_tracked_processes(metadata),
+                        None => storage_lock.update_tracked_pids(expanded_pids.clone()),
+                    }
+                    dropped_batches += storage_lock.dropped_batches();
+                    degraded_domains.extend(storage_lock.degraded_domains());
+                    let records = storage_lock.poll_data();
+                    let memory = storage_lock.trace_stats();
+                    trace_bytes += memory.estimated_bytes() as u64;
+                    trace_peak_bytes += memory.peak_bytes() as u64;
+                    records
+                } else {
+                    Vec::new()
+                };
+
+                let mut all_records = cpu_records;
                 all_records.extend(gpu_records);
-                let tick = aggregate_energy_records(&all_records, &active_pid_to_group);
+                all_records.extend(amd_gpu_records);
+                all_records.extend(intel_gpu_records);
+                all_records.extend(jetson_records);
+                all_records.extend(meter_records);
+                all_records.extend(network_records);
+                all_records.extend(storage_records);
+                let derived_records = derived_devices.evaluate(&all_records);
+                if !derived_records.is_empty() {
+                    cpu_group.lock().await.ingest(&derived_records);
+                    all_records.extend(derived_records);
+                }
+                let tick = {
+                    let mut priority = device_priority.write().unwrap();
+                    priority.observe(&all_records);
+                    aggregate_energy_records_with_priority(
+                        &all_records,
+                        &active_pid_to_group,
+                        &priority,
+                    )
+                };
 
                 let current_timestamp = chrono::Utc::now().timestamp_millis();
+                let tick_instant = Instant::now();
+                let tick_secs = last_tick
+                    .replace(tick_instant)
+                    .map(|last| tick_instant.duration_since(last).as_secs_f64());
+                let is_idle = match tick_secs {
+                    Some(secs) if idle.config().enabled && secs > 0.0 => {
+                        let (cpu_percent, _) = cpu_tracker.update();
+                        let power_watts = tick.system_total.total() / secs;
+                        idle.observe(current_timestamp, power_watts, cpu_percent)
+                    }
+                    _ => false,
+                };
                 if tick_state.start_timestamp == 0 {
                     tick_state.start_timestamp = current_timestamp;
                     *start_timestamp.write().unwrap() = current_timestamp;
@@ -901,7 +1793,7 @@ impl Monitor {
                     .map(|workload| (workload.group_id.clone(), workload.energy.clone()))
                     .collect();
 
-                {
+                let enforced = {
                     let mut snap = snapshot.write().unwrap();
                     snap.timestamp = current_timestamp;
                     snap.gpu_available = gpu_available;
@@ -909,17 +1801,31 @@ impl Monitor {
                     snap.system_total = cumulative_system_total;
                     snap.workloads = workloads;
                     snap.unattributed = cumulative_unattributed;
+                    update_energy_totals(&mut snap, &tick, &energy_domains);
                     snap.tracked_pids = expanded_pids;
                     snap.diagnostics = MonitorDiagnostics {
                         collection_ticks,
                         process_scans: process_scan_count.load(Ordering::SeqCst),
                         process_groups: groups.len(),
                         tracked_pids: snap.tracked_pids.len(),
+                        idle: is_idle,
+                        dropped_batches,
+                        trace_bytes,
+                        trace_peak_bytes,
+                        degraded_domains,
                     };
-                }
+                    snap.clone()
+                };
+                // Budget actions signal processes and write cgroup files, so
+                // they run after readers are let back in.
+                budgets.lock().unwrap().update(&enforced);
                 *last_pid_to_group.write().unwrap() = current_pid_to_group;
 
-                tokio::time::sleep(interval).await;
+                if is_idle {
+                    sleep_while_idle(&mut idle, &mut cpu_tracker, interval, &is_running).await;
+                } else {
+                    tokio::time::sleep(interval).await;
+                }
             }
         }));
     }
@@ -934,7 +1840,7 @@ impl Monitor {
 
         self.scan_handle = Some(tokio::spawn(async move {
             while is_running.load(Ordering::SeqCst) {
-                let groups = tokio::task::spawn_blocking(|| group_processes(&scan_processes()))
+                let groups = tokio::task::spawn_blocking(discover_process_groups)
                     .await
                     .unwrap_or_default();
                 *discovered_groups.write().unwrap() = groups;
@@ -1002,10 +1908,11 @@ fn resolve_missing_process_names(pids: &[u32], process_names: &mut HashMap<u32,
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::collectors::Rapl;
 
-    fn skip_if_rapl_unavailable() -> bool {
-        if !Rapl::is_available() {
-            eprintln!("skipping hardware-backed Monitor test: RAPL unavailable");
+    fn skip_if_cpu_energy_unavailable() -> bool {
+        if !CpuEnergy::is_available() {
+            eprintln!("skipping Monitor test: no CPU energy backend available");
             return true;
         }
         false
@@ -1056,6 +1963,7 @@ mod tests {
             user: "cached-user".to_string(),
             pids: Vec::new(),
             representative_pid: pid,
+            container: None,
         }];
 
         let refreshed = refresh_explicit_pid_groups(&cached);
@@ -1067,6 +1975,50 @@ mod tests {
         assert!(refreshed[0].pids.contains(&pid));
     }
 
+    #[test]
+    fn cgroup_groups_follow_members_and_keep_their_pid() {
+        let dir = std::env::temp_dir().join(format!("emt-cgroup-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("cgroup.procs"), "40\n30\n").unwrap();
+        let cgroup = CgroupTarget::open(&dir).unwrap();
+        let id = format!("cgroup:{}", dir.display());
+        let known = HashMap::from([(
+            id.clone(),
+            ProcessGroup {
+                id: id.clone(),
+                name: "job".to_string(),
+                user: "alice".to_string(),
+                pids: vec![40],
+                representative_pid: 40,
+                container: None,
+            },
+        )]);
+
+        let groups = cgroup_groups(std::slice::from_ref(&cgroup), &known);
+
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].pids, [30, 40]);
+        assert_eq!(groups[0].representative_pid, 40);
+        assert_eq!(groups[0].user, "alice");
+        assert_eq!(
+            tracked_cgroups(std::slice::from_ref(&cgroup), &groups),
+            [TrackedCgroup {
+                pid: 40,
+                target: cgroup.clone(),
+            }]
+        );
+        let mut carried = HashMap::from([(7, ("bob".to_string(), "old".to_string()))]);
+        let metadata = cgroup_process_metadata(&groups, &mut carried);
+        assert_eq!(metadata.len(), 2);
+        assert_eq!(metadata[0].task, cgroup.name());
+        assert_eq!(metadata[0].pids, [30, 40]);
+        assert_eq!(metadata[1].pids, [7]);
+        assert!(carried.contains_key(&40));
+        std::fs::write(dir.join("cgroup.procs"), "").unwrap();
+        assert!(cgroup_groups(std::slice::from_ref(&cgroup), &known).is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
     #[test]
     fn process_snapshots_for_group_include_pid_energy_power_and_names() {
         let group = ProcessGroup {
@@ -1075,6 +2027,7 @@ mod tests {
             user: "user".to_string(),
             pids: vec![123, 456],
             representative_pid: 123,
+            container: None,
         };
         let cumulative_by_pid = HashMap::from([(
             123,
@@ -1142,6 +2095,7 @@ mod tests {
                     user: "alice".to_string(),
                     pids: vec![100],
                     representative_pid: 100,
+                    container: None,
                 },
             ),
             (
@@ -1152,6 +2106,7 @@ mod tests {
                     user: "alice".to_string(),
                     pids: vec![200],
                     representative_pid: 200,
+                    container: None,
                 },
             ),
         ]);
@@ -1232,6 +2187,7 @@ mod tests {
             energy: DeviceEnergy::default(),
             power_watts: 0.0,
             percentage_of_system: 0.0,
+            container: None,
         }];
 
         let retained = retained_pid_to_group_map(&workloads);
@@ -1239,6 +2195,37 @@ mod tests {
         assert_eq!(retained.get(&456), Some(&"pid:123".to_string()));
     }
 
+    #[test]
+    fn snapshot_totals_count_auxiliary_psys_once_with_its_parts() {
+        let config = EmtConfig::default();
+        let mut priority = DevicePriority::new(config.device_priority.clone());
+        let record = |pid: u32, device: &str, energy: f64| EnergyRecord {
+            pid,
+            timestamp_us: 0,
+            monotonic_ns: 0,
+            device: device.to_string(),
+            energy,
+            raw_counter: None,
+        };
+        let records = [
+            record(7, "rapl:socket:0:package", 6.0),
+            record(7, "rapl:system:dram", 1.0),
+            record(7, "rapl:system:psys", 10.0),
+            record(7, "nvidia:gpu:0", 4.0),
+        ];
+        priority.observe(&records);
+        let tick = aggregate_energy_records_with_priority(&records, &HashMap::new(), &priority);
+
+        let mut snap = MetricsSnapshot::default();
+        update_energy_totals(&mut snap, &tick, &config.energy_domains);
+        update_energy_totals(&mut snap, &tick, &config.energy_domains);
+
+        assert_eq!(snap.auxiliary["rapl:system:psys"], 20.0);
+        assert_eq!(snap.devices["rapl:socket:0:package"], 12.0);
+        assert_eq!(snap.totals.total_joules, 28.0);
+        assert_eq!(snap.totals.components["platform:other"], 6.0);
+    }
+
     #[test]
     fn metrics_snapshot_default() {
         let snap = MetricsSnapshot::default();
@@ -1258,8 +2245,8 @@ mod tests {
     #[test]
     fn monitor_initial_snapshot_reports_gpu_availability() {
         let monitor = Monitor::new(EmtConfig::default(), Some(vec![std::process::id()]));
-        let expected_gpu_available =
-            std::env::var_os("EMT_DISABLE_GPU").is_none() && NvidiaGpu::is_available();
+        let expected_gpu_available = std::env::var_os("EMT_DISABLE_GPU").is_none()
+            && (NvidiaGpu::is_available() || AmdGpu::is_available() || IntelGpu::is_available());
 
         let snapshot = monitor.snapshot.read().unwrap();
 
@@ -1272,11 +2259,17 @@ mod tests {
         config.collection.rate_hz = 4.0;
         let monitor = Monitor::new(config, Some(vec![std::process::id()]));
 
-        assert_eq!(monitor.rapl_group.try_lock().unwrap().batch_size(), 1);
+        assert_eq!(monitor.cpu_group.try_lock().unwrap().batch_size(), 1);
 
         if let Some(gpu_group) = &monitor.gpu_group {
             assert_eq!(gpu_group.try_lock().unwrap().batch_size(), 1);
         }
+        if let Some(gpu_group) = &monitor.amd_gpu_group {
+            assert_eq!(gpu_group.try_lock().unwrap().batch_size(), 1);
+        }
+        if let Some(gpu_group) = &monitor.intel_gpu_group {
+            assert_eq!(gpu_group.try_lock().unwrap().batch_size(), 1);
+        }
     }
 
     #[test]
@@ -1299,7 +2292,7 @@ mod tests {
 
     #[tokio::test]
     async fn monitor_starts_and_stops_cleanly() {
-        if skip_if_rapl_unavailable() {
+        if skip_if_cpu_energy_unavailable() {
             return;
         }
         let config = EmtConfig::default();
@@ -1316,7 +2309,7 @@ mod tests {
 
     #[tokio::test]
     async fn monitor_handle_returns_non_zero_energy_after_running() {
-        if skip_if_rapl_unavailable() {
+        if skip_if_cpu_energy_unavailable() {
             return;
         }
         let config = EmtConfig::default();
@@ -1340,7 +2333,7 @@ mod tests {
 
     #[tokio::test]
     async fn double_commence_is_noop() {
-        if skip_if_rapl_unavailable() {
+        if skip_if_cpu_energy_unavailable() {
             return;
         }
         let config = EmtConfig::default();
@@ -1354,7 +2347,7 @@ mod tests {
 
     #[tokio::test]
     async fn monitor_without_pids_uses_scan_task() {
-        if skip_if_rapl_unavailable() {
+        if skip_if_cpu_energy_unavailable() {
             return;
         }
         let config = EmtConfig::default();
@@ -1378,7 +2371,7 @@ mod tests {
 
     #[tokio::test]
     async fn monitor_snapshot_has_device_breakdown() {
-        if skip_if_rapl_unavailable() {
+        if skip_if_cpu_energy_unavailable() {
             return;
         }
         let config = EmtConfig::default();
@@ -1407,7 +2400,7 @@ mod tests {
 
     #[tokio::test]
     async fn consumed_energy_by_pid_backwards_compat() {
-        if skip_if_rapl_unavailable() {
+        if skip_if_cpu_energy_unavailable() {
             return;
         }
         let config = EmtConfig::default();
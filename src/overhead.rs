@@ -0,0 +1,350 @@
+/// Overhead Module
+///
+/// Measures the observer cost of collection for `emt overhead`. The
+/// configured collectors are read at each rate of a sweep for a short
+/// period while EMT tracks its own process, and the report gives, per rate,
+/// the CPU time the process used, the energy attributed to it and how long
+/// each sample took to read, so users can pick a sampling rate with a known
+/// cost.
+///
+/// Energy is the process's share of the CPU devices, which RAPL attributes
+/// by CPU utilization; devices that device priority marks as auxiliary are
+/// left out, like in every total.
+use crate::collectors::{AmdGpu, CpuEnergy, IntelGpu, MeterCollector, NvidiaGpu};
+use crate::config::EmtConfig;
+use crate::device_priority::{DevicePriority, DeviceRole};
+use crate::energy_group::{EnergyCollector, EnergyRecord};
+use crate::units::format_significant;
+use crate::utils::errors::CollectorError;
+use serde::Serialize;
+use std::fs;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+/// Clock ticks per second of `/proc/<pid>/stat` CPU times.
+const USER_HZ: f64 = 100.0;
+
+#[derive(Debug, Error)]
+pub enum OverheadError {
+    #[error("no collector is available on this host")]
+    NoCollectors,
+    #[error("rate {0} must be a finite value greater than 0")]
+    InvalidRate(f64),
+}
+
+/// Options of `emt overhead`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OverheadOptions {
+    /// Sampling rates to measure, in Hz
+    pub rates: Vec<f64>,
+    /// How long to sample at each rate
+    pub duration: Duration,
+}
+
+/// Observer cost at one sampling rate.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RateOverhead {
+    pub rate_hz: f64,
+    pub samples: usize,
+    pub elapsed_seconds: f64,
+    /// CPU time used by the process, user and system
+    pub cpu_seconds: f64,
+    /// `cpu_seconds` as a percentage of one CPU over the period
+    pub cpu_percent: f64,
+    /// Energy attributed to the process
+    pub energy_joules: f64,
+    pub power_watts: f64,
+    pub latency_mean_ms: f64,
+    pub latency_p95_ms: f64,
+    pub latency_max_ms: f64,
+}
+
+impl RateOverhead {
+    fn from_run(
+        rate_hz: f64,
+        elapsed_seconds: f64,
+        cpu_seconds: f64,
+        energy_joules: f64,
+        latencies: &mut [Duration],
+    ) -> Self {
+        latencies.sort();
+        let millis = |latency: Duration| latency.as_secs_f64() * 1000.0;
+        let latency_mean_ms = if latencies.is_empty() {
+            0.0
+        } else {
+            latencies.iter().copied().map(millis).sum::<f64>() / latencies.len() as f64
+        };
+        let p95_index = (latencies.len() as f64 * 0.95).ceil() as usize;
+        let per_second = |value: f64| {
+            if elapsed_seconds > 0.0 {
+                value / elapsed_seconds
+            } else {
+                0.0
+            }
+        };
+        Self {
+            rate_hz,
+            samples: latencies.len(),
+            elapsed_seconds,
+            cpu_seconds,
+            cpu_percent: per_second(cpu_seconds) * 100.0,
+            energy_joules,
+            power_watts: per_second(energy_joules),
+            latency_mean_ms,
+            latency_p95_ms: p95_index
+                .checked_sub(1)
+                .map_or(0.0, |index| millis(latencies[index])),
+            latency_max_ms: latencies.last().copied().map_or(0.0, millis),
+        }
+    }
+}
+
+/// Result of `emt overhead`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct OverheadReport {
+    /// Collectors that were read
+    pub collectors: Vec<String>,
+    pub duration_seconds: f64,
+    pub rates: Vec<RateOverhead>,
+}
+
+impl OverheadReport {
+    /// The report as a table for the terminal.
+    pub fn render_text(&self) -> String {
+        let mut rows = vec![
+            [
+                "rate (Hz)",
+                "samples",
+                "CPU (s)",
+                "CPU (%)",
+                "energy (J)",
+                "power (W)",
+                "mean (ms)",
+                "p95 (ms)",
+                "max (ms)",
+            ]
+            .map(str::to_string),
+        ];
+        for rate in &self.rates {
+            rows.push([
+                format_significant(rate.rate_hz),
+                rate.samples.to_string(),
+                format_significant(rate.cpu_seconds),
+                format_significant(rate.cpu_percent),
+                format_significant(rate.energy_joules),
+                format_significant(rate.power_watts),
+                format_significant(rate.latency_mean_ms),
+                format_significant(rate.latency_p95_ms),
+                format_significant(rate.latency_max_ms),
+            ]);
+        }
+        let widths: Vec<usize> = (0..rows[0].len())
+            .map(|column| {
+                rows.iter()
+                    .map(|row| row[column].chars().count())
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect();
+
+        let mut lines = vec![
+            format!("Collectors: {}", self.collectors.join(", ")),
+            format!(
+                "Sampled for {} s at each rate; latency is per sample",
+                format_significant(self.duration_seconds)
+            ),
+            String::new(),
+        ];
+        for row in rows {
+            let cells: Vec<String> = row
+                .iter()
+                .zip(&widths)
+                .map(|(cell, &width)| format!("{cell:>width$}"))
+                .collect();
+            lines.push(format!("  {}", cells.join("  ")));
+        }
+        lines.push(String::new());
+        lines.join("\n")
+    }
+}
+
+/// A configured collector ready to read.
+enum OverheadCollector {
+    Cpu(Box<CpuEnergy>),
+    Gpu(NvidiaGpu),
+    AmdGpu(AmdGpu),
+    IntelGpu(IntelGpu),
+    Meter(MeterCollector),
+}
+
+impl OverheadCollector {
+    /// The collectors `config` enables that are available, tracking this
+    /// process so CPU collectors attribute energy to it.
+    fn open_all(config: &EmtConfig) -> Vec<(String, Self)> {
+        let pid = std::process::id();
+        let mut collectors = Vec::new();
+        let cpu = CpuEnergy::new(&config.cpu_energy);
+        if let Some(backend) = cpu.backend() {
+            cpu.set_tracked_pids(vec![pid]);
+            collectors.push((backend.as_str().to_string(), Self::Cpu(Box::new(cpu))));
+        }
+        if NvidiaGpu::is_available()
+            && let Ok(gpu) = NvidiaGpu::new()
+        {
+            gpu.set_tracked_pids(vec![pid]);
+            collectors.push(("nvml".to_string(), Self::Gpu(gpu)));
+        }
+        if AmdGpu::is_available() {
+            let gpu = AmdGpu::default();
+            gpu.set_tracked_pids(vec![pid]);
+            collectors.push(("amdgpu".to_string(), Self::AmdGpu(gpu)));
+        }
+        if IntelGpu::is_available() {
+            let gpu = IntelGpu::default();
+            gpu.set_tracked_pids(vec![pid]);
+            collectors.push(("intel-gpu".to_string(), Self::IntelGpu(gpu)));
+        }
+        if !config.exec_collectors.is_empty()
+            || !config.http_collectors.is_empty()
+            || !config.bmc_collectors.is_empty()
+        {
+            let meters = MeterCollector::new(&config.exec_collectors, &config.http_collectors)
+                .with_bmc(&config.bmc_collectors);
+            collectors.push(("meters".to_string(), Self::Meter(meters)));
+        }
+        collectors
+    }
+
+    async fn read(&self) -> Result<Vec<EnergyRecord>, CollectorError> {
+        match self {
+            Self::Cpu(cpu) => cpu.get_energy_trace().await,
+            Self::Gpu(gpu) => gpu.get_energy_trace().await,
+            Self::AmdGpu(gpu) => gpu.get_energy_trace().await,
+            Self::IntelGpu(gpu) => gpu.get_energy_trace().await,
+            Self::Meter(meter) => meter.get_energy_trace().await,
+        }
+    }
+}
+
+/// CPU time used by this process so far, in seconds.
+fn own_cpu_seconds() -> Option<f64> {
+    let stat = fs::read_to_string("/proc/self/stat").ok()?;
+    // Fields after the command name, which may contain spaces
+    let fields: Vec<&str> = stat[stat.rfind(')')? + 2..].split_whitespace().collect();
+    let utime: u64 = fields.get(11)?.parse().ok()?;
+    let stime: u64 = fields.get(12)?.parse().ok()?;
+    Some((utime + stime) as f64 / USER_HZ)
+}
+
+/// Read the configured collectors at each of `options.rates` for
+/// `options.duration` and measure what collection costs this process.
+pub async fn measure_overhead(
+    config: &EmtConfig,
+    options: &OverheadOptions,
+) -> Result<OverheadReport, OverheadError> {
+    if let Some(&rate) = options
+        .rates
+        .iter()
+        .find(|rate| !(rate.is_finite() && **rate > 0.0))
+    {
+        return Err(OverheadError::InvalidRate(rate));
+    }
+    let collectors = OverheadCollector::open_all(config);
+    if collectors.is_empty() {
+        return Err(OverheadError::NoCollectors);
+    }
+    let pid = std::process::id();
+    let mut priority = DevicePriority::new(config.device_priority.clone());
+    let read_all = async |priority: &mut DevicePriority| {
+        let mut own_joules = 0.0;
+        for (name, collector) in &collectors {
+            match collector.read().await {
+                Ok(records) => {
+                    priority.observe(&records);
+                    own_joules += records
+                        .iter()
+                        .filter(|record| {
+                            record.pid == pid
+                                && priority.role(&record.device) == DeviceRole::Authoritative
+                        })
+                        .map(|record| record.energy)
+                        .sum::<f64>();
+                }
+                Err(e) => log::warn!("Failed to read {name}: {e}"),
+            }
+        }
+        own_joules
+    };
+
+    let mut rates = Vec::new();
+    for &rate_hz in &options.rates {
+        let interval = Duration::from_secs_f64(1.0 / rate_hz);
+        // The first read only sets the baselines of cumulative counters.
+        read_all(&mut priority).await;
+        let started = Instant::now();
+        let cpu_started = own_cpu_seconds().unwrap_or(0.0);
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        let mut latencies = Vec::new();
+        let mut energy_joules = 0.0;
+        while started.elapsed() < options.duration {
+            ticker.tick().await;
+            let read_started = Instant::now();
+            energy_joules += read_all(&mut priority).await;
+            latencies.push(read_started.elapsed());
+        }
+        let cpu_seconds = (own_cpu_seconds().unwrap_or(0.0) - cpu_started).max(0.0);
+        rates.push(RateOverhead::from_run(
+            rate_hz,
+            started.elapsed().as_secs_f64(),
+            cpu_seconds,
+            energy_joules,
+            &mut latencies,
+        ));
+    }
+    Ok(OverheadReport {
+        collectors: collectors.into_iter().map(|(name, _)| name).collect(),
+        duration_seconds: options.duration.as_secs_f64(),
+        rates,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summarizes_cpu_energy_and_latency_per_rate() {
+        let mut latencies: Vec<Duration> = (1..=20).rev().map(Duration::from_millis).collect();
+
+        let rate = RateOverhead::from_run(10.0, 2.0, 0.05, 0.4, &mut latencies);
+
+        assert_eq!(rate.samples, 20);
+        assert!((rate.cpu_percent - 2.5).abs() < 1e-9);
+        assert!((rate.power_watts - 0.2).abs() < 1e-9);
+        assert!((rate.latency_mean_ms - 10.5).abs() < 1e-9);
+        assert!((rate.latency_p95_ms - 19.0).abs() < 1e-9);
+        assert!((rate.latency_max_ms - 20.0).abs() < 1e-9);
+
+        let idle = RateOverhead::from_run(1.0, 0.0, 0.0, 0.0, &mut []);
+        assert_eq!(idle.latency_p95_ms, 0.0);
+        assert_eq!(idle.power_watts, 0.0);
+    }
+
+    #[test]
+    fn own_cpu_time_is_readable() {
+        assert!(own_cpu_seconds().is_some_and(|seconds| seconds >= 0.0));
+    }
+
+    #[tokio::test]
+    async fn rejects_rates_that_are_not_positive() {
+        let options = OverheadOptions {
+            rates: vec![10.0, 0.0],
+            duration: Duration::from_millis(10),
+        };
+        assert!(matches!(
+            measure_overhead(&EmtConfig::default(), &options).await,
+            Err(OverheadError::InvalidRate(_))
+        ));
+    }
+}
@@ -0,0 +1,316 @@
+/// Peak Events Module
+///
+/// Detects peak power episodes per device: runs of consecutive collector
+/// intervals whose power stays at or above a threshold. Each episode becomes a
+/// [`PeakEvent`] with its start, end, peak power and energy, so capacity
+/// planning can see how close workloads get to power limits.
+///
+/// The threshold is either an absolute wattage or a fraction of the device's
+/// rated maximum power from the device registry. Devices without a rated
+/// maximum are only checked when an absolute threshold is set.
+///
+/// Detection runs on the record stream rather than the rotating trace, so
+/// events outlive the trace retention window.
+use crate::device_registry::DeviceRegistry;
+use crate::energy_group::EnergyRecord;
+use crate::utils::time::ExportTimezone;
+use polars::prelude::*;
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+
+/// Thresholds for peak detection.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PeakDetection {
+    /// Absolute threshold in watts, applied to every device.
+    pub threshold_watts: Option<f64>,
+    /// Threshold as a fraction of the device's rated maximum power; used when
+    /// no absolute threshold is set.
+    pub limit_fraction: f64,
+    /// Episodes shorter than this are discarded.
+    pub min_duration: Duration,
+}
+
+impl Default for PeakDetection {
+    fn default() -> Self {
+        Self {
+            threshold_watts: None,
+            limit_fraction: 0.9,
+            min_duration: Duration::ZERO,
+        }
+    }
+}
+
+/// One peak power episode on a device.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PeakEvent {
+    pub device: String,
+    /// Start of the first interval above the threshold, in Unix milliseconds.
+    pub start: i64,
+    /// End of the last interval above the threshold, in Unix milliseconds.
+    pub end: i64,
+    pub duration_seconds: f64,
+    pub peak_watts: f64,
+    /// Energy consumed during the episode.
+    pub energy_joules: f64,
+    pub threshold_watts: f64,
+    /// Rated maximum power of the device, when known.
+    pub limit_watts: Option<f64>,
+    /// Whether the episode was still ongoing at the last observation.
+    pub ongoing: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Mark {
+    monotonic_ns: i64,
+    timestamp: i64,
+}
+
+#[derive(Debug, Default)]
+struct DeviceState {
+    last: Option<Mark>,
+    open: Option<(Mark, PeakEvent)>,
+}
+
+/// Streaming peak detector fed with energy records in time order.
+#[derive(Debug)]
+pub struct PeakDetector {
+    config: PeakDetection,
+    devices: HashMap<String, DeviceState>,
+    events: Vec<PeakEvent>,
+}
+
+impl PeakDetector {
+    pub fn new(config: PeakDetection) -> Self {
+        Self {
+            config,
+            devices: HashMap::new(),
+            events: Vec::new(),
+        }
+    }
+
+    /// Feed one batch of records. Records of one collector iteration must not
+    /// be split across batches, since they are summed per device first.
+    pub fn observe(&mut self, records: &[EnergyRecord], registry: &DeviceRegistry) {
+        let mut iterations: BTreeMap<(i64, &str), (i64, f64)> = BTreeMap::new();
+        for record in records {
+            iterations
+                .entry((record.monotonic_ns, record.device.as_str()))
+                .or_insert((record.timestamp_us.div_euclid(1000), 0.0))
+                .1 += record.energy;
+        }
+
+        for ((monotonic_ns, device), (timestamp, joules)) in iterations {
+            let limit_watts = registry
+                .id(device)
+                .and_then(|id| registry.get(id))
+                .and_then(|info| info.max_power_watts);
+            let Some(threshold) = self
+                .config
+                .threshold_watts
+                .or(limit_watts.map(|limit| limit * self.config.limit_fraction))
+            else {
+                continue;
+            };
+            let now = Mark {
+                monotonic_ns,
+                timestamp,
+            };
+            self.observe_interval(device, now, joules, threshold, limit_watts);
+        }
+    }
+
+    fn observe_interval(
+        &mut self,
+        device: &str,
+        now: Mark,
+        joules: f64,
+        threshold: f64,
+        limit_watts: Option<f64>,
+    ) {
+        let state = self.devices.entry(device.to_string()).or_default();
+        let Some(last) = state.last.replace(now) else {
+            return;
+        };
+        let elapsed_ns = now.monotonic_ns - last.monotonic_ns;
+        if elapsed_ns <= 0 {
+            state.last = Some(last);
+            return;
+        }
+        let watts = joules / (elapsed_ns as f64 / 1e9);
+
+        if watts >= threshold {
+            let (start, event) = state.open.get_or_insert_with(|| {
+                (
+                    last,
+                    PeakEvent {
+                        device: device.to_string(),
+                        start: last.timestamp,
+                        end: last.timestamp,
+                        duration_seconds: 0.0,
+                        peak_watts: watts,
+                        energy_joules: 0.0,
+                        threshold_watts: threshold,
+                        limit_watts,
+                        ongoing: true,
+                    },
+                )
+            });
+            event.end = now.timestamp;
+            event.duration_seconds = (now.monotonic_ns - start.monotonic_ns) as f64 / 1e9;
+            event.peak_watts = event.peak_watts.max(watts);
+            event.energy_joules += joules;
+        } else if let Some((_, mut event)) = state.open.take() {
+            event.ongoing = false;
+            if event.duration_seconds >= self.config.min_duration.as_secs_f64() {
+                log::warn!(
+                    "Peak power on {}: {:.1} W for {:.1} s ({:.1} J, threshold {:.1} W)",
+                    event.device,
+                    event.peak_watts,
+                    event.duration_seconds,
+                    event.energy_joules,
+                    event.threshold_watts
+                );
+                self.events.push(event);
+            }
+        }
+    }
+
+    /// Finished episodes followed by ongoing ones, in detection order.
+    pub fn events(&self) -> Vec<PeakEvent> {
+        let mut ongoing: Vec<PeakEvent> = self
+            .devices
+            .values()
+            .filter_map(|state| state.open.as_ref().map(|(_, event)| event.clone()))
+            .filter(|event| event.duration_seconds >= self.config.min_duration.as_secs_f64())
+            .collect();
+        ongoing.sort_by(|a, b| a.start.cmp(&b.start).then(a.device.cmp(&b.device)));
+        self.events.iter().cloned().chain(ongoing).collect()
+    }
+}
+
+/// The events table: `device | start | end | duration_seconds | peak_watts |
+/// energy_joules | threshold_watts | limit_watts | ongoing`, with wall-clock
+/// times rendered in `timezone`.
+pub fn peak_events_to_dataframe(
+    events: &[PeakEvent],
+    timezone: ExportTimezone,
+) -> PolarsResult<DataFrame> {
+    DataFrame::new(vec![
+        Column::new(
+            "device".into(),
+            events.iter().map(|e| e.device.as_str()).collect::<Vec<_>>(),
+        ),
+        Column::new(
+            "start".into(),
+            events
+                .iter()
+                .map(|e| timezone.format_millis(e.start))
+                .collect::<Vec<_>>(),
+        ),
+        Column::new(
+            "end".into(),
+            events
+                .iter()
+                .map(|e| timezone.format_millis(e.end))
+                .collect::<Vec<_>>(),
+        ),
+        Column::new(
+            "duration_seconds".into(),
+            events
+                .iter()
+                .map(|e| e.duration_seconds)
+                .collect::<Vec<_>>(),
+        ),
+        Column::new(
+            "peak_watts".into(),
+            events.iter().map(|e| e.peak_watts).collect::<Vec<_>>(),
+        ),
+        Column::new(
+            "energy_joules".into(),
+            events.iter().map(|e| e.energy_joules).collect::<Vec<_>>(),
+        ),
+        Column::new(
+            "threshold_watts".into(),
+            events.iter().map(|e| e.threshold_watts).collect::<Vec<_>>(),
+        ),
+        Column::new(
+            "limit_watts".into(),
+            events.iter().map(|e| e.limit_watts).collect::<Vec<_>>(),
+        ),
+        Column::new(
+            "ongoing".into(),
+            events.iter().map(|e| e.ongoing).collect::<Vec<_>>(),
+        ),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device_registry::DeviceInfo;
+
+    const T0: i64 = 1_700_000_000_000;
+
+    /// One record per second on `device` with the given energies (joules).
+    fn records(device: &str, energies: &[f64]) -> Vec<EnergyRecord> {
+        energies
+            .iter()
+            .enumerate()
+            .map(|(i, energy)| EnergyRecord {
+                pid: 1,
+                timestamp_us: (T0 + i as i64 * 1000) * 1000,
+                monotonic_ns: i as i64 * 1_000_000_000,
+                device: device.to_string(),
+                energy: *energy,
+                raw_counter: None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn detects_episodes_against_rated_limit() {
+        let mut registry = DeviceRegistry::new();
+        registry.register(DeviceInfo::new("gpu").with_max_power_watts(Some(100.0)));
+        registry.id_or_register("cpu");
+        let mut detector = PeakDetector::new(PeakDetection::default());
+
+        let mut batch = records("gpu", &[0.0, 50.0, 95.0, 99.0, 40.0, 92.0]);
+        batch.extend(records("cpu", &[0.0, 500.0, 500.0]));
+        detector.observe(&batch, &registry);
+
+        let events = detector.events();
+        assert_eq!(events.len(), 2);
+        let first = &events[0];
+        assert_eq!((first.start, first.end), (T0 + 1000, T0 + 3000));
+        assert_eq!(first.duration_seconds, 2.0);
+        assert_eq!(first.peak_watts, 99.0);
+        assert_eq!(first.energy_joules, 194.0);
+        assert_eq!(first.limit_watts, Some(100.0));
+        assert!(!first.ongoing);
+        assert!(events[1].ongoing);
+    }
+
+    #[test]
+    fn absolute_threshold_and_min_duration_apply_to_all_devices() {
+        let registry = DeviceRegistry::new();
+        let mut detector = PeakDetector::new(PeakDetection {
+            threshold_watts: Some(10.0),
+            min_duration: Duration::from_secs(2),
+            ..PeakDetection::default()
+        });
+
+        detector.observe(
+            &records("cpu", &[0.0, 20.0, 1.0, 20.0, 20.0, 1.0]),
+            &registry,
+        );
+
+        let events = detector.events();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].start, T0 + 2000);
+        let table = peak_events_to_dataframe(&events, ExportTimezone::Utc).unwrap();
+        assert_eq!(
+            table.column("start").unwrap().str().unwrap().get(0),
+            Some("2023-11-14T22:13:22.000Z")
+        );
+    }
+}
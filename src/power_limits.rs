@@ -0,0 +1,390 @@
+/// Power Limits
+///
+/// GPU power limits read and set through NVML, so budgets can cap a GPU's
+/// board power rather than only pause or throttle the processes using it.
+/// Limits belong to the physical GPU: a MIG instance, addressed as
+/// `<gpu>:<instance>`, is capped through its parent, and caps on several
+/// instances of one GPU share its limit.
+///
+/// [`GpuPowerCaps`] tracks who capped which GPU. The lowest cap requested
+/// on a GPU is applied, clamped to the range the GPU accepts. The first cap
+/// remembers the GPU's limit and turns on persistence mode, so the driver
+/// does not unload and drop the limit while no process uses the GPU; both
+/// are restored once the last cap on the GPU is lifted.
+use nvml_wrapper::Nvml;
+use nvml_wrapper::error::NvmlError;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::str::FromStr;
+
+/// Errors raised while reading or setting power limits.
+#[derive(Debug, thiserror::Error)]
+pub enum PowerLimitError {
+    #[error("invalid GPU '{0}' (expected an index, a GPU UUID or <gpu>:<MIG instance>)")]
+    InvalidGpu(String),
+    #[error("MIG instance '{0}' must be addressed as <gpu>:<MIG instance>")]
+    MigUuid(String),
+    #[error("power limit must be a positive number of watts, got {0}")]
+    InvalidLimit(f64),
+    #[error("NVML error: {0}")]
+    Nvml(#[from] NvmlError),
+}
+
+/// A GPU, by index or UUID, or one of its MIG instances.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct GpuTarget {
+    /// Index or UUID of the physical GPU
+    pub gpu: String,
+    pub mig_instance: Option<u32>,
+}
+
+impl FromStr for GpuTarget {
+    type Err = PowerLimitError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || PowerLimitError::InvalidGpu(s.to_string());
+        if s.starts_with("MIG-") {
+            return Err(PowerLimitError::MigUuid(s.to_string()));
+        }
+        let (gpu, mig_instance) = match s.split_once(':') {
+            Some((gpu, instance)) => (gpu, Some(instance.parse().map_err(|_| invalid())?)),
+            None => (s, None),
+        };
+        if !(gpu.starts_with("GPU-") || gpu.parse::<u32>().is_ok()) {
+            return Err(invalid());
+        }
+        Ok(Self {
+            gpu: gpu.to_string(),
+            mig_instance,
+        })
+    }
+}
+
+impl fmt::Display for GpuTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.mig_instance {
+            Some(instance) => write!(f, "{}:{instance}", self.gpu),
+            None => f.write_str(&self.gpu),
+        }
+    }
+}
+
+/// Power limit settings of a GPU, in watts.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GpuPowerLimit {
+    /// Limit set by the power management algorithm
+    pub limit_watts: f64,
+    pub default_watts: f64,
+    pub min_watts: f64,
+    pub max_watts: f64,
+    /// Limit in effect, the lowest of all limiters
+    pub enforced_watts: f64,
+    pub persistence_mode: bool,
+}
+
+impl GpuPowerLimit {
+    /// `watts` within the range the GPU accepts.
+    pub fn clamp(&self, watts: f64) -> f64 {
+        watts.clamp(self.min_watts, self.max_watts)
+    }
+}
+
+/// Driver side of GPU power limits, replaceable in tests. GPUs are named
+/// by their UUID once resolved.
+pub trait GpuPowerControl: Send {
+    /// UUID of the GPU with index or UUID `gpu`.
+    fn uuid(&self, gpu: &str) -> Result<String, PowerLimitError>;
+    fn power_limit(&self, uuid: &str) -> Result<GpuPowerLimit, PowerLimitError>;
+    fn set_power_limit(&self, uuid: &str, watts: f64) -> Result<(), PowerLimitError>;
+    fn set_persistence_mode(&self, uuid: &str, enabled: bool) -> Result<(), PowerLimitError>;
+}
+
+/// Power limits through NVML. Setting them requires root.
+pub struct NvmlPowerControl {
+    nvml: Nvml,
+}
+
+impl NvmlPowerControl {
+    pub fn new() -> Result<Self, PowerLimitError> {
+        Ok(Self {
+            nvml: Nvml::init()?,
+        })
+    }
+
+    fn device(&self, gpu: &str) -> Result<nvml_wrapper::Device<'_>, PowerLimitError> {
+        Ok(match gpu.parse::<u32>() {
+            Ok(index) => self.nvml.device_by_index(index)?,
+            Err(_) => self.nvml.device_by_uuid(gpu)?,
+        })
+    }
+}
+
+impl GpuPowerControl for NvmlPowerControl {
+    fn uuid(&self, gpu: &str) -> Result<String, PowerLimitError> {
+        Ok(self.device(gpu)?.uuid()?)
+    }
+
+    fn power_limit(&self, uuid: &str) -> Result<GpuPowerLimit, PowerLimitError> {
+        let device = self.device(uuid)?;
+        let constraints = device.power_management_limit_constraints()?;
+        let watts = |milliwatts: u32| f64::from(milliwatts) / 1000.0;
+        Ok(GpuPowerLimit {
+            limit_watts: watts(device.power_management_limit()?),
+            default_watts: watts(device.power_management_limit_default()?),
+            min_watts: watts(constraints.min_limit),
+            max_watts: watts(constraints.max_limit),
+            enforced_watts: watts(device.enforced_power_limit()?),
+            persistence_mode: device.is_in_persistent_mode()?,
+        })
+    }
+
+    fn set_power_limit(&self, uuid: &str, watts: f64) -> Result<(), PowerLimitError> {
+        let milliwatts = (watts * 1000.0).round() as u32;
+        Ok(self.device(uuid)?.set_power_management_limit(milliwatts)?)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn set_persistence_mode(&self, uuid: &str, enabled: bool) -> Result<(), PowerLimitError> {
+        Ok(self.device(uuid)?.set_persistent(enabled)?)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn set_persistence_mode(&self, _uuid: &str, _enabled: bool) -> Result<(), PowerLimitError> {
+        Err(NvmlError::NotSupported.into())
+    }
+}
+
+/// A capped GPU: its settings before the first cap and the cap of each
+/// holder.
+#[derive(Debug)]
+struct CappedGpu {
+    original: GpuPowerLimit,
+    caps: HashMap<String, f64>,
+}
+
+/// GPU power caps requested by several holders, such as budget targets.
+/// Every cap is lifted when this is dropped.
+pub struct GpuPowerCaps {
+    control: Box<dyn GpuPowerControl>,
+    /// Capped GPUs by UUID
+    gpus: BTreeMap<String, CappedGpu>,
+}
+
+impl GpuPowerCaps {
+    pub fn new(control: Box<dyn GpuPowerControl>) -> Self {
+        Self {
+            control,
+            gpus: BTreeMap::new(),
+        }
+    }
+
+    /// Current settings of the GPU of `target`.
+    pub fn power_limit(&self, target: &GpuTarget) -> Result<GpuPowerLimit, PowerLimitError> {
+        self.control.power_limit(&self.control.uuid(&target.gpu)?)
+    }
+
+    /// Cap the GPU of `target` at `watts` on behalf of `holder`, replacing
+    /// the holder's earlier cap on that GPU. Returns the limit now applied
+    /// to the GPU.
+    pub fn cap(
+        &mut self,
+        holder: &str,
+        target: &GpuTarget,
+        watts: f64,
+    ) -> Result<f64, PowerLimitError> {
+        if !(watts.is_finite() && watts > 0.0) {
+            return Err(PowerLimitError::InvalidLimit(watts));
+        }
+        let uuid = self.control.uuid(&target.gpu)?;
+        if !self.gpus.contains_key(&uuid) {
+            let original = self.control.power_limit(&uuid)?;
+            if !original.persistence_mode
+                && let Err(e) = self.control.set_persistence_mode(&uuid, true)
+            {
+                log::warn!("Could not enable persistence mode on GPU {uuid}: {e}");
+            }
+            self.gpus.insert(
+                uuid.clone(),
+                CappedGpu {
+                    original,
+                    caps: HashMap::new(),
+                },
+            );
+        }
+        let gpu = self.gpus.get_mut(&uuid).expect("inserted above");
+        gpu.caps.insert(holder.to_string(), watts);
+        let applied = gpu
+            .original
+            .clamp(gpu.caps.values().copied().fold(f64::MAX, f64::min));
+        match self.control.set_power_limit(&uuid, applied) {
+            Ok(()) => Ok(applied),
+            Err(e) => {
+                gpu.caps.remove(holder);
+                self.settle(&uuid);
+                Err(e)
+            }
+        }
+    }
+
+    /// Lift every cap of `holder`.
+    pub fn uncap(&mut self, holder: &str) {
+        let held: Vec<String> = self
+            .gpus
+            .iter_mut()
+            .filter_map(|(uuid, gpu)| gpu.caps.remove(holder).map(|_| uuid.clone()))
+            .collect();
+        for uuid in held {
+            self.settle(&uuid);
+        }
+    }
+
+    /// Lift every cap, restoring each GPU's original settings.
+    pub fn release(&mut self) {
+        let uuids: Vec<String> = self.gpus.keys().cloned().collect();
+        for uuid in uuids {
+            if let Some(gpu) = self.gpus.get_mut(&uuid) {
+                gpu.caps.clear();
+            }
+            self.settle(&uuid);
+        }
+    }
+
+    /// Apply the lowest remaining cap on `uuid`, or restore its original
+    /// settings when none is left.
+    fn settle(&mut self, uuid: &str) {
+        let Some(gpu) = self.gpus.get(uuid) else {
+            return;
+        };
+        if let Some(watts) = gpu.caps.values().copied().reduce(f64::min) {
+            if let Err(e) = self
+                .control
+                .set_power_limit(uuid, gpu.original.clamp(watts))
+            {
+                log::warn!("Failed to set the power limit of GPU {uuid}: {e}");
+            }
+            return;
+        }
+        let original = gpu.original;
+        self.gpus.remove(uuid);
+        if let Err(e) = self.control.set_power_limit(uuid, original.limit_watts) {
+            log::warn!("Failed to restore the power limit of GPU {uuid}: {e}");
+        }
+        if !original.persistence_mode
+            && let Err(e) = self.control.set_persistence_mode(uuid, false)
+        {
+            log::debug!("Could not restore persistence mode of GPU {uuid}: {e}");
+        }
+    }
+}
+
+impl Drop for GpuPowerCaps {
+    fn drop(&mut self) {
+        self.release();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    /// Two GPUs, indices 0 and 1, accepting 100-300 W; every change is
+    /// logged.
+    #[derive(Default, Clone)]
+    struct FakeGpus {
+        calls: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl GpuPowerControl for FakeGpus {
+        fn uuid(&self, gpu: &str) -> Result<String, PowerLimitError> {
+            match gpu {
+                "0" | "GPU-a" => Ok("GPU-a".to_string()),
+                "1" | "GPU-b" => Ok("GPU-b".to_string()),
+                _ => Err(NvmlError::NotFound.into()),
+            }
+        }
+
+        fn power_limit(&self, _uuid: &str) -> Result<GpuPowerLimit, PowerLimitError> {
+            Ok(GpuPowerLimit {
+                limit_watts: 250.0,
+                default_watts: 250.0,
+                min_watts: 100.0,
+                max_watts: 300.0,
+                enforced_watts: 250.0,
+                persistence_mode: false,
+            })
+        }
+
+        fn set_power_limit(&self, uuid: &str, watts: f64) -> Result<(), PowerLimitError> {
+            self.calls
+                .lock()
+                .unwrap()
+                .push(format!("limit {uuid} {watts}"));
+            Ok(())
+        }
+
+        fn set_persistence_mode(&self, uuid: &str, enabled: bool) -> Result<(), PowerLimitError> {
+            self.calls
+                .lock()
+                .unwrap()
+                .push(format!("persistence {uuid} {enabled}"));
+            Ok(())
+        }
+    }
+
+    fn target(s: &str) -> GpuTarget {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_gpus_and_mig_instances() {
+        assert_eq!(
+            target("1:2"),
+            GpuTarget {
+                gpu: "1".to_string(),
+                mig_instance: Some(2)
+            }
+        );
+        assert_eq!(target("GPU-5f3e:0").to_string(), "GPU-5f3e:0");
+        assert!(matches!(
+            "MIG-5f3e".parse::<GpuTarget>(),
+            Err(PowerLimitError::MigUuid(_))
+        ));
+        assert!("gpu0".parse::<GpuTarget>().is_err());
+        assert!("0:x".parse::<GpuTarget>().is_err());
+    }
+
+    #[test]
+    fn mig_instances_share_the_lowest_cap_of_their_gpu() {
+        let gpus = FakeGpus::default();
+        let calls = Arc::clone(&gpus.calls);
+        let mut caps = GpuPowerCaps::new(Box::new(gpus));
+
+        assert_eq!(caps.cap("train", &target("0:1"), 200.0).unwrap(), 200.0);
+        assert_eq!(caps.cap("serve", &target("GPU-a:2"), 50.0).unwrap(), 100.0);
+        assert!(matches!(
+            caps.cap("serve", &target("0"), 0.0),
+            Err(PowerLimitError::InvalidLimit(_))
+        ));
+        caps.uncap("serve");
+        caps.uncap("train");
+        caps.cap("train", &target("1"), 150.0).unwrap();
+        drop(caps);
+
+        assert_eq!(
+            *calls.lock().unwrap(),
+            [
+                "persistence GPU-a true",
+                "limit GPU-a 200",
+                "limit GPU-a 100",
+                "limit GPU-a 200",
+                "limit GPU-a 250",
+                "persistence GPU-a false",
+                "persistence GPU-b true",
+                "limit GPU-b 150",
+                "limit GPU-b 250",
+                "persistence GPU-b false",
+            ]
+        );
+    }
+}
@@ -0,0 +1,451 @@
+/// Power Statistics Module
+///
+/// Distribution statistics over instantaneous power. Average power hides short
+/// spikes, so this module derives one power sample per collection interval and
+/// reports percentiles (p50/p95/p99), the maximum and fixed-width histograms.
+///
+/// A sample is the energy of one collector iteration divided by the time since
+/// the previous iteration of the same series, measured on `monotonic_ns`.
+/// Records sharing a monotonic timestamp are summed first, so per-device power
+/// covers all processes and per-process power covers all devices. The first
+/// iteration of a series has no interval and yields no sample. Series can be
+/// smoothed with a [`PowerSmoothing`] for display.
+///
+/// [`power_trace`] resamples power per pid and device into fixed windows
+/// instead, weighting each sample by its interval.
+use crate::smoothing::PowerSmoothing;
+use crate::utils::time::{MONOTONIC_COLUMN, TIMESTAMP_COLUMN, timestamp_dtype, timestamp_millis};
+use polars::prelude::*;
+use std::collections::BTreeMap;
+
+/// Column of [`power_trace`] with the mean power of each window, in watts.
+pub const POWER_COLUMN: &str = "power_watts";
+const READ_COLUMN: &str = "__read";
+const INTERVAL_COLUMN: &str = "__interval_ns";
+
+/// How power samples are grouped into series.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerGrouping {
+    /// One series per device name.
+    Device,
+    /// One series per pid.
+    Process,
+}
+
+/// Percentile summary of one power series, in watts.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PowerStats {
+    /// Device name or pid, depending on the grouping.
+    pub key: String,
+    pub samples: usize,
+    pub mean_watts: f64,
+    pub p50_watts: f64,
+    pub p95_watts: f64,
+    pub p99_watts: f64,
+    pub max_watts: f64,
+}
+
+impl PowerStats {
+    /// Summarize `samples`; `None` when there are no samples.
+    pub fn from_samples(key: impl Into<String>, samples: &[f64]) -> Option<Self> {
+        if samples.is_empty() {
+            return None;
+        }
+        let mut sorted = samples.to_vec();
+        sorted.sort_by(f64::total_cmp);
+        Some(Self {
+            key: key.into(),
+            samples: sorted.len(),
+            mean_watts: sorted.iter().sum::<f64>() / sorted.len() as f64,
+            p50_watts: percentile(&sorted, 0.50),
+            p95_watts: percentile(&sorted, 0.95),
+            p99_watts: percentile(&sorted, 0.99),
+            max_watts: sorted[sorted.len() - 1],
+        })
+    }
+}
+
+/// Percentile `q` (0..=1) of sorted values, interpolating linearly between
+/// the closest ranks.
+pub fn percentile(sorted: &[f64], q: f64) -> f64 {
+    let rank = q.clamp(0.0, 1.0) * (sorted.len() - 1) as f64;
+    let (lower, upper) = (rank.floor() as usize, rank.ceil() as usize);
+    sorted[lower] + (sorted[upper] - sorted[lower]) * (rank - lower as f64)
+}
+
+/// Fixed-width histogram of power samples.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PowerHistogram {
+    /// Series key, as in [`PowerStats::key`].
+    pub key: String,
+    /// `counts.len() + 1` bin edges in watts, from the smallest to the largest
+    /// sample.
+    pub edges: Vec<f64>,
+    pub counts: Vec<u64>,
+}
+
+impl PowerHistogram {
+    /// Bin `samples` into `bins` equal-width bins (at least one). The last bin
+    /// includes its upper edge.
+    pub fn from_samples(key: impl Into<String>, samples: &[f64], bins: usize) -> Self {
+        let bins = bins.max(1);
+        let min = samples.iter().copied().fold(f64::INFINITY, f64::min);
+        let max = samples.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        let (min, max) = if samples.is_empty() {
+            (0.0, 0.0)
+        } else {
+            (min, max)
+        };
+        let width = (max - min) / bins as f64;
+
+        let mut counts = vec![0u64; bins];
+        for sample in samples {
+            let bin = if width > 0.0 {
+                (((sample - min) / width) as usize).min(bins - 1)
+            } else {
+                0
+            };
+            counts[bin] += 1;
+        }
+        Self {
+            key: key.into(),
+            edges: (0..=bins).map(|i| min + width * i as f64).collect(),
+            counts,
+        }
+    }
+}
+
+/// One power sample.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PowerPoint {
+    /// Wall-clock time at the end of the interval, in Unix milliseconds.
+    pub timestamp: i64,
+    /// Monotonic time at the end of the interval.
+    pub monotonic_ns: i64,
+    pub watts: f64,
+}
+
+/// Power series from `trace` (`pid | device | energy | timestamp |
+/// monotonic_ns`), keeping records whose wall-clock time (Unix ms) lies within
+/// `start..=end`. Series are keyed by device name or pid and ordered by key;
+/// points are in time order.
+pub fn power_series(
+    trace: &DataFrame,
+    grouping: PowerGrouping,
+    start: Option<i64>,
+    end: Option<i64>,
+) -> PolarsResult<BTreeMap<String, Vec<PowerPoint>>> {
+    // key -> monotonic_ns -> (timestamp, joules)
+    let mut energy: BTreeMap<String, BTreeMap<i64, (i64, f64)>> = BTreeMap::new();
+    if trace.height() > 0 {
+        let pids = trace.column("pid")?.u32()?;
+        let devices = trace.column("device")?.str()?;
+        let energies = trace.column("energy")?.f64()?;
+        let timestamps = timestamp_millis(trace)?;
+        let monotonic = trace.column(MONOTONIC_COLUMN)?.cast(&DataType::Int64)?;
+        let monotonic = monotonic.i64()?;
+
+        for row in 0..trace.height() {
+            let (Some(pid), Some(device), Some(joules), Some(timestamp), Some(monotonic_ns)) = (
+                pids.get(row),
+                devices.get(row),
+                energies.get(row),
+                timestamps.get(row),
+                monotonic.get(row),
+            ) else {
+                continue;
+            };
+            if start.is_some_and(|start| timestamp < start)
+                || end.is_some_and(|end| timestamp > end)
+            {
+                continue;
+            }
+            let key = match grouping {
+                PowerGrouping::Device => device.to_string(),
+                PowerGrouping::Process => pid.to_string(),
+            };
+            energy
+                .entry(key)
+                .or_default()
+                .entry(monotonic_ns)
+                .or_insert((timestamp, 0.0))
+                .1 += joules;
+        }
+    }
+
+    Ok(energy
+        .into_iter()
+        .map(|(key, series)| {
+            let points = series
+                .iter()
+                .zip(series.iter().skip(1))
+                .filter(|((previous, _), (current, _))| current > previous)
+                .map(
+                    |((previous, _), (current, (timestamp, joules)))| PowerPoint {
+                        timestamp: *timestamp,
+                        monotonic_ns: *current,
+                        watts: joules / ((current - previous) as f64 / 1e9),
+                    },
+                )
+                .collect();
+            (key, points)
+        })
+        .collect())
+}
+
+/// [`power_series`] with `smoothing` applied to every series.
+pub fn smoothed_power_series(
+    trace: &DataFrame,
+    grouping: PowerGrouping,
+    start: Option<i64>,
+    end: Option<i64>,
+    smoothing: PowerSmoothing,
+) -> PolarsResult<BTreeMap<String, Vec<PowerPoint>>> {
+    let mut series = power_series(trace, grouping, start, end)?;
+    for points in series.values_mut() {
+        let raw: Vec<(f64, f64)> = points
+            .iter()
+            .map(|point| (point.monotonic_ns as f64 / 1e9, point.watts))
+            .collect();
+        for (point, watts) in points.iter_mut().zip(smoothing.apply(&raw)) {
+            point.watts = watts;
+        }
+    }
+    Ok(series)
+}
+
+/// Power sample values per series, as used for percentiles and histograms.
+pub fn power_samples(
+    trace: &DataFrame,
+    grouping: PowerGrouping,
+    start: Option<i64>,
+    end: Option<i64>,
+) -> PolarsResult<BTreeMap<String, Vec<f64>>> {
+    Ok(power_series(trace, grouping, start, end)?
+        .into_iter()
+        .map(|(key, points)| (key, points.iter().map(|point| point.watts).collect()))
+        .collect())
+}
+
+/// Percentile summaries for every series with at least one sample.
+pub fn power_stats(
+    trace: &DataFrame,
+    grouping: PowerGrouping,
+    start: Option<i64>,
+    end: Option<i64>,
+) -> PolarsResult<Vec<PowerStats>> {
+    Ok(power_samples(trace, grouping, start, end)?
+        .into_iter()
+        .filter_map(|(key, samples)| PowerStats::from_samples(key, &samples))
+        .collect())
+}
+
+/// Histograms with `bins` bins for every series with at least one sample.
+pub fn power_histograms(
+    trace: &DataFrame,
+    grouping: PowerGrouping,
+    start: Option<i64>,
+    end: Option<i64>,
+    bins: usize,
+) -> PolarsResult<Vec<PowerHistogram>> {
+    Ok(power_samples(trace, grouping, start, end)?
+        .into_iter()
+        .filter(|(_, samples)| !samples.is_empty())
+        .map(|(key, samples)| PowerHistogram::from_samples(key, &samples, bins))
+        .collect())
+}
+
+/// Mean power per pid and device in windows of `every` wall-clock time, as
+/// a `pid | device | timestamp | power_watts` frame ordered by window start.
+///
+/// Each record's energy was used over the time since the previous read of
+/// its device, measured on `monotonic_ns`, so late reads weigh as much as
+/// they cover. A window's power is its energy over the summed intervals of
+/// its records; the first read of each device has no interval and is left
+/// out. Windows start at multiples of `every` since the Unix epoch.
+pub fn power_trace(trace: &DataFrame, every: std::time::Duration) -> PolarsResult<DataFrame> {
+    let every_ns = i64::try_from(every.as_nanos()).unwrap_or(i64::MAX);
+    polars_ensure!(every_ns > 0, InvalidOperation: "the resampling interval must be positive");
+    if trace.height() == 0 {
+        return DataFrame::new(vec![
+            Column::new_empty("pid".into(), &DataType::UInt32),
+            Column::new_empty("device".into(), &DataType::String),
+            Column::new_empty(TIMESTAMP_COLUMN.into(), &timestamp_dtype()),
+            Column::new_empty(POWER_COLUMN.into(), &DataType::Float64),
+        ]);
+    }
+
+    let read = col(MONOTONIC_COLUMN)
+        .cast(DataType::Int64)
+        .alias(READ_COLUMN);
+    let trace = trace.clone().lazy().with_column(read);
+    // One row per read of a device, with the time since its previous read
+    let reads = trace
+        .clone()
+        .select([col("device"), col(READ_COLUMN)])
+        .unique(None, UniqueKeepStrategy::Any)
+        .sort(["device", READ_COLUMN], SortMultipleOptions::default())
+        .with_column(
+            (col(READ_COLUMN) - col(READ_COLUMN).shift(lit(1)).over([col("device")]))
+                .alias(INTERVAL_COLUMN),
+        );
+    let window = Duration::parse(&format!("{every_ns}ns"));
+    let seconds = col(INTERVAL_COLUMN).sum().cast(DataType::Float64) / lit(1e9);
+    trace
+        .join(
+            reads,
+            [col("device"), col(READ_COLUMN)],
+            [col("device"), col(READ_COLUMN)],
+            JoinArgs::new(JoinType::Inner),
+        )
+        .filter(col(INTERVAL_COLUMN).gt(lit(0)))
+        .sort([TIMESTAMP_COLUMN], SortMultipleOptions::default())
+        .group_by_dynamic(
+            col(TIMESTAMP_COLUMN),
+            [col("pid"), col("device")],
+            DynamicGroupOptions {
+                every: window,
+                period: window,
+                offset: Duration::parse("0ns"),
+                ..DynamicGroupOptions::default()
+            },
+        )
+        .agg([(col("energy").sum() / seconds).alias(POWER_COLUMN)])
+        .select([
+            col("pid"),
+            col("device"),
+            col(TIMESTAMP_COLUMN),
+            col(POWER_COLUMN),
+        ])
+        .sort(
+            [TIMESTAMP_COLUMN, "device", "pid"],
+            SortMultipleOptions::default(),
+        )
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::time::{monotonic_dtype, normalize_timestamps};
+    use polars::df;
+
+    const T0: i64 = 1_700_000_000_000;
+
+    /// Two pids on one device sampled every 0.5 s; the third interval spikes.
+    fn trace() -> DataFrame {
+        let trace = df![
+            "pid" => [1u32, 2, 1, 2, 1, 2, 1, 2],
+            "device" => ["cpu"; 8],
+            "energy" => [9.0, 9.0, 5.0, 5.0, 50.0, 50.0, 5.0, 5.0],
+            "timestamp" => [T0, T0, T0 + 500, T0 + 500, T0 + 1_000, T0 + 1_000, T0 + 1_500, T0 + 1_500],
+            MONOTONIC_COLUMN => [0i64, 0, 500_000_000, 500_000_000, 1_000_000_000,
+                1_000_000_000, 1_500_000_000, 1_500_000_000],
+        ]
+        .unwrap();
+        let mut trace = normalize_timestamps(trace).unwrap();
+        let monotonic = trace
+            .column(MONOTONIC_COLUMN)
+            .unwrap()
+            .cast(&monotonic_dtype())
+            .unwrap();
+        trace.with_column(monotonic).unwrap();
+        trace
+    }
+
+    #[test]
+    fn power_stats_expose_spikes_per_device_and_process() {
+        let by_device = power_stats(&trace(), PowerGrouping::Device, None, None).unwrap();
+        let by_process = power_stats(&trace(), PowerGrouping::Process, None, None).unwrap();
+
+        assert_eq!(by_device.len(), 1);
+        let cpu = &by_device[0];
+        assert_eq!(cpu.samples, 3);
+        assert_eq!(cpu.p50_watts, 20.0);
+        assert_eq!(cpu.max_watts, 200.0);
+        assert!(cpu.p99_watts > 190.0 && cpu.p99_watts < 200.0);
+        assert!(cpu.mean_watts > cpu.p50_watts);
+
+        let keys: Vec<_> = by_process.iter().map(|s| s.key.as_str()).collect();
+        assert_eq!(keys, vec!["1", "2"]);
+        assert_eq!(by_process[0].max_watts, 100.0);
+    }
+
+    #[test]
+    fn power_stats_respect_time_range() {
+        let stats = power_stats(
+            &trace(),
+            PowerGrouping::Device,
+            Some(T0 + 500),
+            Some(T0 + 1_000),
+        )
+        .unwrap();
+
+        assert_eq!(stats[0].samples, 1);
+        assert_eq!(stats[0].p50_watts, 200.0);
+    }
+
+    #[test]
+    fn power_trace_weighs_samples_by_their_interval() {
+        let power = power_trace(&trace(), std::time::Duration::from_secs(1)).unwrap();
+
+        assert_eq!(power.height(), 4);
+        let pids: Vec<_> = power
+            .column("pid")
+            .unwrap()
+            .u32()
+            .unwrap()
+            .into_no_null_iter()
+            .collect();
+        assert_eq!(pids, [1, 2, 1, 2]);
+        let timestamps: Vec<_> = timestamp_millis(&power)
+            .unwrap()
+            .into_no_null_iter()
+            .collect();
+        assert_eq!(timestamps, [T0, T0, T0 + 1_000, T0 + 1_000]);
+        // The first read only starts the first window's interval, and the
+        // second window averages 100 W and 10 W over half a second each
+        let watts: Vec<_> = power
+            .column(POWER_COLUMN)
+            .unwrap()
+            .f64()
+            .unwrap()
+            .into_no_null_iter()
+            .collect();
+        assert_eq!(watts, [10.0, 10.0, 55.0, 55.0]);
+
+        let empty = power_trace(&trace().head(Some(0)), std::time::Duration::from_secs(1)).unwrap();
+        assert_eq!(empty.get_column_names(), power.get_column_names());
+        assert!(power_trace(&trace(), std::time::Duration::ZERO).is_err());
+    }
+
+    #[test]
+    fn histogram_bins_cover_sample_range() {
+        let histogram = PowerHistogram::from_samples("cpu", &[10.0, 12.0, 15.0, 20.0], 2);
+
+        assert_eq!(histogram.edges, vec![10.0, 15.0, 20.0]);
+        assert_eq!(histogram.counts, vec![2, 2]);
+        assert_eq!(
+            PowerHistogram::from_samples("gpu", &[5.0, 5.0], 4).counts,
+            vec![2, 0, 0, 0]
+        );
+    }
+
+    #[test]
+    fn smoothed_series_keep_timestamps_and_average_watts() {
+        let series = smoothed_power_series(
+            &trace(),
+            PowerGrouping::Device,
+            None,
+            None,
+            "mean:2".parse().unwrap(),
+        )
+        .unwrap();
+
+        let cpu = &series["cpu"];
+        let watts: Vec<_> = cpu.iter().map(|point| point.watts).collect();
+        assert_eq!(watts, vec![20.0, 110.0, 110.0]);
+        assert_eq!(cpu[0].timestamp, T0 + 500);
+        assert_eq!(cpu[2].monotonic_ns, 1_500_000_000);
+    }
+}
@@ -4,6 +4,8 @@ use std::path::Path;
 
 use users::{Users, UsersCache};
 
+use crate::container::{ContainerInfo, ContainerMetadata, detect_container};
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ProcessInfo {
     pub pid: u32,
@@ -22,6 +24,8 @@ pub struct ProcessGroup {
     pub user: String,
     pub pids: Vec<u32>,
     pub representative_pid: u32,
+    /// Container the group's processes run in, when grouped by cgroup
+    pub container: Option<ContainerInfo>,
 }
 
 pub trait GroupingStrategy {
@@ -42,6 +46,7 @@ struct CgroupLabel {
     key: String,
     name: String,
     priority: u8,
+    container: Option<ContainerInfo>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -53,21 +58,25 @@ struct ProcStatIds {
 
 impl GroupingStrategy for CgroupGrouping {
     fn group(&self, processes: &[ProcessInfo]) -> Vec<ProcessGroup> {
-        let mut groups: BTreeMap<String, (String, Vec<&ProcessInfo>)> = BTreeMap::new();
+        let mut groups: BTreeMap<String, (CgroupLabel, Vec<&ProcessInfo>)> = BTreeMap::new();
 
         for process in processes {
             let label = cgroup_label(&process.cgroup_path).unwrap_or_else(unscoped_label);
             groups
-                .entry(label.key)
-                .or_insert_with(|| (label.name, Vec::new()))
+                .entry(label.key.clone())
+                .or_insert_with(|| (label, Vec::new()))
                 .1
                 .push(process);
         }
 
         groups
             .into_iter()
-            .filter_map(|(key, (name, processes))| {
-                build_group(format!("cgroup:{key}"), name, &processes, None)
+            .filter_map(|(key, (label, processes))| {
+                let group = build_group(format!("cgroup:{key}"), label.name, &processes, None)?;
+                Some(ProcessGroup {
+                    container: label.container,
+                    ..group
+                })
             })
             .collect()
     }
@@ -199,6 +208,20 @@ pub fn group_processes(processes: &[ProcessInfo]) -> Vec<ProcessGroup> {
     cgroup_groups
 }
 
+/// Look up the name and image of each group's container, and name the group
+/// after its container once the runtime knows it.
+pub fn resolve_containers(groups: &mut [ProcessGroup], metadata: &ContainerMetadata) {
+    for group in groups {
+        let Some(container) = &mut group.container else {
+            continue;
+        };
+        metadata.resolve(container);
+        if let Some(name) = &container.name {
+            group.name = name.clone();
+        }
+    }
+}
+
 pub fn pid_to_group_map(groups: &[ProcessGroup]) -> HashMap<u32, String> {
     let mut map = HashMap::new();
 
@@ -221,7 +244,7 @@ pub fn tracked_pids(groups: &[ProcessGroup]) -> Vec<u32> {
     pids
 }
 
-fn read_cgroup_path(pid: u32) -> Option<String> {
+pub(crate) fn read_cgroup_path(pid: u32) -> Option<String> {
     let contents = fs::read_to_string(format!("/proc/{pid}/cgroup")).ok()?;
     let path = select_cgroup_path(&contents);
     if path.is_empty() { None } else { Some(path) }
@@ -435,6 +458,22 @@ fn cgroup_label(path: &str) -> Option<CgroupLabel> {
         return None;
     }
 
+    // Containers group everything inside them, including the units of
+    // system containers such as LXC.
+    if let Some((segment, container)) = detect_container(path) {
+        let name = if segment.ends_with(".scope") {
+            normalize_systemd_segment(segment)
+        } else {
+            container.label()
+        };
+        return Some(CgroupLabel {
+            key: container.key(),
+            name,
+            priority: 4,
+            container: Some(container),
+        });
+    }
+
     for segment in segments.iter().rev() {
         if is_systemd_unit_or_scope_segment(segment) && !is_generic_systemd_segment(segment) {
             let name = normalize_systemd_segment(segment);
@@ -442,6 +481,7 @@ fn cgroup_label(path: &str) -> Option<CgroupLabel> {
                 key: name.clone(),
                 name,
                 priority: 3,
+                container: None,
             });
         }
     }
@@ -453,6 +493,7 @@ fn cgroup_label(path: &str) -> Option<CgroupLabel> {
                 key: name.clone(),
                 name,
                 priority: 2,
+                container: None,
             });
         }
     }
@@ -467,6 +508,7 @@ fn cgroup_label(path: &str) -> Option<CgroupLabel> {
                 key: name.clone(),
                 name,
                 priority: 1,
+                container: None,
             }
         })
 }
@@ -484,6 +526,7 @@ fn unscoped_label() -> CgroupLabel {
         key: "unscoped".to_string(),
         name: "unscoped".to_string(),
         priority: 0,
+        container: None,
     }
 }
 
@@ -635,6 +678,7 @@ fn build_group(
         user: common_user(processes),
         pids,
         representative_pid,
+        container: None,
     })
 }
 
@@ -751,6 +795,53 @@ mod tests {
         assert_eq!(groups[1].representative_pid, 100);
     }
 
+    #[test]
+    fn cgroup_grouping_keeps_containers_together() {
+        let podman = "/user.slice/user-1000.slice/user@1000.service/user.slice/libpod-0123456789abcdef.scope";
+        let processes = vec![
+            process(
+                100,
+                Some(1),
+                "systemd",
+                "root",
+                "/lxc.payload.web/init.scope",
+            ),
+            process(
+                101,
+                Some(100),
+                "nginx",
+                "root",
+                "/lxc.payload.web/system.slice/nginx.service",
+            ),
+            process(200, Some(1), "nginx", "root", "/system.slice/nginx.service"),
+            process(
+                300,
+                Some(1),
+                "redis",
+                "alice",
+                &format!("{podman}/container"),
+            ),
+        ];
+
+        let groups = CgroupGrouping.group(&processes);
+        let ids: Vec<&str> = groups.iter().map(|group| group.id.as_str()).collect();
+
+        assert_eq!(
+            ids,
+            vec![
+                "cgroup:lxc:web",
+                "cgroup:nginx.service",
+                "cgroup:podman:0123456789abcdef"
+            ]
+        );
+        assert_eq!(groups[0].name, "web");
+        assert_eq!(groups[0].pids, vec![100, 101]);
+        assert_eq!(groups[1].container, None);
+        let container = groups[2].container.as_ref().expect("podman container");
+        assert_eq!(container.rootless_uid, Some(1000));
+        assert_eq!(groups[2].name, "libpod-0123456789abcdef.scope");
+    }
+
     #[test]
     fn parent_lineage_grouping_uses_descendant_below_pid_one_or_two() {
         let processes = vec![
@@ -927,6 +1018,7 @@ mod tests {
                 user: "synthetic".to_string(),
                 representative_pid: pids.first().copied().unwrap_or_default(),
                 pids,
+                container: None,
             }]
         }
     }
@@ -964,6 +1056,7 @@ mod tests {
                 user: "alice".to_string(),
                 pids: vec![3, 1, 3],
                 representative_pid: 1,
+                container: None,
             },
             ProcessGroup {
                 id: "a".to_string(),
@@ -971,6 +1064,7 @@ mod tests {
                 user: "alice".to_string(),
                 pids: vec![2, 1],
                 representative_pid: 2,
+                container: None,
             },
         ];
 
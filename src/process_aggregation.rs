@@ -1,3 +1,4 @@
+use crate::device_priority::{DevicePriority, DeviceRole};
 use crate::energy_group::EnergyRecord;
 use crate::monitor::DeviceEnergy;
 use std::collections::HashMap;
@@ -15,10 +16,14 @@ pub struct GroupedEnergyTick {
     pub group_energy: HashMap<String, DeviceEnergy>,
     pub pid_energy: HashMap<u32, DeviceEnergy>,
     pub unattributed: DeviceEnergy,
+    /// Energy per authoritative device.
+    pub devices: HashMap<String, f64>,
+    /// Energy from auxiliary devices, excluded from the totals above.
+    pub auxiliary: HashMap<String, f64>,
 }
 
 pub fn classify_record_device(record: &EnergyRecord) -> AggregatedDeviceClass {
-    if record.device.starts_with("nvidia:") {
+    if record.device.starts_with("nvidia:") || record.device.starts_with("amd:gpu:") {
         AggregatedDeviceClass::Gpu
     } else if record.device == "rapl:system:dram" {
         AggregatedDeviceClass::Dram
@@ -30,13 +35,32 @@ pub fn classify_record_device(record: &EnergyRecord) -> AggregatedDeviceClass {
 pub fn aggregate_energy_records(
     records: &[EnergyRecord],
     pid_to_group: &HashMap<u32, String>,
+) -> GroupedEnergyTick {
+    aggregate_energy_records_with_priority(records, pid_to_group, &DevicePriority::default())
+}
+
+/// Aggregate records like [`aggregate_energy_records`], routing devices that
+/// `priority` marks as auxiliary into `GroupedEnergyTick::auxiliary` instead
+/// of the totals.
+pub fn aggregate_energy_records_with_priority(
+    records: &[EnergyRecord],
+    pid_to_group: &HashMap<u32, String>,
+    priority: &DevicePriority,
 ) -> GroupedEnergyTick {
     let mut system_total = DeviceEnergy::default();
     let mut group_energy: HashMap<String, DeviceEnergy> = HashMap::new();
     let mut pid_energy: HashMap<u32, DeviceEnergy> = HashMap::new();
     let mut groups_sum = DeviceEnergy::default();
+    let mut auxiliary: HashMap<String, f64> = HashMap::new();
+    let mut devices: HashMap<String, f64> = HashMap::new();
 
     for record in records {
+        if priority.role(&record.device) == DeviceRole::Auxiliary {
+            *auxiliary.entry(record.device.clone()).or_default() += record.energy;
+            continue;
+        }
+
+        *devices.entry(record.device.clone()).or_default() += record.energy;
         let device_class = classify_record_device(record);
         accumulate_device_energy(&mut system_total, device_class, record.energy);
 
@@ -56,6 +80,8 @@ pub fn aggregate_energy_records(
         group_energy,
         pid_energy,
         unattributed,
+        devices,
+        auxiliary,
     }
 }
 
@@ -87,9 +113,11 @@ mod tests {
     fn record(pid: u32, device: &str, energy: f64) -> EnergyRecord {
         EnergyRecord {
             pid,
-            timestamp: 0,
+            timestamp_us: 0,
+            monotonic_ns: 0,
             device: device.to_string(),
             energy,
+            raw_counter: None,
         }
     }
 
@@ -118,6 +146,10 @@ mod tests {
             classify_record_device(&record(1, "nvidia:gpu:0", 1.0)),
             AggregatedDeviceClass::Gpu
         );
+        assert_eq!(
+            classify_record_device(&record(1, "amd:gpu:0", 1.0)),
+            AggregatedDeviceClass::Gpu
+        );
     }
 
     #[test]
@@ -164,6 +196,25 @@ mod tests {
         assert!(!tick.pid_energy.contains_key(&999));
     }
 
+    #[test]
+    fn auxiliary_devices_are_excluded_from_totals() {
+        let mut priority = DevicePriority::new(crate::config::EmtConfig::default().device_priority);
+        let records = vec![
+            record(101, "rapl:socket:0:package", 2.0),
+            record(101, "rapl:system:psys", 3.0),
+            record(0, "rapl:system:psys", 1.0),
+        ];
+        priority.observe(&records);
+        let pid_to_group = HashMap::from([(101, "work".to_string())]);
+
+        let tick = aggregate_energy_records_with_priority(&records, &pid_to_group, &priority);
+
+        assert_close(tick.system_total.total(), 2.0);
+        assert_close(tick.group_energy.get("work").unwrap().cpu_joules, 2.0);
+        assert_close(tick.unattributed.total(), 0.0);
+        assert_close(*tick.auxiliary.get("rapl:system:psys").unwrap(), 4.0);
+    }
+
     #[test]
     fn percentage_math_handles_zero_and_expected_percent() {
         let group = DeviceEnergy {
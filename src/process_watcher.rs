@@ -0,0 +1,143 @@
+/// Process Watcher Module
+///
+/// Follows the process trees of the pids an `EnergyGroup` tracks. The pids
+/// given to a collector are a snapshot, so workers that a long training job
+/// forks after `commence()` would go unattributed while exited pids stay
+/// tracked. When following children, the watcher re-walks `/proc` at an
+/// interval from the tracked roots and reports the tracked set whenever it
+/// changes. The walk also starts from the processes tracked so far, so a
+/// worker whose parent exited and that was reparented keeps being tracked.
+use crate::utils::psutils::walk_child_pids;
+use std::time::{Duration, Instant};
+
+/// Tracked pids of a group, optionally extended with their descendants.
+#[derive(Debug, Clone, Default)]
+pub struct ProcessWatcher {
+    /// Pids the group was asked to track
+    roots: Vec<u32>,
+    /// Pids handed to the collector, sorted
+    tracked: Vec<u32>,
+    /// How often to re-scan, when following children
+    interval: Option<Duration>,
+    last_scan: Option<Instant>,
+}
+
+impl ProcessWatcher {
+    /// Follow the children of the roots, re-scanning every `interval`, or
+    /// track the roots only with `None`. Returns the pids to track now.
+    pub fn set_interval(&mut self, interval: Option<Duration>) -> Vec<u32> {
+        self.interval = interval;
+        self.tracked.clear();
+        self.rescan();
+        self.tracked.clone()
+    }
+
+    /// Track `roots`, and their descendants when following children.
+    /// Returns the pids to track now.
+    pub fn set_roots(&mut self, roots: Vec<u32>) -> Vec<u32> {
+        self.roots = roots;
+        self.tracked.clear();
+        self.rescan();
+        self.tracked.clone()
+    }
+
+    /// Pids currently tracked
+    pub fn tracked_pids(&self) -> &[u32] {
+        &self.tracked
+    }
+
+    /// Re-scan when following children and the interval has elapsed.
+    /// Returns the pids to track if they changed.
+    pub fn poll(&mut self) -> Option<Vec<u32>> {
+        let interval = self.interval?;
+        if self
+            .last_scan
+            .is_some_and(|last_scan| last_scan.elapsed() < interval)
+        {
+            return None;
+        }
+        self.rescan().then(|| self.tracked.clone())
+    }
+
+    /// Recompute the tracked pids; returns whether they changed.
+    fn rescan(&mut self) -> bool {
+        let mut pids = if self.interval.is_some() {
+            self.last_scan = Some(Instant::now());
+            let mut starts = self.roots.clone();
+            starts.extend_from_slice(&self.tracked);
+            walk_child_pids(&starts)
+        } else {
+            self.roots.clone()
+        };
+        pids.sort_unstable();
+        pids.dedup();
+        let changed = pids != self.tracked;
+        self.tracked = pids;
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+
+    #[test]
+    fn tracks_only_the_roots_unless_following_children() {
+        let mut watcher = ProcessWatcher::default();
+
+        assert_eq!(watcher.set_roots(vec![9, 3, 9]), [3, 9]);
+        assert_eq!(watcher.poll(), None);
+        assert_eq!(watcher.tracked_pids(), [3, 9]);
+    }
+
+    /// Poll until the tracked pids satisfy `done`.
+    fn poll_until(watcher: &mut ProcessWatcher, done: impl Fn(&[u32]) -> bool) -> Vec<u32> {
+        for _ in 0..100 {
+            watcher.poll();
+            if done(watcher.tracked_pids()) {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        watcher.tracked_pids().to_vec()
+    }
+
+    #[test]
+    fn follows_children_spawned_later_and_their_reparented_descendants() {
+        // The shell forks `sleep` once a line arrives on its stdin.
+        let mut shell = Command::new("sh")
+            .args(["-c", "read _; sleep 30; :"])
+            .stdin(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let shell_pid = shell.id();
+        let mut watcher = ProcessWatcher::default();
+        watcher.set_roots(vec![shell_pid]);
+        assert_eq!(watcher.set_interval(Some(Duration::ZERO)), [shell_pid]);
+
+        writeln!(shell.stdin.as_mut().unwrap()).unwrap();
+        let tracked = poll_until(&mut watcher, |pids| pids.len() == 2);
+        let sleep_pid = *tracked.iter().find(|&&pid| pid != shell_pid).unwrap();
+
+        shell.kill().unwrap();
+        shell.wait().unwrap();
+        let tracked = poll_until(&mut watcher, |pids| !pids.contains(&shell_pid));
+        assert_eq!(tracked, [sleep_pid]);
+
+        Command::new("kill")
+            .arg(sleep_pid.to_string())
+            .status()
+            .unwrap();
+    }
+
+    #[test]
+    fn waits_for_the_interval_between_scans() {
+        let mut watcher = ProcessWatcher::default();
+        watcher.set_roots(vec![std::process::id()]);
+        watcher.set_interval(Some(Duration::from_secs(3600)));
+
+        assert_eq!(watcher.poll(), None);
+    }
+}
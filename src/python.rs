@@ -1,15 +1,49 @@
-use crate::collectors::{NvidiaGpu, Rapl};
-use crate::config::EmtConfig;
-use crate::energy_group::{EnergyCollector, EnergyGroup};
+use crate::arrow_stream::export_frame;
+use crate::carbon::CarbonFootprint;
+use crate::collectors::attribution::{AttributionShare, DeviceClass};
+use crate::collectors::rapl::{CpuAttribution, RaplBackend};
+use crate::collectors::{
+    AmdGpu, CompositeCollector, GpuUtilizationRecord, IntelGpu, JetsonIna, Network, NvidiaGpu,
+    Rapl, Storage,
+};
+use crate::config::{EmtConfig, NetworkEnergyConfig, StorageEnergyConfig};
+use crate::device_registry::{DEVICE_ID_COLUMN, DeviceRegistry};
+use crate::energy_group::{
+    BackpressurePolicy, CO2_COLUMN, CUMULATIVE_ENERGY_COLUMN, DegradedDomain, EnergyCollector,
+    EnergyGroup, ORIGINAL_ENERGY_COLUMN, QUALITY_COLUMN, RAW_COUNTER_COLUMN, RecordOutput,
+    SAMPLE_DURATION_COLUMN, TraceMemoryStats,
+};
+use crate::energy_totals::EnergyTotals;
+use crate::forecast::{Completion, EnergyForecast, ForecastMethod, ForecastScope};
+use crate::metric_channels::METRIC_COLUMN_PREFIX;
 use crate::monitor::{Monitor, MonitorHandle};
+use crate::peak_events::{PeakDetection, PeakEvent};
+use crate::power_stats::{PowerGrouping, PowerHistogram, PowerPoint, PowerStats};
+use crate::query::{Filter, TimeRange};
+use crate::regions::RegionEnergy;
+use crate::sampler::{SamplerSubscription, shared_sampler};
+use crate::schema::SchemaError;
+use crate::sessions::SessionSummary;
+use crate::sinks::{TraceSink, open_sink};
+use crate::smoothing::PowerSmoothing;
+use crate::spike_filter::SpikeFiltering;
+use crate::suspend::SuspendGap;
+use crate::trace_recorder::{ParquetOptions, ParquetTraceRecorder, gpu_utilization_frame};
+use crate::trace_summary::TraceSummary;
 use crate::utils::errors::MonitoringError;
+use crate::utils::time::timestamp_millis;
+use crate::work::Efficiency;
 use polars::prelude::DataFrame;
-use pyo3::exceptions::{PyRuntimeError, PyTypeError};
+use pyo3::exceptions::{PyRuntimeError, PyTypeError, PyValueError};
 use pyo3::prelude::*;
-use pyo3::types::{PyAny, PyDict, PyType};
-use std::collections::HashMap;
+use pyo3::types::{PyAny, PyCapsule, PyDict, PyType};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
 use tokio::runtime::{Builder, Runtime};
 
+#[cfg(feature = "macos")]
+use crate::collectors::AppleSmc;
+
 fn to_py_err(err: MonitoringError) -> PyErr {
     PyRuntimeError::new_err(err.to_string())
 }
@@ -25,14 +59,33 @@ fn build_runtime() -> PyResult<Runtime> {
 #[derive(Debug, Default)]
 pub struct PyRaplCollector {
     rapl_path: Option<String>,
+    attribution: CpuAttribution,
+    backend: RaplBackend,
+    domain_attribution: BTreeMap<DeviceClass, AttributionShare>,
 }
 
 #[pymethods]
 impl PyRaplCollector {
     #[new]
-    #[pyo3(signature = (rapl_path=None))]
-    fn new(rapl_path: Option<String>) -> Self {
-        Self { rapl_path }
+    #[pyo3(signature = (rapl_path=None, attribution="system", backend="auto", domain_attribution=None))]
+    fn new(
+        rapl_path: Option<String>,
+        attribution: &str,
+        backend: &str,
+        domain_attribution: Option<BTreeMap<String, String>>,
+    ) -> PyResult<Self> {
+        let domain_attribution = domain_attribution
+            .unwrap_or_default()
+            .iter()
+            .map(|(class, share)| Ok((class.parse()?, share.parse()?)))
+            .collect::<Result<_, String>>()
+            .map_err(PyValueError::new_err)?;
+        Ok(Self {
+            rapl_path,
+            attribution: attribution.parse().map_err(PyValueError::new_err)?,
+            backend: backend.parse().map_err(PyValueError::new_err)?,
+            domain_attribution,
+        })
     }
 
     #[staticmethod]
@@ -41,6 +94,22 @@ impl PyRaplCollector {
     }
 }
 
+impl PyRaplCollector {
+    /// A subscription to the process-wide sampler of RAPL collectors with
+    /// these settings, so groups reading them share one read per tick.
+    fn subscribe(&self, rate: f64) -> SamplerSubscription<Rapl> {
+        shared_sampler(&format!("{self:?}"), rate, || self.open()).subscribe()
+    }
+
+    fn open(&self) -> Rapl {
+        self.domain_attribution.iter().fold(
+            Rapl::with_backend(self.rapl_path.clone(), self.backend)
+                .with_attribution(self.attribution),
+            |rapl, (&class, share)| rapl.with_attribution_strategy(class, share.strategy()),
+        )
+    }
+}
+
 #[pyclass(name = "NvidiaGpuCollector", module = "emt._rust")]
 #[derive(Debug)]
 pub struct PyNvidiaGpuCollector {
@@ -63,9 +132,185 @@ impl PyNvidiaGpuCollector {
     }
 }
 
+impl PyNvidiaGpuCollector {
+    fn open(&self) -> PyResult<NvidiaGpu> {
+        if self.device_ids.is_empty() {
+            NvidiaGpu::new()
+        } else {
+            NvidiaGpu::with_device_filter(self.device_ids.clone())
+        }
+        .map_err(|e| PyRuntimeError::new_err(format!("NVML init failed: {}", e)))
+    }
+}
+
+#[pyclass(name = "AmdGpuCollector", module = "emt._rust")]
+#[derive(Debug, Default)]
+pub struct PyAmdGpuCollector {
+    drm_path: Option<String>,
+}
+
+#[pymethods]
+impl PyAmdGpuCollector {
+    #[new]
+    #[pyo3(signature = (drm_path=None))]
+    fn new(drm_path: Option<String>) -> Self {
+        Self { drm_path }
+    }
+
+    #[staticmethod]
+    fn is_available() -> bool {
+        AmdGpu::is_available()
+    }
+}
+
+#[pyclass(name = "IntelGpuCollector", module = "emt._rust")]
+#[derive(Debug, Default)]
+pub struct PyIntelGpuCollector {
+    drm_path: Option<String>,
+}
+
+#[pymethods]
+impl PyIntelGpuCollector {
+    #[new]
+    #[pyo3(signature = (drm_path=None))]
+    fn new(drm_path: Option<String>) -> Self {
+        Self { drm_path }
+    }
+
+    #[staticmethod]
+    fn is_available() -> bool {
+        IntelGpu::is_available()
+    }
+}
+
+/// Power rails of NVIDIA Jetson boards, read from their INA3221 monitors.
+#[pyclass(name = "JetsonCollector", module = "emt._rust")]
+#[derive(Debug, Default)]
+pub struct PyJetsonCollector {
+    i2c_path: Option<String>,
+}
+
+#[pymethods]
+impl PyJetsonCollector {
+    #[new]
+    #[pyo3(signature = (i2c_path=None))]
+    fn new(i2c_path: Option<String>) -> Self {
+        Self { i2c_path }
+    }
+
+    #[staticmethod]
+    fn is_available() -> bool {
+        JetsonIna::is_available()
+    }
+}
+
+/// Network interface energy estimated from their traffic.
+#[pyclass(name = "NetworkCollector", module = "emt._rust")]
+#[derive(Debug, Default)]
+pub struct PyNetworkCollector {
+    config: NetworkEnergyConfig,
+}
+
+#[pymethods]
+impl PyNetworkCollector {
+    #[new]
+    #[pyo3(signature = (joules_per_byte=None, interfaces=None))]
+    fn new(joules_per_byte: Option<f64>, interfaces: Option<Vec<String>>) -> PyResult<Self> {
+        let mut config = NetworkEnergyConfig::default();
+        if let Some(joules_per_byte) = joules_per_byte {
+            if !(joules_per_byte.is_finite() && joules_per_byte > 0.0) {
+                return Err(PyValueError::new_err(
+                    "joules_per_byte must be a finite value greater than 0",
+                ));
+            }
+            config.joules_per_byte = joules_per_byte;
+        }
+        config.interfaces = interfaces.unwrap_or_default();
+        Ok(Self { config })
+    }
+
+    #[staticmethod]
+    fn is_available() -> bool {
+        Network::is_available()
+    }
+}
+
+/// Storage device energy estimated from their I/O.
+#[pyclass(name = "StorageCollector", module = "emt._rust")]
+#[derive(Debug, Default)]
+pub struct PyStorageCollector {
+    config: StorageEnergyConfig,
+}
+
+#[pymethods]
+impl PyStorageCollector {
+    /// `joules_per_gb` maps device types, `nvme`, `ssd` or `hdd`, to the
+    /// energy of each gigabyte they transfer.
+    #[new]
+    #[pyo3(signature = (joules_per_gb=None, devices=None))]
+    fn new(
+        joules_per_gb: Option<HashMap<String, f64>>,
+        devices: Option<Vec<String>>,
+    ) -> PyResult<Self> {
+        let mut config = StorageEnergyConfig::default();
+        for (kind, value) in joules_per_gb.unwrap_or_default() {
+            let coefficient = match kind.as_str() {
+                "nvme" => &mut config.joules_per_gb.nvme,
+                "ssd" => &mut config.joules_per_gb.ssd,
+                "hdd" => &mut config.joules_per_gb.hdd,
+                _ => {
+                    return Err(PyValueError::new_err(format!(
+                        "unknown device type '{kind}' (expected nvme, ssd or hdd)"
+                    )));
+                }
+            };
+            if !(value.is_finite() && value > 0.0) {
+                return Err(PyValueError::new_err(format!(
+                    "joules_per_gb of {kind} must be a finite value greater than 0"
+                )));
+            }
+            *coefficient = value;
+        }
+        config.devices = devices.unwrap_or_default();
+        Ok(Self { config })
+    }
+
+    #[staticmethod]
+    fn is_available() -> bool {
+        Storage::is_available()
+    }
+}
+
+/// Apple Silicon CPU, GPU and ANE energy from `powermetrics`, which needs
+/// root.
+#[cfg(feature = "macos")]
+#[pyclass(name = "AppleSmcCollector", module = "emt._rust")]
+#[derive(Debug, Default)]
+pub struct PyAppleSmcCollector {
+    powermetrics_path: Option<String>,
+}
+
+#[cfg(feature = "macos")]
+#[pymethods]
+impl PyAppleSmcCollector {
+    #[new]
+    #[pyo3(signature = (powermetrics_path=None))]
+    fn new(powermetrics_path: Option<String>) -> Self {
+        Self { powermetrics_path }
+    }
+
+    #[staticmethod]
+    fn is_available() -> bool {
+        AppleSmc::is_available()
+    }
+}
+
 enum PyEnergyGroupInner {
-    Rapl(EnergyGroup<Rapl>),
+    Rapl(EnergyGroup<SamplerSubscription<Rapl>>),
     NvidiaGpu(EnergyGroup<NvidiaGpu>),
+    AmdGpu(EnergyGroup<AmdGpu>),
+    IntelGpu(EnergyGroup<IntelGpu>),
+    Composite(EnergyGroup<CompositeCollector>),
 }
 
 impl PyEnergyGroupInner {
@@ -73,6 +318,39 @@ impl PyEnergyGroupInner {
         match self {
             Self::Rapl(group) => group.set_tracked_pids(pids),
             Self::NvidiaGpu(group) => group.set_tracked_pids(pids),
+            Self::AmdGpu(group) => group.set_tracked_pids(pids),
+            Self::IntelGpu(group) => group.set_tracked_pids(pids),
+            Self::Composite(group) => group.set_tracked_pids(pids),
+        }
+    }
+
+    fn apply_config(&mut self, config: &EmtConfig) {
+        match self {
+            Self::Rapl(group) => group.apply_config(config, "cpu"),
+            Self::NvidiaGpu(group) => group.apply_config(config, "gpu"),
+            Self::AmdGpu(group) => group.apply_config(config, "amd_gpu"),
+            Self::IntelGpu(group) => group.apply_config(config, "intel_gpu"),
+            Self::Composite(group) => group.apply_config(config, "composite"),
+        }
+    }
+
+    fn follow_children(&self, interval: Option<std::time::Duration>) {
+        match self {
+            Self::Rapl(group) => group.follow_children(interval),
+            Self::NvidiaGpu(group) => group.follow_children(interval),
+            Self::AmdGpu(group) => group.follow_children(interval),
+            Self::IntelGpu(group) => group.follow_children(interval),
+            Self::Composite(group) => group.follow_children(interval),
+        }
+    }
+
+    fn tracked_pids(&self) -> Vec<u32> {
+        match self {
+            Self::Rapl(group) => group.tracked_pids(),
+            Self::NvidiaGpu(group) => group.tracked_pids(),
+            Self::AmdGpu(group) => group.tracked_pids(),
+            Self::IntelGpu(group) => group.tracked_pids(),
+            Self::Composite(group) => group.tracked_pids(),
         }
     }
 
@@ -80,6 +358,33 @@ impl PyEnergyGroupInner {
         match self {
             Self::Rapl(group) => runtime.block_on(group.commence()),
             Self::NvidiaGpu(group) => runtime.block_on(group.commence()),
+            Self::AmdGpu(group) => runtime.block_on(group.commence()),
+            Self::IntelGpu(group) => runtime.block_on(group.commence()),
+            Self::Composite(group) => runtime.block_on(group.commence()),
+        }
+    }
+
+    fn calibrate_baseline(
+        &mut self,
+        runtime: &Runtime,
+        duration: std::time::Duration,
+    ) -> Result<BTreeMap<String, f64>, MonitoringError> {
+        match self {
+            Self::Rapl(group) => runtime.block_on(group.calibrate_baseline(duration)),
+            Self::NvidiaGpu(group) => runtime.block_on(group.calibrate_baseline(duration)),
+            Self::AmdGpu(group) => runtime.block_on(group.calibrate_baseline(duration)),
+            Self::IntelGpu(group) => runtime.block_on(group.calibrate_baseline(duration)),
+            Self::Composite(group) => runtime.block_on(group.calibrate_baseline(duration)),
+        }
+    }
+
+    fn baseline_watts(&self) -> Option<BTreeMap<String, f64>> {
+        match self {
+            Self::Rapl(group) => group.baseline_watts().cloned(),
+            Self::NvidiaGpu(group) => group.baseline_watts().cloned(),
+            Self::AmdGpu(group) => group.baseline_watts().cloned(),
+            Self::IntelGpu(group) => group.baseline_watts().cloned(),
+            Self::Composite(group) => group.baseline_watts().cloned(),
         }
     }
 
@@ -91,13 +396,35 @@ impl PyEnergyGroupInner {
             Self::NvidiaGpu(group) => {
                 group.poll_data();
             }
+            Self::AmdGpu(group) => {
+                group.poll_data();
+            }
+            Self::IntelGpu(group) => {
+                group.poll_data();
+            }
+            Self::Composite(group) => {
+                group.poll_data();
+            }
         }
     }
 
-    fn shutdown(&mut self) -> Result<(), MonitoringError> {
+    fn next_batch(&mut self, runtime: &Runtime) -> Option<(DataFrame, DataFrame)> {
         match self {
-            Self::Rapl(group) => group.shutdown(),
-            Self::NvidiaGpu(group) => group.shutdown(),
+            Self::Rapl(group) => runtime.block_on(group.next_batch()),
+            Self::NvidiaGpu(group) => runtime.block_on(group.next_batch()),
+            Self::AmdGpu(group) => runtime.block_on(group.next_batch()),
+            Self::IntelGpu(group) => runtime.block_on(group.next_batch()),
+            Self::Composite(group) => runtime.block_on(group.next_batch()),
+        }
+    }
+
+    fn shutdown(&mut self, runtime: &Runtime) -> Result<(), MonitoringError> {
+        match self {
+            Self::Rapl(group) => runtime.block_on(group.shutdown()),
+            Self::NvidiaGpu(group) => runtime.block_on(group.shutdown()),
+            Self::AmdGpu(group) => runtime.block_on(group.shutdown()),
+            Self::IntelGpu(group) => runtime.block_on(group.shutdown()),
+            Self::Composite(group) => runtime.block_on(group.shutdown()),
         }
     }
 
@@ -105,6 +432,9 @@ impl PyEnergyGroupInner {
         match self {
             Self::Rapl(group) => group.is_running(),
             Self::NvidiaGpu(group) => group.is_running(),
+            Self::AmdGpu(group) => group.is_running(),
+            Self::IntelGpu(group) => group.is_running(),
+            Self::Composite(group) => group.is_running(),
         }
     }
 
@@ -112,6 +442,51 @@ impl PyEnergyGroupInner {
         match self {
             Self::Rapl(group) => group.energy_trace(),
             Self::NvidiaGpu(group) => group.energy_trace(),
+            Self::AmdGpu(group) => group.energy_trace(),
+            Self::IntelGpu(group) => group.energy_trace(),
+            Self::Composite(group) => group.energy_trace(),
+        }
+    }
+
+    fn metric_trace(&self) -> &DataFrame {
+        match self {
+            Self::Rapl(group) => group.metric_trace(),
+            Self::NvidiaGpu(group) => group.metric_trace(),
+            Self::AmdGpu(group) => group.metric_trace(),
+            Self::IntelGpu(group) => group.metric_trace(),
+            Self::Composite(group) => group.metric_trace(),
+        }
+    }
+
+    fn energy_trace_with_metrics(&self) -> PyResult<DataFrame> {
+        let result = match self {
+            Self::Rapl(group) => group.energy_trace_with_metrics(),
+            Self::NvidiaGpu(group) => group.energy_trace_with_metrics(),
+            Self::AmdGpu(group) => group.energy_trace_with_metrics(),
+            Self::IntelGpu(group) => group.energy_trace_with_metrics(),
+            Self::Composite(group) => group.energy_trace_with_metrics(),
+        };
+        result.map_err(|err| PyRuntimeError::new_err(err.to_string()))
+    }
+
+    fn record_metric(&mut self, name: &str, value: f64) -> PyResult<()> {
+        let result = match self {
+            Self::Rapl(group) => group.record_metric(name, value),
+            Self::NvidiaGpu(group) => group.record_metric(name, value),
+            Self::AmdGpu(group) => group.record_metric(name, value),
+            Self::IntelGpu(group) => group.record_metric(name, value),
+            Self::Composite(group) => group.record_metric(name, value),
+        };
+        result.map_err(|err| PyValueError::new_err(err.to_string()))
+    }
+
+    fn devices(&self) -> &DeviceRegistry {
+        match self {
+            Self::Rapl(group) => group.devices(),
+            Self::NvidiaGpu(group) => group.devices(),
+            Self::AmdGpu(group) => group.devices(),
+            Self::IntelGpu(group) => group.devices(),
+            Self::Composite(group) => group.devices(),
         }
     }
 
@@ -119,8 +494,392 @@ impl PyEnergyGroupInner {
         match self {
             Self::Rapl(group) => group.total_consumed_energy(),
             Self::NvidiaGpu(group) => group.total_consumed_energy(),
+            Self::AmdGpu(group) => group.total_consumed_energy(),
+            Self::IntelGpu(group) => group.total_consumed_energy(),
+            Self::Composite(group) => group.total_consumed_energy(),
+        }
+    }
+
+    fn energy_totals(&self) -> EnergyTotals {
+        match self {
+            Self::Rapl(group) => group.energy_totals(),
+            Self::NvidiaGpu(group) => group.energy_totals(),
+            Self::AmdGpu(group) => group.energy_totals(),
+            Self::IntelGpu(group) => group.energy_totals(),
+            Self::Composite(group) => group.energy_totals(),
+        }
+    }
+
+    fn set_carbon_intensity(&mut self, g_per_kwh: f64) {
+        match self {
+            Self::Rapl(group) => group.set_carbon_intensity(g_per_kwh),
+            Self::NvidiaGpu(group) => group.set_carbon_intensity(g_per_kwh),
+            Self::AmdGpu(group) => group.set_carbon_intensity(g_per_kwh),
+            Self::IntelGpu(group) => group.set_carbon_intensity(g_per_kwh),
+            Self::Composite(group) => group.set_carbon_intensity(g_per_kwh),
+        }
+    }
+
+    fn carbon_footprint(&self) -> Option<CarbonFootprint> {
+        match self {
+            Self::Rapl(group) => group.carbon_footprint(),
+            Self::NvidiaGpu(group) => group.carbon_footprint(),
+            Self::AmdGpu(group) => group.carbon_footprint(),
+            Self::IntelGpu(group) => group.carbon_footprint(),
+            Self::Composite(group) => group.carbon_footprint(),
+        }
+    }
+
+    fn set_record_output(&mut self, output: RecordOutput) {
+        match self {
+            Self::Rapl(group) => group.set_record_output(output),
+            Self::NvidiaGpu(group) => group.set_record_output(output),
+            Self::AmdGpu(group) => group.set_record_output(output),
+            Self::IntelGpu(group) => group.set_record_output(output),
+            Self::Composite(group) => group.set_record_output(output),
+        }
+    }
+
+    fn set_backpressure(&mut self, capacity: usize, policy: BackpressurePolicy) {
+        match self {
+            Self::Rapl(group) => group.set_backpressure(capacity, policy),
+            Self::NvidiaGpu(group) => group.set_backpressure(capacity, policy),
+            Self::AmdGpu(group) => group.set_backpressure(capacity, policy),
+            Self::IntelGpu(group) => group.set_backpressure(capacity, policy),
+            Self::Composite(group) => group.set_backpressure(capacity, policy),
+        }
+    }
+
+    fn set_auto_drain(&mut self, enabled: bool) {
+        match self {
+            Self::Rapl(group) => group.set_auto_drain(enabled),
+            Self::NvidiaGpu(group) => group.set_auto_drain(enabled),
+            Self::AmdGpu(group) => group.set_auto_drain(enabled),
+            Self::IntelGpu(group) => group.set_auto_drain(enabled),
+            Self::Composite(group) => group.set_auto_drain(enabled),
+        }
+    }
+
+    fn set_write_ahead_log(&mut self, path: &str) -> PyResult<usize> {
+        let result = match self {
+            Self::Rapl(group) => group.set_write_ahead_log(path),
+            Self::NvidiaGpu(group) => group.set_write_ahead_log(path),
+            Self::AmdGpu(group) => group.set_write_ahead_log(path),
+            Self::IntelGpu(group) => group.set_write_ahead_log(path),
+            Self::Composite(group) => group.set_write_ahead_log(path),
+        };
+        result.map_err(|err| PyRuntimeError::new_err(err.to_string()))
+    }
+
+    fn write_parquet(&self, dir: &str, options: ParquetOptions) -> PyResult<usize> {
+        let result = match self {
+            Self::Rapl(group) => group.write_parquet(dir, options),
+            Self::NvidiaGpu(group) => group.write_parquet(dir, options),
+            Self::AmdGpu(group) => group.write_parquet(dir, options),
+            Self::IntelGpu(group) => group.write_parquet(dir, options),
+            Self::Composite(group) => group.write_parquet(dir, options),
+        };
+        result.map_err(|err| PyRuntimeError::new_err(err.to_string()))
+    }
+
+    fn dropped_batches(&self) -> u64 {
+        match self {
+            Self::Rapl(group) => group.dropped_batches(),
+            Self::NvidiaGpu(group) => group.dropped_batches(),
+            Self::AmdGpu(group) => group.dropped_batches(),
+            Self::IntelGpu(group) => group.dropped_batches(),
+            Self::Composite(group) => group.dropped_batches(),
+        }
+    }
+
+    fn gpu_utilization(&self, runtime: &Runtime) -> Result<Vec<GpuUtilizationRecord>, String> {
+        match self {
+            Self::Rapl(_) | Self::AmdGpu(_) | Self::IntelGpu(_) | Self::Composite(_) => {
+                Ok(Vec::new())
+            }
+            Self::NvidiaGpu(group) => runtime.block_on(group.collector().get_utilization_trace()),
+        }
+    }
+
+    fn degraded_domains(&self) -> Vec<DegradedDomain> {
+        match self {
+            Self::Rapl(group) => group.degraded_domains(),
+            Self::NvidiaGpu(group) => group.degraded_domains(),
+            Self::AmdGpu(group) => group.degraded_domains(),
+            Self::IntelGpu(group) => group.degraded_domains(),
+            Self::Composite(group) => group.degraded_domains(),
+        }
+    }
+
+    fn trace_stats(&self) -> TraceMemoryStats {
+        match self {
+            Self::Rapl(group) => group.trace_stats(),
+            Self::NvidiaGpu(group) => group.trace_stats(),
+            Self::AmdGpu(group) => group.trace_stats(),
+            Self::IntelGpu(group) => group.trace_stats(),
+            Self::Composite(group) => group.trace_stats(),
+        }
+    }
+
+    fn set_peak_detection(&mut self, config: PeakDetection) {
+        match self {
+            Self::Rapl(group) => group.set_peak_detection(config),
+            Self::NvidiaGpu(group) => group.set_peak_detection(config),
+            Self::AmdGpu(group) => group.set_peak_detection(config),
+            Self::IntelGpu(group) => group.set_peak_detection(config),
+            Self::Composite(group) => group.set_peak_detection(config),
+        }
+    }
+
+    fn set_spike_filter(&mut self, config: SpikeFiltering) {
+        match self {
+            Self::Rapl(group) => group.set_spike_filter(config),
+            Self::NvidiaGpu(group) => group.set_spike_filter(config),
+            Self::AmdGpu(group) => group.set_spike_filter(config),
+            Self::IntelGpu(group) => group.set_spike_filter(config),
+            Self::Composite(group) => group.set_spike_filter(config),
         }
     }
+
+    fn peak_events(&self) -> Vec<PeakEvent> {
+        match self {
+            Self::Rapl(group) => group.peak_events(),
+            Self::NvidiaGpu(group) => group.peak_events(),
+            Self::AmdGpu(group) => group.peak_events(),
+            Self::IntelGpu(group) => group.peak_events(),
+            Self::Composite(group) => group.peak_events(),
+        }
+    }
+
+    fn suspend_gaps(&self) -> Vec<SuspendGap> {
+        match self {
+            Self::Rapl(group) => group.suspend_gaps(),
+            Self::NvidiaGpu(group) => group.suspend_gaps(),
+            Self::AmdGpu(group) => group.suspend_gaps(),
+            Self::IntelGpu(group) => group.suspend_gaps(),
+            Self::Composite(group) => group.suspend_gaps(),
+        }
+    }
+
+    fn begin_region(&mut self, name: &str) {
+        match self {
+            Self::Rapl(group) => group.begin_region(name),
+            Self::NvidiaGpu(group) => group.begin_region(name),
+            Self::AmdGpu(group) => group.begin_region(name),
+            Self::IntelGpu(group) => group.begin_region(name),
+            Self::Composite(group) => group.begin_region(name),
+        }
+    }
+
+    fn end_region(&mut self, name: &str) -> PyResult<()> {
+        let result = match self {
+            Self::Rapl(group) => group.end_region(name),
+            Self::NvidiaGpu(group) => group.end_region(name),
+            Self::AmdGpu(group) => group.end_region(name),
+            Self::IntelGpu(group) => group.end_region(name),
+            Self::Composite(group) => group.end_region(name),
+        };
+        result.map_err(|err| PyRuntimeError::new_err(err.to_string()))
+    }
+
+    fn begin_session(&mut self, name: &str) -> PyResult<()> {
+        let result = match self {
+            Self::Rapl(group) => group.begin_session(name),
+            Self::NvidiaGpu(group) => group.begin_session(name),
+            Self::AmdGpu(group) => group.begin_session(name),
+            Self::IntelGpu(group) => group.begin_session(name),
+            Self::Composite(group) => group.begin_session(name),
+        };
+        result.map_err(|err| PyRuntimeError::new_err(err.to_string()))
+    }
+
+    fn end_session(&mut self) -> PyResult<String> {
+        let result = match self {
+            Self::Rapl(group) => group.end_session(),
+            Self::NvidiaGpu(group) => group.end_session(),
+            Self::AmdGpu(group) => group.end_session(),
+            Self::IntelGpu(group) => group.end_session(),
+            Self::Composite(group) => group.end_session(),
+        };
+        result.map_err(|err| PyRuntimeError::new_err(err.to_string()))
+    }
+
+    fn session_summary(&self, name: &str) -> PyResult<SessionSummary> {
+        let result = match self {
+            Self::Rapl(group) => group.session_summary(name),
+            Self::NvidiaGpu(group) => group.session_summary(name),
+            Self::AmdGpu(group) => group.session_summary(name),
+            Self::IntelGpu(group) => group.session_summary(name),
+            Self::Composite(group) => group.session_summary(name),
+        };
+        result.map_err(|err| PyRuntimeError::new_err(err.to_string()))
+    }
+
+    fn session_trace(&self) -> PyResult<DataFrame> {
+        let result = match self {
+            Self::Rapl(group) => group.session_trace(),
+            Self::NvidiaGpu(group) => group.session_trace(),
+            Self::AmdGpu(group) => group.session_trace(),
+            Self::IntelGpu(group) => group.session_trace(),
+            Self::Composite(group) => group.session_trace(),
+        };
+        result.map_err(|err| PyRuntimeError::new_err(err.to_string()))
+    }
+
+    fn add_sink(&mut self, sink: Box<dyn TraceSink>) {
+        match self {
+            Self::Rapl(group) => group.add_sink(sink),
+            Self::NvidiaGpu(group) => group.add_sink(sink),
+            Self::AmdGpu(group) => group.add_sink(sink),
+            Self::IntelGpu(group) => group.add_sink(sink),
+            Self::Composite(group) => group.add_sink(sink),
+        }
+    }
+
+    fn add_work(&mut self, unit: &str, count: f64, pid: Option<u32>) -> PyResult<()> {
+        let result = match self {
+            Self::Rapl(group) => group.add_work(unit, count, pid),
+            Self::NvidiaGpu(group) => group.add_work(unit, count, pid),
+            Self::AmdGpu(group) => group.add_work(unit, count, pid),
+            Self::IntelGpu(group) => group.add_work(unit, count, pid),
+            Self::Composite(group) => group.add_work(unit, count, pid),
+        };
+        result.map_err(|err| PyValueError::new_err(err.to_string()))
+    }
+
+    fn efficiency(&self) -> PyResult<Vec<Efficiency>> {
+        let result = match self {
+            Self::Rapl(group) => group.efficiency(),
+            Self::NvidiaGpu(group) => group.efficiency(),
+            Self::AmdGpu(group) => group.efficiency(),
+            Self::IntelGpu(group) => group.efficiency(),
+            Self::Composite(group) => group.efficiency(),
+        };
+        result.map_err(|err| PyRuntimeError::new_err(err.to_string()))
+    }
+
+    fn forecast(
+        &self,
+        scope: &ForecastScope,
+        completion: Completion,
+        method: ForecastMethod,
+    ) -> PyResult<Option<EnergyForecast>> {
+        let result = match self {
+            Self::Rapl(group) => group.forecast(scope, completion, method),
+            Self::NvidiaGpu(group) => group.forecast(scope, completion, method),
+            Self::AmdGpu(group) => group.forecast(scope, completion, method),
+            Self::IntelGpu(group) => group.forecast(scope, completion, method),
+            Self::Composite(group) => group.forecast(scope, completion, method),
+        };
+        result.map_err(|err| PyValueError::new_err(err.to_string()))
+    }
+
+    fn power_stats(
+        &self,
+        grouping: PowerGrouping,
+        start: Option<i64>,
+        end: Option<i64>,
+    ) -> PyResult<Vec<PowerStats>> {
+        let result = match self {
+            Self::Rapl(group) => group.power_stats(grouping, start, end),
+            Self::NvidiaGpu(group) => group.power_stats(grouping, start, end),
+            Self::AmdGpu(group) => group.power_stats(grouping, start, end),
+            Self::IntelGpu(group) => group.power_stats(grouping, start, end),
+            Self::Composite(group) => group.power_stats(grouping, start, end),
+        };
+        result.map_err(|err| PyRuntimeError::new_err(err.to_string()))
+    }
+
+    fn query(&self, range: TimeRange, filter: &Filter) -> PyResult<DataFrame> {
+        let result = match self {
+            Self::Rapl(group) => group.query(range, filter),
+            Self::NvidiaGpu(group) => group.query(range, filter),
+            Self::AmdGpu(group) => group.query(range, filter),
+            Self::IntelGpu(group) => group.query(range, filter),
+            Self::Composite(group) => group.query(range, filter),
+        };
+        result
+            .and_then(|query| query.collect())
+            .map_err(|err| PyRuntimeError::new_err(err.to_string()))
+    }
+
+    fn power_series(
+        &self,
+        grouping: PowerGrouping,
+        start: Option<i64>,
+        end: Option<i64>,
+        smoothing: PowerSmoothing,
+    ) -> PyResult<BTreeMap<String, Vec<PowerPoint>>> {
+        let result = match self {
+            Self::Rapl(group) => group.power_series(grouping, start, end, smoothing),
+            Self::NvidiaGpu(group) => group.power_series(grouping, start, end, smoothing),
+            Self::AmdGpu(group) => group.power_series(grouping, start, end, smoothing),
+            Self::IntelGpu(group) => group.power_series(grouping, start, end, smoothing),
+            Self::Composite(group) => group.power_series(grouping, start, end, smoothing),
+        };
+        result.map_err(|err| PyRuntimeError::new_err(err.to_string()))
+    }
+
+    fn power_histograms(
+        &self,
+        grouping: PowerGrouping,
+        start: Option<i64>,
+        end: Option<i64>,
+        bins: usize,
+    ) -> PyResult<Vec<PowerHistogram>> {
+        let result = match self {
+            Self::Rapl(group) => group.power_histograms(grouping, start, end, bins),
+            Self::NvidiaGpu(group) => group.power_histograms(grouping, start, end, bins),
+            Self::AmdGpu(group) => group.power_histograms(grouping, start, end, bins),
+            Self::IntelGpu(group) => group.power_histograms(grouping, start, end, bins),
+            Self::Composite(group) => group.power_histograms(grouping, start, end, bins),
+        };
+        result.map_err(|err| PyRuntimeError::new_err(err.to_string()))
+    }
+
+    fn total_energy_by_device(&self) -> PyResult<BTreeMap<String, f64>> {
+        let result = match self {
+            Self::Rapl(group) => group.total_energy_by_device(),
+            Self::NvidiaGpu(group) => group.total_energy_by_device(),
+            Self::AmdGpu(group) => group.total_energy_by_device(),
+            Self::IntelGpu(group) => group.total_energy_by_device(),
+            Self::Composite(group) => group.total_energy_by_device(),
+        };
+        result.map_err(|err| PyRuntimeError::new_err(err.to_string()))
+    }
+
+    fn total_energy_by_pid(&self) -> PyResult<BTreeMap<u32, f64>> {
+        let result = match self {
+            Self::Rapl(group) => group.total_energy_by_pid(),
+            Self::NvidiaGpu(group) => group.total_energy_by_pid(),
+            Self::AmdGpu(group) => group.total_energy_by_pid(),
+            Self::IntelGpu(group) => group.total_energy_by_pid(),
+            Self::Composite(group) => group.total_energy_by_pid(),
+        };
+        result.map_err(|err| PyRuntimeError::new_err(err.to_string()))
+    }
+
+    fn summary(&self) -> PyResult<TraceSummary> {
+        let result = match self {
+            Self::Rapl(group) => group.summary(),
+            Self::NvidiaGpu(group) => group.summary(),
+            Self::AmdGpu(group) => group.summary(),
+            Self::IntelGpu(group) => group.summary(),
+            Self::Composite(group) => group.summary(),
+        };
+        result.map_err(|err| PyRuntimeError::new_err(err.to_string()))
+    }
+
+    fn energy_by_region(&self, per_device: bool) -> PyResult<Vec<RegionEnergy>> {
+        let result = match self {
+            Self::Rapl(group) => group.energy_by_region(per_device),
+            Self::NvidiaGpu(group) => group.energy_by_region(per_device),
+            Self::AmdGpu(group) => group.energy_by_region(per_device),
+            Self::IntelGpu(group) => group.energy_by_region(per_device),
+            Self::Composite(group) => group.energy_by_region(per_device),
+        };
+        result.map_err(|err| PyRuntimeError::new_err(err.to_string()))
+    }
 }
 
 fn energy_trace_to_py_dict(py: Python<'_>, trace: &DataFrame) -> PyResult<Py<PyAny>> {
@@ -129,6 +888,7 @@ fn energy_trace_to_py_dict(py: Python<'_>, trace: &DataFrame) -> PyResult<Py<PyA
     if trace.is_empty() || trace.width() == 0 {
         dict.set_item("pid", Vec::<u32>::new())?;
         dict.set_item("device", Vec::<String>::new())?;
+        dict.set_item("device_id", Vec::<u32>::new())?;
         dict.set_item("energy", Vec::<f64>::new())?;
         dict.set_item("timestamp", Vec::<i64>::new())?;
         return Ok(dict.into_any().unbind());
@@ -151,6 +911,14 @@ fn energy_trace_to_py_dict(py: Python<'_>, trace: &DataFrame) -> PyResult<Py<PyA
         .flatten()
         .map(str::to_string)
         .collect::<Vec<_>>();
+    let device_ids = trace
+        .column(DEVICE_ID_COLUMN)
+        .map_err(|err| PyRuntimeError::new_err(err.to_string()))?
+        .u32()
+        .map_err(|err| PyRuntimeError::new_err(err.to_string()))?
+        .iter()
+        .flatten()
+        .collect::<Vec<_>>();
     let energies = trace
         .column("energy")
         .map_err(|err| PyRuntimeError::new_err(err.to_string()))?
@@ -159,10 +927,7 @@ fn energy_trace_to_py_dict(py: Python<'_>, trace: &DataFrame) -> PyResult<Py<PyA
         .iter()
         .flatten()
         .collect::<Vec<_>>();
-    let timestamps = trace
-        .column("timestamp")
-        .map_err(|err| PyRuntimeError::new_err(err.to_string()))?
-        .i64()
+    let timestamps = timestamp_millis(trace)
         .map_err(|err| PyRuntimeError::new_err(err.to_string()))?
         .iter()
         .flatten()
@@ -170,11 +935,211 @@ fn energy_trace_to_py_dict(py: Python<'_>, trace: &DataFrame) -> PyResult<Py<PyA
 
     dict.set_item("pid", pids)?;
     dict.set_item("device", devices)?;
+    dict.set_item("device_id", device_ids)?;
     dict.set_item("energy", energies)?;
     dict.set_item("timestamp", timestamps)?;
+    for name in [
+        CUMULATIVE_ENERGY_COLUMN,
+        RAW_COUNTER_COLUMN,
+        CO2_COLUMN,
+        SAMPLE_DURATION_COLUMN,
+        ORIGINAL_ENERGY_COLUMN,
+    ] {
+        if let Ok(column) = trace.column(name) {
+            let values = column
+                .f64()
+                .map_err(|err| PyRuntimeError::new_err(err.to_string()))?
+                .iter()
+                .collect::<Vec<_>>();
+            dict.set_item(name, values)?;
+        }
+    }
+    if let Ok(column) = trace.column(QUALITY_COLUMN) {
+        let values = column
+            .str()
+            .map_err(|err| PyRuntimeError::new_err(err.to_string()))?
+            .iter()
+            .collect::<Vec<_>>();
+        dict.set_item(QUALITY_COLUMN, values)?;
+    }
+    for column in trace.get_columns() {
+        if column.name().starts_with(METRIC_COLUMN_PREFIX) {
+            let values = column
+                .f64()
+                .map_err(|err| PyRuntimeError::new_err(err.to_string()))?
+                .iter()
+                .collect::<Vec<_>>();
+            dict.set_item(column.name().as_str(), values)?;
+        }
+    }
+    Ok(dict.into_any().unbind())
+}
+
+fn metric_trace_to_py_dict(py: Python<'_>, metrics: &DataFrame) -> PyResult<Py<PyAny>> {
+    let dict = PyDict::new(py);
+    if metrics.height() == 0 {
+        dict.set_item("name", Vec::<String>::new())?;
+        dict.set_item("value", Vec::<f64>::new())?;
+        dict.set_item("timestamp", Vec::<i64>::new())?;
+        return Ok(dict.into_any().unbind());
+    }
+    let names = metrics
+        .column("name")
+        .and_then(|column| column.str().cloned())
+        .map_err(|err| PyRuntimeError::new_err(err.to_string()))?;
+    let values = metrics
+        .column("value")
+        .and_then(|column| column.f64().cloned())
+        .map_err(|err| PyRuntimeError::new_err(err.to_string()))?;
+    let timestamps =
+        timestamp_millis(metrics).map_err(|err| PyRuntimeError::new_err(err.to_string()))?;
+    dict.set_item(
+        "name",
+        names
+            .iter()
+            .flatten()
+            .map(str::to_string)
+            .collect::<Vec<_>>(),
+    )?;
+    dict.set_item("value", values.iter().flatten().collect::<Vec<_>>())?;
+    dict.set_item("timestamp", timestamps.iter().flatten().collect::<Vec<_>>())?;
     Ok(dict.into_any().unbind())
 }
 
+fn energy_totals_to_py_dict(py: Python<'_>, totals: &EnergyTotals) -> PyResult<Py<PyAny>> {
+    let dict = PyDict::new(py);
+    dict.set_item("total_joules", totals.total_joules)?;
+    dict.set_item("domains", &totals.domains)?;
+    dict.set_item("components", &totals.components)?;
+    Ok(dict.into_any().unbind())
+}
+
+fn device_table_to_py_dict(py: Python<'_>, devices: &DeviceRegistry) -> PyResult<Py<PyAny>> {
+    let dict = PyDict::new(py);
+    let infos = devices.iter().collect::<Vec<_>>();
+    dict.set_item(
+        DEVICE_ID_COLUMN,
+        infos.iter().map(|(id, _)| *id).collect::<Vec<_>>(),
+    )?;
+    dict.set_item(
+        "device",
+        infos
+            .iter()
+            .map(|(_, d)| d.name.clone())
+            .collect::<Vec<_>>(),
+    )?;
+    dict.set_item(
+        "kind",
+        infos
+            .iter()
+            .map(|(_, d)| d.kind.as_str())
+            .collect::<Vec<_>>(),
+    )?;
+    dict.set_item(
+        "socket",
+        infos.iter().map(|(_, d)| d.socket).collect::<Vec<_>>(),
+    )?;
+    dict.set_item(
+        "vendor",
+        infos
+            .iter()
+            .map(|(_, d)| d.vendor.clone())
+            .collect::<Vec<_>>(),
+    )?;
+    dict.set_item(
+        "model",
+        infos
+            .iter()
+            .map(|(_, d)| d.model.clone())
+            .collect::<Vec<_>>(),
+    )?;
+    dict.set_item(
+        "max_power_watts",
+        infos
+            .iter()
+            .map(|(_, d)| d.max_power_watts)
+            .collect::<Vec<_>>(),
+    )?;
+    dict.set_item(
+        "source",
+        infos
+            .iter()
+            .map(|(_, d)| d.source.clone())
+            .collect::<Vec<_>>(),
+    )?;
+    Ok(dict.into_any().unbind())
+}
+
+const COLLECTOR_TYPE_ERROR: &str = "collector must be an instance of RaplCollector, NvidiaGpuCollector, AmdGpuCollector, IntelGpuCollector, JetsonCollector, NetworkCollector or StorageCollector, or a list of them";
+
+/// A collector of an `EnergyGroup` read together with others, and the name
+/// it is logged under.
+fn boxed_collector(
+    collector: &Bound<'_, PyAny>,
+    rate: f64,
+) -> PyResult<(&'static str, Box<dyn EnergyCollector>)> {
+    if let Ok(collector_ref) = collector.extract::<PyRef<'_, PyRaplCollector>>() {
+        return Ok(("rapl", Box::new(collector_ref.subscribe(rate))));
+    }
+    if let Ok(collector_ref) = collector.extract::<PyRef<'_, PyNvidiaGpuCollector>>() {
+        return Ok(("nvidia", Box::new(collector_ref.open()?)));
+    }
+    if let Ok(collector_ref) = collector.extract::<PyRef<'_, PyAmdGpuCollector>>() {
+        return Ok(("amd", Box::new(AmdGpu::new(collector_ref.drm_path.clone()))));
+    }
+    if let Ok(collector_ref) = collector.extract::<PyRef<'_, PyIntelGpuCollector>>() {
+        let gpu = IntelGpu::new(collector_ref.drm_path.clone());
+        return Ok(("intel", Box::new(gpu)));
+    }
+    if let Ok(collector_ref) = collector.extract::<PyRef<'_, PyJetsonCollector>>() {
+        let jetson = JetsonIna::new(collector_ref.i2c_path.clone());
+        return Ok(("jetson", Box::new(jetson)));
+    }
+    if let Ok(collector_ref) = collector.extract::<PyRef<'_, PyNetworkCollector>>() {
+        let network = Network::new(collector_ref.config.clone());
+        return Ok(("network", Box::new(network)));
+    }
+    if let Ok(collector_ref) = collector.extract::<PyRef<'_, PyStorageCollector>>() {
+        let storage = Storage::new(collector_ref.config.clone());
+        return Ok(("storage", Box::new(storage)));
+    }
+    #[cfg(feature = "macos")]
+    if let Ok(collector_ref) = collector.extract::<PyRef<'_, PyAppleSmcCollector>>() {
+        let smc = AppleSmc::new(collector_ref.powermetrics_path.clone());
+        return Ok(("apple", Box::new(smc)));
+    }
+    Err(PyTypeError::new_err(COLLECTOR_TYPE_ERROR))
+}
+
+/// A trace exported through the Arrow PyCapsule interface.
+#[pyclass(name = "ArrowTrace", module = "emt._rust")]
+pub struct PyArrowTrace {
+    frame: DataFrame,
+}
+
+#[pymethods]
+impl PyArrowTrace {
+    /// A fresh Arrow C stream over the trace. The trace is always exported
+    /// in its own schema, which the interface lets consumers cast.
+    #[pyo3(signature = (requested_schema=None))]
+    fn __arrow_c_stream__<'py>(
+        &self,
+        py: Python<'py>,
+        requested_schema: Option<Bound<'py, PyAny>>,
+    ) -> PyResult<Bound<'py, PyCapsule>> {
+        drop(requested_schema);
+        PyCapsule::new(
+            py,
+            export_frame(self.frame.clone()),
+            Some(c"arrow_array_stream".to_owned()),
+        )
+    }
+
+    fn __len__(&self) -> usize {
+        self.frame.height()
+    }
+}
+
 #[pyclass(name = "EnergyGroup", module = "emt._rust")]
 pub struct PyEnergyGroup {
     runtime: Runtime,
@@ -202,8 +1167,7 @@ impl PyEnergyGroup {
         batch_size: Option<usize>,
     ) -> PyResult<Self> {
         if let Ok(collector_ref) = collector.extract::<PyRef<'_, PyRaplCollector>>() {
-            let group =
-                EnergyGroup::new(Rapl::new(collector_ref.rapl_path.clone()), rate, batch_size);
+            let group = EnergyGroup::new(collector_ref.subscribe(rate), rate, batch_size);
             let result = Self::with_inner(PyEnergyGroupInner::Rapl(group))?;
             if let Some(pids) = pids {
                 result.inner.set_tracked_pids(pids);
@@ -212,14 +1176,7 @@ impl PyEnergyGroup {
         }
 
         if let Ok(collector_ref) = collector.extract::<PyRef<'_, PyNvidiaGpuCollector>>() {
-            let nvidia_collector = if collector_ref.device_ids.is_empty() {
-                NvidiaGpu::new()
-                    .map_err(|e| PyRuntimeError::new_err(format!("NVML init failed: {}", e)))?
-            } else {
-                NvidiaGpu::with_device_filter(collector_ref.device_ids.clone())
-                    .map_err(|e| PyRuntimeError::new_err(format!("NVML init failed: {}", e)))?
-            };
-            let group = EnergyGroup::new(nvidia_collector, rate, batch_size);
+            let group = EnergyGroup::new(collector_ref.open()?, rate, batch_size);
             let result = Self::with_inner(PyEnergyGroupInner::NvidiaGpu(group))?;
             if let Some(pids) = pids {
                 result.inner.set_tracked_pids(pids);
@@ -227,20 +1184,132 @@ impl PyEnergyGroup {
             return Ok(result);
         }
 
-        Err(PyTypeError::new_err(
-            "collector must be an instance of RaplCollector or NvidiaGpuCollector",
-        ))
+        if let Ok(collector_ref) = collector.extract::<PyRef<'_, PyAmdGpuCollector>>() {
+            let group = EnergyGroup::new(
+                AmdGpu::new(collector_ref.drm_path.clone()),
+                rate,
+                batch_size,
+            );
+            let result = Self::with_inner(PyEnergyGroupInner::AmdGpu(group))?;
+            if let Some(pids) = pids {
+                result.inner.set_tracked_pids(pids);
+            }
+            return Ok(result);
+        }
+
+        if let Ok(collector_ref) = collector.extract::<PyRef<'_, PyIntelGpuCollector>>() {
+            let group = EnergyGroup::new(
+                IntelGpu::new(collector_ref.drm_path.clone()),
+                rate,
+                batch_size,
+            );
+            let result = Self::with_inner(PyEnergyGroupInner::IntelGpu(group))?;
+            if let Some(pids) = pids {
+                result.inner.set_tracked_pids(pids);
+            }
+            return Ok(result);
+        }
+
+        if let Ok(collectors) = collector.extract::<Vec<Bound<'_, PyAny>>>() {
+            let mut composite = CompositeCollector::new();
+            for collector in &collectors {
+                let (name, member) = boxed_collector(collector, rate)?;
+                composite.push(name, member);
+            }
+            let group = EnergyGroup::new(composite, rate, batch_size);
+            let result = Self::with_inner(PyEnergyGroupInner::Composite(group))?;
+            if let Some(pids) = pids {
+                result.inner.set_tracked_pids(pids);
+            }
+            return Ok(result);
+        }
+
+        // Collectors without a group variant of their own run as the only
+        // member of a composite.
+        let (name, member) = boxed_collector(collector, rate)?;
+        let mut composite = CompositeCollector::new();
+        composite.push(name, member);
+        let group = EnergyGroup::new(composite, rate, batch_size);
+        let result = Self::with_inner(PyEnergyGroupInner::Composite(group))?;
+        if let Some(pids) = pids {
+            result.inner.set_tracked_pids(pids);
+        }
+        Ok(result)
+    }
+
+    /// A group of `collector` configured by the YAML or TOML file at
+    /// `path`, with its `collection` rate and batch size.
+    #[classmethod]
+    #[pyo3(signature = (collector, path, pids=None))]
+    fn from_config(
+        cls: &Bound<'_, PyType>,
+        collector: &Bound<'_, PyAny>,
+        path: &str,
+        pids: Option<Vec<u32>>,
+    ) -> PyResult<Self> {
+        let config = EmtConfig::from_file(Path::new(path))
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        let mut group = Self::create(
+            cls,
+            collector,
+            config.collection.rate_hz,
+            pids,
+            config.collection.batch_size,
+        )?;
+        group.inner.apply_config(&config);
+        Ok(group)
     }
 
     fn set_tracked_pids(&self, pids: Vec<u32>) {
         self.inner.set_tracked_pids(pids);
     }
 
+    /// Also track the descendants of the tracked pids, re-scanning every
+    /// `interval_secs` while collecting, or only the given pids with `None`.
+    #[pyo3(signature = (interval_secs=Some(1.0)))]
+    fn follow_children(&self, interval_secs: Option<f64>) -> PyResult<()> {
+        let interval = interval_secs
+            .map(std::time::Duration::try_from_secs_f64)
+            .transpose()
+            .map_err(|err| PyValueError::new_err(err.to_string()))?;
+        self.inner.follow_children(interval);
+        Ok(())
+    }
+
+    /// Pids energy is currently attributed to
+    fn tracked_pids(&self) -> Vec<u32> {
+        self.inner.tracked_pids()
+    }
+
     fn commence(&mut self, py: Python<'_>) -> PyResult<()> {
         let runtime = &self.runtime;
         py.detach(|| self.inner.commence(runtime).map_err(to_py_err))
     }
 
+    /// Measure each device's idle power for `duration_secs` before
+    /// `commence`, adding the `marginal_energy` column with the energy above
+    /// it to the trace. Returns the baselines in watts by device.
+    fn calibrate_baseline(
+        &mut self,
+        py: Python<'_>,
+        duration_secs: f64,
+    ) -> PyResult<BTreeMap<String, f64>> {
+        let duration = std::time::Duration::try_from_secs_f64(duration_secs)
+            .map_err(|err| PyValueError::new_err(err.to_string()))?;
+        let runtime = &self.runtime;
+        let inner = &mut self.inner;
+        py.detach(|| {
+            inner
+                .calibrate_baseline(runtime, duration)
+                .map_err(to_py_err)
+        })
+    }
+
+    /// Idle power in watts by device, or `None` before `calibrate_baseline`
+    fn baseline_watts(&self) -> Option<BTreeMap<String, f64>> {
+        self.inner.baseline_watts()
+    }
+
     fn poll_data(&mut self, py: Python<'_>) -> PyResult<()> {
         py.detach(|| {
             self.inner.poll_data();
@@ -248,8 +1317,25 @@ impl PyEnergyGroup {
         })
     }
 
+    /// Wait for the next batch and return it without adding it to the trace,
+    /// as `(records, devices)` dicts in the layout of `energy_trace()` and
+    /// `devices()`. Returns `None` once the group has stopped.
+    fn next_batch(&mut self, py: Python<'_>) -> PyResult<Option<(Py<PyAny>, Py<PyAny>)>> {
+        let runtime = &self.runtime;
+        let inner = &mut self.inner;
+        let Some((batch, _)) = py.detach(|| inner.next_batch(runtime)) else {
+            return Ok(None);
+        };
+        Ok(Some((
+            energy_trace_to_py_dict(py, &batch)?,
+            device_table_to_py_dict(py, self.inner.devices())?,
+        )))
+    }
+
     fn shutdown(&mut self, py: Python<'_>) -> PyResult<()> {
-        py.detach(|| self.inner.shutdown().map_err(to_py_err))
+        let runtime = &self.runtime;
+        let inner = &mut self.inner;
+        py.detach(|| inner.shutdown(runtime).map_err(to_py_err))
     }
 
     fn is_running(&self) -> bool {
@@ -260,11 +1346,630 @@ impl PyEnergyGroup {
         self.inner.total_consumed_energy()
     }
 
-    fn energy_trace(&self, py: Python<'_>) -> PyResult<Py<PyAny>> {
-        energy_trace_to_py_dict(py, self.inner.energy_trace())
+    /// Energy of each device in the trace, in joules
+    fn total_energy_by_device(&self) -> PyResult<BTreeMap<String, f64>> {
+        self.inner.total_energy_by_device()
+    }
+
+    /// Energy attributed to each pid in the trace, in joules
+    fn total_energy_by_pid(&self) -> PyResult<BTreeMap<u32, f64>> {
+        self.inner.total_energy_by_pid()
+    }
+
+    /// Energy in J and kWh, duration, mean and peak power of the trace
+    fn summary(&self, py: Python<'_>) -> PyResult<Py<PyAny>> {
+        let summary = self.inner.summary()?;
+        let dict = PyDict::new(py);
+        dict.set_item("energy_joules", summary.energy_joules)?;
+        dict.set_item("energy_kwh", summary.energy_kwh)?;
+        dict.set_item("duration_seconds", summary.duration_seconds)?;
+        dict.set_item("mean_power_watts", summary.mean_power_watts)?;
+        dict.set_item("peak_power_watts", summary.peak_power_watts)?;
+        Ok(dict.into_any().unbind())
+    }
+
+    /// Platform total counting each energy domain once, energy per domain
+    /// and a non-overlapping breakdown per component.
+    fn energy_totals(&self, py: Python<'_>) -> PyResult<Py<PyAny>> {
+        energy_totals_to_py_dict(py, &self.inner.energy_totals())
+    }
+
+    /// Trace columns as lists. With `with_metrics`, adds a `metric_<name>`
+    /// list per recorded metric holding the latest value at each row.
+    #[pyo3(signature = (with_metrics=false))]
+    fn energy_trace(&self, py: Python<'_>, with_metrics: bool) -> PyResult<Py<PyAny>> {
+        if with_metrics {
+            energy_trace_to_py_dict(py, &self.inner.energy_trace_with_metrics()?)
+        } else {
+            energy_trace_to_py_dict(py, self.inner.energy_trace())
+        }
+    }
+
+    /// The trace as an `ArrowTrace`, which `pyarrow.table` and other Arrow
+    /// readers take without copying it. With `with_metrics`, adds a
+    /// `metric_<name>` column per recorded metric.
+    #[pyo3(signature = (with_metrics=false))]
+    fn arrow_trace(&self, with_metrics: bool) -> PyResult<PyArrowTrace> {
+        let frame = if with_metrics {
+            self.inner.energy_trace_with_metrics()?
+        } else {
+            self.inner.energy_trace().clone()
+        };
+        Ok(PyArrowTrace { frame })
+    }
+
+    /// The trace as a `polars.DataFrame`, handed over without copying.
+    /// Needs the `polars` package.
+    #[pyo3(signature = (with_metrics=false))]
+    fn trace_frame<'py>(&self, py: Python<'py>, with_metrics: bool) -> PyResult<Bound<'py, PyAny>> {
+        let trace = Bound::new(py, self.arrow_trace(with_metrics)?)?;
+        py.import("polars")?.getattr("DataFrame")?.call1((trace,))
+    }
+
+    /// Record the current value of an application metric, such as batch
+    /// loss or queue depth.
+    fn record_metric(&mut self, name: &str, value: f64) -> PyResult<()> {
+        self.inner.record_metric(name, value)
+    }
+
+    /// Recorded metrics as `{"name": [...], "value": [...], "timestamp": [...]}`.
+    fn metric_trace(&self, py: Python<'_>) -> PyResult<Py<PyAny>> {
+        metric_trace_to_py_dict(py, self.inner.metric_trace())
+    }
+
+    fn devices(&self, py: Python<'_>) -> PyResult<Py<PyAny>> {
+        device_table_to_py_dict(py, self.inner.devices())
+    }
+
+    /// Add `cumulative_energy`, `raw_counter`, `co2`, `sample_duration_ms`
+    /// and/or `quality` and `original_energy` columns to the trace. Call
+    /// before `commence`.
+    #[pyo3(signature = (cumulative_energy=false, raw_counter=false, quality=false, co2=false, sample_duration=false))]
+    fn set_record_output(
+        &mut self,
+        cumulative_energy: bool,
+        raw_counter: bool,
+        quality: bool,
+        co2: bool,
+        sample_duration: bool,
+    ) {
+        self.inner.set_record_output(RecordOutput {
+            cumulative_energy,
+            raw_counter,
+            quality,
+            co2,
+            sample_duration,
+        });
+    }
+
+    /// Convert energy to emissions at `g_per_kwh` grams CO2e per kWh from
+    /// now on.
+    fn set_carbon_intensity(&mut self, g_per_kwh: f64) -> PyResult<()> {
+        if !(g_per_kwh.is_finite() && g_per_kwh >= 0.0) {
+            return Err(PyValueError::new_err(
+                "g_per_kwh must be a finite value of at least 0",
+            ));
+        }
+        self.inner.set_carbon_intensity(g_per_kwh);
+        Ok(())
+    }
+
+    /// `{"grams_co2e", "devices", "intensity_g_per_kwh"}`, or `None` until
+    /// a carbon intensity is set
+    fn carbon_footprint(&self, py: Python<'_>) -> PyResult<Option<Py<PyAny>>> {
+        self.inner
+            .carbon_footprint()
+            .map(|footprint| {
+                let dict = PyDict::new(py);
+                dict.set_item("grams_co2e", footprint.grams_co2e)?;
+                dict.set_item("devices", footprint.devices)?;
+                dict.set_item("intensity_g_per_kwh", footprint.intensity_g_per_kwh)?;
+                Ok(dict.into_any().unbind())
+            })
+            .transpose()
+    }
+
+    /// Set the capacity of the collector channel, in batches, and the policy
+    /// for a full channel: `"block"`, `"drop_oldest"` or `"drop_newest"`.
+    /// Call before `commence`.
+    #[pyo3(signature = (capacity=10, policy="block"))]
+    fn set_backpressure(&mut self, capacity: usize, policy: &str) -> PyResult<()> {
+        self.inner
+            .set_backpressure(capacity, parse_backpressure_policy(policy)?);
+        Ok(())
+    }
+
+    /// Drain the collector channel in a background task, so the collector
+    /// keeps its pace between `poll_data` calls. Call before `commence`.
+    fn set_auto_drain(&mut self, enabled: bool) {
+        self.inner.set_auto_drain(enabled);
+    }
+
+    /// Batches discarded by the backpressure policy.
+    fn dropped_batches(&self) -> u64 {
+        self.inner.dropped_batches()
+    }
+
+    /// SM and memory utilization in percent of each GPU (`pid` None) and of
+    /// each tracked process on it since the previous call, through NVML.
+    /// Empty for CPU and AMD GPU groups.
+    fn gpu_utilization(&self, py: Python<'_>) -> PyResult<Vec<Py<PyAny>>> {
+        let runtime = &self.runtime;
+        let records = py
+            .detach(|| self.inner.gpu_utilization(runtime))
+            .map_err(PyRuntimeError::new_err)?;
+        records
+            .into_iter()
+            .map(|record| {
+                let dict = PyDict::new(py);
+                dict.set_item("pid", record.pid)?;
+                dict.set_item("device", record.device)?;
+                dict.set_item("timestamp_us", record.timestamp_us)?;
+                dict.set_item("monotonic_ns", record.monotonic_ns)?;
+                dict.set_item("sm_percent", record.sm_percent)?;
+                dict.set_item("memory_percent", record.memory_percent)?;
+                Ok(dict.into_any().unbind())
+            })
+            .collect()
+    }
+
+    /// Write the energy trace to a Parquet dataset at `path`, partitioned
+    /// by `"day"` or `"hour"` and compressed with `"zstd"`, `"lz4"`,
+    /// `"snappy"` or `"uncompressed"`. Returns the number of rows written.
+    #[pyo3(signature = (path, partition_by="day", compression="zstd"))]
+    fn write_parquet(
+        &self,
+        py: Python<'_>,
+        path: &str,
+        partition_by: &str,
+        compression: &str,
+    ) -> PyResult<usize> {
+        let options = parse_parquet_options(partition_by, compression)?;
+        py.detach(|| self.inner.write_parquet(path, options))
+    }
+
+    /// Write what `gpu_utilization()` would return to a Parquet dataset at
+    /// `path`, with the options of `write_parquet`. Returns the number of
+    /// rows written.
+    #[pyo3(signature = (path, partition_by="day", compression="zstd"))]
+    fn write_gpu_utilization_parquet(
+        &self,
+        py: Python<'_>,
+        path: &str,
+        partition_by: &str,
+        compression: &str,
+    ) -> PyResult<usize> {
+        let options = parse_parquet_options(partition_by, compression)?;
+        let runtime = &self.runtime;
+        py.detach(|| {
+            let records = self
+                .inner
+                .gpu_utilization(runtime)
+                .map_err(PyRuntimeError::new_err)?;
+            gpu_utilization_frame(&records)
+                .map_err(SchemaError::from)
+                .and_then(|frame| {
+                    ParquetTraceRecorder::new(path.into())
+                        .with_options(options)
+                        .write(&frame)
+                })
+                .map_err(|err| PyRuntimeError::new_err(err.to_string()))
+        })
+    }
+
+    /// Domains the collector is skipping because they could not be
+    /// discovered or read, while the others keep reporting.
+    fn degraded_domains(&self, py: Python<'_>) -> PyResult<Vec<Py<PyAny>>> {
+        self.inner
+            .degraded_domains()
+            .into_iter()
+            .map(|domain| {
+                let dict = PyDict::new(py);
+                dict.set_item("domain", domain.domain)?;
+                dict.set_item("error", domain.error)?;
+                dict.set_item("failed_reads", domain.failed_reads)?;
+                Ok(dict.into_any().unbind())
+            })
+            .collect()
+    }
+
+    /// Rows and estimated memory of the in-memory traces, with their high
+    /// watermark, for tuning retention and batching.
+    fn trace_stats(&self) -> HashMap<String, u64> {
+        let stats = self.inner.trace_stats();
+        HashMap::from([
+            (
+                "energy_trace_rows".to_string(),
+                stats.energy_trace_rows as u64,
+            ),
+            (
+                "energy_trace_bytes".to_string(),
+                stats.energy_trace_stats.estimated_bytes as u64,
+            ),
+            (
+                "metric_trace_bytes".to_string(),
+                stats.metric_trace_stats.estimated_bytes as u64,
+            ),
+            (
+                "estimated_bytes".to_string(),
+                stats.estimated_bytes() as u64,
+            ),
+            ("peak_bytes".to_string(), stats.peak_bytes() as u64),
+        ])
+    }
+
+    /// Sync every batch to the write-ahead log at `path` before it reaches
+    /// the trace, replaying what a previous run left there first. Returns
+    /// the number of replayed records.
+    fn set_write_ahead_log(&mut self, path: &str) -> PyResult<usize> {
+        self.inner.set_write_ahead_log(path)
+    }
+
+    /// Detect peak power episodes at `threshold_watts`, or at `limit_fraction`
+    /// of each device's rated maximum power when no absolute threshold is set.
+    #[pyo3(signature = (threshold_watts=None, limit_fraction=0.9, min_duration_secs=0.0))]
+    fn set_peak_detection(
+        &mut self,
+        threshold_watts: Option<f64>,
+        limit_fraction: f64,
+        min_duration_secs: f64,
+    ) -> PyResult<()> {
+        let min_duration = std::time::Duration::try_from_secs_f64(min_duration_secs)
+            .map_err(|err| PyValueError::new_err(err.to_string()))?;
+        self.inner.set_peak_detection(PeakDetection {
+            threshold_watts,
+            limit_fraction,
+            min_duration,
+        });
+        Ok(())
+    }
+
+    /// Correct implausible readings from now on: power above
+    /// `max_power_watts`, or above `limit_factor` times each device's rated
+    /// maximum power when no absolute maximum is set, is clamped to it, and
+    /// with a `median_window` of 1 or more, power above `median_factor`
+    /// times the median of the preceding intervals is replaced by it. Adds
+    /// the `quality` and `original_energy` columns. Call before `commence`.
+    #[pyo3(signature = (max_power_watts=None, limit_factor=2.0, median_window=0, median_factor=10.0))]
+    fn set_spike_filter(
+        &mut self,
+        max_power_watts: Option<f64>,
+        limit_factor: f64,
+        median_window: usize,
+        median_factor: f64,
+    ) -> PyResult<()> {
+        let config = SpikeFiltering {
+            max_power_watts,
+            limit_factor,
+            median_window,
+            median_factor,
+        };
+        config.validate().map_err(PyValueError::new_err)?;
+        self.inner.set_spike_filter(config);
+        Ok(())
+    }
+
+    fn peak_events(&self, py: Python<'_>) -> PyResult<Vec<Py<PyAny>>> {
+        self.inner
+            .peak_events()
+            .into_iter()
+            .map(|event| {
+                let dict = PyDict::new(py);
+                dict.set_item("device", event.device)?;
+                dict.set_item("start", event.start)?;
+                dict.set_item("end", event.end)?;
+                dict.set_item("duration_seconds", event.duration_seconds)?;
+                dict.set_item("peak_watts", event.peak_watts)?;
+                dict.set_item("energy_joules", event.energy_joules)?;
+                dict.set_item("threshold_watts", event.threshold_watts)?;
+                dict.set_item("limit_watts", event.limit_watts)?;
+                dict.set_item("ongoing", event.ongoing)?;
+                Ok(dict.into_any().unbind())
+            })
+            .collect()
+    }
+
+    fn suspend_gaps(&self, py: Python<'_>) -> PyResult<Vec<Py<PyAny>>> {
+        self.inner
+            .suspend_gaps()
+            .into_iter()
+            .map(|gap| {
+                let dict = PyDict::new(py);
+                dict.set_item("start", gap.start.timestamp())?;
+                dict.set_item("end", gap.end.timestamp())?;
+                dict.set_item("suspended_seconds", gap.suspended_seconds)?;
+                Ok(dict.into_any().unbind())
+            })
+            .collect()
+    }
+
+    fn begin_region(&mut self, name: &str) {
+        self.inner.begin_region(name);
+    }
+
+    fn end_region(&mut self, name: &str) -> PyResult<()> {
+        self.inner.end_region(name)
+    }
+
+    /// Begin a named session, such as a training epoch. One session is
+    /// active at a time.
+    fn begin_session(&mut self, name: &str) -> PyResult<()> {
+        self.inner.begin_session(name)
+    }
+
+    /// End the active session, returning its name.
+    fn end_session(&mut self) -> PyResult<String> {
+        self.inner.end_session()
+    }
+
+    /// Energy, duration and average power of the sessions named `name`,
+    /// with a `devices` dict of their energy per device.
+    fn session_summary(&self, py: Python<'_>, name: &str) -> PyResult<Py<PyAny>> {
+        let summary = self.inner.session_summary(name)?;
+        let dict = PyDict::new(py);
+        dict.set_item("session", summary.session)?;
+        dict.set_item("start", summary.start)?;
+        dict.set_item("end", summary.end)?;
+        dict.set_item("duration_seconds", summary.duration_seconds)?;
+        dict.set_item("energy_joules", summary.energy_joules)?;
+        dict.set_item("average_power_watts", summary.average_power_watts)?;
+        dict.set_item("devices", summary.devices)?;
+        Ok(dict.into_any().unbind())
+    }
+
+    /// The trace as an `ArrowTrace` with a `session` column and a marker
+    /// row, with a `session_event` of `begin` or `end`, at every session
+    /// boundary.
+    fn session_trace(&self) -> PyResult<PyArrowTrace> {
+        Ok(PyArrowTrace {
+            frame: self.inner.session_trace()?,
+        })
+    }
+
+    /// Stream every record to `path` as it arrives, as CSV, JSON Lines or
+    /// an Arrow IPC stream by its `.csv`, `.jsonl` or `.arrows` extension.
+    /// CSV and JSON Lines append to an existing file.
+    fn add_sink(&mut self, path: std::path::PathBuf) -> PyResult<()> {
+        let sink = open_sink(&path).map_err(|err| PyValueError::new_err(err.to_string()))?;
+        self.inner.add_sink(sink);
+        Ok(())
+    }
+
+    /// Count `count` units of work, such as requests or tokens, done now,
+    /// by `pid` if given.
+    #[pyo3(signature = (unit, count, pid=None))]
+    fn add_work(&mut self, unit: &str, count: f64, pid: Option<u32>) -> PyResult<()> {
+        self.inner.add_work(unit, count, pid)
+    }
+
+    /// Energy per unit of reported work of each region, then of each
+    /// process work was reported for.
+    fn efficiency(&self, py: Python<'_>) -> PyResult<Vec<Py<PyAny>>> {
+        self.inner
+            .efficiency()?
+            .into_iter()
+            .map(|efficiency| {
+                let dict = PyDict::new(py);
+                dict.set_item("region", efficiency.region)?;
+                dict.set_item("pid", efficiency.pid)?;
+                dict.set_item("unit", efficiency.unit)?;
+                dict.set_item("work", efficiency.work)?;
+                dict.set_item("energy_joules", efficiency.energy_joules)?;
+                dict.set_item("joules_per_unit", efficiency.joules_per_unit)?;
+                Ok(dict.into_any().unbind())
+            })
+            .collect()
+    }
+
+    /// Energy of process `pid` or region `region` projected to completion,
+    /// given either as `remaining_secs` or as the `progress` fraction of
+    /// the work done so far. `method` is `"ewma[:<alpha>]"` or
+    /// `"holt[:<alpha>,<beta>]"`. `None` until enough has been recorded.
+    #[pyo3(signature = (pid=None, region=None, remaining_secs=None, progress=None, method="holt"))]
+    fn forecast(
+        &self,
+        py: Python<'_>,
+        pid: Option<u32>,
+        region: Option<String>,
+        remaining_secs: Option<f64>,
+        progress: Option<f64>,
+        method: &str,
+    ) -> PyResult<Option<Py<PyAny>>> {
+        let scope = match (pid, region) {
+            (Some(pid), None) => ForecastScope::Process(pid),
+            (None, Some(region)) => ForecastScope::Region(region),
+            _ => return Err(PyValueError::new_err("pass exactly one of pid and region")),
+        };
+        let completion = match (remaining_secs, progress) {
+            (Some(seconds), None) => Completion::Remaining(seconds),
+            (None, Some(fraction)) => Completion::Progress(fraction),
+            _ => {
+                return Err(PyValueError::new_err(
+                    "pass exactly one of remaining_secs and progress",
+                ));
+            }
+        };
+        let method = method
+            .parse::<ForecastMethod>()
+            .map_err(PyValueError::new_err)?;
+        self.inner
+            .forecast(&scope, completion, method)?
+            .map(|forecast| {
+                let dict = PyDict::new(py);
+                dict.set_item("energy_joules", forecast.energy_joules)?;
+                dict.set_item("elapsed_seconds", forecast.elapsed_seconds)?;
+                dict.set_item("power_watts", forecast.power_watts)?;
+                dict.set_item("trend_watts_per_second", forecast.trend_watts_per_second)?;
+                dict.set_item("remaining_seconds", forecast.remaining_seconds)?;
+                dict.set_item("remaining_joules", forecast.remaining_joules)?;
+                dict.set_item("total_joules", forecast.total_joules)?;
+                Ok(dict.into_any().unbind())
+            })
+            .transpose()
+    }
+
+    /// Records between `start` and `end` (Unix milliseconds, inclusive) from
+    /// memory and from the segments written by recorders, in the layout of
+    /// `energy_trace()`. `devices` takes name patterns; each given list must
+    /// match one of its entries.
+    #[pyo3(signature = (start=None, end=None, devices=None, pids=None, users=None, tasks=None))]
+    fn query(
+        &self,
+        start: Option<i64>,
+        end: Option<i64>,
+        devices: Option<Vec<String>>,
+        pids: Option<Vec<u32>>,
+        users: Option<Vec<String>>,
+        tasks: Option<Vec<String>>,
+    ) -> PyResult<Py<PyAny>> {
+        let filter = Filter {
+            devices: devices.unwrap_or_default(),
+            pids: pids.unwrap_or_default(),
+            users: users.unwrap_or_default(),
+            tasks: tasks.unwrap_or_default(),
+        };
+        let records = self.inner.query(TimeRange { start, end }, &filter)?;
+        Python::attach(|py| energy_trace_to_py_dict(py, &records))
+    }
+
+    /// Power percentiles per `"device"` or `"process"`, optionally limited to
+    /// records between `start` and `end` (Unix milliseconds).
+    #[pyo3(signature = (by="device", start=None, end=None))]
+    fn power_stats(
+        &self,
+        py: Python<'_>,
+        by: &str,
+        start: Option<i64>,
+        end: Option<i64>,
+    ) -> PyResult<Vec<Py<PyAny>>> {
+        self.inner
+            .power_stats(parse_power_grouping(by)?, start, end)?
+            .into_iter()
+            .map(|stats| {
+                let dict = PyDict::new(py);
+                dict.set_item("key", stats.key)?;
+                dict.set_item("samples", stats.samples)?;
+                dict.set_item("mean_watts", stats.mean_watts)?;
+                dict.set_item("p50_watts", stats.p50_watts)?;
+                dict.set_item("p95_watts", stats.p95_watts)?;
+                dict.set_item("p99_watts", stats.p99_watts)?;
+                dict.set_item("max_watts", stats.max_watts)?;
+                Ok(dict.into_any().unbind())
+            })
+            .collect()
+    }
+
+    /// Power over time per `"device"` or `"process"` as
+    /// `{key: {"timestamp": [...], "watts": [...]}}`, smoothed with a spec such
+    /// as `"mean:5"` or `"median:2s"`.
+    #[pyo3(signature = (by="device", start=None, end=None, smoothing="none"))]
+    fn power_series(
+        &self,
+        py: Python<'_>,
+        by: &str,
+        start: Option<i64>,
+        end: Option<i64>,
+        smoothing: &str,
+    ) -> PyResult<Py<PyAny>> {
+        let smoothing = smoothing
+            .parse::<PowerSmoothing>()
+            .map_err(PyValueError::new_err)?;
+        let series = self
+            .inner
+            .power_series(parse_power_grouping(by)?, start, end, smoothing)?;
+        let result = PyDict::new(py);
+        for (key, points) in series {
+            let dict = PyDict::new(py);
+            dict.set_item(
+                "timestamp",
+                points.iter().map(|p| p.timestamp).collect::<Vec<_>>(),
+            )?;
+            dict.set_item("watts", points.iter().map(|p| p.watts).collect::<Vec<_>>())?;
+            result.set_item(key, dict)?;
+        }
+        Ok(result.into_any().unbind())
+    }
+
+    /// Mean power per pid and device in windows of `resample_secs`, as an
+    /// `ArrowTrace` with `pid`, `device`, `timestamp` and `power_watts`
+    /// columns.
+    #[pyo3(signature = (resample_secs=1.0))]
+    fn power_trace(&self, resample_secs: f64) -> PyResult<PyArrowTrace> {
+        let resample = std::time::Duration::try_from_secs_f64(resample_secs)
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        let frame = crate::power_stats::power_trace(self.inner.energy_trace(), resample)
+            .map_err(|err| PyRuntimeError::new_err(err.to_string()))?;
+        Ok(PyArrowTrace { frame })
+    }
+
+    #[pyo3(signature = (by="device", start=None, end=None, bins=20))]
+    fn power_histograms(
+        &self,
+        py: Python<'_>,
+        by: &str,
+        start: Option<i64>,
+        end: Option<i64>,
+        bins: usize,
+    ) -> PyResult<Vec<Py<PyAny>>> {
+        self.inner
+            .power_histograms(parse_power_grouping(by)?, start, end, bins)?
+            .into_iter()
+            .map(|histogram| {
+                let dict = PyDict::new(py);
+                dict.set_item("key", histogram.key)?;
+                dict.set_item("edges", histogram.edges)?;
+                dict.set_item("counts", histogram.counts)?;
+                Ok(dict.into_any().unbind())
+            })
+            .collect()
+    }
+
+    #[pyo3(signature = (per_device=false))]
+    fn energy_by_region(&self, py: Python<'_>, per_device: bool) -> PyResult<Vec<Py<PyAny>>> {
+        self.inner
+            .energy_by_region(per_device)?
+            .into_iter()
+            .map(|totals| {
+                let dict = PyDict::new(py);
+                dict.set_item("region", totals.region)?;
+                dict.set_item("device", totals.device)?;
+                dict.set_item("energy_joules", totals.energy_joules)?;
+                dict.set_item("duration_seconds", totals.duration_seconds)?;
+                dict.set_item("average_power_watts", totals.average_power_watts)?;
+                Ok(dict.into_any().unbind())
+            })
+            .collect()
+    }
+}
+
+fn parse_power_grouping(by: &str) -> PyResult<PowerGrouping> {
+    match by {
+        "device" => Ok(PowerGrouping::Device),
+        "process" => Ok(PowerGrouping::Process),
+        _ => Err(PyValueError::new_err(format!(
+            "by must be 'device' or 'process', got '{by}'"
+        ))),
     }
 }
 
+fn parse_backpressure_policy(policy: &str) -> PyResult<BackpressurePolicy> {
+    match policy {
+        "block" => Ok(BackpressurePolicy::Block),
+        "drop_oldest" => Ok(BackpressurePolicy::DropOldest),
+        "drop_newest" => Ok(BackpressurePolicy::DropNewest),
+        _ => Err(PyValueError::new_err(format!(
+            "policy must be 'block', 'drop_oldest' or 'drop_newest', got '{policy}'"
+        ))),
+    }
+}
+
+fn parse_parquet_options(partition_by: &str, compression: &str) -> PyResult<ParquetOptions> {
+    Ok(ParquetOptions {
+        partitioning: partition_by.parse().map_err(PyValueError::new_err)?,
+        compression: compression.parse().map_err(PyValueError::new_err)?,
+    })
+}
+
 // ─── RustMonitor: high-level PyO3 wrapper around Monitor ───────────────────
 
 #[pyclass(name = "RustMonitor", module = "emt._rust")]
@@ -351,6 +2056,17 @@ impl PyRustMonitor {
         })
     }
 
+    #[getter]
+    fn energy_totals(&self, py: Python<'_>) -> PyResult<Py<PyAny>> {
+        let handle = self
+            .handle
+            .as_ref()
+            .ok_or_else(|| PyRuntimeError::new_err("Monitor not commenced"))?
+            .clone();
+        let totals = py.detach(move || handle.snapshot().totals);
+        energy_totals_to_py_dict(py, &totals)
+    }
+
     #[getter]
     fn device_sources(&self, py: Python<'_>) -> PyResult<HashMap<String, String>> {
         let handle = self
@@ -377,8 +2093,17 @@ impl PyRustMonitor {
 #[pymodule]
 fn _rust(module: &Bound<'_, PyModule>) -> PyResult<()> {
     module.add_class::<PyEnergyGroup>()?;
+    module.add_class::<PyArrowTrace>()?;
     module.add_class::<PyRaplCollector>()?;
     module.add_class::<PyNvidiaGpuCollector>()?;
+    module.add_class::<PyAmdGpuCollector>()?;
+    module.add_class::<PyIntelGpuCollector>()?;
+    module.add_class::<PyJetsonCollector>()?;
+    module.add_class::<PyNetworkCollector>()?;
+    module.add_class::<PyStorageCollector>()?;
+    #[cfg(feature = "macos")]
+    module.add_class::<PyAppleSmcCollector>()?;
     module.add_class::<PyRustMonitor>()?;
+    module.add("SCHEMA_VERSION", crate::schema::SCHEMA_VERSION)?;
     Ok(())
 }
@@ -0,0 +1,177 @@
+/// Query Module
+///
+/// Time-range and predicate queries over an energy trace. A query covers the
+/// rows still in memory and the segments recorders have already written to
+/// disk, so it reaches past the in-memory retention window. Rows present in
+/// both are returned once.
+///
+/// [`EnergyGroup::query`](crate::energy_group::EnergyGroup::query) is the
+/// entry point. It returns a `LazyFrame` in the layout of
+/// [`EnergyGroup::joined_trace`](crate::energy_group::EnergyGroup::joined_trace),
+/// so callers can aggregate further before collecting.
+use crate::device_registry::{DEVICE_ID_COLUMN, DeviceRegistry};
+use crate::utils::pattern::matches_any;
+use crate::utils::time::{MONOTONIC_COLUMN, TIMESTAMP_COLUMN, timestamp_dtype};
+use polars::prelude::*;
+
+/// Inclusive wall-clock bounds in Unix milliseconds; `None` is unbounded.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TimeRange {
+    pub start: Option<i64>,
+    pub end: Option<i64>,
+}
+
+impl TimeRange {
+    /// Every row.
+    pub fn all() -> Self {
+        Self::default()
+    }
+
+    /// Rows from `start` to `end`, both inclusive.
+    pub fn between(start: i64, end: i64) -> Self {
+        Self {
+            start: Some(start),
+            end: Some(end),
+        }
+    }
+
+    /// Rows at or after `start`.
+    pub fn since(start: i64) -> Self {
+        Self {
+            start: Some(start),
+            end: None,
+        }
+    }
+
+    pub(crate) fn predicate(&self) -> Option<Expr> {
+        let bound = |millis: i64| lit(millis * 1000).cast(timestamp_dtype());
+        let start = self
+            .start
+            .map(|start| col(TIMESTAMP_COLUMN).gt_eq(bound(start)));
+        // Rows carry microseconds; keep the whole last millisecond.
+        let end = self.end.map(|end| col(TIMESTAMP_COLUMN).lt(bound(end + 1)));
+        [start, end].into_iter().flatten().reduce(Expr::and)
+    }
+}
+
+/// Row predicates. Each non-empty list must match one of its entries; empty
+/// lists match everything.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Filter {
+    /// Device name patterns (`*` and `?` globs)
+    pub devices: Vec<String>,
+    pub pids: Vec<u32>,
+    /// Users of tracked processes
+    pub users: Vec<String>,
+    /// Tasks of tracked processes
+    pub tasks: Vec<String>,
+}
+
+impl Filter {
+    /// Predicate on trace columns, resolving device patterns to ids.
+    fn trace_predicate(&self, devices: &DeviceRegistry) -> Option<Expr> {
+        let device_ids = (!self.devices.is_empty()).then(|| {
+            any_of(
+                DEVICE_ID_COLUMN,
+                devices
+                    .iter()
+                    .filter(|(_, info)| matches_any(&self.devices, &info.name))
+                    .map(|(id, _)| lit(id)),
+            )
+        });
+        let pids =
+            (!self.pids.is_empty()).then(|| any_of("pid", self.pids.iter().map(|&pid| lit(pid))));
+        [device_ids, pids].into_iter().flatten().reduce(Expr::and)
+    }
+
+    /// Predicate on the process metadata joined onto the trace.
+    fn process_predicate(&self) -> Option<Expr> {
+        let users = (!self.users.is_empty())
+            .then(|| any_of("user", self.users.iter().map(|user| lit(user.as_str()))));
+        let tasks = (!self.tasks.is_empty())
+            .then(|| any_of("task", self.tasks.iter().map(|task| lit(task.as_str()))));
+        [users, tasks].into_iter().flatten().reduce(Expr::and)
+    }
+}
+
+/// `column` equal to any of `values`; false when there are none.
+pub(crate) fn any_of(column: &str, values: impl Iterator<Item = Expr>) -> Expr {
+    values
+        .map(|value| col(column).eq(value))
+        .reduce(Expr::or)
+        .unwrap_or(lit(false))
+}
+
+/// Attach process (`user`, `task`) and device metadata to trace rows.
+pub(crate) fn join_metadata(
+    trace: LazyFrame,
+    tracked_processes: &DataFrame,
+    devices: &DeviceRegistry,
+) -> PolarsResult<LazyFrame> {
+    let devices = devices.to_dataframe()?.drop("device")?;
+    Ok(trace
+        .left_join(tracked_processes.clone().lazy(), col("pid"), col("pid"))
+        .left_join(devices.lazy(), col(DEVICE_ID_COLUMN), col(DEVICE_ID_COLUMN)))
+}
+
+/// `frame` with the columns of `schema`, in its order and types; missing
+/// columns are null.
+pub(crate) fn align_columns(frame: &DataFrame, schema: &Schema) -> PolarsResult<DataFrame> {
+    let columns = schema
+        .iter()
+        .map(|(name, dtype)| match frame.column(name) {
+            Ok(column) => column.cast(dtype),
+            Err(_) => Ok(Column::full_null(name.clone(), frame.height(), dtype)),
+        })
+        .collect::<PolarsResult<Vec<_>>>()?;
+    DataFrame::new(columns)
+}
+
+/// Query `memory` together with `stored` segments (any column order), which
+/// are brought to the layout of `memory` and deduplicated against it.
+pub(crate) fn query_trace(
+    memory: DataFrame,
+    stored: Vec<DataFrame>,
+    tracked_processes: &DataFrame,
+    devices: &DeviceRegistry,
+    range: TimeRange,
+    filter: &Filter,
+) -> PolarsResult<LazyFrame> {
+    let schema = memory.schema().clone();
+    let mut trace = if stored.is_empty() {
+        memory.lazy()
+    } else {
+        let mut frames = Vec::with_capacity(stored.len() + 1);
+        for frame in stored.into_iter().filter(|frame| frame.height() > 0) {
+            frames.push(align_columns(&frame, &schema)?.lazy());
+        }
+        frames.push(memory.lazy());
+        concat(frames, UnionArgs::default())?
+            .unique_stable(
+                Some(cols([
+                    "pid",
+                    DEVICE_ID_COLUMN,
+                    TIMESTAMP_COLUMN,
+                    MONOTONIC_COLUMN,
+                ])),
+                UniqueKeepStrategy::Last,
+            )
+            .sort(
+                [TIMESTAMP_COLUMN],
+                SortMultipleOptions::default().with_maintain_order(true),
+            )
+    };
+
+    if let Some(predicate) = [range.predicate(), filter.trace_predicate(devices)]
+        .into_iter()
+        .flatten()
+        .reduce(Expr::and)
+    {
+        trace = trace.filter(predicate);
+    }
+    let mut joined = join_metadata(trace, tracked_processes, devices)?;
+    if let Some(predicate) = filter.process_predicate() {
+        joined = joined.filter(predicate);
+    }
+    Ok(joined)
+}
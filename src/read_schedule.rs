@@ -0,0 +1,126 @@
+/// Read Schedule Module
+///
+/// Spreads counter reads across the collection interval. Without it every
+/// device is read at the start of each tick, so many sockets, DRAM domains
+/// and GPUs cause a periodic burst of CPU and I/O from the monitor itself.
+/// Each [`ReadPhase`] gives devices matching a pattern an offset, as a
+/// fraction of the interval, before which they are not read. Records are
+/// still stamped when their device is actually read.
+use crate::utils::pattern::glob_match;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+/// Phase offset of the devices matching a pattern.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReadPhase {
+    /// Device name pattern (`*` and `?` globs)
+    pub devices: String,
+    /// Offset from the start of the tick, as a fraction of the interval
+    pub phase: f64,
+}
+
+impl ReadPhase {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(0.0..1.0).contains(&self.phase) {
+            return Err(format!(
+                "phase of '{}' must be at least 0 and below 1",
+                self.devices
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// When in each tick a collector reads each of its devices.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ReadSchedule {
+    interval: Duration,
+    phases: Vec<ReadPhase>,
+}
+
+impl ReadSchedule {
+    pub fn new(interval: Duration, phases: Vec<ReadPhase>) -> Self {
+        Self { interval, phases }
+    }
+
+    /// Offset of `device` from the start of a tick; the first matching phase
+    /// applies, and devices without one are read right away.
+    pub fn offset(&self, device: &str) -> Duration {
+        self.phases
+            .iter()
+            .find(|phase| glob_match(&phase.devices, device))
+            .map_or(Duration::ZERO, |phase| self.interval.mul_f64(phase.phase))
+    }
+
+    /// Wait until `device` is due in the tick that started at `tick_start`.
+    /// Devices are read in the collector's order, so one whose offset has
+    /// passed is read immediately.
+    pub async fn wait(&self, tick_start: Instant, device: &str) {
+        let due = tick_start + self.offset(device);
+        tokio::time::sleep_until(due.into()).await;
+    }
+
+    /// Like [`ReadSchedule::wait`], for reads on a blocking thread.
+    pub fn wait_blocking(&self, tick_start: Instant, device: &str) {
+        let due = tick_start + self.offset(device);
+        std::thread::sleep(due.saturating_duration_since(Instant::now()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn phase(devices: &str, phase: f64) -> ReadPhase {
+        ReadPhase {
+            devices: devices.to_string(),
+            phase,
+        }
+    }
+
+    #[test]
+    fn first_matching_phase_sets_the_offset() {
+        let schedule = ReadSchedule::new(
+            Duration::from_millis(200),
+            vec![
+                phase("rapl:socket:1:*", 0.5),
+                phase("rapl:*", 0.25),
+                phase("nvidia:gpu:*", 0.75),
+            ],
+        );
+
+        assert_eq!(
+            schedule.offset("rapl:socket:1:package"),
+            Duration::from_millis(100)
+        );
+        assert_eq!(
+            schedule.offset("rapl:system:dram"),
+            Duration::from_millis(50)
+        );
+        assert_eq!(schedule.offset("nvidia:gpu:0"), Duration::from_millis(150));
+        assert_eq!(schedule.offset("exec:pdu:outlet"), Duration::ZERO);
+        assert_eq!(
+            ReadSchedule::default().offset("rapl:system:psys"),
+            Duration::ZERO
+        );
+    }
+
+    #[tokio::test]
+    async fn reads_wait_for_their_offset_within_the_tick() {
+        let schedule = ReadSchedule::new(Duration::from_millis(100), vec![phase("late", 0.5)]);
+        let tick_start = Instant::now();
+
+        schedule.wait(tick_start, "early").await;
+        assert!(tick_start.elapsed() < Duration::from_millis(50));
+        schedule.wait(tick_start, "late").await;
+        assert!(tick_start.elapsed() >= Duration::from_millis(50));
+    }
+
+    #[test]
+    fn validate_rejects_phases_outside_the_interval() {
+        assert!(phase("*", 0.0).validate().is_ok());
+        assert!(phase("*", 1.0).validate().is_err());
+        assert!(phase("*", -0.1).validate().is_err());
+        assert!(phase("*", f64::NAN).validate().is_err());
+    }
+}
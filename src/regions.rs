@@ -0,0 +1,300 @@
+/// Region Module
+///
+/// Named regions mark spans of a run, such as a training epoch or an inference
+/// batch, between a `begin` and an `end` marker. Energy is aggregated per
+/// region by summing the trace records whose monotonic time falls inside the
+/// span, which keeps the result stable across wall-clock adjustments.
+///
+/// A name may be used for several spans; they are aggregated together.
+/// Regions may nest or overlap, in which case a record counts towards every
+/// region that contains it.
+use crate::utils::time::{ExportTimezone, MONOTONIC_COLUMN, RecordTime};
+use polars::prelude::*;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Errors raised by region markers.
+#[derive(Debug, thiserror::Error)]
+pub enum RegionError {
+    #[error("region '{0}' is not open")]
+    NotOpen(String),
+}
+
+/// One marked span. `end` is `None` while the region is open.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Region {
+    pub name: String,
+    pub start: RecordTime,
+    pub end: Option<RecordTime>,
+}
+
+impl Region {
+    /// Whether a record taken at `monotonic_ns` falls inside the region,
+    /// treating an open region as extending to `now`.
+    pub(crate) fn contains(&self, monotonic_ns: i64, now: RecordTime) -> bool {
+        let end = self.end.unwrap_or(now);
+        monotonic_ns > self.start.monotonic_ns && monotonic_ns <= end.monotonic_ns
+    }
+
+    pub(crate) fn duration_ns(&self, now: RecordTime) -> i64 {
+        (self.end.unwrap_or(now).monotonic_ns - self.start.monotonic_ns).max(0)
+    }
+}
+
+/// Begin/end markers recorded during a run.
+#[derive(Debug, Clone, Default)]
+pub struct RegionMarkers {
+    regions: Vec<Region>,
+}
+
+impl RegionMarkers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Open a region named `name` now.
+    pub fn begin(&mut self, name: &str) {
+        self.begin_at(name, RecordTime::now());
+    }
+
+    pub fn begin_at(&mut self, name: &str, time: RecordTime) {
+        self.regions.push(Region {
+            name: name.to_string(),
+            start: time,
+            end: None,
+        });
+    }
+
+    /// Close the most recently opened region named `name` now.
+    pub fn end(&mut self, name: &str) -> Result<(), RegionError> {
+        self.end_at(name, RecordTime::now())
+    }
+
+    pub fn end_at(&mut self, name: &str, time: RecordTime) -> Result<(), RegionError> {
+        let region = self
+            .regions
+            .iter_mut()
+            .rev()
+            .find(|region| region.name == name && region.end.is_none())
+            .ok_or_else(|| RegionError::NotOpen(name.to_string()))?;
+        region.end = Some(time);
+        Ok(())
+    }
+
+    /// All regions in the order they were opened.
+    pub fn regions(&self) -> &[Region] {
+        &self.regions
+    }
+}
+
+/// Energy consumed inside a named region.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RegionEnergy {
+    pub region: String,
+    /// Device the energy was measured on; `None` when aggregated over devices.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub device: Option<String>,
+    pub energy_joules: f64,
+    /// Summed length of the region's spans.
+    pub duration_seconds: f64,
+    pub average_power_watts: f64,
+}
+
+/// Aggregate `trace` (`device | energy | monotonic_ns`) over `regions`.
+///
+/// Returns one entry per region name in first-opened order, or one per region
+/// and device when `per_device` is set. Open regions are measured up to `now`.
+pub fn energy_by_region(
+    trace: &DataFrame,
+    regions: &[Region],
+    per_device: bool,
+    now: RecordTime,
+) -> PolarsResult<Vec<RegionEnergy>> {
+    let mut names: Vec<&str> = Vec::new();
+    let mut durations: Vec<i64> = Vec::new();
+    for region in regions {
+        match names.iter().position(|name| *name == region.name) {
+            Some(index) => durations[index] += region.duration_ns(now),
+            None => {
+                names.push(&region.name);
+                durations.push(region.duration_ns(now));
+            }
+        }
+    }
+
+    let mut energy: Vec<BTreeMap<&str, f64>> = vec![BTreeMap::new(); names.len()];
+    if trace.height() > 0 {
+        let devices = trace.column("device")?.str()?;
+        let energies = trace.column("energy")?.f64()?;
+        let monotonic = trace.column(MONOTONIC_COLUMN)?.cast(&DataType::Int64)?;
+        let monotonic = monotonic.i64()?;
+
+        for ((device, joules), monotonic_ns) in devices.iter().zip(energies).zip(monotonic) {
+            let (Some(device), Some(joules), Some(monotonic_ns)) = (device, joules, monotonic_ns)
+            else {
+                continue;
+            };
+            let key = if per_device { device } else { "" };
+            for region in regions.iter().filter(|r| r.contains(monotonic_ns, now)) {
+                let index = names.iter().position(|name| *name == region.name);
+                if let Some(index) = index {
+                    *energy[index].entry(key).or_insert(0.0) += joules;
+                }
+            }
+        }
+    }
+
+    let mut result = Vec::new();
+    for ((name, duration_ns), by_device) in names.iter().zip(durations).zip(energy) {
+        let duration_seconds = duration_ns as f64 / 1e9;
+        let entry = |device: Option<String>, energy_joules: f64| RegionEnergy {
+            region: name.to_string(),
+            device,
+            energy_joules,
+            duration_seconds,
+            average_power_watts: if duration_seconds > 0.0 {
+                energy_joules / duration_seconds
+            } else {
+                0.0
+            },
+        };
+        if per_device {
+            result.extend(
+                by_device
+                    .into_iter()
+                    .map(|(device, joules)| entry(Some(device.to_string()), joules)),
+            );
+        } else {
+            result.push(entry(None, by_device.values().sum()));
+        }
+    }
+    Ok(result)
+}
+
+/// Region boundaries as a frame:
+/// `region | start | end | start_monotonic_ns | end_monotonic_ns`, with
+/// wall-clock times rendered in `timezone`. Open regions have null ends.
+pub fn regions_to_dataframe(
+    regions: &[Region],
+    timezone: ExportTimezone,
+) -> PolarsResult<DataFrame> {
+    DataFrame::new(vec![
+        Column::new(
+            "region".into(),
+            regions.iter().map(|r| r.name.as_str()).collect::<Vec<_>>(),
+        ),
+        Column::new(
+            "start".into(),
+            regions
+                .iter()
+                .map(|r| timezone.format_millis(r.start.timestamp()))
+                .collect::<Vec<_>>(),
+        ),
+        Column::new(
+            "end".into(),
+            regions
+                .iter()
+                .map(|r| r.end.map(|end| timezone.format_millis(end.timestamp())))
+                .collect::<Vec<_>>(),
+        ),
+        Column::new(
+            format!("start_{MONOTONIC_COLUMN}").into(),
+            regions
+                .iter()
+                .map(|r| r.start.monotonic_ns)
+                .collect::<Vec<_>>(),
+        ),
+        Column::new(
+            format!("end_{MONOTONIC_COLUMN}").into(),
+            regions
+                .iter()
+                .map(|r| r.end.map(|end| end.monotonic_ns))
+                .collect::<Vec<_>>(),
+        ),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::time::monotonic_dtype;
+    use polars::df;
+
+    fn at(seconds: i64) -> RecordTime {
+        RecordTime {
+            timestamp_us: 1_700_000_000_000_000 + seconds * 1_000_000,
+            monotonic_ns: seconds * 1_000_000_000,
+        }
+    }
+
+    fn trace() -> DataFrame {
+        let mut trace = df![
+            "device" => ["cpu", "gpu", "cpu", "gpu", "cpu"],
+            "energy" => [1.0, 2.0, 3.0, 4.0, 5.0],
+            MONOTONIC_COLUMN => [1_000_000_000i64, 1_000_000_000, 2_000_000_000, 2_000_000_000, 5_000_000_000],
+        ]
+        .unwrap();
+        let monotonic = trace
+            .column(MONOTONIC_COLUMN)
+            .unwrap()
+            .cast(&monotonic_dtype())
+            .unwrap();
+        trace.with_column(monotonic).unwrap();
+        trace
+    }
+
+    #[test]
+    fn energy_by_region_sums_records_inside_spans() {
+        let mut markers = RegionMarkers::new();
+        markers.begin_at("epoch", at(0));
+        markers.end_at("epoch", at(1)).unwrap();
+        markers.begin_at("epoch", at(4));
+        markers.end_at("epoch", at(6)).unwrap();
+        markers.begin_at("eval", at(1));
+
+        let totals = energy_by_region(&trace(), markers.regions(), false, at(2)).unwrap();
+
+        assert_eq!(totals.len(), 2);
+        assert_eq!(totals[0].region, "epoch");
+        assert_eq!(totals[0].energy_joules, 1.0 + 2.0 + 5.0);
+        assert_eq!(totals[0].duration_seconds, 3.0);
+        assert!((totals[0].average_power_watts - 8.0 / 3.0).abs() < 1e-9);
+        assert_eq!(totals[1].region, "eval");
+        assert_eq!(totals[1].energy_joules, 3.0 + 4.0);
+    }
+
+    #[test]
+    fn energy_by_region_splits_per_device() {
+        let mut markers = RegionMarkers::new();
+        markers.begin_at("step", at(0));
+        markers.end_at("step", at(2)).unwrap();
+
+        let totals = energy_by_region(&trace(), markers.regions(), true, at(9)).unwrap();
+
+        let by_device: Vec<_> = totals
+            .iter()
+            .map(|t| (t.device.as_deref().unwrap(), t.energy_joules))
+            .collect();
+        assert_eq!(by_device, vec![("cpu", 4.0), ("gpu", 6.0)]);
+        assert_eq!(totals[1].average_power_watts, 3.0);
+    }
+
+    #[test]
+    fn end_requires_an_open_region() {
+        let mut markers = RegionMarkers::new();
+
+        assert!(matches!(
+            markers.end_at("missing", at(1)),
+            Err(RegionError::NotOpen(_))
+        ));
+        markers.begin_at("a", at(0));
+        markers.end_at("a", at(1)).unwrap();
+        assert!(markers.end_at("a", at(2)).is_err());
+
+        let table = regions_to_dataframe(markers.regions(), ExportTimezone::Utc).unwrap();
+        assert_eq!(
+            table.column("end").unwrap().str().unwrap().get(0),
+            Some("2023-11-14T22:13:21.000Z")
+        );
+    }
+}
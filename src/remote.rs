@@ -0,0 +1,369 @@
+/// Remote Module
+///
+/// Ad-hoc measurements of machines reachable over SSH, behind `emt remote`,
+/// for hosts where installing a daemon is not an option. EMT starts itself
+/// on each host as an agent (`emt agent`, optionally copied there for the
+/// run), which monitors every process and writes the new rows of its joined
+/// trace to stdout as JSON Lines. The rows are spooled locally as they
+/// arrive and, once the run ends, merged into one trace with a `host`
+/// column as `emt merge` does.
+use crate::convert::{ConvertError, write_json_lines};
+use crate::merge::{MergeError, MergeInput, merge_traces};
+use crate::monitor::Monitor;
+use crate::utils::time::{ExportTimezone, timestamp_micros};
+use polars::prelude::{BooleanChunked, DataFrame, PolarsError, PolarsResult};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::future::Future;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{ExitStatus, Stdio};
+use std::time::Duration;
+use thiserror::Error;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::Command;
+use tokio::task::JoinSet;
+
+/// Subcommand that runs the agent.
+pub const AGENT_COMMAND: &str = "agent";
+
+/// Errors raised while measuring remote hosts.
+#[derive(Debug, Error)]
+pub enum RemoteError {
+    #[error("failed to run ssh for {host}: {source}")]
+    Ssh { host: String, source: io::Error },
+    #[error("could not copy emt to {host}")]
+    Upload { host: String },
+    #[error("agent on {host} failed ({status})")]
+    Agent { host: String, status: ExitStatus },
+    #[error("no rows received from any host")]
+    NoRows,
+    #[error(transparent)]
+    Merge(#[from] MergeError),
+    #[error(transparent)]
+    Convert(#[from] ConvertError),
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    #[error("Polars error: {0}")]
+    Polars(#[from] PolarsError),
+}
+
+/// An SSH destination such as `user@node` or an alias from `~/.ssh/config`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteHost {
+    pub destination: String,
+}
+
+impl RemoteHost {
+    pub fn new(destination: impl Into<String>) -> Self {
+        Self {
+            destination: destination.into(),
+        }
+    }
+
+    /// Name used in the `host` column: the destination without its user.
+    pub fn name(&self) -> &str {
+        self.destination
+            .rsplit_once('@')
+            .map_or(self.destination.as_str(), |(_, host)| host)
+    }
+}
+
+/// How the hosts are reached and the agents run.
+#[derive(Debug, Clone)]
+pub struct RemoteOptions {
+    /// Monitoring time on each host
+    pub duration: Duration,
+    /// Collection rate in Hz, else the host's configured rate
+    pub rate_hz: Option<f64>,
+    /// emt executable on the hosts
+    pub agent_path: String,
+    /// Copy this executable to each host for the run instead of using
+    /// `agent_path`; the copy is removed afterwards
+    pub upload: bool,
+    /// Extra `ssh -o` options, such as `Port=2222`
+    pub ssh_options: Vec<String>,
+}
+
+impl Default for RemoteOptions {
+    fn default() -> Self {
+        Self {
+            duration: Duration::from_secs(10),
+            rate_hz: None,
+            agent_path: "emt".to_string(),
+            upload: false,
+            ssh_options: Vec::new(),
+        }
+    }
+}
+
+impl RemoteOptions {
+    /// `ssh` invocation running `command` on `host`. Batch mode keeps ssh
+    /// from prompting, since stdin and stdout carry data.
+    fn ssh(&self, host: &RemoteHost, command: &str) -> Command {
+        let mut ssh = Command::new("ssh");
+        ssh.args(["-T", "-o", "BatchMode=yes"]);
+        for option in &self.ssh_options {
+            ssh.args(["-o", option]);
+        }
+        ssh.args(["--", &host.destination, command])
+            .kill_on_drop(true);
+        ssh
+    }
+
+    /// Remote shell command starting the agent at `path`.
+    fn agent_command(&self, path: &str) -> String {
+        let mut command = format!(
+            "{path} {AGENT_COMMAND} --duration {}",
+            self.duration.as_secs()
+        );
+        if let Some(rate) = self.rate_hz {
+            command.push_str(&format!(" --rate {rate}"));
+        }
+        command
+    }
+}
+
+/// Rows merged from every host that sent any, and the hosts that failed.
+#[derive(Debug)]
+pub struct RemoteTrace {
+    pub trace: DataFrame,
+    pub failures: Vec<RemoteError>,
+}
+
+/// Run the agent on each of `hosts` and merge what they stream back. When
+/// `stop` completes first, the agents are stopped and the rows received so
+/// far are merged. Streams are spooled in `spool_dir` while the run lasts.
+pub async fn collect_remote(
+    hosts: &[RemoteHost],
+    options: &RemoteOptions,
+    spool_dir: &Path,
+    stop: impl Future<Output = ()>,
+) -> Result<RemoteTrace, RemoteError> {
+    fs::create_dir_all(spool_dir)?;
+    let mut sessions = JoinSet::new();
+    for (index, host) in hosts.iter().enumerate() {
+        let spool = spool_path(spool_dir, index);
+        sessions.spawn(stream_host(host.clone(), options.clone(), spool));
+    }
+
+    let mut failures = Vec::new();
+    let all_finished = async {
+        while let Some(session) = sessions.join_next().await {
+            if let Ok(Err(e)) = session {
+                failures.push(e);
+            }
+        }
+    };
+    tokio::select! {
+        () = all_finished => {}
+        () = stop => {}
+    }
+    // Dropping the remaining sessions kills their ssh processes.
+    sessions.shutdown().await;
+
+    // Hosts that failed or were stopped part way keep the rows they sent.
+    let inputs: Vec<MergeInput> = hosts
+        .iter()
+        .enumerate()
+        .map(|(index, host)| MergeInput {
+            path: spool_path(spool_dir, index),
+            host: Some(host.name().to_string()),
+        })
+        .filter(|input| fs::metadata(&input.path).is_ok_and(|metadata| metadata.len() > 0))
+        .collect();
+    let merged = if inputs.is_empty() {
+        Err(failures.pop().unwrap_or(RemoteError::NoRows))
+    } else {
+        merge_traces(&inputs, &HashMap::new(), None).map_err(RemoteError::from)
+    };
+    let _ = fs::remove_dir_all(spool_dir);
+    Ok(RemoteTrace {
+        trace: merged?,
+        failures,
+    })
+}
+
+fn spool_path(spool_dir: &Path, index: usize) -> PathBuf {
+    spool_dir.join(format!("{index}.jsonl"))
+}
+
+/// Run the agent on `host`, appending the lines it writes to `spool`.
+async fn stream_host(
+    host: RemoteHost,
+    options: RemoteOptions,
+    spool: PathBuf,
+) -> Result<(), RemoteError> {
+    let ssh_error = |source| RemoteError::Ssh {
+        host: host.destination.clone(),
+        source,
+    };
+    let command = if options.upload {
+        let path = upload_agent(&host, &options).await?;
+        format!(
+            "{}; status=$?; rm -f {path}; exit $status",
+            options.agent_command(&path)
+        )
+    } else {
+        options.agent_command(&options.agent_path)
+    };
+
+    let mut child = options
+        .ssh(&host, &command)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(ssh_error)?;
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let mut lines = BufReader::new(stdout).lines();
+    let mut spool = File::create(spool)?;
+    while let Some(line) = lines.next_line().await.map_err(ssh_error)? {
+        writeln!(spool, "{line}")?;
+    }
+    let status = child.wait().await.map_err(ssh_error)?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(RemoteError::Agent {
+            host: host.destination.clone(),
+            status,
+        })
+    }
+}
+
+/// Copy the running executable to a temporary file on `host`; returns its
+/// path there.
+async fn upload_agent(host: &RemoteHost, options: &RemoteOptions) -> Result<String, RemoteError> {
+    let ssh_error = |source| RemoteError::Ssh {
+        host: host.destination.clone(),
+        source,
+    };
+    let executable = fs::read(std::env::current_exe()?)?;
+    let mut child = options
+        .ssh(
+            host,
+            r#"f=$(mktemp "${TMPDIR:-/tmp}/emt-agent.XXXXXX") && cat > "$f" && chmod 700 "$f" && echo "$f""#,
+        )
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(ssh_error)?;
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    stdin.write_all(&executable).await.map_err(ssh_error)?;
+    drop(stdin);
+    let output = child.wait_with_output().await.map_err(ssh_error)?;
+    let path = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if !output.status.success() || path.is_empty() {
+        return Err(RemoteError::Upload {
+            host: host.destination.clone(),
+        });
+    }
+    Ok(path)
+}
+
+/// Agent side: writes the rows a monitor records to `out` as JSON Lines,
+/// each row once.
+pub struct AgentStream<W: Write> {
+    out: W,
+    /// Newest timestamp sent per device, in Unix microseconds
+    sent_until: HashMap<String, i64>,
+}
+
+impl<W: Write> AgentStream<W> {
+    pub fn new(out: W) -> Self {
+        Self {
+            out,
+            sent_until: HashMap::new(),
+        }
+    }
+
+    /// Send the rows `monitor` recorded since the last call; returns how
+    /// many. Fails once the reader has gone away.
+    pub async fn send_new_rows(&mut self, monitor: &Monitor) -> Result<usize, RemoteError> {
+        let trace = monitor.joined_trace().await?.collect()?;
+        let rows = new_rows(&trace, &mut self.sent_until)?;
+        if rows.height() > 0 {
+            write_json_lines(&rows, &mut self.out, ExportTimezone::Utc)?;
+            self.out.flush()?;
+        }
+        Ok(rows.height())
+    }
+}
+
+/// Rows of `trace` newer than those sent before for their device, moving
+/// `sent_until` forward. Devices are tracked apart since each collector
+/// group appends its rows on its own schedule.
+fn new_rows(trace: &DataFrame, sent_until: &mut HashMap<String, i64>) -> PolarsResult<DataFrame> {
+    if trace.height() == 0 {
+        return Ok(trace.clone());
+    }
+    let timestamps = timestamp_micros(trace)?;
+    let devices = trace.column("device")?.str()?;
+    let previous = sent_until.clone();
+    let fresh: BooleanChunked = timestamps
+        .iter()
+        .zip(devices.iter())
+        .map(|(timestamp, device)| {
+            let (Some(timestamp), Some(device)) = (timestamp, device) else {
+                return false;
+            };
+            if previous.get(device).is_some_and(|&sent| timestamp <= sent) {
+                return false;
+            }
+            let newest = sent_until.entry(device.to_string()).or_insert(timestamp);
+            *newest = (*newest).max(timestamp);
+            true
+        })
+        .collect();
+    trace.filter(&fresh)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::time::{TIMESTAMP_COLUMN, timestamp_dtype};
+    use polars::prelude::*;
+
+    fn trace(rows: &[(&str, i64)]) -> DataFrame {
+        let devices: Vec<&str> = rows.iter().map(|(device, _)| *device).collect();
+        let timestamps: Vec<i64> = rows.iter().map(|(_, timestamp)| *timestamp).collect();
+        DataFrame::new(vec![
+            Column::new("device".into(), devices),
+            Column::new(TIMESTAMP_COLUMN.into(), timestamps)
+                .cast(&timestamp_dtype())
+                .unwrap(),
+        ])
+        .unwrap()
+    }
+
+    #[test]
+    fn agent_sends_each_row_once_per_device() {
+        let mut sent_until = HashMap::new();
+        let first = trace(&[("cpu", 10), ("cpu", 20)]);
+        assert_eq!(new_rows(&first, &mut sent_until).unwrap().height(), 2);
+
+        // The GPU group appends rows older than the newest CPU row.
+        let second = trace(&[("cpu", 10), ("cpu", 20), ("gpu", 15), ("cpu", 30)]);
+        let rows = new_rows(&second, &mut sent_until).unwrap();
+        assert_eq!(
+            timestamp_micros(&rows).unwrap().to_vec(),
+            [Some(15), Some(30)]
+        );
+        assert_eq!(new_rows(&second, &mut sent_until).unwrap().height(), 0);
+    }
+
+    #[test]
+    fn remote_hosts_are_named_without_user() {
+        assert_eq!(RemoteHost::new("alice@node1").name(), "node1");
+        assert_eq!(RemoteHost::new("gpu-box").name(), "gpu-box");
+
+        let options = RemoteOptions {
+            rate_hz: Some(2.5),
+            ..RemoteOptions::default()
+        };
+        assert_eq!(
+            options.agent_command("/opt/emt"),
+            "/opt/emt agent --duration 10 --rate 2.5"
+        );
+    }
+}
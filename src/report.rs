@@ -0,0 +1,950 @@
+/// Report Module
+///
+/// Summarizes a stored trace for `emt report`: energy per device, the
+/// processes and users that consumed the most, energy per marked region,
+/// energy per unit of reported work, and emissions and cost estimated with
+/// the configured footprint factors.
+///
+/// Devices that device priority rules mark as auxiliary are listed with the
+/// other devices but left out of every total, as in live snapshots.
+use crate::config::{DevicePriorityRule, FootprintConfig, MeasurementUnitsConfig};
+use crate::convert::{ConvertError, read_trace};
+use crate::device_priority::{DevicePriority, DeviceRole};
+use crate::device_registry::DEVICE_ID_COLUMN;
+use crate::encryption::{ENCRYPTED_EXTENSION, TraceCipher};
+use crate::power_stats::{PowerGrouping, power_series};
+use crate::regions::{Region, RegionEnergy, energy_by_region};
+use crate::schema::{
+    REGIONS_FILE_NAME, SCHEMA_VERSION, SchemaError, WORK_FILE_NAME, read_regions_csv, read_work_csv,
+};
+use crate::units::format_significant;
+use crate::utils::time::{ExportTimezone, MONOTONIC_COLUMN, RecordTime, timestamp_micros};
+use crate::work::{Efficiency, WorkEvent, efficiency_by_process, efficiency_by_region};
+use polars::prelude::*;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+const JOULES_PER_KWH: f64 = 3_600_000.0;
+
+/// Errors raised while building a report.
+#[derive(Debug, Error)]
+pub enum ReportError {
+    #[error(transparent)]
+    Convert(#[from] ConvertError),
+    #[error(transparent)]
+    Schema(#[from] SchemaError),
+    #[error("Polars error: {0}")]
+    Polars(#[from] PolarsError),
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// What a report lists and how it values energy.
+#[derive(Debug, Clone, Copy)]
+pub struct ReportOptions<'a> {
+    /// Number of processes and users listed
+    pub top: usize,
+    pub device_priority: &'a [DevicePriorityRule],
+    pub footprint: &'a FootprintConfig,
+    pub timezone: ExportTimezone,
+}
+
+/// Summary of a trace.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Report {
+    pub schema_version: u32,
+    /// Time of the first row
+    pub start: Option<String>,
+    /// Time of the last row
+    pub end: Option<String>,
+    pub duration_seconds: f64,
+    /// Energy of all non-auxiliary devices
+    pub energy_joules: f64,
+    pub emissions_grams: Option<f64>,
+    pub cost: Option<f64>,
+    pub currency: Option<String>,
+    /// Every device, by name
+    pub devices: Vec<DeviceTotal>,
+    /// Top processes by energy
+    pub processes: Vec<ProcessTotal>,
+    /// Top users by energy; empty for traces without a `user` column
+    pub users: Vec<UserTotal>,
+    pub regions: Vec<RegionEnergy>,
+    /// Energy per unit of reported work, per region and then per process
+    pub efficiency: Vec<Efficiency>,
+    /// System power over each collection interval, for charts; empty
+    /// without monotonic readings
+    #[serde(skip)]
+    pub power_timeline: Vec<f64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DeviceTotal {
+    pub device: String,
+    pub energy_joules: f64,
+    pub average_power_watts: f64,
+    /// Highest power over one collection interval; needs monotonic readings
+    pub peak_power_watts: Option<f64>,
+    /// Excluded from totals by device priority
+    pub auxiliary: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ProcessTotal {
+    pub pid: u32,
+    pub task: Option<String>,
+    pub user: Option<String>,
+    pub energy_joules: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct UserTotal {
+    pub user: String,
+    pub energy_joules: f64,
+}
+
+/// Report on the trace at `path`, a file or trace directory. Regions and
+/// work come from the region and work tables below a directory.
+pub fn report_trace(
+    path: &Path,
+    options: ReportOptions<'_>,
+    cipher: Option<&TraceCipher>,
+) -> Result<Report, ReportError> {
+    let trace = read_trace(path, cipher)?;
+    let mut region_tables = Vec::new();
+    let mut work_tables = Vec::new();
+    if path.is_dir() {
+        collect_tables(path, REGIONS_FILE_NAME, &mut region_tables)?;
+        collect_tables(path, WORK_FILE_NAME, &mut work_tables)?;
+    }
+    // Groups recorded into one directory share their regions and work.
+    let mut regions: Vec<Region> = Vec::new();
+    for table in region_tables {
+        for region in read_regions_csv(&table, cipher)? {
+            if !regions.contains(&region) {
+                regions.push(region);
+            }
+        }
+    }
+    let mut work: Vec<WorkEvent> = Vec::new();
+    for table in work_tables {
+        for event in read_work_csv(&table, cipher)? {
+            if !work.contains(&event) {
+                work.push(event);
+            }
+        }
+    }
+    Ok(build_report(&trace, &regions, &work, options)?)
+}
+
+/// Tables named `file_name`, plain or sealed, below `dir`.
+fn collect_tables(dir: &Path, file_name: &str, tables: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    let sealed = format!("{file_name}.{ENCRYPTED_EXTENSION}");
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_tables(&path, file_name, tables)?;
+        } else if path
+            .file_name()
+            .is_some_and(|name| name == file_name || *name == *sealed)
+        {
+            tables.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Summarize `trace` (`pid | timestamp | device | energy`, optionally with
+/// `device_id`, `monotonic_ns`, `user` and `task`), its `regions` and the
+/// `work` reported during it.
+pub fn build_report(
+    trace: &DataFrame,
+    regions: &[Region],
+    work: &[WorkEvent],
+    options: ReportOptions<'_>,
+) -> PolarsResult<Report> {
+    let devices = device_labels(trace)?;
+    let energies = trace.column("energy")?.cast(&DataType::Float64)?;
+    let energies = energies.f64()?;
+    let pids = trace.column("pid")?.cast(&DataType::UInt32)?;
+    let pids = pids.u32()?;
+    let users = optional_strings(trace, "user")?;
+    let tasks = optional_strings(trace, "task")?;
+    let timestamps = timestamp_micros(trace)?;
+    let (start_us, end_us) = (timestamps.min(), timestamps.max());
+    let duration_seconds = match (start_us, end_us) {
+        (Some(start), Some(end)) => (end - start) as f64 / 1e6,
+        _ => 0.0,
+    };
+
+    let mut priority = DevicePriority::new(options.device_priority.to_vec());
+    for device in &devices {
+        priority.observe_device(device);
+    }
+    let mut by_device: BTreeMap<&str, (f64, bool)> = BTreeMap::new();
+    let mut by_pid: HashMap<u32, ProcessTotal> = HashMap::new();
+    let mut by_user: HashMap<&str, f64> = HashMap::new();
+    let mut counted = Vec::with_capacity(trace.height());
+    let mut energy_joules = 0.0;
+    for (row, device) in devices.iter().enumerate() {
+        let joules = energies.get(row).unwrap_or(0.0);
+        let auxiliary = by_device
+            .entry(device)
+            .or_insert_with(|| (0.0, priority.role(device) == DeviceRole::Auxiliary));
+        auxiliary.0 += joules;
+        counted.push(!auxiliary.1);
+        if auxiliary.1 {
+            continue;
+        }
+        energy_joules += joules;
+        let user = users.as_ref().and_then(|users| users.get(row));
+        if let Some(pid) = pids.get(row) {
+            let process = by_pid.entry(pid).or_insert_with(|| ProcessTotal {
+                pid,
+                task: None,
+                user: None,
+                energy_joules: 0.0,
+            });
+            process.energy_joules += joules;
+            if process.task.is_none() {
+                process.task = tasks
+                    .as_ref()
+                    .and_then(|tasks| tasks.get(row))
+                    .map(str::to_string);
+            }
+            if process.user.is_none() {
+                process.user = user.map(str::to_string);
+            }
+        }
+        if let Some(user) = user {
+            *by_user.entry(user).or_insert(0.0) += joules;
+        }
+    }
+
+    let average_power = |joules: f64| {
+        if duration_seconds > 0.0 {
+            joules / duration_seconds
+        } else {
+            0.0
+        }
+    };
+    let process_energy: HashMap<u32, f64> = by_pid
+        .iter()
+        .map(|(pid, process)| (*pid, process.energy_joules))
+        .collect();
+    let mut processes: Vec<_> = by_pid.into_values().collect();
+    processes.sort_by(|a, b| {
+        b.energy_joules
+            .total_cmp(&a.energy_joules)
+            .then(a.pid.cmp(&b.pid))
+    });
+    processes.truncate(options.top);
+    let mut users: Vec<_> = by_user
+        .into_iter()
+        .map(|(user, energy_joules)| UserTotal {
+            user: user.to_string(),
+            energy_joules,
+        })
+        .collect();
+    users.sort_by(|a, b| {
+        b.energy_joules
+            .total_cmp(&a.energy_joules)
+            .then_with(|| a.user.cmp(&b.user))
+    });
+    users.truncate(options.top);
+
+    let peaks = peak_power(trace)?;
+    let (region_totals, mut efficiency) = match trace_end(trace, end_us)? {
+        Some(end) => {
+            let totals = region_energy(trace, &devices, &counted, regions, end)?;
+            let efficiency = efficiency_by_region(&totals, regions, work, end);
+            (totals, efficiency)
+        }
+        None => (Vec::new(), Vec::new()),
+    };
+    efficiency.extend(efficiency_by_process(&process_energy, work));
+    let kwh = energy_joules / JOULES_PER_KWH;
+    Ok(Report {
+        schema_version: SCHEMA_VERSION,
+        start: start_us.map(|start| options.timezone.format_micros(start)),
+        end: end_us.map(|end| options.timezone.format_micros(end)),
+        duration_seconds,
+        energy_joules,
+        emissions_grams: options
+            .footprint
+            .carbon_intensity_g_per_kwh
+            .map(|intensity| kwh * intensity),
+        cost: options.footprint.price_per_kwh.map(|price| kwh * price),
+        currency: options.footprint.currency.clone(),
+        devices: by_device
+            .into_iter()
+            .map(|(device, (energy_joules, auxiliary))| DeviceTotal {
+                device: device.to_string(),
+                energy_joules,
+                average_power_watts: average_power(energy_joules),
+                peak_power_watts: peaks.get(device).copied(),
+                auxiliary,
+            })
+            .collect(),
+        processes,
+        users,
+        regions: region_totals,
+        efficiency,
+        power_timeline: power_timeline(trace, &counted)?,
+    })
+}
+
+/// Power of the `counted` rows over each collection interval.
+fn power_timeline(trace: &DataFrame, counted: &[bool]) -> PolarsResult<Vec<f64>> {
+    let Ok(monotonic) = trace.column(MONOTONIC_COLUMN) else {
+        return Ok(Vec::new());
+    };
+    let monotonic = monotonic.cast(&DataType::Int64)?;
+    let energies = trace.column("energy")?.cast(&DataType::Float64)?;
+    let mut by_tick: BTreeMap<i64, f64> = BTreeMap::new();
+    for ((tick, joules), _) in monotonic
+        .i64()?
+        .iter()
+        .zip(energies.f64()?.iter())
+        .zip(counted)
+        .filter(|(_, counted)| **counted)
+    {
+        if let Some(tick) = tick {
+            *by_tick.entry(tick).or_insert(0.0) += joules.unwrap_or(0.0);
+        }
+    }
+    // The first reading has no interval to divide by.
+    Ok(by_tick
+        .iter()
+        .zip(by_tick.iter().skip(1))
+        .map(|((previous, _), (tick, joules))| joules / ((tick - previous) as f64 / 1e9))
+        .collect())
+}
+
+/// Highest interval power of each named device.
+fn peak_power(trace: &DataFrame) -> PolarsResult<HashMap<String, f64>> {
+    if trace.column(MONOTONIC_COLUMN).is_err() || trace.column("device").is_err() {
+        return Ok(HashMap::new());
+    }
+    Ok(power_series(trace, PowerGrouping::Device, None, None)?
+        .into_iter()
+        .filter_map(|(device, points)| {
+            let peak = points.iter().map(|point| point.watts).reduce(f64::max)?;
+            Some((device, peak))
+        })
+        .collect())
+}
+
+/// Device name of each row, falling back to its id.
+fn device_labels(trace: &DataFrame) -> PolarsResult<Vec<String>> {
+    let names = optional_strings(trace, "device")?;
+    let ids = match trace.column(DEVICE_ID_COLUMN) {
+        Ok(ids) => Some(ids.cast(&DataType::UInt32)?),
+        Err(_) => None,
+    };
+    let ids = ids.as_ref().map(|ids| ids.u32()).transpose()?;
+    Ok((0..trace.height())
+        .map(|row| {
+            match (
+                names.as_ref().and_then(|names| names.get(row)),
+                ids.and_then(|ids| ids.get(row)),
+            ) {
+                (Some(name), _) => name.to_string(),
+                (None, Some(id)) => format!("device {id}"),
+                (None, None) => "unknown".to_string(),
+            }
+        })
+        .collect())
+}
+
+fn optional_strings(trace: &DataFrame, name: &str) -> PolarsResult<Option<StringChunked>> {
+    match trace.column(name) {
+        Ok(column) => Ok(Some(column.cast(&DataType::String)?.str()?.clone())),
+        Err(_) => Ok(None),
+    }
+}
+
+/// Time of the last row, at which open regions end. Traces without
+/// monotonic readings have none, and so no regions.
+fn trace_end(trace: &DataFrame, end_us: Option<i64>) -> PolarsResult<Option<RecordTime>> {
+    let Ok(monotonic) = trace.column(MONOTONIC_COLUMN) else {
+        return Ok(None);
+    };
+    Ok(Some(RecordTime {
+        timestamp_us: end_us.unwrap_or_default(),
+        monotonic_ns: monotonic
+            .cast(&DataType::Int64)?
+            .i64()?
+            .max()
+            .unwrap_or_default(),
+    }))
+}
+
+/// Energy of the `counted` rows per region, open regions ending at `end`.
+fn region_energy(
+    trace: &DataFrame,
+    devices: &[String],
+    counted: &[bool],
+    regions: &[Region],
+    end: RecordTime,
+) -> PolarsResult<Vec<RegionEnergy>> {
+    if regions.is_empty() {
+        return Ok(Vec::new());
+    }
+    let rows = DataFrame::new(vec![
+        Column::new("device".into(), devices),
+        trace.column("energy")?.cast(&DataType::Float64)?,
+        trace.column(MONOTONIC_COLUMN)?.cast(&DataType::Int64)?,
+    ])?
+    .filter(&BooleanChunked::from_slice("counted".into(), counted))?;
+    energy_by_region(&rows, regions, false, end)
+}
+
+/// Sparkline levels, lowest first.
+const SPARK_LEVELS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+/// Characters of the Markdown power chart.
+const CHART_WIDTH: usize = 60;
+
+/// Column of a report table.
+struct TableColumn {
+    name: &'static str,
+    unit: Option<String>,
+    right_aligned: bool,
+}
+
+impl TableColumn {
+    fn text(name: &'static str) -> Self {
+        Self {
+            name,
+            unit: None,
+            right_aligned: false,
+        }
+    }
+
+    fn number(name: &'static str, unit: &str) -> Self {
+        Self {
+            name,
+            unit: Some(unit.to_string()),
+            right_aligned: true,
+        }
+    }
+
+    fn header(&self, upper_case: bool) -> String {
+        let name = if upper_case {
+            self.name.to_uppercase()
+        } else {
+            self.name.to_string()
+        };
+        match &self.unit {
+            Some(unit) => format!("{name} ({unit})"),
+            None => name,
+        }
+    }
+}
+
+/// A titled table of a report.
+struct Table {
+    title: &'static str,
+    columns: Vec<TableColumn>,
+    rows: Vec<Vec<String>>,
+}
+
+impl Report {
+    /// Tables for the terminal, with energy and power in `units`.
+    pub fn render_text(&self, units: &MeasurementUnitsConfig) -> String {
+        let mut lines: Vec<String> = self
+            .overview(units)
+            .into_iter()
+            .map(|(label, value)| format!("{:<11}{value}", format!("{label}:")))
+            .collect();
+        for table in self.tables(units) {
+            push_text_table(&mut lines, &table);
+        }
+        lines.push(String::new());
+        lines.join("\n")
+    }
+
+    /// Markdown for job summaries, merge requests or tickets: the overview,
+    /// a sparkline of system power and the tables of
+    /// [`render_text`](Self::render_text).
+    pub fn render_markdown(&self, units: &MeasurementUnitsConfig) -> String {
+        let mut lines = vec![
+            "## Energy summary".to_string(),
+            String::new(),
+            "| | |".to_string(),
+            "|---|---|".to_string(),
+        ];
+        for (label, value) in self.overview(units) {
+            lines.push(format!("| {label} | {} |", escape_markdown(&value)));
+        }
+        if let Some(chart) = sparkline(&self.power_timeline, CHART_WIDTH) {
+            let low = self
+                .power_timeline
+                .iter()
+                .copied()
+                .fold(f64::INFINITY, f64::min);
+            let high = self
+                .power_timeline
+                .iter()
+                .copied()
+                .fold(f64::NEG_INFINITY, f64::max);
+            let power = |watts: f64| format_significant(units.convert_power_from_watts(watts));
+            lines.extend([
+                String::new(),
+                "### Power".to_string(),
+                String::new(),
+                "```text".to_string(),
+                chart,
+                "```".to_string(),
+                String::new(),
+                format!(
+                    "{} to {} {} over {} collection intervals.",
+                    power(low),
+                    power(high),
+                    units.power,
+                    self.power_timeline.len()
+                ),
+            ]);
+        }
+        for table in self.tables(units) {
+            push_markdown_table(&mut lines, &table);
+        }
+        lines.push(String::new());
+        lines.join("\n")
+    }
+
+    /// Labelled totals heading every rendering.
+    fn overview(&self, units: &MeasurementUnitsConfig) -> Vec<(&'static str, String)> {
+        let mut overview = vec![(
+            "Period",
+            match (&self.start, &self.end) {
+                (Some(start), Some(end)) => {
+                    format!("{start} to {end} ({:.1} s)", self.duration_seconds)
+                }
+                _ => "empty trace".to_string(),
+            },
+        )];
+        overview.push(("Energy", units.format_energy(self.energy_joules)));
+        if let Some(grams) = self.emissions_grams {
+            overview.push(("Emissions", format!("{grams:.3} gCO2e")));
+        }
+        if let Some(cost) = self.cost {
+            let currency = self.currency.as_deref().unwrap_or_default();
+            overview.push((
+                "Cost",
+                format!("{cost:.4} {currency}").trim_end().to_string(),
+            ));
+        }
+        overview
+    }
+
+    fn tables(&self, units: &MeasurementUnitsConfig) -> Vec<Table> {
+        let energy = |joules: f64| format_significant(units.convert_energy_from_joules(joules));
+        let power = |watts: f64| format_significant(units.convert_power_from_watts(watts));
+        let energy_column = || TableColumn::number("Energy", &units.energy);
+        vec![
+            Table {
+                title: "Devices",
+                columns: vec![
+                    TableColumn::text("Device"),
+                    energy_column(),
+                    TableColumn::number("Avg power", &units.power),
+                    TableColumn::number("Peak power", &units.power),
+                ],
+                rows: self
+                    .devices
+                    .iter()
+                    .map(|device| {
+                        let name = if device.auxiliary {
+                            format!("{} (auxiliary)", device.device)
+                        } else {
+                            device.device.clone()
+                        };
+                        vec![
+                            name,
+                            energy(device.energy_joules),
+                            power(device.average_power_watts),
+                            device.peak_power_watts.map(power).unwrap_or_default(),
+                        ]
+                    })
+                    .collect(),
+            },
+            Table {
+                title: "Top processes",
+                columns: vec![
+                    TableColumn {
+                        right_aligned: true,
+                        ..TableColumn::text("PID")
+                    },
+                    energy_column(),
+                    TableColumn::text("Task"),
+                    TableColumn::text("User"),
+                ],
+                rows: self
+                    .processes
+                    .iter()
+                    .map(|process| {
+                        vec![
+                            process.pid.to_string(),
+                            energy(process.energy_joules),
+                            process.task.clone().unwrap_or_default(),
+                            process.user.clone().unwrap_or_default(),
+                        ]
+                    })
+                    .collect(),
+            },
+            Table {
+                title: "Top users",
+                columns: vec![TableColumn::text("User"), energy_column()],
+                rows: self
+                    .users
+                    .iter()
+                    .map(|user| vec![user.user.clone(), energy(user.energy_joules)])
+                    .collect(),
+            },
+            Table {
+                title: "Regions",
+                columns: vec![
+                    TableColumn::text("Region"),
+                    energy_column(),
+                    TableColumn::number("Duration", "s"),
+                    TableColumn::number("Avg power", &units.power),
+                ],
+                rows: self
+                    .regions
+                    .iter()
+                    .map(|region| {
+                        vec![
+                            region.region.clone(),
+                            energy(region.energy_joules),
+                            format!("{:.1}", region.duration_seconds),
+                            power(region.average_power_watts),
+                        ]
+                    })
+                    .collect(),
+            },
+            Table {
+                title: "Efficiency",
+                columns: vec![
+                    TableColumn::text("Scope"),
+                    TableColumn {
+                        right_aligned: true,
+                        ..TableColumn::text("Work")
+                    },
+                    TableColumn::text("Unit"),
+                    TableColumn::number("Energy per unit", &units.energy),
+                ],
+                rows: self
+                    .efficiency
+                    .iter()
+                    .map(|efficiency| {
+                        let scope = match (&efficiency.region, efficiency.pid) {
+                            (Some(region), _) => region.clone(),
+                            (None, Some(pid)) => format!("pid {pid}"),
+                            (None, None) => String::new(),
+                        };
+                        vec![
+                            scope,
+                            format_significant(efficiency.work),
+                            efficiency.unit.clone(),
+                            efficiency.joules_per_unit.map(energy).unwrap_or_default(),
+                        ]
+                    })
+                    .collect(),
+            },
+        ]
+    }
+}
+
+/// Append `table` with aligned columns; empty tables are left out.
+fn push_text_table(lines: &mut Vec<String>, table: &Table) {
+    if table.rows.is_empty() {
+        return;
+    }
+    let header = table
+        .columns
+        .iter()
+        .map(|column| column.header(true))
+        .collect();
+    let rows: Vec<Vec<String>> = std::iter::once(header)
+        .chain(table.rows.iter().cloned())
+        .collect();
+    let widths: Vec<usize> = (0..table.columns.len())
+        .map(|index| {
+            rows.iter()
+                .map(|row| row[index].chars().count())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+
+    lines.push(String::new());
+    lines.push(table.title.to_string());
+    for row in rows {
+        let cells: Vec<String> = row
+            .iter()
+            .zip(&table.columns)
+            .zip(&widths)
+            .map(|((cell, column), &width)| {
+                if column.right_aligned {
+                    format!("{cell:>width$}")
+                } else {
+                    format!("{cell:<width$}")
+                }
+            })
+            .collect();
+        lines.push(format!("  {}", cells.join("  ")).trim_end().to_string());
+    }
+}
+
+/// Append `table` as a Markdown table; empty tables are left out.
+fn push_markdown_table(lines: &mut Vec<String>, table: &Table) {
+    if table.rows.is_empty() {
+        return;
+    }
+    let row = |cells: Vec<String>| format!("| {} |", cells.join(" | "));
+    lines.push(String::new());
+    lines.push(format!("### {}", table.title));
+    lines.push(String::new());
+    lines.push(row(table
+        .columns
+        .iter()
+        .map(|column| column.header(false))
+        .collect()));
+    lines.push(row(table
+        .columns
+        .iter()
+        .map(|column| if column.right_aligned { "---:" } else { "---" }.to_string())
+        .collect()));
+    for cells in &table.rows {
+        lines.push(row(cells
+            .iter()
+            .map(|cell| escape_markdown(cell))
+            .collect()));
+    }
+}
+
+fn escape_markdown(cell: &str) -> String {
+    cell.replace('|', "\\|")
+}
+
+/// `values` as one sparkline character each, averaged down to at most
+/// `width` characters; `None` without values.
+fn sparkline(values: &[f64], width: usize) -> Option<String> {
+    if values.is_empty() || width == 0 {
+        return None;
+    }
+    let columns: Vec<f64> = (0..width.min(values.len()))
+        .map(|index| {
+            let start = index * values.len() / width.min(values.len());
+            let end = (index + 1) * values.len() / width.min(values.len());
+            values[start..end].iter().sum::<f64>() / (end - start) as f64
+        })
+        .collect();
+    let low = columns.iter().copied().fold(f64::INFINITY, f64::min);
+    let high = columns.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let top = (SPARK_LEVELS.len() - 1) as f64;
+    Some(
+        columns
+            .iter()
+            .map(|value| {
+                let level = if high > low {
+                    ((value - low) / (high - low) * top).round() as usize
+                } else {
+                    0
+                };
+                SPARK_LEVELS[level.min(SPARK_LEVELS.len() - 1)]
+            })
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::EmtConfig;
+    use crate::utils::time::{TIMESTAMP_COLUMN, monotonic_dtype, timestamp_dtype};
+    use crate::work::WorkCounters;
+
+    const START_US: i64 = 1_700_000_000_000_000;
+
+    fn trace() -> DataFrame {
+        let devices = [
+            "rapl:socket:0:package",
+            "rapl:system:psys",
+            "rapl:socket:0:package",
+            "nvidia:0",
+            "rapl:socket:0:package",
+        ];
+        DataFrame::new(vec![
+            Column::new("pid".into(), [1u32, 1, 2, 2, 3]),
+            Column::new(
+                TIMESTAMP_COLUMN.into(),
+                [0i64, 0, 1, 2, 2].map(|s| START_US + s * 1_000_000),
+            )
+            .cast(&timestamp_dtype())
+            .unwrap(),
+            Column::new("device".into(), devices),
+            Column::new("energy".into(), [2.0, 9.0, 4.0, 6.0, 1.0]),
+            Column::new(
+                MONOTONIC_COLUMN.into(),
+                [0i64, 0, 1, 2, 2].map(|s| s * 1_000_000_000),
+            )
+            .cast(&monotonic_dtype())
+            .unwrap(),
+            Column::new("user".into(), ["alice", "alice", "bob", "bob", "alice"]),
+            Column::new(
+                "task".into(),
+                [Some("train"), Some("train"), None, None, Some("eval")],
+            ),
+        ])
+        .unwrap()
+    }
+
+    fn options<'a>(config: &'a EmtConfig, top: usize) -> ReportOptions<'a> {
+        ReportOptions {
+            top,
+            device_priority: &config.device_priority,
+            footprint: &config.footprint,
+            timezone: ExportTimezone::Utc,
+        }
+    }
+
+    #[test]
+    fn report_totals_skip_auxiliary_devices() {
+        let mut config = EmtConfig::default();
+        config.footprint.carbon_intensity_g_per_kwh = Some(360_000.0);
+        config.footprint.price_per_kwh = Some(3_600.0);
+        config.footprint.currency = Some("EUR".to_string());
+        let regions = [Region {
+            name: "epoch".to_string(),
+            start: RecordTime {
+                timestamp_us: START_US,
+                monotonic_ns: 0,
+            },
+            end: None,
+        }];
+
+        let report = build_report(&trace(), &regions, &[], options(&config, 2)).unwrap();
+
+        assert_eq!(report.duration_seconds, 2.0);
+        assert_eq!(report.energy_joules, 13.0);
+        assert_eq!(report.emissions_grams, Some(1.3));
+        assert_eq!(report.cost, Some(0.013));
+        let psys = report
+            .devices
+            .iter()
+            .find(|device| device.device == "rapl:system:psys")
+            .unwrap();
+        assert!(psys.auxiliary);
+        assert_eq!(psys.average_power_watts, 4.5);
+        let package = &report.devices[1];
+        assert_eq!(package.device, "rapl:socket:0:package");
+        assert_eq!(package.peak_power_watts, Some(4.0));
+        assert_eq!(report.devices[0].peak_power_watts, None);
+        assert_eq!(
+            report
+                .processes
+                .iter()
+                .map(|process| (process.pid, process.energy_joules))
+                .collect::<Vec<_>>(),
+            [(2, 10.0), (1, 2.0)]
+        );
+        assert_eq!(report.processes[1].task.as_deref(), Some("train"));
+        assert_eq!(report.users[0].user, "bob");
+        assert_eq!(report.users[1].energy_joules, 3.0);
+        assert_eq!(report.regions[0].energy_joules, 11.0);
+    }
+
+    #[test]
+    fn report_divides_energy_by_reported_work() {
+        let config = EmtConfig::default();
+        let at = |seconds: i64| RecordTime {
+            timestamp_us: START_US + seconds * 1_000_000,
+            monotonic_ns: seconds * 1_000_000_000,
+        };
+        let regions = [Region {
+            name: "epoch".to_string(),
+            start: at(0),
+            end: None,
+        }];
+        let mut work = WorkCounters::new();
+        work.add_at("images", 22.0, None, at(1)).unwrap();
+        work.add_at("tokens", 20.0, Some(2), at(2)).unwrap();
+
+        let report = build_report(&trace(), &regions, work.events(), options(&config, 10)).unwrap();
+
+        let efficiency: Vec<_> = report
+            .efficiency
+            .iter()
+            .map(|e| {
+                (
+                    e.region.as_deref(),
+                    e.pid,
+                    e.unit.as_str(),
+                    e.joules_per_unit,
+                )
+            })
+            .collect();
+        assert_eq!(
+            efficiency,
+            [
+                (Some("epoch"), None, "images", Some(0.5)),
+                (Some("epoch"), None, "tokens", Some(0.55)),
+                (None, Some(2), "tokens", Some(0.5)),
+            ]
+        );
+        let text = report.render_text(&MeasurementUnitsConfig::default());
+        assert!(text.contains("  SCOPE   WORK  UNIT    ENERGY PER UNIT (Joules)"));
+        assert!(text.contains("  pid 2  20.00  tokens                    0.5000"));
+    }
+
+    #[test]
+    fn text_report_lists_tables_in_configured_units() {
+        let config = EmtConfig::default();
+        let report = build_report(&trace(), &[], &[], options(&config, 10)).unwrap();
+        let units = MeasurementUnitsConfig {
+            energy: "kJ".to_string(),
+            power: "mW".to_string(),
+        };
+
+        let text = report.render_text(&units);
+
+        assert!(text.contains("Energy:    0.01300 kJ"));
+        assert!(text.contains("rapl:system:psys (auxiliary)"));
+        assert!(text.contains("Top users"));
+        assert!(!text.contains("Regions"));
+        assert!(!text.contains("Emissions"));
+        let header = text
+            .lines()
+            .find(|line| line.trim_start().starts_with("PID"))
+            .unwrap();
+        assert_eq!(header, "  PID  ENERGY (kJ)  TASK   USER");
+    }
+
+    #[test]
+    fn markdown_report_charts_system_power() {
+        let config = EmtConfig::default();
+        let mut report = build_report(&trace(), &[], &[], options(&config, 10)).unwrap();
+        assert_eq!(report.power_timeline, [4.0, 7.0]);
+        report.processes[0].task = Some("a|b".to_string());
+
+        let markdown = report.render_markdown(&MeasurementUnitsConfig::default());
+
+        assert!(markdown.starts_with("## Energy summary\n"));
+        assert!(markdown.contains("| Energy | 13.00 Joules |"));
+        assert!(markdown.contains("```text\n▁█\n```"));
+        assert!(markdown.contains("4.000 to 7.000 Watts over 2 collection intervals."));
+        assert!(
+            markdown
+                .contains("| PID | Energy (Joules) | Task | User |\n| ---: | ---: | --- | --- |")
+        );
+        assert!(markdown.contains("a\\|b"));
+        assert_eq!(sparkline(&[1.0, 3.0, 2.0, 4.0], 2).as_deref(), Some("▁█"));
+        assert_eq!(sparkline(&[5.0; 3], 10).as_deref(), Some("▁▁▁"));
+        assert_eq!(sparkline(&[], 10), None);
+    }
+}
@@ -0,0 +1,491 @@
+/// Run Module
+///
+/// Records a trace for `emt run` and lists the collectors of this host for
+/// `emt list-collectors`. A run reads the chosen collectors at a fixed rate
+/// for a set time, attributing energy to the given pids and to the children
+/// they fork, and returns the trace joined with the device table, ready to
+/// be written in any format `emt convert` writes.
+use crate::collectors::{
+    AmdGpu, CpuEnergy, IntelGpu, JetsonIna, MeterCollector, Network, NvidiaGpu, Storage,
+};
+use crate::config::EmtConfig;
+use crate::energy_group::{EnergyCollector, EnergyGroup};
+use crate::utils::errors::MonitoringError;
+use polars::prelude::{DataFrame, LazyFrame, PolarsError, SortMultipleOptions, UnionArgs, concat};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+use thiserror::Error;
+
+/// How often a run re-scans for children of its pids.
+const CHILD_SCAN_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Error)]
+pub enum RunError {
+    #[error(
+        "unknown collector '{0}' (expected cpu, nvidia, amd, intel, jetson, meters, network or storage)"
+    )]
+    UnknownCollector(String),
+    #[error("rate {0} must be a finite value greater than 0")]
+    InvalidRate(f64),
+    #[error("{0} is not available on this host")]
+    Unavailable(CollectorKind),
+    #[error("no collector is available on this host")]
+    NoCollectors,
+    #[error(transparent)]
+    Monitoring(#[from] MonitoringError),
+    #[error("Polars error: {0}")]
+    Polars(#[from] PolarsError),
+}
+
+/// A kind of collector a run can read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CollectorKind {
+    /// CPU energy, from the first backend of `cpu_energy.backends` that
+    /// passes preflight
+    #[serde(alias = "rapl")]
+    Cpu,
+    /// NVIDIA GPUs through NVML
+    Nvidia,
+    /// AMD GPUs through amdgpu hwmon
+    Amd,
+    /// Intel GPUs through i915 or xe hwmon
+    Intel,
+    /// Jetson power rails through their INA3221 monitors
+    Jetson,
+    /// The configured `exec_collectors`, `http_collectors` and
+    /// `bmc_collectors`
+    Meters,
+    /// Network interfaces, estimated from their traffic with the
+    /// `network` energy model
+    Network,
+    /// Storage devices, estimated from their I/O with the `storage` energy
+    /// model
+    Storage,
+}
+
+impl CollectorKind {
+    pub const ALL: [Self; 8] = [
+        Self::Cpu,
+        Self::Nvidia,
+        Self::Amd,
+        Self::Intel,
+        Self::Jetson,
+        Self::Meters,
+        Self::Network,
+        Self::Storage,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Cpu => "cpu",
+            Self::Nvidia => "nvidia",
+            Self::Amd => "amd",
+            Self::Intel => "intel",
+            Self::Jetson => "jetson",
+            Self::Meters => "meters",
+            Self::Network => "network",
+            Self::Storage => "storage",
+        }
+    }
+}
+
+impl fmt::Display for CollectorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for CollectorKind {
+    type Err = RunError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            // RAPL is what the CPU collector reads on most hosts.
+            "rapl" => Ok(Self::Cpu),
+            _ => Self::ALL
+                .into_iter()
+                .find(|kind| kind.as_str() == name)
+                .ok_or_else(|| RunError::UnknownCollector(name.to_string())),
+        }
+    }
+}
+
+/// Whether one collector can be read on this host.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CollectorStatus {
+    pub collector: CollectorKind,
+    /// Backend, GPU library or meter the status is about
+    pub source: String,
+    pub available: bool,
+    /// What was found, or why it cannot be read
+    pub detail: String,
+}
+
+/// Status of every collector `config` could read: each CPU energy backend
+/// in probe order, the GPU libraries, the Jetson rails, the configured
+/// meters and the network and storage models when they are configured.
+pub fn list_collectors(config: &EmtConfig) -> Vec<CollectorStatus> {
+    let mut statuses: Vec<CollectorStatus> = CpuEnergy::new(&config.cpu_energy)
+        .preflight()
+        .iter()
+        .map(|preflight| CollectorStatus {
+            collector: CollectorKind::Cpu,
+            source: preflight.backend.as_str().to_string(),
+            available: preflight.available,
+            detail: preflight.detail.clone(),
+        })
+        .collect();
+    let nvidia = NvidiaGpu::is_available();
+    statuses.push(CollectorStatus {
+        collector: CollectorKind::Nvidia,
+        source: "nvml".to_string(),
+        available: nvidia,
+        detail: if nvidia {
+            "NVML reports at least one GPU".to_string()
+        } else {
+            "NVML could not be loaded or reports no GPU".to_string()
+        },
+    });
+    let amd = AmdGpu::is_available();
+    statuses.push(CollectorStatus {
+        collector: CollectorKind::Amd,
+        source: "amdgpu".to_string(),
+        available: amd,
+        detail: if amd {
+            "an amdgpu card with a readable hwmon sensor".to_string()
+        } else {
+            "no amdgpu card with a readable hwmon sensor".to_string()
+        },
+    });
+    let intel = IntelGpu::is_available();
+    statuses.push(CollectorStatus {
+        collector: CollectorKind::Intel,
+        source: "i915/xe".to_string(),
+        available: intel,
+        detail: if intel {
+            "an Intel GPU with a readable hwmon energy counter".to_string()
+        } else {
+            "no Intel GPU with a readable hwmon energy counter".to_string()
+        },
+    });
+    let jetson = JetsonIna::is_available();
+    statuses.push(CollectorStatus {
+        collector: CollectorKind::Jetson,
+        source: "ina3221".to_string(),
+        available: jetson,
+        detail: if jetson {
+            "a Jetson INA3221 with a readable power rail".to_string()
+        } else {
+            "no Jetson INA3221 with a readable power rail".to_string()
+        },
+    });
+    let meters = config
+        .exec_collectors
+        .iter()
+        .map(|meter| format!("exec:{}", meter.name))
+        .chain(
+            config
+                .http_collectors
+                .iter()
+                .map(|meter| format!("http:{}", meter.name)),
+        )
+        .chain(
+            config
+                .bmc_collectors
+                .iter()
+                .map(|meter| format!("bmc:{}", meter.name)),
+        );
+    statuses.extend(meters.map(|source| CollectorStatus {
+        collector: CollectorKind::Meters,
+        source,
+        available: true,
+        detail: "configured".to_string(),
+    }));
+    if config.network.is_some() {
+        let network = Network::is_available();
+        statuses.push(CollectorStatus {
+            collector: CollectorKind::Network,
+            source: "net/dev".to_string(),
+            available: network,
+            detail: if network {
+                "interface byte counters are readable".to_string()
+            } else {
+                "/proc/self/net/dev is not readable".to_string()
+            },
+        });
+    }
+    if config.storage.is_some() {
+        let storage = Storage::is_available();
+        statuses.push(CollectorStatus {
+            collector: CollectorKind::Storage,
+            source: "diskstats".to_string(),
+            available: storage,
+            detail: if storage {
+                "disk statistics are readable".to_string()
+            } else {
+                "/proc/diskstats is not readable".to_string()
+            },
+        });
+    }
+    statuses
+}
+
+/// The statuses as a table for the terminal.
+pub fn render_collectors(statuses: &[CollectorStatus]) -> String {
+    let source_width = statuses
+        .iter()
+        .map(|status| status.source.len())
+        .max()
+        .unwrap_or(0);
+    let mut lines: Vec<String> = statuses
+        .iter()
+        .map(|status| {
+            format!(
+                "{:<7} {:<source_width$}  {:<11}  {}",
+                status.collector.as_str(),
+                status.source,
+                if status.available {
+                    "available"
+                } else {
+                    "unavailable"
+                },
+                status.detail
+            )
+        })
+        .collect();
+    lines.push(String::new());
+    lines.join("\n")
+}
+
+/// What `emt run` reads, and for how long.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunOptions {
+    /// Collectors to read; `collection.collectors` when empty, and every
+    /// available one when both are
+    pub collectors: Vec<CollectorKind>,
+    /// Collection rate, in Hz
+    pub rate: f64,
+    /// Processes energy is attributed to, with their children; device
+    /// totals only when empty
+    pub pids: Vec<u32>,
+    pub duration: Duration,
+}
+
+/// A collector group of a run.
+enum RunGroup {
+    Cpu(EnergyGroup<CpuEnergy>),
+    Nvidia(EnergyGroup<NvidiaGpu>),
+    Amd(EnergyGroup<AmdGpu>),
+    Intel(EnergyGroup<IntelGpu>),
+    Jetson(EnergyGroup<JetsonIna>),
+    Meters(EnergyGroup<MeterCollector>),
+    Network(EnergyGroup<Network>),
+    Storage(EnergyGroup<Storage>),
+}
+
+impl RunGroup {
+    /// A group reading `kind`, or `None` when it is not available.
+    fn open(kind: CollectorKind, config: &EmtConfig, options: &RunOptions) -> Option<Self> {
+        Some(match kind {
+            CollectorKind::Cpu => {
+                let cpu = CpuEnergy::new(&config.cpu_energy);
+                cpu.backend()?;
+                Self::Cpu(Self::group(cpu, options))
+            }
+            CollectorKind::Nvidia => {
+                if !NvidiaGpu::is_available() {
+                    return None;
+                }
+                Self::Nvidia(Self::group(NvidiaGpu::new().ok()?, options))
+            }
+            CollectorKind::Amd => {
+                if !AmdGpu::is_available() {
+                    return None;
+                }
+                Self::Amd(Self::group(AmdGpu::default(), options))
+            }
+            CollectorKind::Intel => {
+                if !IntelGpu::is_available() {
+                    return None;
+                }
+                Self::Intel(Self::group(IntelGpu::default(), options))
+            }
+            CollectorKind::Jetson => {
+                if !JetsonIna::is_available() {
+                    return None;
+                }
+                Self::Jetson(Self::group(JetsonIna::default(), options))
+            }
+            CollectorKind::Meters => {
+                if config.exec_collectors.is_empty()
+                    && config.http_collectors.is_empty()
+                    && config.bmc_collectors.is_empty()
+                {
+                    return None;
+                }
+                let meters = MeterCollector::new(&config.exec_collectors, &config.http_collectors)
+                    .with_bmc(&config.bmc_collectors);
+                Self::Meters(Self::group(meters, options))
+            }
+            CollectorKind::Network => {
+                let network = config.network.clone()?;
+                if !Network::is_available() {
+                    return None;
+                }
+                Self::Network(Self::group(Network::new(network), options))
+            }
+            CollectorKind::Storage => {
+                let storage = config.storage.clone()?;
+                if !Storage::is_available() {
+                    return None;
+                }
+                Self::Storage(Self::group(Storage::new(storage), options))
+            }
+        })
+    }
+
+    fn group<C: EnergyCollector>(collector: C, options: &RunOptions) -> EnergyGroup<C> {
+        let mut group = EnergyGroup::new(collector, options.rate, Some(1));
+        // Keep the whole run, with room for the batches still in flight.
+        group.set_trace_retention(options.duration.as_secs() as i64 + 60);
+        group.set_auto_drain(true);
+        if !options.pids.is_empty() {
+            group.set_tracked_pids(options.pids.clone());
+            group.follow_children(Some(CHILD_SCAN_INTERVAL));
+        }
+        group
+    }
+
+    async fn commence(&mut self) -> Result<(), MonitoringError> {
+        match self {
+            Self::Cpu(group) => group.commence().await,
+            Self::Nvidia(group) => group.commence().await,
+            Self::Amd(group) => group.commence().await,
+            Self::Intel(group) => group.commence().await,
+            Self::Jetson(group) => group.commence().await,
+            Self::Meters(group) => group.commence().await,
+            Self::Network(group) => group.commence().await,
+            Self::Storage(group) => group.commence().await,
+        }
+    }
+
+    /// Stop collecting and return the trace joined with its metadata.
+    async fn finish(&mut self) -> Result<LazyFrame, RunError> {
+        let trace = match self {
+            Self::Cpu(group) => group.shutdown().await.map(|()| group.joined_trace()),
+            Self::Nvidia(group) => group.shutdown().await.map(|()| group.joined_trace()),
+            Self::Amd(group) => group.shutdown().await.map(|()| group.joined_trace()),
+            Self::Intel(group) => group.shutdown().await.map(|()| group.joined_trace()),
+            Self::Jetson(group) => group.shutdown().await.map(|()| group.joined_trace()),
+            Self::Meters(group) => group.shutdown().await.map(|()| group.joined_trace()),
+            Self::Network(group) => group.shutdown().await.map(|()| group.joined_trace()),
+            Self::Storage(group) => group.shutdown().await.map(|()| group.joined_trace()),
+        };
+        Ok(trace??)
+    }
+}
+
+/// Read the collectors of `options` for its duration and return their
+/// trace, joined with the device table and ordered by time.
+pub async fn record_trace(config: &EmtConfig, options: &RunOptions) -> Result<DataFrame, RunError> {
+    if !(options.rate.is_finite() && options.rate > 0.0) {
+        return Err(RunError::InvalidRate(options.rate));
+    }
+    let collectors = if options.collectors.is_empty() {
+        &config.collection.collectors
+    } else {
+        &options.collectors
+    };
+    let mut groups = Vec::new();
+    if collectors.is_empty() {
+        groups.extend(
+            CollectorKind::ALL
+                .into_iter()
+                .filter_map(|kind| RunGroup::open(kind, config, options)),
+        );
+        if groups.is_empty() {
+            return Err(RunError::NoCollectors);
+        }
+    } else {
+        for &kind in collectors {
+            groups.push(RunGroup::open(kind, config, options).ok_or(RunError::Unavailable(kind))?);
+        }
+    }
+
+    for group in &mut groups {
+        group.commence().await?;
+    }
+    tokio::time::sleep(options.duration).await;
+    let mut traces = Vec::with_capacity(groups.len());
+    for group in &mut groups {
+        traces.push(group.finish().await?);
+    }
+    Ok(concat(traces, UnionArgs::default())?
+        .sort(["timestamp"], SortMultipleOptions::default())
+        .collect()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn collector_names_parse_with_rapl_as_cpu() {
+        assert_eq!("rapl".parse::<CollectorKind>().unwrap(), CollectorKind::Cpu);
+        assert_eq!(
+            "nvidia".parse::<CollectorKind>().unwrap(),
+            CollectorKind::Nvidia
+        );
+        assert_eq!(
+            "intel".parse::<CollectorKind>().unwrap(),
+            CollectorKind::Intel
+        );
+        assert!(matches!(
+            "tpu".parse::<CollectorKind>(),
+            Err(RunError::UnknownCollector(_))
+        ));
+    }
+
+    #[test]
+    fn lists_every_cpu_backend_and_configured_meter() {
+        let config = EmtConfig {
+            exec_collectors: serde_yml::from_str(
+                "- name: pdu\n  command: [echo]\n- name: bmc\n  command: [echo]\n",
+            )
+            .unwrap(),
+            ..EmtConfig::default()
+        };
+
+        let statuses = list_collectors(&config);
+
+        let cpu = statuses
+            .iter()
+            .filter(|status| status.collector == CollectorKind::Cpu)
+            .count();
+        assert_eq!(cpu, config.cpu_energy.backends.len());
+        let meters: Vec<&str> = statuses
+            .iter()
+            .filter(|status| status.collector == CollectorKind::Meters)
+            .map(|status| status.source.as_str())
+            .collect();
+        assert_eq!(meters, ["exec:pdu", "exec:bmc"]);
+        assert!(render_collectors(&statuses).contains("meters  exec:pdu"));
+    }
+
+    #[tokio::test]
+    async fn rejects_rates_that_are_not_positive() {
+        let options = RunOptions {
+            collectors: vec![CollectorKind::Cpu],
+            rate: 0.0,
+            pids: Vec::new(),
+            duration: Duration::from_millis(10),
+        };
+        assert!(matches!(
+            record_trace(&EmtConfig::default(), &options).await,
+            Err(RunError::InvalidRate(_))
+        ));
+    }
+}
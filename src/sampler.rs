@@ -0,0 +1,517 @@
+/// Sampler Module
+///
+/// Shares one energy collector between several `EnergyGroup`s. Collectors
+/// turn cumulative counters into deltas, so every collector instance re-reads
+/// every counter and keeps its own baselines and utilization trackers. A
+/// [`SharedSampler`] instead reads the counters exactly once per tick, for
+/// the union of all subscribers' pids, and fans the records out.
+///
+/// Each [`SamplerSubscription`] is itself an [`EnergyCollector`] with an
+/// independent pid set. It receives the records of its own pids. Energy that
+/// the tick attributed to anyone else is folded into the unattributed pid 0,
+/// so per-device totals are the same for every subscriber.
+///
+/// The sampler reads on a fixed cadence, making up for reads that overran
+/// the interval by the [`MissedTickPolicy`] its subscribers set.
+///
+/// [`shared_sampler`] keeps one sampler per collector type, settings and
+/// rate for the whole process, while any group holds it. Monitors, CPU groups built
+/// with [`EnergyGroup::cpu`](crate::energy_group::EnergyGroup::cpu) and the
+/// Python RAPL groups subscribe to it, so they read the counters once per
+/// tick between them. Tracked cgroups are passed to the collector together;
+/// a collector tracking cgroups attributes to them only, so subscribers
+/// tracking cgroups and subscribers tracking pids should not be mixed.
+use crate::collectors::cpu_energy::CpuEnergy;
+use crate::config::CpuEnergyConfig;
+use crate::device_registry::DeviceInfo;
+use crate::energy_group::{DegradedDomain, EnergyCollector, EnergyRecord, MissedTickPolicy};
+use crate::read_schedule::ReadSchedule;
+use crate::utils::cgroup::TrackedCgroup;
+use crate::utils::errors::CollectorError;
+use async_trait::async_trait;
+use std::any::{Any, TypeId};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, Weak};
+use std::time::Duration;
+
+const UNATTRIBUTED_PID: u32 = 0;
+/// Ticks kept for a subscriber that stopped draining, e.g. a group that was
+/// shut down but not dropped.
+const MAX_PENDING_TICKS: usize = 10_000;
+
+/// Reads one collector once per tick on behalf of all its subscribers.
+pub struct SharedSampler<C: EnergyCollector> {
+    collector: C,
+    interval: Duration,
+    /// The policy a subscriber last set
+    missed_ticks: Mutex<MissedTickPolicy>,
+    subscribers: Mutex<Vec<Weak<Subscriber>>>,
+    /// Whether the background tick task has been spawned
+    started: AtomicBool,
+    /// Whether the last tick found no subscribers, so counter baselines are
+    /// stale and the next read must be discarded
+    idle: AtomicBool,
+    /// Bumped whenever rediscovery changes the collector's devices
+    device_generation: AtomicU64,
+}
+
+#[derive(Default)]
+struct Subscriber {
+    pids: Mutex<HashSet<u32>>,
+    cgroups: Mutex<Vec<TrackedCgroup>>,
+    /// Set once the subscriber first collects; ticks are queued from then on
+    active: AtomicBool,
+    pending: Mutex<VecDeque<Vec<EnergyRecord>>>,
+}
+
+impl<C: EnergyCollector> SharedSampler<C> {
+    /// Share `collector`, reading it at `rate` Hz once the first subscriber
+    /// starts collecting.
+    pub fn new(collector: C, rate: f64) -> Arc<Self> {
+        Arc::new(Self {
+            collector,
+            interval: Duration::from_secs_f64(1.0 / rate),
+            missed_ticks: Mutex::new(MissedTickPolicy::default()),
+            subscribers: Mutex::new(Vec::new()),
+            started: AtomicBool::new(false),
+            idle: AtomicBool::new(true),
+            device_generation: AtomicU64::new(0),
+        })
+    }
+
+    /// The shared collector
+    pub fn collector(&self) -> &C {
+        &self.collector
+    }
+
+    /// Add a subscriber with an empty pid set.
+    pub fn subscribe(self: &Arc<Self>) -> SamplerSubscription<C> {
+        let subscriber = Arc::new(Subscriber::default());
+        self.subscribers
+            .lock()
+            .unwrap()
+            .push(Arc::downgrade(&subscriber));
+        SamplerSubscription {
+            sampler: Arc::clone(self),
+            subscriber,
+            seen_generation: AtomicU64::new(self.device_generation.load(Ordering::SeqCst)),
+        }
+    }
+
+    /// Number of live subscriptions
+    pub fn subscriber_count(&self) -> usize {
+        self.live_subscribers().len()
+    }
+
+    fn live_subscribers(&self) -> Vec<Arc<Subscriber>> {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        subscribers.retain(|subscriber| subscriber.strong_count() > 0);
+        subscribers.iter().filter_map(Weak::upgrade).collect()
+    }
+
+    /// Read the collector once and queue the records for every active
+    /// subscriber.
+    async fn tick(&self) -> Result<(), CollectorError> {
+        let subscribers: Vec<_> = self
+            .live_subscribers()
+            .into_iter()
+            .filter(|subscriber| subscriber.active.load(Ordering::SeqCst))
+            .collect();
+        if subscribers.is_empty() {
+            self.idle.store(true, Ordering::SeqCst);
+            return Ok(());
+        }
+
+        let mut pids: Vec<u32> = subscribers
+            .iter()
+            .flat_map(|subscriber| subscriber.pids.lock().unwrap().clone())
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        pids.sort_unstable();
+        let mut cgroups: Vec<TrackedCgroup> = Vec::new();
+        for subscriber in &subscribers {
+            for cgroup in subscriber.cgroups.lock().unwrap().iter() {
+                if !cgroups.iter().any(|other| other.pid == cgroup.pid) {
+                    cgroups.push(cgroup.clone());
+                }
+            }
+        }
+        self.collector.set_tracked_pids(pids);
+        self.collector.set_tracked_cgroups(cgroups);
+        let records = self.collector.get_energy_trace().await?;
+
+        // Deltas after an idle spell span the whole spell; use the read as
+        // the new baseline only.
+        if self.idle.swap(false, Ordering::SeqCst) {
+            return Ok(());
+        }
+        for subscriber in subscribers {
+            let records = records_for(&subscriber.own_pids(), &records);
+            let mut pending = subscriber.pending.lock().unwrap();
+            if pending.len() == MAX_PENDING_TICKS {
+                pending.pop_front();
+            }
+            pending.push_back(records);
+        }
+        Ok(())
+    }
+
+    /// Spawn the tick task on first use. It ends once the sampler is dropped,
+    /// or with the runtime it was spawned on, after which the next collecting
+    /// subscriber spawns it again.
+    fn ensure_started(self: &Arc<Self>) {
+        if self.started.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let sampler = Arc::downgrade(self);
+        let mut ticker = tokio::time::interval(self.interval);
+        tokio::spawn(async move {
+            let _running = TickTask(sampler.clone());
+            // The first tick is immediate; the first read follows one
+            // interval after the subscriber's own first read.
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                let Some(sampler) = sampler.upgrade() else {
+                    break;
+                };
+                ticker.set_missed_tick_behavior((*sampler.missed_ticks.lock().unwrap()).into());
+                if let Err(e) = sampler.tick().await {
+                    log::error!("Shared sampler failed to collect: {}", e);
+                }
+            }
+        });
+    }
+}
+
+/// Marks the sampler's tick task as stopped when the task is dropped.
+struct TickTask<C: EnergyCollector>(Weak<SharedSampler<C>>);
+
+impl<C: EnergyCollector> Drop for TickTask<C> {
+    fn drop(&mut self) {
+        if let Some(sampler) = self.0.upgrade() {
+            sampler.started.store(false, Ordering::SeqCst);
+        }
+    }
+}
+
+impl Subscriber {
+    /// Pids whose records are the subscriber's own: its tracked pids and
+    /// the pids standing in for its tracked cgroups.
+    fn own_pids(&self) -> HashSet<u32> {
+        let mut pids = self.pids.lock().unwrap().clone();
+        pids.extend(self.cgroups.lock().unwrap().iter().map(|cgroup| cgroup.pid));
+        pids
+    }
+}
+
+/// The records of `records` as seen by a subscriber tracking `pids`: records
+/// of its own pids unchanged, all other energy as one unattributed record per
+/// device and time.
+fn records_for(pids: &HashSet<u32>, records: &[EnergyRecord]) -> Vec<EnergyRecord> {
+    let mut own = Vec::new();
+    let mut unattributed: Vec<EnergyRecord> = Vec::new();
+    for record in records {
+        if record.pid != UNATTRIBUTED_PID && pids.contains(&record.pid) {
+            own.push(record.clone());
+            continue;
+        }
+        match unattributed.iter_mut().find(|other| {
+            other.device == record.device && other.monotonic_ns == record.monotonic_ns
+        }) {
+            Some(other) => other.energy += record.energy,
+            None => unattributed.push(EnergyRecord {
+                pid: UNATTRIBUTED_PID,
+                ..record.clone()
+            }),
+        }
+    }
+    own.extend(
+        unattributed
+            .into_iter()
+            .filter(|record| record.energy > 0.0),
+    );
+    own
+}
+
+/// One group's view of a [`SharedSampler`].
+pub struct SamplerSubscription<C: EnergyCollector> {
+    sampler: Arc<SharedSampler<C>>,
+    subscriber: Arc<Subscriber>,
+    seen_generation: AtomicU64,
+}
+
+impl<C: EnergyCollector> SamplerSubscription<C> {
+    /// The sampler this subscription reads from
+    pub fn sampler(&self) -> &Arc<SharedSampler<C>> {
+        &self.sampler
+    }
+
+    fn take_pending(&self) -> Vec<EnergyRecord> {
+        self.subscriber
+            .pending
+            .lock()
+            .unwrap()
+            .drain(..)
+            .flatten()
+            .collect()
+    }
+}
+
+#[async_trait]
+impl<C: EnergyCollector> EnergyCollector for SamplerSubscription<C> {
+    fn set_tracked_pids(&self, pids: Vec<u32>) {
+        *self.subscriber.pids.lock().unwrap() = pids.into_iter().collect();
+    }
+
+    fn set_tracked_cgroups(&self, cgroups: Vec<TrackedCgroup>) {
+        *self.subscriber.cgroups.lock().unwrap() = cgroups;
+    }
+
+    /// Sets the schedule of the shared collector, for every subscriber.
+    fn set_read_schedule(&self, schedule: ReadSchedule) {
+        self.sampler.collector.set_read_schedule(schedule);
+    }
+
+    /// Records of all ticks since the previous call.
+    async fn get_energy_trace(&self) -> Result<Vec<EnergyRecord>, CollectorError> {
+        self.subscriber.active.store(true, Ordering::SeqCst);
+        self.sampler.ensure_started();
+        Ok(self.take_pending())
+    }
+
+    /// Sets the policy of the shared tick task, for every subscriber.
+    fn set_missed_tick_policy(&self, policy: MissedTickPolicy) {
+        *self.sampler.missed_ticks.lock().unwrap() = policy;
+    }
+
+    fn devices(&self) -> Vec<DeviceInfo> {
+        self.sampler.collector.devices()
+    }
+
+    fn degraded_domains(&self) -> Vec<DegradedDomain> {
+        self.sampler.collector.degraded_domains()
+    }
+
+    fn rediscover(&self) -> bool {
+        let generation = &self.sampler.device_generation;
+        if self.sampler.collector.rediscover() {
+            generation.fetch_add(1, Ordering::SeqCst);
+        }
+        let current = generation.load(Ordering::SeqCst);
+        self.seen_generation.swap(current, Ordering::SeqCst) != current
+    }
+
+    fn is_available() -> bool {
+        C::is_available()
+    }
+}
+
+/// Samplers handed out by [`shared_sampler`], by collector type, settings
+/// and rate.
+type SamplerRegistry = HashMap<(TypeId, String, u64), Weak<dyn Any + Send + Sync>>;
+
+/// The process-wide sampler of `C` collectors configured by `settings`, any
+/// rendering of what configures them, reading at `rate` Hz. The first call
+/// creates it from `open()`; later calls with the same settings and rate
+/// return the same sampler while anyone holds it. Another rate gets a
+/// sampler, and collector, of its own.
+pub fn shared_sampler<C: EnergyCollector>(
+    settings: &str,
+    rate: f64,
+    open: impl FnOnce() -> C,
+) -> Arc<SharedSampler<C>> {
+    static SAMPLERS: OnceLock<Mutex<SamplerRegistry>> = OnceLock::new();
+    let mut samplers = SAMPLERS.get_or_init(Mutex::default).lock().unwrap();
+    samplers.retain(|_, sampler| sampler.strong_count() > 0);
+    let key = (TypeId::of::<C>(), settings.to_string(), rate.to_bits());
+    if let Some(sampler) = samplers.get(&key).and_then(Weak::upgrade)
+        && let Ok(sampler) = sampler.downcast::<SharedSampler<C>>()
+    {
+        return sampler;
+    }
+    let sampler = SharedSampler::new(open(), rate);
+    let shared: Arc<dyn Any + Send + Sync> = sampler.clone();
+    samplers.insert(key, Arc::downgrade(&shared));
+    sampler
+}
+
+/// The process-wide sampler of CPU energy collectors built from `config`;
+/// see [`shared_sampler`].
+pub fn shared_cpu_sampler(config: &CpuEnergyConfig, rate: f64) -> Arc<SharedSampler<CpuEnergy>> {
+    shared_sampler(&format!("{config:?}"), rate, || CpuEnergy::new(config))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::energy_group::EnergyGroup;
+    use crate::utils::time::MONOTONIC_COLUMN;
+    use polars::prelude::DataType;
+    use std::sync::atomic::AtomicUsize;
+
+    /// Reports 1 J per tracked pid and 2 J unattributed on one device.
+    #[derive(Default)]
+    struct CountingCollector {
+        pids: Mutex<Vec<u32>>,
+        reads: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl EnergyCollector for CountingCollector {
+        fn set_tracked_pids(&self, pids: Vec<u32>) {
+            *self.pids.lock().unwrap() = pids;
+        }
+
+        async fn get_energy_trace(&self) -> Result<Vec<EnergyRecord>, CollectorError> {
+            let read = self.reads.fetch_add(1, Ordering::SeqCst) as i64;
+            let record = |pid, energy| EnergyRecord {
+                pid,
+                timestamp_us: 1_700_000_000_000_000 + read,
+                monotonic_ns: read,
+                device: "test:device".to_string(),
+                energy,
+                raw_counter: None,
+            };
+            let pids = self.pids.lock().unwrap().clone();
+            Ok(pids
+                .into_iter()
+                .map(|pid| record(pid, 1.0))
+                .chain([record(UNATTRIBUTED_PID, 2.0)])
+                .collect())
+        }
+
+        fn is_available() -> bool {
+            true
+        }
+    }
+
+    fn energy_by_pid(records: &[EnergyRecord]) -> Vec<(u32, f64)> {
+        records.iter().map(|r| (r.pid, r.energy)).collect()
+    }
+
+    #[tokio::test]
+    async fn one_read_per_tick_is_fanned_out_to_every_subscriber() {
+        let sampler = SharedSampler::new(CountingCollector::default(), 0.001);
+        let first = sampler.subscribe();
+        let second = sampler.subscribe();
+        first.set_tracked_pids(vec![10, 11]);
+        second.set_tracked_pids(vec![20]);
+        assert!(first.get_energy_trace().await.unwrap().is_empty());
+        assert!(second.get_energy_trace().await.unwrap().is_empty());
+
+        // The first read only establishes the baseline.
+        sampler.tick().await.unwrap();
+        sampler.tick().await.unwrap();
+        sampler.tick().await.unwrap();
+        assert_eq!(sampler.collector().reads.load(Ordering::SeqCst), 3);
+
+        let first_records = first.get_energy_trace().await.unwrap();
+        assert_eq!(
+            energy_by_pid(&first_records),
+            [
+                (10, 1.0),
+                (11, 1.0),
+                (0, 3.0),
+                (10, 1.0),
+                (11, 1.0),
+                (0, 3.0)
+            ]
+        );
+        let second_records = second.get_energy_trace().await.unwrap();
+        assert_eq!(
+            energy_by_pid(&second_records),
+            [(20, 1.0), (0, 4.0), (20, 1.0), (0, 4.0)]
+        );
+        assert!(first.get_energy_trace().await.unwrap().is_empty());
+    }
+
+    fn read_indices(group: &EnergyGroup<SamplerSubscription<CountingCollector>>) -> Vec<i64> {
+        let monotonic = group
+            .energy_trace()
+            .column(MONOTONIC_COLUMN)
+            .unwrap()
+            .cast(&DataType::Int64)
+            .unwrap();
+        monotonic.i64().unwrap().into_no_null_iter().collect()
+    }
+
+    #[tokio::test]
+    async fn groups_of_one_shared_sampler_get_the_same_reads() {
+        // A rate slow enough that only the explicit ticks below read.
+        let sampler = shared_sampler("groups_share_reads", 0.001, CountingCollector::default);
+        let again = shared_sampler::<CountingCollector>("groups_share_reads", 0.001, || {
+            unreachable!("the sampler is reused")
+        });
+        assert!(Arc::ptr_eq(&sampler, &again));
+        let faster = shared_sampler("groups_share_reads", 0.002, CountingCollector::default);
+        assert!(!Arc::ptr_eq(&sampler, &faster));
+        let mut first = EnergyGroup::new(sampler.subscribe(), 200.0, Some(1));
+        let mut second = EnergyGroup::new(again.subscribe(), 200.0, Some(1));
+        first.set_tracked_pids(vec![10]);
+        second.set_tracked_pids(vec![20]);
+
+        first.commence().await.unwrap();
+        second.commence().await.unwrap();
+        // The first read only establishes the baseline.
+        for _ in 0..3 {
+            sampler.tick().await.unwrap();
+        }
+        let deadline = std::time::Instant::now() + Duration::from_secs(10);
+        while (first.energy_trace().height() < 4 || second.energy_trace().height() < 4)
+            && std::time::Instant::now() < deadline
+        {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+            first.poll_data();
+            second.poll_data();
+        }
+        first.shutdown().await.unwrap();
+        second.shutdown().await.unwrap();
+
+        let first_reads: HashSet<i64> = read_indices(&first).into_iter().collect();
+        let second_reads: HashSet<i64> = read_indices(&second).into_iter().collect();
+        assert_eq!(first_reads, HashSet::from([1, 2]));
+        assert_eq!(second_reads, first_reads);
+        let pids = |group: &EnergyGroup<_>| -> HashSet<u32> {
+            let pids = group.energy_trace().column("pid").unwrap();
+            pids.u32().unwrap().into_no_null_iter().collect()
+        };
+        assert_eq!(pids(&first), HashSet::from([10, UNATTRIBUTED_PID]));
+        assert_eq!(pids(&second), HashSet::from([20, UNATTRIBUTED_PID]));
+    }
+
+    #[tokio::test]
+    async fn subscribers_set_the_missed_tick_policy_of_the_sampler() {
+        let sampler = SharedSampler::new(CountingCollector::default(), 0.001);
+        let subscription = sampler.subscribe();
+        assert_eq!(
+            *sampler.missed_ticks.lock().unwrap(),
+            MissedTickPolicy::Skip
+        );
+
+        let mut group = EnergyGroup::new(subscription, 1.0, Some(1));
+        group.set_missed_tick_policy(MissedTickPolicy::Burst);
+
+        assert_eq!(
+            *sampler.missed_ticks.lock().unwrap(),
+            MissedTickPolicy::Burst
+        );
+    }
+
+    #[tokio::test]
+    async fn dropped_subscriptions_stop_receiving_and_pause_reads() {
+        let sampler = SharedSampler::new(CountingCollector::default(), 0.001);
+        let subscription = sampler.subscribe();
+        subscription.set_tracked_pids(vec![10]);
+        subscription.get_energy_trace().await.unwrap();
+        sampler.tick().await.unwrap();
+        assert_eq!(sampler.subscriber_count(), 1);
+
+        drop(subscription);
+        sampler.tick().await.unwrap();
+        assert_eq!(sampler.subscriber_count(), 0);
+        assert_eq!(sampler.collector().reads.load(Ordering::SeqCst), 1);
+    }
+}
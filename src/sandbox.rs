@@ -0,0 +1,464 @@
+/// Sandbox Module
+///
+/// Optional post-initialization hardening for the headless exporter. EMT is
+/// often started with elevated privileges so it can read RAPL counters; once
+/// collectors are discovered and the listener is bound, the process only needs
+/// read access to a handful of pseudo filesystems and write access to its
+/// output directories. [`SandboxPolicy::for_monitor`] derives both from the
+/// enabled collectors and the trace and write-ahead log directories.
+///
+/// Two layers are applied:
+/// - Landlock restricts filesystem access to the configured read/write paths.
+/// - A seccomp filter rejects syscalls the monitor never needs at runtime
+///   (module loading, mounts, ptrace, exec, ...) with `EPERM`.
+///
+/// Both layers only affect the calling thread and threads spawned afterwards
+/// (Landlock additionally requests all-thread enforcement where the kernel
+/// supports it), so [`apply`] must run before the async runtime starts its
+/// worker threads.
+use crate::config::{BmcSource, EmtConfig, SandboxConfig};
+use crate::run::CollectorKind;
+use std::path::{Path, PathBuf};
+
+/// Pseudo filesystem paths the collectors and process discovery read from.
+const DEFAULT_READ_PATHS: &[&str] = &[
+    "/proc",
+    "/sys/class/powercap",
+    "/sys/devices/virtual/powercap",
+    "/sys/devices/system/cpu",
+    "/sys/fs/cgroup",
+    "/sys/bus/event_source",
+    "/etc/passwd",
+    "/etc/group",
+    "/etc/localtime",
+];
+
+/// Paths a collector reads beyond the defaults. Sysfs class and bus
+/// directories hold symlinks into `/sys/devices`, which Landlock checks
+/// once they are resolved.
+fn collector_read_paths(kind: CollectorKind) -> &'static [&'static str] {
+    match kind {
+        CollectorKind::Cpu => &["/dev/cpu"],
+        CollectorKind::Nvidia => &["/sys/bus/pci", "/sys/devices", "/proc/driver/nvidia"],
+        CollectorKind::Amd | CollectorKind::Intel => {
+            &["/sys/class/drm", "/sys/class/hwmon", "/sys/devices"]
+        }
+        CollectorKind::Jetson => &["/sys/bus/i2c", "/sys/class/hwmon", "/sys/devices"],
+        CollectorKind::Network => &["/sys/class/net", "/sys/devices"],
+        CollectorKind::Storage => &["/sys/block", "/sys/class/block", "/sys/devices"],
+        CollectorKind::Meters => &[],
+    }
+}
+
+/// Programs and shared libraries a spawned collector command loads.
+const EXEC_READ_PATHS: &[&str] = &[
+    "/usr",
+    "/bin",
+    "/sbin",
+    "/lib",
+    "/lib64",
+    "/etc/ld.so.cache",
+];
+
+/// Syscalls rejected once the sandbox is active.
+#[cfg(target_os = "linux")]
+const DENIED_SYSCALLS: &[i64] = &[
+    libc::SYS_ptrace,
+    libc::SYS_process_vm_writev,
+    libc::SYS_mount,
+    libc::SYS_umount2,
+    libc::SYS_pivot_root,
+    libc::SYS_chroot,
+    libc::SYS_unshare,
+    libc::SYS_setns,
+    libc::SYS_reboot,
+    libc::SYS_kexec_load,
+    libc::SYS_init_module,
+    libc::SYS_finit_module,
+    libc::SYS_delete_module,
+    libc::SYS_swapon,
+    libc::SYS_swapoff,
+    libc::SYS_bpf,
+    libc::SYS_add_key,
+    libc::SYS_request_key,
+    libc::SYS_keyctl,
+];
+
+/// Exec syscalls, rejected unless the policy allows spawning commands.
+#[cfg(target_os = "linux")]
+const EXEC_SYSCALLS: &[i64] = &[libc::SYS_execve, libc::SYS_execveat];
+
+/// Errors that can occur while applying the sandbox.
+#[derive(Debug, thiserror::Error)]
+pub enum SandboxError {
+    #[error("Landlock error: {0}")]
+    Landlock(String),
+    #[error("Seccomp error: {0}")]
+    Seccomp(String),
+    #[error("Sandboxing is not supported on this platform")]
+    Unsupported,
+}
+
+/// How much of the requested filesystem restriction the kernel enforced.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilesystemEnforcement {
+    Full,
+    Partial,
+    None,
+}
+
+/// Result of applying the sandbox.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SandboxStatus {
+    pub filesystem: FilesystemEnforcement,
+    pub denied_syscalls: usize,
+}
+
+/// Resolved set of paths and permissions enforced by the sandbox.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SandboxPolicy {
+    /// Paths (and everything beneath them) the process may read.
+    pub read_paths: Vec<PathBuf>,
+    /// Paths (and everything beneath them) the process may read and write.
+    pub write_paths: Vec<PathBuf>,
+    /// Whether `execve`/`execveat` remain allowed.
+    pub allow_exec: bool,
+}
+
+impl SandboxPolicy {
+    /// Build a policy from config, adding the default collector read paths.
+    ///
+    /// Paths that do not exist on this host are dropped so a missing optional
+    /// interface (for example no cgroup v2 mount) does not fail the sandbox.
+    pub fn from_config(config: &SandboxConfig) -> Self {
+        let read_paths = DEFAULT_READ_PATHS
+            .iter()
+            .map(PathBuf::from)
+            .chain(config.read_paths.iter().map(PathBuf::from))
+            .collect();
+
+        Self {
+            read_paths,
+            write_paths: config.write_paths.iter().map(PathBuf::from).collect(),
+            allow_exec: config.allow_exec,
+        }
+        .existing_only()
+    }
+
+    /// Build the policy for a monitor running with `config`: the `sandbox`
+    /// settings plus the paths the enabled collectors read, write access to
+    /// the trace and write-ahead log directories, and exec when a collector
+    /// runs a command.
+    pub fn for_monitor(config: &EmtConfig) -> Self {
+        let mut policy = Self::from_config(&config.sandbox);
+        let enabled = |kind| {
+            config.collection.collectors.is_empty() || config.collection.collectors.contains(&kind)
+        };
+        for kind in CollectorKind::ALL.into_iter().filter(|kind| enabled(*kind)) {
+            policy
+                .read_paths
+                .extend(collector_read_paths(kind).iter().map(PathBuf::from));
+        }
+        // NVML opens its device nodes for writing.
+        if enabled(CollectorKind::Nvidia)
+            && let Ok(entries) = std::fs::read_dir("/dev")
+        {
+            policy.write_paths.extend(
+                entries
+                    .flatten()
+                    .filter(|entry| entry.file_name().to_string_lossy().starts_with("nvidia"))
+                    .map(|entry| entry.path()),
+            );
+        }
+
+        let mut programs = Vec::new();
+        if enabled(CollectorKind::Meters) {
+            programs.extend(
+                config
+                    .exec_collectors
+                    .iter()
+                    .filter_map(|meter| meter.command.first()),
+            );
+            programs.extend(
+                config
+                    .bmc_collectors
+                    .iter()
+                    .filter_map(|bmc| match &bmc.source {
+                        BmcSource::Ipmi(ipmi) => Some(&ipmi.ipmitool),
+                        BmcSource::Redfish(_) => None,
+                    }),
+            );
+        }
+        if !programs.is_empty() {
+            policy.allow_exec = true;
+            policy
+                .read_paths
+                .extend(EXEC_READ_PATHS.iter().map(PathBuf::from));
+            policy.read_paths.extend(
+                programs
+                    .into_iter()
+                    .filter_map(|program| program_path(program)),
+            );
+        }
+
+        let output_dirs = [
+            config.collection.trace_dir.as_deref(),
+            config.collection.wal_dir.as_deref(),
+        ];
+        for dir in output_dirs.into_iter().flatten() {
+            // Landlock only grants access to paths that exist.
+            if let Err(e) = std::fs::create_dir_all(dir) {
+                log::warn!("Sandbox cannot grant write access to {}: {}", dir, e);
+            }
+            policy.write_paths.push(PathBuf::from(dir));
+        }
+        policy.existing_only()
+    }
+
+    /// Grant write access to the directory that will contain `output_path`.
+    pub fn allow_output_file(&mut self, output_path: &Path) {
+        let parent = match output_path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        if !self.write_paths.contains(&parent) {
+            self.write_paths.push(parent);
+        }
+    }
+
+    fn existing_only(mut self) -> Self {
+        self.read_paths.retain(|path| path.exists());
+        self.write_paths.retain(|path| path.exists());
+        self.read_paths.sort();
+        self.read_paths.dedup();
+        self.write_paths.sort();
+        self.write_paths.dedup();
+        self
+    }
+}
+
+/// Executable `program` resolves to, searched on `PATH` when it has no
+/// directory.
+fn program_path(program: &str) -> Option<PathBuf> {
+    if program.contains('/') {
+        return Some(PathBuf::from(program));
+    }
+    std::env::split_paths(&std::env::var_os("PATH")?)
+        .map(|dir| dir.join(program))
+        .find(|path| path.is_file())
+}
+
+/// Restrict the current process according to `policy`.
+#[cfg(target_os = "linux")]
+pub fn apply(policy: &SandboxPolicy) -> Result<SandboxStatus, SandboxError> {
+    let filesystem = apply_landlock(policy)?;
+    let denied_syscalls = apply_seccomp(policy)?;
+    Ok(SandboxStatus {
+        filesystem,
+        denied_syscalls,
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn apply(_policy: &SandboxPolicy) -> Result<SandboxStatus, SandboxError> {
+    Err(SandboxError::Unsupported)
+}
+
+#[cfg(target_os = "linux")]
+fn apply_landlock(policy: &SandboxPolicy) -> Result<FilesystemEnforcement, SandboxError> {
+    use landlock::{
+        ABI, Access, AccessFs, RestrictSelfAttr, Ruleset, RulesetAttr, RulesetCreatedAttr,
+        RulesetStatus, path_beneath_rules,
+    };
+
+    let abi = ABI::V2;
+    let to_error = |err: landlock::RulesetError| SandboxError::Landlock(err.to_string());
+
+    let status = Ruleset::default()
+        .handle_access(AccessFs::from_all(abi))
+        .map_err(to_error)?
+        .create()
+        .map_err(to_error)?
+        .add_rules(path_beneath_rules(
+            &policy.read_paths,
+            AccessFs::from_read(abi),
+        ))
+        .map_err(to_error)?
+        .add_rules(path_beneath_rules(
+            &policy.write_paths,
+            AccessFs::from_all(abi),
+        ))
+        .map_err(to_error)?
+        .all_threads(true)
+        .map_err(to_error)?
+        .restrict_self()
+        .map_err(to_error)?;
+
+    Ok(match status.ruleset {
+        RulesetStatus::FullyEnforced => FilesystemEnforcement::Full,
+        RulesetStatus::PartiallyEnforced => FilesystemEnforcement::Partial,
+        RulesetStatus::NotEnforced => FilesystemEnforcement::None,
+    })
+}
+
+#[cfg(target_os = "linux")]
+fn denied_syscalls(policy: &SandboxPolicy) -> Vec<i64> {
+    let mut syscalls = DENIED_SYSCALLS.to_vec();
+    if !policy.allow_exec {
+        syscalls.extend_from_slice(EXEC_SYSCALLS);
+    }
+    syscalls
+}
+
+#[cfg(target_os = "linux")]
+fn seccomp_program(policy: &SandboxPolicy) -> Result<seccompiler::BpfProgram, SandboxError> {
+    use seccompiler::{SeccompAction, SeccompFilter, TargetArch};
+    use std::collections::BTreeMap;
+
+    let arch = TargetArch::try_from(std::env::consts::ARCH)
+        .map_err(|err| SandboxError::Seccomp(err.to_string()))?;
+    let rules = denied_syscalls(policy)
+        .into_iter()
+        .map(|syscall| (syscall, Vec::new()))
+        .collect::<BTreeMap<_, _>>();
+
+    SeccompFilter::new(
+        rules,
+        SeccompAction::Allow,
+        SeccompAction::Errno(libc::EPERM as u32),
+        arch,
+    )
+    .and_then(seccompiler::BpfProgram::try_from)
+    .map_err(|err| SandboxError::Seccomp(err.to_string()))
+}
+
+#[cfg(target_os = "linux")]
+fn apply_seccomp(policy: &SandboxPolicy) -> Result<usize, SandboxError> {
+    let program = seccomp_program(policy)?;
+    seccompiler::apply_filter_all_threads(&program)
+        .map_err(|err| SandboxError::Seccomp(err.to_string()))?;
+    Ok(denied_syscalls(policy).len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn policy_drops_paths_missing_on_this_host() {
+        let config = SandboxConfig {
+            enabled: true,
+            read_paths: vec!["/nonexistent/emt/read".to_string()],
+            write_paths: vec!["/nonexistent/emt/write".to_string()],
+            allow_exec: false,
+        };
+
+        let policy = SandboxPolicy::from_config(&config);
+
+        assert!(policy.read_paths.iter().all(|path| path.exists()));
+        assert!(policy.write_paths.is_empty());
+        assert!(policy.read_paths.contains(&PathBuf::from("/proc")));
+    }
+
+    #[test]
+    fn policy_lists_each_path_once() {
+        let config = SandboxConfig {
+            enabled: true,
+            read_paths: vec!["/proc".to_string(), "/sys".to_string(), "/proc".to_string()],
+            write_paths: Vec::new(),
+            allow_exec: false,
+        };
+
+        let policy = SandboxPolicy::from_config(&config);
+
+        let proc_entries = policy
+            .read_paths
+            .iter()
+            .filter(|path| *path == Path::new("/proc"))
+            .count();
+        assert_eq!(proc_entries, 1);
+    }
+
+    #[test]
+    fn monitor_policy_grants_trace_and_wal_directories() {
+        let dir = TempDir::new().unwrap();
+        let traces = dir.path().join("traces");
+        let wal = dir.path().join("wal");
+        let mut config = EmtConfig::default();
+        config.collection.trace_dir = Some(traces.to_string_lossy().into_owned());
+        config.collection.wal_dir = Some(wal.to_string_lossy().into_owned());
+
+        let policy = SandboxPolicy::for_monitor(&config);
+
+        assert!(policy.write_paths.contains(&traces));
+        assert!(policy.write_paths.contains(&wal));
+        assert!(!policy.allow_exec);
+    }
+
+    #[test]
+    fn monitor_policy_allows_exec_for_command_collectors() {
+        let mut config = EmtConfig::default();
+        config
+            .exec_collectors
+            .push(crate::config::ExecCollectorConfig {
+                name: "pdu".to_string(),
+                command: vec!["/bin/sh".to_string(), "-c".to_string(), "echo".to_string()],
+                interval_secs: 1.0,
+            });
+
+        let policy = SandboxPolicy::for_monitor(&config);
+        assert!(policy.allow_exec);
+
+        config.collection.collectors = vec![CollectorKind::Cpu];
+        assert!(!SandboxPolicy::for_monitor(&config).allow_exec);
+    }
+
+    #[test]
+    fn gpu_collectors_read_hwmon_through_sys_devices() {
+        for kind in [
+            CollectorKind::Amd,
+            CollectorKind::Intel,
+            CollectorKind::Jetson,
+        ] {
+            let paths = collector_read_paths(kind);
+            assert!(paths.contains(&"/sys/class/hwmon"));
+            assert!(paths.contains(&"/sys/devices"));
+        }
+        assert!(collector_read_paths(CollectorKind::Cpu).contains(&"/dev/cpu"));
+    }
+
+    #[test]
+    fn allow_output_file_grants_parent_directory() {
+        let dir = TempDir::new().unwrap();
+        let mut policy = SandboxPolicy::default();
+
+        policy.allow_output_file(&dir.path().join("snapshot.json"));
+        policy.allow_output_file(&dir.path().join("other.json"));
+        policy.allow_output_file(Path::new("relative.json"));
+
+        assert_eq!(
+            policy.write_paths,
+            vec![dir.path().to_path_buf(), PathBuf::from(".")]
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn exec_is_denied_unless_allowed() {
+        let mut policy = SandboxPolicy::default();
+        assert!(denied_syscalls(&policy).contains(&libc::SYS_execve));
+
+        policy.allow_exec = true;
+        assert!(!denied_syscalls(&policy).contains(&libc::SYS_execve));
+        assert!(denied_syscalls(&policy).contains(&libc::SYS_ptrace));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn seccomp_program_compiles_for_host_arch() {
+        let program = seccomp_program(&SandboxPolicy::default()).unwrap();
+
+        assert!(!program.is_empty());
+    }
+}
@@ -0,0 +1,580 @@
+/// Export Schema Module
+///
+/// Every export EMT writes (trace CSV files, JSON reports and snapshots, and
+/// the `emt._rust` extension module) carries an explicit schema version so
+/// downstream tooling can detect layout changes instead of silently misreading
+/// columns. Readers accept any version up to [`SCHEMA_VERSION`] and migrate
+/// older data forward; exports written before versioning existed are treated
+/// as [`LEGACY_SCHEMA_VERSION`].
+///
+/// Version history:
+/// - 1: unversioned exports (`pid,timestamp,device,energy` traces).
+/// - 2: explicit version marker; snapshots gain the `auxiliary` device map.
+/// - 3: trace timestamps are RFC 3339 and traces gain `monotonic_ns`;
+///   snapshots gain `time`, the RFC 3339 rendering of `timestamp`.
+/// - 4: trace rows reference devices by `device_id`; device names and
+///   metadata live in a `devices.csv` table next to the segments.
+/// - 5: trace timestamps have microsecond precision and are taken per device
+///   read.
+use crate::device_registry::{DEVICE_ID_COLUMN, DeviceRegistry};
+use crate::encryption::{ENCRYPTED_EXTENSION, EncryptionError, TraceCipher, is_encrypted};
+use crate::energy_group::{
+    CO2_COLUMN, CUMULATIVE_ENERGY_COLUMN, ORIGINAL_ENERGY_COLUMN, QUALITY_COLUMN,
+    RAW_COUNTER_COLUMN, SAMPLE_DURATION_COLUMN,
+};
+use crate::regions::Region;
+use crate::utils::time::{
+    ExportTimezone, MONOTONIC_COLUMN, RecordTime, TIMESTAMP_COLUMN, monotonic_dtype,
+    parse_export_timestamp_micros, timestamp_dtype,
+};
+use crate::work::WorkEvent;
+use polars::prelude::*;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::fs;
+use std::io::Cursor;
+use std::path::Path;
+
+/// Schema version written by this build.
+pub const SCHEMA_VERSION: u32 = 5;
+/// Version assumed for exports without a version marker.
+pub const LEGACY_SCHEMA_VERSION: u32 = 1;
+/// JSON field and CSV comment key carrying the schema version.
+pub const SCHEMA_VERSION_KEY: &str = "schema_version";
+/// File name of the device table written next to trace segments.
+pub const DEVICES_FILE_NAME: &str = "devices.csv";
+/// File name of the region boundaries written next to trace segments.
+pub const REGIONS_FILE_NAME: &str = "regions.csv";
+/// File name of the peak power events written next to trace segments.
+pub const PEAK_EVENTS_FILE_NAME: &str = "peak_events.csv";
+/// File name of the suspend gaps written next to trace segments.
+pub const SUSPEND_GAPS_FILE_NAME: &str = "suspend_gaps.csv";
+/// File name of the work reports written next to trace segments.
+pub const WORK_FILE_NAME: &str = "work.csv";
+/// File name of the host description written into trace directories.
+pub const HOST_FILE_NAME: &str = "host.json";
+
+/// Errors that can occur while reading versioned exports.
+#[derive(Debug, thiserror::Error)]
+pub enum SchemaError {
+    #[error("schema version {0} is newer than supported version {SCHEMA_VERSION}")]
+    Unsupported(u32),
+    #[error("invalid schema version: {0}")]
+    InvalidVersion(String),
+    #[error("missing column '{0}'")]
+    MissingColumn(&'static str),
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Polars error: {0}")]
+    Polars(#[from] PolarsError),
+    #[error("file is encrypted but no key was provided")]
+    KeyRequired,
+    #[error(transparent)]
+    Encryption(#[from] EncryptionError),
+}
+
+/// Frame magic at the start of every zstd stream.
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// Comment line written at the top of trace CSV files.
+pub fn trace_csv_version_line() -> String {
+    format!("# {SCHEMA_VERSION_KEY}: {SCHEMA_VERSION}\n")
+}
+
+/// Parse the version from the first line of a trace CSV file.
+fn parse_trace_csv_version(first_line: &str) -> Result<u32, SchemaError> {
+    let Some(comment) = first_line.trim().strip_prefix('#') else {
+        return Ok(LEGACY_SCHEMA_VERSION);
+    };
+    let Some((key, value)) = comment.split_once(':') else {
+        return Ok(LEGACY_SCHEMA_VERSION);
+    };
+    if key.trim() != SCHEMA_VERSION_KEY {
+        return Ok(LEGACY_SCHEMA_VERSION);
+    }
+    let version = value
+        .trim()
+        .parse()
+        .map_err(|_| SchemaError::InvalidVersion(value.trim().to_string()))?;
+    check_supported(version)
+}
+
+fn check_supported(version: u32) -> Result<u32, SchemaError> {
+    if version == 0 {
+        return Err(SchemaError::InvalidVersion(version.to_string()));
+    }
+    if version > SCHEMA_VERSION {
+        return Err(SchemaError::Unsupported(version));
+    }
+    Ok(version)
+}
+
+/// Read a trace CSV written by any supported version and migrate it to the
+/// current layout. Zstd-compressed segments (`*.zst`) are decompressed
+/// transparently.
+pub fn read_trace_csv(path: &Path) -> Result<DataFrame, SchemaError> {
+    read_trace_csv_with_cipher(path, None)
+}
+
+/// Like [`read_trace_csv`], additionally opening encrypted segments
+/// (`*.enc`) with `cipher`.
+///
+/// Device names are resolved from the `devices.csv` table in the same
+/// directory when one exists; otherwise the `device` column stays null for
+/// traces that only carry ids.
+pub fn read_trace_csv_with_cipher(
+    path: &Path,
+    cipher: Option<&TraceCipher>,
+) -> Result<DataFrame, SchemaError> {
+    let (frame, version) = read_versioned_csv(path, cipher)?;
+    let mut trace = migrate_trace(frame, version)?;
+
+    let devices_path = path.with_file_name(DEVICES_FILE_NAME);
+    let mut sealed_devices_path = devices_path.clone().into_os_string();
+    sealed_devices_path.push(".");
+    sealed_devices_path.push(ENCRYPTED_EXTENSION);
+    let table = [devices_path, sealed_devices_path.into()]
+        .into_iter()
+        .find(|candidate| candidate.exists());
+    if version >= 4
+        && let Some(table) = table
+    {
+        let devices = read_devices_csv(&table, cipher)?;
+        trace = resolve_device_names(trace, &devices)?;
+    }
+    Ok(trace)
+}
+
+/// Read a `devices.csv` table written alongside trace segments. The
+/// `source` column is null in tables written before it existed.
+pub fn read_devices_csv(
+    path: &Path,
+    cipher: Option<&TraceCipher>,
+) -> Result<DataFrame, SchemaError> {
+    let (frame, _) = read_versioned_csv(path, cipher)?;
+    let columns = [
+        (DEVICE_ID_COLUMN, DataType::UInt32),
+        ("device", DataType::String),
+        ("kind", DataType::String),
+        ("socket", DataType::UInt32),
+        ("vendor", DataType::String),
+        ("model", DataType::String),
+        ("max_power_watts", DataType::Float64),
+    ];
+    let mut table = Vec::with_capacity(columns.len());
+    for (name, dtype) in columns {
+        let column = frame
+            .column(name)
+            .map_err(|_| SchemaError::MissingColumn(name))?;
+        table.push(column.cast(&dtype)?);
+    }
+    table.push(match frame.column("source") {
+        Ok(column) => column.cast(&DataType::String)?,
+        Err(_) => Column::full_null("source".into(), frame.height(), &DataType::String),
+    });
+    Ok(DataFrame::new(table)?)
+}
+
+/// Read a `regions.csv` table written alongside trace segments. Rows
+/// without a start are skipped.
+pub fn read_regions_csv(
+    path: &Path,
+    cipher: Option<&TraceCipher>,
+) -> Result<Vec<Region>, SchemaError> {
+    let (frame, _) = read_versioned_csv(path, cipher)?;
+    let column = |name: &'static str, dtype: DataType| {
+        frame
+            .column(name)
+            .map_err(|_| SchemaError::MissingColumn(name))?
+            .cast(&dtype)
+            .map_err(SchemaError::from)
+    };
+    let names = column("region", DataType::String)?;
+    let starts = column("start", DataType::String)?;
+    let ends = column("end", DataType::String)?;
+    let start_monotonic = column("start_monotonic_ns", DataType::Int64)?;
+    let end_monotonic = column("end_monotonic_ns", DataType::Int64)?;
+
+    let time = |timestamp: Option<&str>, monotonic_ns: Option<i64>| {
+        Some(RecordTime {
+            timestamp_us: parse_export_timestamp_micros(timestamp?)?,
+            monotonic_ns: monotonic_ns?,
+        })
+    };
+    let mut regions = Vec::with_capacity(frame.height());
+    for row in 0..frame.height() {
+        let Some(name) = names.str()?.get(row) else {
+            continue;
+        };
+        let Some(start) = time(starts.str()?.get(row), start_monotonic.i64()?.get(row)) else {
+            continue;
+        };
+        regions.push(Region {
+            name: name.to_string(),
+            start,
+            end: time(ends.str()?.get(row), end_monotonic.i64()?.get(row)),
+        });
+    }
+    Ok(regions)
+}
+
+/// Read a `work.csv` table written alongside trace segments. Rows without
+/// a unit, count or time are skipped.
+pub fn read_work_csv(
+    path: &Path,
+    cipher: Option<&TraceCipher>,
+) -> Result<Vec<WorkEvent>, SchemaError> {
+    let (frame, _) = read_versioned_csv(path, cipher)?;
+    let column = |name: &'static str, dtype: DataType| {
+        frame
+            .column(name)
+            .map_err(|_| SchemaError::MissingColumn(name))?
+            .cast(&dtype)
+            .map_err(SchemaError::from)
+    };
+    let units = column("unit", DataType::String)?;
+    let counts = column("count", DataType::Float64)?;
+    let pids = column("pid", DataType::UInt32)?;
+    let times = column("time", DataType::String)?;
+    let monotonic = column(MONOTONIC_COLUMN, DataType::Int64)?;
+
+    let mut events = Vec::with_capacity(frame.height());
+    for row in 0..frame.height() {
+        let (Some(unit), Some(count), Some(timestamp), Some(monotonic_ns)) = (
+            units.str()?.get(row),
+            counts.f64()?.get(row),
+            times.str()?.get(row),
+            monotonic.i64()?.get(row),
+        ) else {
+            continue;
+        };
+        let Some(timestamp_us) = parse_export_timestamp_micros(timestamp) else {
+            continue;
+        };
+        events.push(WorkEvent {
+            unit: unit.to_string(),
+            count,
+            pid: pids.u32()?.get(row),
+            time: RecordTime {
+                timestamp_us,
+                monotonic_ns,
+            },
+        });
+    }
+    Ok(events)
+}
+
+/// Open, decompress and parse a versioned CSV export.
+fn read_versioned_csv(
+    path: &Path,
+    cipher: Option<&TraceCipher>,
+) -> Result<(DataFrame, u32), SchemaError> {
+    let mut bytes = fs::read(path)?;
+    if is_encrypted(&bytes) {
+        bytes = cipher.ok_or(SchemaError::KeyRequired)?.open(&bytes)?;
+    }
+    if bytes.starts_with(&ZSTD_MAGIC) {
+        bytes = zstd::decode_all(bytes.as_slice())?;
+    }
+    let first_line = bytes
+        .split(|byte| *byte == b'\n')
+        .next()
+        .unwrap_or_default();
+    let version = parse_trace_csv_version(&String::from_utf8_lossy(first_line))?;
+
+    let frame = CsvReadOptions::default()
+        .with_has_header(true)
+        .with_parse_options(CsvParseOptions::default().with_comment_prefix(Some("#")))
+        .into_reader_with_file_handle(Cursor::new(bytes))
+        .finish()?;
+    Ok((frame, version))
+}
+
+/// Bring a trace frame of schema `version` to the current column layout:
+/// `pid | timestamp | device_id | device | energy | monotonic_ns`, followed
+/// by `cumulative_energy` and `raw_counter` when the export has them.
+pub fn migrate_trace(frame: DataFrame, version: u32) -> Result<DataFrame, SchemaError> {
+    check_supported(version)?;
+    // Version 1 -> 2 only introduced the version marker. Version 3 switched
+    // timestamps from Unix milliseconds to RFC 3339 and added `monotonic_ns`,
+    // which older traces lack and get as nulls. Version 4 replaced device
+    // names with ids; older traces get ids local to the file, in order of
+    // first appearance. Version 5 only made timestamps more precise.
+    let column = |name: &'static str| {
+        frame
+            .column(name)
+            .map_err(|_| SchemaError::MissingColumn(name))
+    };
+
+    let timestamps = column(TIMESTAMP_COLUMN)?;
+    let timestamps = match timestamps.str() {
+        Ok(values) => Column::new(
+            TIMESTAMP_COLUMN.into(),
+            values
+                .iter()
+                .map(|value| value.and_then(parse_export_timestamp_micros))
+                .collect::<Vec<_>>(),
+        )
+        .cast(&timestamp_dtype())?,
+        Err(_) => timestamps
+            .cast(&DataType::Int64)?
+            .cast(&DataType::Datetime(
+                TimeUnit::Milliseconds,
+                Some(TimeZone::UTC),
+            ))?
+            .cast(&timestamp_dtype())?,
+    };
+
+    let (device_ids, devices) = if version >= 4 {
+        // Converted exports may carry names next to the ids.
+        let devices = match frame.column("device") {
+            Ok(devices) => devices.cast(&DataType::String)?,
+            Err(_) => Column::full_null("device".into(), frame.height(), &DataType::String),
+        };
+        (column(DEVICE_ID_COLUMN)?.cast(&DataType::UInt32)?, devices)
+    } else {
+        let devices = column("device")?.cast(&DataType::String)?;
+        let mut registry = DeviceRegistry::new();
+        let ids = devices
+            .str()?
+            .iter()
+            .map(|name| name.map(|name| registry.id_or_register(name)))
+            .collect::<Vec<_>>();
+        (Column::new(DEVICE_ID_COLUMN.into(), ids), devices)
+    };
+
+    let monotonic = match frame.column(MONOTONIC_COLUMN) {
+        Ok(column) => column.cast(&DataType::Int64)?,
+        Err(_) => Column::full_null(MONOTONIC_COLUMN.into(), frame.height(), &DataType::Int64),
+    };
+
+    let mut columns = vec![
+        column("pid")?.cast(&DataType::UInt32)?,
+        timestamps,
+        device_ids,
+        devices,
+        column("energy")?.cast(&DataType::Float64)?,
+        monotonic.cast(&monotonic_dtype())?,
+    ];
+    for (name, dtype) in [
+        (CUMULATIVE_ENERGY_COLUMN, DataType::Float64),
+        (RAW_COUNTER_COLUMN, DataType::Float64),
+        (CO2_COLUMN, DataType::Float64),
+        (SAMPLE_DURATION_COLUMN, DataType::Float64),
+        (QUALITY_COLUMN, DataType::String),
+        (ORIGINAL_ENERGY_COLUMN, DataType::Float64),
+    ] {
+        if let Ok(optional) = frame.column(name) {
+            columns.push(optional.cast(&dtype)?);
+        }
+    }
+    Ok(DataFrame::new(columns)?)
+}
+
+/// Fill the `device` column of a migrated trace from a device table.
+fn resolve_device_names(trace: DataFrame, devices: &DataFrame) -> Result<DataFrame, SchemaError> {
+    let ids = devices.column(DEVICE_ID_COLUMN)?.u32()?;
+    let names = devices.column("device")?.str()?;
+    let by_id: HashMap<u32, &str> = ids
+        .iter()
+        .zip(names.iter())
+        .filter_map(|(id, name)| Some((id?, name?)))
+        .collect();
+
+    let resolved = trace
+        .column(DEVICE_ID_COLUMN)?
+        .u32()?
+        .iter()
+        .map(|id| id.and_then(|id| by_id.get(&id).copied()))
+        .collect::<Vec<_>>();
+    let mut trace = trace;
+    trace.with_column(Column::new("device".into(), resolved))?;
+    Ok(trace)
+}
+
+/// Bring a JSON snapshot or report of any supported version to the current
+/// layout, stamping it with [`SCHEMA_VERSION`].
+pub fn migrate_json(value: Value) -> Result<Value, SchemaError> {
+    let Value::Object(mut object) = value else {
+        return Err(SchemaError::InvalidVersion(
+            "expected a JSON object".to_string(),
+        ));
+    };
+    let version = match object.get(SCHEMA_VERSION_KEY) {
+        None => LEGACY_SCHEMA_VERSION,
+        Some(raw) => raw
+            .as_u64()
+            .and_then(|version| u32::try_from(version).ok())
+            .ok_or_else(|| SchemaError::InvalidVersion(raw.to_string()))?,
+    };
+    let version = check_supported(version)?;
+
+    if version < 2 && is_snapshot(&object) {
+        object
+            .entry("auxiliary")
+            .or_insert_with(|| Value::Object(Map::new()));
+    }
+    if version < 3
+        && is_snapshot(&object)
+        && let Some(millis) = object.get("timestamp").and_then(Value::as_i64)
+    {
+        let time = ExportTimezone::Utc.format_millis(millis);
+        object.entry("time").or_insert(Value::String(time));
+    }
+    object.insert(SCHEMA_VERSION_KEY.to_string(), Value::from(SCHEMA_VERSION));
+    Ok(Value::Object(object))
+}
+
+fn is_snapshot(object: &Map<String, Value>) -> bool {
+    object.contains_key("system_total") && object.contains_key("unattributed")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn read_trace_csv_accepts_legacy_and_current_files() {
+        let dir = TempDir::new().unwrap();
+        let legacy = dir.path().join("legacy.csv");
+        let current = dir.path().join("current.csv");
+        let package = DeviceRegistry::new().id_or_register("rapl:socket:0:package");
+        fs::write(
+            &legacy,
+            "pid,timestamp,device,energy\n7,1000,rapl:socket:0:package,1.5\n",
+        )
+        .unwrap();
+        fs::write(
+            &current,
+            format!(
+                "{}pid,timestamp,device_id,energy,monotonic_ns\n7,1970-01-01T00:00:01.000Z,{package},1.5,\n",
+                trace_csv_version_line()
+            ),
+        )
+        .unwrap();
+
+        for path in [legacy, current] {
+            let frame = read_trace_csv(&path).unwrap();
+            assert_eq!(frame.height(), 1);
+            assert_eq!(frame.column("pid").unwrap().dtype(), &DataType::UInt32);
+            assert_eq!(
+                frame.column("timestamp").unwrap().dtype(),
+                &timestamp_dtype()
+            );
+            assert_eq!(frame.column("monotonic_ns").unwrap().null_count(), 1);
+            assert_eq!(
+                frame.column("device_id").unwrap().u32().unwrap().get(0),
+                Some(package)
+            );
+            assert_eq!(
+                frame.column("energy").unwrap().f64().unwrap().get(0),
+                Some(1.5)
+            );
+        }
+    }
+
+    #[test]
+    fn read_trace_csv_resolves_device_names_from_device_table() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("trace_0.csv");
+        let header = trace_csv_version_line();
+        fs::write(
+            &path,
+            format!("{header}pid,timestamp,device_id,energy,monotonic_ns\n7,0,1,2.5,10\n"),
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join(DEVICES_FILE_NAME),
+            format!(
+                "{header}device_id,device,kind,socket,vendor,model,max_power_watts\n\
+                 0,rapl:socket:0:package,cpu_package,0,GenuineIntel,,165.0\n\
+                 1,rapl:system:dram,dram,,GenuineIntel,,\n"
+            ),
+        )
+        .unwrap();
+
+        let frame = read_trace_csv(&path).unwrap();
+        let devices = read_devices_csv(&dir.path().join(DEVICES_FILE_NAME), None).unwrap();
+
+        assert_eq!(
+            frame.column("device").unwrap().str().unwrap().get(0),
+            Some("rapl:system:dram")
+        );
+        assert_eq!(devices.column("socket").unwrap().null_count(), 1);
+        // Written before devices recorded their source
+        assert_eq!(devices.column("source").unwrap().null_count(), 2);
+    }
+
+    #[test]
+    fn read_trace_csv_decompresses_zstd_segments() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("trace_0.csv.zst");
+        let rows = format!(
+            "{}pid,timestamp,device,energy\n7,1000,rapl:system:dram,2.5\n",
+            "# schema_version: 2\n"
+        );
+        fs::write(&path, zstd::encode_all(rows.as_bytes(), 3).unwrap()).unwrap();
+
+        let frame = read_trace_csv(&path).unwrap();
+
+        assert_eq!(
+            frame.column("device").unwrap().str().unwrap().get(0),
+            Some("rapl:system:dram")
+        );
+    }
+
+    #[test]
+    fn read_trace_csv_opens_encrypted_segments_with_key() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("trace_0.csv.zst.enc");
+        let cipher = TraceCipher::from_hex_key(&"ab".repeat(32)).unwrap();
+        let rows = "pid,timestamp,device,energy\n7,1000,nvidia:gpu:0,4.0\n";
+        let compressed = zstd::encode_all(rows.as_bytes(), 3).unwrap();
+        fs::write(&path, cipher.seal(&compressed).unwrap()).unwrap();
+
+        assert!(matches!(
+            read_trace_csv(&path),
+            Err(SchemaError::KeyRequired)
+        ));
+        let frame = read_trace_csv_with_cipher(&path, Some(&cipher)).unwrap();
+        assert_eq!(frame.height(), 1);
+    }
+
+    #[test]
+    fn read_trace_csv_rejects_newer_versions() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("future.csv");
+        fs::write(&path, "# schema_version: 99\npid,timestamp,device,energy\n").unwrap();
+
+        assert!(matches!(
+            read_trace_csv(&path),
+            Err(SchemaError::Unsupported(99))
+        ));
+    }
+
+    #[test]
+    fn migrate_json_upgrades_legacy_snapshots() {
+        let legacy = json!({
+            "timestamp": 1,
+            "system_total": {"cpu_joules": 1.0, "gpu_joules": 0.0},
+            "unattributed": {"cpu_joules": 0.0, "gpu_joules": 0.0},
+        });
+
+        let migrated = migrate_json(legacy).unwrap();
+
+        assert_eq!(migrated["schema_version"], json!(SCHEMA_VERSION));
+        assert_eq!(migrated["auxiliary"], json!({}));
+        assert_eq!(migrated["time"], json!("1970-01-01T00:00:00.001Z"));
+        assert!(matches!(
+            migrate_json(json!({"schema_version": 99})),
+            Err(SchemaError::Unsupported(99))
+        ));
+        assert!(matches!(
+            migrate_json(json!({"schema_version": "two"})),
+            Err(SchemaError::InvalidVersion(_))
+        ));
+    }
+}
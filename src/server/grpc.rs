@@ -0,0 +1,310 @@
+/// gRPC Module
+///
+/// The `emt.v1.EnergyMonitor` service of `proto/emt.proto`, for a central
+/// aggregator collecting the energy of a cluster. `StreamEnergy` pushes the
+/// record batches of every collector group to each subscriber as the
+/// background monitoring loop receives them; `GetSummary` and
+/// `ListProcesses` answer from the latest monitor snapshot.
+///
+/// A subscriber too slow to keep up with the last 1024 batches has its
+/// stream ended with `DATA_LOSS` rather than silently missing records, as
+/// tonic ends a response stream at its first error.
+use crate::energy_group::EnergyRecord;
+use crate::monitor::{DeviceEnergy, MonitorHandle, ProcessEnergySnapshot, WorkloadSnapshot};
+use crate::sinks::TraceSink;
+use std::collections::HashSet;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use thiserror::Error;
+use tokio::net::TcpListener;
+use tokio::sync::{broadcast, watch};
+use tokio_stream::wrappers::errors::BroadcastStreamRecvError;
+use tokio_stream::wrappers::{BroadcastStream, TcpListenerStream, WatchStream};
+use tokio_stream::{Stream, StreamExt};
+use tonic::transport::Server;
+use tonic::{Request, Response, Status};
+
+/// Messages and service stubs generated from `proto/emt.proto`.
+pub mod proto {
+    tonic::include_proto!("emt.v1");
+}
+
+use proto::energy_monitor_server::{EnergyMonitor, EnergyMonitorServer};
+
+/// Batches buffered for each `StreamEnergy` subscriber
+const STREAM_CAPACITY: usize = 1024;
+
+#[derive(Debug, Error)]
+pub enum GrpcError {
+    #[error("gRPC transport error: {0}")]
+    Transport(#[from] tonic::transport::Error),
+}
+
+type Batch = Arc<[EnergyRecord]>;
+
+/// Forwards the batches of a collector group to the stream subscribers.
+struct BroadcastSink(broadcast::Sender<Batch>);
+
+impl TraceSink for BroadcastSink {
+    fn write_batch(&mut self, records: &[EnergyRecord]) -> io::Result<()> {
+        if self.0.receiver_count() > 0 {
+            // Sending only fails once every subscriber is gone.
+            let _ = self.0.send(records.into());
+        }
+        Ok(())
+    }
+}
+
+/// Implementation of the `EnergyMonitor` service over a running monitor.
+#[derive(Clone)]
+pub struct EnergyService {
+    handle: MonitorHandle,
+    batches: broadcast::Sender<Batch>,
+    /// Notified when the server shuts down
+    closing: watch::Sender<()>,
+}
+
+impl EnergyService {
+    pub fn new(handle: MonitorHandle) -> Self {
+        Self {
+            handle,
+            batches: broadcast::channel(STREAM_CAPACITY).0,
+            closing: watch::channel(()).0,
+        }
+    }
+
+    /// A sink streaming a collector group's records to the subscribers, for
+    /// [`Monitor::add_sinks`](crate::monitor::Monitor::add_sinks).
+    pub fn sink(&self) -> Box<dyn TraceSink> {
+        Box::new(BroadcastSink(self.batches.clone()))
+    }
+
+    /// Serve the service on `listener` until `shutdown` completes.
+    pub async fn serve(
+        self,
+        listener: TcpListener,
+        shutdown: impl Future<Output = ()>,
+    ) -> Result<(), GrpcError> {
+        let closing = self.closing.clone();
+        // The server waits for open streams, so they are ended first.
+        let shutdown = async move {
+            shutdown.await;
+            closing.send_replace(());
+        };
+        Server::builder()
+            .add_service(EnergyMonitorServer::new(self))
+            .serve_with_incoming_shutdown(TcpListenerStream::new(listener), shutdown)
+            .await?;
+        Ok(())
+    }
+}
+
+impl From<&EnergyRecord> for proto::EnergyRecord {
+    fn from(record: &EnergyRecord) -> Self {
+        Self {
+            pid: record.pid,
+            timestamp_us: record.timestamp_us,
+            monotonic_ns: record.monotonic_ns,
+            device: record.device.clone(),
+            energy: record.energy,
+            raw_counter: record.raw_counter,
+        }
+    }
+}
+
+impl From<&DeviceEnergy> for proto::DeviceEnergy {
+    fn from(energy: &DeviceEnergy) -> Self {
+        Self {
+            cpu_joules: energy.cpu_joules,
+            dram_joules: energy.dram_joules,
+            gpu_joules: energy.gpu_joules,
+        }
+    }
+}
+
+impl From<&ProcessEnergySnapshot> for proto::Process {
+    fn from(process: &ProcessEnergySnapshot) -> Self {
+        Self {
+            pid: process.pid,
+            name: process.name.clone(),
+            energy: Some((&process.energy).into()),
+            power_watts: process.power_watts,
+        }
+    }
+}
+
+impl From<&WorkloadSnapshot> for proto::Workload {
+    fn from(workload: &WorkloadSnapshot) -> Self {
+        Self {
+            root_pid: workload.root_pid,
+            group_id: workload.group_id.clone(),
+            name: workload.name.clone(),
+            user: workload.user.clone(),
+            is_live: workload.is_live,
+            energy: Some((&workload.energy).into()),
+            power_watts: workload.power_watts,
+            processes: workload.processes.iter().map(Into::into).collect(),
+        }
+    }
+}
+
+/// The records of `batch` of one of `pids`, or all of them without pids.
+fn filter_batch(batch: &[EnergyRecord], pids: &HashSet<u32>) -> Vec<proto::EnergyRecord> {
+    batch
+        .iter()
+        .filter(|record| pids.is_empty() || pids.contains(&record.pid))
+        .map(Into::into)
+        .collect()
+}
+
+#[tonic::async_trait]
+impl EnergyMonitor for EnergyService {
+    type StreamEnergyStream =
+        Pin<Box<dyn Stream<Item = Result<proto::EnergyBatch, Status>> + Send>>;
+
+    async fn stream_energy(
+        &self,
+        request: Request<proto::StreamEnergyRequest>,
+    ) -> Result<Response<Self::StreamEnergyStream>, Status> {
+        let pids: HashSet<u32> = request.into_inner().pids.into_iter().collect();
+        let batches = BroadcastStream::new(self.batches.subscribe());
+        let stream = batches
+            .filter_map(move |batch| match batch {
+                Ok(batch) => {
+                    let records = filter_batch(&batch, &pids);
+                    (!records.is_empty()).then_some(Ok(proto::EnergyBatch { records }))
+                }
+                Err(BroadcastStreamRecvError::Lagged(skipped)) => Some(Err(Status::data_loss(
+                    format!("subscriber fell behind by {skipped} batches"),
+                ))),
+            })
+            .map(Some)
+            .merge(WatchStream::from_changes(self.closing.subscribe()).map(|()| None))
+            .map_while(|batch| batch);
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    async fn get_summary(
+        &self,
+        _request: Request<proto::GetSummaryRequest>,
+    ) -> Result<Response<proto::Summary>, Status> {
+        let snapshot = self.handle.snapshot();
+        Ok(Response::new(proto::Summary {
+            timestamp: snapshot.timestamp,
+            total_joules: snapshot.totals.total_joules,
+            system_total: Some((&snapshot.system_total).into()),
+            unattributed: Some((&snapshot.unattributed).into()),
+            devices: snapshot.devices.into_iter().collect(),
+        }))
+    }
+
+    async fn list_processes(
+        &self,
+        _request: Request<proto::ListProcessesRequest>,
+    ) -> Result<Response<proto::ProcessList>, Status> {
+        let snapshot = self.handle.snapshot();
+        Ok(Response::new(proto::ProcessList {
+            workloads: snapshot.workloads.iter().map(Into::into).collect(),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::monitor::MetricsSnapshot;
+    use proto::energy_monitor_client::EnergyMonitorClient;
+    use std::collections::BTreeMap;
+
+    fn record(pid: u32, energy: f64) -> EnergyRecord {
+        EnergyRecord {
+            pid,
+            timestamp_us: 1_000,
+            monotonic_ns: 2_000,
+            device: "rapl:socket:0:package".to_string(),
+            energy,
+            raw_counter: None,
+        }
+    }
+
+    fn snapshot() -> MetricsSnapshot {
+        let energy = DeviceEnergy {
+            cpu_joules: 3.0,
+            ..DeviceEnergy::default()
+        };
+        let mut snapshot = MetricsSnapshot {
+            timestamp: 42,
+            devices: BTreeMap::from([("rapl:socket:0:package".to_string(), 5.0)]),
+            workloads: vec![WorkloadSnapshot {
+                root_pid: 7,
+                group_id: "7".to_string(),
+                name: "train".to_string(),
+                user: "ci".to_string(),
+                processes: vec![ProcessEnergySnapshot {
+                    pid: 7,
+                    name: "train".to_string(),
+                    energy: energy.clone(),
+                    power_watts: 1.5,
+                }],
+                is_live: true,
+                energy,
+                power_watts: 1.5,
+                percentage_of_system: 60.0,
+                container: None,
+            }],
+            ..MetricsSnapshot::default()
+        };
+        snapshot.totals.total_joules = 5.0;
+        snapshot
+    }
+
+    #[tokio::test]
+    async fn streams_batches_and_answers_from_the_snapshot() {
+        let service = EnergyService::new(MonitorHandle::from_snapshot(snapshot()));
+        let mut sink = service.sink();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+        let server = tokio::spawn(service.serve(listener, async {
+            let _ = stopped.await;
+        }));
+
+        let mut client = EnergyMonitorClient::connect(format!("http://{address}"))
+            .await
+            .unwrap();
+        let summary = client
+            .get_summary(proto::GetSummaryRequest {})
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(summary.total_joules, 5.0);
+        assert_eq!(summary.devices["rapl:socket:0:package"], 5.0);
+        let workloads = client
+            .list_processes(proto::ListProcessesRequest {})
+            .await
+            .unwrap()
+            .into_inner()
+            .workloads;
+        assert_eq!(workloads.len(), 1);
+        assert_eq!(workloads[0].processes[0].pid, 7);
+
+        let mut stream = client
+            .stream_energy(proto::StreamEnergyRequest { pids: vec![7] })
+            .await
+            .unwrap()
+            .into_inner();
+        // Batches without records of the pids are not sent.
+        sink.write_batch(&[record(8, 1.0)]).unwrap();
+        sink.write_batch(&[record(7, 2.0), record(8, 1.0)]).unwrap();
+        let batch = stream.message().await.unwrap().unwrap();
+        assert_eq!(batch.records.len(), 1);
+        assert_eq!(batch.records[0].energy, 2.0);
+
+        // Shutting down ends open streams instead of waiting for them.
+        stop.send(()).unwrap();
+        assert!(stream.message().await.unwrap().is_none());
+        server.await.unwrap().unwrap();
+    }
+}
@@ -0,0 +1,350 @@
+/// HTTP API Module
+///
+/// A JSON API over a running monitor, for dashboards and scripts that do
+/// not link Rust or Python:
+///
+/// - `GET /processes`: the tracked workloads and their processes, from the
+///   latest monitor snapshot;
+/// - `GET /summary`: total, unattributed and per-device energy, from the
+///   same snapshot;
+/// - `GET /energy?pid=&since=`: the energy records of the collector groups'
+///   traces, of one process and newer than `since` Unix milliseconds when
+///   given, with their total per device;
+/// - `GET /energy.arrows?pid=&since=`: the same records as an Arrow IPC
+///   stream, for pandas, polars or DuckDB on another machine;
+/// - `POST /track`: start tracking the process of a `{"pid": ...}` body and
+///   the children it forks, on monitors of explicit pids.
+///
+/// `/energy` queries the groups' traces through a
+/// [`TraceReader`](crate::monitor::TraceReader), so it covers the rows in
+/// memory and the segments already written under `collection.trace_dir`.
+use crate::arrow_stream::{ARROW_STREAM_MEDIA_TYPE, RecordStreamWriter};
+use crate::energy_group::{EnergyRecord, RAW_COUNTER_COLUMN};
+use crate::monitor::{DeviceEnergy, MonitorHandle, ProcessTracker, TraceReader, WorkloadSnapshot};
+use crate::query::{Filter, TimeRange};
+use crate::utils::time::{MONOTONIC_COLUMN, TIMESTAMP_COLUMN};
+use axum::extract::{Query, State};
+use axum::http::{StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use polars::prelude::{DataFrame, DataType, PolarsResult};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::io;
+use tokio::net::TcpListener;
+
+/// The HTTP API of a running monitor.
+#[derive(Clone)]
+pub struct HttpApi {
+    handle: MonitorHandle,
+    tracker: Option<ProcessTracker>,
+    traces: TraceReader,
+}
+
+impl HttpApi {
+    /// An API answering from `handle` and the traces of `traces`, from
+    /// [`Monitor::trace_reader`](crate::monitor::Monitor::trace_reader).
+    /// Without a `tracker`, from
+    /// [`Monitor::process_tracker`](crate::monitor::Monitor::process_tracker),
+    /// `POST /track` is refused.
+    pub fn new(
+        handle: MonitorHandle,
+        tracker: Option<ProcessTracker>,
+        traces: TraceReader,
+    ) -> Self {
+        Self {
+            handle,
+            tracker,
+            traces,
+        }
+    }
+
+    /// The trace records matching `query`, oldest first.
+    async fn records(&self, query: &EnergyQuery) -> PolarsResult<Vec<EnergyRecord>> {
+        let range = query.since.map(TimeRange::since).unwrap_or_default();
+        let filter = Filter {
+            pids: query.pid.into_iter().collect(),
+            ..Filter::default()
+        };
+        let mut records = Vec::new();
+        for trace in self.traces.query(range, &filter).await? {
+            records.extend(trace_records(&trace)?);
+        }
+        records.sort_by_key(|record| record.timestamp_us);
+        Ok(records)
+    }
+
+    pub fn router(self) -> Router {
+        Router::new()
+            .route("/processes", get(processes))
+            .route("/summary", get(summary))
+            .route("/energy", get(energy))
+            .route("/energy.arrows", get(energy_stream))
+            .route("/track", post(track))
+            .with_state(self)
+    }
+
+    /// Serve the API on `listener` until `shutdown` completes.
+    pub async fn serve(
+        self,
+        listener: TcpListener,
+        shutdown: impl Future<Output = ()> + Send + 'static,
+    ) -> io::Result<()> {
+        axum::serve(listener, self.router())
+            .with_graceful_shutdown(shutdown)
+            .await
+    }
+}
+
+fn error(status: StatusCode, message: impl Into<String>) -> Response {
+    (status, Json(json!({ "error": message.into() }))).into_response()
+}
+
+#[derive(Serialize)]
+struct ProcessList {
+    workloads: Vec<WorkloadSnapshot>,
+}
+
+async fn processes(State(api): State<HttpApi>) -> Json<ProcessList> {
+    Json(ProcessList {
+        workloads: api.handle.snapshot().workloads,
+    })
+}
+
+#[derive(Serialize)]
+struct Summary {
+    /// Unix milliseconds of the snapshot
+    timestamp: i64,
+    total_joules: f64,
+    system_total: DeviceEnergy,
+    unattributed: DeviceEnergy,
+    devices: BTreeMap<String, f64>,
+}
+
+async fn summary(State(api): State<HttpApi>) -> Json<Summary> {
+    let snapshot = api.handle.snapshot();
+    Json(Summary {
+        timestamp: snapshot.timestamp,
+        total_joules: snapshot.totals.total_joules,
+        system_total: snapshot.system_total,
+        unattributed: snapshot.unattributed,
+        devices: snapshot.devices,
+    })
+}
+
+#[derive(Debug, Deserialize)]
+struct EnergyQuery {
+    pid: Option<u32>,
+    /// Unix milliseconds
+    since: Option<i64>,
+}
+
+#[derive(Serialize)]
+struct EnergyResponse {
+    total_joules: f64,
+    devices: BTreeMap<String, f64>,
+    records: Vec<EnergyRecord>,
+}
+
+async fn energy(State(api): State<HttpApi>, Query(query): Query<EnergyQuery>) -> Response {
+    let records = match api.records(&query).await {
+        Ok(records) => records,
+        Err(e) => return error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+    };
+    let mut devices = BTreeMap::new();
+    for record in &records {
+        *devices.entry(record.device.clone()).or_default() += record.energy;
+    }
+    Json(EnergyResponse {
+        total_joules: devices.values().sum(),
+        devices,
+        records,
+    })
+    .into_response()
+}
+
+async fn energy_stream(State(api): State<HttpApi>, Query(query): Query<EnergyQuery>) -> Response {
+    let stream = api.records(&query).await.and_then(|records| {
+        let mut writer = RecordStreamWriter::new(Vec::new())?;
+        writer.write(&records)?;
+        writer.finish()
+    });
+    match stream {
+        Ok(bytes) => ([(header::CONTENT_TYPE, ARROW_STREAM_MEDIA_TYPE)], bytes).into_response(),
+        Err(e) => error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+    }
+}
+
+/// The rows of a trace in the layout of
+/// [`EnergyGroup::query`](crate::energy_group::EnergyGroup::query) as records.
+fn trace_records(trace: &DataFrame) -> PolarsResult<Vec<EnergyRecord>> {
+    let pids = trace.column("pid")?.u32()?;
+    let devices = trace.column("device")?.str()?;
+    let energy = trace.column("energy")?.f64()?;
+    let timestamps = trace.column(TIMESTAMP_COLUMN)?.cast(&DataType::Int64)?;
+    let monotonic = trace.column(MONOTONIC_COLUMN)?.cast(&DataType::Int64)?;
+    let raw_counters = match trace.column(RAW_COUNTER_COLUMN) {
+        Ok(column) => Some(column.f64()?.clone()),
+        Err(_) => None,
+    };
+    let timestamps = timestamps.i64()?;
+    let monotonic = monotonic.i64()?;
+    Ok((0..trace.height())
+        .map(|i| EnergyRecord {
+            pid: pids.get(i).unwrap_or_default(),
+            timestamp_us: timestamps.get(i).unwrap_or_default(),
+            monotonic_ns: monotonic.get(i).unwrap_or_default(),
+            device: devices.get(i).unwrap_or_default().to_string(),
+            energy: energy.get(i).unwrap_or_default(),
+            raw_counter: raw_counters.as_ref().and_then(|raw| raw.get(i)),
+        })
+        .collect())
+}
+
+#[derive(Debug, Deserialize)]
+struct TrackRequest {
+    pid: u32,
+}
+
+async fn track(State(api): State<HttpApi>, Json(request): Json<TrackRequest>) -> Response {
+    let Some(tracker) = api.tracker else {
+        return error(
+            StatusCode::CONFLICT,
+            "the monitor tracks every process or cgroups, not explicit pids",
+        );
+    };
+    let pid = request.pid;
+    match tokio::task::spawn_blocking(move || tracker.track(pid)).await {
+        Ok(Some(added)) => Json(json!({ "pid": pid, "added": added })).into_response(),
+        Ok(None) => error(
+            StatusCode::NOT_FOUND,
+            format!("process {pid} is not running"),
+        ),
+        Err(e) => error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collectors::composite::CompositeCollector;
+    use crate::config::EmtConfig;
+    use crate::energy_group::EnergyGroup;
+    use crate::monitor::{MetricsSnapshot, Monitor};
+    use axum::body::{Body, to_bytes};
+    use axum::http::Request;
+    use polars::prelude::{IpcStreamReader, SerReader};
+    use serde_json::Value;
+    use std::sync::Arc;
+    use tokio::sync::Mutex;
+    use tower::ServiceExt;
+
+    fn record(pid: u32, timestamp_us: i64, energy: f64) -> EnergyRecord {
+        EnergyRecord {
+            pid,
+            timestamp_us,
+            monotonic_ns: timestamp_us * 1000,
+            device: "rapl:socket:0:package".to_string(),
+            energy,
+            raw_counter: None,
+        }
+    }
+
+    async fn call(api: &HttpApi, request: Request<Body>) -> (StatusCode, Value) {
+        let response = api.clone().router().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    fn get(uri: &str) -> Request<Body> {
+        Request::get(uri).body(Body::empty()).unwrap()
+    }
+
+    fn post_track(pid: u32) -> Request<Body> {
+        Request::post("/track")
+            .header("content-type", "application/json")
+            .body(Body::from(format!("{{\"pid\": {pid}}}")))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn queries_group_traces_by_pid_and_time() {
+        let monitor = Monitor::new(EmtConfig::default(), Some(vec![std::process::id()]));
+        let mut group = EnergyGroup::new(CompositeCollector::new(), 1.0, None);
+        group.ingest(&[record(7, 1_000_000, 1.0), record(8, 1_000_000, 2.0)]);
+        group.ingest(&[record(7, 5_000_000, 3.0)]);
+        group.ingest(&[record(7, 12_000_000, 4.0)]);
+        let mut traces = TraceReader::default();
+        traces.add_group(Arc::new(Mutex::new(group)));
+        let api = HttpApi::new(
+            MonitorHandle::from_snapshot(MetricsSnapshot::default()),
+            monitor.process_tracker(),
+            traces,
+        );
+
+        let (status, body) = call(&api, get("/energy?pid=7")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["total_joules"], 8.0);
+        assert_eq!(body["records"].as_array().unwrap().len(), 3);
+        assert_eq!(body["records"][1]["timestamp_us"], 5_000_000);
+        let (_, body) = call(&api, get("/energy?since=6000")).await;
+        assert_eq!(body["devices"]["rapl:socket:0:package"], 4.0);
+
+        let response = api
+            .clone()
+            .router()
+            .oneshot(get("/energy.arrows?pid=7&since=2000"))
+            .await
+            .unwrap();
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            ARROW_STREAM_MEDIA_TYPE
+        );
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let trace = IpcStreamReader::new(std::io::Cursor::new(body))
+            .finish()
+            .unwrap();
+        assert_eq!(trace.height(), 2);
+
+        let (status, body) = call(&api, get("/summary")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["total_joules"], 0.0);
+        let (_, body) = call(&api, get("/processes")).await;
+        assert!(body["workloads"].as_array().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn tracks_running_processes_on_monitors_of_explicit_pids() {
+        let monitor = Monitor::new(EmtConfig::default(), Some(Vec::new()));
+        let tracker = monitor.process_tracker().unwrap();
+        let api = HttpApi::new(
+            MonitorHandle::from_snapshot(MetricsSnapshot::default()),
+            Some(tracker.clone()),
+            TraceReader::default(),
+        );
+
+        let pid = std::process::id();
+        let (status, body) = call(&api, post_track(pid)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["added"], true);
+        let (_, body) = call(&api, post_track(pid)).await;
+        assert_eq!(body["added"], false);
+        assert_eq!(tracker.root_pids(), [pid]);
+        let (status, _) = call(&api, post_track(u32::MAX)).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let monitor_all = Monitor::new(EmtConfig::default(), None);
+        assert!(monitor_all.process_tracker().is_none());
+        let api = HttpApi::new(
+            MonitorHandle::from_snapshot(MetricsSnapshot::default()),
+            None,
+            TraceReader::default(),
+        );
+        let (status, _) = call(&api, post_track(pid)).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+    }
+}
@@ -0,0 +1,6 @@
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod http;
+#[cfg(feature = "grpc")]
+pub use grpc::{EnergyService, GrpcError};
+pub use http::HttpApi;
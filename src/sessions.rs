@@ -0,0 +1,399 @@
+/// Session Module
+///
+/// Sessions split a run into named phases, such as training epochs, each
+/// between a `begin` and an `end` marker. Unlike regions, which may nest
+/// and overlap, at most one session is active at a time, so every trace
+/// record belongs to at most one session. [`session_trace`] labels the
+/// records with a `session` column and adds a marker row at every boundary,
+/// and [`session_summary`] aggregates the energy of one session.
+///
+/// Records are assigned by monotonic time, as for regions. A name may be
+/// used for several sessions; they are summarized together. A summary's
+/// energy counts each energy domain once, as in
+/// [`EnergyTotals`](crate::energy_totals::EnergyTotals), while its devices
+/// list every device.
+use crate::config::EnergyDomainRule;
+use crate::energy_totals::EnergyTotals;
+use crate::regions::{Region, energy_by_region};
+use crate::utils::time::{MONOTONIC_COLUMN, RecordTime, TIMESTAMP_COLUMN};
+use polars::prelude::*;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Trace column naming the session of each record.
+pub const SESSION_COLUMN: &str = "session";
+/// Trace column marking session boundaries: `begin` or `end` on marker
+/// rows, null on records.
+pub const SESSION_EVENT_COLUMN: &str = "session_event";
+
+/// Errors raised by session markers.
+#[derive(Debug, thiserror::Error)]
+pub enum SessionError {
+    #[error("session '{0}' is still active")]
+    Active(String),
+    #[error("no session is active")]
+    NotActive,
+    #[error("no session named '{0}'")]
+    Unknown(String),
+    #[error(transparent)]
+    Polars(#[from] PolarsError),
+}
+
+/// Begin/end markers of the sessions of a run.
+#[derive(Debug, Clone, Default)]
+pub struct SessionMarkers {
+    sessions: Vec<Region>,
+}
+
+impl SessionMarkers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Begin a session named `name` now.
+    pub fn begin(&mut self, name: &str) -> Result<(), SessionError> {
+        self.begin_at(name, RecordTime::now())
+    }
+
+    pub fn begin_at(&mut self, name: &str, time: RecordTime) -> Result<(), SessionError> {
+        if let Some(active) = self.active() {
+            return Err(SessionError::Active(active.name.clone()));
+        }
+        self.sessions.push(Region {
+            name: name.to_string(),
+            start: time,
+            end: None,
+        });
+        Ok(())
+    }
+
+    /// End the active session now, returning its name.
+    pub fn end(&mut self) -> Result<String, SessionError> {
+        self.end_at(RecordTime::now())
+    }
+
+    pub fn end_at(&mut self, time: RecordTime) -> Result<String, SessionError> {
+        let session = self
+            .sessions
+            .last_mut()
+            .filter(|session| session.end.is_none())
+            .ok_or(SessionError::NotActive)?;
+        session.end = Some(time);
+        Ok(session.name.clone())
+    }
+
+    /// The session begun and not yet ended, if any.
+    pub fn active(&self) -> Option<&Region> {
+        self.sessions.last().filter(|session| session.end.is_none())
+    }
+
+    /// All sessions in the order they were begun.
+    pub fn sessions(&self) -> &[Region] {
+        &self.sessions
+    }
+}
+
+/// Energy consumed within a named session.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SessionSummary {
+    pub session: String,
+    /// Unix milliseconds of the first begin marker
+    pub start: i64,
+    /// Unix milliseconds of the last end marker; `None` while active
+    pub end: Option<i64>,
+    /// Summed length of the sessions of this name
+    pub duration_seconds: f64,
+    pub energy_joules: f64,
+    pub average_power_watts: f64,
+    /// Energy per device
+    pub devices: BTreeMap<String, f64>,
+}
+
+/// Aggregate the records of `trace` (`device | energy | monotonic_ns`)
+/// within the sessions named `name`, counting the domains of `rules` once.
+/// An active session is measured up to `now`.
+pub fn session_summary(
+    trace: &DataFrame,
+    sessions: &[Region],
+    name: &str,
+    rules: &[EnergyDomainRule],
+    now: RecordTime,
+) -> Result<SessionSummary, SessionError> {
+    let named: Vec<Region> = sessions
+        .iter()
+        .filter(|session| session.name == name)
+        .cloned()
+        .collect();
+    let (Some(first), Some(last)) = (named.first(), named.last()) else {
+        return Err(SessionError::Unknown(name.to_string()));
+    };
+    let duration_ns: i64 = named.iter().map(|session| session.duration_ns(now)).sum();
+    let duration_seconds = duration_ns as f64 / 1e9;
+    let per_device = energy_by_region(trace, &named, true, now)?;
+    let devices: BTreeMap<String, f64> = per_device
+        .into_iter()
+        .filter_map(|totals| Some((totals.device?, totals.energy_joules)))
+        .collect();
+    let energy_joules = EnergyTotals::from_devices(rules, &devices).total_joules;
+    Ok(SessionSummary {
+        session: name.to_string(),
+        start: first.start.timestamp(),
+        end: last.end.map(|end| end.timestamp()),
+        duration_seconds,
+        energy_joules,
+        average_power_watts: if duration_seconds > 0.0 {
+            energy_joules / duration_seconds
+        } else {
+            0.0
+        },
+        devices,
+    })
+}
+
+/// `trace` with a [`SESSION_COLUMN`] naming the session of each record,
+/// null outside sessions, and a marker row per begin and end of a session,
+/// ordered by monotonic time. Marker rows carry the session, the
+/// [`SESSION_EVENT_COLUMN`] and their time, and nulls elsewhere.
+pub fn session_trace(
+    trace: &DataFrame,
+    sessions: &[Region],
+    now: RecordTime,
+) -> PolarsResult<DataFrame> {
+    let monotonic = trace.column(MONOTONIC_COLUMN)?.cast(&DataType::Int64)?;
+    let labels: Vec<Option<&str>> = monotonic
+        .i64()?
+        .iter()
+        .map(|monotonic_ns| {
+            let monotonic_ns = monotonic_ns?;
+            sessions
+                .iter()
+                .find(|session| session.contains(monotonic_ns, now))
+                .map(|session| session.name.as_str())
+        })
+        .collect();
+    let mut records = trace.clone();
+    records.with_column(Column::new(SESSION_COLUMN.into(), labels))?;
+    records.with_column(Column::full_null(
+        SESSION_EVENT_COLUMN.into(),
+        records.height(),
+        &DataType::String,
+    ))?;
+
+    let markers: Vec<(&str, &str, RecordTime)> = sessions
+        .iter()
+        .flat_map(|session| {
+            std::iter::once((session.name.as_str(), "begin", session.start))
+                .chain(session.end.map(|end| (session.name.as_str(), "end", end)))
+        })
+        .collect();
+    let columns = records
+        .get_columns()
+        .iter()
+        .map(|column| {
+            let name = column.name().as_str();
+            let values = if name == SESSION_COLUMN {
+                Column::new(
+                    name.into(),
+                    markers
+                        .iter()
+                        .map(|(session, _, _)| *session)
+                        .collect::<Vec<_>>(),
+                )
+            } else if name == SESSION_EVENT_COLUMN {
+                Column::new(
+                    name.into(),
+                    markers
+                        .iter()
+                        .map(|(_, event, _)| *event)
+                        .collect::<Vec<_>>(),
+                )
+            } else if name == TIMESTAMP_COLUMN {
+                Column::new(
+                    name.into(),
+                    markers
+                        .iter()
+                        .map(|(_, _, time)| time.timestamp_us)
+                        .collect::<Vec<_>>(),
+                )
+            } else if name == MONOTONIC_COLUMN {
+                Column::new(
+                    name.into(),
+                    markers
+                        .iter()
+                        .map(|(_, _, time)| time.monotonic_ns)
+                        .collect::<Vec<_>>(),
+                )
+            } else {
+                Column::full_null(name.into(), markers.len(), column.dtype())
+            };
+            values.cast(column.dtype())
+        })
+        .collect::<PolarsResult<Vec<_>>>()?;
+    records.vstack_mut(&DataFrame::new(columns)?)?;
+    records.sort(
+        [MONOTONIC_COLUMN],
+        SortMultipleOptions::default().with_maintain_order(true),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::default_energy_domains;
+    use crate::utils::time::{monotonic_dtype, timestamp_dtype};
+    use polars::df;
+
+    fn at(seconds: i64) -> RecordTime {
+        RecordTime {
+            timestamp_us: 1_700_000_000_000_000 + seconds * 1_000_000,
+            monotonic_ns: seconds * 1_000_000_000,
+        }
+    }
+
+    fn trace() -> DataFrame {
+        let mut trace = df![
+            "device" => ["cpu", "gpu", "cpu", "cpu"],
+            "energy" => [1.0, 2.0, 3.0, 5.0],
+            TIMESTAMP_COLUMN => [1i64, 1, 2, 5].map(|s| at(s).timestamp_us),
+            MONOTONIC_COLUMN => [1i64, 1, 2, 5].map(|s| at(s).monotonic_ns),
+        ]
+        .unwrap();
+        let timestamp = trace
+            .column(TIMESTAMP_COLUMN)
+            .unwrap()
+            .cast(&timestamp_dtype())
+            .unwrap();
+        let monotonic = trace
+            .column(MONOTONIC_COLUMN)
+            .unwrap()
+            .cast(&monotonic_dtype())
+            .unwrap();
+        trace.with_column(timestamp).unwrap();
+        trace.with_column(monotonic).unwrap();
+        trace
+    }
+
+    #[test]
+    fn one_session_is_active_at_a_time() {
+        let mut markers = SessionMarkers::new();
+
+        assert!(matches!(
+            markers.end_at(at(0)),
+            Err(SessionError::NotActive)
+        ));
+        markers.begin_at("epoch_1", at(0)).unwrap();
+        assert!(matches!(
+            markers.begin_at("epoch_2", at(1)),
+            Err(SessionError::Active(name)) if name == "epoch_1"
+        ));
+        assert_eq!(markers.end_at(at(1)).unwrap(), "epoch_1");
+        markers.begin_at("epoch_2", at(1)).unwrap();
+        assert_eq!(markers.active().unwrap().name, "epoch_2");
+        assert_eq!(markers.sessions().len(), 2);
+    }
+
+    #[test]
+    fn session_summary_sums_energy_within_the_named_sessions() {
+        let mut markers = SessionMarkers::new();
+        markers.begin_at("epoch_1", at(0)).unwrap();
+        markers.end_at(at(1)).unwrap();
+        markers.begin_at("eval", at(1)).unwrap();
+        markers.end_at(at(3)).unwrap();
+        markers.begin_at("epoch_1", at(4)).unwrap();
+
+        let summary = session_summary(&trace(), markers.sessions(), "epoch_1", &[], at(6)).unwrap();
+        assert_eq!(summary.energy_joules, 1.0 + 2.0 + 5.0);
+        assert_eq!(summary.devices["gpu"], 2.0);
+        assert_eq!(summary.duration_seconds, 3.0);
+        assert_eq!(summary.start, at(0).timestamp());
+        assert_eq!(summary.end, None);
+
+        let summary = session_summary(&trace(), markers.sessions(), "eval", &[], at(6)).unwrap();
+        assert_eq!(summary.energy_joules, 3.0);
+        assert_eq!(summary.average_power_watts, 1.5);
+        assert!(matches!(
+            session_summary(&trace(), markers.sessions(), "missing", &[], at(6)),
+            Err(SessionError::Unknown(_))
+        ));
+    }
+
+    #[test]
+    fn session_summary_counts_psys_and_its_packages_once() {
+        let mut trace = trace();
+        let devices = Series::new(
+            "device".into(),
+            [
+                "rapl:system:psys",
+                "rapl:socket:0:package",
+                "rapl:system:psys",
+                "rapl:system:psys",
+            ],
+        );
+        trace.with_column(devices).unwrap();
+        let mut markers = SessionMarkers::new();
+        markers.begin_at("epoch_1", at(0)).unwrap();
+        markers.end_at(at(3)).unwrap();
+        let rules = default_energy_domains();
+
+        let summary =
+            session_summary(&trace, markers.sessions(), "epoch_1", &rules, at(6)).unwrap();
+        assert_eq!(summary.energy_joules, 1.0 + 3.0);
+        assert_eq!(summary.devices["rapl:socket:0:package"], 2.0);
+        let summary = session_summary(&trace, markers.sessions(), "epoch_1", &[], at(6)).unwrap();
+        assert_eq!(summary.energy_joules, 6.0);
+    }
+
+    #[test]
+    fn session_trace_labels_records_and_adds_marker_rows() {
+        let mut markers = SessionMarkers::new();
+        markers.begin_at("epoch_1", at(0)).unwrap();
+        markers.end_at(at(1)).unwrap();
+        markers.begin_at("epoch_2", at(3)).unwrap();
+
+        let labelled = session_trace(&trace(), markers.sessions(), at(6)).unwrap();
+
+        let sessions: Vec<Option<&str>> = labelled
+            .column(SESSION_COLUMN)
+            .unwrap()
+            .str()
+            .unwrap()
+            .iter()
+            .collect();
+        let events: Vec<Option<&str>> = labelled
+            .column(SESSION_EVENT_COLUMN)
+            .unwrap()
+            .str()
+            .unwrap()
+            .iter()
+            .collect();
+        assert_eq!(
+            sessions,
+            [
+                Some("epoch_1"),
+                Some("epoch_1"),
+                Some("epoch_1"),
+                Some("epoch_1"),
+                None,
+                Some("epoch_2"),
+                Some("epoch_2"),
+            ]
+        );
+        assert_eq!(
+            events,
+            [
+                Some("begin"),
+                None,
+                None,
+                Some("end"),
+                None,
+                Some("begin"),
+                None
+            ]
+        );
+        assert_eq!(labelled.column("energy").unwrap().null_count(), 3);
+        assert_eq!(
+            labelled.column(TIMESTAMP_COLUMN).unwrap().dtype(),
+            &timestamp_dtype()
+        );
+    }
+}
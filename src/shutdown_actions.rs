@@ -0,0 +1,222 @@
+/// Shutdown Actions Module
+///
+/// Exports configured under `export.on_shutdown`. [`Monitor::shutdown`]
+/// runs them once collection has stopped, so a run leaves durable artifacts
+/// without the caller exporting anything itself. The CLI calls `shutdown`
+/// from its signal handler too, so runs ended by Ctrl-C or `SIGTERM` export
+/// as well.
+///
+/// [`Monitor::shutdown`]: crate::monitor::Monitor::shutdown
+use crate::device_labels::DeviceLabels;
+use crate::encryption::{EncryptionError, TraceCipher};
+use crate::metrics_sink::{MetricsSink, PrometheusSink};
+use crate::monitor::MetricsSnapshot;
+use crate::schema::{SCHEMA_VERSION, SCHEMA_VERSION_KEY};
+use crate::utils::time::ExportTimezone;
+use polars::prelude::{LazyFrame, ParquetWriter, PolarsError};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::time::Duration;
+use thiserror::Error;
+
+/// Time allowed for pushing the final metrics.
+const PUSH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// One export run at shutdown.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ShutdownAction {
+    /// Write the trace, joined with process and device metadata, to a
+    /// Parquet file at this path.
+    Parquet(String),
+    /// Write the final metrics snapshot as JSON to this path.
+    SummaryJson(String),
+    /// Push the final metrics in Prometheus text format to this
+    /// Pushgateway URL (`http://host:9091/metrics/job/<job>`).
+    PushMetrics(String),
+}
+
+impl ShutdownAction {
+    /// Destination path or URL
+    pub fn target(&self) -> &str {
+        match self {
+            Self::Parquet(target) | Self::SummaryJson(target) | Self::PushMetrics(target) => target,
+        }
+    }
+}
+
+/// Errors raised by a shutdown action.
+#[derive(Debug, Error)]
+pub enum ShutdownActionError {
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Failed to build trace: {0}")]
+    Polars(#[from] PolarsError),
+    #[error("Failed to serialize summary: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    Encryption(#[from] EncryptionError),
+    #[error("Failed to encode metrics: {0}")]
+    Prometheus(#[from] prometheus::Error),
+    #[error("Failed to push metrics: {0}")]
+    Push(#[from] reqwest::Error),
+}
+
+/// What the actions export, gathered once the monitor has stopped.
+pub struct ShutdownArtifacts<'a> {
+    /// Joined trace of every collector group
+    pub trace: LazyFrame,
+    pub snapshot: &'a MetricsSnapshot,
+    pub timezone: ExportTimezone,
+    /// Files are sealed with this cipher when set.
+    pub cipher: Option<&'a TraceCipher>,
+    /// Aliases and labels of devices in pushed metrics
+    pub device_labels: DeviceLabels,
+}
+
+/// Run `action` against `artifacts`.
+pub async fn run_shutdown_action(
+    action: &ShutdownAction,
+    artifacts: &ShutdownArtifacts<'_>,
+) -> Result<(), ShutdownActionError> {
+    match action {
+        ShutdownAction::Parquet(path) => {
+            let mut trace = artifacts.trace.clone().collect()?;
+            let mut contents = Vec::new();
+            ParquetWriter::new(&mut contents).finish(&mut trace)?;
+            write_file(path, contents, artifacts.cipher)
+        }
+        ShutdownAction::SummaryJson(path) => {
+            let mut contents =
+                serde_json::to_vec_pretty(&summary_json(artifacts.snapshot, artifacts.timezone)?)?;
+            contents.push(b'\n');
+            write_file(path, contents, artifacts.cipher)
+        }
+        ShutdownAction::PushMetrics(url) => push_metrics(url, artifacts).await,
+    }
+}
+
+/// The snapshot with the schema version and a formatted `time` added.
+fn summary_json(
+    snapshot: &MetricsSnapshot,
+    timezone: ExportTimezone,
+) -> Result<serde_json::Value, serde_json::Error> {
+    let mut summary = serde_json::to_value(snapshot)?;
+    if let Some(fields) = summary.as_object_mut() {
+        fields.insert(SCHEMA_VERSION_KEY.to_string(), SCHEMA_VERSION.into());
+        fields.insert(
+            "time".to_string(),
+            timezone.format_millis(snapshot.timestamp).into(),
+        );
+    }
+    Ok(summary)
+}
+
+fn write_file(
+    path: &str,
+    mut contents: Vec<u8>,
+    cipher: Option<&TraceCipher>,
+) -> Result<(), ShutdownActionError> {
+    if let Some(cipher) = cipher {
+        contents = cipher.seal(&contents)?;
+    }
+    fs::write(path, contents)?;
+    Ok(())
+}
+
+async fn push_metrics(
+    url: &str,
+    artifacts: &ShutdownArtifacts<'_>,
+) -> Result<(), ShutdownActionError> {
+    let mut sink = PrometheusSink::new()?;
+    sink.set_device_labels(artifacts.device_labels.clone());
+    sink.update(artifacts.snapshot);
+    reqwest::Client::builder()
+        .timeout(PUSH_TIMEOUT)
+        .build()?
+        .put(url)
+        .header(reqwest::header::CONTENT_TYPE, prometheus::TEXT_FORMAT)
+        .body(sink.encode_text()?)
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use polars::prelude::{IntoLazy, ParquetReader, SerReader, df};
+    use tempfile::TempDir;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    fn artifacts(snapshot: &MetricsSnapshot) -> ShutdownArtifacts<'_> {
+        ShutdownArtifacts {
+            trace: df!("pid" => [1u32, 2], "energy" => [1.5, 2.5])
+                .unwrap()
+                .lazy(),
+            snapshot,
+            timezone: ExportTimezone::Utc,
+            cipher: None,
+            device_labels: DeviceLabels::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn parquet_and_summary_files_are_written() {
+        let dir = TempDir::new().unwrap();
+        let parquet = dir.path().join("run.parquet");
+        let summary = dir.path().join("summary.json");
+        let snapshot = MetricsSnapshot {
+            timestamp: 1_700_000_000_000,
+            ..MetricsSnapshot::default()
+        };
+        let artifacts = artifacts(&snapshot);
+
+        for action in [
+            ShutdownAction::Parquet(parquet.display().to_string()),
+            ShutdownAction::SummaryJson(summary.display().to_string()),
+        ] {
+            run_shutdown_action(&action, &artifacts).await.unwrap();
+        }
+
+        let trace = ParquetReader::new(fs::File::open(&parquet).unwrap())
+            .finish()
+            .unwrap();
+        assert_eq!(trace, artifacts.trace.collect().unwrap());
+        let summary: serde_json::Value =
+            serde_json::from_slice(&fs::read(&summary).unwrap()).unwrap();
+        assert_eq!(summary[SCHEMA_VERSION_KEY], SCHEMA_VERSION);
+        assert_eq!(summary["time"], "2023-11-14T22:13:20.000Z");
+    }
+
+    #[tokio::test]
+    async fn final_metrics_are_pushed_in_text_format() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/metrics/job/emt", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buffer = [0u8; 4096];
+            while !String::from_utf8_lossy(&request).contains("emt_dropped_batches_total 0") {
+                let read = stream.read(&mut buffer).await.unwrap();
+                assert!(read > 0, "connection closed before the body arrived");
+                request.extend_from_slice(&buffer[..read]);
+            }
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+                .await
+                .unwrap();
+            String::from_utf8(request).unwrap()
+        });
+        let snapshot = MetricsSnapshot::default();
+
+        run_shutdown_action(&ShutdownAction::PushMetrics(url), &artifacts(&snapshot))
+            .await
+            .unwrap();
+
+        let request = server.await.unwrap();
+        assert!(request.starts_with("PUT /metrics/job/emt HTTP/1.1"));
+        assert!(request.contains("# TYPE emt_dropped_batches_total counter"));
+    }
+}
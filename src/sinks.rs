@@ -0,0 +1,255 @@
+/// Sinks Module
+///
+/// Streams energy records to disk as each batch leaves the collector
+/// channel, before it reaches the in-memory trace. Unlike a
+/// [`TraceRecorder`](crate::trace_recorder::TraceRecorder), which
+/// periodically copies rows out of the rotating trace, a sink sees every
+/// record exactly once, in the order the collector produced it, so nothing
+/// is lost to trace rotation and nothing waits for the next flush.
+///
+/// `CsvSink` and `JsonlSink` append to their file, with the fields of
+/// [`EnergyRecord`] in its order: `pid`, `timestamp_us`, `monotonic_ns`,
+/// `device`, `energy` and `raw_counter`. `ArrowStreamSink` writes the same
+/// fields as an Arrow IPC stream, one record batch per batch.
+use crate::arrow_stream::RecordStreamWriter;
+use crate::energy_group::EnergyRecord;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+const CSV_HEADER: &str = "pid,timestamp_us,monotonic_ns,device,energy,raw_counter";
+
+#[derive(Debug, Error)]
+pub enum SinkError {
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    #[error("Arrow error: {0}")]
+    Arrow(#[from] polars::prelude::PolarsError),
+    #[error("unsupported sink format for {0} (expected .csv, .jsonl or .arrows)")]
+    UnknownFormat(PathBuf),
+}
+
+/// Destination of the records of an [`EnergyGroup`](crate::energy_group::EnergyGroup),
+/// written batch by batch as they arrive.
+pub trait TraceSink: Send {
+    /// Write one batch and make it durable before returning.
+    fn write_batch(&mut self, records: &[EnergyRecord]) -> io::Result<()>;
+}
+
+/// Open a sink at `path`, a CSV, JSON Lines or Arrow IPC stream file by
+/// its extension.
+pub fn open_sink(path: &Path) -> Result<Box<dyn TraceSink>, SinkError> {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("csv") => Ok(Box::new(CsvSink::create(path)?)),
+        Some("jsonl") => Ok(Box::new(JsonlSink::create(path)?)),
+        Some("arrows") => Ok(Box::new(ArrowStreamSink::create(path)?)),
+        _ => Err(SinkError::UnknownFormat(path.to_path_buf())),
+    }
+}
+
+fn open_append(path: &Path) -> io::Result<File> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+/// Appends records to a CSV file, writing the header to a new or empty one.
+pub struct CsvSink {
+    writer: BufWriter<File>,
+}
+
+impl CsvSink {
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = open_append(path.as_ref())?;
+        let is_empty = file.metadata()?.len() == 0;
+        let mut writer = BufWriter::new(file);
+        if is_empty {
+            writeln!(writer, "{CSV_HEADER}")?;
+            writer.flush()?;
+        }
+        Ok(Self { writer })
+    }
+}
+
+/// `field` quoted when it holds a separator, quote or line break.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+impl TraceSink for CsvSink {
+    fn write_batch(&mut self, records: &[EnergyRecord]) -> io::Result<()> {
+        for record in records {
+            writeln!(
+                self.writer,
+                "{},{},{},{},{},{}",
+                record.pid,
+                record.timestamp_us,
+                record.monotonic_ns,
+                csv_field(&record.device),
+                record.energy,
+                record
+                    .raw_counter
+                    .map(|raw| raw.to_string())
+                    .unwrap_or_default()
+            )?;
+        }
+        self.writer.flush()
+    }
+}
+
+/// Appends records to a JSON Lines file, one object per record.
+pub struct JsonlSink {
+    writer: BufWriter<File>,
+}
+
+impl JsonlSink {
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self {
+            writer: BufWriter::new(open_append(path.as_ref())?),
+        })
+    }
+}
+
+impl TraceSink for JsonlSink {
+    fn write_batch(&mut self, records: &[EnergyRecord]) -> io::Result<()> {
+        for record in records {
+            serde_json::to_writer(&mut self.writer, record)?;
+            self.writer.write_all(b"\n")?;
+        }
+        self.writer.flush()
+    }
+}
+
+/// Writes records to an Arrow IPC stream file, one record batch per batch.
+///
+/// A stream starts with its schema, so unlike the text sinks this one
+/// replaces an existing file. The end-of-stream marker is written when the
+/// sink is dropped; readers also accept a stream cut off after any batch.
+pub struct ArrowStreamSink {
+    writer: Option<RecordStreamWriter<File>>,
+}
+
+impl ArrowStreamSink {
+    pub fn create(path: impl AsRef<Path>) -> Result<Self, SinkError> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        // Unbuffered, so every batch is in the file once it is written.
+        let file = File::create(path)?;
+        Ok(Self {
+            writer: Some(RecordStreamWriter::new(file)?),
+        })
+    }
+}
+
+impl TraceSink for ArrowStreamSink {
+    fn write_batch(&mut self, records: &[EnergyRecord]) -> io::Result<()> {
+        self.writer
+            .as_mut()
+            .expect("writer is only taken on drop")
+            .write(records)
+            .map_err(io::Error::other)
+    }
+}
+
+impl Drop for ArrowStreamSink {
+    fn drop(&mut self) {
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.finish();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(device: &str, raw_counter: Option<f64>) -> EnergyRecord {
+        EnergyRecord {
+            pid: 7,
+            timestamp_us: 1_000,
+            monotonic_ns: 2_000,
+            device: device.to_string(),
+            energy: 0.5,
+            raw_counter,
+        }
+    }
+
+    #[test]
+    fn csv_sink_appends_under_one_header_and_quotes_devices() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("out").join("trace.csv");
+
+        let mut sink = open_sink(&path).unwrap();
+        sink.write_batch(&[record("rapl:socket:0:package", Some(12.5))])
+            .unwrap();
+        drop(sink);
+        let mut sink = CsvSink::create(&path).unwrap();
+        sink.write_batch(&[record("exec:pdu,a", None)]).unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "pid,timestamp_us,monotonic_ns,device,energy,raw_counter\n\
+             7,1000,2000,rapl:socket:0:package,0.5,12.5\n\
+             7,1000,2000,\"exec:pdu,a\",0.5,\n"
+        );
+    }
+
+    #[test]
+    fn jsonl_sink_writes_one_record_per_line() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("trace.jsonl");
+
+        let mut sink = open_sink(&path).unwrap();
+        sink.write_batch(&[record("a", None), record("b", Some(1.0))])
+            .unwrap();
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let devices: Vec<String> = contents
+            .lines()
+            .map(|line| serde_json::from_str::<EnergyRecord>(line).unwrap().device)
+            .collect();
+        assert_eq!(devices, ["a", "b"]);
+        assert!(matches!(
+            open_sink(&dir.path().join("trace.parquet")),
+            Err(SinkError::UnknownFormat(_))
+        ));
+    }
+
+    #[test]
+    fn arrow_stream_sink_writes_a_readable_stream_per_file() {
+        use polars::prelude::{IpcStreamReader, SerReader};
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("trace.arrows");
+        let read = |path: &Path| {
+            IpcStreamReader::new(File::open(path).unwrap())
+                .finish()
+                .unwrap()
+        };
+
+        let mut sink = open_sink(&path).unwrap();
+        sink.write_batch(&[record("a", Some(1.0))]).unwrap();
+        sink.write_batch(&[record("b", None)]).unwrap();
+        assert_eq!(read(&path).height(), 2);
+        drop(sink);
+        assert_eq!(read(&path).height(), 2);
+
+        let mut sink = open_sink(&path).unwrap();
+        sink.write_batch(&[record("c", None)]).unwrap();
+        drop(sink);
+        let trace = read(&path);
+        assert_eq!(trace.height(), 1);
+        assert_eq!(
+            trace.column("device").unwrap().str().unwrap().get(0),
+            Some("c")
+        );
+    }
+}
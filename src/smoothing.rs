@@ -0,0 +1,159 @@
+/// Smoothing Module
+///
+/// Rolling-window smoothing for power series. Raw per-interval power from
+/// RAPL at 10 Hz swings by tens of watts between samples; a trailing mean or
+/// median over a few samples or seconds makes trends readable without hiding
+/// sustained changes.
+///
+/// Smoothing is written as `none`, `mean:<window>` or `median:<window>`, where
+/// the window is a sample count (`mean:10`) or a duration in seconds
+/// (`median:2.5s`).
+use std::fmt;
+use std::str::FromStr;
+
+/// Width of a trailing smoothing window.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SmoothingWindow {
+    /// The current value and up to `n - 1` preceding ones.
+    Samples(usize),
+    /// Values no older than this many seconds before the current one.
+    Seconds(f64),
+}
+
+/// Rolling smoothing applied to a power series.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum PowerSmoothing {
+    #[default]
+    None,
+    Mean(SmoothingWindow),
+    Median(SmoothingWindow),
+}
+
+impl PowerSmoothing {
+    /// Smooth `points` (`(seconds, value)`, in time order), returning one
+    /// value per point computed over its trailing window.
+    pub fn apply(&self, points: &[(f64, f64)]) -> Vec<f64> {
+        let (window, median) = match *self {
+            Self::None => return points.iter().map(|(_, value)| *value).collect(),
+            Self::Mean(window) => (window, false),
+            Self::Median(window) => (window, true),
+        };
+
+        let mut first = 0;
+        let mut smoothed = Vec::with_capacity(points.len());
+        for (index, (at, _)) in points.iter().enumerate() {
+            match window {
+                SmoothingWindow::Samples(samples) => {
+                    first = (index + 1).saturating_sub(samples.max(1));
+                }
+                SmoothingWindow::Seconds(seconds) => {
+                    while at - points[first].0 > seconds {
+                        first += 1;
+                    }
+                }
+            }
+            let mut values: Vec<f64> = points[first..=index].iter().map(|(_, v)| *v).collect();
+            smoothed.push(if median {
+                values.sort_by(f64::total_cmp);
+                let middle = values.len() / 2;
+                if values.len().is_multiple_of(2) {
+                    (values[middle - 1] + values[middle]) / 2.0
+                } else {
+                    values[middle]
+                }
+            } else {
+                values.iter().sum::<f64>() / values.len() as f64
+            });
+        }
+        smoothed
+    }
+}
+
+impl FromStr for PowerSmoothing {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let spec = spec.trim();
+        if spec == "none" {
+            return Ok(Self::None);
+        }
+        let invalid = || {
+            format!("invalid smoothing '{spec}': expected none, mean:<window> or median:<window>")
+        };
+        let (method, window) = spec.split_once(':').ok_or_else(invalid)?;
+        let window = match window.strip_suffix('s') {
+            Some(seconds) => seconds
+                .parse::<f64>()
+                .ok()
+                .filter(|seconds| seconds.is_finite() && *seconds > 0.0)
+                .map(SmoothingWindow::Seconds),
+            None => window
+                .parse::<usize>()
+                .ok()
+                .filter(|samples| *samples > 0)
+                .map(SmoothingWindow::Samples),
+        }
+        .ok_or_else(invalid)?;
+        match method {
+            "mean" => Ok(Self::Mean(window)),
+            "median" => Ok(Self::Median(window)),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl fmt::Display for PowerSmoothing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (method, window) = match self {
+            Self::None => return f.write_str("none"),
+            Self::Mean(window) => ("mean", window),
+            Self::Median(window) => ("median", window),
+        };
+        match window {
+            SmoothingWindow::Samples(samples) => write!(f, "{method}:{samples}"),
+            SmoothingWindow::Seconds(seconds) => write!(f, "{method}:{seconds}s"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POINTS: [(f64, f64); 5] = [
+        (0.0, 10.0),
+        (1.0, 20.0),
+        (2.0, 90.0),
+        (3.0, 20.0),
+        (4.0, 10.0),
+    ];
+
+    #[test]
+    fn mean_and_median_use_trailing_sample_windows() {
+        let mean: PowerSmoothing = "mean:2".parse().unwrap();
+        let median: PowerSmoothing = "median:3".parse().unwrap();
+
+        assert_eq!(mean.apply(&POINTS), vec![10.0, 15.0, 55.0, 55.0, 15.0]);
+        assert_eq!(median.apply(&POINTS), vec![10.0, 15.0, 20.0, 20.0, 20.0]);
+        assert_eq!(PowerSmoothing::None.apply(&POINTS)[2], 90.0);
+    }
+
+    #[test]
+    fn time_windows_follow_timestamps() {
+        let mean: PowerSmoothing = "mean:1.5s".parse().unwrap();
+
+        assert_eq!(mean.apply(&POINTS), vec![10.0, 15.0, 55.0, 55.0, 15.0]);
+        assert_eq!(mean.to_string(), "mean:1.5s");
+    }
+
+    #[test]
+    fn parse_rejects_malformed_specs() {
+        for spec in ["mean", "mode:3", "mean:0", "median:-1s", "mean:abc"] {
+            assert!(spec.parse::<PowerSmoothing>().is_err(), "{spec}");
+        }
+        assert_eq!(
+            "none".parse::<PowerSmoothing>().unwrap(),
+            PowerSmoothing::None
+        );
+    }
+}
@@ -0,0 +1,340 @@
+/// Spike Filter Module
+///
+/// Screens energy records for implausible readings as they are ingested, such
+/// as an occasional bad sysfs read that reports minutes of energy in one
+/// interval. Records are summed per device and collector interval, like peak
+/// detection does, and the interval's power is checked:
+///
+/// - power above a plausible maximum, absolute or a multiple of the device's
+///   rated maximum power, is clamped to that maximum;
+/// - power far above the median of the device's preceding intervals is
+///   replaced by that median;
+/// - negative energy is clamped to zero.
+///
+/// A corrected interval scales the energy of each of its records by the same
+/// factor. Every record carries a [`RecordQuality`] flag and, when corrected,
+/// its original energy, so totals are protected while each correction stays
+/// auditable in the trace.
+use crate::device_registry::DeviceRegistry;
+use crate::energy_group::EnergyRecord;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+/// Thresholds for spike filtering.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SpikeFiltering {
+    /// Plausible maximum power in watts, applied to every device.
+    pub max_power_watts: Option<f64>,
+    /// Plausible maximum as a multiple of the device's rated maximum power;
+    /// used when no absolute maximum is set.
+    pub limit_factor: f64,
+    /// Preceding intervals per device whose median power a new interval is
+    /// compared with; 0 disables the median filter.
+    pub median_window: usize,
+    /// Intervals above this multiple of the median are replaced by it.
+    pub median_factor: f64,
+}
+
+impl Default for SpikeFiltering {
+    fn default() -> Self {
+        Self {
+            max_power_watts: None,
+            limit_factor: 2.0,
+            median_window: 0,
+            median_factor: 10.0,
+        }
+    }
+}
+
+impl SpikeFiltering {
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(max) = self.max_power_watts
+            && !(max.is_finite() && max > 0.0)
+        {
+            return Err("max_power_watts must be a finite value greater than 0".to_string());
+        }
+        for (name, factor) in [
+            ("limit_factor", self.limit_factor),
+            ("median_factor", self.median_factor),
+        ] {
+            if !(factor.is_finite() && factor >= 1.0) {
+                return Err(format!("{name} must be a finite value of at least 1"));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Data-quality flag of one record.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RecordQuality {
+    /// Taken as read
+    #[default]
+    Ok,
+    /// Clamped to the plausible maximum power, or negative energy to zero
+    Clamped,
+    /// Replaced by the median power of the preceding intervals
+    MedianFiltered,
+}
+
+impl RecordQuality {
+    /// Spelling in the trace's quality column.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Ok => "ok",
+            Self::Clamped => "clamped",
+            Self::MedianFiltered => "median_filtered",
+        }
+    }
+}
+
+/// Outcome of screening one record.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RecordScreening {
+    pub quality: RecordQuality,
+    /// Energy as read, when the record was corrected
+    pub original_energy: Option<f64>,
+}
+
+#[derive(Debug, Default)]
+struct DeviceHistory {
+    last_monotonic_ns: Option<i64>,
+    /// Power of the latest intervals after clamping, oldest first
+    recent: VecDeque<f64>,
+}
+
+/// Streaming spike filter fed with energy records in time order.
+#[derive(Debug)]
+pub struct SpikeFilter {
+    config: SpikeFiltering,
+    devices: HashMap<String, DeviceHistory>,
+}
+
+impl SpikeFilter {
+    pub fn new(config: SpikeFiltering) -> Self {
+        Self {
+            config,
+            devices: HashMap::new(),
+        }
+    }
+
+    /// Correct implausible intervals of one batch in place, returning the
+    /// screening of each record. Records of one collector iteration must not
+    /// be split across batches, since they are summed per device first.
+    pub fn screen(
+        &mut self,
+        records: &mut [EnergyRecord],
+        registry: &DeviceRegistry,
+    ) -> Vec<RecordScreening> {
+        let mut intervals: BTreeMap<(i64, &str), (f64, Vec<usize>)> = BTreeMap::new();
+        for (index, record) in records.iter().enumerate() {
+            let (joules, members) = intervals
+                .entry((record.monotonic_ns, record.device.as_str()))
+                .or_default();
+            *joules += record.energy;
+            members.push(index);
+        }
+
+        let mut corrections = Vec::new();
+        for ((monotonic_ns, device), (joules, members)) in intervals {
+            let limit_watts = registry
+                .id(device)
+                .and_then(|id| registry.get(id))
+                .and_then(|info| info.max_power_watts);
+            if let Some((quality, kept)) =
+                self.screen_interval(device, monotonic_ns, joules, limit_watts)
+            {
+                log::warn!(
+                    "Implausible reading on {}: {:.3} J kept as {:.3} J ({})",
+                    device,
+                    joules,
+                    kept,
+                    quality.as_str()
+                );
+                let scale = if joules > 0.0 { kept / joules } else { 0.0 };
+                corrections.push((quality, scale, members));
+            }
+        }
+
+        let mut screening = vec![RecordScreening::default(); records.len()];
+        for (quality, scale, members) in corrections {
+            for index in members {
+                let record = &mut records[index];
+                screening[index] = RecordScreening {
+                    quality,
+                    original_energy: Some(record.energy),
+                };
+                record.energy *= scale;
+            }
+        }
+        screening
+    }
+
+    /// Quality and energy to keep for an interval of `device` ending at
+    /// `monotonic_ns`, or `None` if it is kept as read.
+    fn screen_interval(
+        &mut self,
+        device: &str,
+        monotonic_ns: i64,
+        joules: f64,
+        limit_watts: Option<f64>,
+    ) -> Option<(RecordQuality, f64)> {
+        let max_watts = self
+            .config
+            .max_power_watts
+            .or(limit_watts.map(|limit| limit * self.config.limit_factor));
+        let (window, median_factor) = (self.config.median_window, self.config.median_factor);
+
+        let state = self.devices.entry(device.to_string()).or_default();
+        let elapsed_ns = match state.last_monotonic_ns.replace(monotonic_ns) {
+            Some(last) if monotonic_ns > last => monotonic_ns - last,
+            Some(last) => {
+                state.last_monotonic_ns = Some(last);
+                0
+            }
+            None => 0,
+        };
+        if joules < 0.0 {
+            return Some((RecordQuality::Clamped, 0.0));
+        }
+        if elapsed_ns == 0 {
+            return None;
+        }
+        let seconds = elapsed_ns as f64 / 1e9;
+        let watts = joules / seconds;
+
+        let (mut quality, mut kept_watts) = match max_watts {
+            Some(max) if watts > max => (RecordQuality::Clamped, max),
+            _ => (RecordQuality::Ok, watts),
+        };
+        if window > 0 {
+            // The history keeps power before median filtering, so a
+            // sustained change moves the median and stops being filtered.
+            let clamped_watts = kept_watts;
+            if state.recent.len() >= window {
+                let median = median(&state.recent);
+                if median > 0.0 && kept_watts > median * median_factor {
+                    quality = RecordQuality::MedianFiltered;
+                    kept_watts = median;
+                }
+                state.recent.pop_front();
+            }
+            state.recent.push_back(clamped_watts);
+        }
+        (quality != RecordQuality::Ok).then_some((quality, kept_watts * seconds))
+    }
+}
+
+fn median(values: &VecDeque<f64>) -> f64 {
+    let mut sorted: Vec<f64> = values.iter().copied().collect();
+    sorted.sort_by(f64::total_cmp);
+    let middle = sorted.len() / 2;
+    if sorted.len().is_multiple_of(2) {
+        (sorted[middle - 1] + sorted[middle]) / 2.0
+    } else {
+        sorted[middle]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device_registry::DeviceInfo;
+
+    /// One record per second for each pid on `device`, energies in joules.
+    fn records(device: &str, pids: &[u32], energies: &[f64]) -> Vec<EnergyRecord> {
+        energies
+            .iter()
+            .enumerate()
+            .flat_map(|(i, energy)| {
+                pids.iter().map(move |&pid| EnergyRecord {
+                    pid,
+                    timestamp_us: 1_700_000_000_000_000 + i as i64 * 1_000_000,
+                    monotonic_ns: i as i64 * 1_000_000_000,
+                    device: device.to_string(),
+                    energy: *energy / pids.len() as f64,
+                    raw_counter: None,
+                })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn clamps_power_above_a_multiple_of_the_rated_maximum() {
+        let mut registry = DeviceRegistry::new();
+        registry.register(DeviceInfo::new("gpu").with_max_power_watts(Some(100.0)));
+        let mut filter = SpikeFilter::new(SpikeFiltering::default());
+
+        let mut batch = records("gpu", &[1, 2], &[0.0, 150.0, 800.0, -4.0]);
+        // No rated maximum and no absolute one: kept as read.
+        batch.extend(records("cpu", &[1], &[0.0, 5_000.0]));
+        let screening = filter.screen(&mut batch, &registry);
+
+        let energies: Vec<f64> = batch.iter().map(|r| r.energy).collect();
+        assert_eq!(
+            energies,
+            [0.0, 0.0, 75.0, 75.0, 100.0, 100.0, 0.0, 0.0, 0.0, 5_000.0]
+        );
+        assert_eq!(screening[2], RecordScreening::default());
+        assert_eq!(
+            screening[4],
+            RecordScreening {
+                quality: RecordQuality::Clamped,
+                original_energy: Some(400.0),
+            }
+        );
+        assert_eq!(screening[6].quality, RecordQuality::Clamped);
+        assert_eq!(screening[6].original_energy, Some(-2.0));
+        assert_eq!(screening[9].quality, RecordQuality::Ok);
+    }
+
+    #[test]
+    fn median_filter_replaces_isolated_spikes_but_follows_sustained_changes() {
+        let registry = DeviceRegistry::new();
+        let mut filter = SpikeFilter::new(SpikeFiltering {
+            median_window: 3,
+            median_factor: 5.0,
+            ..SpikeFiltering::default()
+        });
+
+        let mut batch = records(
+            "cpu",
+            &[1],
+            &[0.0, 10.0, 12.0, 11.0, 900.0, 10.0, 80.0, 80.0, 80.0],
+        );
+        let screening = filter.screen(&mut batch, &registry);
+
+        let energies: Vec<f64> = batch.iter().map(|r| r.energy).collect();
+        assert_eq!(
+            energies,
+            [0.0, 10.0, 12.0, 11.0, 11.0, 10.0, 11.0, 80.0, 80.0]
+        );
+        assert_eq!(screening[4].quality, RecordQuality::MedianFiltered);
+        assert_eq!(screening[4].original_energy, Some(900.0));
+        assert_eq!(screening[6].quality, RecordQuality::MedianFiltered);
+        assert_eq!(screening[7].quality, RecordQuality::Ok);
+    }
+
+    #[test]
+    fn validate_rejects_implausible_thresholds() {
+        assert!(SpikeFiltering::default().validate().is_ok());
+        let invalid = [
+            SpikeFiltering {
+                max_power_watts: Some(0.0),
+                ..SpikeFiltering::default()
+            },
+            SpikeFiltering {
+                limit_factor: 0.5,
+                ..SpikeFiltering::default()
+            },
+            SpikeFiltering {
+                median_factor: f64::NAN,
+                ..SpikeFiltering::default()
+            },
+        ];
+        for config in invalid {
+            assert!(config.validate().is_err());
+        }
+    }
+}
@@ -0,0 +1,152 @@
+/// Suspend Module
+///
+/// Detects system suspend and hibernation between two collector iterations.
+/// The monotonic clock stops while the system sleeps, whereas the reference
+/// clock (`CLOCK_BOOTTIME` on Linux, wall-clock time elsewhere) keeps running,
+/// so a sleep shows up as the reference clock advancing further than the
+/// monotonic one.
+///
+/// Energy counters read across a sleep are meaningless: RAPL counters may be
+/// reset by firmware or wrap while the package is powered down. The collector
+/// loop therefore zeroes the energy of the first iteration after a resume.
+/// That iteration becomes the new baseline, and its rows mark the gap in the
+/// trace. Each gap is also kept as a [`SuspendGap`].
+use crate::utils::time::{ExportTimezone, MONOTONIC_COLUMN, RecordTime};
+use polars::prelude::*;
+
+/// Clock drift below this is not treated as a suspend.
+const MIN_SUSPEND_NS: i64 = 1_000_000_000;
+
+/// One detected suspend/resume cycle.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SuspendGap {
+    /// Last observation before the suspend.
+    pub start: RecordTime,
+    /// First observation after the resume.
+    pub end: RecordTime,
+    /// Time spent suspended, which the monotonic clock does not count.
+    pub suspended_seconds: f64,
+}
+
+/// Compares monotonic and reference clock progress between observations.
+#[derive(Debug, Default)]
+pub struct SuspendDetector {
+    last: Option<(RecordTime, i64)>,
+}
+
+impl SuspendDetector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Observe the clocks now, returning the gap if the system slept since
+    /// the previous observation.
+    pub fn observe(&mut self) -> Option<SuspendGap> {
+        self.observe_at(RecordTime::now(), reference_clock_ns())
+    }
+
+    /// Observe `time` together with the reference clock reading
+    /// `reference_ns` taken at the same instant.
+    pub fn observe_at(&mut self, time: RecordTime, reference_ns: i64) -> Option<SuspendGap> {
+        let (last, last_reference_ns) = self.last.replace((time, reference_ns))?;
+        let monotonic_elapsed = time.monotonic_ns - last.monotonic_ns;
+        let suspended_ns = (reference_ns - last_reference_ns) - monotonic_elapsed;
+        (suspended_ns >= MIN_SUSPEND_NS).then(|| SuspendGap {
+            start: last,
+            end: time,
+            suspended_seconds: suspended_ns as f64 / 1e9,
+        })
+    }
+}
+
+/// Nanoseconds on a clock that keeps running while the system is suspended.
+#[cfg(target_os = "linux")]
+fn reference_clock_ns() -> i64 {
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    // SAFETY: `ts` is a valid, writable timespec.
+    if unsafe { libc::clock_gettime(libc::CLOCK_BOOTTIME, &mut ts) } == 0 {
+        ts.tv_sec * 1_000_000_000 + ts.tv_nsec
+    } else {
+        crate::utils::time::now_millis() * 1_000_000
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn reference_clock_ns() -> i64 {
+    crate::utils::time::now_millis() * 1_000_000
+}
+
+/// The gaps table: `start | end | suspended_seconds | monotonic_ns`, with
+/// wall-clock times rendered in `timezone`. `monotonic_ns` is the monotonic
+/// time of the resume, which matches the zeroed rows in the trace.
+pub fn suspend_gaps_to_dataframe(
+    gaps: &[SuspendGap],
+    timezone: ExportTimezone,
+) -> PolarsResult<DataFrame> {
+    DataFrame::new(vec![
+        Column::new(
+            "start".into(),
+            gaps.iter()
+                .map(|g| timezone.format_millis(g.start.timestamp()))
+                .collect::<Vec<_>>(),
+        ),
+        Column::new(
+            "end".into(),
+            gaps.iter()
+                .map(|g| timezone.format_millis(g.end.timestamp()))
+                .collect::<Vec<_>>(),
+        ),
+        Column::new(
+            "suspended_seconds".into(),
+            gaps.iter().map(|g| g.suspended_seconds).collect::<Vec<_>>(),
+        ),
+        Column::new(
+            MONOTONIC_COLUMN.into(),
+            gaps.iter().map(|g| g.end.monotonic_ns).collect::<Vec<_>>(),
+        ),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(monotonic_secs: i64, wall_secs: i64) -> RecordTime {
+        RecordTime {
+            timestamp_us: 1_700_000_000_000_000 + wall_secs * 1_000_000,
+            monotonic_ns: monotonic_secs * 1_000_000_000,
+        }
+    }
+
+    #[test]
+    fn reference_clock_running_ahead_is_a_suspend() {
+        let mut detector = SuspendDetector::new();
+
+        assert!(detector.observe_at(at(0, 0), 0).is_none());
+        assert!(detector.observe_at(at(1, 1), 1_000_000_000).is_none());
+        let gap = detector
+            .observe_at(at(2, 602), 602_000_000_000)
+            .expect("suspend detected");
+        assert_eq!(gap.start, at(1, 1));
+        assert_eq!(gap.end, at(2, 602));
+        assert_eq!(gap.suspended_seconds, 600.0);
+        assert!(detector.observe_at(at(3, 603), 603_000_000_000).is_none());
+
+        let table = suspend_gaps_to_dataframe(&[gap], ExportTimezone::Utc).unwrap();
+        assert_eq!(
+            table.column("end").unwrap().str().unwrap().get(0),
+            Some("2023-11-14T22:23:22.000Z")
+        );
+    }
+
+    #[test]
+    fn live_clocks_show_no_suspend() {
+        let mut detector = SuspendDetector::new();
+
+        assert!(detector.observe().is_none());
+        assert!(detector.observe().is_none());
+    }
+}
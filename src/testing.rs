@@ -0,0 +1,222 @@
+/// Testing Module
+///
+/// Fixtures for exercising collectors without the hardware they read.
+/// [`FakePowercap`] lays out a powercap directory the way the kernel does,
+/// with scripted `energy_uj` readings, for
+/// [`Rapl::new`](crate::collectors::Rapl::new) to discover:
+///
+/// ```ignore
+/// let powercap = FakePowercap::new()
+///     .package(0, [0, 2_000_000])
+///     .subzone(0, "dram", [0, 500_000])
+///     .build()?;
+/// let rapl = Rapl::new(Some(powercap.path_string()));
+/// powercap.advance()?;
+/// ```
+///
+/// Enabled by the `testing` feature, and always in this crate's own tests.
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+/// One zone of a fake powercap tree.
+#[derive(Debug, Clone)]
+struct Zone {
+    /// Directory name, e.g. `intel-rapl:0:1`
+    entry: String,
+    /// Contents of the `name` file, e.g. `package-0` or `dram`
+    name: String,
+    /// `energy_uj` readings, in micro-joules, one per step
+    readings: Vec<u64>,
+    max_energy_range_uj: Option<u64>,
+}
+
+/// Builder of a fake powercap directory tree.
+#[derive(Debug, Clone)]
+pub struct FakePowercap {
+    prefix: String,
+    zones: Vec<Zone>,
+}
+
+impl Default for FakePowercap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FakePowercap {
+    /// A tree of `intel-rapl` zones, as on Intel and on AMD Zen CPUs.
+    pub fn new() -> Self {
+        Self::with_prefix("intel-rapl")
+    }
+
+    /// A tree whose zone directories start with `prefix`, such as
+    /// `intel-rapl-mmio`.
+    pub fn with_prefix(prefix: &str) -> Self {
+        Self {
+            prefix: prefix.to_string(),
+            zones: Vec::new(),
+        }
+    }
+
+    /// Add the package zone `<prefix>:<socket>`, named `package-<socket>`.
+    pub fn package(self, socket: u32, readings: impl IntoIterator<Item = u64>) -> Self {
+        let entry = format!("{}:{socket}", self.prefix);
+        self.zone(entry, format!("package-{socket}"), readings)
+    }
+
+    /// Add a subzone of the package of `socket`, such as `core`, `uncore`
+    /// or `dram`, numbered after the subzones added before it.
+    pub fn subzone(self, socket: u32, name: &str, readings: impl IntoIterator<Item = u64>) -> Self {
+        let parent = format!("{}:{socket}:", self.prefix);
+        let index = self
+            .zones
+            .iter()
+            .filter(|zone| zone.entry.starts_with(&parent))
+            .count();
+        self.zone(format!("{parent}{index}"), name.to_string(), readings)
+    }
+
+    /// Add the top-level `psys` zone, numbered after the packages.
+    pub fn psys(self, readings: impl IntoIterator<Item = u64>) -> Self {
+        let index = self
+            .zones
+            .iter()
+            .filter(|zone| zone.entry.matches(':').count() == 1)
+            .count();
+        let entry = format!("{}:{index}", self.prefix);
+        self.zone(entry, "psys".to_string(), readings)
+    }
+
+    /// Set `max_energy_range_uj` of the most recently added zone.
+    pub fn max_energy_range(mut self, microjoules: u64) -> Self {
+        if let Some(zone) = self.zones.last_mut() {
+            zone.max_energy_range_uj = Some(microjoules);
+        }
+        self
+    }
+
+    fn zone(
+        mut self,
+        entry: String,
+        name: String,
+        readings: impl IntoIterator<Item = u64>,
+    ) -> Self {
+        self.zones.push(Zone {
+            entry,
+            name,
+            readings: readings.into_iter().collect(),
+            max_energy_range_uj: None,
+        });
+        self
+    }
+
+    /// Write the tree to a new temporary directory, with every zone at its
+    /// first reading.
+    pub fn build(self) -> io::Result<FakePowercapTree> {
+        let dir = tempfile::Builder::new().prefix("emt-powercap-").tempdir()?;
+        for zone in &self.zones {
+            let path = dir.path().join(&zone.entry);
+            fs::create_dir_all(&path)?;
+            fs::write(path.join("name"), format!("{}\n", zone.name))?;
+            if let Some(range) = zone.max_energy_range_uj {
+                fs::write(path.join("max_energy_range_uj"), format!("{range}\n"))?;
+            }
+        }
+        let tree = FakePowercapTree {
+            dir,
+            zones: self.zones,
+            step: 0,
+        };
+        tree.write_readings()?;
+        Ok(tree)
+    }
+}
+
+/// A fake powercap tree on disk, removed when dropped.
+#[derive(Debug)]
+pub struct FakePowercapTree {
+    dir: TempDir,
+    zones: Vec<Zone>,
+    step: usize,
+}
+
+impl FakePowercapTree {
+    /// Root of the tree, to pass as the powercap directory.
+    pub fn path(&self) -> &Path {
+        self.dir.path()
+    }
+
+    /// [`path`](Self::path) as the string [`Rapl::new`](crate::collectors::Rapl::new)
+    /// takes.
+    pub fn path_string(&self) -> String {
+        self.path().to_string_lossy().into_owned()
+    }
+
+    /// Directory of the zone `entry`, such as `intel-rapl:0:0`.
+    pub fn zone_path(&self, entry: &str) -> PathBuf {
+        self.path().join(entry)
+    }
+
+    /// Move every zone to its next reading. Zones whose script has run out
+    /// keep their last one.
+    pub fn advance(&mut self) -> io::Result<()> {
+        self.step += 1;
+        self.write_readings()
+    }
+
+    fn write_readings(&self) -> io::Result<()> {
+        for zone in &self.zones {
+            let reading = zone
+                .readings
+                .get(self.step)
+                .or(zone.readings.last())
+                .copied()
+                .unwrap_or(0);
+            fs::write(
+                self.path().join(&zone.entry).join("energy_uj"),
+                format!("{reading}\n"),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lays_out_zones_and_steps_through_their_readings() {
+        let mut tree = FakePowercap::new()
+            .package(0, [10, 20])
+            .subzone(0, "core", [1, 2, 3])
+            .subzone(0, "dram", [])
+            .package(1, [5])
+            .max_energy_range(100)
+            .psys([7])
+            .build()
+            .unwrap();
+        let read = |tree: &FakePowercapTree, entry: &str, file: &str| {
+            fs::read_to_string(tree.zone_path(entry).join(file))
+                .unwrap()
+                .trim()
+                .to_string()
+        };
+
+        assert_eq!(read(&tree, "intel-rapl:0:1", "name"), "dram");
+        assert_eq!(read(&tree, "intel-rapl:2", "name"), "psys");
+        assert_eq!(read(&tree, "intel-rapl:1", "max_energy_range_uj"), "100");
+        assert_eq!(read(&tree, "intel-rapl:0", "energy_uj"), "10");
+        assert_eq!(read(&tree, "intel-rapl:0:1", "energy_uj"), "0");
+        tree.advance().unwrap();
+        tree.advance().unwrap();
+        assert_eq!(read(&tree, "intel-rapl:0", "energy_uj"), "20");
+        assert_eq!(read(&tree, "intel-rapl:0:0", "energy_uj"), "3");
+
+        let path = tree.path().to_path_buf();
+        drop(tree);
+        assert!(!path.exists());
+    }
+}
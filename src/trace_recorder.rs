@@ -3,10 +3,36 @@
 /// Provides a trait and implementations for flushing energy trace data to disk.
 /// The `CsvTraceRecorder` writes data from a `RotatingTrace` to CSV files with
 /// automatic file rotation based on size limits.
+use crate::collectors::GpuUtilizationRecord;
+use crate::device_registry::{DEVICE_ID_COLUMN, DeviceRegistry};
+use crate::encryption::{ENCRYPTED_EXTENSION, STREAM_MAGIC, TraceCipher, is_encrypted};
+use crate::energy_group::{
+    CO2_COLUMN, CUMULATIVE_ENERGY_COLUMN, ORIGINAL_ENERGY_COLUMN, QUALITY_COLUMN,
+    RAW_COUNTER_COLUMN, RecordOutput, SAMPLE_DURATION_COLUMN,
+};
+use crate::peak_events::{PeakEvent, peak_events_to_dataframe};
+use crate::regions::{Region, regions_to_dataframe};
+use crate::schema::{
+    DEVICES_FILE_NAME, PEAK_EVENTS_FILE_NAME, REGIONS_FILE_NAME, SUSPEND_GAPS_FILE_NAME,
+    SchemaError, WORK_FILE_NAME, read_trace_csv_with_cipher, trace_csv_version_line,
+};
+use crate::suspend::{SuspendGap, suspend_gaps_to_dataframe};
+use crate::utils::time::{
+    ExportTimezone, MONOTONIC_COLUMN, TIMESTAMP_COLUMN, monotonic_dtype, timestamp_dtype,
+    timestamp_micros,
+};
 use crate::utils::trace_rotation::RotatingTrace;
+use crate::work::{WorkEvent, work_to_dataframe};
+use polars::prelude::{
+    BooleanChunked, Column, CsvWriter, DataFrame, NewChunkedArray, ParquetCompression,
+    ParquetReader, ParquetWriter, PolarsResult, SerReader, SerWriter,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs::{self, File, OpenOptions};
-use std::io::Write;
-use std::path::PathBuf;
+use std::io::{Cursor, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
 /// Trait for recording trace data to persistent storage.
 ///
@@ -15,15 +41,73 @@ use std::path::PathBuf;
 pub trait TraceRecorder: Send + Sync {
     /// Flush data from the given trace to persistent storage.
     fn flush(&mut self, trace: &RotatingTrace);
+
+    /// Receive the device table the trace's `device_id` column refers to.
+    /// Called before every `flush`; recorders that do not export device
+    /// metadata can ignore it.
+    fn record_devices(&mut self, _devices: &DeviceRegistry) {}
+
+    /// Receive the region markers recorded so far. Called before every
+    /// `flush`; recorders that do not export regions can ignore it.
+    fn record_regions(&mut self, _regions: &[Region]) {}
+
+    /// Receive the peak power events detected so far. Called before every
+    /// `flush`; recorders that do not export events can ignore it.
+    fn record_peak_events(&mut self, _events: &[PeakEvent]) {}
+
+    /// Receive the suspend gaps detected so far. Called before every `flush`;
+    /// recorders that do not export gaps can ignore it.
+    fn record_suspend_gaps(&mut self, _gaps: &[SuspendGap]) {}
+
+    /// Receive the work reported so far. Called before every `flush`;
+    /// recorders that do not export work can ignore it.
+    fn record_work(&mut self, _events: &[WorkEvent]) {}
+
+    /// Read back the segments written so far, each in the layout returned
+    /// by [`read_trace_csv`](crate::schema::read_trace_csv). Recorders that
+    /// cannot be read back return none.
+    fn stored_trace(&self) -> Result<Vec<DataFrame>, SchemaError> {
+        Ok(Vec::new())
+    }
+
+    /// Whether flushed rows are kept once the process exits, so the
+    /// write-ahead log can drop them. Recorders that only forward the trace
+    /// elsewhere return false.
+    fn persists(&self) -> bool {
+        true
+    }
 }
 
+const MICROS_PER_DAY: i64 = 86_400_000_000;
+const MICROS_PER_HOUR: i64 = 3_600_000_000;
+
+/// File name suffixes a trace segment can carry once sealed.
+const SEGMENT_SUFFIXES: [&str; 4] = ["", ".zst", ".enc", ".zst.enc"];
+
 /// A CSV-based trace recorder that writes energy records to rotating CSV files.
 ///
 /// Behavior:
-/// - Writes all columns from the RotatingTrace DataFrame (pid, timestamp, device, energy).
+/// - Starts every file with a `# schema_version: N` comment line.
+/// - Writes pid, timestamp, device_id, energy and monotonic_ns for every row.
+///   Timestamps are RFC 3339 in the configured timezone (UTC by default);
+///   `monotonic_ns` is left empty for frames without it.
+/// - Adds `cumulative_energy` and `raw_counter` when the trace has them; the
+///   columns of the first flushed trace are kept for all files.
+/// - Keeps the device table in `devices.csv` next to the segments, rewritten
+///   whenever new devices appear. Ids come from the registry passed to
+///   `record_devices`; devices it does not know are registered locally.
+/// - Keeps region boundaries in `regions.csv`, peak power events in
+///   `peak_events.csv`, suspend gaps in `suspend_gaps.csv` and work reports
+///   in `work.csv`, each rewritten whenever it changes.
 /// - Rotates to a new file when the current file exceeds `max_file_size_bytes`.
 /// - Keeps at most `max_files` CSV files, deleting the oldest when the limit is exceeded.
 /// - Only flushes records newer than the last flushed timestamp to avoid duplicates.
+/// - Optionally compresses finished segments with zstd (`trace_N.csv.zst`),
+///   on rotation and when the recorder is dropped.
+/// - Optionally encrypts segments with AES-256-GCM (`trace_N.csv.enc`). The
+///   file being appended to is an encrypted stream that every flush adds a
+///   frame to, so plaintext never reaches the disk, even after a crash.
+///   With compression too, finished segments become `trace_N.csv.zst.enc`.
 pub struct CsvTraceRecorder {
     output_dir: PathBuf,
     max_file_size_bytes: u64,
@@ -32,7 +116,27 @@ pub struct CsvTraceRecorder {
     current_file_path: Option<PathBuf>,
     current_file_size: u64,
     file_index: usize,
+    /// Unix microseconds of the newest row written
     last_flushed_timestamp: Option<i64>,
+    zstd_level: Option<i32>,
+    cipher: Option<TraceCipher>,
+    /// Rows not yet written as a frame of the encrypted segment
+    pending: Vec<u8>,
+    /// Frames in the encrypted segment being appended to
+    frames_written: u64,
+    timezone: ExportTimezone,
+    /// Optional columns in the file header
+    record_output: RecordOutput,
+    devices: DeviceRegistry,
+    devices_written: usize,
+    regions: Vec<Region>,
+    regions_written: bool,
+    peak_events: Vec<PeakEvent>,
+    peak_events_written: bool,
+    suspend_gaps: Vec<SuspendGap>,
+    suspend_gaps_written: bool,
+    work: Vec<WorkEvent>,
+    work_written: bool,
 }
 
 impl CsvTraceRecorder {
@@ -56,37 +160,168 @@ impl CsvTraceRecorder {
             current_file_size: 0,
             file_index: 0,
             last_flushed_timestamp: None,
+            zstd_level: None,
+            cipher: None,
+            pending: Vec::new(),
+            frames_written: 0,
+            timezone: ExportTimezone::default(),
+            record_output: RecordOutput::default(),
+            devices: DeviceRegistry::new(),
+            devices_written: 0,
+            regions: Vec::new(),
+            regions_written: true,
+            peak_events: Vec::new(),
+            peak_events_written: true,
+            suspend_gaps: Vec::new(),
+            suspend_gaps_written: true,
+            work: Vec::new(),
+            work_written: true,
         }
     }
 
+    /// Render timestamps in `timezone` instead of UTC.
+    pub fn with_timezone(mut self, timezone: ExportTimezone) -> Self {
+        self.timezone = timezone;
+        self
+    }
+
+    /// Compress rotated files with zstd at `level` (1-22; 3 is a good default).
+    pub fn with_zstd_compression(mut self, level: i32) -> Self {
+        self.zstd_level = Some(level);
+        self
+    }
+
     /// Generate the file path for a given index.
     fn file_path_for_index(&self, index: usize) -> PathBuf {
         self.output_dir.join(format!("trace_{}.csv", index))
     }
 
+    /// Path the segment of `index` is appended to: the plain path, or its
+    /// `.enc` variant when encrypting.
+    fn segment_path(&self, index: usize) -> PathBuf {
+        let path = self.file_path_for_index(index);
+        match self.cipher {
+            Some(_) => {
+                let mut sealed = path.into_os_string();
+                sealed.push(".");
+                sealed.push(ENCRYPTED_EXTENSION);
+                sealed.into()
+            }
+            None => path,
+        }
+    }
+
     /// Ensure the output directory exists.
     fn ensure_output_dir(&self) -> std::io::Result<()> {
         fs::create_dir_all(&self.output_dir)
     }
 
-    /// Open a new CSV file and write the header row.
-    fn rotate_file(&mut self) -> std::io::Result<()> {
-        // Close current file if open
-        self.current_file = None;
+    /// Encrypt segments, including the one being appended to, with `cipher`.
+    pub fn with_encryption(mut self, cipher: TraceCipher) -> Self {
+        self.cipher = Some(cipher);
+        self
+    }
 
-        // Advance to next file index
-        self.file_index += 1;
-        let path = self.file_path_for_index(self.file_index);
+    /// Compress a closed segment to `<path>.zst` and remove the plain file.
+    fn compress_segment(path: &Path, level: i32) -> std::io::Result<PathBuf> {
+        let mut compressed_path = path.as_os_str().to_owned();
+        compressed_path.push(".zst");
+        let output = File::create(&compressed_path)?;
+        zstd::stream::copy_encode(File::open(path)?, output, level)?;
+        fs::remove_file(path)?;
+        Ok(compressed_path.into())
+    }
+
+    /// Compress an encrypted segment `<name>.enc` to `<name>.zst.enc` and
+    /// remove it, decrypting it in memory only.
+    fn compress_sealed_segment(
+        path: &Path,
+        level: i32,
+        cipher: &TraceCipher,
+    ) -> Result<PathBuf, SchemaError> {
+        let plaintext = cipher.open(&fs::read(path)?)?;
+        let compressed = zstd::encode_all(plaintext.as_slice(), level)?;
+        let mut compressed_path = path.with_extension("").into_os_string();
+        compressed_path.push(".zst.");
+        compressed_path.push(ENCRYPTED_EXTENSION);
+        fs::write(&compressed_path, cipher.seal(&compressed)?)?;
+        fs::remove_file(path)?;
+        Ok(compressed_path.into())
+    }
+
+    /// Apply the configured compression to a closed segment. Encrypted
+    /// segments were sealed as they were written.
+    fn seal_segment(&self, path: &Path) {
+        let Some(level) = self.zstd_level else {
+            return;
+        };
+        let compressed = match &self.cipher {
+            Some(cipher) => Self::compress_sealed_segment(path, level, cipher).map(drop),
+            None => Self::compress_segment(path, level)
+                .map(drop)
+                .map_err(SchemaError::from),
+        };
+        if let Err(e) = compressed {
+            log::warn!("Failed to compress trace segment {}: {}", path.display(), e);
+        }
+    }
+
+    /// Write `bytes` to the current segment, or hold them for the next frame
+    /// when encrypting.
+    fn write_bytes(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+        if self.cipher.is_some() {
+            self.pending.extend_from_slice(bytes);
+        } else if let Some(ref mut file) = self.current_file {
+            file.write_all(bytes)?;
+        }
+        Ok(())
+    }
+
+    /// Encrypt the held rows as the next frame of the current segment.
+    fn write_pending_frame(&mut self) -> Result<(), SchemaError> {
+        let (Some(cipher), Some(file)) = (&self.cipher, &mut self.current_file) else {
+            return Ok(());
+        };
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        file.write_all(&cipher.seal_frame(self.frames_written, &self.pending)?)?;
+        self.frames_written += 1;
+        self.pending.clear();
+        Ok(())
+    }
 
-        let file = OpenOptions::new()
+    /// Create the segment file at `path` as the current one, starting the
+    /// stream of frames when encrypting.
+    fn open_segment(&mut self, path: PathBuf) -> std::io::Result<()> {
+        let mut file = OpenOptions::new()
             .create(true)
             .write(true)
             .truncate(true)
             .open(&path)?;
-
+        if self.cipher.is_some() {
+            file.write_all(STREAM_MAGIC)?;
+        }
         self.current_file = Some(file);
         self.current_file_path = Some(path);
         self.current_file_size = 0;
+        self.frames_written = 0;
+        self.pending.clear();
+        Ok(())
+    }
+
+    /// Open a new CSV file and write the header row.
+    fn rotate_file(&mut self) -> std::io::Result<()> {
+        // Close current file if open
+        self.write_pending_frame().map_err(std::io::Error::other)?;
+        self.current_file = None;
+        if let Some(path) = self.current_file_path.take() {
+            self.seal_segment(&path);
+        }
+
+        // Advance to next file index
+        self.file_index += 1;
+        self.open_segment(self.segment_path(self.file_index))?;
 
         // Write CSV header
         self.write_header()?;
@@ -97,11 +332,30 @@ impl CsvTraceRecorder {
         Ok(())
     }
 
-    /// Write the CSV header row.
+    /// Write the schema version line and the CSV header row.
     fn write_header(&mut self) -> std::io::Result<()> {
-        let header = "pid,timestamp,device,energy\n";
-        if let Some(ref mut file) = self.current_file {
-            file.write_all(header.as_bytes())?;
+        let mut header = format!(
+            "{}pid,timestamp,{DEVICE_ID_COLUMN},energy,{MONOTONIC_COLUMN}",
+            trace_csv_version_line()
+        );
+        if self.record_output.cumulative_energy {
+            header.push_str(&format!(",{CUMULATIVE_ENERGY_COLUMN}"));
+        }
+        if self.record_output.raw_counter {
+            header.push_str(&format!(",{RAW_COUNTER_COLUMN}"));
+        }
+        if self.record_output.co2 {
+            header.push_str(&format!(",{CO2_COLUMN}"));
+        }
+        if self.record_output.sample_duration {
+            header.push_str(&format!(",{SAMPLE_DURATION_COLUMN}"));
+        }
+        if self.record_output.quality {
+            header.push_str(&format!(",{QUALITY_COLUMN},{ORIGINAL_ENERGY_COLUMN}"));
+        }
+        header.push('\n');
+        if self.current_file.is_some() {
+            self.write_bytes(header.as_bytes())?;
             self.current_file_size += header.len() as u64;
         }
         Ok(())
@@ -111,18 +365,7 @@ impl CsvTraceRecorder {
     fn ensure_file_open(&mut self) -> std::io::Result<()> {
         if self.current_file.is_none() {
             self.ensure_output_dir()?;
-            let path = self.file_path_for_index(self.file_index);
-
-            let file = OpenOptions::new()
-                .create(true)
-                .write(true)
-                .truncate(true)
-                .open(&path)?;
-
-            self.current_file = Some(file);
-            self.current_file_path = Some(path);
-            self.current_file_size = 0;
-
+            self.open_segment(self.segment_path(self.file_index))?;
             self.write_header()?;
         }
         Ok(())
@@ -138,8 +381,72 @@ impl CsvTraceRecorder {
         let oldest_to_keep = self.file_index - self.max_files + 1;
         for i in 0..oldest_to_keep {
             let path = self.file_path_for_index(i);
-            let _ = fs::remove_file(path);
+            for suffix in SEGMENT_SUFFIXES {
+                let mut variant = path.as_os_str().to_owned();
+                variant.push(suffix);
+                let _ = fs::remove_file(variant);
+            }
+        }
+    }
+
+    /// Write the device table to `devices.csv` (`devices.csv.enc` when
+    /// encrypting), replacing the previous version.
+    fn write_devices(&self) -> Result<(), SchemaError> {
+        self.write_table(DEVICES_FILE_NAME, self.devices.to_dataframe()?)
+    }
+
+    /// Write the region boundaries to `regions.csv` (`regions.csv.enc` when
+    /// encrypting), replacing the previous version.
+    fn write_regions(&self) -> Result<(), SchemaError> {
+        self.write_table(
+            REGIONS_FILE_NAME,
+            regions_to_dataframe(&self.regions, self.timezone)?,
+        )
+    }
+
+    /// Write the work reports to `work.csv` (`work.csv.enc` when
+    /// encrypting), replacing the previous version.
+    fn write_work(&self) -> Result<(), SchemaError> {
+        self.write_table(
+            WORK_FILE_NAME,
+            work_to_dataframe(&self.work, self.timezone)?,
+        )
+    }
+
+    /// Write the peak power events to `peak_events.csv`
+    /// (`peak_events.csv.enc` when encrypting), replacing the previous version.
+    fn write_peak_events(&self) -> Result<(), SchemaError> {
+        self.write_table(
+            PEAK_EVENTS_FILE_NAME,
+            peak_events_to_dataframe(&self.peak_events, self.timezone)?,
+        )
+    }
+
+    /// Write the suspend gaps to `suspend_gaps.csv`
+    /// (`suspend_gaps.csv.enc` when encrypting), replacing the previous version.
+    fn write_suspend_gaps(&self) -> Result<(), SchemaError> {
+        self.write_table(
+            SUSPEND_GAPS_FILE_NAME,
+            suspend_gaps_to_dataframe(&self.suspend_gaps, self.timezone)?,
+        )
+    }
+
+    /// Write `table` as a versioned CSV file named `file_name`.
+    fn write_table(&self, file_name: &str, mut table: DataFrame) -> Result<(), SchemaError> {
+        let mut contents = trace_csv_version_line().into_bytes();
+        CsvWriter::new(&mut contents).finish(&mut table)?;
+
+        let path = self.output_dir.join(file_name);
+        match &self.cipher {
+            Some(cipher) => {
+                let mut sealed_path = path.into_os_string();
+                sealed_path.push(".");
+                sealed_path.push(ENCRYPTED_EXTENSION);
+                fs::write(sealed_path, cipher.seal(&contents)?)?;
+            }
+            None => fs::write(path, contents)?,
         }
+        Ok(())
     }
 
     /// Write a single CSV row.
@@ -147,10 +454,20 @@ impl CsvTraceRecorder {
         &mut self,
         pid: u32,
         timestamp: i64,
-        device: &str,
+        device_id: u32,
         energy: f64,
+        monotonic_ns: Option<i64>,
+        optional: &str,
     ) -> std::io::Result<()> {
-        let row = format!("{},{},{},{}\n", pid, timestamp, device, energy);
+        let row = format!(
+            "{},{},{},{},{}{}\n",
+            pid,
+            self.timezone.format_micros(timestamp),
+            device_id,
+            energy,
+            monotonic_ns.map(|ns| ns.to_string()).unwrap_or_default(),
+            optional
+        );
         let row_bytes = row.as_bytes();
 
         // Check if we need to rotate before writing
@@ -159,8 +476,8 @@ impl CsvTraceRecorder {
             self.rotate_file()?;
         }
 
-        if let Some(ref mut file) = self.current_file {
-            file.write_all(row_bytes)?;
+        if self.current_file.is_some() {
+            self.write_bytes(row_bytes)?;
             self.current_file_size += row_bytes.len() as u64;
         }
 
@@ -168,6 +485,23 @@ impl CsvTraceRecorder {
     }
 }
 
+impl Drop for CsvTraceRecorder {
+    fn drop(&mut self) {
+        if let Err(e) = self.write_pending_frame() {
+            log::error!("Failed to write trace rows: {}", e);
+        }
+        if self.zstd_level.is_none() {
+            return;
+        }
+        if let Some(mut file) = self.current_file.take() {
+            let _ = file.flush();
+        }
+        if let Some(path) = self.current_file_path.take() {
+            self.seal_segment(&path);
+        }
+    }
+}
+
 impl TraceRecorder for CsvTraceRecorder {
     fn flush(&mut self, trace: &RotatingTrace) {
         let df = trace.data();
@@ -184,13 +518,6 @@ impl TraceRecorder for CsvTraceRecorder {
                 return;
             }
         };
-        let timestamp_col = match df.column("timestamp") {
-            Ok(col) => col,
-            Err(e) => {
-                log::error!("Failed to get 'timestamp' column from trace: {}", e);
-                return;
-            }
-        };
         let device_col = match df.column("device") {
             Ok(col) => col,
             Err(e) => {
@@ -214,13 +541,18 @@ impl TraceRecorder for CsvTraceRecorder {
                 return;
             }
         };
-        let timestamps = match timestamp_col.i64() {
+        let timestamps = match timestamp_micros(df) {
             Ok(ca) => ca,
             Err(e) => {
-                log::error!("Failed to cast 'timestamp' column to i64: {}", e);
+                log::error!("Failed to read 'timestamp' column from trace: {}", e);
                 return;
             }
         };
+        let monotonic = df
+            .column(MONOTONIC_COLUMN)
+            .and_then(|col| col.cast(&polars::prelude::DataType::Int64))
+            .ok();
+        let monotonic = monotonic.as_ref().and_then(|col| col.i64().ok());
         let devices = match device_col.str() {
             Ok(ca) => ca,
             Err(e) => {
@@ -236,6 +568,26 @@ impl TraceRecorder for CsvTraceRecorder {
             }
         };
 
+        let optional_column = |name| df.column(name).ok().and_then(|col| col.f64().ok());
+        let cumulative_energies = optional_column(CUMULATIVE_ENERGY_COLUMN);
+        let raw_counters = optional_column(RAW_COUNTER_COLUMN);
+        let co2 = optional_column(CO2_COLUMN);
+        let sample_durations = optional_column(SAMPLE_DURATION_COLUMN);
+        let qualities = df
+            .column(QUALITY_COLUMN)
+            .ok()
+            .and_then(|col| col.str().ok());
+        let original_energies = optional_column(ORIGINAL_ENERGY_COLUMN);
+        if self.current_file.is_none() && self.file_index == 0 {
+            self.record_output = RecordOutput {
+                cumulative_energy: cumulative_energies.is_some(),
+                raw_counter: raw_counters.is_some(),
+                quality: qualities.is_some(),
+                co2: co2.is_some(),
+                sample_duration: sample_durations.is_some(),
+            };
+        }
+
         // Ensure we have an open file
         if let Err(e) = self.ensure_file_open() {
             log::error!("Failed to open trace output file: {}", e);
@@ -270,7 +622,33 @@ impl TraceRecorder for CsvTraceRecorder {
                 None => continue,
             };
 
-            if let Err(e) = self.write_row(pid, ts, device, energy) {
+            let monotonic_ns = monotonic.and_then(|ca| ca.get(row_idx));
+            let mut optional = String::new();
+            for (enabled, values) in [
+                (self.record_output.cumulative_energy, cumulative_energies),
+                (self.record_output.raw_counter, raw_counters),
+                (self.record_output.co2, co2),
+                (self.record_output.sample_duration, sample_durations),
+            ] {
+                if enabled {
+                    optional.push(',');
+                    if let Some(value) = values.and_then(|ca| ca.get(row_idx)) {
+                        optional.push_str(&value.to_string());
+                    }
+                }
+            }
+            if self.record_output.quality {
+                optional.push(',');
+                if let Some(quality) = qualities.and_then(|ca| ca.get(row_idx)) {
+                    optional.push_str(quality);
+                }
+                optional.push(',');
+                if let Some(value) = original_energies.and_then(|ca| ca.get(row_idx)) {
+                    optional.push_str(&value.to_string());
+                }
+            }
+            let device_id = self.devices.id_or_register(device);
+            if let Err(e) = self.write_row(pid, ts, device_id, energy, monotonic_ns, &optional) {
                 log::error!("Failed to write trace row: {}", e);
                 return;
             }
@@ -287,16 +665,423 @@ impl TraceRecorder for CsvTraceRecorder {
             self.last_flushed_timestamp = max_timestamp;
         }
 
-        // Flush the file to disk
-        if let Some(ref mut file) = self.current_file {
-            let _ = file.flush();
+        if let Err(e) = self.write_pending_frame() {
+            log::error!("Failed to write trace rows: {}", e);
+        }
+
+        // Sync to disk so a crash loses at most one flush interval
+        if let Some(ref file) = self.current_file
+            && let Err(e) = file.sync_data()
+        {
+            log::warn!("Failed to sync trace file: {}", e);
+        }
+
+        if self.devices.len() > self.devices_written {
+            match self.write_devices() {
+                Ok(()) => self.devices_written = self.devices.len(),
+                Err(e) => log::error!("Failed to write device table: {}", e),
+            }
+        }
+
+        if !self.regions_written {
+            match self.write_regions() {
+                Ok(()) => self.regions_written = true,
+                Err(e) => log::error!("Failed to write region table: {}", e),
+            }
+        }
+
+        if !self.peak_events_written {
+            match self.write_peak_events() {
+                Ok(()) => self.peak_events_written = true,
+                Err(e) => log::error!("Failed to write peak events table: {}", e),
+            }
+        }
+
+        if !self.suspend_gaps_written {
+            match self.write_suspend_gaps() {
+                Ok(()) => self.suspend_gaps_written = true,
+                Err(e) => log::error!("Failed to write suspend gaps table: {}", e),
+            }
+        }
+
+        if !self.work_written {
+            match self.write_work() {
+                Ok(()) => self.work_written = true,
+                Err(e) => log::error!("Failed to write work table: {}", e),
+            }
+        }
+    }
+
+    fn record_devices(&mut self, devices: &DeviceRegistry) {
+        if devices.len() > self.devices.len() {
+            self.devices = devices.clone();
+        }
+    }
+
+    fn record_regions(&mut self, regions: &[Region]) {
+        if regions != self.regions.as_slice() {
+            self.regions = regions.to_vec();
+            self.regions_written = false;
+        }
+    }
+
+    fn record_peak_events(&mut self, events: &[PeakEvent]) {
+        if events != self.peak_events.as_slice() {
+            self.peak_events = events.to_vec();
+            self.peak_events_written = false;
+        }
+    }
+
+    fn record_suspend_gaps(&mut self, gaps: &[SuspendGap]) {
+        if gaps != self.suspend_gaps.as_slice() {
+            self.suspend_gaps = gaps.to_vec();
+            self.suspend_gaps_written = false;
+        }
+    }
+
+    fn record_work(&mut self, events: &[WorkEvent]) {
+        if events != self.work.as_slice() {
+            self.work = events.to_vec();
+            self.work_written = false;
+        }
+    }
+
+    fn stored_trace(&self) -> Result<Vec<DataFrame>, SchemaError> {
+        let mut segments = Vec::new();
+        for index in 0..=self.file_index {
+            let path = self.file_path_for_index(index);
+            for suffix in SEGMENT_SUFFIXES {
+                let mut variant = path.as_os_str().to_owned();
+                variant.push(suffix);
+                let variant = PathBuf::from(variant);
+                if variant.exists() {
+                    segments.push(read_trace_csv_with_cipher(&variant, self.cipher.as_ref())?);
+                }
+            }
+        }
+        Ok(segments)
+    }
+}
+
+/// Trace file format written by the monitor's recorders.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TraceFormat {
+    /// Rotating CSV segments ([`CsvTraceRecorder`])
+    #[default]
+    Csv,
+    /// Day-partitioned Parquet dataset ([`ParquetTraceRecorder`])
+    Parquet,
+}
+
+/// Time span of each partition of a Parquet dataset.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ParquetPartitioning {
+    /// `date=YYYY-MM-DD`
+    #[default]
+    Day,
+    /// `date=YYYY-MM-DD/hour=HH`
+    Hour,
+}
+
+impl FromStr for ParquetPartitioning {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "day" => Ok(Self::Day),
+            "hour" => Ok(Self::Hour),
+            _ => Err(format!(
+                "partitioning must be 'day' or 'hour', got '{name}'"
+            )),
+        }
+    }
+}
+
+/// Compression codec of Parquet files.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ParquetCodec {
+    Uncompressed,
+    Snappy,
+    Lz4,
+    #[default]
+    Zstd,
+}
+
+impl ParquetCodec {
+    fn compression(self) -> ParquetCompression {
+        match self {
+            Self::Uncompressed => ParquetCompression::Uncompressed,
+            Self::Snappy => ParquetCompression::Snappy,
+            Self::Lz4 => ParquetCompression::Lz4Raw,
+            Self::Zstd => ParquetCompression::Zstd(None),
+        }
+    }
+}
+
+impl FromStr for ParquetCodec {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "uncompressed" => Ok(Self::Uncompressed),
+            "snappy" => Ok(Self::Snappy),
+            "lz4" => Ok(Self::Lz4),
+            "zstd" => Ok(Self::Zstd),
+            _ => Err(format!(
+                "compression must be 'uncompressed', 'snappy', 'lz4' or 'zstd', got '{name}'"
+            )),
+        }
+    }
+}
+
+/// How a Parquet dataset is laid out and compressed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ParquetOptions {
+    pub partitioning: ParquetPartitioning,
+    pub compression: ParquetCodec,
+}
+
+/// A trace recorder that writes a Parquet dataset partitioned by UTC day,
+/// or by hour.
+///
+/// Behavior:
+/// - Every flush writes the rows newer than the last flushed timestamp as
+///   new part files, one per partition the rows fall on:
+///   `<dir>/date=YYYY-MM-DD/part-<session>-<seq>.parquet`, with an
+///   `hour=HH` directory below the date when partitioned by hour.
+/// - Rows keep the in-memory trace layout, including device names, so each
+///   part is self-describing.
+/// - The session is the recorder's start time plus a random suffix. A
+///   restarted recorder pointed at the same directory adds parts of a new
+///   session next to the old ones. Existing files are never overwritten.
+/// - Parts are compressed with zstd unless another codec is set.
+/// - With encryption, parts are written sealed as `*.parquet.enc`.
+pub struct ParquetTraceRecorder {
+    dataset_dir: PathBuf,
+    session: String,
+    next_part: usize,
+    /// Unix microseconds of the newest row written
+    last_flushed_timestamp: Option<i64>,
+    cipher: Option<TraceCipher>,
+    options: ParquetOptions,
+}
+
+impl ParquetTraceRecorder {
+    /// Write parts of a new session into the dataset at `dataset_dir`,
+    /// which may already hold parts of earlier sessions.
+    pub fn new(dataset_dir: PathBuf) -> Self {
+        Self {
+            dataset_dir,
+            session: format!(
+                "{}-{:08x}",
+                chrono::Utc::now().format("%Y%m%dT%H%M%SZ"),
+                rand::random::<u32>()
+            ),
+            next_part: 0,
+            last_flushed_timestamp: None,
+            cipher: None,
+            options: ParquetOptions::default(),
+        }
+    }
+
+    /// Seal parts with `cipher`.
+    pub fn with_encryption(mut self, cipher: TraceCipher) -> Self {
+        self.cipher = Some(cipher);
+        self
+    }
+
+    /// Partition and compress parts as `options` say.
+    pub fn with_options(mut self, options: ParquetOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Session name used in this recorder's part files
+    pub fn session(&self) -> &str {
+        &self.session
+    }
+
+    /// Write the rows of `frame` newer than any written before, such as a
+    /// trace or a GPU utilization frame with a `timestamp` column. Returns
+    /// the number of rows written.
+    pub fn write(&mut self, frame: &DataFrame) -> Result<usize, SchemaError> {
+        if frame.is_empty() {
+            return Ok(0);
+        }
+        let timestamps = timestamp_micros(frame)?;
+        let span_us = match self.options.partitioning {
+            ParquetPartitioning::Day => MICROS_PER_DAY,
+            ParquetPartitioning::Hour => MICROS_PER_HOUR,
+        };
+
+        // New rows grouped by partition, oldest first: row mask and newest
+        // timestamp per partition
+        let mut partitions: BTreeMap<i64, (Vec<bool>, i64)> = BTreeMap::new();
+        for (row_idx, ts) in timestamps.iter().enumerate() {
+            let Some(ts) = ts else { continue };
+            if self.last_flushed_timestamp.is_some_and(|last| ts <= last) {
+                continue;
+            }
+            let (mask, newest) = partitions
+                .entry(ts.div_euclid(span_us))
+                .or_insert_with(|| (vec![false; frame.height()], ts));
+            mask[row_idx] = true;
+            *newest = (*newest).max(ts);
+        }
+
+        let mut written = 0;
+        for (partition, (mask, newest)) in partitions {
+            let mut rows = frame.filter(&BooleanChunked::from_slice("mask".into(), &mask))?;
+            self.write_part(partition * span_us, &mut rows)?;
+            written += rows.height();
+            self.last_flushed_timestamp = Some(newest);
+        }
+        Ok(written)
+    }
+
+    /// Write `rows`, all from the partition starting at `start_us`, as the
+    /// next part of the session.
+    fn write_part(&mut self, start_us: i64, rows: &mut DataFrame) -> Result<(), SchemaError> {
+        let start = chrono::DateTime::from_timestamp_micros(start_us).unwrap_or_default();
+        let mut partition = self
+            .dataset_dir
+            .join(format!("date={}", start.format("%Y-%m-%d")));
+        if self.options.partitioning == ParquetPartitioning::Hour {
+            partition = partition.join(format!("hour={}", start.format("%H")));
+        }
+        fs::create_dir_all(&partition)?;
+
+        let mut contents = Vec::new();
+        ParquetWriter::new(&mut contents)
+            .with_compression(self.options.compression.compression())
+            .finish(rows)?;
+        let extension = match &self.cipher {
+            Some(cipher) => {
+                contents = cipher.seal(&contents)?;
+                format!("parquet.{ENCRYPTED_EXTENSION}")
+            }
+            None => "parquet".to_string(),
+        };
+        // Parts are only ever created, so an existing dataset is extended.
+        let mut file = loop {
+            let path = partition.join(format!(
+                "part-{}-{:05}.{extension}",
+                self.session, self.next_part
+            ));
+            self.next_part += 1;
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(file) => break file,
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e.into()),
+            }
+        };
+        file.write_all(&contents)?;
+        file.sync_data()?;
+        Ok(())
+    }
+
+    /// Part files of every session in the dataset, in path order, whether
+    /// partitioned by day or by hour.
+    fn part_paths(&self) -> std::io::Result<Vec<PathBuf>> {
+        let mut parts = Vec::new();
+        if self.dataset_dir.exists() {
+            collect_part_paths(&self.dataset_dir, &mut parts)?;
+        }
+        parts.sort();
+        Ok(parts)
+    }
+}
+
+/// NVIDIA utilization records as a frame in the layout of the energy trace,
+/// for [`ParquetTraceRecorder::write`]:
+/// `pid | device | sm_percent | memory_percent | timestamp | monotonic_ns`,
+/// with a null `pid` for whole devices.
+pub fn gpu_utilization_frame(records: &[GpuUtilizationRecord]) -> PolarsResult<DataFrame> {
+    DataFrame::new(vec![
+        Column::new(
+            "pid".into(),
+            records.iter().map(|r| r.pid).collect::<Vec<_>>(),
+        ),
+        Column::new(
+            "device".into(),
+            records.iter().map(|r| r.device.clone()).collect::<Vec<_>>(),
+        ),
+        Column::new(
+            "sm_percent".into(),
+            records.iter().map(|r| r.sm_percent).collect::<Vec<_>>(),
+        ),
+        Column::new(
+            "memory_percent".into(),
+            records.iter().map(|r| r.memory_percent).collect::<Vec<_>>(),
+        ),
+        Column::new(
+            TIMESTAMP_COLUMN.into(),
+            records.iter().map(|r| r.timestamp_us).collect::<Vec<_>>(),
+        )
+        .cast(&timestamp_dtype())?,
+        Column::new(
+            MONOTONIC_COLUMN.into(),
+            records.iter().map(|r| r.monotonic_ns).collect::<Vec<_>>(),
+        )
+        .cast(&monotonic_dtype())?,
+    ])
+}
+
+/// Add the part files under the partition directories of `dir` to `parts`.
+fn collect_part_paths(dir: &Path, parts: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        if path.is_dir() {
+            if name.contains('=') {
+                collect_part_paths(&path, parts)?;
+            }
+        } else if name.starts_with("part-")
+            && (name.ends_with(".parquet")
+                || name.ends_with(&format!(".parquet.{ENCRYPTED_EXTENSION}")))
+        {
+            parts.push(path);
+        }
+    }
+    Ok(())
+}
+
+impl TraceRecorder for ParquetTraceRecorder {
+    fn flush(&mut self, trace: &RotatingTrace) {
+        if let Err(e) = self.write(trace.data()) {
+            log::error!(
+                "Failed to write Parquet trace part to {}: {}",
+                self.dataset_dir.display(),
+                e
+            );
         }
     }
+
+    fn stored_trace(&self) -> Result<Vec<DataFrame>, SchemaError> {
+        let mut parts = Vec::new();
+        for path in self.part_paths()? {
+            let mut bytes = fs::read(&path)?;
+            if is_encrypted(&bytes) {
+                bytes = self
+                    .cipher
+                    .as_ref()
+                    .ok_or(SchemaError::KeyRequired)?
+                    .open(&bytes)?;
+            }
+            parts.push(ParquetReader::new(Cursor::new(bytes)).finish()?);
+        }
+        Ok(parts)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::utils::time::RecordTime;
     use crate::utils::trace_rotation::RotatingTrace;
     use polars::df;
     use std::time::{SystemTime, UNIX_EPOCH};
@@ -358,23 +1143,60 @@ mod tests {
         let file_path = tmp_dir.path().join("trace_0.csv");
         let contents = fs::read_to_string(file_path).unwrap();
         let lines: Vec<&str> = contents.lines().collect();
+        let mut devices = DeviceRegistry::new();
+        let (cpu, gpu) = (
+            devices.id_or_register("cpu").to_string(),
+            devices.id_or_register("gpu").to_string(),
+        );
 
-        assert_eq!(lines[0], "pid,timestamp,device,energy");
-        assert_eq!(lines.len(), 3); // header + 2 data rows
+        assert_eq!(lines[0], "# schema_version: 5");
+        assert_eq!(lines[1], "pid,timestamp,device_id,energy,monotonic_ns");
+        assert_eq!(lines.len(), 4); // version + header + 2 data rows
 
         // Verify first data row
-        let fields: Vec<&str> = lines[1].split(',').collect();
+        let fields: Vec<&str> = lines[2].split(',').collect();
         assert_eq!(fields[0], "42");
-        assert_eq!(fields[2], "cpu");
+        assert_eq!(fields[2], cpu);
         assert_eq!(fields[3], "1.5");
 
         // Verify second data row
-        let fields: Vec<&str> = lines[2].split(',').collect();
+        let fields: Vec<&str> = lines[3].split(',').collect();
         assert_eq!(fields[0], "43");
-        assert_eq!(fields[2], "gpu");
+        assert_eq!(fields[2], gpu);
         assert_eq!(fields[3], "2.5");
     }
 
+    #[test]
+    fn csv_recorder_renders_timestamps_in_configured_timezone() {
+        let tmp_dir = TempDir::new().unwrap();
+        let mut recorder = CsvTraceRecorder::new(tmp_dir.path().to_path_buf(), None, None)
+            .with_timezone("Asia/Tokyo".parse().unwrap());
+        let mut trace = RotatingTrace::new(i64::MAX);
+        let data = df![
+            "pid" => vec![1u32],
+            "timestamp" => vec![1_700_000_000_123i64],
+            "device" => vec!["cpu".to_string()],
+            "energy" => vec![1.0],
+        ]
+        .unwrap();
+        trace.append(&data).unwrap();
+
+        recorder.flush(&trace);
+
+        let path = tmp_dir.path().join("trace_0.csv");
+        let contents = fs::read_to_string(&path).unwrap();
+        let cpu = DeviceRegistry::new().id_or_register("cpu");
+        assert_eq!(
+            contents.lines().nth(2),
+            Some(format!("1,2023-11-15T07:13:20.123000+09:00,{cpu},1,").as_str())
+        );
+        let frame = crate::schema::read_trace_csv(&path).unwrap();
+        assert_eq!(
+            timestamp_micros(&frame).unwrap().get(0),
+            Some(1_700_000_000_123_000)
+        );
+    }
+
     #[test]
     fn csv_recorder_rotates_files() {
         let tmp_dir = TempDir::new().unwrap();
@@ -394,7 +1216,7 @@ mod tests {
 
         recorder.flush(&trace);
 
-        // With a 50-byte limit, the header alone is ~25 bytes, so rows should
+        // With a 50-byte limit, the header alone is ~47 bytes, so rows should
         // cause rotation. Check that multiple files were created.
         let csv_files: Vec<_> = fs::read_dir(tmp_dir.path())
             .unwrap()
@@ -409,6 +1231,63 @@ mod tests {
         );
     }
 
+    #[test]
+    fn csv_recorder_compresses_rotated_segments() {
+        let tmp_dir = TempDir::new().unwrap();
+        let mut recorder = CsvTraceRecorder::new(tmp_dir.path().to_path_buf(), Some(120), Some(10))
+            .with_zstd_compression(3);
+        let now = current_timestamp_secs();
+        let trace = make_trace_with_data((0..4).map(|i| now + i).collect());
+
+        recorder.flush(&trace);
+
+        let rotated = tmp_dir.path().join("trace_0.csv.zst");
+        assert!(rotated.exists());
+        assert!(!tmp_dir.path().join("trace_0.csv").exists());
+        let frame = crate::schema::read_trace_csv(&rotated).unwrap();
+        assert!(frame.height() > 0);
+    }
+
+    #[test]
+    fn csv_recorder_encrypts_segments_including_active_file_on_drop() {
+        let tmp_dir = TempDir::new().unwrap();
+        let key = "cd".repeat(32);
+        let mut recorder = CsvTraceRecorder::new(tmp_dir.path().to_path_buf(), None, None)
+            .with_zstd_compression(3)
+            .with_encryption(TraceCipher::from_hex_key(&key).unwrap());
+        recorder.flush(&make_trace_with_data(vec![current_timestamp_secs()]));
+        drop(recorder);
+
+        let sealed = tmp_dir.path().join("trace_0.csv.zst.enc");
+        assert!(sealed.exists());
+        assert!(!tmp_dir.path().join("trace_0.csv").exists());
+        let cipher = TraceCipher::from_hex_key(&key).unwrap();
+        let frame = crate::schema::read_trace_csv_with_cipher(&sealed, Some(&cipher)).unwrap();
+        assert_eq!(frame.height(), 1);
+    }
+
+    #[test]
+    fn csv_recorder_never_writes_plaintext_when_encrypting() {
+        let tmp_dir = TempDir::new().unwrap();
+        let key = "ef".repeat(32);
+        let mut recorder = CsvTraceRecorder::new(tmp_dir.path().to_path_buf(), None, None)
+            .with_encryption(TraceCipher::from_hex_key(&key).unwrap());
+        let now = current_timestamp_secs();
+        recorder.flush(&make_trace_with_data(vec![now]));
+        recorder.flush(&make_trace_with_data(vec![now, now + 1]));
+        // A killed process never runs `Drop`.
+        std::mem::forget(recorder);
+
+        assert!(!tmp_dir.path().join("trace_0.csv").exists());
+        let active = tmp_dir.path().join("trace_0.csv.enc");
+        let bytes = fs::read(&active).unwrap();
+        assert!(is_encrypted(&bytes));
+        assert!(!bytes.windows(b"pid".len()).any(|window| window == b"pid"));
+        let cipher = TraceCipher::from_hex_key(&key).unwrap();
+        let frame = crate::schema::read_trace_csv_with_cipher(&active, Some(&cipher)).unwrap();
+        assert_eq!(frame.height(), 2);
+    }
+
     #[test]
     fn csv_recorder_respects_max_files() {
         let tmp_dir = TempDir::new().unwrap();
@@ -428,11 +1307,12 @@ mod tests {
 
         recorder.flush(&trace);
 
-        // Count remaining CSV files
+        // Count remaining CSV segments (the device table is not a segment)
         let csv_files: Vec<_> = fs::read_dir(tmp_dir.path())
             .unwrap()
             .filter_map(|e| e.ok())
             .filter(|e| e.path().extension().is_some_and(|ext| ext == "csv"))
+            .filter(|e| e.file_name() != DEVICES_FILE_NAME)
             .collect();
 
         assert!(
@@ -501,17 +1381,341 @@ mod tests {
         let contents = fs::read_to_string(file_path).unwrap();
         let lines: Vec<&str> = contents.lines().collect();
 
-        // header + 2 original records + 1 new record = 4 lines
+        // version + header + 2 original records + 1 new record = 5 lines
         assert_eq!(
             lines.len(),
-            4,
-            "Expected 4 lines (header + 3 records), got {}",
+            5,
+            "Expected 5 lines (version + header + 3 records), got {}",
             lines.len()
         );
 
         // Verify the third data row is the new record
-        let fields: Vec<&str> = lines[3].split(',').collect();
+        let fields: Vec<&str> = lines[4].split(',').collect();
         assert_eq!(fields[0], "3");
         assert_eq!(fields[3], "30");
     }
+
+    #[test]
+    fn csv_recorder_output_reads_back_through_schema_reader() {
+        let tmp_dir = TempDir::new().unwrap();
+        let mut recorder = CsvTraceRecorder::new(tmp_dir.path().to_path_buf(), None, None);
+        let trace = make_trace_with_data(vec![current_timestamp_secs()]);
+
+        recorder.flush(&trace);
+
+        let frame = crate::schema::read_trace_csv(&tmp_dir.path().join("trace_0.csv")).unwrap();
+        assert_eq!(frame.height(), 1);
+        assert_eq!(frame.column("pid").unwrap().u32().unwrap().get(0), Some(1));
+        assert_eq!(
+            frame.column("device").unwrap().str().unwrap().get(0),
+            Some("cpu")
+        );
+    }
+
+    #[test]
+    fn csv_recorder_writes_device_table_with_registry_ids() {
+        let tmp_dir = TempDir::new().unwrap();
+        let mut recorder = CsvTraceRecorder::new(tmp_dir.path().to_path_buf(), None, None);
+        let mut devices = DeviceRegistry::new();
+        devices.id_or_register("gpu");
+        devices.register(
+            crate::device_registry::DeviceInfo::new("cpu").with_max_power_watts(Some(65.0)),
+        );
+
+        recorder.record_devices(&devices);
+        recorder.flush(&make_trace_with_data(vec![current_timestamp_secs()]));
+
+        let contents = fs::read_to_string(tmp_dir.path().join("trace_0.csv")).unwrap();
+        assert!(contents.lines().nth(2).unwrap().starts_with("1,"));
+        let table =
+            crate::schema::read_devices_csv(&tmp_dir.path().join(DEVICES_FILE_NAME), None).unwrap();
+        assert_eq!(table.height(), 2);
+        assert_eq!(
+            table
+                .column("max_power_watts")
+                .unwrap()
+                .f64()
+                .unwrap()
+                .get(1),
+            Some(65.0)
+        );
+    }
+
+    #[test]
+    fn csv_recorder_writes_region_boundaries() {
+        let tmp_dir = TempDir::new().unwrap();
+        let mut recorder = CsvTraceRecorder::new(tmp_dir.path().to_path_buf(), None, None);
+        let mut markers = crate::regions::RegionMarkers::new();
+        markers.begin("warmup");
+        markers.end("warmup").unwrap();
+        markers.begin("train, epoch 1");
+
+        recorder.record_regions(markers.regions());
+        recorder.flush(&make_trace_with_data(vec![current_timestamp_secs()]));
+
+        let contents = fs::read_to_string(tmp_dir.path().join(REGIONS_FILE_NAME)).unwrap();
+        let lines: Vec<_> = contents.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(
+            lines[1],
+            "region,start,end,start_monotonic_ns,end_monotonic_ns"
+        );
+        assert!(lines[2].starts_with("warmup,"));
+        assert!(lines[3].starts_with("\"train, epoch 1\","));
+
+        let regions =
+            crate::schema::read_regions_csv(&tmp_dir.path().join(REGIONS_FILE_NAME), None).unwrap();
+        assert_eq!(regions.len(), 2);
+        assert_eq!(
+            regions[0].start.monotonic_ns,
+            markers.regions()[0].start.monotonic_ns
+        );
+        assert!(regions[0].end.is_some());
+        assert_eq!(regions[1].name, "train, epoch 1");
+        assert_eq!(regions[1].end, None);
+    }
+
+    #[test]
+    fn csv_recorder_writes_work_reports() {
+        let tmp_dir = TempDir::new().unwrap();
+        let mut recorder = CsvTraceRecorder::new(tmp_dir.path().to_path_buf(), None, None);
+        let mut work = crate::work::WorkCounters::new();
+        work.add("requests", 12.0, None).unwrap();
+        work.add("tokens", 512.0, Some(42)).unwrap();
+
+        recorder.record_work(work.events());
+        recorder.flush(&make_trace_with_data(vec![current_timestamp_secs()]));
+
+        let events =
+            crate::schema::read_work_csv(&tmp_dir.path().join(WORK_FILE_NAME), None).unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].unit, "requests");
+        assert_eq!(events[0].pid, None);
+        assert_eq!(events[1].count, 512.0);
+        assert_eq!(events[1].pid, Some(42));
+        assert_eq!(
+            events[1].time.monotonic_ns,
+            work.events()[1].time.monotonic_ns
+        );
+    }
+
+    #[test]
+    fn csv_recorder_writes_optional_record_columns() {
+        let tmp_dir = TempDir::new().unwrap();
+        let mut recorder = CsvTraceRecorder::new(tmp_dir.path().to_path_buf(), None, None);
+        let mut trace = RotatingTrace::new(3600);
+        let now = current_timestamp_secs();
+        trace
+            .append(
+                &df![
+                    "pid" => [1u32, 1],
+                    "timestamp" => [now, now + 1],
+                    "device" => ["cpu", "cpu"],
+                    "energy" => [2.0, 3.0],
+                    CUMULATIVE_ENERGY_COLUMN => [2.0, 5.0],
+                    RAW_COUNTER_COLUMN => [Some(100.0), None],
+                    CO2_COLUMN => [Some(0.25), None],
+                    QUALITY_COLUMN => ["clamped", "ok"],
+                    ORIGINAL_ENERGY_COLUMN => [Some(40.0), None],
+                ]
+                .unwrap(),
+            )
+            .unwrap();
+
+        recorder.flush(&trace);
+
+        let path = tmp_dir.path().join("trace_0.csv");
+        let contents = fs::read_to_string(&path).unwrap();
+        let lines: Vec<_> = contents.lines().collect();
+        assert_eq!(
+            lines[1],
+            "pid,timestamp,device_id,energy,monotonic_ns,cumulative_energy,raw_counter,co2,quality,original_energy"
+        );
+        assert!(lines[2].ends_with(",,2,100,0.25,clamped,40"));
+        assert!(lines[3].ends_with(",,5,,,ok,"));
+
+        let frame = crate::schema::read_trace_csv(&path).unwrap();
+        let cumulative = frame
+            .column(CUMULATIVE_ENERGY_COLUMN)
+            .unwrap()
+            .f64()
+            .unwrap();
+        assert_eq!(cumulative.get(1), Some(5.0));
+        let raw = frame.column(RAW_COUNTER_COLUMN).unwrap().f64().unwrap();
+        assert_eq!(raw.get(1), None);
+        let co2 = frame.column(CO2_COLUMN).unwrap().f64().unwrap();
+        assert_eq!(co2.get(0), Some(0.25));
+        let quality = frame.column(QUALITY_COLUMN).unwrap().str().unwrap();
+        assert_eq!(quality.get(0), Some("clamped"));
+        let original = frame.column(ORIGINAL_ENERGY_COLUMN).unwrap().f64().unwrap();
+        assert_eq!(original.get(0), Some(40.0));
+        assert_eq!(original.get(1), None);
+    }
+
+    #[test]
+    fn csv_recorder_writes_suspend_gaps_table() {
+        let tmp_dir = TempDir::new().unwrap();
+        let mut recorder = CsvTraceRecorder::new(tmp_dir.path().to_path_buf(), None, None);
+        let gap = SuspendGap {
+            start: RecordTime {
+                timestamp_us: 1_700_000_000_000_000,
+                monotonic_ns: 5_000_000_000,
+            },
+            end: RecordTime {
+                timestamp_us: 1_700_000_600_000_000,
+                monotonic_ns: 5_100_000_000,
+            },
+            suspended_seconds: 599.9,
+        };
+
+        recorder.record_suspend_gaps(&[gap]);
+        recorder.flush(&make_trace_with_data(vec![current_timestamp_secs()]));
+
+        let contents = fs::read_to_string(tmp_dir.path().join(SUSPEND_GAPS_FILE_NAME)).unwrap();
+        let lines: Vec<_> = contents.lines().collect();
+        assert_eq!(lines[1], "start,end,suspended_seconds,monotonic_ns");
+        assert_eq!(
+            lines[2],
+            "2023-11-14T22:13:20.000Z,2023-11-14T22:23:20.000Z,599.9,5100000000"
+        );
+    }
+
+    #[test]
+    fn csv_recorder_writes_peak_events_table() {
+        let tmp_dir = TempDir::new().unwrap();
+        let mut recorder = CsvTraceRecorder::new(tmp_dir.path().to_path_buf(), None, None);
+        let event = PeakEvent {
+            device: "gpu".to_string(),
+            start: 1_700_000_000_000,
+            end: 1_700_000_002_000,
+            duration_seconds: 2.0,
+            peak_watts: 310.0,
+            energy_joules: 600.0,
+            threshold_watts: 270.0,
+            limit_watts: Some(300.0),
+            ongoing: false,
+        };
+
+        recorder.record_peak_events(&[event]);
+        recorder.flush(&make_trace_with_data(vec![current_timestamp_secs()]));
+
+        let contents = fs::read_to_string(tmp_dir.path().join(PEAK_EVENTS_FILE_NAME)).unwrap();
+        assert!(
+            contents
+                .lines()
+                .nth(1)
+                .unwrap()
+                .starts_with("device,start,end")
+        );
+        assert!(
+            contents
+                .lines()
+                .nth(2)
+                .unwrap()
+                .starts_with("gpu,2023-11-14T22:13:20.000Z,")
+        );
+    }
+
+    #[test]
+    fn parquet_recorder_extends_dataset_across_sessions() {
+        let tmp_dir = TempDir::new().unwrap();
+        // 2023-11-14T23:59:59Z and 2023-11-15T00:00:01Z
+        let trace = make_trace_with_data(vec![1_700_006_399_000, 1_700_006_401_000]);
+        let mut first = ParquetTraceRecorder::new(tmp_dir.path().to_path_buf());
+        first.flush(&trace);
+        first.flush(&trace);
+
+        let mut second = ParquetTraceRecorder::new(tmp_dir.path().to_path_buf());
+        assert_ne!(first.session(), second.session());
+        second.flush(&trace);
+
+        let parts = second.part_paths().unwrap();
+        let names: Vec<String> = parts
+            .iter()
+            .map(|path| {
+                let partition = path.parent().unwrap().file_name().unwrap();
+                partition.to_string_lossy().into_owned()
+            })
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "date=2023-11-14",
+                "date=2023-11-14",
+                "date=2023-11-15",
+                "date=2023-11-15"
+            ]
+        );
+        let stored = first.stored_trace().unwrap();
+        assert_eq!(stored.len(), 4);
+        assert!(stored.iter().all(|part| part.height() == 1));
+        assert_eq!(
+            stored[0].column("device").unwrap().str().unwrap().get(0),
+            Some("cpu")
+        );
+    }
+
+    #[test]
+    fn parquet_recorder_partitions_by_hour_with_the_chosen_codec() {
+        // 2023-11-14T22:59:59Z and 2023-11-14T23:00:01Z
+        let trace = make_trace_with_data(vec![1_700_002_799_000, 1_700_002_801_000]);
+        for codec in ["uncompressed", "snappy", "lz4", "zstd"] {
+            let tmp_dir = TempDir::new().unwrap();
+            let mut recorder = ParquetTraceRecorder::new(tmp_dir.path().to_path_buf())
+                .with_options(ParquetOptions {
+                    partitioning: ParquetPartitioning::Hour,
+                    compression: codec.parse().unwrap(),
+                });
+
+            assert_eq!(recorder.write(trace.data()).unwrap(), 2);
+            assert_eq!(recorder.write(trace.data()).unwrap(), 0);
+
+            let partitions: Vec<PathBuf> = recorder
+                .part_paths()
+                .unwrap()
+                .iter()
+                .map(|path| {
+                    path.parent()
+                        .unwrap()
+                        .strip_prefix(tmp_dir.path())
+                        .unwrap()
+                        .to_path_buf()
+                })
+                .collect();
+            assert_eq!(
+                partitions,
+                [
+                    Path::new("date=2023-11-14/hour=22"),
+                    Path::new("date=2023-11-14/hour=23")
+                ]
+            );
+            let stored = recorder.stored_trace().unwrap();
+            assert_eq!(stored.iter().map(DataFrame::height).sum::<usize>(), 2);
+        }
+        assert!("brotli".parse::<ParquetCodec>().is_err());
+        assert!("minute".parse::<ParquetPartitioning>().is_err());
+    }
+
+    #[test]
+    fn utilization_frame_keeps_device_rows_with_a_null_pid() {
+        let record = |pid| GpuUtilizationRecord {
+            pid,
+            timestamp_us: 1_700_000_000_000_000,
+            monotonic_ns: 5,
+            device: "nvidia:gpu:0".to_string(),
+            sm_percent: 40.0,
+            memory_percent: 10.0,
+        };
+
+        let frame = gpu_utilization_frame(&[record(None), record(Some(7))]).unwrap();
+
+        assert_eq!(frame.height(), 2);
+        let pids = frame.column("pid").unwrap().u32().unwrap();
+        assert_eq!(pids.get(0), None);
+        assert_eq!(pids.get(1), Some(7));
+        assert_eq!(
+            frame.column(TIMESTAMP_COLUMN).unwrap().dtype(),
+            &timestamp_dtype()
+        );
+    }
 }
@@ -0,0 +1,267 @@
+/// Trace Summary Module
+///
+/// Energy totals and summary statistics over an energy trace. They cover
+/// the rows the trace holds, so for an
+/// [`EnergyGroup`](crate::energy_group::EnergyGroup) the retention window.
+/// Totals count each energy domain once under the group's
+/// [`EnergyDomainRule`]s, as [`EnergyTotals`] does, so RAPL PSYS and the
+/// packages it contains are not added together; only
+/// [`total_energy_by_device`] lists every device as recorded, derived ones
+/// included.
+///
+/// Power is taken per read: the records sharing a monotonic timestamp are
+/// totalled and divided by the time since the previous read. The first read
+/// has no interval, so its energy is left out of the mean power.
+use crate::config::EnergyDomainRule;
+use crate::derived::DERIVED_PREFIX;
+use crate::energy_totals::EnergyTotals;
+use crate::utils::time::MONOTONIC_COLUMN;
+use polars::prelude::*;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+const JOULES_PER_KWH: f64 = 3_600_000.0;
+
+/// Energy and power of a trace.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct TraceSummary {
+    pub energy_joules: f64,
+    pub energy_kwh: f64,
+    /// Time from the first to the last read, in seconds
+    pub duration_seconds: f64,
+    /// Energy after the first read over `duration_seconds`; zero before a
+    /// second read
+    pub mean_power_watts: f64,
+    /// Highest power of any read interval; zero before a second read
+    pub peak_power_watts: f64,
+}
+
+/// Rows of measured devices, leaving out derived ones.
+fn measured(trace: &DataFrame) -> PolarsResult<LazyFrame> {
+    let is_measured: BooleanChunked = trace
+        .column("device")?
+        .str()?
+        .into_iter()
+        .map(|device| device.is_some_and(|device| !device.starts_with(DERIVED_PREFIX)))
+        .collect();
+    Ok(trace.filter(&is_measured)?.lazy())
+}
+
+/// Energy per measured device for each value of `key`, cast to `i64`.
+fn device_energy_by(
+    trace: &DataFrame,
+    key: &str,
+) -> PolarsResult<BTreeMap<i64, BTreeMap<String, f64>>> {
+    let totals = measured(trace)?
+        .group_by([col(key).cast(DataType::Int64).alias(key), col("device")])
+        .agg([col("energy").cast(DataType::Float64).sum()])
+        .collect()?;
+    let keys = totals.column(key)?.i64()?;
+    let devices = totals.column("device")?.str()?;
+    let energy = totals.column("energy")?.f64()?;
+    let mut by_key: BTreeMap<i64, BTreeMap<String, f64>> = BTreeMap::new();
+    for ((key, device), joules) in keys.into_iter().zip(devices).zip(energy) {
+        if let (Some(key), Some(device)) = (key, device) {
+            by_key
+                .entry(key)
+                .or_default()
+                .insert(device.to_string(), joules.unwrap_or(0.0));
+        }
+    }
+    Ok(by_key)
+}
+
+/// Energy of `trace` in joules, each domain of `rules` counted once.
+pub fn total_energy(trace: &DataFrame, rules: &[EnergyDomainRule]) -> PolarsResult<f64> {
+    let devices = total_energy_by_device(trace)?;
+    Ok(EnergyTotals::from_devices(rules, &devices).total_joules)
+}
+
+/// Energy of each device in `trace`, derived devices included, in joules.
+pub fn total_energy_by_device(trace: &DataFrame) -> PolarsResult<BTreeMap<String, f64>> {
+    let totals = trace
+        .clone()
+        .lazy()
+        .group_by([col("device")])
+        .agg([col("energy").sum()])
+        .collect()?;
+    let devices = totals.column("device")?.str()?;
+    let energy = totals.column("energy")?.f64()?;
+    Ok(devices
+        .into_iter()
+        .zip(energy)
+        .filter_map(|(device, joules)| Some((device?.to_string(), joules.unwrap_or(0.0))))
+        .collect())
+}
+
+/// Energy attributed to each pid in `trace`, each domain of `rules`
+/// counted once, in joules. Pid 0 holds the unattributed energy.
+pub fn total_energy_by_pid(
+    trace: &DataFrame,
+    rules: &[EnergyDomainRule],
+) -> PolarsResult<BTreeMap<u32, f64>> {
+    Ok(device_energy_by(trace, "pid")?
+        .into_iter()
+        .map(|(pid, devices)| {
+            (
+                pid as u32,
+                EnergyTotals::from_devices(rules, &devices).total_joules,
+            )
+        })
+        .collect())
+}
+
+/// Energy, duration and power of `trace`, each domain of `rules` counted
+/// once.
+pub fn summary(trace: &DataFrame, rules: &[EnergyDomainRule]) -> PolarsResult<TraceSummary> {
+    let reads: Vec<(i64, f64)> = device_energy_by(trace, MONOTONIC_COLUMN)?
+        .into_iter()
+        .map(|(monotonic_ns, devices)| {
+            (
+                monotonic_ns,
+                EnergyTotals::from_devices(rules, &devices).total_joules,
+            )
+        })
+        .collect();
+    let (Some(&(first_ns, first_joules)), Some(&(last_ns, _))) = (reads.first(), reads.last())
+    else {
+        return Ok(TraceSummary::default());
+    };
+
+    let energy_joules = total_energy(trace, rules)?;
+    let duration_seconds = (last_ns - first_ns) as f64 / 1e9;
+    let mean_power_watts = if duration_seconds > 0.0 {
+        (energy_joules - first_joules) / duration_seconds
+    } else {
+        0.0
+    };
+    let peak_power_watts = reads
+        .windows(2)
+        .map(|pair| pair[1].1 / ((pair[1].0 - pair[0].0) as f64 / 1e9))
+        .fold(0.0, f64::max);
+    Ok(TraceSummary {
+        energy_joules,
+        energy_kwh: energy_joules / JOULES_PER_KWH,
+        duration_seconds,
+        mean_power_watts,
+        peak_power_watts,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::default_energy_domains;
+    use crate::utils::time::monotonic_dtype;
+    use polars::df;
+
+    /// Two reads of a package shared by two pids, then one busier read,
+    /// half a second apart, plus a derived copy of the package.
+    fn trace() -> DataFrame {
+        let mut trace = df![
+            "pid" => [1u32, 2, 1, 2, 1, 0, 0, 0],
+            "device" => [
+                "rapl:socket:0:package",
+                "rapl:socket:0:package",
+                "rapl:socket:0:package",
+                "rapl:socket:0:package",
+                "rapl:socket:0:package",
+                "derived:cpu_total",
+                "derived:cpu_total",
+                "derived:cpu_total",
+            ],
+            "energy" => [3.0, 1.0, 4.0, 1.0, 15.0, 4.0, 5.0, 15.0],
+            MONOTONIC_COLUMN => [
+                0i64,
+                0,
+                500_000_000,
+                500_000_000,
+                1_000_000_000,
+                0,
+                500_000_000,
+                1_000_000_000,
+            ],
+        ]
+        .unwrap();
+        trace
+            .apply(MONOTONIC_COLUMN, |column| {
+                column.cast(&monotonic_dtype()).unwrap()
+            })
+            .unwrap();
+        trace
+    }
+
+    #[test]
+    fn totals_skip_derived_devices_except_per_device() {
+        let trace = trace();
+        assert_eq!(
+            total_energy(&trace, &default_energy_domains()).unwrap(),
+            24.0
+        );
+        assert_eq!(
+            total_energy_by_device(&trace).unwrap(),
+            BTreeMap::from([
+                ("derived:cpu_total".to_string(), 24.0),
+                ("rapl:socket:0:package".to_string(), 24.0),
+            ])
+        );
+        assert_eq!(
+            total_energy_by_pid(&trace, &default_energy_domains()).unwrap(),
+            BTreeMap::from([(1, 22.0), (2, 2.0)])
+        );
+    }
+
+    #[test]
+    fn summary_takes_power_per_read_interval() {
+        let summary = summary(&trace(), &default_energy_domains()).unwrap();
+        assert_eq!(
+            summary,
+            TraceSummary {
+                energy_joules: 24.0,
+                energy_kwh: 24.0 / JOULES_PER_KWH,
+                duration_seconds: 1.0,
+                // 5 J then 15 J over two half-second intervals
+                mean_power_watts: 20.0,
+                peak_power_watts: 30.0,
+            }
+        );
+        assert_eq!(
+            super::summary(&trace().head(Some(0)), &default_energy_domains()).unwrap(),
+            TraceSummary::default()
+        );
+    }
+
+    #[test]
+    fn totals_count_psys_and_its_packages_once() {
+        let mut trace = df![
+            "pid" => [1u32, 1, 1, 1],
+            "device" => [
+                "rapl:system:psys",
+                "rapl:socket:0:package",
+                "rapl:system:psys",
+                "rapl:socket:0:package",
+            ],
+            "energy" => [10.0, 6.0, 12.0, 8.0],
+            MONOTONIC_COLUMN => [0i64, 0, 1_000_000_000, 1_000_000_000],
+        ]
+        .unwrap();
+        trace
+            .apply(MONOTONIC_COLUMN, |column| {
+                column.cast(&monotonic_dtype()).unwrap()
+            })
+            .unwrap();
+        let rules = default_energy_domains();
+
+        assert_eq!(total_energy(&trace, &rules).unwrap(), 22.0);
+        assert_eq!(
+            total_energy_by_pid(&trace, &rules).unwrap(),
+            BTreeMap::from([(1, 22.0)])
+        );
+        assert_eq!(total_energy_by_device(&trace).unwrap().len(), 2);
+        let summary = summary(&trace, &rules).unwrap();
+        assert_eq!(summary.energy_joules, 22.0);
+        assert_eq!(summary.peak_power_watts, 12.0);
+        assert_eq!(summary.mean_power_watts, 12.0);
+        assert_eq!(total_energy(&trace, &[]).unwrap(), 36.0);
+    }
+}
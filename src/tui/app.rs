@@ -1,12 +1,16 @@
+use crate::forecast::{Completion, EnergyForecast, EnergyForecaster, ForecastMethod};
 use crate::metrics_sink::MetricsSink;
 use crate::monitor::{DeviceEnergy, MetricsSnapshot, MonitorHandle, WorkloadSnapshot};
 use crate::process_aggregation::percentage_of_system;
+use crate::smoothing::PowerSmoothing;
 use std::cmp::Ordering;
 use std::collections::{HashMap, HashSet, VecDeque};
 use std::time::Instant;
 
 const POWER_HISTORY_WINDOW_SECS: f64 = 60.0;
 const POWER_HISTORY_MAX_SAMPLES: usize = 120;
+/// Horizon of the energy forecast shown for the selected workload.
+pub const FORECAST_HORIZON_SECS: f64 = 3_600.0;
 
 pub struct App {
     handle: MonitorHandle,
@@ -27,6 +31,12 @@ impl App {
         }
     }
 
+    /// Smooth the power history shown in the sparklines.
+    pub fn with_power_smoothing(mut self, smoothing: PowerSmoothing) -> Self {
+        self.sink = self.sink.with_power_smoothing(smoothing);
+        self
+    }
+
     pub fn refresh(&mut self) {
         let selected_group_id = self.selected_group_id();
         let snapshot = self.handle.snapshot();
@@ -50,6 +60,12 @@ impl App {
         self.sink.power_history()
     }
 
+    /// Energy the selected workload is projected to use over the next
+    /// [`FORECAST_HORIZON_SECS`].
+    pub fn selected_forecast(&self) -> Option<EnergyForecast> {
+        self.sink.forecast(&self.selected_group_id()?)
+    }
+
     pub fn sort_mode(&self) -> SortMode {
         self.state.sort_mode
     }
@@ -295,9 +311,17 @@ pub struct TuiSink {
     baseline: Option<ResetBaseline>,
     hidden_dead_group_ids: HashSet<String>,
     power_history: RollingPowerHistory,
+    power_smoothing: PowerSmoothing,
+    /// Energy forecasters of live workloads, by group id
+    forecasters: HashMap<String, EnergyForecaster>,
 }
 
 impl TuiSink {
+    pub fn with_power_smoothing(mut self, smoothing: PowerSmoothing) -> Self {
+        self.power_smoothing = smoothing;
+        self
+    }
+
     pub fn snapshot(&self) -> &MetricsSnapshot {
         &self.snapshot
     }
@@ -309,7 +333,18 @@ impl TuiSink {
     }
 
     pub fn power_history(&self) -> PowerHistorySnapshot {
-        self.power_history.snapshot()
+        self.power_history.snapshot(self.power_smoothing)
+    }
+
+    /// Energy workload `group_id` is projected to use over the next
+    /// [`FORECAST_HORIZON_SECS`]; `None` for dead workloads and until two
+    /// snapshots have been seen.
+    pub fn forecast(&self, group_id: &str) -> Option<EnergyForecast> {
+        self.forecasters
+            .get(group_id)?
+            .forecast(Completion::Remaining(FORECAST_HORIZON_SECS))
+            .ok()
+            .flatten()
     }
 
     pub fn reset(&mut self) {
@@ -435,6 +470,22 @@ impl MetricsSink for TuiSink {
         if snapshot.timestamp > 0 {
             self.power_history
                 .record(snapshot.timestamp as f64 / 1_000.0, &snapshot.system_total);
+            let live: Vec<&WorkloadSnapshot> = snapshot
+                .workloads
+                .iter()
+                .filter(|workload| workload.is_live)
+                .collect();
+            self.forecasters
+                .retain(|group_id, _| live.iter().any(|w| w.group_id == *group_id));
+            for workload in live {
+                self.forecasters
+                    .entry(workload.group_id.clone())
+                    .or_insert_with(|| EnergyForecaster::new(ForecastMethod::default()))
+                    .observe(
+                        timestamp_to_secs(snapshot.timestamp),
+                        workload.energy.total(),
+                    );
+            }
         }
         self.snapshot = snapshot.clone();
     }
@@ -587,11 +638,11 @@ impl RollingPowerHistory {
         self.prune(at_secs);
     }
 
-    fn snapshot(&self) -> PowerHistorySnapshot {
+    fn snapshot(&self, smoothing: PowerSmoothing) -> PowerHistorySnapshot {
         PowerHistorySnapshot {
-            cpu: Self::values(&self.cpu),
-            dram: Self::values(&self.dram),
-            gpu: Self::values(&self.gpu),
+            cpu: Self::values(&self.cpu, smoothing),
+            dram: Self::values(&self.dram, smoothing),
+            gpu: Self::values(&self.gpu, smoothing),
         }
     }
 
@@ -660,8 +711,12 @@ impl RollingPowerHistory {
         }
     }
 
-    fn values(samples: &VecDeque<PowerSample>) -> Vec<f64> {
-        samples.iter().map(|sample| sample.watts).collect()
+    fn values(samples: &VecDeque<PowerSample>, smoothing: PowerSmoothing) -> Vec<f64> {
+        let points: Vec<(f64, f64)> = samples
+            .iter()
+            .map(|sample| (sample.at_secs, sample.watts))
+            .collect();
+        smoothing.apply(&points)
     }
 }
 
@@ -737,6 +792,7 @@ mod tests {
             energy,
             power_watts: 0.0,
             percentage_of_system: 0.0,
+            container: None,
         }
     }
 
@@ -876,6 +932,18 @@ mod tests {
         assert_eq!(sink.power_history().dram, vec![2.0]);
     }
 
+    #[test]
+    fn tui_sink_smooths_power_history() {
+        let mut sink = TuiSink::default().with_power_smoothing("mean:2".parse().unwrap());
+
+        sink.update(&snapshot(1_000, energy(0.0, 0.0, 0.0)));
+        sink.update(&snapshot(2_000, energy(10.0, 0.0, 0.0)));
+        sink.update(&snapshot(3_000, energy(40.0, 0.0, 0.0)));
+
+        assert_eq!(sink.power_history().cpu, vec![10.0, 20.0]);
+        assert_eq!(sink.power_history().latest_cpu(), Some(20.0));
+    }
+
     #[test]
     fn reset_zeroes_display_counters_and_preserves_workload_groups() {
         let mut sink = TuiSink::default();
@@ -1010,6 +1078,29 @@ mod tests {
         assert!(sink.power_history().cpu.is_empty());
     }
 
+    #[test]
+    fn sink_forecasts_live_workloads_over_the_next_hour() {
+        let mut sink = TuiSink::default();
+        let at = |timestamp: i64, joules: f64, is_live: bool| {
+            let mut snapshot = snapshot(timestamp, energy(joules, 0.0, 0.0));
+            let mut workload = workload("pid:1", "train", energy(joules, 0.0, 0.0));
+            workload.is_live = is_live;
+            snapshot.workloads.push(workload);
+            snapshot
+        };
+
+        sink.update(&at(1_000, 0.0, true));
+        assert_eq!(sink.forecast("pid:1"), None);
+        sink.update(&at(3_000, 100.0, true));
+
+        let forecast = sink.forecast("pid:1").unwrap();
+        assert_eq!(forecast.power_watts, 50.0);
+        assert_eq!(forecast.remaining_joules, 50.0 * FORECAST_HORIZON_SECS);
+
+        sink.update(&at(4_000, 100.0, false));
+        assert_eq!(sink.forecast("pid:1"), None);
+    }
+
     #[test]
     fn records_power_from_cumulative_energy_deltas() {
         let mut history = RollingPowerHistory::new(60.0, 120);
@@ -1017,7 +1108,7 @@ mod tests {
         history.record(0.0, &energy(0.0, 0.0, 0.0));
         history.record(2.0, &energy(10.0, 2.0, 0.0));
 
-        let snapshot = history.snapshot();
+        let snapshot = history.snapshot(PowerSmoothing::None);
         assert_eq!(snapshot.cpu, vec![5.0]);
         assert_eq!(snapshot.dram, vec![1.0]);
         assert!(snapshot.gpu.is_empty());
@@ -1031,7 +1122,7 @@ mod tests {
         history.record(1.0, &energy(2.0, 0.0, 0.0));
         history.record(2.0, &energy(2.0, 0.0, 0.0));
 
-        assert_eq!(history.snapshot().cpu, vec![2.0, 0.0]);
+        assert_eq!(history.snapshot(PowerSmoothing::None).cpu, vec![2.0, 0.0]);
     }
 
     #[test]
@@ -1043,7 +1134,10 @@ mod tests {
             history.record(sample as f64, &energy(sample as f64, 0.0, 0.0));
         }
 
-        assert_eq!(history.snapshot().cpu, vec![1.0, 1.0, 1.0]);
+        assert_eq!(
+            history.snapshot(PowerSmoothing::None).cpu,
+            vec![1.0, 1.0, 1.0]
+        );
     }
 
     #[test]
@@ -1055,7 +1149,10 @@ mod tests {
             history.record(sample as f64, &energy(sample as f64, 0.0, 0.0));
         }
 
-        assert_eq!(history.snapshot().cpu, vec![1.0, 1.0, 1.0]);
+        assert_eq!(
+            history.snapshot(PowerSmoothing::None).cpu,
+            vec![1.0, 1.0, 1.0]
+        );
         assert_eq!(history.cpu.front().map(|sample| sample.at_secs), Some(2.0));
     }
 
@@ -1067,7 +1164,7 @@ mod tests {
         history.record(1.0, &energy(5.0, 0.0, 0.0));
         history.record(0.5, &energy(10.0, 0.0, 0.0));
 
-        assert!(history.snapshot().cpu.is_empty());
+        assert!(history.snapshot(PowerSmoothing::None).cpu.is_empty());
     }
 
     fn workload_names(snapshot: &MetricsSnapshot) -> Vec<&str> {
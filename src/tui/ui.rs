@@ -1,6 +1,8 @@
+use crate::forecast::EnergyForecast;
 use crate::monitor::{DeviceSource, MetricsSnapshot};
 use crate::tui::App;
 use crate::tui::app::{PowerHistorySnapshot, SortMode};
+use crate::units::{format_energy, format_power, format_significant};
 use ratatui::Frame;
 use ratatui::layout::{Constraint, Direction, Layout, Rect};
 use ratatui::style::{Color, Modifier, Style};
@@ -17,13 +19,19 @@ pub fn render(frame: &mut Frame, app: &App) {
     let selected_group_index = app.selected_group_index();
     let expanded_group_ids = app.expanded_group_ids();
     let child_scroll_offsets = app.child_scroll_offsets();
+    let forecast = app.selected_forecast();
+
+    let header = HeaderView {
+        uptime,
+        display_elapsed,
+        power_history: &power_history,
+        forecast: forecast.as_ref(),
+    };
 
     render_snapshot(
         frame,
         &snapshot,
-        uptime,
-        display_elapsed,
-        &power_history,
+        &header,
         sort_mode,
         selected_group_index,
         expanded_group_ids,
@@ -31,12 +39,18 @@ pub fn render(frame: &mut Frame, app: &App) {
     );
 }
 
+/// What the header shows besides the snapshot.
+struct HeaderView<'a> {
+    uptime: f64,
+    display_elapsed: f64,
+    power_history: &'a PowerHistorySnapshot,
+    forecast: Option<&'a EnergyForecast>,
+}
+
 fn render_snapshot(
     frame: &mut Frame,
     snapshot: &MetricsSnapshot,
-    uptime: f64,
-    display_elapsed: f64,
-    power_history: &PowerHistorySnapshot,
+    header: &HeaderView,
     sort_mode: SortMode,
     selected_group_index: usize,
     expanded_group_ids: &HashSet<String>,
@@ -51,14 +65,7 @@ fn render_snapshot(
         ])
         .split(frame.area());
 
-    render_header(
-        frame,
-        chunks[0],
-        snapshot,
-        uptime,
-        display_elapsed,
-        power_history,
-    );
+    render_header(frame, chunks[0], snapshot, header);
     render_body(
         frame,
         chunks[1],
@@ -70,14 +77,13 @@ fn render_snapshot(
     render_footer(frame, chunks[2], sort_mode);
 }
 
-fn render_header(
-    frame: &mut Frame,
-    area: Rect,
-    snapshot: &MetricsSnapshot,
-    uptime: f64,
-    display_elapsed: f64,
-    power_history: &PowerHistorySnapshot,
-) {
+fn render_header(frame: &mut Frame, area: Rect, snapshot: &MetricsSnapshot, header: &HeaderView) {
+    let HeaderView {
+        uptime,
+        display_elapsed,
+        power_history,
+        forecast,
+    } = *header;
     let total_energy = snapshot.system_total.total();
     let power = if display_elapsed > 0.0 {
         total_energy / display_elapsed
@@ -90,7 +96,7 @@ fn render_header(
 
     let mut device_line = vec![
         Span::styled("    CPU: ", Style::default().fg(Color::Yellow)),
-        Span::raw(format!("{:.4} J", snapshot.system_total.cpu_joules)),
+        Span::raw(format_energy(snapshot.system_total.cpu_joules)),
     ];
     append_dram_header(
         &mut device_line,
@@ -101,24 +107,37 @@ fn render_header(
         device_line.extend([
             Span::raw("    "),
             Span::styled("GPU: ", Style::default().fg(Color::Yellow)),
-            Span::raw(format!("{:.4} J", snapshot.system_total.gpu_joules)),
+            Span::raw(format_energy(snapshot.system_total.gpu_joules)),
+        ]);
+    }
+
+    let mut status_line = vec![
+        Span::styled("    Uptime: ", Style::default().fg(Color::Green)),
+        Span::raw(format!("{mins:02}:{secs:02}")),
+        Span::raw(format!("    Tracked PIDs: {}", snapshot.tracked_pids.len())),
+    ];
+    if let Some(forecast) = forecast {
+        status_line.extend([
+            Span::raw("    "),
+            Span::styled("Selected, next hour: ", Style::default().fg(Color::Green)),
+            Span::raw(format!(
+                "~{} at {}",
+                format_energy(forecast.remaining_joules),
+                format_power(forecast.power_watts)
+            )),
         ]);
     }
 
     let lines = vec![
         Line::from(vec![
             Span::styled("  Avg Power: ", Style::default().fg(Color::Cyan)),
-            Span::raw(format!("{power:.2} W")),
+            Span::raw(format_power(power)),
             Span::raw("    "),
             Span::styled("Energy: ", Style::default().fg(Color::Cyan)),
-            Span::raw(format!("{total_energy:.4} J")),
+            Span::raw(format_energy(total_energy)),
         ]),
         Line::from(device_line),
-        Line::from(vec![
-            Span::styled("    Uptime: ", Style::default().fg(Color::Green)),
-            Span::raw(format!("{mins:02}:{secs:02}")),
-            Span::raw(format!("    Tracked PIDs: {}", snapshot.tracked_pids.len())),
-        ]),
+        Line::from(status_line),
     ];
 
     let block = Block::default()
@@ -156,13 +175,13 @@ fn append_dram_header(device_line: &mut Vec<Span>, source: DeviceSource, dram_jo
         DeviceSource::Measured => {
             device_line.extend([
                 Span::styled("DRAM: ", Style::default().fg(Color::Yellow)),
-                Span::raw(format!("{dram_joules:.4} J")),
+                Span::raw(format_energy(dram_joules)),
             ]);
         }
         DeviceSource::IncludedInPackage | DeviceSource::MeasuredPackage => {
             device_line.extend(disabled_dram_spans("[included in CPU]"));
         }
-        DeviceSource::Unavailable => {
+        DeviceSource::Estimated | DeviceSource::Unavailable => {
             device_line.extend(disabled_dram_spans("[unavailable]"));
         }
     }
@@ -253,7 +272,7 @@ fn render_dram_power_history(
         DeviceSource::IncludedInPackage | DeviceSource::MeasuredPackage => {
             render_disabled_power_label(frame, label_area, "DRAM: --");
         }
-        DeviceSource::Unavailable => {
+        DeviceSource::Estimated | DeviceSource::Unavailable => {
             render_disabled_power_label(frame, label_area, "DRAM: --");
         }
     }
@@ -281,7 +300,7 @@ fn render_power_label(
     color: Color,
 ) {
     let value = latest_watts
-        .map(|watts| format!("{watts:.2} W"))
+        .map(format_power)
         .unwrap_or_else(|| "--".to_string());
     let label = Paragraph::new(Line::from(vec![Span::styled(
         format!("{label}: {value}"),
@@ -442,8 +461,8 @@ fn workload_table_rows(
             cells: vec![
                 format!("{disclosure}{}", workload.name),
                 workload.user.clone(),
-                format!("{:.4}", workload.energy.total()),
-                format!("{:.2}", workload.power_watts),
+                format_significant(workload.energy.total()),
+                format_significant(workload.power_watts),
                 format!("{:.1}%", workload.percentage_of_system),
             ],
             style,
@@ -461,8 +480,8 @@ fn workload_table_rows(
                     cells: vec![
                         format!("  pid {} {}", process.pid, process.name),
                         String::new(),
-                        format!("{:.4}", process.energy.total()),
-                        format!("{:.2}", process.power_watts),
+                        format_significant(process.energy.total()),
+                        format_significant(process.power_watts),
                         String::new(),
                     ],
                     style: Style::default().fg(Color::DarkGray),
@@ -559,6 +578,7 @@ mod tests {
                 },
                 power_watts: 2.5,
                 percentage_of_system: 62.5,
+                container: None,
             }],
             unattributed: DeviceEnergy {
                 cpu_joules: 60.0,
@@ -582,9 +602,12 @@ mod tests {
                 render_snapshot(
                     frame,
                     &snapshot,
-                    60.0,
-                    60.0,
-                    &power_history,
+                    &HeaderView {
+                        uptime: 60.0,
+                        display_elapsed: 60.0,
+                        power_history: &power_history,
+                        forecast: None,
+                    },
                     SortMode::Energy,
                     0,
                     &expanded,
@@ -594,13 +617,13 @@ mod tests {
             .unwrap();
 
         let screen = terminal.backend().to_string();
-        assert!(screen.contains("Avg Power: 4.00 W"));
-        assert!(screen.contains("CPU: 5.00 W"));
-        assert!(screen.contains("DRAM: 1.50 W"));
+        assert!(screen.contains("Avg Power: 4.000 W"));
+        assert!(screen.contains("CPU: 5.000 W"));
+        assert!(screen.contains("DRAM: 1.500 W"));
         assert!(!screen.contains("GPU:"));
-        assert!(!screen.contains("GPU: 3.00 W"));
+        assert!(!screen.contains("GPU: 3.000 W"));
         assert!(screen.contains("Avg Power (W)"));
-        assert!(screen.contains("2.50"));
+        assert!(screen.contains("2.500"));
         assert!(screen.contains("python workload.py"));
         assert!(screen.contains("Tracked PIDs: 2"));
         assert!(screen.contains("sort energy"));
@@ -639,9 +662,12 @@ mod tests {
                 render_snapshot(
                     frame,
                     &snapshot,
-                    60.0,
-                    60.0,
-                    &power_history,
+                    &HeaderView {
+                        uptime: 60.0,
+                        display_elapsed: 60.0,
+                        power_history: &power_history,
+                        forecast: None,
+                    },
                     SortMode::Energy,
                     0,
                     &expanded,
@@ -653,8 +679,8 @@ mod tests {
         let screen = terminal.backend().to_string();
         assert!(screen.contains("DRAM: [included in CPU]"));
         assert!(screen.contains("DRAM: --"));
-        assert!(!screen.contains("DRAM: 0.0000 J"));
-        assert!(!screen.contains("DRAM: 1.50 W"));
+        assert!(!screen.contains("DRAM: 0 J"));
+        assert!(!screen.contains("DRAM: 1.500 W"));
         assert!(!screen.contains("DRAM unavailable"));
     }
 
@@ -690,9 +716,12 @@ mod tests {
                 render_snapshot(
                     frame,
                     &snapshot,
-                    60.0,
-                    60.0,
-                    &power_history,
+                    &HeaderView {
+                        uptime: 60.0,
+                        display_elapsed: 60.0,
+                        power_history: &power_history,
+                        forecast: None,
+                    },
                     SortMode::Energy,
                     0,
                     &expanded,
@@ -704,8 +733,8 @@ mod tests {
         let screen = terminal.backend().to_string();
         assert!(screen.contains("DRAM: [unavailable]"));
         assert!(screen.contains("DRAM: --"));
-        assert!(!screen.contains("DRAM: 0.0000 J"));
-        assert!(!screen.contains("DRAM: 1.50 W"));
+        assert!(!screen.contains("DRAM: 0 J"));
+        assert!(!screen.contains("DRAM: 1.500 W"));
         assert!(!screen.contains("DRAM included in package energy"));
     }
 
@@ -742,9 +771,12 @@ mod tests {
                 render_snapshot(
                     frame,
                     &snapshot,
-                    60.0,
-                    60.0,
-                    &power_history,
+                    &HeaderView {
+                        uptime: 60.0,
+                        display_elapsed: 60.0,
+                        power_history: &power_history,
+                        forecast: None,
+                    },
                     SortMode::Energy,
                     0,
                     &expanded,
@@ -754,8 +786,8 @@ mod tests {
             .unwrap();
 
         let screen = terminal.backend().to_string();
-        assert!(screen.contains("GPU: 30.0000 J"));
-        assert!(screen.contains("GPU: 3.00 W"));
+        assert!(screen.contains("GPU: 30.00 J"));
+        assert!(screen.contains("GPU: 3.000 W"));
     }
 
     #[test]
@@ -791,6 +823,7 @@ mod tests {
                 },
                 power_watts: 12.0,
                 percentage_of_system: 100.0,
+                container: None,
             }],
             unattributed: DeviceEnergy::default(),
             tracked_pids: vec![123],
@@ -806,9 +839,12 @@ mod tests {
                 render_snapshot(
                     frame,
                     &snapshot,
-                    2.0,
-                    2.0,
-                    &power_history,
+                    &HeaderView {
+                        uptime: 2.0,
+                        display_elapsed: 2.0,
+                        power_history: &power_history,
+                        forecast: None,
+                    },
                     SortMode::Energy,
                     0,
                     &expanded,
@@ -820,8 +856,8 @@ mod tests {
         let screen = terminal.backend().to_string();
         assert!(screen.contains("v cargo test"));
         assert!(screen.contains("pid 123 cargo"));
-        assert!(screen.contains("15.0000"));
-        assert!(screen.contains("7.50"));
+        assert!(screen.contains("15.00"));
+        assert!(screen.contains("7.500"));
     }
 
     #[test]
@@ -857,6 +893,7 @@ mod tests {
                 },
                 power_watts: 4.0,
                 percentage_of_system: 100.0,
+                container: None,
             }],
             unattributed: DeviceEnergy::default(),
             tracked_pids: vec![123],
@@ -895,6 +932,7 @@ mod tests {
                     },
                     power_watts: 3.0,
                     percentage_of_system: 37.5,
+                    container: None,
                 },
                 WorkloadSnapshot {
                     root_pid: 200,
@@ -919,6 +957,7 @@ mod tests {
                     },
                     power_watts: 0.0,
                     percentage_of_system: 62.5,
+                    container: None,
                 },
             ],
             unattributed: DeviceEnergy::default(),
@@ -969,6 +1008,7 @@ mod tests {
                 },
                 power_watts: 3.0,
                 percentage_of_system: 100.0,
+                container: None,
             }],
             unattributed: DeviceEnergy::default(),
             tracked_pids: vec![1, 2, 3],
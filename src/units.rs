@@ -0,0 +1,274 @@
+/// Units
+///
+/// The energy and power units EMT reports in, as in `emt.utils.units`, and
+/// the formatting shared by run summaries, the TUI, live output and
+/// reports. Values are printed with a fixed number of significant digits
+/// rather than of decimals, so a short run reported in kWh does not print
+/// as zero and a long one in Joules does not print noise digits.
+use std::fmt;
+use std::str::FromStr;
+
+/// Significant digits of formatted values.
+const SIGNIFICANT_DIGITS: i32 = 4;
+
+/// Most decimals printed, bounding the length of tiny values.
+const MAX_DECIMALS: i32 = 9;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EnergyUnit {
+    Joules,
+    Kilojoules,
+    Microjoules,
+    Millijoules,
+    WattHours,
+    KilowattHours,
+}
+
+impl EnergyUnit {
+    /// Joules in one unit.
+    pub fn joules(self) -> f64 {
+        match self {
+            Self::Joules => 1.0,
+            Self::Kilojoules => 1_000.0,
+            Self::Microjoules => 1e-6,
+            Self::Millijoules => 1e-3,
+            Self::WattHours => 3_600.0,
+            Self::KilowattHours => 3_600_000.0,
+        }
+    }
+
+    /// Spelling in `measurement_units`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Joules => "Joules",
+            Self::Kilojoules => "kJ",
+            Self::Microjoules => "\u{03bc}J",
+            Self::Millijoules => "mJ",
+            Self::WattHours => "Wh",
+            Self::KilowattHours => "kWh",
+        }
+    }
+
+    /// Short symbol, as printed by [`format_energy`].
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Self::Joules => "J",
+            unit => unit.name(),
+        }
+    }
+
+    pub fn from_joules(self, joules: f64) -> f64 {
+        joules / self.joules()
+    }
+
+    pub fn to_joules(self, value: f64) -> f64 {
+        value * self.joules()
+    }
+
+    /// `joules` in this unit, followed by its name.
+    pub fn format(self, joules: f64) -> String {
+        format!("{} {}", format_significant(self.from_joules(joules)), self)
+    }
+}
+
+impl fmt::Display for EnergyUnit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for EnergyUnit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Joules" => Ok(Self::Joules),
+            "kJ" => Ok(Self::Kilojoules),
+            "\u{03bc}J" | "uJ" => Ok(Self::Microjoules),
+            "mJ" => Ok(Self::Millijoules),
+            "Wh" => Ok(Self::WattHours),
+            "kWh" => Ok(Self::KilowattHours),
+            _ => Err(format!(
+                "unsupported energy unit '{s}' (expected Joules, kJ, \u{03bc}J, mJ, Wh or kWh)"
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerUnit {
+    Watts,
+    Kilowatts,
+    Milliwatts,
+}
+
+impl PowerUnit {
+    /// Watts in one unit.
+    pub fn watts(self) -> f64 {
+        match self {
+            Self::Watts => 1.0,
+            Self::Kilowatts => 1_000.0,
+            Self::Milliwatts => 1e-3,
+        }
+    }
+
+    /// Spelling in `measurement_units`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Watts => "Watts",
+            Self::Kilowatts => "kW",
+            Self::Milliwatts => "mW",
+        }
+    }
+
+    /// Short symbol, as printed by [`format_power`].
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Self::Watts => "W",
+            unit => unit.name(),
+        }
+    }
+
+    pub fn from_watts(self, watts: f64) -> f64 {
+        watts / self.watts()
+    }
+
+    /// `watts` in this unit, followed by its name.
+    pub fn format(self, watts: f64) -> String {
+        format!("{} {}", format_significant(self.from_watts(watts)), self)
+    }
+}
+
+impl fmt::Display for PowerUnit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for PowerUnit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Watts" => Ok(Self::Watts),
+            "kW" => Ok(Self::Kilowatts),
+            "mW" => Ok(Self::Milliwatts),
+            _ => Err(format!(
+                "unsupported power unit '{s}' (expected Watts, kW or mW)"
+            )),
+        }
+    }
+}
+
+/// `value` with four significant digits, never in exponent notation.
+pub fn format_significant(value: f64) -> String {
+    if value == 0.0 || !value.is_finite() {
+        return format!("{}", value.abs());
+    }
+    let magnitude = value.abs().log10().floor() as i32;
+    let decimals = (SIGNIFICANT_DIGITS - 1 - magnitude).clamp(0, MAX_DECIMALS) as usize;
+    format!("{value:.decimals$}")
+}
+
+/// `joules` in the largest of mJ, J, Wh and kWh it amounts to at least one
+/// of, such as `12.35 Wh`.
+pub fn format_energy(joules: f64) -> String {
+    let unit = scale(
+        joules,
+        &[
+            EnergyUnit::KilowattHours,
+            EnergyUnit::WattHours,
+            EnergyUnit::Joules,
+            EnergyUnit::Millijoules,
+        ],
+        EnergyUnit::Joules,
+        EnergyUnit::joules,
+    );
+    format!(
+        "{} {}",
+        format_significant(unit.from_joules(joules)),
+        unit.symbol()
+    )
+}
+
+/// `watts` in the largest of mW, W and kW it amounts to at least one of,
+/// such as `250.0 W`.
+pub fn format_power(watts: f64) -> String {
+    let unit = scale(
+        watts,
+        &[
+            PowerUnit::Kilowatts,
+            PowerUnit::Watts,
+            PowerUnit::Milliwatts,
+        ],
+        PowerUnit::Watts,
+        PowerUnit::watts,
+    );
+    format!(
+        "{} {}",
+        format_significant(unit.from_watts(watts)),
+        unit.symbol()
+    )
+}
+
+/// The first of `units`, largest first, that `value` amounts to at least
+/// one of; the smallest for smaller values and `base` for zero.
+fn scale<U: Copy>(value: f64, units: &[U], base: U, size: fn(U) -> f64) -> U {
+    if value == 0.0 || !value.is_finite() {
+        return base;
+    }
+    units
+        .iter()
+        .copied()
+        .find(|unit| value.abs() >= size(*unit))
+        .or(units.last().copied())
+        .unwrap_or(base)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_python_unit_names() {
+        assert_eq!("kWh".parse(), Ok(EnergyUnit::KilowattHours));
+        assert_eq!("uJ".parse(), Ok(EnergyUnit::Microjoules));
+        assert_eq!("\u{03bc}J".parse(), Ok(EnergyUnit::Microjoules));
+        assert_eq!("mW".parse(), Ok(PowerUnit::Milliwatts));
+        assert!("J".parse::<EnergyUnit>().is_err());
+        assert!("W".parse::<PowerUnit>().is_err());
+        assert_eq!(EnergyUnit::Joules.to_string(), "Joules");
+    }
+
+    #[test]
+    fn converts_between_units() {
+        assert_eq!(EnergyUnit::KilowattHours.from_joules(7_200_000.0), 2.0);
+        assert_eq!(EnergyUnit::WattHours.to_joules(0.5), 1_800.0);
+        assert_eq!(PowerUnit::Milliwatts.from_watts(2.5), 2_500.0);
+    }
+
+    #[test]
+    fn formats_significant_digits() {
+        assert_eq!(format_significant(1234.567), "1235");
+        assert_eq!(format_significant(12.3456), "12.35");
+        assert_eq!(format_significant(0.0123456), "0.01235");
+        assert_eq!(format_significant(-4.0), "-4.000");
+        assert_eq!(format_significant(0.0), "0");
+        assert_eq!(format_significant(-0.0), "0");
+        assert_eq!(format_significant(1e-12), "0.000000000");
+        assert_eq!(EnergyUnit::KilowattHours.format(36.0), "0.00001000 kWh");
+        assert_eq!(PowerUnit::Watts.format(20.0), "20.00 Watts");
+    }
+
+    #[test]
+    fn scales_to_readable_units() {
+        assert_eq!(format_energy(0.0), "0 J");
+        assert_eq!(format_energy(0.25), "250.0 mJ");
+        assert_eq!(format_energy(42.0), "42.00 J");
+        assert_eq!(format_energy(45_000.0), "12.50 Wh");
+        assert_eq!(format_energy(9_000_000.0), "2.500 kWh");
+        assert_eq!(format_power(0.005), "5.000 mW");
+        assert_eq!(format_power(250.0), "250.0 W");
+        assert_eq!(format_power(1_500.0), "1.500 kW");
+    }
+}
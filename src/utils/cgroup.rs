@@ -0,0 +1,259 @@
+/// Cgroup Targets
+///
+/// Reads a cgroup v2 directory directly: its member processes from
+/// `cgroup.procs`, CPU time from `cpu.stat` and memory from
+/// `memory.current`. Monitoring a cgroup this way follows processes as they
+/// enter or leave it, without walking `/proc`.
+///
+/// systemd units and slices are resolved to their cgroups by asking systemd
+/// over D-Bus, through `systemctl show`.
+///
+/// A [`CgroupAttributor`] also picks the child cgroups of parents such as
+/// `system.slice` or `kubepods.slice` as they come and go, so energy is
+/// attributed per service or container rather than per pid.
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Instant;
+
+/// Mount point of the cgroup v2 hierarchy.
+pub const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+/// A cgroup v2 directory monitored as one workload.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CgroupTarget {
+    path: PathBuf,
+}
+
+impl CgroupTarget {
+    /// Open the cgroup at `path`; relative paths are below [`CGROUP_ROOT`].
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = Path::new(CGROUP_ROOT).join(path);
+        if !path.join("cgroup.procs").is_file() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} is not a cgroup v2 directory", path.display()),
+            ));
+        }
+        Ok(Self { path })
+    }
+
+    /// Open the cgroup of the systemd unit or slice `unit`, such as
+    /// `nginx.service` or `batch.slice`; the unit must be active.
+    pub fn for_systemd_unit(unit: &str) -> io::Result<Self> {
+        let output = Command::new("systemctl")
+            .args(["show", "--property=ControlGroup", "--", unit])
+            .output()?;
+        if !output.status.success() {
+            return Err(io::Error::other(format!(
+                "systemctl show {unit} failed: {}",
+                String::from_utf8_lossy(&output.stderr)
+                    .trim()
+                    .replace('\n', "; ")
+            )));
+        }
+        let properties = String::from_utf8_lossy(&output.stdout);
+        let cgroup = unit_control_group(&properties).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("systemd unit {unit} has no cgroup; is it running?"),
+            )
+        })?;
+        Self::open(cgroup.trim_start_matches('/'))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Last path component, or `/` for the root cgroup.
+    pub fn name(&self) -> String {
+        self.path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "/".to_string())
+    }
+
+    /// Processes currently in the cgroup; empty once it is removed.
+    pub fn pids(&self) -> Vec<u32> {
+        fs::read_to_string(self.path.join("cgroup.procs"))
+            .map(|contents| parse_pids(&contents))
+            .unwrap_or_default()
+    }
+
+    /// CPU time of the cgroup and its descendants, exited processes
+    /// included, in microseconds.
+    pub fn cpu_usage_usec(&self) -> Option<u64> {
+        parse_cpu_usage_usec(&fs::read_to_string(self.path.join("cpu.stat")).ok()?)
+    }
+
+    /// Child cgroups, such as the services of a slice or the containers of
+    /// a pod, in path order; empty once the cgroup is removed.
+    pub fn children(&self) -> Vec<CgroupTarget> {
+        let Ok(entries) = fs::read_dir(&self.path) else {
+            return Vec::new();
+        };
+        let mut children: Vec<CgroupTarget> = entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.join("cgroup.procs").is_file())
+            .map(|path| CgroupTarget { path })
+            .collect();
+        children.sort_by(|a, b| a.path.cmp(&b.path));
+        children
+    }
+
+    /// Memory charged to the cgroup, in bytes; `None` without the memory
+    /// controller.
+    pub fn memory_bytes(&self) -> Option<u64> {
+        fs::read_to_string(self.path.join("memory.current"))
+            .ok()?
+            .trim()
+            .parse()
+            .ok()
+    }
+}
+
+/// The cgroups energy is attributed to, each as a whole: fixed targets,
+/// plus the current children of parent cgroups, so services and containers
+/// started later get their own share.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CgroupAttributor {
+    targets: Vec<CgroupTarget>,
+    parents: Vec<CgroupTarget>,
+}
+
+impl CgroupAttributor {
+    /// Attribute to each of `targets`.
+    pub fn new(targets: Vec<CgroupTarget>) -> Self {
+        Self {
+            targets,
+            parents: Vec::new(),
+        }
+    }
+
+    /// Also attribute to each child cgroup of `parent`.
+    pub fn with_children_of(mut self, parent: CgroupTarget) -> Self {
+        self.parents.push(parent);
+        self
+    }
+
+    /// Whether there is no cgroup to attribute to, now or later.
+    pub fn is_empty(&self) -> bool {
+        self.targets.is_empty() && self.parents.is_empty()
+    }
+
+    /// Cgroups to attribute to now: the targets, then the children of each
+    /// parent that are not targets themselves.
+    pub fn cgroups(&self) -> Vec<CgroupTarget> {
+        let mut cgroups = self.targets.clone();
+        for parent in &self.parents {
+            for child in parent.children() {
+                if !cgroups.contains(&child) {
+                    cgroups.push(child);
+                }
+            }
+        }
+        cgroups
+    }
+}
+
+impl From<Vec<CgroupTarget>> for CgroupAttributor {
+    fn from(targets: Vec<CgroupTarget>) -> Self {
+        Self::new(targets)
+    }
+}
+
+/// A cgroup attributed as a whole; its records carry `pid`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrackedCgroup {
+    pub pid: u32,
+    pub target: CgroupTarget,
+}
+
+/// Turns successive `cpu.stat` readings into CPU percentages.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct CgroupCpuTracker {
+    last: Option<(u64, Instant)>,
+}
+
+impl CgroupCpuTracker {
+    /// CPU percentage of one core since the previous reading; `None` on the
+    /// first reading and while the cgroup cannot be read.
+    pub(crate) fn update(&mut self, target: &CgroupTarget) -> Option<f64> {
+        let usage_usec = target.cpu_usage_usec()?;
+        let now = Instant::now();
+        let (last_usage, last_read) = self.last.replace((usage_usec, now))?;
+        let elapsed_usec = now.duration_since(last_read).as_micros() as f64;
+        (elapsed_usec > 0.0)
+            .then(|| usage_usec.saturating_sub(last_usage) as f64 / elapsed_usec * 100.0)
+    }
+}
+
+fn parse_pids(contents: &str) -> Vec<u32> {
+    contents
+        .lines()
+        .filter_map(|line| line.trim().parse().ok())
+        .collect()
+}
+
+/// `ControlGroup` of `systemctl show` output; empty for inactive units.
+fn unit_control_group(properties: &str) -> Option<&str> {
+    properties
+        .lines()
+        .find_map(|line| line.strip_prefix("ControlGroup="))
+        .filter(|cgroup| !cgroup.is_empty())
+}
+
+fn parse_cpu_usage_usec(contents: &str) -> Option<u64> {
+    contents.lines().find_map(|line| {
+        let (key, value) = line.split_once(' ')?;
+        (key == "usage_usec").then(|| value.trim().parse().ok())?
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_cgroup_files() {
+        assert_eq!(parse_pids("12\n7\n\n"), [12, 7]);
+        assert_eq!(
+            parse_cpu_usage_usec("usage_usec 1500\nuser_usec 1000\nsystem_usec 500\n"),
+            Some(1500)
+        );
+        assert_eq!(parse_cpu_usage_usec("user_usec 1000\n"), None);
+        assert_eq!(
+            unit_control_group("ControlGroup=/system.slice/nginx.service\n"),
+            Some("/system.slice/nginx.service")
+        );
+        assert_eq!(unit_control_group("ControlGroup=\n"), None);
+        assert!(CgroupTarget::open("/nonexistent/emt").is_err());
+    }
+
+    #[test]
+    fn attributor_follows_the_children_of_parents() {
+        let root = tempfile::TempDir::new().unwrap();
+        let cgroup = |path: &str| {
+            let dir = root.path().join(path);
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join("cgroup.procs"), "").unwrap();
+            CgroupTarget::open(&dir).unwrap()
+        };
+        let slice = cgroup("system.slice");
+        let nginx = cgroup("system.slice/nginx.service");
+        let batch = cgroup("batch.slice");
+        fs::create_dir_all(root.path().join("system.slice/not-a-cgroup")).unwrap();
+
+        let attributor =
+            CgroupAttributor::new(vec![batch.clone(), nginx.clone()]).with_children_of(slice);
+        assert_eq!(attributor.cgroups(), [batch.clone(), nginx.clone()]);
+
+        let redis = cgroup("system.slice/redis.service");
+        assert_eq!(attributor.cgroups(), [batch, nginx, redis]);
+        assert!(CgroupAttributor::default().is_empty());
+        assert!(!attributor.is_empty());
+    }
+}
@@ -1,3 +1,5 @@
+use std::fmt;
+use std::io;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -6,6 +8,80 @@ pub enum MonitoringError {
     SysinfoError(String),
     #[error("Process discovery error: {0}")]
     ProcessDiscoveryError(String),
+    #[error("Failed to get energy trace: {0}")]
+    Collector(#[from] CollectorError),
     #[error("Other error: {0}")]
     Other(String),
 }
+
+/// Why a collector could not read its energy.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum CollectorError {
+    /// A counter or device could not be read
+    #[error("I/O error: {0}")]
+    Io(String),
+    /// A counter exists but this process may not read it
+    #[error("permission denied: {0}")]
+    Permission(String),
+    /// A counter was read but holds no valid value
+    #[error("parse error: {0}")]
+    Parse(String),
+    /// A device or counter that was read before is gone
+    #[error("device lost: {0}")]
+    DeviceLost(String),
+    /// The collector has nothing it can read on this host
+    #[error("unsupported: {0}")]
+    Unsupported(String),
+}
+
+impl CollectorError {
+    /// Classify a failed read of `location`: denied access, a file or
+    /// device that went away, or any other I/O error.
+    pub fn from_io(location: impl fmt::Display, error: &io::Error) -> Self {
+        // ENXIO and ENODEV, as reads of a removed device return them
+        const DEVICE_ERRNOS: [i32; 2] = [6, 19];
+        match error.kind() {
+            io::ErrorKind::PermissionDenied => Self::Permission(location.to_string()),
+            io::ErrorKind::NotFound => Self::DeviceLost(location.to_string()),
+            _ if error
+                .raw_os_error()
+                .is_some_and(|errno| DEVICE_ERRNOS.contains(&errno)) =>
+            {
+                Self::DeviceLost(format!("{}: {}", location, error))
+            }
+            _ => Self::Io(format!("{}: {}", location, error)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn classifies_io_errors_by_cause() {
+        let path = Path::new("/sys/class/powercap/intel-rapl:0/energy_uj");
+        let denied = io::Error::from(io::ErrorKind::PermissionDenied);
+        let missing = io::Error::from(io::ErrorKind::NotFound);
+        let removed = io::Error::from_raw_os_error(19);
+        let other = io::Error::other("short read");
+
+        assert_eq!(
+            CollectorError::from_io(path.display(), &denied),
+            CollectorError::Permission(path.display().to_string())
+        );
+        assert!(matches!(
+            CollectorError::from_io(path.display(), &missing),
+            CollectorError::DeviceLost(_)
+        ));
+        assert!(matches!(
+            CollectorError::from_io(path.display(), &removed),
+            CollectorError::DeviceLost(_)
+        ));
+        assert!(matches!(
+            CollectorError::from_io(path.display(), &other),
+            CollectorError::Io(_)
+        ));
+    }
+}
@@ -0,0 +1,75 @@
+/// Match `text` against a shell-style glob `pattern`.
+///
+/// `*` matches any run of characters (including `:`), `?` matches exactly one
+/// character, and every other character matches itself. Used for device and
+/// source name rules in configuration.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((star_p, star_t)) => {
+                    p = star_p + 1;
+                    t = star_t + 1;
+                    backtrack = Some((star_p, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Returns true when any of `patterns` matches `text`.
+pub fn matches_any<S: AsRef<str>>(patterns: &[S], text: &str) -> bool {
+    patterns
+        .iter()
+        .any(|pattern| glob_match(pattern.as_ref(), text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn literal_patterns_match_exactly() {
+        assert!(glob_match("rapl:system:dram", "rapl:system:dram"));
+        assert!(!glob_match("rapl:system:dram", "rapl:system:psys"));
+        assert!(!glob_match("rapl", "rapl:system"));
+    }
+
+    #[test]
+    fn wildcards_match_segments_and_characters() {
+        assert!(glob_match(
+            "rapl:socket:*:package",
+            "rapl:socket:12:package"
+        ));
+        assert!(glob_match("nvidia:*", "nvidia:gpu:0"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("gpu:?", "gpu:3"));
+        assert!(!glob_match("gpu:?", "gpu:10"));
+        assert!(!glob_match("rapl:socket:*:package", "rapl:socket:0:core"));
+    }
+
+    #[test]
+    fn matches_any_checks_every_pattern() {
+        let patterns = vec!["nvidia:*".to_string(), "rapl:system:*".to_string()];
+
+        assert!(matches_any(&patterns, "rapl:system:psys"));
+        assert!(!matches_any(&patterns, "rapl:socket:0:package"));
+    }
+}
@@ -1,3 +1,5 @@
+use crate::container::{ContainerInfo, ContainerMetadata, detect_container};
+use crate::process::read_cgroup_path;
 use crate::utils::errors::MonitoringError;
 use std::collections::{HashMap, HashSet, VecDeque};
 use std::fs;
@@ -162,14 +164,24 @@ pub fn scan_roots() -> Vec<ProcessRoot> {
 
 // ─── Legacy functions (still used by main.rs) ────────────────────────────────
 
-/// A group of processes belonging to the same user and application
-#[derive(Debug)]
+/// A group of processes belonging to the same user and application, or
+/// to the same container
+#[derive(Debug, Default)]
 pub struct ProcessGroup {
     pub user: String,
     pub task: String,
     pub pids: Vec<usize>,
+    /// Name of the container the processes run in, else its runtime and
+    /// short id
+    pub container: Option<String>,
+    /// Kubernetes pod of the container, as `namespace/name`
+    pub pod: Option<String>,
 }
 
+/// User and task of a group, and its container and pod when grouped by
+/// container.
+type GroupKey = (String, String, Option<String>, Option<String>);
+
 pub fn resolve_username(uid: u32, users_cache: &UsersCache) -> String {
     users_cache
         .get_user_by_uid(uid)
@@ -181,11 +193,30 @@ pub fn resolve_group_name(name: &str) -> String {
     name.split('/').next().unwrap_or("unknown").to_string()
 }
 
-/// Collects all process from the system and groups them by user and application
-fn collect_all() -> Result<HashMap<(String, String), Vec<usize>>, MonitoringError> {
+/// Container of `pid`, looked up in `metadata` once per container.
+fn process_container(
+    pid: u32,
+    metadata: &ContainerMetadata,
+    resolved: &mut HashMap<String, ContainerInfo>,
+) -> Option<ContainerInfo> {
+    let cgroup_path = read_cgroup_path(pid)?;
+    let (_, container) = detect_container(&cgroup_path)?;
+    let container = resolved.entry(container.key()).or_insert_with(|| {
+        let mut container = container;
+        metadata.resolve(&mut container);
+        container
+    });
+    Some(container.clone())
+}
+
+/// Collects all process from the system and groups them by user and
+/// application, or processes in containers by container
+fn collect_all(by_container: bool) -> Result<HashMap<GroupKey, Vec<usize>>, MonitoringError> {
     let system = System::new_all();
     let users_cache = UsersCache::new();
-    let mut groups: HashMap<(String, String), Vec<usize>> = HashMap::new();
+    let metadata = ContainerMetadata::default();
+    let mut containers = HashMap::new();
+    let mut groups: HashMap<GroupKey, Vec<usize>> = HashMap::new();
 
     // If there are no processes, treat as an error
     let processes = system.processes();
@@ -200,11 +231,24 @@ fn collect_all() -> Result<HashMap<(String, String), Vec<usize>>, MonitoringErro
             .user_id()
             .map(|uid| resolve_username(**uid, &users_cache))
             .unwrap_or_else(|| "unknown".to_string());
-        let app = resolve_group_name(&process.name().to_string_lossy());
-        groups
-            .entry((user, app))
-            .or_default()
-            .push(pid.as_u32() as usize);
+        let container = by_container
+            .then(|| process_container(pid.as_u32(), &metadata, &mut containers))
+            .flatten();
+        let key = match container {
+            Some(container) => (
+                user,
+                container.label(),
+                Some(container.label()),
+                container.pod,
+            ),
+            None => (
+                user,
+                resolve_group_name(&process.name().to_string_lossy()),
+                None,
+                None,
+            ),
+        };
+        groups.entry(key).or_default().push(pid.as_u32() as usize);
     }
 
     Ok(groups)
@@ -241,10 +285,7 @@ fn expand_pids_with_children(system: &System, pids: &[usize]) -> Vec<usize> {
 }
 
 /// Filters process groups to only include groups that have at least one of the specified PIDs.
-fn filter_groups_by_pids(
-    groups: &mut HashMap<(String, String), Vec<usize>>,
-    selected_pids: &[usize],
-) {
+fn filter_groups_by_pids(groups: &mut HashMap<GroupKey, Vec<usize>>, selected_pids: &[usize]) {
     groups.retain(|_, pids| {
         pids.retain(|pid| selected_pids.contains(pid));
         !pids.is_empty()
@@ -253,8 +294,11 @@ fn filter_groups_by_pids(
 
 // Collects process groups based on the provided PIDs, if not explicitly provided collect all.
 // When PIDs are provided, also includes all child processes (process tree).
+// With `by_container`, processes in a container are grouped by container
+// instead of by application, read from `/proc/<pid>/cgroup`.
 pub fn collect_process_groups(
     selected_pids: Option<Vec<usize>>,
+    by_container: bool,
 ) -> Result<Vec<ProcessGroup>, MonitoringError> {
     let system = System::new_all();
 
@@ -272,11 +316,11 @@ pub fn collect_process_groups(
                 expanded_pids.len()
             );
 
-            let mut groups = collect_all()?;
+            let mut groups = collect_all(by_container)?;
             filter_groups_by_pids(&mut groups, &expanded_pids);
             Ok(groups)
         }
-        None => collect_all(),
+        None => collect_all(by_container),
     }?;
 
     if groups.is_empty() {
@@ -287,10 +331,12 @@ pub fn collect_process_groups(
 
     let tracked_processes: Vec<ProcessGroup> = groups
         .into_iter()
-        .map(|((user, application), pids)| ProcessGroup {
+        .map(|((user, task, container, pod), pids)| ProcessGroup {
             user,
-            task: application,
+            task,
             pids,
+            container,
+            pod,
         })
         .collect();
 
@@ -0,0 +1,309 @@
+/// Time Module
+///
+/// Single source of truth for timestamp semantics. Every energy record carries
+/// a pair captured at the same instant:
+/// - `timestamp`: wall-clock time as Unix microseconds (UTC), stored in trace
+///   frames as a `Datetime(us, UTC)` column;
+/// - `monotonic_ns`: nanoseconds on a monotonic clock anchored at the first
+///   use in the process, stored as a `Duration(ns)` column. Unlike wall-clock
+///   time it never jumps with NTP or manual clock changes, so it is the one to
+///   use for interval arithmetic within a run.
+///
+/// Collectors stamp every device read separately, right after the read, so
+/// domains read in one iteration carry their own times.
+///
+/// Exports render wall-clock time as RFC 3339 in a configurable timezone:
+/// trace rows with microsecond precision, everything else with milliseconds.
+use chrono::{DateTime, Local, SecondsFormat, Utc};
+use chrono_tz::Tz;
+use polars::prelude::*;
+use std::str::FromStr;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+/// Trace column holding wall-clock time.
+pub const TIMESTAMP_COLUMN: &str = "timestamp";
+/// Trace column holding monotonic time.
+pub const MONOTONIC_COLUMN: &str = "monotonic_ns";
+
+/// Integer timestamps below this magnitude are taken to be Unix seconds.
+/// Only used when normalizing frames supplied by callers, whose integer
+/// timestamps are otherwise taken as Unix milliseconds.
+const UNIX_MILLIS_THRESHOLD: i64 = 10_000_000_000;
+
+/// Wall-clock and monotonic time captured together.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecordTime {
+    /// Unix microseconds (UTC).
+    pub timestamp_us: i64,
+    /// Nanoseconds since the process monotonic anchor.
+    pub monotonic_ns: i64,
+}
+
+impl RecordTime {
+    pub fn now() -> Self {
+        let monotonic_ns = monotonic_anchor().elapsed().as_nanos() as i64;
+        Self {
+            timestamp_us: now_micros(),
+            monotonic_ns,
+        }
+    }
+
+    /// Wall-clock time in Unix milliseconds.
+    pub fn timestamp(&self) -> i64 {
+        self.timestamp_us.div_euclid(1000)
+    }
+}
+
+fn monotonic_anchor() -> Instant {
+    static ANCHOR: OnceLock<Instant> = OnceLock::new();
+    *ANCHOR.get_or_init(Instant::now)
+}
+
+/// Current wall-clock time in Unix milliseconds.
+pub fn now_millis() -> i64 {
+    Utc::now().timestamp_millis()
+}
+
+/// Current wall-clock time in Unix microseconds.
+pub fn now_micros() -> i64 {
+    Utc::now().timestamp_micros()
+}
+
+/// Data type of the wall-clock column in trace frames.
+pub fn timestamp_dtype() -> DataType {
+    DataType::Datetime(TimeUnit::Microseconds, Some(TimeZone::UTC))
+}
+
+fn millis_dtype() -> DataType {
+    DataType::Datetime(TimeUnit::Milliseconds, Some(TimeZone::UTC))
+}
+
+/// Data type of the monotonic column in trace frames.
+pub fn monotonic_dtype() -> DataType {
+    DataType::Duration(TimeUnit::Nanoseconds)
+}
+
+/// Bring the wall-clock column of `frame` to [`timestamp_dtype`].
+///
+/// Integer columns are interpreted as Unix milliseconds, or as Unix seconds
+/// when every value is below the millisecond range; datetime columns of any
+/// unit are converted. Frames without a timestamp column are returned as-is.
+pub fn normalize_timestamps(mut frame: DataFrame) -> PolarsResult<DataFrame> {
+    let Ok(column) = frame.column(TIMESTAMP_COLUMN) else {
+        return Ok(frame);
+    };
+    let normalized = match column.dtype() {
+        dtype if *dtype == timestamp_dtype() => return Ok(frame),
+        DataType::Datetime(_, _) => column.cast(&timestamp_dtype())?,
+        _ => {
+            let values = column.cast(&DataType::Int64)?;
+            let values = values.i64()?;
+            let in_seconds = values
+                .iter()
+                .flatten()
+                .all(|value| value.abs() < UNIX_MILLIS_THRESHOLD);
+            let millis: Int64Chunked = if in_seconds {
+                values.apply_values(|value| value.saturating_mul(1000))
+            } else {
+                values.clone()
+            };
+            millis
+                .into_series()
+                .cast(&millis_dtype())?
+                .cast(&timestamp_dtype())?
+                .with_name(TIMESTAMP_COLUMN.into())
+                .into()
+        }
+    };
+    frame.with_column(normalized)?;
+    Ok(frame)
+}
+
+/// Wall-clock values of a normalized trace frame, in Unix milliseconds.
+pub fn timestamp_millis(frame: &DataFrame) -> PolarsResult<Int64Chunked> {
+    let column = frame.column(TIMESTAMP_COLUMN)?;
+    Ok(column
+        .cast(&millis_dtype())?
+        .cast(&DataType::Int64)?
+        .i64()?
+        .clone())
+}
+
+/// Wall-clock values of a normalized trace frame, in Unix microseconds.
+pub fn timestamp_micros(frame: &DataFrame) -> PolarsResult<Int64Chunked> {
+    let column = frame.column(TIMESTAMP_COLUMN)?;
+    Ok(column
+        .cast(&timestamp_dtype())?
+        .cast(&DataType::Int64)?
+        .i64()?
+        .clone())
+}
+
+/// Timezone used to render wall-clock time in exports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExportTimezone {
+    #[default]
+    Utc,
+    /// The host's local timezone.
+    Local,
+    /// An IANA timezone such as `Europe/London`.
+    Named(Tz),
+}
+
+impl FromStr for ExportTimezone {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "UTC" | "utc" => Ok(Self::Utc),
+            "local" => Ok(Self::Local),
+            _ => Tz::from_str(name)
+                .map(Self::Named)
+                .map_err(|_| format!("unknown timezone '{name}'")),
+        }
+    }
+}
+
+impl ExportTimezone {
+    /// Render Unix milliseconds as RFC 3339 with millisecond precision.
+    ///
+    /// Values outside chrono's range fall back to the raw millisecond count,
+    /// which [`parse_export_timestamp_micros`] also accepts.
+    pub fn format_millis(&self, millis: i64) -> String {
+        let Some(utc) = DateTime::<Utc>::from_timestamp_millis(millis) else {
+            return millis.to_string();
+        };
+        match self {
+            Self::Utc => utc.to_rfc3339_opts(SecondsFormat::Millis, true),
+            Self::Local => utc
+                .with_timezone(&Local)
+                .to_rfc3339_opts(SecondsFormat::Millis, false),
+            Self::Named(tz) => utc
+                .with_timezone(tz)
+                .to_rfc3339_opts(SecondsFormat::Millis, false),
+        }
+    }
+
+    /// Render Unix microseconds as RFC 3339 with microsecond precision.
+    ///
+    /// Values outside chrono's range fall back to [`Self::format_millis`].
+    pub fn format_micros(&self, micros: i64) -> String {
+        let Some(utc) = DateTime::<Utc>::from_timestamp_micros(micros) else {
+            return self.format_millis(micros.div_euclid(1000));
+        };
+        match self {
+            Self::Utc => utc.to_rfc3339_opts(SecondsFormat::Micros, true),
+            Self::Local => utc
+                .with_timezone(&Local)
+                .to_rfc3339_opts(SecondsFormat::Micros, false),
+            Self::Named(tz) => utc
+                .with_timezone(tz)
+                .to_rfc3339_opts(SecondsFormat::Micros, false),
+        }
+    }
+}
+
+/// Parse an exported wall-clock value (RFC 3339 of any precision, or Unix
+/// milliseconds) to Unix microseconds.
+pub fn parse_export_timestamp_micros(value: &str) -> Option<i64> {
+    let value = value.trim();
+    DateTime::parse_from_rfc3339(value)
+        .map(|time| time.timestamp_micros())
+        .ok()
+        .or_else(|| value.parse::<i64>().ok().map(|millis| millis * 1000))
+}
+
+/// Parse a positive interval such as `500ms`, `10s`, `1m` or `2h`; a bare
+/// number is seconds.
+pub fn parse_interval(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    let (number, seconds_per_unit) = [("ms", 1e-3), ("s", 1.0), ("m", 60.0), ("h", 3600.0)]
+        .into_iter()
+        .find_map(|(suffix, scale)| value.strip_suffix(suffix).map(|number| (number, scale)))
+        .unwrap_or((value, 1.0));
+    let seconds = number.trim().parse::<f64>().ok()? * seconds_per_unit;
+    Duration::try_from_secs_f64(seconds)
+        .ok()
+        .filter(|interval| !interval.is_zero())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use polars::df;
+
+    #[test]
+    fn normalize_timestamps_converts_seconds_and_millis_to_datetime() {
+        let seconds = df!["timestamp" => [1_700_000_000i64, 1_700_000_001]].unwrap();
+        let millis = df!["timestamp" => [1_700_000_000_000i64, 1_700_000_001_000]].unwrap();
+
+        for frame in [seconds, millis] {
+            let frame = normalize_timestamps(frame).unwrap();
+            assert_eq!(
+                frame.column("timestamp").unwrap().dtype(),
+                &timestamp_dtype()
+            );
+            assert_eq!(
+                timestamp_millis(&frame).unwrap().get(1),
+                Some(1_700_000_001_000)
+            );
+        }
+    }
+
+    #[test]
+    fn record_time_is_monotonic() {
+        let first = RecordTime::now();
+        let second = RecordTime::now();
+
+        assert!(second.monotonic_ns >= first.monotonic_ns);
+        assert!(first.timestamp() > UNIX_MILLIS_THRESHOLD);
+        assert_eq!(first.timestamp(), first.timestamp_us / 1000);
+    }
+
+    #[test]
+    fn export_timezone_formats_and_round_trips() {
+        let millis = 1_700_000_000_123;
+        let berlin: ExportTimezone = "Europe/Berlin".parse().unwrap();
+
+        assert_eq!(
+            ExportTimezone::Utc.format_millis(millis),
+            "2023-11-14T22:13:20.123Z"
+        );
+        assert_eq!(
+            berlin.format_millis(millis),
+            "2023-11-14T23:13:20.123+01:00"
+        );
+        assert_eq!(
+            parse_export_timestamp_micros(&berlin.format_millis(millis)),
+            Some(millis * 1000)
+        );
+        assert_eq!(
+            parse_export_timestamp_micros("1700000000123"),
+            Some(millis * 1000)
+        );
+
+        let micros = 1_700_000_000_123_456;
+        assert_eq!(
+            berlin.format_micros(micros),
+            "2023-11-14T23:13:20.123456+01:00"
+        );
+        assert_eq!(
+            parse_export_timestamp_micros(&berlin.format_micros(micros)),
+            Some(micros)
+        );
+        assert!("Mars/Olympus".parse::<ExportTimezone>().is_err());
+    }
+
+    #[test]
+    fn intervals_take_unit_suffixes() {
+        assert_eq!(parse_interval("500ms"), Some(Duration::from_millis(500)));
+        assert_eq!(parse_interval("10s"), Some(Duration::from_secs(10)));
+        assert_eq!(parse_interval("1m"), Some(Duration::from_secs(60)));
+        assert_eq!(parse_interval("2h"), Some(Duration::from_secs(7200)));
+        assert_eq!(parse_interval("2.5"), Some(Duration::from_millis(2500)));
+        for invalid in ["0s", "-1", "10x", "s", ""] {
+            assert_eq!(parse_interval(invalid), None, "{invalid}");
+        }
+    }
+}
@@ -4,6 +4,14 @@
 /// Similar to log rotation, it keeps only recent data within a configurable time window
 /// (default: 1 hour) to prevent unbounded memory growth.
 ///
+/// Appends are constant-time: batches are kept as they arrive and only
+/// concatenated when the trace is next read. That read copies the new
+/// batches into one chunk and merges it with the newest chunks no more than
+/// twice its size, leaving older chunks untouched. Chunks therefore at
+/// least double in size from newest to oldest, so a column holds a
+/// logarithmic number of them, and a row is copied a logarithmic number of
+/// times over a run rather than on every read.
+///
 /// # Examples
 ///
 /// ```ignore
@@ -12,9 +20,32 @@
 /// rotating_trace.cleanup()?; // Periodically remove old entries
 /// ```
 use crate::utils::errors::MonitoringError;
+use crate::utils::pattern::glob_match;
+use crate::utils::time::{
+    TIMESTAMP_COLUMN, normalize_timestamps, now_micros, now_millis, timestamp_micros,
+    timestamp_millis,
+};
 use polars::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::OnceLock;
 use std::time::{SystemTime, UNIX_EPOCH};
 
+/// Bound on the chunks of a trace column: one per bit of the row count.
+pub const MAX_CHUNKS: usize = usize::BITS as usize;
+/// Size ratio below which the newest chunk is merged into the one before it.
+const CHUNK_GROWTH: usize = 2;
+
+/// Retention override for devices matching a glob pattern (e.g. `rapl:system:psys`
+/// or `nvidia:*`).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeviceRetention {
+    /// Device name pattern; `*` and `?` are wildcards.
+    pub device: String,
+    /// Time window to keep for matching devices, in seconds.
+    pub retention_seconds: i64,
+}
+
 /// Configuration for trace rotation behavior
 #[derive(Debug, Clone)]
 pub struct RotationConfig {
@@ -22,6 +53,9 @@ pub struct RotationConfig {
     pub retention_seconds: i64,
     /// Automatically cleanup on append if true (default: true)
     pub auto_cleanup: bool,
+    /// Per-device overrides; the first matching pattern wins, otherwise
+    /// `retention_seconds` applies
+    pub device_retention: Vec<DeviceRetention>,
 }
 
 impl Default for RotationConfig {
@@ -29,6 +63,7 @@ impl Default for RotationConfig {
         Self {
             retention_seconds: 3600, // 1 hour default
             auto_cleanup: true,
+            device_retention: Vec::new(),
         }
     }
 }
@@ -37,10 +72,23 @@ impl RotationConfig {
     pub fn new(retention_seconds: i64) -> Self {
         Self {
             retention_seconds,
-            auto_cleanup: true,
+            ..Self::default()
         }
     }
 
+    pub fn with_device_retention(mut self, device_retention: Vec<DeviceRetention>) -> Self {
+        self.device_retention = device_retention;
+        self
+    }
+
+    /// Retention window that applies to `device`.
+    pub fn retention_for(&self, device: &str) -> i64 {
+        self.device_retention
+            .iter()
+            .find(|rule| glob_match(&rule.device, device))
+            .map_or(self.retention_seconds, |rule| rule.retention_seconds)
+    }
+
     pub fn with_auto_cleanup(mut self, auto_cleanup: bool) -> Self {
         self.auto_cleanup = auto_cleanup;
         self
@@ -50,18 +98,28 @@ impl RotationConfig {
 /// A rotating trace buffer that maintains limited history
 ///
 /// Automatically removes entries older than the configured retention window.
-/// Works with any DataFrame containing a "timestamp" column. Collector records
-/// use Unix milliseconds, while some tests and callers may still use Unix
-/// seconds; cleanup accepts both units.
+/// Works with any DataFrame containing a "timestamp" column. Appended frames
+/// are normalized to a `Datetime(us, UTC)` column (see
+/// [`normalize_timestamps`]), so retention always compares microseconds.
+/// The `monotonic_ns` column of collector frames is kept alongside as is;
+/// retention only looks at the wall-clock timestamp.
 pub struct RotatingTrace {
-    /// The trace data DataFrame with columns: pid | timestamp | device | <metric>
-    data: DataFrame,
+    /// Rows up to the latest read, with columns:
+    /// pid | timestamp | device | <metric> | monotonic_ns
+    base: DataFrame,
+    /// Batches appended since the latest read
+    pending: Vec<DataFrame>,
+    /// `base` and `pending` as one frame, built by the first read after an
+    /// append
+    view: OnceLock<DataFrame>,
     /// Rotation configuration
     config: RotationConfig,
     /// Last cleanup timestamp (to avoid excessive cleanup operations)
     last_cleanup_time: i64,
     /// Cleanup interval in seconds to throttle cleanup operations
     cleanup_interval_seconds: i64,
+    /// Largest estimated size the data has reached, in bytes
+    peak_bytes: usize,
 }
 
 impl RotatingTrace {
@@ -73,10 +131,13 @@ impl RotatingTrace {
     /// Create a new rotating trace with custom configuration
     pub fn with_config(config: RotationConfig) -> Self {
         Self {
-            data: DataFrame::default(),
+            base: DataFrame::default(),
+            pending: Vec::new(),
+            view: OnceLock::new(),
             config,
             last_cleanup_time: current_timestamp_secs(),
             cleanup_interval_seconds: 60, // Cleanup at most every 60 seconds
+            peak_bytes: 0,
         }
     }
 
@@ -87,12 +148,30 @@ impl RotatingTrace {
 
     /// Get a reference to the trace data
     pub fn data(&self) -> &DataFrame {
-        &self.data
+        if self.pending.is_empty() {
+            return &self.base;
+        }
+        self.view
+            .get_or_init(|| concat_batches(&self.base, &self.pending))
     }
 
     /// Get a mutable reference to the trace data
     pub fn data_mut(&mut self) -> &mut DataFrame {
-        &mut self.data
+        self.compact();
+        &mut self.base
+    }
+
+    /// Fold the pending batches into `base`, reusing the frame of the latest
+    /// read when there is one.
+    fn compact(&mut self) {
+        if self.pending.is_empty() {
+            return;
+        }
+        self.base = match self.view.take() {
+            Some(view) => view,
+            None => concat_batches(&self.base, &self.pending),
+        };
+        self.pending.clear();
     }
 
     /// Get the retention window in seconds
@@ -100,9 +179,28 @@ impl RotatingTrace {
         self.config.retention_seconds
     }
 
+    /// Longest retention window of any device, in seconds
+    pub fn longest_retention_seconds(&self) -> i64 {
+        self.config
+            .device_retention
+            .iter()
+            .map(|rule| rule.retention_seconds)
+            .fold(self.config.retention_seconds, i64::max)
+    }
+
     /// Get the number of rows in the trace
     pub fn row_count(&self) -> usize {
-        self.data.height()
+        self.base.height() + self.pending.iter().map(DataFrame::height).sum::<usize>()
+    }
+
+    /// Estimated heap size of the trace data in bytes
+    pub fn estimated_bytes(&self) -> usize {
+        self.base.estimated_size()
+            + self
+                .pending
+                .iter()
+                .map(DataFrame::estimated_size)
+                .sum::<usize>()
     }
 
     /// Append new records to the trace
@@ -117,21 +215,34 @@ impl RotatingTrace {
         if !new_data
             .get_column_names()
             .iter()
-            .any(|name| *name == "timestamp")
+            .any(|name| *name == TIMESTAMP_COLUMN)
         {
             return Err(MonitoringError::Other(
                 "DataFrame must contain a 'timestamp' column for rotation".to_string(),
             ));
         }
+        let new_data = normalize_timestamps(new_data.clone()).map_err(|e| {
+            MonitoringError::Other(format!("Failed to normalize timestamps: {}", e))
+        })?;
 
-        // Append new data to existing trace
-        if self.data.is_empty() {
-            self.data = new_data.clone();
+        // A read since the previous append already concatenated the batches
+        if self.view.get().is_some() {
+            self.compact();
+        }
+        if self.base.is_empty() && self.pending.is_empty() {
+            self.base = new_data;
         } else {
-            self.data = self.data.vstack(new_data).map_err(|e| {
-                MonitoringError::Other(format!("Failed to append trace data: {}", e))
-            })?;
+            // Check the batch stacks onto the trace now, as reads cannot fail
+            self.base
+                .head(Some(0))
+                .vstack(&new_data.head(Some(0)))
+                .map_err(|e| {
+                    MonitoringError::Other(format!("Failed to append trace data: {}", e))
+                })?;
+            self.pending.push(new_data);
         }
+        // Measured before cleanup, which only ever shrinks the data
+        self.peak_bytes = self.peak_bytes.max(self.estimated_bytes());
 
         // Auto cleanup if enabled
         if self.config.auto_cleanup {
@@ -147,36 +258,53 @@ impl RotatingTrace {
     /// Remove entries older than the retention window
     ///
     /// This operation filters the DataFrame to keep only entries with timestamps
-    /// within the last `retention_seconds`.
+    /// within the last `retention_seconds`, or within the matching per-device
+    /// window when `device_retention` overrides are configured.
     pub fn cleanup(&mut self) -> Result<(), MonitoringError> {
-        if self.data.is_empty() {
+        self.compact();
+        if self.base.is_empty() {
             self.last_cleanup_time = Self::get_current_timestamp();
             return Ok(());
         }
 
         let now = Self::get_current_timestamp();
-        let cutoff_secs = now - self.config.retention_seconds;
-        let cutoff_millis = cutoff_secs.saturating_mul(1000);
+        let now_us = now_micros();
 
-        // Get timestamp column
-        let timestamp_col = self.data.column("timestamp").map_err(|e| {
+        let timestamps = timestamp_micros(&self.base).map_err(|e| {
             MonitoringError::Other(format!("Failed to access timestamp column: {}", e))
         })?;
 
-        // Cast to i64 if needed
-        let timestamps = timestamp_col.i64().map_err(|e| {
-            MonitoringError::Other(format!("Timestamp column is not i64 type: {}", e))
-        })?;
-
         // Create filter mask for rows within retention window
-        let mask = timestamps
-            .iter()
-            .map(|opt_ts| {
-                opt_ts
-                    .map(|ts| timestamp_is_after_cutoff(ts, cutoff_secs, cutoff_millis))
-                    .unwrap_or(false)
-            })
-            .collect::<Vec<_>>();
+        let mask = if self.config.device_retention.is_empty() {
+            let cutoff_us = retention_cutoff_micros(now_us, self.config.retention_seconds);
+            timestamps
+                .iter()
+                .map(|opt_ts| opt_ts.is_some_and(|ts| ts > cutoff_us))
+                .collect::<Vec<_>>()
+        } else {
+            let devices = self
+                .base
+                .column("device")
+                .and_then(|col| col.str())
+                .map_err(|e| {
+                    MonitoringError::Other(format!("Failed to access device column: {}", e))
+                })?;
+            // Resolve each distinct device's cutoff once
+            let mut cutoffs: HashMap<&str, i64> = HashMap::new();
+            timestamps
+                .iter()
+                .zip(devices.iter())
+                .map(|(opt_ts, opt_device)| {
+                    let (Some(ts), Some(device)) = (opt_ts, opt_device) else {
+                        return false;
+                    };
+                    let cutoff_us = *cutoffs.entry(device).or_insert_with(|| {
+                        retention_cutoff_micros(now_us, self.config.retention_for(device))
+                    });
+                    ts > cutoff_us
+                })
+                .collect::<Vec<_>>()
+        };
 
         // Convert mask to BooleanChunked
         let mask_series = Series::new("filter".into(), mask);
@@ -185,8 +313,8 @@ impl RotatingTrace {
             .map_err(|e| MonitoringError::Other(format!("Failed to create boolean mask: {}", e)))?;
 
         // Filter the DataFrame
-        self.data = self
-            .data
+        self.base = self
+            .base
             .filter(mask_bool)
             .map_err(|e| MonitoringError::Other(format!("Failed to filter trace data: {}", e)))?;
 
@@ -201,38 +329,31 @@ impl RotatingTrace {
 
     /// Get statistics about the trace
     pub fn stats(&self) -> TraceStats {
-        let row_count = self.data.height();
-        let oldest_timestamp = if row_count > 0 {
-            self.data
-                .column("timestamp")
-                .ok()
-                .and_then(|col| col.i64().ok())
-                .and_then(|s| s.iter().flatten().min())
-        } else {
-            None
-        };
-
-        let newest_timestamp = if row_count > 0 {
-            self.data
-                .column("timestamp")
-                .ok()
-                .and_then(|col| col.i64().ok())
-                .and_then(|s| s.iter().flatten().max())
+        let row_count = self.row_count();
+        let timestamps = if row_count > 0 {
+            timestamp_millis(self.data()).ok()
         } else {
             None
         };
+        let oldest_timestamp = timestamps.as_ref().and_then(|s| s.min());
+        let newest_timestamp = timestamps.as_ref().and_then(|s| s.max());
 
+        let estimated_bytes = self.estimated_bytes();
         TraceStats {
             row_count,
             oldest_timestamp,
             newest_timestamp,
             retention_seconds: self.config.retention_seconds,
+            estimated_bytes,
+            peak_bytes: self.peak_bytes.max(estimated_bytes),
         }
     }
 
     /// Clear all data from the trace
     pub fn clear(&mut self) {
-        self.data = DataFrame::default();
+        self.base = DataFrame::default();
+        self.pending.clear();
+        self.view.take();
         self.last_cleanup_time = Self::get_current_timestamp();
     }
 
@@ -241,6 +362,11 @@ impl RotatingTrace {
         self.config.retention_seconds = seconds;
     }
 
+    /// Replace the per-device retention overrides
+    pub fn set_device_retention(&mut self, device_retention: Vec<DeviceRetention>) {
+        self.config.device_retention = device_retention;
+    }
+
     /// Update cleanup interval (throttling mechanism)
     pub fn set_cleanup_interval_seconds(&mut self, seconds: i64) {
         self.cleanup_interval_seconds = seconds;
@@ -251,46 +377,68 @@ impl RotatingTrace {
 #[derive(Debug, Clone)]
 pub struct TraceStats {
     pub row_count: usize,
+    /// Oldest wall-clock time in Unix milliseconds
     pub oldest_timestamp: Option<i64>,
+    /// Newest wall-clock time in Unix milliseconds
     pub newest_timestamp: Option<i64>,
     pub retention_seconds: i64,
+    /// Estimated heap size of the trace data in bytes
+    pub estimated_bytes: usize,
+    /// Largest estimated size since the trace was created, in bytes
+    pub peak_bytes: usize,
 }
 
 impl TraceStats {
     /// Get the age of the oldest entry in seconds
     pub fn oldest_age_seconds(&self) -> Option<i64> {
-        let now = current_timestamp_secs();
-        self.oldest_timestamp
-            .map(|ts| now - timestamp_to_seconds(ts))
+        let now_ms = now_millis();
+        self.oldest_timestamp.map(|ts| (now_ms - ts) / 1000)
     }
 
     /// Get the span of data in seconds (newest - oldest)
     pub fn data_span_seconds(&self) -> Option<i64> {
         match (self.oldest_timestamp, self.newest_timestamp) {
-            (Some(oldest), Some(newest)) => {
-                Some(timestamp_to_seconds(newest) - timestamp_to_seconds(oldest))
-            }
+            (Some(oldest), Some(newest)) => Some((newest - oldest) / 1000),
             _ => None,
         }
     }
 }
 
-const UNIX_MILLIS_THRESHOLD: i64 = 10_000_000_000;
+/// `base` followed by `batches`, which were checked to stack onto it. The
+/// batches become one chunk, merged with the trailing chunks of `base` that
+/// are at most [`CHUNK_GROWTH`] times its size; the chunks before them are
+/// shared with `base`.
+fn concat_batches(base: &DataFrame, batches: &[DataFrame]) -> DataFrame {
+    let chunk_lengths: Vec<usize> = base
+        .get_columns()
+        .first()
+        .map(|column| column.as_materialized_series().chunk_lengths().collect())
+        .unwrap_or_default();
+    let mut tail_rows: usize = batches.iter().map(DataFrame::height).sum();
+    let mut kept_rows = base.height();
+    for length in chunk_lengths.iter().rev() {
+        if *length > CHUNK_GROWTH * tail_rows {
+            break;
+        }
+        tail_rows += length;
+        kept_rows -= length;
+    }
 
-fn timestamp_is_after_cutoff(timestamp: i64, cutoff_secs: i64, cutoff_millis: i64) -> bool {
-    if timestamp.abs() >= UNIX_MILLIS_THRESHOLD {
-        timestamp > cutoff_millis
-    } else {
-        timestamp > cutoff_secs
+    let mut tail = base.slice(kept_rows as i64, base.height() - kept_rows);
+    for batch in batches {
+        tail.vstack_mut(batch)
+            .expect("trace batches are checked to stack on append");
     }
+    tail.as_single_chunk_par();
+    let mut data = base.slice(0, kept_rows);
+    data.vstack_mut(&tail)
+        .expect("trace batches are checked to stack on append");
+    data
 }
 
-fn timestamp_to_seconds(timestamp: i64) -> i64 {
-    if timestamp.abs() >= UNIX_MILLIS_THRESHOLD {
-        timestamp / 1000
-    } else {
-        timestamp
-    }
+/// Oldest Unix microsecond kept by a `retention_seconds` window ending at `now_us`.
+fn retention_cutoff_micros(now_us: i64, retention_seconds: i64) -> i64 {
+    now_us.saturating_sub(retention_seconds.saturating_mul(1_000_000))
 }
 
 /// Get current timestamp in seconds since UNIX_EPOCH
@@ -374,6 +522,150 @@ mod tests {
         assert_eq!(trace.row_count(), 2);
     }
 
+    #[test]
+    fn test_cleanup_is_consistent_across_timestamp_units() {
+        let mut trace = RotatingTrace::new(100);
+        let now = current_timestamp_secs();
+
+        let seconds = df![
+            "pid" => vec![1u32, 1u32],
+            "timestamp" => vec![now - 200, now],
+            "device" => vec!["cpu".to_string(), "cpu".to_string()],
+            "energy" => vec![10.0, 20.0],
+        ]
+        .unwrap();
+        let millis = df![
+            "pid" => vec![2u32, 2u32],
+            "timestamp" => vec![(now - 200) * 1000, now * 1000],
+            "device" => vec!["gpu".to_string(), "gpu".to_string()],
+            "energy" => vec![30.0, 40.0],
+        ]
+        .unwrap();
+
+        trace.append(&seconds).unwrap();
+        trace.append(&millis).unwrap();
+        trace.force_cleanup().unwrap();
+
+        assert_eq!(
+            trace.data().column("timestamp").unwrap().dtype(),
+            &crate::utils::time::timestamp_dtype()
+        );
+        let energy = trace.data().column("energy").unwrap().f64().unwrap();
+        assert_eq!(
+            energy.into_no_null_iter().collect::<Vec<_>>(),
+            vec![20.0, 40.0]
+        );
+    }
+
+    #[test]
+    fn test_cleanup_applies_per_device_retention() {
+        let config = RotationConfig::new(100).with_device_retention(vec![DeviceRetention {
+            device: "rapl:system:psys".to_string(),
+            retention_seconds: 7 * 24 * 3600,
+        }]);
+        let mut trace = RotatingTrace::with_config(config);
+        let now_ms = current_timestamp_secs() * 1000;
+
+        let data = df![
+            "pid" => vec![0u32, 1u32, 1u32],
+            "timestamp" => vec![now_ms - 200_000, now_ms - 200_000, now_ms],
+            "device" => vec![
+                "rapl:system:psys".to_string(),
+                "rapl:socket:0:package".to_string(),
+                "rapl:socket:0:package".to_string(),
+            ],
+            "energy" => vec![10.0, 20.0, 30.0],
+        ]
+        .unwrap();
+
+        trace.append(&data).unwrap();
+        trace.force_cleanup().unwrap();
+
+        let devices = trace.data().column("device").unwrap().str().unwrap();
+        assert_eq!(trace.row_count(), 2);
+        assert_eq!(devices.get(0), Some("rapl:system:psys"));
+        assert_eq!(trace.config.retention_for("rapl:socket:1:package"), 100);
+    }
+
+    #[test]
+    fn test_appends_are_concatenated_on_read() {
+        let mut trace = RotatingTrace::new(3600);
+        let now = current_timestamp_secs();
+        let batch = |energy: f64| {
+            df![
+                "pid" => vec![1u32],
+                "timestamp" => vec![now],
+                "device" => vec!["cpu".to_string()],
+                "energy" => vec![energy],
+            ]
+            .unwrap()
+        };
+
+        for i in 0..(3 * MAX_CHUNKS) {
+            trace.append(&batch(i as f64)).unwrap();
+            if i % 10 == 0 {
+                assert_eq!(trace.data().height(), i + 1);
+            }
+        }
+        assert_eq!(trace.row_count(), 3 * MAX_CHUNKS);
+        let data = trace.data();
+        assert!(data.max_n_chunks() <= MAX_CHUNKS);
+        let energy = data.column("energy").unwrap().f64().unwrap();
+        assert_eq!(
+            energy.get(3 * MAX_CHUNKS - 1),
+            Some((3 * MAX_CHUNKS - 1) as f64)
+        );
+
+        // A batch that does not stack onto the trace is refused on append.
+        let mismatched = batch(0.0).drop("energy").unwrap();
+        assert!(trace.append(&mismatched).is_err());
+        assert_eq!(trace.row_count(), 3 * MAX_CHUNKS);
+    }
+
+    #[test]
+    fn test_reads_merge_only_the_newest_chunks() {
+        let mut trace = RotatingTrace::new(3600);
+        let now = current_timestamp_secs();
+        let batch = |energy: f64| {
+            df![
+                "pid" => vec![1u32],
+                "timestamp" => vec![now],
+                "device" => vec!["cpu".to_string()],
+                "energy" => vec![energy],
+            ]
+            .unwrap()
+        };
+        let chunk_lengths = |trace: &RotatingTrace| -> Vec<usize> {
+            trace
+                .data()
+                .column("energy")
+                .unwrap()
+                .as_materialized_series()
+                .chunk_lengths()
+                .collect()
+        };
+
+        for i in 0..1000 {
+            trace.append(&batch(i as f64)).unwrap();
+            trace.data();
+        }
+        let lengths = chunk_lengths(&trace);
+        assert!(lengths.len() <= 11, "{lengths:?}");
+        assert!(
+            lengths
+                .windows(2)
+                .all(|pair| pair[0] > CHUNK_GROWTH * pair[1])
+        );
+
+        // One more row leaves the oldest chunks as they were.
+        trace.append(&batch(1000.0)).unwrap();
+        let after = chunk_lengths(&trace);
+        assert_eq!(after[..after.len() - 1], lengths[..after.len() - 1]);
+        assert_eq!(trace.data().height(), 1001);
+        let energy = trace.data().column("energy").unwrap().f64().unwrap();
+        assert!((0..1001).all(|i| energy.get(i) == Some(i as f64)));
+    }
+
     #[test]
     fn test_stats() {
         let mut trace = RotatingTrace::new(3600);
@@ -394,6 +686,12 @@ mod tests {
         assert!(stats.oldest_timestamp.is_some());
         assert!(stats.newest_timestamp.is_some());
         assert_eq!(stats.retention_seconds, 3600);
+        assert!(stats.estimated_bytes > 0);
+
+        trace.clear();
+        let stats = trace.stats();
+        assert_eq!(stats.estimated_bytes, 0);
+        assert!(stats.peak_bytes > 0);
     }
 
     #[test]
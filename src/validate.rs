@@ -0,0 +1,612 @@
+/// Validate Module
+///
+/// Runs two collectors covering the same domain side by side for `emt
+/// validate`, such as the powercap and perf_event RAPL backends, or RAPL
+/// against a BMC read through an `exec` meter, and reports how far their
+/// power readings disagree, so users can decide which backend to trust on
+/// their hardware.
+///
+/// Both sources are read together every interval; meters are read at that
+/// interval whatever their configured one. Devices are paired by name
+/// without their source prefix, so `rapl:socket:0:package` and
+/// `model:socket:0:package` are one domain. When device patterns are given,
+/// each side's matching devices are instead summed into a single `total`
+/// domain, for sources that name their devices differently.
+use crate::collectors::cpu_energy::CpuEnergyBackend;
+use crate::collectors::{CpuEnergy, MeterCollector};
+use crate::config::{CpuEnergyConfig, EmtConfig};
+use crate::energy_group::{EnergyCollector, EnergyRecord};
+use crate::units::format_significant;
+use crate::utils::errors::CollectorError;
+use crate::utils::pattern::matches_any;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+/// Domain the devices of each side are summed into when patterns are given.
+const TOTAL_DOMAIN: &str = "total";
+
+#[derive(Debug, Error)]
+pub enum ValidateError {
+    #[error(
+        "unknown source '{0}' (expected powercap, perf_event, msr, model, exec:NAME, http:NAME or bmc:NAME)"
+    )]
+    UnknownSource(String),
+    #[error("no meter '{0}' is configured")]
+    UnknownMeter(String),
+    #[error("{name} is not available: {detail}")]
+    Unavailable { name: String, detail: String },
+    #[error("failed to read {name}: {source}")]
+    Read {
+        name: String,
+        source: CollectorError,
+    },
+    #[error("the two sources share no domain; pass device patterns to compare totals")]
+    NoCommonDomain,
+}
+
+/// A collector compared by `emt validate`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValidationSource {
+    /// A CPU energy backend
+    Cpu(CpuEnergyBackend),
+    /// A configured `exec_collectors` meter, by name
+    Exec(String),
+    /// A configured `http_collectors` meter, by name
+    Http(String),
+    /// A configured `bmc_collectors` meter, by name
+    Bmc(String),
+}
+
+impl FromStr for ValidationSource {
+    type Err = ValidateError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(backend) = CpuEnergyBackend::DEFAULT_ORDER
+            .into_iter()
+            .find(|backend| backend.as_str() == s)
+        {
+            return Ok(Self::Cpu(backend));
+        }
+        match s.split_once(':') {
+            Some(("exec", name)) if !name.is_empty() => Ok(Self::Exec(name.to_string())),
+            Some(("http", name)) if !name.is_empty() => Ok(Self::Http(name.to_string())),
+            Some(("bmc", name)) if !name.is_empty() => Ok(Self::Bmc(name.to_string())),
+            _ => Err(ValidateError::UnknownSource(s.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for ValidationSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Cpu(backend) => f.write_str(backend.as_str()),
+            Self::Exec(name) => write!(f, "exec:{name}"),
+            Self::Http(name) => write!(f, "http:{name}"),
+            Self::Bmc(name) => write!(f, "bmc:{name}"),
+        }
+    }
+}
+
+/// How long and how finely to compare, and which devices.
+#[derive(Debug, Clone)]
+pub struct ValidationOptions {
+    pub duration: Duration,
+    pub interval: Duration,
+    /// Patterns of the first source's devices summed into one domain
+    pub devices_a: Vec<String>,
+    /// Patterns of the second source's devices summed into one domain
+    pub devices_b: Vec<String>,
+}
+
+impl ValidationOptions {
+    /// Domain `device` belongs to on a side with `patterns`, if any.
+    fn domain<'a>(&self, device: &'a str, patterns: &[String]) -> Option<&'a str> {
+        if self.devices_a.is_empty() && self.devices_b.is_empty() {
+            return Some(device.split_once(':').map_or(device, |(_, rest)| rest));
+        }
+        (patterns.is_empty() || matches_any(patterns, device)).then_some(TOTAL_DOMAIN)
+    }
+}
+
+/// Energy of one domain over one interval, as read by each source.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sample {
+    pub seconds: f64,
+    pub joules_a: f64,
+    pub joules_b: f64,
+}
+
+/// Energy per domain over one interval, as read by each source.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Interval {
+    pub seconds: f64,
+    pub joules_a: BTreeMap<String, f64>,
+    pub joules_b: BTreeMap<String, f64>,
+}
+
+/// How far the two sources disagree on one domain. Differences are the
+/// second source minus the first.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Disagreement {
+    pub domain: String,
+    pub samples: usize,
+    pub energy_joules_a: f64,
+    pub energy_joules_b: f64,
+    pub mean_watts_a: f64,
+    pub mean_watts_b: f64,
+    /// Mean power difference, i.e. the bias of the second source
+    pub mean_difference_watts: f64,
+    /// Bias relative to the first source's mean power; `None` when it is 0
+    pub relative_difference_percent: Option<f64>,
+    pub mean_absolute_difference_watts: f64,
+    pub rms_difference_watts: f64,
+    pub max_absolute_difference_watts: f64,
+    /// Pearson correlation of per-interval power; `None` with fewer than two
+    /// samples or a constant series
+    pub correlation: Option<f64>,
+}
+
+impl Disagreement {
+    /// Statistics over `samples`; `None` without any.
+    pub fn from_samples(domain: &str, samples: &[Sample]) -> Option<Self> {
+        if samples.is_empty() {
+            return None;
+        }
+        let seconds: f64 = samples.iter().map(|sample| sample.seconds).sum();
+        let energy_joules_a: f64 = samples.iter().map(|sample| sample.joules_a).sum();
+        let energy_joules_b: f64 = samples.iter().map(|sample| sample.joules_b).sum();
+        let (mean_watts_a, mean_watts_b) = (energy_joules_a / seconds, energy_joules_b / seconds);
+        let watts: Vec<(f64, f64)> = samples
+            .iter()
+            .map(|sample| {
+                (
+                    sample.joules_a / sample.seconds,
+                    sample.joules_b / sample.seconds,
+                )
+            })
+            .collect();
+        let count = watts.len() as f64;
+        let differences = watts.iter().map(|(a, b)| b - a);
+        let mean_difference_watts = mean_watts_b - mean_watts_a;
+        Some(Self {
+            domain: domain.to_string(),
+            samples: samples.len(),
+            energy_joules_a,
+            energy_joules_b,
+            mean_watts_a,
+            mean_watts_b,
+            mean_difference_watts,
+            relative_difference_percent: (mean_watts_a != 0.0)
+                .then(|| mean_difference_watts / mean_watts_a * 100.0),
+            mean_absolute_difference_watts: differences.clone().map(f64::abs).sum::<f64>() / count,
+            rms_difference_watts: (differences.clone().map(|d| d * d).sum::<f64>() / count).sqrt(),
+            max_absolute_difference_watts: differences.map(f64::abs).fold(0.0, f64::max),
+            correlation: correlation(&watts),
+        })
+    }
+}
+
+/// Pearson correlation of the pairs in `points`.
+fn correlation(points: &[(f64, f64)]) -> Option<f64> {
+    if points.len() < 2 {
+        return None;
+    }
+    let count = points.len() as f64;
+    let mean_a = points.iter().map(|(a, _)| a).sum::<f64>() / count;
+    let mean_b = points.iter().map(|(_, b)| b).sum::<f64>() / count;
+    let (mut covariance, mut variance_a, mut variance_b) = (0.0, 0.0, 0.0);
+    for (a, b) in points {
+        covariance += (a - mean_a) * (b - mean_b);
+        variance_a += (a - mean_a).powi(2);
+        variance_b += (b - mean_b).powi(2);
+    }
+    let denominator = (variance_a * variance_b).sqrt();
+    (denominator > 0.0).then(|| covariance / denominator)
+}
+
+/// Result of `emt validate`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ValidationReport {
+    pub source_a: String,
+    pub source_b: String,
+    pub duration_seconds: f64,
+    pub interval_seconds: f64,
+    pub domains: Vec<Disagreement>,
+    /// Domains only the first source reported
+    pub unmatched_a: Vec<String>,
+    /// Domains only the second source reported
+    pub unmatched_b: Vec<String>,
+}
+
+impl ValidationReport {
+    /// Statistics per domain seen by both sources, from per-interval energy
+    /// per domain of each source.
+    pub fn from_intervals(
+        source_a: &ValidationSource,
+        source_b: &ValidationSource,
+        options: &ValidationOptions,
+        intervals: &[Interval],
+    ) -> Result<Self, ValidateError> {
+        let mut samples: BTreeMap<&str, Vec<Sample>> = BTreeMap::new();
+        let (mut seen_a, mut seen_b) = (BTreeSet::new(), BTreeSet::new());
+        for interval in intervals {
+            seen_a.extend(interval.joules_a.keys().map(String::as_str));
+            seen_b.extend(interval.joules_b.keys().map(String::as_str));
+            for (domain, &a) in &interval.joules_a {
+                if let Some(&b) = interval.joules_b.get(domain) {
+                    samples.entry(domain).or_default().push(Sample {
+                        seconds: interval.seconds,
+                        joules_a: a,
+                        joules_b: b,
+                    });
+                }
+            }
+        }
+        let domains: Vec<Disagreement> = samples
+            .iter()
+            .filter_map(|(domain, samples)| Disagreement::from_samples(domain, samples))
+            .collect();
+        if domains.is_empty() {
+            return Err(ValidateError::NoCommonDomain);
+        }
+        let unmatched = |seen: BTreeSet<&str>| {
+            seen.into_iter()
+                .filter(|domain| !samples.contains_key(domain))
+                .map(str::to_string)
+                .collect()
+        };
+        Ok(Self {
+            source_a: source_a.to_string(),
+            source_b: source_b.to_string(),
+            duration_seconds: intervals.iter().map(|interval| interval.seconds).sum(),
+            interval_seconds: options.interval.as_secs_f64(),
+            domains,
+            unmatched_a: unmatched(seen_a),
+            unmatched_b: unmatched(seen_b),
+        })
+    }
+
+    /// The report as a table for the terminal, with power in watts.
+    pub fn render_text(&self) -> String {
+        let mut rows = vec![
+            [
+                "domain",
+                "samples",
+                "A (W)",
+                "B (W)",
+                "B-A (W)",
+                "B-A (%)",
+                "mean |B-A| (W)",
+                "RMS (W)",
+                "max |B-A| (W)",
+                "corr",
+            ]
+            .map(str::to_string),
+        ];
+        let optional = |value: Option<f64>| value.map_or("-".to_string(), format_significant);
+        for domain in &self.domains {
+            rows.push([
+                domain.domain.clone(),
+                domain.samples.to_string(),
+                format_significant(domain.mean_watts_a),
+                format_significant(domain.mean_watts_b),
+                format_significant(domain.mean_difference_watts),
+                optional(domain.relative_difference_percent),
+                format_significant(domain.mean_absolute_difference_watts),
+                format_significant(domain.rms_difference_watts),
+                format_significant(domain.max_absolute_difference_watts),
+                optional(domain.correlation),
+            ]);
+        }
+        let widths: Vec<usize> = (0..rows[0].len())
+            .map(|column| {
+                rows.iter()
+                    .map(|row| row[column].chars().count())
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect();
+
+        let mut lines = vec![
+            format!("A: {}", self.source_a),
+            format!("B: {}", self.source_b),
+            format!(
+                "Compared over {} s at {} s intervals",
+                format_significant(self.duration_seconds),
+                format_significant(self.interval_seconds)
+            ),
+            String::new(),
+        ];
+        for row in rows {
+            let cells: Vec<String> = row
+                .iter()
+                .zip(&widths)
+                .enumerate()
+                .map(|(column, (cell, &width))| {
+                    if column == 0 {
+                        format!("{cell:<width$}")
+                    } else {
+                        format!("{cell:>width$}")
+                    }
+                })
+                .collect();
+            lines.push(format!("  {}", cells.join("  ")).trim_end().to_string());
+        }
+        for (source, unmatched) in [("A", &self.unmatched_a), ("B", &self.unmatched_b)] {
+            if !unmatched.is_empty() {
+                lines.push(String::new());
+                lines.push(format!("Only seen by {source}: {}", unmatched.join(", ")));
+            }
+        }
+        lines.push(String::new());
+        lines.join("\n")
+    }
+}
+
+/// A source ready to read.
+enum SourceCollector {
+    Cpu(Box<CpuEnergy>),
+    Meter(MeterCollector),
+}
+
+impl SourceCollector {
+    fn open(
+        source: &ValidationSource,
+        config: &EmtConfig,
+        interval: Duration,
+    ) -> Result<Self, ValidateError> {
+        match source {
+            ValidationSource::Cpu(backend) => {
+                let cpu = CpuEnergy::new(&CpuEnergyConfig {
+                    backends: vec![*backend],
+                    ..config.cpu_energy.clone()
+                });
+                if cpu.backend().is_none() {
+                    let detail = cpu
+                        .preflight()
+                        .first()
+                        .map(|preflight| preflight.detail.clone())
+                        .unwrap_or_default();
+                    return Err(ValidateError::Unavailable {
+                        name: source.to_string(),
+                        detail,
+                    });
+                }
+                // CPU collectors only report while a process is tracked; its
+                // share and the unattributed rest add up to the device.
+                cpu.set_tracked_pids(vec![std::process::id()]);
+                Ok(Self::Cpu(Box::new(cpu)))
+            }
+            ValidationSource::Exec(name) => {
+                let mut meter = config
+                    .exec_collectors
+                    .iter()
+                    .find(|meter| &meter.name == name)
+                    .cloned()
+                    .ok_or_else(|| ValidateError::UnknownMeter(source.to_string()))?;
+                meter.interval_secs = interval.as_secs_f64();
+                Ok(Self::Meter(MeterCollector::new(&[meter], &[])))
+            }
+            ValidationSource::Http(name) => {
+                let mut meter = config
+                    .http_collectors
+                    .iter()
+                    .find(|meter| &meter.name == name)
+                    .cloned()
+                    .ok_or_else(|| ValidateError::UnknownMeter(source.to_string()))?;
+                meter.interval_secs = interval.as_secs_f64();
+                Ok(Self::Meter(MeterCollector::new(&[], &[meter])))
+            }
+            ValidationSource::Bmc(name) => {
+                let mut meter = config
+                    .bmc_collectors
+                    .iter()
+                    .find(|meter| &meter.name == name)
+                    .cloned()
+                    .ok_or_else(|| ValidateError::UnknownMeter(source.to_string()))?;
+                meter.interval_secs = interval.as_secs_f64();
+                Ok(Self::Meter(
+                    MeterCollector::new(&[], &[]).with_bmc(&[meter]),
+                ))
+            }
+        }
+    }
+
+    async fn read(&self) -> Result<Vec<EnergyRecord>, CollectorError> {
+        match self {
+            Self::Cpu(cpu) => cpu.get_energy_trace().await,
+            Self::Meter(meter) => meter.get_energy_trace().await,
+        }
+    }
+}
+
+/// Read `source_a` and `source_b` together every `options.interval` for
+/// `options.duration` and compare their power per domain.
+pub async fn validate_sources(
+    source_a: &ValidationSource,
+    source_b: &ValidationSource,
+    config: &EmtConfig,
+    options: &ValidationOptions,
+) -> Result<ValidationReport, ValidateError> {
+    let collector_a = SourceCollector::open(source_a, config, options.interval)?;
+    let collector_b = SourceCollector::open(source_b, config, options.interval)?;
+    let read = |source: &ValidationSource, result: Result<Vec<EnergyRecord>, CollectorError>| {
+        result.map_err(|error| ValidateError::Read {
+            name: source.to_string(),
+            source: error,
+        })
+    };
+    let energy_by_domain = |records: Vec<EnergyRecord>, patterns: &[String]| {
+        let mut energy: BTreeMap<String, f64> = BTreeMap::new();
+        for record in &records {
+            if let Some(domain) = options.domain(&record.device, patterns) {
+                *energy.entry(domain.to_string()).or_insert(0.0) += record.energy;
+            }
+        }
+        energy
+    };
+
+    // The first reads only set the baselines of cumulative counters.
+    let (baseline_a, baseline_b) = tokio::join!(collector_a.read(), collector_b.read());
+    read(source_a, baseline_a)?;
+    read(source_b, baseline_b)?;
+    let started = Instant::now();
+    let mut last = started;
+    let mut ticker = tokio::time::interval_at(
+        tokio::time::Instant::from_std(started) + options.interval,
+        options.interval,
+    );
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    let mut intervals = Vec::new();
+    while last.duration_since(started) < options.duration {
+        ticker.tick().await;
+        let (records_a, records_b) = tokio::join!(collector_a.read(), collector_b.read());
+        let now = Instant::now();
+        let seconds = now.duration_since(last).as_secs_f64();
+        last = now;
+        intervals.push(Interval {
+            seconds,
+            joules_a: energy_by_domain(read(source_a, records_a)?, &options.devices_a),
+            joules_b: energy_by_domain(read(source_b, records_b)?, &options.devices_b),
+        });
+    }
+    ValidationReport::from_intervals(source_a, source_b, options, &intervals)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(devices_a: &[&str], devices_b: &[&str]) -> ValidationOptions {
+        ValidationOptions {
+            duration: Duration::from_secs(3),
+            interval: Duration::from_secs(1),
+            devices_a: devices_a.iter().map(|d| d.to_string()).collect(),
+            devices_b: devices_b.iter().map(|d| d.to_string()).collect(),
+        }
+    }
+
+    fn interval(seconds: f64, a: &[(&str, f64)], b: &[(&str, f64)]) -> Interval {
+        let energy = |entries: &[(&str, f64)]| {
+            entries
+                .iter()
+                .map(|(domain, joules)| (domain.to_string(), *joules))
+                .collect()
+        };
+        Interval {
+            seconds,
+            joules_a: energy(a),
+            joules_b: energy(b),
+        }
+    }
+
+    #[test]
+    fn parses_backends_and_meters() {
+        assert_eq!(
+            "perf_event".parse::<ValidationSource>().unwrap(),
+            ValidationSource::Cpu(CpuEnergyBackend::PerfEvent)
+        );
+        assert_eq!(
+            "exec:ipmi".parse::<ValidationSource>().unwrap(),
+            ValidationSource::Exec("ipmi".to_string())
+        );
+        assert_eq!(
+            ValidationSource::Http("pdu".to_string()).to_string(),
+            "http:pdu"
+        );
+        assert_eq!(
+            "bmc:node".parse::<ValidationSource>().unwrap(),
+            ValidationSource::Bmc("node".to_string())
+        );
+        assert!("exec:".parse::<ValidationSource>().is_err());
+        assert!("rapl".parse::<ValidationSource>().is_err());
+    }
+
+    #[test]
+    fn pairs_devices_by_name_or_sums_them_by_pattern() {
+        let by_name = options(&[], &[]);
+        assert_eq!(
+            by_name.domain("rapl:socket:0:package", &[]),
+            Some("socket:0:package")
+        );
+        assert_eq!(
+            by_name.domain("model:socket:0:package", &[]),
+            Some("socket:0:package")
+        );
+
+        let by_pattern = options(&["rapl:*:package"], &[]);
+        let patterns = &by_pattern.devices_a;
+        assert_eq!(
+            by_pattern.domain("rapl:socket:1:package", patterns),
+            Some(TOTAL_DOMAIN)
+        );
+        assert_eq!(by_pattern.domain("rapl:socket:1:dram", patterns), None);
+        assert_eq!(
+            by_pattern.domain("exec:ipmi:psu", &by_pattern.devices_b),
+            Some(TOTAL_DOMAIN)
+        );
+    }
+
+    #[test]
+    fn reports_bias_spread_and_correlation_per_shared_domain() {
+        let intervals = [
+            interval(
+                1.0,
+                &[("socket:0:package", 10.0), ("socket:0:dram", 2.0)],
+                &[("socket:0:package", 11.0)],
+            ),
+            interval(
+                1.0,
+                &[("socket:0:package", 20.0), ("socket:0:dram", 2.0)],
+                &[("socket:0:package", 21.0)],
+            ),
+            interval(
+                2.0,
+                &[("socket:0:package", 60.0)],
+                &[("socket:0:package", 58.0), ("psys", 90.0)],
+            ),
+        ];
+
+        let report = ValidationReport::from_intervals(
+            &ValidationSource::Cpu(CpuEnergyBackend::Powercap),
+            &ValidationSource::Cpu(CpuEnergyBackend::PerfEvent),
+            &options(&[], &[]),
+            &intervals,
+        )
+        .unwrap();
+
+        assert_eq!(report.duration_seconds, 4.0);
+        assert_eq!(report.domains.len(), 1);
+        let package = &report.domains[0];
+        assert_eq!(package.samples, 3);
+        assert_eq!(
+            (package.energy_joules_a, package.energy_joules_b),
+            (90.0, 90.0)
+        );
+        assert_eq!(package.mean_difference_watts, 0.0);
+        assert_eq!(package.relative_difference_percent, Some(0.0));
+        assert_eq!(package.mean_absolute_difference_watts, 1.0);
+        assert_eq!(package.rms_difference_watts, 1.0);
+        assert_eq!(package.max_absolute_difference_watts, 1.0);
+        assert!(package.correlation.unwrap() > 0.9);
+        assert_eq!(report.unmatched_a, ["socket:0:dram"]);
+        assert_eq!(report.unmatched_b, ["psys"]);
+        assert!(report.render_text().contains("Only seen by B: psys"));
+
+        let disjoint = [interval(1.0, &[("a", 1.0)], &[("b", 1.0)])];
+        assert!(matches!(
+            ValidationReport::from_intervals(
+                &ValidationSource::Cpu(CpuEnergyBackend::Powercap),
+                &ValidationSource::Exec("ipmi".to_string()),
+                &options(&[], &[]),
+                &disjoint,
+            ),
+            Err(ValidateError::NoCommonDomain)
+        ));
+    }
+}
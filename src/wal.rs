@@ -0,0 +1,323 @@
+/// Write-Ahead Log Module
+///
+/// An append-only file of collector batches. `EnergyGroup` appends every
+/// batch here, synced to disk, before it applies the batch to the in-memory
+/// trace. A restarted group replays the log, so a crash does not lose
+/// batches that recorders had not persisted yet.
+///
+/// Layout: an 8-byte magic header, then one entry per batch. Each entry is
+/// the payload length and its CRC-32 (both `u32` little-endian), then the
+/// bincode-encoded records. A torn or corrupt tail, such as an entry cut
+/// short by a crash, is dropped when the log is opened.
+use crate::energy_group::EnergyRecord;
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+const MAGIC: &[u8; 8] = b"EMTWAL\x00\x01";
+const HEADER_LEN: u64 = MAGIC.len() as u64;
+/// Length and checksum preceding each payload
+const ENTRY_HEADER_LEN: usize = 8;
+
+/// Errors raised while opening or writing a write-ahead log.
+#[derive(Debug, Error)]
+pub enum WalError {
+    #[error("WAL I/O error: {0}")]
+    Io(#[from] io::Error),
+    #[error("{0} is not an EMT write-ahead log")]
+    NotAWal(PathBuf),
+    #[error("Failed to encode batch: {0}")]
+    Encode(#[from] bincode::error::EncodeError),
+}
+
+/// Entry position in the file, for cutting the log back.
+#[derive(Debug, Clone, Copy)]
+struct Entry {
+    /// Offset just past the entry
+    end: u64,
+    /// Newest record timestamp in the batch, in Unix microseconds
+    newest_us: i64,
+}
+
+/// Append-only batch log; see the module docs.
+#[derive(Debug)]
+pub struct WriteAheadLog {
+    path: PathBuf,
+    file: File,
+    entries: VecDeque<Entry>,
+}
+
+impl WriteAheadLog {
+    /// Open or create the log at `path` and return the batches it holds,
+    /// oldest first. A torn tail is truncated away.
+    pub fn open(path: impl Into<PathBuf>) -> Result<(Self, Vec<Vec<EnergyRecord>>), WalError> {
+        let path = path.into();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(&path)?;
+        let mut bytes = fs::read(&path)?;
+        if bytes.len() < MAGIC.len() && MAGIC.starts_with(&bytes) {
+            // New, or the header itself was torn.
+            file.set_len(0)?;
+            (&file).write_all(MAGIC)?;
+            file.sync_data()?;
+            bytes = MAGIC.to_vec();
+        } else if !bytes.starts_with(MAGIC) {
+            return Err(WalError::NotAWal(path));
+        }
+
+        let (batches, entries) = decode_entries(&bytes);
+        let valid_len = entries.back().map_or(HEADER_LEN, |entry| entry.end);
+        if valid_len < bytes.len() as u64 {
+            log::warn!(
+                "Discarding {} bytes of torn write-ahead log tail in {}",
+                bytes.len() as u64 - valid_len,
+                path.display()
+            );
+            file.set_len(valid_len)?;
+            file.sync_data()?;
+        }
+
+        Ok((
+            Self {
+                path,
+                file,
+                entries,
+            },
+            batches,
+        ))
+    }
+
+    /// Path of the log file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Number of batches in the log
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether the log holds no batches
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Append `batch` and sync it to disk.
+    pub fn append(&mut self, batch: &[EnergyRecord]) -> Result<(), WalError> {
+        if batch.is_empty() {
+            return Ok(());
+        }
+        let payload = bincode::serde::encode_to_vec(batch, bincode::config::standard())?;
+        let mut entry = Vec::with_capacity(ENTRY_HEADER_LEN + payload.len());
+        entry.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        entry.extend_from_slice(&crc32fast::hash(&payload).to_le_bytes());
+        entry.extend_from_slice(&payload);
+        self.file.write_all(&entry)?;
+        self.file.sync_data()?;
+
+        self.entries.push_back(Entry {
+            end: self.len_bytes() + entry.len() as u64,
+            newest_us: newest_timestamp(batch),
+        });
+        Ok(())
+    }
+
+    /// Drop the leading batches whose records are all older than
+    /// `cutoff_us` (Unix microseconds). `i64::MAX` empties the log.
+    ///
+    /// The file is rewritten only once the dropped part is at least half of
+    /// it, so frequent calls stay cheap. Batches kept until then are
+    /// replayed after a restart, where retention drops them again.
+    pub fn discard_before(&mut self, cutoff_us: i64) -> Result<(), WalError> {
+        let dropped = self
+            .entries
+            .iter()
+            .take_while(|entry| entry.newest_us < cutoff_us)
+            .count();
+        if dropped == 0 {
+            return Ok(());
+        }
+        if dropped == self.entries.len() {
+            self.file.set_len(HEADER_LEN)?;
+            self.file.sync_data()?;
+            self.entries.clear();
+            return Ok(());
+        }
+
+        let start = self.entries[dropped - 1].end;
+        let end = self.len_bytes();
+        if start - HEADER_LEN < end - start {
+            return Ok(());
+        }
+        let bytes = fs::read(&self.path)?;
+        let mut compacted_path = self.path.clone().into_os_string();
+        compacted_path.push(".tmp");
+        let compacted_path = PathBuf::from(compacted_path);
+        let mut compacted = File::create(&compacted_path)?;
+        compacted.write_all(MAGIC)?;
+        compacted.write_all(&bytes[start as usize..end as usize])?;
+        compacted.sync_data()?;
+        fs::rename(&compacted_path, &self.path)?;
+
+        self.file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .open(&self.path)?;
+        self.entries.drain(..dropped);
+        let shift = start - HEADER_LEN;
+        for entry in &mut self.entries {
+            entry.end -= shift;
+        }
+        Ok(())
+    }
+
+    fn len_bytes(&self) -> u64 {
+        self.entries.back().map_or(HEADER_LEN, |entry| entry.end)
+    }
+}
+
+/// Decode entries after the header until the first incomplete or corrupt
+/// one.
+fn decode_entries(bytes: &[u8]) -> (Vec<Vec<EnergyRecord>>, VecDeque<Entry>) {
+    let mut batches = Vec::new();
+    let mut entries = VecDeque::new();
+    let mut offset = HEADER_LEN as usize;
+    while let Some(header) = bytes.get(offset..offset + ENTRY_HEADER_LEN) {
+        let len = u32::from_le_bytes(header[..4].try_into().unwrap()) as usize;
+        let crc = u32::from_le_bytes(header[4..].try_into().unwrap());
+        let payload_start = offset + ENTRY_HEADER_LEN;
+        let Some(payload) = bytes.get(payload_start..payload_start + len) else {
+            break;
+        };
+        if crc32fast::hash(payload) != crc {
+            break;
+        }
+        let Ok((batch, _)) = bincode::serde::decode_from_slice::<Vec<EnergyRecord>, _>(
+            payload,
+            bincode::config::standard(),
+        ) else {
+            break;
+        };
+        offset = payload_start + len;
+        entries.push_back(Entry {
+            end: offset as u64,
+            newest_us: newest_timestamp(&batch),
+        });
+        batches.push(batch);
+    }
+    (batches, entries)
+}
+
+fn newest_timestamp(batch: &[EnergyRecord]) -> i64 {
+    batch
+        .iter()
+        .map(|record| record.timestamp_us)
+        .max()
+        .unwrap_or(i64::MIN)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn batch(timestamp_us: i64) -> Vec<EnergyRecord> {
+        vec![EnergyRecord {
+            pid: 1,
+            timestamp_us,
+            monotonic_ns: timestamp_us * 1000,
+            device: "rapl:socket:0:package".to_string(),
+            energy: 1.5,
+            raw_counter: Some(100.0),
+        }]
+    }
+
+    fn timestamps(batches: &[Vec<EnergyRecord>]) -> Vec<i64> {
+        batches.iter().flatten().map(|r| r.timestamp_us).collect()
+    }
+
+    #[test]
+    fn batches_are_replayed_after_reopening() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("wal").join("cpu.wal");
+        let (mut wal, replayed) = WriteAheadLog::open(&path).unwrap();
+        assert!(replayed.is_empty());
+        wal.append(&batch(1)).unwrap();
+        wal.append(&batch(2)).unwrap();
+        drop(wal);
+
+        let (wal, replayed) = WriteAheadLog::open(&path).unwrap();
+
+        assert_eq!(timestamps(&replayed), [1, 2]);
+        assert_eq!(replayed[0][0].raw_counter, Some(100.0));
+        assert_eq!(wal.len(), 2);
+    }
+
+    #[test]
+    fn torn_tail_is_truncated() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("cpu.wal");
+        let (mut wal, _) = WriteAheadLog::open(&path).unwrap();
+        wal.append(&batch(1)).unwrap();
+        wal.append(&batch(2)).unwrap();
+        drop(wal);
+        let len = fs::metadata(&path).unwrap().len();
+        OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_len(len - 3)
+            .unwrap();
+
+        let (mut wal, replayed) = WriteAheadLog::open(&path).unwrap();
+        assert_eq!(timestamps(&replayed), [1]);
+        wal.append(&batch(3)).unwrap();
+        drop(wal);
+
+        let (_, replayed) = WriteAheadLog::open(&path).unwrap();
+        assert_eq!(timestamps(&replayed), [1, 3]);
+    }
+
+    #[test]
+    fn discarding_old_batches_compacts_the_file() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("cpu.wal");
+        let (mut wal, _) = WriteAheadLog::open(&path).unwrap();
+        for timestamp in 1..=4 {
+            wal.append(&batch(timestamp)).unwrap();
+        }
+
+        wal.discard_before(2).unwrap();
+        assert_eq!(wal.len(), 4, "less than half is kept in place");
+        wal.discard_before(3).unwrap();
+        assert_eq!(wal.len(), 2);
+        wal.append(&batch(5)).unwrap();
+        drop(wal);
+        let (mut wal, replayed) = WriteAheadLog::open(&path).unwrap();
+        assert_eq!(timestamps(&replayed), [3, 4, 5]);
+
+        wal.discard_before(i64::MAX).unwrap();
+        assert!(wal.is_empty());
+        assert_eq!(fs::metadata(&path).unwrap().len(), HEADER_LEN);
+    }
+
+    #[test]
+    fn other_files_are_rejected() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("trace_0.csv");
+        fs::write(&path, "pid,timestamp\n").unwrap();
+
+        assert!(matches!(
+            WriteAheadLog::open(&path),
+            Err(WalError::NotAWal(_))
+        ));
+    }
+}
@@ -0,0 +1,286 @@
+/// Work Counters
+///
+/// Counts of the work a run does, such as requests served, images processed
+/// or tokens generated, reported by the workload as it goes. Dividing energy
+/// by work gives efficiency in joules per unit of work: per region, from the
+/// work reported while the region was open, and per process, from the work
+/// reported for its pid.
+///
+/// Each report is stamped with the time it was made, so work counts towards
+/// a region the same way trace records do, and a region repeated or nested
+/// counts it once per span.
+use crate::regions::{Region, RegionEnergy};
+use crate::utils::time::{ExportTimezone, MONOTONIC_COLUMN, RecordTime};
+use polars::prelude::*;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+/// Errors raised by work counters.
+#[derive(Debug, thiserror::Error)]
+pub enum WorkError {
+    #[error("work unit must be non-empty")]
+    EmptyUnit,
+    #[error("work count must be a non-negative number, got {0}")]
+    InvalidCount(f64),
+}
+
+/// Work of one unit reported at `time`, optionally done by process `pid`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WorkEvent {
+    pub unit: String,
+    pub count: f64,
+    pub pid: Option<u32>,
+    pub time: RecordTime,
+}
+
+/// Work reported during a run.
+#[derive(Debug, Clone, Default)]
+pub struct WorkCounters {
+    events: Vec<WorkEvent>,
+}
+
+impl WorkCounters {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count `count` units of work done now, by `pid` if given.
+    pub fn add(&mut self, unit: &str, count: f64, pid: Option<u32>) -> Result<(), WorkError> {
+        self.add_at(unit, count, pid, RecordTime::now())
+    }
+
+    pub fn add_at(
+        &mut self,
+        unit: &str,
+        count: f64,
+        pid: Option<u32>,
+        time: RecordTime,
+    ) -> Result<(), WorkError> {
+        if unit.is_empty() {
+            return Err(WorkError::EmptyUnit);
+        }
+        if !(count.is_finite() && count >= 0.0) {
+            return Err(WorkError::InvalidCount(count));
+        }
+        self.events.push(WorkEvent {
+            unit: unit.to_string(),
+            count,
+            pid,
+            time,
+        });
+        Ok(())
+    }
+
+    /// All reports in the order they were made.
+    pub fn events(&self) -> &[WorkEvent] {
+        &self.events
+    }
+}
+
+/// Energy per unit of work of a region or a process.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Efficiency {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub region: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pid: Option<u32>,
+    pub unit: String,
+    pub work: f64,
+    pub energy_joules: f64,
+    /// `None` when the reported work adds up to zero
+    pub joules_per_unit: Option<f64>,
+}
+
+impl Efficiency {
+    fn new(region: Option<String>, pid: Option<u32>, unit: &str, work: f64, joules: f64) -> Self {
+        Self {
+            region,
+            pid,
+            unit: unit.to_string(),
+            work,
+            energy_joules: joules,
+            joules_per_unit: (work > 0.0).then(|| joules / work),
+        }
+    }
+}
+
+/// Efficiency of each region of `energy` (totals over devices, as returned
+/// by [`energy_by_region`](crate::regions::energy_by_region)) per unit of
+/// work reported inside its spans. Open regions extend to `now`; regions
+/// without work are left out.
+pub fn efficiency_by_region(
+    energy: &[RegionEnergy],
+    regions: &[Region],
+    events: &[WorkEvent],
+    now: RecordTime,
+) -> Vec<Efficiency> {
+    let mut result = Vec::new();
+    for totals in energy.iter().filter(|totals| totals.device.is_none()) {
+        let mut work: BTreeMap<&str, f64> = BTreeMap::new();
+        for region in regions.iter().filter(|region| region.name == totals.region) {
+            for event in events
+                .iter()
+                .filter(|event| region.contains(event.time.monotonic_ns, now))
+            {
+                *work.entry(&event.unit).or_insert(0.0) += event.count;
+            }
+        }
+        result.extend(work.into_iter().map(|(unit, work)| {
+            Efficiency::new(
+                Some(totals.region.clone()),
+                None,
+                unit,
+                work,
+                totals.energy_joules,
+            )
+        }));
+    }
+    result
+}
+
+/// Efficiency of each process work was reported for, from its energy in
+/// `energy_by_pid`, ordered by pid and unit.
+pub fn efficiency_by_process(
+    energy_by_pid: &HashMap<u32, f64>,
+    events: &[WorkEvent],
+) -> Vec<Efficiency> {
+    let mut work: BTreeMap<(u32, &str), f64> = BTreeMap::new();
+    for event in events {
+        if let Some(pid) = event.pid {
+            *work.entry((pid, &event.unit)).or_insert(0.0) += event.count;
+        }
+    }
+    work.into_iter()
+        .map(|((pid, unit), work)| {
+            let joules = energy_by_pid.get(&pid).copied().unwrap_or(0.0);
+            Efficiency::new(None, Some(pid), unit, work, joules)
+        })
+        .collect()
+}
+
+/// Work reports as a frame: `unit | count | pid | time | monotonic_ns`,
+/// with wall-clock times rendered in `timezone`.
+pub fn work_to_dataframe(
+    events: &[WorkEvent],
+    timezone: ExportTimezone,
+) -> PolarsResult<DataFrame> {
+    DataFrame::new(vec![
+        Column::new(
+            "unit".into(),
+            events.iter().map(|e| e.unit.as_str()).collect::<Vec<_>>(),
+        ),
+        Column::new(
+            "count".into(),
+            events.iter().map(|e| e.count).collect::<Vec<_>>(),
+        ),
+        Column::new(
+            "pid".into(),
+            events.iter().map(|e| e.pid).collect::<Vec<_>>(),
+        ),
+        Column::new(
+            "time".into(),
+            events
+                .iter()
+                .map(|e| timezone.format_millis(e.time.timestamp()))
+                .collect::<Vec<_>>(),
+        ),
+        Column::new(
+            MONOTONIC_COLUMN.into(),
+            events
+                .iter()
+                .map(|e| e.time.monotonic_ns)
+                .collect::<Vec<_>>(),
+        ),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::regions::RegionMarkers;
+
+    fn at(seconds: i64) -> RecordTime {
+        RecordTime {
+            timestamp_us: 1_700_000_000_000_000 + seconds * 1_000_000,
+            monotonic_ns: seconds * 1_000_000_000,
+        }
+    }
+
+    fn region_energy(region: &str, energy_joules: f64) -> RegionEnergy {
+        RegionEnergy {
+            region: region.to_string(),
+            device: None,
+            energy_joules,
+            duration_seconds: 1.0,
+            average_power_watts: energy_joules,
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_work() {
+        let mut work = WorkCounters::new();
+        assert!(matches!(work.add("", 1.0, None), Err(WorkError::EmptyUnit)));
+        assert!(matches!(
+            work.add("requests", -1.0, None),
+            Err(WorkError::InvalidCount(_))
+        ));
+        assert!(work.add("requests", f64::NAN, None).is_err());
+        assert!(work.events().is_empty());
+    }
+
+    #[test]
+    fn regions_divide_energy_by_work_inside_their_spans() {
+        let mut markers = RegionMarkers::new();
+        markers.begin_at("batch", at(0));
+        markers.end_at("batch", at(2)).unwrap();
+        markers.begin_at("batch", at(5));
+        markers.begin_at("idle", at(3));
+        markers.end_at("idle", at(4)).unwrap();
+        let mut work = WorkCounters::new();
+        work.add_at("images", 30.0, None, at(1)).unwrap();
+        work.add_at("images", 10.0, Some(7), at(6)).unwrap();
+        work.add_at("requests", 4.0, None, at(2)).unwrap();
+        // Outside every region.
+        work.add_at("images", 100.0, None, at(10)).unwrap();
+
+        let efficiency = efficiency_by_region(
+            &[region_energy("batch", 80.0), region_energy("idle", 5.0)],
+            markers.regions(),
+            work.events(),
+            at(8),
+        );
+
+        assert_eq!(efficiency.len(), 2);
+        assert_eq!(efficiency[0].region.as_deref(), Some("batch"));
+        assert_eq!(efficiency[0].unit, "images");
+        assert_eq!(efficiency[0].work, 40.0);
+        assert_eq!(efficiency[0].joules_per_unit, Some(2.0));
+        assert_eq!(efficiency[1].unit, "requests");
+        assert_eq!(efficiency[1].joules_per_unit, Some(20.0));
+    }
+
+    #[test]
+    fn processes_divide_their_energy_by_their_work() {
+        let mut work = WorkCounters::new();
+        work.add_at("tokens", 500.0, Some(20), at(1)).unwrap();
+        work.add_at("tokens", 500.0, Some(20), at(2)).unwrap();
+        work.add_at("tokens", 0.0, Some(30), at(2)).unwrap();
+        work.add_at("tokens", 9.0, None, at(2)).unwrap();
+        let energy = HashMap::from([(20, 250.0), (30, 10.0)]);
+
+        let efficiency = efficiency_by_process(&energy, work.events());
+
+        assert_eq!(efficiency.len(), 2);
+        assert_eq!(efficiency[0].pid, Some(20));
+        assert_eq!(efficiency[0].work, 1_000.0);
+        assert_eq!(efficiency[0].joules_per_unit, Some(0.25));
+        assert_eq!(efficiency[1].joules_per_unit, None);
+
+        let table = work_to_dataframe(work.events(), ExportTimezone::Utc).unwrap();
+        assert_eq!(table.height(), 4);
+        assert_eq!(
+            table.column("time").unwrap().str().unwrap().get(0),
+            Some("2023-11-14T22:13:21.000Z")
+        );
+    }
+}